@@ -0,0 +1,139 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+#ifndef FLAME_H
+#define FLAME_H
+
+/* Generated by cbindgen from client/ffi/src/lib.rs, do not edit it manually. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Memory ownership: the handles are owned by the caller, and each of them must be released
+// exactly once by the matching `flame_*_free`, which accepts NULL. The strings and buffers
+// passed in are only borrowed during the call, and the outputs are copied into the buffers of
+// the caller. The handles can be used from several threads at the same time, but not after or
+// while being freed.
+
+// The result code of the calls.
+typedef enum FlameCode {
+  FLAME_CODE_OK = 0,
+  FLAME_CODE_NOT_FOUND = 1,
+  FLAME_CODE_INVALID_ARGUMENT = 2,
+  FLAME_CODE_UNAVAILABLE = 3,
+  FLAME_CODE_TASK_FAILED = 4,
+  FLAME_CODE_UNIMPLEMENTED = 5,
+  FLAME_CODE_INTERNAL = 6,
+  // The output buffer is too small; the required size is returned by `output_len`.
+  FLAME_CODE_BUFFER_TOO_SMALL = 7,
+  FLAME_CODE_PERMISSION_DENIED = 8,
+  // The call did not complete before its timeout or deadline.
+  FLAME_CODE_DEADLINE_EXCEEDED = 9,
+} FlameCode;
+
+// The connection to the session manager.
+typedef struct FlameConnection FlameConnection;
+
+// The session created by `flame_create_session`.
+typedef struct FlameSession FlameSession;
+
+// The task submitted by `flame_submit_task`.
+typedef struct FlameTask FlameTask;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Connects to the session manager at `endpoint`, e.g. `http://127.0.0.1:8080`.
+//
+// # Safety
+// `endpoint` must be a NUL-terminated string; `conn` must point to a writable handle, which
+// is set to a new connection on success and must be released by `flame_connection_free`.
+enum FlameCode flame_connect(const char *endpoint,
+                             struct FlameConnection **conn,
+                             char *err,
+                             size_t err_len);
+
+// Releases the connection.
+//
+// # Safety
+// `conn` must be NULL or a handle from `flame_connect` which was not freed.
+void flame_connection_free(struct FlameConnection *conn);
+
+// Creates a session of the application with the slots of each task.
+//
+// # Safety
+// `conn` must be a valid connection; `application` must be a NUL-terminated string; `session`
+// must point to a writable handle, which must be released by `flame_session_free`.
+enum FlameCode flame_create_session(const struct FlameConnection *conn,
+                                    const char *application,
+                                    int32_t slots,
+                                    struct FlameSession **session,
+                                    char *err,
+                                    size_t err_len);
+
+// Closes the session; the handle still has to be released by `flame_session_free`.
+//
+// # Safety
+// `session` must be a valid session.
+enum FlameCode flame_close_session(const struct FlameSession *session, char *err, size_t err_len);
+
+// Releases the session handle without closing the session.
+//
+// # Safety
+// `session` must be NULL or a handle from `flame_create_session` which was not freed.
+void flame_session_free(struct FlameSession *session);
+
+// Submits a task with `input_len` bytes of `input` to the session.
+//
+// # Safety
+// `session` must be a valid session; `input` must be readable for `input_len` bytes, or NULL
+// if `input_len` is 0; `task` must point to a writable handle, which must be released by
+// `flame_task_free`.
+enum FlameCode flame_submit_task(const struct FlameSession *session,
+                                 const uint8_t *input,
+                                 size_t input_len,
+                                 struct FlameTask **task,
+                                 char *err,
+                                 size_t err_len);
+
+// Waits for the task, and copies its output into `output`; the size of the output is set
+// to `output_len`. If the output is larger than `output_cap`, `FLAME_CODE_BUFFER_TOO_SMALL`
+// is returned and the call can be repeated with a larger buffer without waiting again. The
+// failure message of a failed task is returned by the error buffer.
+//
+// # Safety
+// `session` and `task` must be valid handles; `output` must be writable for `output_cap`
+// bytes, or NULL if `output_cap` is 0; `output_len` must be writable.
+enum FlameCode flame_wait_task(const struct FlameSession *session,
+                               const struct FlameTask *task,
+                               uint8_t *output,
+                               size_t output_cap,
+                               size_t *output_len,
+                               char *err,
+                               size_t err_len);
+
+// Releases the task handle.
+//
+// # Safety
+// `task` must be NULL or a handle from `flame_submit_task` which was not freed.
+void flame_task_free(struct FlameTask *task);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* FLAME_H */
@@ -0,0 +1,423 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The C ABI of flame-client, see `include/flame.h` for the header.
+//!
+//! Memory ownership:
+//! * The handles, i.e. `FlameConnection`, `FlameSession` and `FlameTask`, are created by the
+//!   library and owned by the caller, who must release each of them exactly once by the
+//!   matching `flame_*_free`; freeing NULL is a no-op.
+//! * A session or task handle does not borrow the handle it was created from, e.g. a session
+//!   is still valid after its connection is freed.
+//! * The strings and buffers passed to the library are only borrowed during the call; the
+//!   outputs are copied into the buffers provided by the caller, so the caller never frees
+//!   memory allocated by the library other than by `flame_*_free`.
+//! * The handles can be used from several threads at the same time, but must not be used
+//!   after or while being freed.
+
+use std::ffi::{c_char, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::sync::{Arc, Mutex};
+
+use tokio::runtime::Runtime;
+
+use flame_client::{self as flame, FlameError, SessionAttributes, TaskOutput};
+
+/// The result code of the calls.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlameCode {
+    Ok = 0,
+    NotFound = 1,
+    InvalidArgument = 2,
+    Unavailable = 3,
+    TaskFailed = 4,
+    Unimplemented = 5,
+    Internal = 6,
+    /// The output buffer is too small; the required size is returned by `output_len`.
+    BufferTooSmall = 7,
+    PermissionDenied = 8,
+    /// The call did not complete before its timeout or deadline.
+    DeadlineExceeded = 9,
+}
+
+impl From<&FlameError> for FlameCode {
+    fn from(e: &FlameError) -> Self {
+        match e {
+            FlameError::NotFound(_) => FlameCode::NotFound,
+            FlameError::InvalidArgument(_) => FlameCode::InvalidArgument,
+            FlameError::Unavailable { .. } | FlameError::ResourceExhausted { .. } => {
+                FlameCode::Unavailable
+            }
+            FlameError::TaskFailed { .. } => FlameCode::TaskFailed,
+            FlameError::Unimplemented(_) => FlameCode::Unimplemented,
+            FlameError::PermissionDenied(_) => FlameCode::PermissionDenied,
+            FlameError::DeadlineExceeded(_) | FlameError::Expired { .. } => {
+                FlameCode::DeadlineExceeded
+            }
+            FlameError::Internal(_) => FlameCode::Internal,
+        }
+    }
+}
+
+/// The connection to the session manager.
+pub struct FlameConnection {
+    runtime: Arc<Runtime>,
+    conn: flame::Connection,
+}
+
+/// The session created by `flame_create_session`.
+pub struct FlameSession {
+    runtime: Arc<Runtime>,
+    session: flame::Session,
+}
+
+/// The task submitted by `flame_submit_task`.
+pub struct FlameTask {
+    task: flame::Task,
+    /// The result of the task once it's completed, so the output can be fetched again with
+    /// a larger buffer.
+    result: Mutex<Option<Result<TaskOutput, FlameError>>>,
+}
+
+/// Error buffer of the caller; the message is truncated and always NUL-terminated.
+struct ErrorBuf {
+    buf: *mut c_char,
+    len: usize,
+}
+
+impl ErrorBuf {
+    fn clear(&self) {
+        if !self.buf.is_null() && self.len > 0 {
+            unsafe { *self.buf = 0 };
+        }
+    }
+
+    fn set(&self, message: &str) {
+        if self.buf.is_null() || self.len == 0 {
+            return;
+        }
+
+        let mut n = message.len().min(self.len - 1);
+        while !message.is_char_boundary(n) {
+            n -= 1;
+        }
+        unsafe {
+            ptr::copy_nonoverlapping(message.as_ptr(), self.buf as *mut u8, n);
+            *self.buf.add(n) = 0;
+        }
+    }
+}
+
+/// Runs the call, and reports its error or panic by the code and the error buffer.
+fn call(err: *mut c_char, err_len: usize, f: impl FnOnce() -> Result<(), FlameError>) -> FlameCode {
+    let err = ErrorBuf {
+        buf: err,
+        len: err_len,
+    };
+    err.clear();
+
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => FlameCode::Ok,
+        Ok(Err(e)) => {
+            err.set(&e.to_string());
+            FlameCode::from(&e)
+        }
+        Err(_) => {
+            err.set("panic in flame-client");
+            FlameCode::Internal
+        }
+    }
+}
+
+fn invalid(name: &str) -> FlameError {
+    FlameError::InvalidArgument(format!("<{}> is null", name))
+}
+
+unsafe fn to_str<'a>(s: *const c_char, name: &str) -> Result<&'a str, FlameError> {
+    if s.is_null() {
+        return Err(invalid(name));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| FlameError::InvalidArgument(format!("<{}> is not UTF-8", name)))
+}
+
+unsafe fn to_ref<'a, T>(p: *const T, name: &str) -> Result<&'a T, FlameError> {
+    p.as_ref().ok_or_else(|| invalid(name))
+}
+
+unsafe fn to_out<'a, T>(p: *mut *mut T, name: &str) -> Result<&'a mut *mut T, FlameError> {
+    let out = p.as_mut().ok_or_else(|| invalid(name))?;
+    *out = ptr::null_mut();
+    Ok(out)
+}
+
+/// Connects to the session manager at `endpoint`, e.g. `http://127.0.0.1:8080`.
+///
+/// # Safety
+/// `endpoint` must be a NUL-terminated string; `conn` must point to a writable handle, which
+/// is set to a new connection on success and must be released by `flame_connection_free`.
+#[no_mangle]
+pub unsafe extern "C" fn flame_connect(
+    endpoint: *const c_char,
+    conn: *mut *mut FlameConnection,
+    err: *mut c_char,
+    err_len: usize,
+) -> FlameCode {
+    call(err, err_len, || {
+        let endpoint = to_str(endpoint, "endpoint")?;
+        let out = to_out(conn, "conn")?;
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| FlameError::Internal(format!("failed to start runtime: {}", e)))?;
+        let connection = runtime.block_on(flame::connect(endpoint))?;
+
+        *out = Box::into_raw(Box::new(FlameConnection {
+            runtime: Arc::new(runtime),
+            conn: connection,
+        }));
+        Ok(())
+    })
+}
+
+/// Releases the connection.
+///
+/// # Safety
+/// `conn` must be NULL or a handle from `flame_connect` which was not freed.
+#[no_mangle]
+pub unsafe extern "C" fn flame_connection_free(conn: *mut FlameConnection) {
+    if !conn.is_null() {
+        drop(Box::from_raw(conn));
+    }
+}
+
+/// Creates a session of the application with the slots of each task.
+///
+/// # Safety
+/// `conn` must be a valid connection; `application` must be a NUL-terminated string; `session`
+/// must point to a writable handle, which must be released by `flame_session_free`.
+#[no_mangle]
+pub unsafe extern "C" fn flame_create_session(
+    conn: *const FlameConnection,
+    application: *const c_char,
+    slots: i32,
+    session: *mut *mut FlameSession,
+    err: *mut c_char,
+    err_len: usize,
+) -> FlameCode {
+    call(err, err_len, || {
+        let conn = to_ref(conn, "conn")?;
+        let application = to_str(application, "application")?;
+        let out = to_out(session, "session")?;
+
+        let attrs = SessionAttributes {
+            application: application.to_string(),
+            slots,
+            ..Default::default()
+        };
+        let ssn = conn.runtime.block_on(conn.conn.create_session(&attrs))?;
+
+        *out = Box::into_raw(Box::new(FlameSession {
+            runtime: conn.runtime.clone(),
+            session: ssn,
+        }));
+        Ok(())
+    })
+}
+
+/// Closes the session; the handle still has to be released by `flame_session_free`.
+///
+/// # Safety
+/// `session` must be a valid session.
+#[no_mangle]
+pub unsafe extern "C" fn flame_close_session(
+    session: *const FlameSession,
+    err: *mut c_char,
+    err_len: usize,
+) -> FlameCode {
+    call(err, err_len, || {
+        let ssn = to_ref(session, "session")?;
+        ssn.runtime.block_on(ssn.session.close())
+    })
+}
+
+/// Releases the session handle without closing the session.
+///
+/// # Safety
+/// `session` must be NULL or a handle from `flame_create_session` which was not freed.
+#[no_mangle]
+pub unsafe extern "C" fn flame_session_free(session: *mut FlameSession) {
+    if !session.is_null() {
+        drop(Box::from_raw(session));
+    }
+}
+
+/// Submits a task with `input_len` bytes of `input` to the session.
+///
+/// # Safety
+/// `session` must be a valid session; `input` must be readable for `input_len` bytes, or NULL
+/// if `input_len` is 0; `task` must point to a writable handle, which must be released by
+/// `flame_task_free`.
+#[no_mangle]
+pub unsafe extern "C" fn flame_submit_task(
+    session: *const FlameSession,
+    input: *const u8,
+    input_len: usize,
+    task: *mut *mut FlameTask,
+    err: *mut c_char,
+    err_len: usize,
+) -> FlameCode {
+    call(err, err_len, || {
+        let ssn = to_ref(session, "session")?;
+        let out = to_out(task, "task")?;
+        let input = match input_len {
+            0 => vec![],
+            _ if input.is_null() => return Err(invalid("input")),
+            _ => slice::from_raw_parts(input, input_len).to_vec(),
+        };
+
+        let t = ssn
+            .runtime
+            .block_on(ssn.session.create_task(Some(input.into())))?;
+
+        *out = Box::into_raw(Box::new(FlameTask {
+            task: t,
+            result: Mutex::new(None),
+        }));
+        Ok(())
+    })
+}
+
+/// Waits for the task, and copies its output into `output`; the size of the output is set
+/// to `output_len`. If the output is larger than `output_cap`, `FLAME_CODE_BUFFER_TOO_SMALL`
+/// is returned and the call can be repeated with a larger buffer without waiting again. The
+/// failure message of a failed task is returned by the error buffer.
+///
+/// # Safety
+/// `session` and `task` must be valid handles; `output` must be writable for `output_cap`
+/// bytes, or NULL if `output_cap` is 0; `output_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn flame_wait_task(
+    session: *const FlameSession,
+    task: *const FlameTask,
+    output: *mut u8,
+    output_cap: usize,
+    output_len: *mut usize,
+    err: *mut c_char,
+    err_len: usize,
+) -> FlameCode {
+    let mut too_small = false;
+    let code = call(err, err_len, || {
+        let ssn = to_ref(session, "session")?;
+        let t = to_ref(task, "task")?;
+        let output_len = output_len.as_mut().ok_or_else(|| invalid("output_len"))?;
+        *output_len = 0;
+
+        let cached = t
+            .result
+            .lock()
+            .map_err(|_| FlameError::Internal("mutex ptr".to_string()))?
+            .clone();
+        let res = match cached {
+            Some(res) => res,
+            None => {
+                let res = ssn
+                    .runtime
+                    .block_on(ssn.session.task_output(t.task.clone()));
+                if let Ok(mut result) = t.result.lock() {
+                    *result = Some(res.clone());
+                }
+                res
+            }
+        };
+
+        let data = res?;
+        *output_len = data.len();
+        if data.len() > output_cap {
+            too_small = true;
+            return Ok(());
+        }
+        if !data.is_empty() {
+            if output.is_null() {
+                return Err(invalid("output"));
+            }
+            ptr::copy_nonoverlapping(data.as_ptr(), output, data.len());
+        }
+        Ok(())
+    });
+
+    match (code, too_small) {
+        (FlameCode::Ok, true) => {
+            ErrorBuf {
+                buf: err,
+                len: err_len,
+            }
+            .set("output buffer is too small");
+            FlameCode::BufferTooSmall
+        }
+        (code, _) => code,
+    }
+}
+
+/// Releases the task handle.
+///
+/// # Safety
+/// `task` must be NULL or a handle from `flame_submit_task` which was not freed.
+#[no_mangle]
+pub unsafe extern "C" fn flame_task_free(task: *mut FlameTask) {
+    if !task.is_null() {
+        drop(Box::from_raw(task));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_buf() {
+        let mut buf = [1 as c_char; 8];
+        let err = ErrorBuf {
+            buf: buf.as_mut_ptr(),
+            len: buf.len(),
+        };
+
+        err.set("connection refused");
+        let msg = unsafe { CStr::from_ptr(buf.as_ptr()) };
+        assert_eq!(msg.to_str().unwrap(), "connect");
+
+        err.set("");
+        assert_eq!(buf[0], 0);
+    }
+
+    #[test]
+    fn test_invalid_argument() {
+        let mut conn = ptr::null_mut();
+        let mut buf = [0 as c_char; 64];
+
+        let code = unsafe { flame_connect(ptr::null(), &mut conn, buf.as_mut_ptr(), buf.len()) };
+        assert_eq!(code, FlameCode::InvalidArgument);
+        assert!(conn.is_null());
+
+        let msg = unsafe { CStr::from_ptr(buf.as_ptr()) };
+        assert_eq!(
+            msg.to_str().unwrap(),
+            "invalid argument: <endpoint> is null"
+        );
+    }
+}
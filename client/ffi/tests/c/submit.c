@@ -0,0 +1,86 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+/* Submits a task to the session manager, and prints its output.
+ *
+ *   submit <endpoint> <application> <input>
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "flame.h"
+
+#define ERR_LEN 256
+
+int main(int argc, char **argv) {
+    if (argc != 4) {
+        fprintf(stderr, "usage: %s <endpoint> <application> <input>\n", argv[0]);
+        return 2;
+    }
+
+    char err[ERR_LEN];
+    FlameConnection *conn = NULL;
+    FlameSession *ssn = NULL;
+    FlameTask *task = NULL;
+    char *output = NULL;
+    int rc = 1;
+
+    FlameCode code = flame_connect(argv[1], &conn, err, ERR_LEN);
+    if (code != FLAME_CODE_OK) {
+        fprintf(stderr, "failed to connect: %d %s\n", code, err);
+        goto out;
+    }
+
+    code = flame_create_session(conn, argv[2], 1, &ssn, err, ERR_LEN);
+    if (code != FLAME_CODE_OK) {
+        fprintf(stderr, "failed to create session: %d %s\n", code, err);
+        goto out;
+    }
+
+    code = flame_submit_task(ssn, (const uint8_t *)argv[3], strlen(argv[3]), &task, err, ERR_LEN);
+    if (code != FLAME_CODE_OK) {
+        fprintf(stderr, "failed to submit task: %d %s\n", code, err);
+        goto out;
+    }
+
+    /* Start with a small buffer, and grow it to the size of the output. */
+    size_t cap = 4, len = 0;
+    output = malloc(cap);
+    code = flame_wait_task(ssn, task, (uint8_t *)output, cap, &len, err, ERR_LEN);
+    if (code == FLAME_CODE_BUFFER_TOO_SMALL) {
+        cap = len;
+        output = realloc(output, cap);
+        code = flame_wait_task(ssn, task, (uint8_t *)output, cap, &len, err, ERR_LEN);
+    }
+    if (code != FLAME_CODE_OK) {
+        fprintf(stderr, "task failed: %d %s\n", code, err);
+        goto out;
+    }
+    printf("%.*s\n", (int)len, output);
+
+    code = flame_close_session(ssn, err, ERR_LEN);
+    if (code != FLAME_CODE_OK) {
+        fprintf(stderr, "failed to close session: %d %s\n", code, err);
+        goto out;
+    }
+    rc = 0;
+
+out:
+    free(output);
+    flame_task_free(task);
+    flame_session_free(ssn);
+    flame_connection_free(conn);
+    return rc;
+}
@@ -0,0 +1,116 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use flame_client::testkit::MockServer;
+use flame_client::FlameError;
+
+/// The directory of the cdylib, which is built next to the test binary.
+fn lib_dir() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    exe.parent().unwrap().to_path_buf()
+}
+
+fn cc() -> Command {
+    Command::new(env::var("CC").unwrap_or("cc".to_string()))
+}
+
+/// Whether the C compiler supports the flags, e.g. it has the sanitizer runtime.
+fn supports(flags: &[&str]) -> bool {
+    let dir = lib_dir();
+    let src = dir.join("flame_probe.c");
+    std::fs::write(&src, "int main(void) { return 0; }\n").unwrap();
+
+    cc().args(flags)
+        .arg(&src)
+        .arg("-o")
+        .arg(dir.join("flame_probe"))
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Compiles the C example against the cdylib.
+fn compile(name: &str, flags: &[&str]) -> PathBuf {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let lib_dir = lib_dir();
+    let out = lib_dir.join(name);
+
+    let status = cc()
+        .args(flags)
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg(root.join("tests/c/submit.c"))
+        .arg("-o")
+        .arg(&out)
+        .arg(format!("-L{}", lib_dir.display()))
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-lflame_client_ffi")
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success(), "failed to compile the C example");
+
+    out
+}
+
+/// Runs the C example against a mock server, which completes the task with the input in
+/// upper case.
+async fn run(exe: &Path) -> Result<Output, FlameError> {
+    let server = MockServer::start().await?;
+
+    let mut cmd = tokio::process::Command::new(exe);
+    cmd.args([server.endpoint(), "flmexec", "hello, flame"]);
+    let child = async {
+        cmd.output()
+            .await
+            .map_err(|e| FlameError::Internal(e.to_string()))
+    };
+    let (output, _) = tokio::try_join!(child, server.complete_next_task("HELLO, FLAME"))?;
+
+    Ok(output)
+}
+
+#[tokio::test]
+async fn test_c_example() -> Result<(), FlameError> {
+    let exe = compile("flame_submit", &["-Wall", "-Werror"]);
+    let output = run(&exe).await?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "HELLO, FLAME\n");
+
+    Ok(())
+}
+
+/// Asserts the ownership rules in the header: all the memory is released once the handles
+/// are freed, and nothing is accessed out of the buffers of the caller.
+#[tokio::test]
+async fn test_c_example_sanitizer() -> Result<(), FlameError> {
+    let flags = ["-fsanitize=address", "-g"];
+    if !supports(&flags) {
+        eprintln!("AddressSanitizer is not supported by the C compiler, skip it");
+        return Ok(());
+    }
+    let exe = compile("flame_submit_asan", &flags);
+    let output = run(&exe).await?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(!stderr.contains("Sanitizer"), "{}", stderr);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "HELLO, FLAME\n");
+
+    Ok(())
+}
@@ -0,0 +1,36 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::path::Path;
+
+/// The checked-in header must be the one cbindgen generates from the C ABI in src/lib.rs.
+#[test]
+fn test_header_up_to_date() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let config = cbindgen::Config::from_file(root.join("cbindgen.toml")).unwrap();
+
+    let mut generated = vec![];
+    cbindgen::Builder::new()
+        .with_crate(root)
+        .with_config(config)
+        .generate()
+        .unwrap()
+        .write(&mut generated);
+
+    let header = std::fs::read_to_string(root.join("include/flame.h")).unwrap();
+    assert!(
+        String::from_utf8(generated).unwrap() == header,
+        "include/flame.h is out of date, regenerate it in client/ffi by: \
+         cbindgen --config cbindgen.toml --output include/flame.h"
+    );
+}
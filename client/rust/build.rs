@@ -19,9 +19,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             "flame.ExecutorState",
             "#[allow(clippy::enum_variant_names)]",
         )
+        .type_attribute(
+            "flame.TaskChunk.item",
+            "#[allow(clippy::large_enum_variant)]",
+        )
         .protoc_arg("--experimental_allow_proto3_optional")
         .compile(
-            &["protos/types.proto", "protos/frontend.proto"],
+            &[
+                "protos/types.proto",
+                "protos/frontend.proto",
+                "protos/admin.proto",
+            ],
             &["protos"],
         )?;
 
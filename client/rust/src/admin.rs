@@ -0,0 +1,489 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::Channel;
+
+use common::ctx::FlameContext;
+use common::with_grpc_options;
+
+use crate::flame as rpc;
+use crate::flame::admin_client::AdminClient;
+use crate::trace::TraceFn;
+use crate::{trace_fn, Connection, FlameError, Interceptor};
+
+type FlameAdminClient = AdminClient<InterceptedService<Channel, Interceptor>>;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SchedulerStatus {
+    /// No sessions are bound or unbound by the scheduler if it's paused.
+    pub paused: bool,
+    pub last_cycle_time: Option<DateTime<Utc>>,
+    /// The number of the bindings and unbindings in the last cycle.
+    pub last_decisions: u32,
+    pub total_decisions: u64,
+    pub cycles: u64,
+    /// The background loops of the session manager, e.g. the scheduler and the sweeper.
+    #[serde(default)]
+    pub loops: Vec<LoopHealth>,
+}
+
+/// The health of a background loop of the session manager, which is restarted with backoff
+/// after it failed or panicked, and given up after too many restarts.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LoopHealth {
+    pub name: String,
+    /// running, restarting, failed or stopped.
+    pub state: String,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    pub last_error_time: Option<DateTime<Utc>>,
+}
+
+impl LoopHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.state == "running" && self.restarts == 0
+    }
+}
+
+impl From<rpc::SchedulerStatus> for SchedulerStatus {
+    fn from(status: rpc::SchedulerStatus) -> Self {
+        SchedulerStatus {
+            paused: status.paused,
+            last_cycle_time: status
+                .last_cycle_time
+                .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+            last_decisions: status.last_decisions,
+            total_decisions: status.total_decisions,
+            cycles: status.cycles,
+            loops: status
+                .loops
+                .into_iter()
+                .map(|health| LoopHealth {
+                    name: health.name,
+                    state: health.state,
+                    restarts: health.restarts,
+                    last_error: health.last_error,
+                    last_error_time: health
+                        .last_error_time
+                        .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The backlog of the open sessions of an application.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ApplicationStats {
+    pub name: String,
+    pub pending: u32,
+    /// The age of the oldest pending task, zero if no task is pending.
+    pub oldest_pending_age: Duration,
+}
+
+/// The usage of a quota against its limits; either of the namespace and the application is
+/// set, and the other one matches all.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuotaStats {
+    pub namespace: Option<String>,
+    pub application: Option<String>,
+    pub max_slots: Option<u32>,
+    /// The slots of the executors bound to the sessions of the quota.
+    pub slots: u32,
+    pub max_running_tasks: Option<u32>,
+    pub running_tasks: u32,
+}
+
+impl QuotaStats {
+    /// The name of the quota, e.g. `namespace=research,application=pi`.
+    pub fn name(&self) -> String {
+        let mut parts = vec![];
+        if let Some(namespace) = &self.namespace {
+            parts.push(format!("namespace={}", namespace));
+        }
+        if let Some(application) = &self.application {
+            parts.push(format!("application={}", application));
+        }
+
+        parts.join(",")
+    }
+}
+
+/// The histogram of the observations in seconds; `counts[i]` is the number of the
+/// observations in `(bounds[i-1], bounds[i]]`, and the last one is of those greater than
+/// all the bounds.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    pub bounds: Vec<f64>,
+    pub counts: Vec<u64>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Histogram {
+    pub fn mean(&self) -> Option<Duration> {
+        match self.count {
+            0 => None,
+            n => Some(Duration::from_secs_f64(self.sum / n as f64)),
+        }
+    }
+}
+
+/// The health of the queue, which is computed in every cycle of the scheduler, e.g. for
+/// the autoscalers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClusterStats {
+    pub update_time: Option<DateTime<Utc>>,
+    pub applications: Vec<ApplicationStats>,
+    pub idle_executors: u32,
+    /// The executors binding, bound or unbinding a session.
+    pub bound_executors: u32,
+    /// The latency from the creation of the sessions to their first bound executors.
+    pub bind_latency: Histogram,
+    /// The signals delivered to the autoscaler hook, and those failed to.
+    #[serde(default)]
+    pub autoscaler_deliveries: u64,
+    #[serde(default)]
+    pub autoscaler_failures: u64,
+    /// The executors quarantined for failing too many tasks.
+    #[serde(default)]
+    pub quarantined_executors: u32,
+    /// The acquisitions of the locks and the calls of the storage engine which were slower
+    /// than the thresholds of the self-monitoring.
+    #[serde(default)]
+    pub slow_locks: u64,
+    #[serde(default)]
+    pub slow_engine_calls: u64,
+    /// The mutating Frontend calls rejected by the rate limiter.
+    #[serde(default)]
+    pub throttled_calls: u64,
+    #[serde(default)]
+    pub quotas: Vec<QuotaStats>,
+    /// The executors bound to a session they served recently, and to a session served
+    /// recently by another executor only.
+    #[serde(default)]
+    pub affinity_hits: u64,
+    #[serde(default)]
+    pub affinity_misses: u64,
+    /// The restarts of the background loops of the session manager, and the loops given up.
+    #[serde(default)]
+    pub loop_restarts: u64,
+    #[serde(default)]
+    pub failed_loops: u32,
+    /// The tasks with a locality launched on an executor having its key, and those launched
+    /// on another executor after the locality wait.
+    #[serde(default)]
+    pub locality_hits: u64,
+    #[serde(default)]
+    pub locality_misses: u64,
+}
+
+impl From<rpc::ClusterStats> for ClusterStats {
+    fn from(stats: rpc::ClusterStats) -> Self {
+        let bind_latency = stats.bind_latency.unwrap_or_default();
+        ClusterStats {
+            update_time: stats
+                .update_time
+                .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+            applications: stats
+                .applications
+                .into_iter()
+                .map(|app| ApplicationStats {
+                    name: app.name,
+                    pending: app.pending,
+                    oldest_pending_age: Duration::from_secs_f64(app.oldest_pending_age.max(0.0)),
+                })
+                .collect(),
+            idle_executors: stats.idle_executors,
+            bound_executors: stats.bound_executors,
+            bind_latency: Histogram {
+                bounds: bind_latency.bounds,
+                counts: bind_latency.counts,
+                sum: bind_latency.sum,
+                count: bind_latency.count,
+            },
+            autoscaler_deliveries: stats.autoscaler_deliveries,
+            autoscaler_failures: stats.autoscaler_failures,
+            quarantined_executors: stats.quarantined_executors,
+            slow_locks: stats.slow_locks,
+            slow_engine_calls: stats.slow_engine_calls,
+            throttled_calls: stats.throttled_calls,
+            affinity_hits: stats.affinity_hits,
+            affinity_misses: stats.affinity_misses,
+            loop_restarts: stats.loop_restarts,
+            failed_loops: stats.failed_loops,
+            locality_hits: stats.locality_hits,
+            locality_misses: stats.locality_misses,
+            quotas: stats
+                .quotas
+                .into_iter()
+                .map(|q| QuotaStats {
+                    namespace: q.namespace,
+                    application: q.application,
+                    max_slots: q.max_slots,
+                    slots: q.slots,
+                    max_running_tasks: q.max_running_tasks,
+                    running_tasks: q.running_tasks,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// How the slots were shared by the applications and the namespaces in the last cycle of
+/// the scheduler; the targets are computed by the share math of the scheduling policy.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AllocationReport {
+    pub update_time: Option<DateTime<Utc>>,
+    /// The slots of all the executors.
+    pub total_slots: f64,
+    pub applications: Vec<Allocation>,
+    pub namespaces: Vec<Allocation>,
+    /// The open sessions with pending tasks allocated less than their targets, the longest
+    /// waiting first.
+    pub starved_sessions: Vec<StarvedSession>,
+}
+
+/// The slots of the open sessions of an application or a namespace.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Allocation {
+    pub name: String,
+    /// The slots of the pending and running tasks.
+    pub desired: f64,
+    /// The slots of the executors bound to the sessions.
+    pub allocated: f64,
+    /// The fair-share target of the slots.
+    pub target: f64,
+    /// The allocated slots minus the target, negative if less is allocated.
+    pub deviation: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StarvedSession {
+    pub id: String,
+    pub application: String,
+    pub namespace: String,
+    pub pending: u32,
+    pub allocated: f64,
+    pub target: f64,
+    /// The age of the oldest pending task.
+    pub wait: Duration,
+}
+
+impl From<rpc::AllocationReport> for AllocationReport {
+    fn from(report: rpc::AllocationReport) -> Self {
+        let allocation = |alloc: rpc::Allocation| Allocation {
+            name: alloc.name,
+            desired: alloc.desired,
+            allocated: alloc.allocated,
+            target: alloc.target,
+            deviation: alloc.deviation,
+        };
+        AllocationReport {
+            update_time: report
+                .update_time
+                .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+            total_slots: report.total_slots,
+            applications: report.applications.into_iter().map(allocation).collect(),
+            namespaces: report.namespaces.into_iter().map(allocation).collect(),
+            starved_sessions: report
+                .starved_sessions
+                .into_iter()
+                .map(|ssn| StarvedSession {
+                    id: ssn.id,
+                    application: ssn.application,
+                    namespace: ssn.namespace,
+                    pending: ssn.pending,
+                    allocated: ssn.allocated,
+                    target: ssn.target,
+                    wait: Duration::from_secs_f64(ssn.wait.max(0.0)),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// What the session manager actually runs with, for debugging.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EffectiveConfig {
+    /// The configuration resolved from the file, the environments and the flags of the
+    /// session manager, with the secrets redacted.
+    pub config: FlameContext,
+    /// The kind and the version of the storage engine, e.g. sqlite 3.45.1.
+    pub storage_kind: String,
+    pub storage_version: String,
+    /// The optional features built in, e.g. otel.
+    pub features: Vec<String>,
+    pub build: BuildInfo,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub version: String,
+    /// The architecture and the OS, e.g. x86_64-linux.
+    pub target: String,
+    /// debug or release.
+    pub profile: String,
+}
+
+impl TryFrom<rpc::EffectiveConfig> for EffectiveConfig {
+    type Error = FlameError;
+
+    fn try_from(conf: rpc::EffectiveConfig) -> Result<Self, Self::Error> {
+        let build = conf.build.unwrap_or_default();
+        Ok(EffectiveConfig {
+            config: serde_json::from_str(&conf.config).map_err(|e| {
+                FlameError::Internal(format!("invalid effective configuration: {}", e))
+            })?,
+            storage_kind: conf.storage_kind,
+            storage_version: conf.storage_version,
+            features: conf.features,
+            build: BuildInfo {
+                version: build.version,
+                target: build.target,
+                profile: build.profile,
+            },
+        })
+    }
+}
+
+/// The calls of the admin service; the caller must be allowed to access all namespaces if the
+/// authentication is enabled.
+impl Connection {
+    fn admin_client(&self) -> FlameAdminClient {
+        let client = AdminClient::with_interceptor(self.channel.clone(), self.interceptor.clone());
+        with_grpc_options!(client, self.grpc)
+    }
+
+    /// Stops binding and unbinding sessions from the next cycle of the scheduler.
+    pub async fn pause_scheduling(&self) -> Result<SchedulerStatus, FlameError> {
+        trace_fn!("Connection::pause_scheduling");
+        let mut client = self.admin_client();
+        let status = client
+            .pause_scheduling(rpc::PauseSchedulingRequest {})
+            .await?;
+
+        Ok(status.into_inner().into())
+    }
+
+    pub async fn resume_scheduling(&self) -> Result<SchedulerStatus, FlameError> {
+        trace_fn!("Connection::resume_scheduling");
+        let mut client = self.admin_client();
+        let status = client
+            .resume_scheduling(rpc::ResumeSchedulingRequest {})
+            .await?;
+
+        Ok(status.into_inner().into())
+    }
+
+    pub async fn get_scheduler_status(&self) -> Result<SchedulerStatus, FlameError> {
+        trace_fn!("Connection::get_scheduler_status");
+        let client = self.admin_client();
+        let status = self
+            .retry
+            .run(|| {
+                let mut client = client.clone();
+                async move {
+                    client
+                        .get_scheduler_status(rpc::GetSchedulerStatusRequest {})
+                        .await
+                        .map_err(FlameError::from)
+                }
+            })
+            .await?;
+
+        Ok(status.into_inner().into())
+    }
+
+    /// The health of the queue in the last cycle of the scheduler; the server must support
+    /// `capability::CLUSTER_STATS`.
+    pub async fn get_cluster_stats(&self) -> Result<ClusterStats, FlameError> {
+        trace_fn!("Connection::get_cluster_stats");
+        let client = self.admin_client();
+        let stats = self
+            .retry
+            .run(|| {
+                let mut client = client.clone();
+                async move {
+                    client
+                        .get_cluster_stats(rpc::GetClusterStatsRequest {})
+                        .await
+                        .map_err(FlameError::from)
+                }
+            })
+            .await?;
+
+        Ok(stats.into_inner().into())
+    }
+
+    /// The configuration the session manager runs with; the server must support
+    /// `capability::EFFECTIVE_CONFIG`.
+    pub async fn get_effective_config(&self) -> Result<EffectiveConfig, FlameError> {
+        trace_fn!("Connection::get_effective_config");
+        let client = self.admin_client();
+        let conf = self
+            .retry
+            .run(|| {
+                let mut client = client.clone();
+                async move {
+                    client
+                        .get_effective_config(rpc::GetEffectiveConfigRequest {})
+                        .await
+                        .map_err(FlameError::from)
+                }
+            })
+            .await?;
+
+        conf.into_inner().try_into()
+    }
+
+    /// The fair shares of the applications and the namespaces in the last cycle of the
+    /// scheduler, with the `top` starved sessions; the server must support
+    /// `capability::ALLOCATION_REPORT`.
+    pub async fn get_allocation_report(&self, top: u32) -> Result<AllocationReport, FlameError> {
+        trace_fn!("Connection::get_allocation_report");
+        let client = self.admin_client();
+        let report = self
+            .retry
+            .run(|| {
+                let mut client = client.clone();
+                async move {
+                    client
+                        .get_allocation_report(rpc::GetAllocationReportRequest { top })
+                        .await
+                        .map_err(FlameError::from)
+                }
+            })
+            .await?;
+
+        Ok(report.into_inner().into())
+    }
+
+    /// Persists the in-memory states of the session manager which are behind in its storage;
+    /// returns the number of the sessions and tasks persisted.
+    pub async fn flush_state(&self) -> Result<(u32, u32), FlameError> {
+        trace_fn!("Connection::flush_state");
+        let mut client = self.admin_client();
+        let resp = client
+            .flush_state(rpc::FlushStateRequest {})
+            .await?
+            .into_inner();
+
+        Ok((resp.sessions, resp.tasks))
+    }
+}
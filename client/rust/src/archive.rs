@@ -0,0 +1,440 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The archives of the sessions, which keep a session and its tasks after it's deleted,
+//! e.g. to reproduce or debug a failed batch offline.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tonic::Streaming;
+
+use common::apis::base64_bytes;
+
+use crate::flame as rpc;
+use crate::flame::session_archive::Item;
+use crate::trace::TraceFn;
+use crate::{
+    trace_fn, CommonData, Connection, FlameError, Session, SessionID, SessionState, TaskFailure,
+    TaskID, TaskInput, TaskOutput, TaskState,
+};
+
+/// The version of the archive document, which is bumped on incompatible changes.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedSession {
+    pub id: SessionID,
+    pub namespace: String,
+    pub application: String,
+    pub slots: i32,
+    pub labels: BTreeMap<String, String>,
+    #[serde(default, with = "base64_bytes")]
+    pub common_data: Option<CommonData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_pending_tasks: Option<u32>,
+    pub creation_time: DateTime<Utc>,
+    pub completion_time: Option<DateTime<Utc>>,
+    pub state: SessionState,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedTask {
+    pub id: TaskID,
+    /// The index of the task in its session; the archives of previous versions have none, so
+    /// the tasks are indexed in the order of their ids when imported.
+    #[serde(default)]
+    pub index: u64,
+    pub state: TaskState,
+    pub creation_time: DateTime<Utc>,
+    pub completion_time: Option<DateTime<Utc>>,
+    #[serde(default, with = "base64_bytes")]
+    pub input: Option<TaskInput>,
+    #[serde(default, with = "base64_bytes")]
+    pub output: Option<TaskOutput>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_context: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// The failed task which this one was resubmitted from; the ids are kept by the import.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_task_id: Option<TaskID>,
+    /// The last failure of the task, e.g. why it failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<TaskFailure>,
+}
+
+/// The archive document of a session, i.e. `{"version": 1, "session": {..}, "tasks": [..]}`
+/// with the inputs and outputs in base64; it's written task by task by `ArchiveWriter`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SessionArchive {
+    pub version: u32,
+    pub session: ArchivedSession,
+    pub tasks: Vec<ArchivedTask>,
+}
+
+impl SessionArchive {
+    pub fn read(reader: impl Read) -> Result<Self, FlameError> {
+        let archive: SessionArchive = serde_json::from_reader(reader)
+            .map_err(|e| FlameError::InvalidArgument(format!("invalid archive: {}", e)))?;
+        if archive.version > ARCHIVE_VERSION {
+            return Err(FlameError::InvalidArgument(format!(
+                "archive version {} is newer than {}, please upgrade the client",
+                archive.version, ARCHIVE_VERSION
+            )));
+        }
+
+        Ok(archive)
+    }
+}
+
+/// Writes the archive document of a session task by task, so the tasks are not kept in
+/// memory, e.g. the ones streamed by `Connection::export_session`.
+pub struct ArchiveWriter<W: Write> {
+    writer: W,
+    tasks: usize,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    pub fn new(mut writer: W, session: &ArchivedSession) -> std::io::Result<Self> {
+        write!(writer, "{{\"version\":{},\"session\":", ARCHIVE_VERSION)?;
+        serde_json::to_writer(&mut writer, session)?;
+        write!(writer, ",\"tasks\":[")?;
+
+        Ok(ArchiveWriter { writer, tasks: 0 })
+    }
+
+    pub fn write_task(&mut self, task: &ArchivedTask) -> std::io::Result<()> {
+        if self.tasks > 0 {
+            write!(self.writer, ",")?;
+        }
+        writeln!(self.writer)?;
+        serde_json::to_writer(&mut self.writer, task)?;
+        self.tasks += 1;
+
+        Ok(())
+    }
+
+    /// Completes the document, and returns the number of the tasks written.
+    pub fn finish(mut self) -> std::io::Result<usize> {
+        writeln!(self.writer, "\n]}}")?;
+        self.writer.flush()?;
+
+        Ok(self.tasks)
+    }
+}
+
+/// An exported session; its tasks are streamed in the order of their ids.
+pub struct SessionExport {
+    pub session: ArchivedSession,
+    tasks: Streaming<rpc::SessionArchive>,
+}
+
+impl Stream for SessionExport {
+    type Item = Result<ArchivedTask, FlameError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.tasks.poll_next_unpin(cx).map(|item| {
+            item.map(|item| match item?.item {
+                Some(Item::Task(task)) => ArchivedTask::try_from(task),
+                _ => Err(FlameError::Internal(
+                    "unexpected item in the archive".to_string(),
+                )),
+            })
+        })
+    }
+}
+
+impl Connection {
+    /// Exports the session and its tasks, e.g. to archive it before it's deleted; the tasks
+    /// are streamed one by one, so the large outputs are not sent in one message.
+    pub async fn export_session(&self, id: &SessionID) -> Result<SessionExport, FlameError> {
+        trace_fn!("Connection::export_session");
+        let mut client = self.client();
+        let mut tasks = client
+            .export_session(rpc::ExportSessionRequest {
+                session_id: id.clone(),
+                namespace: self.namespace.clone(),
+            })
+            .await?
+            .into_inner();
+
+        let session = match tasks.message().await? {
+            Some(rpc::SessionArchive {
+                item: Some(Item::Session(ssn)),
+            }) => ArchivedSession::try_from(ssn)?,
+            _ => {
+                return Err(FlameError::Internal(format!(
+                    "no session <{}> in the archive",
+                    id
+                )))
+            }
+        };
+
+        Ok(SessionExport { session, tasks })
+    }
+
+    /// Recreates an archived session as closed for inspection, with a new id; it's imported
+    /// into the namespace of the connection if set, or its original namespace.
+    pub async fn import_session<S>(
+        &self,
+        session: &ArchivedSession,
+        tasks: S,
+    ) -> Result<Session, FlameError>
+    where
+        S: Stream<Item = ArchivedTask> + Send + 'static,
+    {
+        trace_fn!("Connection::import_session");
+        let mut ssn = rpc::Session::from(session);
+        if let Some(spec) = ssn.spec.as_mut().filter(|_| !self.namespace.is_empty()) {
+            spec.namespace = self.namespace.clone();
+        }
+
+        let ssn_id = session.id.clone();
+        let first = rpc::SessionArchive {
+            item: Some(Item::Session(ssn)),
+        };
+        let items =
+            stream::once(async { first }).chain(tasks.map(move |task| rpc::SessionArchive {
+                item: Some(Item::Task(task.to_rpc(&ssn_id))),
+            }));
+
+        let mut client = self.client();
+        let ssn = client.import_session(items).await?;
+
+        Ok(self.session(&ssn.into_inner()))
+    }
+}
+
+impl TryFrom<rpc::Session> for ArchivedSession {
+    type Error = FlameError;
+
+    fn try_from(ssn: rpc::Session) -> Result<Self, Self::Error> {
+        let (Some(metadata), Some(spec), Some(status)) = (ssn.metadata, ssn.spec, ssn.status)
+        else {
+            return Err(FlameError::Internal("incomplete session".to_string()));
+        };
+
+        Ok(ArchivedSession {
+            id: metadata.id,
+            namespace: spec.namespace,
+            application: spec.application,
+            slots: spec.slots,
+            labels: spec.labels.into_iter().collect(),
+            common_data: spec.common_data.map(CommonData::from),
+            max_pending_tasks: spec.max_pending_tasks,
+            creation_time: timestamp(status.creation_time)?,
+            completion_time: status.completion_time.map(timestamp).transpose()?,
+            state: SessionState::try_from(status.state)
+                .map_err(|_| FlameError::Internal("invalid session state".to_string()))?,
+        })
+    }
+}
+
+impl From<&ArchivedSession> for rpc::Session {
+    fn from(ssn: &ArchivedSession) -> Self {
+        rpc::Session {
+            metadata: Some(rpc::Metadata {
+                id: ssn.id.clone(),
+                owner: None,
+            }),
+            spec: Some(rpc::SessionSpec {
+                application: ssn.application.clone(),
+                slots: ssn.slots,
+                common_data: ssn.common_data.clone().map(CommonData::into),
+                labels: ssn.labels.clone().into_iter().collect(),
+                namespace: ssn.namespace.clone(),
+                max_pending_tasks: ssn.max_pending_tasks,
+                ..Default::default()
+            }),
+            status: Some(rpc::SessionStatus {
+                state: ssn.state as i32,
+                creation_time: ssn.creation_time.timestamp(),
+                completion_time: ssn.completion_time.map(|t| t.timestamp()),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+impl TryFrom<rpc::Task> for ArchivedTask {
+    type Error = FlameError;
+
+    fn try_from(task: rpc::Task) -> Result<Self, Self::Error> {
+        let (Some(metadata), Some(spec), Some(status)) = (task.metadata, task.spec, task.status)
+        else {
+            return Err(FlameError::Internal("incomplete task".to_string()));
+        };
+
+        Ok(ArchivedTask {
+            id: metadata.id,
+            index: task.index,
+            state: TaskState::try_from(status.state)
+                .map_err(|_| FlameError::Internal("invalid task state".to_string()))?,
+            creation_time: timestamp(status.creation_time)?,
+            completion_time: status.completion_time.map(timestamp).transpose()?,
+            input: spec.input.map(TaskInput::from),
+            output: spec.output.map(TaskOutput::from),
+            trace_context: spec.trace_context,
+            labels: spec.labels.into_iter().collect(),
+            original_task_id: task.original_task_id,
+            failure: status.failure.as_ref().map(TaskFailure::from),
+        })
+    }
+}
+
+impl ArchivedTask {
+    /// The task of the archived session, which is imported with a new id.
+    fn to_rpc(&self, ssn_id: &SessionID) -> rpc::Task {
+        rpc::Task {
+            metadata: Some(rpc::Metadata {
+                id: self.id.clone(),
+                owner: None,
+            }),
+            index: self.index,
+            original_task_id: self.original_task_id.clone(),
+            spec: Some(rpc::TaskSpec {
+                session_id: ssn_id.clone(),
+                input: self.input.clone().map(TaskInput::into),
+                output: self.output.clone().map(TaskOutput::into),
+                trace_context: self.trace_context.clone(),
+                labels: self.labels.clone().into_iter().collect(),
+                ..Default::default()
+            }),
+            status: Some(rpc::TaskStatus {
+                state: self.state as i32,
+                creation_time: self.creation_time.timestamp(),
+                completion_time: self.completion_time.map(|t| t.timestamp()),
+                progress: None,
+                failure: self.failure.as_ref().map(rpc::TaskFailure::from),
+                executor_id: None,
+                hostname: None,
+                annotations: Default::default(),
+                output_chunks: 0,
+            }),
+        }
+    }
+}
+
+fn timestamp(secs: i64) -> Result<DateTime<Utc>, FlameError> {
+    DateTime::<Utc>::from_timestamp(secs, 0).ok_or(FlameError::Internal(format!(
+        "invalid timestamp <{}>",
+        secs
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bytes::Bytes;
+
+    use crate::FailureReason;
+
+    fn archive() -> SessionArchive {
+        let time = DateTime::<Utc>::from_timestamp(1_700_000_000, 0).unwrap();
+        let task = |id: &str, state| ArchivedTask {
+            id: id.to_string(),
+            index: id.parse::<u64>().unwrap() - 1,
+            state,
+            creation_time: time,
+            completion_time: Some(time),
+            input: Some(Bytes::from(format!("input of {}", id))),
+            output: Some(Bytes::from(vec![0x00, 0xff])),
+            trace_context: None,
+            labels: BTreeMap::new(),
+            original_task_id: None,
+            failure: (state == TaskState::Failed).then(|| TaskFailure {
+                reason: FailureReason::Timeout,
+                message: "session expired".to_string(),
+            }),
+        };
+
+        SessionArchive {
+            version: ARCHIVE_VERSION,
+            session: ArchivedSession {
+                id: "12".to_string(),
+                namespace: "team-a".to_string(),
+                application: "flmexec".to_string(),
+                slots: 2,
+                labels: BTreeMap::from([("env".to_string(), "dev".to_string())]),
+                common_data: None,
+                max_pending_tasks: Some(10),
+                creation_time: time,
+                completion_time: Some(time),
+                state: SessionState::Closed,
+            },
+            tasks: vec![task("1", TaskState::Succeed), task("2", TaskState::Failed)],
+        }
+    }
+
+    #[test]
+    fn test_archive_writer() {
+        let archive = archive();
+        for tasks in 0..=archive.tasks.len() {
+            let mut buf = vec![];
+            let mut writer = ArchiveWriter::new(&mut buf, &archive.session).unwrap();
+            for task in &archive.tasks[..tasks] {
+                writer.write_task(task).unwrap();
+            }
+            assert_eq!(writer.finish().unwrap(), tasks);
+
+            let read = SessionArchive::read(buf.as_slice()).unwrap();
+            assert_eq!(read.session, archive.session);
+            assert_eq!(read.tasks, archive.tasks[..tasks]);
+        }
+    }
+
+    #[test]
+    fn test_read_archive() {
+        let mut archive = archive();
+        archive.version = ARCHIVE_VERSION + 1;
+        let json = serde_json::to_string(&archive).unwrap();
+        let e = SessionArchive::read(json.as_bytes()).unwrap_err();
+        assert!(e.to_string().contains("please upgrade"), "{}", e);
+
+        let e = SessionArchive::read(r#"{"version": 1}"#.as_bytes()).unwrap_err();
+        assert!(matches!(e, FlameError::InvalidArgument(_)), "{}", e);
+    }
+
+    #[test]
+    fn test_read_archive_states() {
+        let mut json = serde_json::to_value(archive()).unwrap();
+        assert_eq!(json["session"]["state"], "closed");
+        assert_eq!(json["tasks"][1]["state"], "failed");
+        assert_eq!(json["tasks"][1]["failure"]["reason"], "timeout");
+
+        // The archives of previous versions have the names of the states.
+        json["session"]["state"] = "Closed".into();
+        json["tasks"][1]["state"] = "Failed".into();
+        json["tasks"][1]["failure"]["reason"] = "Timeout".into();
+        let read = SessionArchive::read(json.to_string().as_bytes()).unwrap();
+        assert_eq!(read, archive());
+    }
+
+    #[test]
+    fn test_rpc_round_trip() {
+        let archive = archive();
+        let ssn = ArchivedSession::try_from(rpc::Session::from(&archive.session)).unwrap();
+        assert_eq!(ssn, archive.session);
+        for task in &archive.tasks {
+            let rpc_task = task.to_rpc(&archive.session.id);
+            assert_eq!(&ArchivedTask::try_from(rpc_task).unwrap(), task);
+        }
+    }
+}
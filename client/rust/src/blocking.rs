@@ -0,0 +1,238 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The synchronous client, which owns a current-thread runtime to drive the async client.
+//! It must not be used inside an async runtime, e.g. in `#[tokio::main]`.
+
+use std::sync::Arc;
+
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::runtime::{Builder, Handle, Runtime};
+use tokio_stream::StreamExt;
+use tonic::Streaming;
+
+use crate::flame as rpc;
+use crate::{
+    Connection, ConnectionBuilder, FlameError, Session, SessionAttributes, SessionID, Task, TaskID,
+    TaskInput, TaskOutput, WatchTaskRequest,
+};
+
+#[derive(Clone)]
+pub struct BlockingConnection {
+    rt: Arc<Runtime>,
+    inner: Connection,
+}
+
+#[derive(Clone)]
+pub struct BlockingSession {
+    rt: Arc<Runtime>,
+    inner: Session,
+}
+
+/// The updates of a task, until the watch is closed by the session manager.
+pub struct TaskWatcher {
+    rt: Arc<Runtime>,
+    stream: Streaming<rpc::Task>,
+}
+
+fn runtime() -> Result<Runtime, FlameError> {
+    if Handle::try_current().is_ok() {
+        return Err(FlameError::InvalidArgument(
+            "the blocking client can not be used inside an async runtime, use the async client instead"
+                .to_string(),
+        ));
+    }
+
+    Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| FlameError::Internal(format!("failed to build runtime: {}", e)))
+}
+
+impl BlockingConnection {
+    pub fn connect(addr: &str) -> Result<Self, FlameError> {
+        Self::with_builder(ConnectionBuilder::new(addr))
+    }
+
+    pub fn with_builder(builder: ConnectionBuilder) -> Result<Self, FlameError> {
+        let rt = Arc::new(runtime()?);
+        let inner = rt.block_on(builder.connect())?;
+
+        Ok(BlockingConnection { rt, inner })
+    }
+
+    pub fn create_session(&self, attrs: &SessionAttributes) -> Result<BlockingSession, FlameError> {
+        let ssn = self.rt.block_on(self.inner.create_session(attrs))?;
+        Ok(self.session(ssn))
+    }
+
+    pub fn get_session(&self, id: &SessionID) -> Result<BlockingSession, FlameError> {
+        let ssn = self.rt.block_on(self.inner.get_session(id))?;
+        Ok(self.session(ssn))
+    }
+
+    pub fn list_session(&self) -> Result<Vec<Session>, FlameError> {
+        self.rt.block_on(self.inner.list_session())
+    }
+
+    fn session(&self, ssn: Session) -> BlockingSession {
+        BlockingSession {
+            rt: self.rt.clone(),
+            inner: ssn,
+        }
+    }
+}
+
+impl BlockingSession {
+    /// The attributes and status of the session when it's got.
+    pub fn session(&self) -> &Session {
+        &self.inner
+    }
+
+    pub fn create_task(&self, input: Option<TaskInput>) -> Result<Task, FlameError> {
+        self.rt.block_on(self.inner.create_task(input))
+    }
+
+    pub fn get_task(&self, id: TaskID) -> Result<Task, FlameError> {
+        self.rt.block_on(self.inner.get_task(id))
+    }
+
+    pub fn run_task(&self, input: impl Into<TaskInput>) -> Result<TaskOutput, FlameError> {
+        self.rt.block_on(self.inner.run_task_output(input))
+    }
+
+    pub fn run_task_json<I, O>(&self, input: &I) -> Result<O, FlameError>
+    where
+        I: Serialize,
+        O: DeserializeOwned,
+    {
+        self.rt.block_on(self.inner.run_task_json(input))
+    }
+
+    pub fn run_task_typed<I, O>(&self, input: &I) -> Result<O, FlameError>
+    where
+        I: Serialize,
+        O: DeserializeOwned,
+    {
+        self.rt.block_on(self.inner.run_task_typed(input))
+    }
+
+    pub fn watch_task(&self, id: TaskID) -> Result<TaskWatcher, FlameError> {
+        let mut client = self
+            .inner
+            .client
+            .clone()
+            .ok_or(FlameError::Internal("no flame client".to_string()))?;
+
+        let watch_task_req = WatchTaskRequest {
+            session_id: self.inner.id.clone(),
+            task_id: id,
+            include_chunks: false,
+        };
+        let stream = self
+            .rt
+            .block_on(client.watch_task(watch_task_req))?
+            .into_inner();
+
+        Ok(TaskWatcher {
+            rt: self.rt.clone(),
+            stream,
+        })
+    }
+
+    pub fn close(&self) -> Result<(), FlameError> {
+        self.rt.block_on(self.inner.close())
+    }
+}
+
+impl Iterator for TaskWatcher {
+    type Item = Result<Task, FlameError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let task = self.rt.block_on(self.stream.next())?;
+        Some(task.map(|t| Task::from(&t)).map_err(FlameError::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, MockFrontend};
+    use crate::{CacheScope, TaskState};
+
+    fn echo(input: &[u8]) -> (rpc::TaskState, Vec<u8>) {
+        match input {
+            b"fail" => (rpc::TaskState::TaskFailed, b"bad input".to_vec()),
+            _ => (rpc::TaskState::TaskSucceed, input.to_vec()),
+        }
+    }
+
+    /// Starts the mock server in a background runtime, which outlives the test.
+    fn start(frontend: MockFrontend) -> String {
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let rt = Runtime::new().unwrap();
+            rt.block_on(async move {
+                tx.send(mock::start(frontend).await.unwrap()).unwrap();
+                std::future::pending::<()>().await;
+            });
+        });
+
+        rx.recv().unwrap()
+    }
+
+    fn open() -> Result<BlockingSession, FlameError> {
+        let conn = BlockingConnection::connect(&start(MockFrontend::new(echo)))?;
+        conn.create_session(&SessionAttributes {
+            application: "mock".to_string(),
+            slots: 1,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_run_task() -> Result<(), FlameError> {
+        let ssn = open()?;
+
+        let output = ssn.run_task("hello")?;
+        assert_eq!(output, TaskOutput::from("hello"));
+
+        let res = ssn.run_task("fail");
+        assert!(matches!(res, Err(FlameError::TaskFailed { .. })));
+
+        let sum: i32 = ssn.run_task_json(&1)?;
+        assert_eq!(sum, 1);
+
+        ssn.close()
+    }
+
+    #[test]
+    fn test_watch_task() -> Result<(), FlameError> {
+        let ssn = open()?;
+
+        let task = ssn.create_task(Some(TaskInput::from("hello")))?;
+        let states = ssn
+            .watch_task(task.id)?
+            .map(|t| t.map(|t| t.state))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(states, vec![TaskState::Running, TaskState::Succeed]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_inside_runtime() {
+        let res = BlockingConnection::connect("http://127.0.0.1:8080");
+        assert!(matches!(res, Err(FlameError::InvalidArgument(_))));
+    }
+}
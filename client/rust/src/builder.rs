@@ -0,0 +1,395 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fs;
+use std::future::Future;
+use std::time::Duration;
+
+use common::ctx::FlameContext;
+use common::endpoint;
+use common::grpc::{GrpcOptions, DEFAULT_MAX_MESSAGE_SIZE};
+use tonic::transport::Endpoint;
+
+use crate::{Compression, Connection, FlameError, Interceptor};
+
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The retry policy of the idempotent calls, e.g. get, list and watch; the interval is
+/// doubled after each retry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub interval: Duration,
+    pub max_interval: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: DEFAULT_MAX_RETRIES,
+            interval: DEFAULT_RETRY_INTERVAL,
+            max_interval: MAX_RETRY_INTERVAL,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// The interval before the retry, or None if there's no more retry.
+    pub(crate) fn backoff(&self, retries: u32) -> Option<Duration> {
+        if retries >= self.max_retries {
+            return None;
+        }
+
+        let interval = self.interval.saturating_mul(2_u32.saturating_pow(retries));
+        Some(interval.min(self.max_interval))
+    }
+
+    /// Calls `f` until it succeeds, or fails with an error which is not retryable.
+    pub(crate) async fn run<F, Fut, T>(&self, f: F) -> Result<T, FlameError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, FlameError>>,
+    {
+        let mut retries = 0;
+        loop {
+            match f().await {
+                Err(e) if e.is_retryable() => match self.backoff(retries) {
+                    // The server may ask to wait longer, e.g. its backlog is full.
+                    Some(interval) => {
+                        let interval = interval.max(e.retry_after().unwrap_or_default());
+                        log::debug!("Retry after {:?}: {}", interval, e);
+                        tokio::time::sleep(interval).await;
+                        retries += 1;
+                    }
+                    None => return Err(e),
+                },
+                res => return res,
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct TlsOptions {
+    ca_certificate: Option<Vec<u8>>,
+    identity: Option<(Vec<u8>, Vec<u8>)>,
+    domain_name: Option<String>,
+}
+
+/// Builds the connection to the session manager with transport options.
+#[derive(Clone, Debug)]
+pub struct ConnectionBuilder {
+    endpoint: String,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    keepalive: Option<Duration>,
+    retry: RetryPolicy,
+    tls: Option<TlsOptions>,
+    namespace: String,
+    token: Option<String>,
+    max_send_message_size: usize,
+    max_recv_message_size: usize,
+    compression: Compression,
+}
+
+impl ConnectionBuilder {
+    pub fn new(endpoint: &str) -> Self {
+        ConnectionBuilder {
+            endpoint: endpoint.to_string(),
+            timeout: None,
+            connect_timeout: None,
+            keepalive: None,
+            retry: RetryPolicy::default(),
+            tls: None,
+            namespace: String::new(),
+            token: None,
+            max_send_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_recv_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            compression: Compression::None,
+        }
+    }
+
+    /// Builds the connection with the endpoint and the `client` options of the configuration.
+    pub fn from_context(ctx: &FlameContext) -> Result<Self, FlameError> {
+        let mut builder = ConnectionBuilder::new(&ctx.endpoint);
+        if let Some(namespace) = &ctx.namespace {
+            builder.namespace = namespace.clone();
+        }
+
+        let invalid = |e: common::FlameError| FlameError::InvalidArgument(e.to_string());
+        if let Some(grpc) = &ctx.grpc {
+            builder.max_send_message_size = grpc.max_send_message_size().map_err(invalid)?;
+            builder.max_recv_message_size = grpc.max_recv_message_size().map_err(invalid)?;
+            builder.compression = grpc.compression().map_err(invalid)?;
+        }
+
+        let Some(conf) = &ctx.client else {
+            return Ok(builder);
+        };
+
+        builder.timeout = conf.timeout().map_err(invalid)?;
+        builder.connect_timeout = conf.connect_timeout().map_err(invalid)?;
+        builder.keepalive = conf.keepalive().map_err(invalid)?;
+        if let Some(retries) = conf.retries {
+            builder.retry.max_retries = retries;
+        }
+        builder.token = conf.token.clone();
+
+        if let Some(tls) = &conf.tls {
+            if let Some(ca_file) = &tls.ca_file {
+                builder = builder.ca_certificate(read_pem(ca_file)?);
+            }
+            if let (Some(cert_file), Some(key_file)) = (&tls.cert_file, &tls.key_file) {
+                builder = builder.identity(read_pem(cert_file)?, read_pem(key_file)?);
+            }
+            if let Some(domain) = &tls.domain {
+                builder = builder.domain_name(domain);
+            }
+        }
+
+        Ok(builder)
+    }
+
+    /// The timeout of each RPC, which is also sent to the session manager, see
+    /// `Connection::with_timeout`; the watch of a task is not limited by it.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// The interval of HTTP/2 keepalive pings, which are also sent when the connection is idle.
+    pub fn keepalive(mut self, interval: Duration) -> Self {
+        self.keepalive = Some(interval);
+        self
+    }
+
+    /// The namespace of the sessions; the default one of the caller if it's not set.
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespace = namespace.to_string();
+        self
+    }
+
+    /// The bearer token to authenticate to the session manager.
+    pub fn token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// The maximum size of a sent message; the larger task inputs are rejected before sending.
+    pub fn max_send_message_size(mut self, size: usize) -> Self {
+        self.max_send_message_size = size;
+        self
+    }
+
+    /// The maximum size of a received message, e.g. the output of a task.
+    pub fn max_recv_message_size(mut self, size: usize) -> Self {
+        self.max_recv_message_size = size;
+        self
+    }
+
+    /// The compression of the sent messages; the codec must be enabled by the feature of the
+    /// same name, e.g. `gzip`.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// The CA bundle in PEM to verify the session manager.
+    pub fn ca_certificate(mut self, pem: Vec<u8>) -> Self {
+        self.tls.get_or_insert_with(Default::default).ca_certificate = Some(pem);
+        self
+    }
+
+    /// The certificate and key in PEM of the client, for mutual TLS.
+    pub fn identity(mut self, cert: Vec<u8>, key: Vec<u8>) -> Self {
+        self.tls.get_or_insert_with(Default::default).identity = Some((cert, key));
+        self
+    }
+
+    pub fn domain_name(mut self, domain: &str) -> Self {
+        self.tls.get_or_insert_with(Default::default).domain_name = Some(domain.to_string());
+        self
+    }
+
+    pub async fn connect(self) -> Result<Connection, FlameError> {
+        let addr = self.endpoint.clone();
+        let mut endpoint = endpoint::to_endpoint(&addr)
+            .map_err(|_| FlameError::InvalidArgument(format!("invalid address <{}>", addr)))?;
+
+        if let Some(timeout) = self.timeout {
+            endpoint = endpoint.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            endpoint = endpoint.connect_timeout(timeout);
+        }
+        if let Some(interval) = self.keepalive {
+            endpoint = endpoint
+                .http2_keep_alive_interval(interval)
+                .keep_alive_while_idle(true);
+        }
+        if let Some(tls) = &self.tls {
+            endpoint = with_tls(endpoint, tls)?;
+        }
+
+        let grpc = GrpcOptions {
+            max_send_message_size: self.max_send_message_size,
+            max_recv_message_size: self.max_recv_message_size,
+            compression: self
+                .compression
+                .encoding()
+                .map_err(|e| FlameError::InvalidArgument(e.to_string()))?,
+        };
+
+        let token = match &self.token {
+            Some(token) => Some(
+                format!("Bearer {}", token)
+                    .parse()
+                    .map_err(|_| FlameError::InvalidArgument("invalid token".to_string()))?,
+            ),
+            None => None,
+        };
+
+        let channel =
+            endpoint::connect(&addr, endpoint)
+                .await
+                .map_err(|e| FlameError::Unavailable {
+                    message: format!("failed to connect <{}>: {}", addr, e),
+                    retryable: true,
+                })?;
+
+        Connection {
+            channel,
+            retry: self.retry,
+            interceptor: Interceptor {
+                token,
+                timeout: self.timeout,
+                deadline: None,
+            },
+            namespace: self.namespace,
+            server: Default::default(),
+            grpc,
+        }
+        .negotiate()
+        .await
+    }
+}
+
+fn read_pem(path: &str) -> Result<Vec<u8>, FlameError> {
+    fs::read(path).map_err(|e| FlameError::InvalidArgument(format!("<{}>: {}", path, e)))
+}
+
+#[cfg(feature = "tls")]
+fn with_tls(endpoint: Endpoint, tls: &TlsOptions) -> Result<Endpoint, FlameError> {
+    use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+
+    let mut config = ClientTlsConfig::new();
+    if let Some(ca) = &tls.ca_certificate {
+        config = config.ca_certificate(Certificate::from_pem(ca));
+    }
+    if let Some((cert, key)) = &tls.identity {
+        config = config.identity(Identity::from_pem(cert, key));
+    }
+    if let Some(domain) = &tls.domain_name {
+        config = config.domain_name(domain);
+    }
+
+    endpoint
+        .tls_config(config)
+        .map_err(|e| FlameError::InvalidArgument(format!("invalid TLS options: {}", e)))
+}
+
+#[cfg(not(feature = "tls"))]
+fn with_tls(_: Endpoint, _: &TlsOptions) -> Result<Endpoint, FlameError> {
+    Err(FlameError::InvalidArgument(
+        "TLS is not supported, please build flame-client with the `tls` feature".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_retries: 4,
+            interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(5),
+        };
+
+        let intervals: Vec<Option<Duration>> = (0..5).map(|i| policy.backoff(i)).collect();
+        assert_eq!(
+            intervals,
+            vec![
+                Some(Duration::from_secs(1)),
+                Some(Duration::from_secs(2)),
+                Some(Duration::from_secs(4)),
+                Some(Duration::from_secs(5)),
+                None,
+            ]
+        );
+        assert_eq!(RetryPolicy::none().backoff(0), None);
+    }
+
+    #[test]
+    fn test_from_context() {
+        let mut ctx = FlameContext::default();
+        assert_eq!(
+            ConnectionBuilder::from_context(&ctx).unwrap().retry,
+            RetryPolicy::default()
+        );
+
+        ctx.namespace = Some("team-a".to_string());
+        ctx.client = Some(common::ctx::FlameClientConf {
+            timeout: Some("30s".to_string()),
+            keepalive: Some("1m".to_string()),
+            retries: Some(0),
+            token: Some("secret".to_string()),
+            ..Default::default()
+        });
+        let builder = ConnectionBuilder::from_context(&ctx).unwrap();
+        assert_eq!(builder.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(builder.keepalive, Some(Duration::from_secs(60)));
+        assert_eq!(builder.retry, RetryPolicy::none());
+        assert_eq!(builder.namespace, "team-a");
+        assert_eq!(builder.token.as_deref(), Some("secret"));
+
+        ctx.client = Some(common::ctx::FlameClientConf {
+            timeout: Some("30".to_string()),
+            ..Default::default()
+        });
+        assert!(matches!(
+            ConnectionBuilder::from_context(&ctx),
+            Err(FlameError::InvalidArgument(_))
+        ));
+    }
+}
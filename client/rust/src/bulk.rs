@@ -0,0 +1,141 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+
+use crate::{FlameError, Session, Task, TaskID, TaskInput, TaskOutput};
+
+/// The results of `Session::map`, tagged with the index of their inputs.
+pub struct TaskResults<'a> {
+    len: usize,
+    inner: BoxStream<'a, (usize, Result<TaskOutput, FlameError>)>,
+}
+
+impl Stream for TaskResults<'_> {
+    type Item = (usize, Result<TaskOutput, FlameError>);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+impl TaskResults<'_> {
+    /// Waits for all the results, and returns them in the order of inputs.
+    pub async fn collect_ordered(mut self) -> Vec<Result<TaskOutput, FlameError>> {
+        let mut results: Vec<Option<Result<TaskOutput, FlameError>>> =
+            (0..self.len).map(|_| None).collect();
+
+        while let Some((index, res)) = self.inner.next().await {
+            results[index] = Some(res);
+        }
+
+        results
+            .into_iter()
+            .map(|res| res.unwrap_or(Err(FlameError::Internal("no task result".to_string()))))
+            .collect()
+    }
+}
+
+impl Session {
+    /// Submits a task for each input, and yields their results as they're completed. At most
+    /// `concurrency` tasks are submitted or watched at the same time; the submission pauses
+    /// for the hint of the server if the backlog of the session is full. The results which
+    /// are not ready by the deadline of the session are `FlameError::Expired`, see
+    /// `with_deadline`.
+    pub fn map<I>(&self, inputs: I, concurrency: usize) -> TaskResults<'_>
+    where
+        I: IntoIterator<Item = TaskInput>,
+    {
+        let inputs: Vec<TaskInput> = inputs.into_iter().collect();
+        let len = inputs.len();
+        let concurrency = concurrency.max(1);
+
+        let submit = stream::iter(inputs.into_iter().enumerate())
+            .map(move |(index, input)| async move {
+                let task = self.submit(input).await;
+                (index, expire(task, None))
+            })
+            .buffered(concurrency)
+            .collect::<Vec<_>>();
+
+        let inner = stream::once(submit)
+            .flat_map(stream::iter)
+            .map(move |(index, task)| async move {
+                match task {
+                    Ok(task) => {
+                        let id = task.id.clone();
+                        (index, expire(self.task_output(task).await, Some(id)))
+                    }
+                    Err(e) => (index, Err(e)),
+                }
+            })
+            .buffer_unordered(concurrency)
+            .boxed();
+
+        TaskResults { len, inner }
+    }
+
+    /// Waits for all the tasks of the session, and returns their outputs in the order of their
+    /// indexes, i.e. the order of their creation even if they were created concurrently; it
+    /// requires `capability::TASK_INDEX`. The outputs which are not ready by the deadline of
+    /// the session are `FlameError::Expired`.
+    pub async fn collect_ordered(&self) -> Result<Vec<Result<TaskOutput, FlameError>>, FlameError> {
+        let mut outputs = vec![];
+        for task in self.list_tasks().await? {
+            let id = task.id.clone();
+            outputs.push(expire(self.task_output(task).await, Some(id)));
+        }
+
+        Ok(outputs)
+    }
+
+    /// Creates the task, and waits for the backlog of the session to drain if it's full.
+    async fn submit(&self, input: TaskInput) -> Result<Task, FlameError> {
+        loop {
+            match self.create_task(Some(input.clone())).await {
+                Err(e) => match e.retry_after() {
+                    // The task would not be created by the deadline anyway.
+                    Some(interval) if self.expires_within(interval) => {
+                        return Err(FlameError::DeadlineExceeded(e.to_string()))
+                    }
+                    Some(interval) => {
+                        log::debug!("Resubmit after {:?}: {}", interval, e);
+                        tokio::time::sleep(interval).await;
+                    }
+                    None => return Err(e),
+                },
+                res => return res,
+            }
+        }
+    }
+
+    /// Whether the deadline of the session passes within the interval.
+    fn expires_within(&self, interval: Duration) -> bool {
+        self.interceptor
+            .deadline
+            .is_some_and(|deadline| Instant::now() + interval >= deadline)
+    }
+}
+
+/// Marks the result which is not ready by the deadline as expired, with the id of its task if
+/// the task was created.
+fn expire<T>(res: Result<T, FlameError>, task_id: Option<TaskID>) -> Result<T, FlameError> {
+    match res {
+        Err(FlameError::DeadlineExceeded(_)) => Err(FlameError::Expired { task_id }),
+        res => res,
+    }
+}
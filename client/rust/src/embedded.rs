@@ -0,0 +1,328 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The embedded mode for the development of applications: the session manager and one executor
+//! run in process, and the tasks are executed by the services of the applications, e.g.
+//!
+//! ```ignore
+//! let conn = embedded::start([("pi", service_fn(|ctx| Ok(ctx.input.clone())))]).await?;
+//! let ssn = conn.create_session(&attrs).await?;
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+pub use common::apis::{SessionContext, TaskContext};
+
+use crate::flame as rpc;
+use crate::testkit::MockServer;
+use crate::{Application, Connection, FlameError, Shim, TaskOutput};
+
+pub type ServicePtr = Arc<Mutex<dyn Service>>;
+
+/// The service of an application, which is called by the executor as its shim.
+#[async_trait]
+pub trait Service: Send + Sync + 'static {
+    async fn on_session_enter(&mut self, _: &SessionContext) -> Result<(), FlameError> {
+        Ok(())
+    }
+    async fn on_task_invoke(&mut self, ctx: &TaskContext)
+        -> Result<Option<TaskOutput>, FlameError>;
+    async fn on_session_leave(&mut self) -> Result<(), FlameError> {
+        Ok(())
+    }
+}
+
+struct ServiceFn<F> {
+    f: F,
+}
+
+#[async_trait]
+impl<F> Service for ServiceFn<F>
+where
+    F: Fn(&TaskContext) -> Result<Option<TaskOutput>, FlameError> + Send + Sync + 'static,
+{
+    async fn on_task_invoke(
+        &mut self,
+        ctx: &TaskContext,
+    ) -> Result<Option<TaskOutput>, FlameError> {
+        (self.f)(ctx)
+    }
+}
+
+/// The service which invokes the closure for each task.
+pub fn service_fn<F>(f: F) -> ServicePtr
+where
+    F: Fn(&TaskContext) -> Result<Option<TaskOutput>, FlameError> + Send + Sync + 'static,
+{
+    Arc::new(Mutex::new(ServiceFn { f }))
+}
+
+/// Starts the embedded session manager with the applications, and connects to it. The
+/// executor runs one task at a time, and leaves the bound session when it gets a task of
+/// another session.
+pub async fn start<I, S>(apps: I) -> Result<Connection, FlameError>
+where
+    I: IntoIterator<Item = (S, ServicePtr)>,
+    S: Into<String>,
+{
+    let apps: BTreeMap<String, ServicePtr> = apps
+        .into_iter()
+        .map(|(name, service)| (name.into(), service))
+        .collect();
+
+    let server = MockServer::start().await?;
+    let conn = server.connect_in_memory().await?;
+    for name in apps.keys() {
+        let app = Application {
+            name: name.clone(),
+            shim: Shim::Log,
+            ..Default::default()
+        };
+        conn.register_application(&app).await?;
+    }
+
+    let executor = Executor {
+        server,
+        apps,
+        bound: None,
+    };
+    tokio::spawn(executor.run());
+
+    Ok(conn)
+}
+
+struct Executor {
+    server: MockServer,
+    apps: BTreeMap<String, ServicePtr>,
+    /// The session bound to the executor, and its service.
+    bound: Option<(String, ServicePtr)>,
+}
+
+impl Executor {
+    async fn run(mut self) {
+        loop {
+            let (ssn, task) = self.server.run_next_task().await;
+            let ssn_id = ssn.metadata.clone().unwrap_or_default().id;
+            let task_id = task.metadata.clone().unwrap_or_default().id;
+
+            let (state, output) = match self.invoke(ssn, task).await {
+                Ok(output) => (rpc::TaskState::TaskSucceed, output.unwrap_or_default()),
+                Err(e) => {
+                    log::debug!("Task <{}/{}> failed: {}", ssn_id, task_id, e);
+                    (rpc::TaskState::TaskFailed, e.to_string().into())
+                }
+            };
+
+            self.server.finish_task(&ssn_id, &task_id, state, &output);
+        }
+    }
+
+    async fn invoke(
+        &mut self,
+        ssn: rpc::Session,
+        task: rpc::Task,
+    ) -> Result<Option<TaskOutput>, FlameError> {
+        let ssn_id = ssn.metadata.unwrap_or_default().id;
+        let spec = ssn.spec.unwrap_or_default();
+
+        let service = match &self.bound {
+            Some((id, service)) if *id == ssn_id => service.clone(),
+            _ => {
+                self.leave().await;
+
+                let Some(service) = self.apps.get(&spec.application).cloned() else {
+                    return Err(FlameError::NotFound(format!(
+                        "application <{}>",
+                        spec.application
+                    )));
+                };
+                let ctx = SessionContext {
+                    ssn_id: ssn_id.clone(),
+                    application: spec.application,
+                    slots: spec.slots,
+                    common_data: spec.common_data.map(TaskOutput::from),
+                    config: spec.config.into_iter().collect(),
+                };
+                service.lock().await.on_session_enter(&ctx).await?;
+
+                self.bound = Some((ssn_id.clone(), service.clone()));
+                service
+            }
+        };
+
+        let spec = task.spec.unwrap_or_default();
+        let ctx = TaskContext {
+            id: task.metadata.unwrap_or_default().id,
+            ssn_id,
+            input: spec.input.map(TaskOutput::from),
+            output: None,
+            trace_context: spec.trace_context,
+            locality: spec.locality,
+            env: spec.env,
+        };
+        let mut service = service.lock().await;
+        service.on_task_invoke(&ctx).await
+    }
+
+    async fn leave(&mut self) {
+        if let Some((ssn_id, service)) = self.bound.take() {
+            if let Err(e) = service.lock().await.on_session_leave().await {
+                log::warn!("Failed to leave session <{}>: {}", ssn_id, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SessionAttributes;
+
+    struct Counter {
+        sessions: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Service for Counter {
+        async fn on_session_enter(&mut self, ctx: &SessionContext) -> Result<(), FlameError> {
+            self.sessions.lock().unwrap().push(ctx.ssn_id.clone());
+            Ok(())
+        }
+
+        async fn on_task_invoke(
+            &mut self,
+            ctx: &TaskContext,
+        ) -> Result<Option<TaskOutput>, FlameError> {
+            let n = self.sessions.lock().unwrap().len();
+            Ok(Some(format!("{}:{}", ctx.ssn_id, n).into()))
+        }
+    }
+
+    fn attrs(application: &str) -> SessionAttributes {
+        SessionAttributes {
+            application: application.to_string(),
+            slots: 1,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embedded() -> Result<(), FlameError> {
+        let sessions = Arc::new(std::sync::Mutex::new(vec![]));
+        let counter: ServicePtr = Arc::new(Mutex::new(Counter {
+            sessions: sessions.clone(),
+        }));
+        let upper = service_fn(|ctx| {
+            let input = ctx.input.clone().unwrap_or_default();
+            Ok(Some(input.to_ascii_uppercase().into()))
+        });
+
+        let conn = start([("counter", counter), ("upper", upper)]).await?;
+        assert_eq!(conn.list_application().await?.len(), 2);
+
+        let ssn = conn.create_session(&attrs("upper")).await?;
+        assert_eq!(ssn.run_task_output("flame").await?, "FLAME");
+
+        let ssn1 = conn.create_session(&attrs("counter")).await?;
+        assert_eq!(ssn1.run_task_output("").await?, format!("{}:1", ssn1.id));
+        assert_eq!(ssn1.run_task_output("").await?, format!("{}:1", ssn1.id));
+        let ssn2 = conn.create_session(&attrs("counter")).await?;
+        assert_eq!(ssn2.run_task_output("").await?, format!("{}:2", ssn2.id));
+        assert_eq!(*sessions.lock().unwrap(), vec![ssn1.id, ssn2.id]);
+
+        let ssn = conn.create_session(&attrs("unknown")).await?;
+        assert!(matches!(
+            ssn.run_task_output("").await,
+            Err(FlameError::TaskFailed { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct Sum {
+        a: i64,
+        b: i64,
+    }
+
+    #[tokio::test]
+    async fn test_typed_message() -> Result<(), FlameError> {
+        let sum = service_fn(|ctx| {
+            let Sum { a, b } = ctx.input_as()?;
+            Ok(Some(crate::message::encode(Default::default(), &(a + b))?))
+        });
+
+        let conn = start([("sum", sum)]).await?;
+        let ssn = conn.create_session(&attrs("sum")).await?;
+        assert_eq!(ssn.run_task_typed::<_, i64>(&Sum { a: 1, b: 2 }).await?, 3);
+
+        // The raw payload is not an envelope, so the task fails instead of the service.
+        match ssn.run_task_output(r#"{"a":1,"b":2}"#).await {
+            Err(FlameError::TaskFailed { message, .. }) => {
+                assert!(
+                    message.contains("unsupported envelope version"),
+                    "{}",
+                    message
+                )
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert_eq!(ssn.run_task_typed::<_, i64>(&Sum { a: 2, b: 3 }).await?, 5);
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_collect_ordered() -> Result<(), FlameError> {
+        let upper = service_fn(|ctx| {
+            let input = ctx.input.clone().unwrap_or_default();
+            Ok(Some(input.to_ascii_uppercase().into()))
+        });
+        let conn = start([("upper", upper)]).await?;
+        let ssn = conn.create_session(&attrs("upper")).await?;
+
+        // The tasks are submitted concurrently, so their order is only known by the indexes.
+        let submitters = (0..4).map(|i| {
+            let ssn = ssn.clone();
+            tokio::spawn(async move {
+                let mut inputs = vec![];
+                for j in 0..25 {
+                    let input = format!("chunk-{}-{}", i, j);
+                    let task = ssn.create_task(Some(input.clone().into())).await?;
+                    inputs.push((task.index, input));
+                }
+                Ok::<_, FlameError>(inputs)
+            })
+        });
+        let mut inputs = BTreeMap::new();
+        for submitter in futures::future::join_all(submitters).await {
+            inputs.extend(submitter.unwrap()?);
+        }
+        assert_eq!(
+            inputs.keys().copied().collect::<Vec<_>>(),
+            (0..100).collect::<Vec<_>>()
+        );
+
+        let outputs = ssn.collect_ordered().await?;
+        assert_eq!(outputs.len(), inputs.len());
+        for (output, input) in outputs.into_iter().zip(inputs.values()) {
+            assert_eq!(output?, input.to_ascii_uppercase());
+        }
+
+        Ok(())
+    }
+}
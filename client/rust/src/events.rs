@@ -0,0 +1,477 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+
+use crate::flame as rpc;
+use crate::flame::session_event::Event;
+use crate::{
+    capability, FlameClient, FlameError, GetSessionRequest, RetryPolicy, Session, SessionID,
+    TaskID, TaskProgress, TaskState, WatchSessionRequest,
+};
+
+/// The number of events buffered for the consumer by default.
+const DEFAULT_EVENT_BUFFER: usize = 1024;
+
+/// The interval to poll the state of the session, if the session manager can not watch it.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionEvent {
+    TaskStateChanged {
+        sequence: u64,
+        task_id: TaskID,
+        state: TaskState,
+    },
+    /// The progress reported by the executor of a running task.
+    TaskProgress {
+        sequence: u64,
+        task_id: TaskID,
+        progress: TaskProgress,
+    },
+    /// The session passed its deadline; its pending and running tasks are failed as timed
+    /// out, and it's closed right after.
+    SessionExpired {
+        sequence: u64,
+        deadline: DateTime<Utc>,
+    },
+    /// The session had no tasks nor calls of its client since `last_activity`, so it's closed
+    /// right after as abandoned.
+    SessionOrphaned {
+        sequence: u64,
+        last_activity: DateTime<Utc>,
+    },
+    /// The executor was quarantined after failing the task; the task is requeued.
+    ExecutorQuarantined {
+        sequence: u64,
+        executor_id: String,
+        task_id: TaskID,
+    },
+    /// The last event of the session.
+    SessionClosed { sequence: u64 },
+    /// Some events were dropped, because the buffer was full or the session manager did not
+    /// keep them any more.
+    Overflow { dropped: u64 },
+}
+
+/// An event in the history of a session, see `Session::history`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionHistoryEvent {
+    pub event_time: DateTime<Utc>,
+    pub event: SessionEvent,
+}
+
+/// The events of a session, see `Session::events`.
+pub struct SessionEvents {
+    inner: ReceiverStream<Result<SessionEvent, FlameError>>,
+}
+
+impl Stream for SessionEvents {
+    type Item = Result<SessionEvent, FlameError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
+
+impl Session {
+    /// Subscribes to the events of the session, until it's closed. The watch is resumed from
+    /// the last seen event if it's dropped by a transient failure; it must be called in a tokio
+    /// runtime. If the session manager does not support `capability::WATCH_SESSION`, the state
+    /// of the session is polled instead, and only `SessionClosed` is reported.
+    pub fn events(&self) -> SessionEvents {
+        self.events_with_buffer(DEFAULT_EVENT_BUFFER)
+    }
+
+    /// Subscribes to the events with at most `size` events buffered; the events received
+    /// when the buffer is full are dropped, and reported by an `Overflow` event.
+    pub fn events_with_buffer(&self, size: usize) -> SessionEvents {
+        let (tx, rx) = mpsc::channel(size.max(1));
+        let pump = EventPump {
+            client: self.client.clone(),
+            ssn_id: self.id.clone(),
+            namespace: self.namespace.clone(),
+            retry: self.retry.clone(),
+            watch: self
+                .server
+                .as_ref()
+                .is_none_or(|s| s.supports(capability::WATCH_SESSION)),
+            tx,
+            since: 0,
+            dropped: 0,
+        };
+        tokio::spawn(pump.run());
+
+        SessionEvents {
+            inner: ReceiverStream::new(rx),
+        }
+    }
+
+    /// The persisted events of the session in `[since, until)` from the oldest to the latest,
+    /// e.g. to find out what happened to a closed session; the oldest events of the long
+    /// sessions are evicted. It requires `capability::SESSION_HISTORY`.
+    pub async fn history(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SessionHistoryEvent>, FlameError> {
+        let client = self
+            .client
+            .clone()
+            .ok_or(FlameError::Internal("no flame client".to_string()))?;
+
+        let events = self
+            .retry
+            .run(|| {
+                let mut client = client.clone();
+                let get_events_req = rpc::GetSessionEventsRequest {
+                    session_id: self.id.clone(),
+                    namespace: self.namespace.clone(),
+                    since: since.map(|t| t.timestamp()),
+                    until: until.map(|t| t.timestamp()),
+                };
+                async move {
+                    client
+                        .get_session_events(get_events_req)
+                        .await
+                        .map_err(FlameError::from)
+                }
+            })
+            .await?
+            .into_inner();
+
+        Ok(events
+            .events
+            .iter()
+            .filter_map(|e| {
+                Some(SessionHistoryEvent {
+                    event_time: DateTime::<Utc>::from_timestamp(e.event_time, 0)?,
+                    event: SessionEvent::from_rpc(e)?,
+                })
+            })
+            .collect())
+    }
+}
+
+/// Forwards the events from the session manager to the consumer.
+struct EventPump {
+    client: Option<FlameClient>,
+    ssn_id: SessionID,
+    namespace: String,
+    retry: RetryPolicy,
+    /// Whether the session manager supports WatchSession.
+    watch: bool,
+    tx: mpsc::Sender<Result<SessionEvent, FlameError>>,
+    /// The sequence of the last seen event.
+    since: u64,
+    dropped: u64,
+}
+
+impl EventPump {
+    async fn run(mut self) {
+        let Some(mut client) = self.client.clone() else {
+            let _ = self
+                .tx
+                .send(Err(FlameError::Internal("no flame client".to_string())))
+                .await;
+            return;
+        };
+
+        if !self.watch {
+            if let Err(e) = self.poll(&client).await {
+                let _ = self.tx.send(Err(e)).await;
+            }
+            return;
+        }
+
+        let mut retries = 0;
+        loop {
+            let since = self.since;
+            let e = match self.watch(&mut client).await {
+                Ok(true) => return,
+                Ok(false) => FlameError::Unavailable {
+                    message: format!(
+                        "watch of session <{}> was closed before the session is closed",
+                        self.ssn_id
+                    ),
+                    retryable: true,
+                },
+                Err(e) => e,
+            };
+            if self.since != since {
+                retries = 0;
+            }
+
+            let interval = match e.is_retryable() {
+                true => self.retry.backoff(retries),
+                false => None,
+            };
+            let Some(interval) = interval else {
+                let _ = self.tx.send(Err(e)).await;
+                return;
+            };
+
+            log::debug!(
+                "Re-watch session <{}> from <{}> after {:?}: {}",
+                self.ssn_id,
+                self.since,
+                interval,
+                e
+            );
+            tokio::time::sleep(interval).await;
+            retries += 1;
+        }
+    }
+
+    /// Returns true if the session was closed or the consumer is gone, or false if the watch
+    /// was closed before that.
+    async fn watch(&mut self, client: &mut FlameClient) -> Result<bool, FlameError> {
+        let watch_ssn_req = WatchSessionRequest {
+            session_id: self.ssn_id.clone(),
+            namespace: self.namespace.clone(),
+            since: self.since,
+        };
+        let mut stream = client.watch_session(watch_ssn_req).await?.into_inner();
+
+        loop {
+            let event = tokio::select! {
+                event = stream.next() => event,
+                _ = self.tx.closed() => return Ok(true),
+            };
+            let Some(event) = event else {
+                return Ok(false);
+            };
+            let event = event?;
+
+            // A sequence not after the last seen one means the session manager was restarted.
+            if event.sequence > self.since + 1 {
+                self.dropped += event.sequence - self.since - 1;
+            }
+            self.since = event.sequence;
+
+            let Some(event) = SessionEvent::from_rpc(&event) else {
+                continue;
+            };
+            let closed = matches!(event, SessionEvent::SessionClosed { .. });
+            if !self.emit(event, closed).await || closed {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Polls the state of the session until it's closed or the consumer is gone.
+    async fn poll(&mut self, client: &FlameClient) -> Result<(), FlameError> {
+        loop {
+            let ssn = self
+                .retry
+                .run(|| {
+                    let mut client = client.clone();
+                    let get_ssn_req = GetSessionRequest {
+                        session_id: self.ssn_id.clone(),
+                        namespace: self.namespace.clone(),
+                    };
+                    async move {
+                        client
+                            .get_session(get_ssn_req)
+                            .await
+                            .map_err(FlameError::from)
+                    }
+                })
+                .await?
+                .into_inner();
+
+            let state = ssn.status.map(|s| s.state).unwrap_or_default();
+            if state == rpc::SessionState::SessionClosed as i32 {
+                self.emit(SessionEvent::SessionClosed { sequence: 0 }, true)
+                    .await;
+                return Ok(());
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {},
+                _ = self.tx.closed() => return Ok(()),
+            }
+        }
+    }
+
+    /// Sends the event to the consumer, or drops it if the buffer is full unless `wait`.
+    /// Returns false if the consumer is gone.
+    async fn emit(&mut self, event: SessionEvent, wait: bool) -> bool {
+        if self.dropped > 0 {
+            let overflow = Ok(SessionEvent::Overflow {
+                dropped: self.dropped,
+            });
+            match self.tx.try_send(overflow) {
+                Ok(_) => self.dropped = 0,
+                Err(TrySendError::Full(overflow)) if wait => {
+                    if self.tx.send(overflow).await.is_err() {
+                        return false;
+                    }
+                    self.dropped = 0;
+                }
+                Err(TrySendError::Full(_)) => {
+                    self.dropped += 1;
+                    return true;
+                }
+                Err(TrySendError::Closed(_)) => return false,
+            }
+        }
+
+        if wait {
+            return self.tx.send(Ok(event)).await.is_ok();
+        }
+
+        match self.tx.try_send(Ok(event)) {
+            Ok(_) => true,
+            Err(TrySendError::Full(_)) => {
+                self.dropped += 1;
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+}
+
+impl SessionEvent {
+    /// Returns None for the unknown events, e.g. from a newer session manager.
+    fn from_rpc(event: &rpc::SessionEvent) -> Option<Self> {
+        match event.event.as_ref()? {
+            Event::TaskStateChanged(changed) => Some(SessionEvent::TaskStateChanged {
+                sequence: event.sequence,
+                task_id: changed.task_id.clone(),
+                state: TaskState::try_from(changed.state).unwrap_or(TaskState::default()),
+            }),
+            Event::TaskProgress(progressed) => Some(SessionEvent::TaskProgress {
+                sequence: event.sequence,
+                task_id: progressed.task_id.clone(),
+                progress: TaskProgress::from(progressed.progress.as_ref()?),
+            }),
+            Event::SessionClosed(_) => Some(SessionEvent::SessionClosed {
+                sequence: event.sequence,
+            }),
+            Event::SessionExpired(expired) => Some(SessionEvent::SessionExpired {
+                sequence: event.sequence,
+                deadline: DateTime::<Utc>::from_timestamp(expired.deadline, 0)?,
+            }),
+            Event::SessionOrphaned(orphaned) => Some(SessionEvent::SessionOrphaned {
+                sequence: event.sequence,
+                last_activity: DateTime::<Utc>::from_timestamp(orphaned.last_activity, 0)?,
+            }),
+            Event::ExecutorQuarantined(quarantined) => Some(SessionEvent::ExecutorQuarantined {
+                sequence: event.sequence,
+                executor_id: quarantined.executor_id.clone(),
+                task_id: quarantined.task_id.clone(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, MockFrontend};
+
+    fn echo(input: &[u8]) -> (rpc::TaskState, Vec<u8>) {
+        (rpc::TaskState::TaskSucceed, input.to_vec())
+    }
+
+    /// Runs two tasks and closes the session, which makes 5 events.
+    async fn run(ssn: &Session) -> Result<(), FlameError> {
+        ssn.run_task_output("1").await?;
+        ssn.run_task_output("2").await?;
+        ssn.close().await
+    }
+
+    fn changed(sequence: u64, task_id: &str, state: TaskState) -> SessionEvent {
+        SessionEvent::TaskStateChanged {
+            sequence,
+            task_id: task_id.to_string(),
+            state,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_events_with_reconnection() -> Result<(), FlameError> {
+        let mock = MockFrontend::new(echo).with_session_drops(1);
+        let ssn = mock::open(mock.clone()).await?;
+
+        let events = ssn.events();
+        run(&ssn).await?;
+
+        let events = events.collect::<Result<Vec<_>, _>>().await?;
+        assert_eq!(
+            events,
+            vec![
+                changed(1, "1", TaskState::Pending),
+                changed(2, "1", TaskState::Succeed),
+                changed(3, "2", TaskState::Pending),
+                changed(4, "2", TaskState::Succeed),
+                SessionEvent::SessionClosed { sequence: 5 },
+            ]
+        );
+        assert_eq!(mock.session_watches(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_events_overflow() -> Result<(), FlameError> {
+        let ssn = mock::open(MockFrontend::new(echo)).await?;
+        run(&ssn).await?;
+
+        let events = ssn.events_with_buffer(2);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let events = events.collect::<Result<Vec<_>, _>>().await?;
+        assert_eq!(
+            events,
+            vec![
+                changed(1, "1", TaskState::Pending),
+                changed(2, "1", TaskState::Succeed),
+                SessionEvent::Overflow { dropped: 2 },
+                SessionEvent::SessionClosed { sequence: 5 },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_events_of_old_server() -> Result<(), FlameError> {
+        // The session manager without GetServerInfo is considered to support no capabilities.
+        let mock = MockFrontend::new(echo).with_capabilities(None);
+        let ssn = mock::open(mock.clone()).await?;
+        assert!(!ssn
+            .server
+            .as_ref()
+            .unwrap()
+            .supports(capability::WATCH_SESSION));
+
+        let events = ssn.events();
+        run(&ssn).await?;
+
+        let events = events.collect::<Result<Vec<_>, _>>().await?;
+        assert_eq!(events, vec![SessionEvent::SessionClosed { sequence: 0 }]);
+        assert_eq!(mock.session_watches(), 0);
+
+        Ok(())
+    }
+}
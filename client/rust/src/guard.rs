@@ -0,0 +1,142 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::ops::Deref;
+
+use tokio::runtime::Handle;
+
+use crate::{FlameError, Session};
+
+/// Closes the session when it's dropped, so the session is not leaked if the client returns
+/// early or panics.
+///
+/// The close on drop is best effort: it's spawned onto the current tokio runtime and not
+/// waited, so it may not finish if the runtime is shutting down; and the session is left open
+/// if there's no runtime at all. Call `close()` for a deterministic cleanup.
+pub struct SessionGuard {
+    ssn: Option<Session>,
+}
+
+impl Session {
+    /// Returns a guard which closes the session when it's dropped.
+    pub fn scoped(self) -> SessionGuard {
+        SessionGuard { ssn: Some(self) }
+    }
+}
+
+impl SessionGuard {
+    /// Closes the session, and waits for the result.
+    pub async fn close(mut self) -> Result<(), FlameError> {
+        match self.ssn.take() {
+            Some(ssn) => ssn.close().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the session without closing it.
+    pub fn into_inner(mut self) -> Session {
+        self.ssn.take().expect("session of guard")
+    }
+}
+
+impl Deref for SessionGuard {
+    type Target = Session;
+
+    fn deref(&self) -> &Self::Target {
+        self.ssn.as_ref().expect("session of guard")
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let Some(ssn) = self.ssn.take() else {
+            return;
+        };
+
+        match Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    if let Err(e) = ssn.close().await {
+                        log::warn!("Failed to close session <{}> on drop: {}", ssn.id, e);
+                    }
+                });
+            }
+            Err(_) => log::warn!(
+                "No runtime to close session <{}> on drop, it's left open.",
+                ssn.id
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::flame as rpc;
+    use crate::mock::{self, MockFrontend};
+
+    fn echo(input: &[u8]) -> (rpc::TaskState, Vec<u8>) {
+        (rpc::TaskState::TaskSucceed, input.to_vec())
+    }
+
+    /// Waits for the close spawned by the guard.
+    async fn wait_closes(mock: &MockFrontend, closes: usize) {
+        for _ in 0..100 {
+            if mock.closes() >= closes {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_close_on_drop() -> Result<(), FlameError> {
+        let mock = MockFrontend::new(echo);
+        let ssn = mock::open(mock.clone()).await?;
+
+        {
+            let ssn = ssn.clone().scoped();
+            assert!(!ssn.is_closed().await?);
+        }
+
+        wait_closes(&mock, 1).await;
+        assert_eq!(mock.closes(), 1);
+        assert!(ssn.is_closed().await?);
+
+        ssn.reopen().await?;
+        assert!(!ssn.is_closed().await?);
+
+        // The session is not closed again after an explicit close or into_inner.
+        ssn.clone().scoped().close().await?;
+        assert_eq!(mock.closes(), 2);
+        ssn.clone().scoped().into_inner();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(mock.closes(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop_without_runtime() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let ssn = rt
+            .block_on(mock::open(MockFrontend::new(echo)))
+            .unwrap()
+            .scoped();
+        drop(rt);
+
+        drop(ssn);
+    }
+}
@@ -11,35 +11,96 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
-use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono::{DateTime, Utc};
 use futures::TryFutureExt;
 use prost::Enumeration;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::interceptor::InterceptedService;
 use tonic::transport::Channel;
-use tonic::transport::Endpoint;
-use tonic::Status;
+use tonic::{Code, Request, Status};
+
+use common::grpc::GrpcOptions;
+use common::with_grpc_options;
 
 use self::rpc::frontend_client::FrontendClient as FlameFrontendClient;
+use self::rpc::task_chunk::Item as Chunk;
 use self::rpc::{
-    CloseSessionRequest, CreateSessionRequest, CreateTaskRequest, GetTaskRequest,
-    ListSessionRequest, SessionSpec, TaskSpec, WatchTaskRequest,
+    CloneSessionRequest, CloseSessionRequest, CreateSessionRequest, CreateTaskRequest,
+    DeleteApplicationRequest, DeleteSessionRequest, DrainExecutorRequest, GetExecutorRequest,
+    GetServerInfoRequest, GetSessionRequest, GetTaskRequest, ListApplicationRequest,
+    ListExecutorRequest, ListSessionRequest, ListSessionTemplateRequest, ListTaskRequest,
+    OpenSessionRequest, RegisterApplicationRequest, ResubmitTaskRequest, SessionSpec, TaskSpec,
+    UncordonExecutorRequest, UpdateSessionRequest, WatchSessionRequest, WatchTaskRequest,
 };
 use crate::flame as rpc;
 use crate::trace::TraceFn;
 
+mod admin;
+mod archive;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod builder;
+mod bulk;
+#[cfg(feature = "embedded")]
+pub mod embedded;
+mod events;
+mod guard;
+pub mod message;
+#[cfg(test)]
+mod mock;
+mod outputs;
+#[cfg(feature = "testkit")]
+pub mod testkit;
 mod trace;
 
+pub use crate::admin::{
+    Allocation, AllocationReport, ApplicationStats, BuildInfo, ClusterStats, EffectiveConfig,
+    Histogram, LoopHealth, QuotaStats, SchedulerStatus, StarvedSession,
+};
+pub use crate::archive::{
+    ArchiveWriter, ArchivedSession, ArchivedTask, SessionArchive, SessionExport, ARCHIVE_VERSION,
+};
+pub use crate::builder::{ConnectionBuilder, RetryPolicy};
+pub use crate::bulk::TaskResults;
+pub use crate::events::{SessionEvent, SessionEvents, SessionHistoryEvent};
+pub use crate::guard::SessionGuard;
+pub use crate::outputs::{OutputChunk, OutputChunks, OutputFilter, TaskOutputEntry, TaskOutputs};
+pub use common::capability;
+pub use common::grpc::Compression;
+
+#[allow(clippy::enum_variant_names)] // The enum values of protobuf are prefixed to be unique.
 mod flame {
     tonic::include_proto!("flame");
 }
 
-type FlameClient = FlameFrontendClient<Channel>;
+type FlameClient = FlameFrontendClient<InterceptedService<Channel, Interceptor>>;
+
+/// The number of the tasks in a page of `ListTask`, so the outputs fit in the messages.
+const LIST_TASK_PAGE: u32 = 1000;
+
+/// The number of the sessions or the executors in a page of `ListSession` and `ListExecutor`,
+/// so the clusters with many of them are not listed in one message.
+const LIST_PAGE: u32 = 1000;
+
+/// The size of the chunks of the inputs sent by `Session::create_task_from_reader`, and the
+/// chunks buffered for the stream.
+const UPLOAD_CHUNK: usize = 1 << 20;
+const UPLOAD_BUFFER: usize = 4;
+
 type TaskID = String;
 type SessionID = String;
+type ExecutorID = String;
 
 type Message = Bytes;
 pub type TaskInput = Message;
@@ -55,16 +116,21 @@ macro_rules! lock_ptr {
     };
 }
 
-pub async fn connect(addr: &str) -> Result<Connection, FlameError> {
-    let endpoint = Endpoint::from_shared(addr.to_string())
-        .map_err(|_| FlameError::InvalidConfig("invalid address".to_string()))?;
-
-    let channel = endpoint
-        .connect()
-        .await
-        .map_err(|_| FlameError::InvalidConfig("failed to connect".to_string()))?;
+/// Calls an idempotent RPC with the retry policy.
+macro_rules! retry_rpc {
+    ( $policy:expr, $client:expr, $method:ident, $req:expr ) => {
+        $policy
+            .run(|| {
+                let mut client = $client.clone();
+                let req = $req.clone();
+                async move { client.$method(req).await.map_err(FlameError::from) }
+            })
+            .await
+    };
+}
 
-    Ok(Connection { channel })
+pub async fn connect(addr: &str) -> Result<Connection, FlameError> {
+    ConnectionBuilder::new(addr).connect().await
 }
 
 #[derive(Error, Debug, Clone)]
@@ -72,69 +138,431 @@ pub enum FlameError {
     #[error("'{0}' not found")]
     NotFound(String),
 
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("unavailable: {message}")]
+    Unavailable { message: String, retryable: bool },
+
+    /// The request is rejected until the resource is released, e.g. the backlog of the
+    /// session is full; it's retried after `retry_after` if the server hints it.
+    #[error("resource exhausted: {message}")]
+    ResourceExhausted {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+
+    #[error("task <{id}> failed: {message}")]
+    TaskFailed { id: TaskID, message: String },
+
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+
+    #[error("'{0}' is not implemented by the server")]
+    Unimplemented(String),
+
+    /// The call did not complete before its timeout or deadline, see `Connection::with_deadline`.
+    #[error("deadline exceeded: {0}")]
+    DeadlineExceeded(String),
+
+    /// The marker of a result of `Session::map` or `Session::collect_ordered` which is not
+    /// ready within the deadline; the task was created and may still complete if it has an id.
+    #[error("expired before the task <{}> completed", task_id.as_deref().unwrap_or("-"))]
+    Expired { task_id: Option<TaskID> },
+
     #[error("'{0}'")]
     Internal(String),
+}
 
-    #[error("'{0}'")]
-    Network(String),
+pub type Error = FlameError;
 
-    #[error("'{0}'")]
-    InvalidConfig(String),
+impl FlameError {
+    /// Whether the request may succeed if it's retried later.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            FlameError::Unavailable {
+                retryable: true,
+                ..
+            } | FlameError::ResourceExhausted { .. }
+        )
+    }
+
+    /// How long to wait before retrying the request, if the server hints it.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            FlameError::ResourceExhausted { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration, strum_macros::Display)]
+/// The states are serialized in lowercase as the ones of the session manager; the names of
+/// the variants are accepted too, e.g. in the archives of previous versions.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Enumeration, strum_macros::Display, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
 pub enum SessionState {
+    #[serde(alias = "Open")]
     Open = 0,
+    #[serde(alias = "Closed")]
     Closed = 1,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration, strum_macros::Display)]
+/// Where the outputs of the succeeded tasks are reused by the new tasks with the same input
+/// and common data, instead of running them again.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Enumeration, strum_macros::Display, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheScope {
+    None = 0,
+    /// The tasks of the same session.
+    Session = 1,
+    /// The sessions of the same application in the namespace, which also have this scope.
+    Application = 2,
+}
+
+impl CacheScope {
+    fn is_none(&self) -> bool {
+        *self == CacheScope::None
+    }
+}
+
+/// When the session is closed as expired, which aborts its pending and running tasks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Deadline {
+    /// The time after the creation of the session, which is measured by the session manager.
+    After(Duration),
+    /// The absolute time, which has to be in the future.
+    At(DateTime<Utc>),
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Enumeration, strum_macros::Display, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
 pub enum TaskState {
+    #[serde(alias = "Pending")]
     Pending = 0,
+    #[serde(alias = "Running")]
     Running = 1,
+    #[serde(alias = "Succeed")]
     Succeed = 2,
+    #[serde(alias = "Failed")]
     Failed = 3,
 }
 
+/// Why a task failed.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Enumeration, strum_macros::Display, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureReason {
+    /// The shim failed to run the task, e.g. it crashed.
+    #[serde(alias = "ShimError")]
+    ShimError = 0,
+    /// The task was not completed in time, e.g. its session passed the deadline.
+    #[serde(alias = "Timeout")]
+    Timeout = 1,
+    /// The task was aborted by the session manager or the user.
+    #[serde(alias = "Aborted")]
+    Aborted = 2,
+    /// The executor of the task stopped sending heartbeats.
+    #[serde(alias = "ExecutorLost")]
+    ExecutorLost = 3,
+    /// The shim of the task was killed for running out of memory.
+    #[serde(alias = "OomKilled")]
+    OomKilled = 4,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Enumeration, strum_macros::Display, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutorState {
+    Idle = 0,
+    Bound = 1,
+    Running = 2,
+    Unknown = 3,
+    Binding = 4,
+    Unbinding = 5,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Enumeration, strum_macros::Display, Serialize, Deserialize,
+)]
+pub enum Shim {
+    Log = 0,
+    Stdio = 1,
+    Wasm = 2,
+}
+
+/// The order of the listed sessions; the ties are broken by the ids of the sessions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration, strum_macros::Display)]
+pub enum SessionOrder {
+    /// The newest sessions first.
+    CreationTime = 0,
+    Id = 1,
+    /// The open sessions first.
+    State = 2,
+    /// The sessions with the most pending tasks first.
+    Pending = 3,
+}
+
 #[derive(Clone)]
 pub struct Connection {
     pub(crate) channel: Channel,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) interceptor: Interceptor,
+    /// The namespace of the sessions; the default one of the caller if empty.
+    pub(crate) namespace: String,
+    pub(crate) server: Arc<ServerInfo>,
+    pub(crate) grpc: GrpcOptions,
 }
 
-#[derive(Clone)]
+/// The version and capabilities of the session manager, which are fetched on connect.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    /// The version of the session manager; it's empty if the session manager is too old to
+    /// report it.
+    pub version: String,
+    pub build: String,
+    /// The tokens of the optional features, see `capability`.
+    pub capabilities: Vec<String>,
+}
+
+impl ServerInfo {
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+}
+
+impl From<rpc::ServerInfo> for ServerInfo {
+    fn from(info: rpc::ServerInfo) -> Self {
+        ServerInfo {
+            version: info.version,
+            build: info.build,
+            capabilities: info.capabilities,
+        }
+    }
+}
+
+/// Adds the bearer token, the timeout and the trace context to the requests.
+#[derive(Clone, Default)]
+pub(crate) struct Interceptor {
+    pub(crate) token: Option<MetadataValue<Ascii>>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) deadline: Option<Instant>,
+}
+
+impl Interceptor {
+    /// The time left for a call, i.e. the shorter of the timeout and the time to the deadline.
+    fn time_left(&self) -> Option<Duration> {
+        let left = self
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+
+        match (self.timeout, left) {
+            (Some(timeout), Some(left)) => Some(timeout.min(left)),
+            (timeout, left) => timeout.or(left),
+        }
+    }
+}
+
+impl tonic::service::Interceptor for Interceptor {
+    fn call(&mut self, mut req: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(token) = &self.token {
+            req.metadata_mut().insert("authorization", token.clone());
+        }
+        // The timeout is sent as `grpc-timeout`, which is enforced by both the channel and the
+        // server, so the server skips the work of a call whose caller is gone; the calls after
+        // the deadline are rejected without being sent.
+        match self.time_left() {
+            Some(left) if left.is_zero() => {
+                return Err(Status::deadline_exceeded("the deadline of the call passed"))
+            }
+            Some(left) => req.set_timeout(left),
+            None => {}
+        }
+
+        trace::inject(req)
+    }
+}
+
+fn new_client(channel: Channel, interceptor: Interceptor, grpc: GrpcOptions) -> FlameClient {
+    let client = FlameFrontendClient::with_interceptor(channel, interceptor);
+    with_grpc_options!(client, grpc)
+}
+
+#[derive(Clone, Default)]
 pub struct SessionAttributes {
     pub application: String,
     pub slots: i32,
+    pub labels: BTreeMap<String, String>,
     pub common_data: Option<CommonData>,
+    /// The settings of the session exported to the workers, e.g. `FLAME_SSN_MODEL_VERSION`
+    /// of `model_version`; it requires `capability::SESSION_CONFIG`.
+    pub config: BTreeMap<String, String>,
+    /// The maximum number of the pending and running tasks; the default of the session
+    /// manager if none.
+    pub max_pending_tasks: Option<u32>,
+    /// The maximum number of the running tasks, e.g. the tasks calling a rate-limited
+    /// service; there is no limit if none, and it requires `capability::SESSION_MAX_RUNNING`.
+    pub max_running_tasks: Option<u32>,
+    /// The cache of the task outputs; it requires `capability::TASK_CACHE`.
+    pub cache_scope: CacheScope,
+    /// The deadline of the session; it requires `capability::SESSION_DEADLINE`.
+    pub deadline: Option<Deadline>,
+    /// The timeout of the tasks of the session, and the times they're requeued after their
+    /// executors are lost; the defaults of the application if none, and they require
+    /// `capability::TASK_SETTINGS`.
+    pub task_timeout: Option<Duration>,
+    pub max_task_retries: Option<u32>,
+    /// The session template in the configuration of the session manager, which gives the
+    /// fields not set above, e.g. an empty application; it requires
+    /// `capability::SESSION_TEMPLATES`.
+    pub template: Option<String>,
+    /// The priority class of the session in the configuration of the session manager, e.g.
+    /// interactive; it requires `capability::PRIORITY_CLASSES`.
+    pub priority_class: Option<String>,
+    /// Whether the session runs the latest application instead of the one when it's created,
+    /// which is recorded if `capability::APPLICATION_SNAPSHOT`.
+    pub latest_application: bool,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Session {
+    #[serde(skip)]
     pub(crate) client: Option<FlameClient>,
+    /// The channel and the interceptor of the client, to rebuild it with other limits.
+    #[serde(skip)]
+    pub(crate) channel: Option<Channel>,
+    #[serde(skip)]
+    pub(crate) interceptor: Interceptor,
+    #[serde(skip)]
+    pub(crate) retry: RetryPolicy,
+    #[serde(skip)]
+    pub(crate) server: Option<Arc<ServerInfo>>,
+    #[serde(skip)]
+    pub(crate) grpc: GrpcOptions,
 
     pub id: SessionID,
+    pub namespace: String,
     pub slots: i32,
     pub application: String,
+    pub labels: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub config: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_pending_tasks: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_running_tasks: Option<u32>,
+    #[serde(default, skip_serializing_if = "CacheScope::is_none")]
+    pub cache_scope: CacheScope,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<DateTime<Utc>>,
+    /// The settings of the tasks created without their own, which are resolved from the
+    /// application when the session is created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_timeout: Option<Duration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_task_retries: Option<u32>,
+    /// The session this one was cloned from by `Connection::clone_session`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cloned_from: Option<SessionID>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority_class: Option<String>,
+    /// The SHA-256 of the application recorded on the session, which is run by its executors;
+    /// it's none if the session runs the latest application.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application_fingerprint: Option<String>,
     pub creation_time: DateTime<Utc>,
+    pub completion_time: Option<DateTime<Utc>>,
 
     pub state: SessionState,
     pub pending: i32,
     pub running: i32,
     pub succeed: i32,
     pub failed: i32,
+    /// Why no executor can ever run the pending tasks of the session, e.g. its slots don't
+    /// fit any executor; it's none if the session is schedulable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unschedulable: Option<String>,
+    /// The annotations set by the executors of the session, e.g. the model they loaded; it
+    /// requires `capability::TASK_ANNOTATIONS`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Task {
     pub id: TaskID,
     pub ssn_id: SessionID,
+    /// The order of the creation of the task in its session, from 0; it's dense even if the
+    /// tasks are created concurrently, and it requires `capability::TASK_INDEX`.
+    #[serde(default)]
+    pub index: u64,
 
     pub state: TaskState,
+    pub creation_time: DateTime<Utc>,
+    pub completion_time: Option<DateTime<Utc>>,
+
+    /// The last progress reported by the executor of the task, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<TaskProgress>,
+    /// The last failure of the task; it's kept if the task is retried, and cleared once the
+    /// task succeeds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<TaskFailure>,
+    /// The executor which ran the task last, and its host; none if it was never launched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executor_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// The failed task which this one was resubmitted from, in the same session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_task_id: Option<TaskID>,
+    /// The key which the task was created with, see `Session::create_task_with_key`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// The locality hint of the task, e.g. the shard of its input.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locality: Option<String>,
+    /// The environment variables of the task, e.g. a random seed.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// The annotations set by the executors of the task, e.g. the rows it processed; those of
+    /// its retries are merged. It requires `capability::TASK_ANNOTATIONS`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+    /// The number of the output chunks streamed by the task so far, see
+    /// `Session::output_chunks`; it requires `capability::OUTPUT_CHUNKS`.
+    #[serde(default)]
+    pub output_chunks: u64,
 
+    #[serde(skip)]
     pub input: Option<TaskInput>,
+    #[serde(skip)]
     pub output: Option<TaskOutput>,
 }
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TaskFailure {
+    pub reason: FailureReason,
+    pub message: String,
+}
+
+/// The progress of a task reported by its executor, e.g. 25%; the payload is opaque to Flame.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TaskProgress {
+    pub percentage: u32,
+    #[serde(skip)]
+    pub payload: Option<Bytes>,
+    pub update_time: DateTime<Utc>,
+}
+
 pub type TaskInformerPtr = Arc<Mutex<dyn TaskInformer>>;
 pub type TaskResultPtr = Arc<Mutex<Result<Task, FlameError>>>;
 
@@ -150,43 +578,540 @@ impl Task {
 }
 
 impl Connection {
+    fn client(&self) -> FlameClient {
+        new_client(self.channel.clone(), self.interceptor.clone(), self.grpc)
+    }
+
+    /// The session of the connection, whose calls have the same limits as the connection.
+    pub(crate) fn session(&self, ssn: &rpc::Session) -> Session {
+        let mut ssn = Session::from(ssn);
+        ssn.client = Some(self.client());
+        ssn.channel = Some(self.channel.clone());
+        ssn.interceptor = self.interceptor.clone();
+        ssn.retry = self.retry.clone();
+        ssn.server = Some(self.server.clone());
+        ssn.grpc = self.grpc;
+
+        ssn
+    }
+
+    /// The connection whose calls time out after `timeout`, e.g. so a slow session manager
+    /// does not hold the callers; the timeout is sent to the session manager, which skips the
+    /// work of the calls whose callers are gone. The sessions of the connection inherit it.
+    pub fn with_timeout(&self, timeout: Duration) -> Connection {
+        let mut conn = self.clone();
+        conn.interceptor.timeout = Some(timeout);
+        conn
+    }
+
+    /// The connection whose calls fail with `DeadlineExceeded` after the deadline, e.g. the
+    /// budget of the request of a service; the calls after it are not sent at all. The
+    /// sessions of the connection inherit it.
+    pub fn with_deadline(&self, deadline: Instant) -> Connection {
+        let mut conn = self.clone();
+        conn.interceptor.deadline = Some(deadline);
+        conn
+    }
+
+    /// The namespace of the sessions; the default one of the caller if empty.
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn server_info(&self) -> &ServerInfo {
+        &self.server
+    }
+
+    /// Whether the session manager supports the capability, e.g. `capability::WATCH_SESSION`.
+    pub fn supports(&self, capability: &str) -> bool {
+        self.server.supports(capability)
+    }
+
+    /// Fetches the information of the session manager; the ones without `GetServerInfo` are
+    /// considered to support no capabilities.
+    pub(crate) async fn negotiate(mut self) -> Result<Self, FlameError> {
+        let client = self.client();
+        let info = match retry_rpc!(self.retry, client, get_server_info, GetServerInfoRequest {}) {
+            Ok(info) => ServerInfo::from(info.into_inner()),
+            Err(FlameError::Unimplemented(_)) => {
+                log::debug!("The session manager does not support GetServerInfo.");
+                ServerInfo::default()
+            }
+            Err(e) => return Err(e),
+        };
+        self.server = Arc::new(info);
+
+        Ok(self)
+    }
+
     pub async fn create_session(&self, attrs: &SessionAttributes) -> Result<Session, FlameError> {
         trace_fn!("Connection::create_session");
+        if let Some(data) = &attrs.common_data {
+            self.grpc.check_size("common_data", data.len())?;
+        }
+        if !attrs.cache_scope.is_none() && !self.supports(capability::TASK_CACHE) {
+            return Err(FlameError::Unimplemented("cache_scope".to_string()));
+        }
+        if attrs.deadline.is_some() && !self.supports(capability::SESSION_DEADLINE) {
+            return Err(FlameError::Unimplemented("deadline".to_string()));
+        }
+        if attrs.template.is_some() && !self.supports(capability::SESSION_TEMPLATES) {
+            return Err(FlameError::Unimplemented("template".to_string()));
+        }
+        if !attrs.config.is_empty() && !self.supports(capability::SESSION_CONFIG) {
+            return Err(FlameError::Unimplemented("config".to_string()));
+        }
+        if attrs.priority_class.is_some() && !self.supports(capability::PRIORITY_CLASSES) {
+            return Err(FlameError::Unimplemented("priority_class".to_string()));
+        }
+        if attrs.max_running_tasks.is_some() && !self.supports(capability::SESSION_MAX_RUNNING) {
+            return Err(FlameError::Unimplemented("max_running_tasks".to_string()));
+        }
+        if (attrs.task_timeout.is_some() || attrs.max_task_retries.is_some())
+            && !self.supports(capability::TASK_SETTINGS)
+        {
+            return Err(FlameError::Unimplemented("task settings".to_string()));
+        }
+        let (deadline, timeout) = match attrs.deadline {
+            Some(Deadline::At(time)) => (Some(time.timestamp()), None),
+            Some(Deadline::After(timeout)) => (None, Some(timeout.as_secs())),
+            None => (None, None),
+        };
 
         let create_ssn_req = CreateSessionRequest {
             session: Some(SessionSpec {
                 application: attrs.application.clone(),
                 slots: attrs.slots,
+                labels: attrs.labels.clone().into_iter().collect(),
                 common_data: attrs.common_data.clone().map(CommonData::into),
+                namespace: self.namespace.clone(),
+                max_pending_tasks: attrs.max_pending_tasks,
+                cache_scope: attrs.cache_scope as i32,
+                deadline,
+                timeout,
+                config: attrs.config.clone().into_iter().collect(),
+                task_timeout: attrs.task_timeout.map(|t| t.as_secs()),
+                max_task_retries: attrs.max_task_retries,
+                cloned_from: None,
+                priority_class: attrs.priority_class.clone(),
+                latest_application: attrs.latest_application,
+                application_fingerprint: None,
+                max_running_tasks: attrs.max_running_tasks,
             }),
+            template: attrs.template.clone().unwrap_or_default(),
         };
 
-        let mut client = FlameClient::new(self.channel.clone());
+        let mut client = self.client();
         let ssn = client.create_session(create_ssn_req).await?;
-        let ssn = ssn.into_inner();
 
-        let mut ssn = Session::from(&ssn);
-        ssn.client = Some(client);
+        Ok(self.session(&ssn.into_inner()))
+    }
+
+    pub async fn get_session(&self, id: &SessionID) -> Result<Session, FlameError> {
+        trace_fn!("Connection::get_session");
+
+        let get_ssn_req = GetSessionRequest {
+            session_id: id.clone(),
+            namespace: self.namespace.clone(),
+        };
+
+        let client = self.client();
+        let ssn = retry_rpc!(self.retry, client, get_session, get_ssn_req)?;
+
+        Ok(self.session(&ssn.into_inner()))
+    }
+
+    /// Creates an open session with the settings and the common data of the session, e.g. to
+    /// run a batch again; the inputs of its tasks are copied as new pending tasks if
+    /// `with_tasks`. The clone runs the application of the session, or the latest one if
+    /// `latest_application`. It requires `capability::SESSION_CLONE`.
+    pub async fn clone_session(
+        &self,
+        id: &SessionID,
+        with_tasks: bool,
+        latest_application: bool,
+    ) -> Result<Session, FlameError> {
+        trace_fn!("Connection::clone_session");
+        if !self.supports(capability::SESSION_CLONE) {
+            return Err(FlameError::Unimplemented("clone_session".to_string()));
+        }
+
+        let clone_ssn_req = CloneSessionRequest {
+            session_id: id.clone(),
+            namespace: self.namespace.clone(),
+            with_tasks,
+            latest_application,
+        };
+
+        let mut client = self.client();
+        let ssn = client.clone_session(clone_ssn_req).await?;
+
+        Ok(self.session(&ssn.into_inner()))
+    }
+
+    /// Deletes a closed session, including its tasks.
+    pub async fn delete_session(&self, id: &SessionID) -> Result<Session, FlameError> {
+        trace_fn!("Connection::delete_session");
+
+        let delete_ssn_req = DeleteSessionRequest {
+            session_id: id.clone(),
+            namespace: self.namespace.clone(),
+        };
+
+        let mut client = self.client();
+        let ssn = client.delete_session(delete_ssn_req).await?;
 
-        Ok(ssn)
+        Ok(Session::from(&ssn.into_inner()))
     }
 
     pub async fn list_session(&self) -> Result<Vec<Session>, FlameError> {
-        let mut client = FlameClient::new(self.channel.clone());
-        let ssn_list = client.list_session(ListSessionRequest {}).await?;
+        self.list_session_by(SessionOrder::default()).await
+    }
+
+    /// Lists the sessions in the order, which is computed by the session manager.
+    pub async fn list_session_by(&self, order: SessionOrder) -> Result<Vec<Session>, FlameError> {
+        self.list_session_in(None, order).await
+    }
+
+    /// Lists the sessions in the state, e.g. the open ones of a cluster with many closed
+    /// sessions; they're filtered by the session manager if it supports
+    /// `capability::LIST_PAGES`.
+    pub async fn list_session_by_state(
+        &self,
+        state: SessionState,
+    ) -> Result<Vec<Session>, FlameError> {
+        self.list_session_in(Some(state), SessionOrder::Id).await
+    }
+
+    /// Lists the sessions one page at a time if the session manager supports
+    /// `capability::LIST_PAGES`, or all at once otherwise.
+    async fn list_session_in(
+        &self,
+        state: Option<SessionState>,
+        order: SessionOrder,
+    ) -> Result<Vec<Session>, FlameError> {
+        let client = self.client();
+        let limit = match self.supports(capability::LIST_PAGES) {
+            true => LIST_PAGE,
+            false => 0,
+        };
+
+        let mut sessions: Vec<Session> = vec![];
+        loop {
+            let list_ssn_req = ListSessionRequest {
+                namespace: self.namespace.clone(),
+                order_by: order as i32,
+                offset: sessions.len() as u32,
+                limit,
+                state: state.map(|s| s as i32),
+            };
+            let page = retry_rpc!(self.retry, client, list_session, list_ssn_req)?.into_inner();
+
+            let n = page.sessions.len();
+            sessions.extend(page.sessions.iter().map(Session::from));
+            if limit == 0 || n < limit as usize {
+                sessions.retain(|ssn| state.is_none_or(|state| ssn.state == state));
+                return Ok(sessions);
+            }
+        }
+    }
+
+    pub async fn register_application(&self, app: &Application) -> Result<(), FlameError> {
+        trace_fn!("Connection::register_application");
+        let mut client = self.client();
+        client
+            .register_application(RegisterApplicationRequest {
+                application: Some(rpc::Application::from(app)),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_application(&self) -> Result<Vec<Application>, FlameError> {
+        trace_fn!("Connection::list_application");
+        let client = self.client();
+        let app_list = retry_rpc!(
+            self.retry,
+            client,
+            list_application,
+            ListApplicationRequest {}
+        )?;
+
+        Ok(app_list
+            .into_inner()
+            .applications
+            .iter()
+            .map(Application::from)
+            .collect())
+    }
 
-        Ok(ssn_list
+    /// The session templates in the configuration of the session manager; it requires
+    /// `capability::SESSION_TEMPLATES`.
+    pub async fn list_session_templates(&self) -> Result<Vec<SessionTemplate>, FlameError> {
+        trace_fn!("Connection::list_session_templates");
+        let client = self.client();
+        let template_list = retry_rpc!(
+            self.retry,
+            client,
+            list_session_template,
+            ListSessionTemplateRequest {}
+        )?;
+
+        Ok(template_list
             .into_inner()
-            .sessions
+            .templates
             .iter()
-            .map(Session::from)
+            .map(SessionTemplate::from)
             .collect())
     }
+
+    pub async fn delete_application(&self, name: &str, force: bool) -> Result<(), FlameError> {
+        trace_fn!("Connection::delete_application");
+        let mut client = self.client();
+        client
+            .delete_application(DeleteApplicationRequest {
+                name: name.to_string(),
+                force,
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Lists the executors in the order of their ids, one page at a time if the session
+    /// manager supports `capability::LIST_PAGES`.
+    pub async fn list_executor(&self) -> Result<Vec<Executor>, FlameError> {
+        trace_fn!("Connection::list_executor");
+        let client = self.client();
+        let limit = match self.supports(capability::LIST_PAGES) {
+            true => LIST_PAGE,
+            false => 0,
+        };
+
+        let mut executors: Vec<Executor> = vec![];
+        loop {
+            let list_exe_req = ListExecutorRequest {
+                offset: executors.len() as u32,
+                limit,
+            };
+            let page = retry_rpc!(self.retry, client, list_executor, list_exe_req)?.into_inner();
+
+            let n = page.executors.len();
+            executors.extend(page.executors.iter().map(Executor::from));
+            if limit == 0 || n < limit as usize {
+                return Ok(executors);
+            }
+        }
+    }
+
+    pub async fn get_executor(&self, id: &ExecutorID) -> Result<Executor, FlameError> {
+        trace_fn!("Connection::get_executor");
+        let client = self.client();
+        let get_exe_req = GetExecutorRequest {
+            executor_id: id.clone(),
+        };
+        let exe = retry_rpc!(self.retry, client, get_executor, get_exe_req)?;
+
+        Ok(Executor::from(&exe.into_inner()))
+    }
+
+    pub async fn drain_executor(&self, id: &ExecutorID) -> Result<Executor, FlameError> {
+        trace_fn!("Connection::drain_executor");
+        let mut client = self.client();
+        let exe = client
+            .drain_executor(DrainExecutorRequest {
+                executor_id: id.clone(),
+            })
+            .await?;
+
+        Ok(Executor::from(&exe.into_inner()))
+    }
+
+    /// Binds new sessions to the drained or quarantined executor again; it requires
+    /// `capability::EXECUTOR_QUARANTINE`.
+    pub async fn uncordon_executor(&self, id: &ExecutorID) -> Result<Executor, FlameError> {
+        trace_fn!("Connection::uncordon_executor");
+        let mut client = self.client();
+        let exe = client
+            .uncordon_executor(UncordonExecutorRequest {
+                executor_id: id.clone(),
+            })
+            .await?;
+
+        Ok(Executor::from(&exe.into_inner()))
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Application {
+    pub name: String,
+    pub shim: Shim,
+    #[serde(default)]
+    pub command: String,
+    #[serde(default)]
+    pub arguments: Vec<String>,
+    #[serde(default)]
+    pub environments: Vec<String>,
+    #[serde(default)]
+    pub working_directory: String,
+    /// The share of the executors relative to the other applications, which is 1 by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+    /// The settings of the sessions and the tasks which are created without their own; the
+    /// timeout is in seconds, and the labels are merged with the ones of the sessions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_slots: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_task_timeout: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_max_retries: Option<u32>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub default_labels: BTreeMap<String, String>,
+    /// The constraints of the inputs of the tasks, which are checked when they're created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<InputConstraints>,
+}
+
+/// The constraints of the inputs of the tasks of an application; the fields and the schema
+/// are checked only if the input is encoded by `message::to_task_input`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputConstraints {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_fields: Vec<String>,
+    /// The JSON schema of the value in the input.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    /// Whether the common data of the sessions is checked too.
+    #[serde(default)]
+    pub strict: bool,
+}
+
+/// The defaults of the sessions created by the template, see `SessionAttributes::template`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SessionTemplate {
+    pub name: String,
+    pub application: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slots: Option<i32>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_pending_tasks: Option<u32>,
+    /// The sessions are closed as expired after it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Executor {
+    pub id: ExecutorID,
+    pub slots: i32,
+    pub applications: Vec<String>,
+    pub labels: BTreeMap<String, String>,
+    /// The locality keys of the executor, e.g. the shards cached on its host.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub locality_keys: Vec<String>,
+
+    pub state: ExecutorState,
+    pub session_id: Option<SessionID>,
+    pub task_id: Option<TaskID>,
+
+    pub creation_time: DateTime<Utc>,
+    pub last_heartbeat: DateTime<Utc>,
+    pub draining: bool,
+    /// The executor failed too many tasks, so it's drained until it's uncordoned.
+    #[serde(default)]
+    pub quarantined: bool,
+
+    #[serde(default)]
+    pub host: HostInfo,
+
+    /// The share of the time the executor ran a task over the rolling windows of the
+    /// session manager; empty if the session manager doesn't track it.
+    #[serde(default)]
+    pub utilization: Vec<Utilization>,
+    /// The seconds the executor ran a task since it was registered, as measured by the
+    /// session manager, and as reported by the executor itself.
+    #[serde(default)]
+    pub busy_seconds: f64,
+    #[serde(default)]
+    pub reported_busy_seconds: Option<f64>,
+}
+
+/// The utilization of an executor over a rolling window.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Utilization {
+    pub window: Duration,
+    /// The busy time of the executor in the window, from 0 to 100.
+    pub percentage: f64,
+}
+
+/// The host of an executor as reported by its executor manager; a field is empty if it's
+/// unknown, e.g. the executor manager is older than the session manager.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HostInfo {
+    pub hostname: String,
+    pub ip: String,
+    pub os: String,
+    pub arch: String,
+    pub version: String,
 }
 
 impl Session {
     pub async fn create_task(&self, input: Option<TaskInput>) -> Result<Task, FlameError> {
+        self.create_task_at(input, None, HashMap::new(), None).await
+    }
+
+    /// Creates a task with a key given by the client, e.g. the index of its input; if the
+    /// session has a task with the key, that task is returned instead of creating another,
+    /// so the tasks can be submitted again after an interruption. It requires
+    /// `capability::TASK_IDEMPOTENCY`.
+    pub async fn create_task_with_key(
+        &self,
+        input: Option<TaskInput>,
+        key: impl Into<String>,
+    ) -> Result<Task, FlameError> {
+        self.create_task_at(input, None, HashMap::new(), Some(key.into()))
+            .await
+    }
+
+    /// Creates a task with a locality hint, e.g. the shard of its input, so it's preferred to
+    /// be launched on the executors having the key; it's launched elsewhere after the locality
+    /// wait of the session manager.
+    pub async fn create_local_task(
+        &self,
+        input: Option<TaskInput>,
+        locality: impl Into<String>,
+    ) -> Result<Task, FlameError> {
+        self.create_task_at(input, Some(locality.into()), HashMap::new(), None)
+            .await
+    }
+
+    /// Creates a task with its own environment variables, e.g. a random seed or an output
+    /// path, which are set for the process of the task by the stdio shim, or passed to the
+    /// services in `TaskContext::env`; see `common::apis::check_task_env` for the limits.
+    pub async fn create_task_with_env(
+        &self,
+        input: Option<TaskInput>,
+        env: HashMap<String, String>,
+    ) -> Result<Task, FlameError> {
+        self.create_task_at(input, None, env, None).await
+    }
+
+    async fn create_task_at(
+        &self,
+        input: Option<TaskInput>,
+        locality: Option<String>,
+        env: HashMap<String, String>,
+        idempotency_key: Option<String>,
+    ) -> Result<Task, FlameError> {
         trace_fn!("Session::create_task");
+        if let Some(input) = &input {
+            self.grpc.check_size("input", input.len())?;
+        }
         let mut client = self
             .client
             .clone()
@@ -197,6 +1122,13 @@ impl Session {
                 session_id: self.id.clone(),
                 input: input.map(|input| input.to_vec()),
                 output: None,
+                trace_context: None,
+                labels: Default::default(),
+                locality,
+                env,
+                timeout: None,
+                max_retries: None,
+                idempotency_key,
             }),
         };
 
@@ -206,9 +1138,83 @@ impl Session {
         Ok(Task::from(&task))
     }
 
+    /// Creates a task with the input read from the reader, which is sent in chunks, so it may
+    /// exceed the message size limit, e.g. a large file; it requires `capability::TASK_UPLOAD`.
+    /// The task is not created if the input can't be read to the end.
+    pub async fn create_task_from_reader<R>(&self, reader: R) -> Result<Task, FlameError>
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        trace_fn!("Session::create_task_from_reader");
+        self.upload_task(reader, None).await
+    }
+
+    /// Creates a task with the input read from the reader and a key given by the client, see
+    /// `create_task_from_reader` and `create_task_with_key`.
+    pub async fn create_task_from_reader_with_key<R>(
+        &self,
+        reader: R,
+        key: impl Into<String>,
+    ) -> Result<Task, FlameError>
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        trace_fn!("Session::create_task_from_reader_with_key");
+        self.upload_task(reader, Some(key.into())).await
+    }
+
+    async fn upload_task<R>(
+        &self,
+        reader: R,
+        idempotency_key: Option<String>,
+    ) -> Result<Task, FlameError>
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        let mut client = self
+            .client
+            .clone()
+            .ok_or(FlameError::Internal("no flame client".to_string()))?;
+
+        let spec = TaskSpec {
+            session_id: self.id.clone(),
+            input: None,
+            output: None,
+            trace_context: None,
+            labels: Default::default(),
+            locality: None,
+            env: HashMap::new(),
+            timeout: None,
+            max_retries: None,
+            idempotency_key,
+        };
+        let (tx, rx) = mpsc::channel(UPLOAD_BUFFER);
+        let upload = tokio::spawn(upload_chunks(reader, spec, tx));
+        let task = client.create_task_stream(ReceiverStream::new(rx)).await;
+        upload
+            .await
+            .map_err(|e| FlameError::Internal(e.to_string()))??;
+
+        let task = task?.into_inner();
+        Ok(Task::from(&task))
+    }
+
+    /// Whether the input exceeds the message size limit, so the task has to be created by
+    /// `create_task_from_reader`.
+    pub fn is_large_input(&self, size: usize) -> bool {
+        self.grpc.check_size("input", size).is_err()
+    }
+
+    /// Whether the session manager supports the capability, e.g. `capability::TASK_UPLOAD`.
+    pub fn supports(&self, capability: &str) -> bool {
+        self.server
+            .as_ref()
+            .is_some_and(|server| server.supports(capability))
+    }
+
     pub async fn get_task(&self, id: TaskID) -> Result<Task, FlameError> {
         trace_fn!("Session::get_task");
-        let mut client = self
+        let client = self
             .client
             .clone()
             .ok_or(FlameError::Internal("no flame client".to_string()))?;
@@ -217,12 +1223,243 @@ impl Session {
             session_id: self.id.clone(),
             task_id: id.clone(),
         };
-        let task = client.get_task(get_task_req).await?;
+        let task = retry_rpc!(self.retry, client, get_task, get_task_req)?;
 
         let task = task.into_inner();
         Ok(Task::from(&task))
     }
 
+    /// Lists the tasks of the session in the order of their indexes, one page at a time; it
+    /// requires `capability::TASK_INDEX`.
+    pub async fn list_tasks(&self) -> Result<Vec<Task>, FlameError> {
+        trace_fn!("Session::list_tasks");
+        self.list_tasks_in(None, None, true).await
+    }
+
+    /// Lists the tasks of the session in the state, e.g. the failed ones to resubmit; it
+    /// requires `capability::TASK_RESUBMIT`.
+    pub async fn list_tasks_by_state(&self, state: TaskState) -> Result<Vec<Task>, FlameError> {
+        trace_fn!("Session::list_tasks_by_state");
+        self.list_tasks_in(Some(state), None, true).await
+    }
+
+    /// Lists the tasks of the session having the annotation key, whatever its value; it
+    /// requires `capability::TASK_ANNOTATIONS`.
+    pub async fn list_tasks_by_annotation(&self, key: &str) -> Result<Vec<Task>, FlameError> {
+        trace_fn!("Session::list_tasks_by_annotation");
+        self.list_tasks_in(None, Some(key), true).await
+    }
+
+    async fn list_tasks_in(
+        &self,
+        state: Option<TaskState>,
+        annotation: Option<&str>,
+        payloads: bool,
+    ) -> Result<Vec<Task>, FlameError> {
+        let client = self
+            .client
+            .clone()
+            .ok_or(FlameError::Internal("no flame client".to_string()))?;
+
+        let mut tasks: Vec<Task> = vec![];
+        loop {
+            let list_task_req = ListTaskRequest {
+                session_id: self.id.clone(),
+                namespace: self.namespace.clone(),
+                start_index: tasks.last().map(|t| t.index + 1).unwrap_or_default(),
+                limit: LIST_TASK_PAGE,
+                state: state.map(|s| s as i32),
+                skip_payloads: !payloads,
+                annotation: annotation.map(str::to_string),
+            };
+            let page = retry_rpc!(self.retry, client, list_task, list_task_req)?.into_inner();
+
+            let n = page.tasks.len();
+            tasks.extend(page.tasks.iter().map(Task::from));
+            if n < LIST_TASK_PAGE as usize {
+                return Ok(tasks);
+            }
+        }
+    }
+
+    /// Creates a pending task with the input of the failed task, which is linked to it by
+    /// `original_task_id`; it requires `capability::TASK_RESUBMIT`.
+    pub async fn resubmit_task(&self, id: TaskID) -> Result<Task, FlameError> {
+        trace_fn!("Session::resubmit_task");
+        let mut client = self
+            .client
+            .clone()
+            .ok_or(FlameError::Internal("no flame client".to_string()))?;
+
+        let resubmit_task_req = ResubmitTaskRequest {
+            session_id: self.id.clone(),
+            task_id: id,
+            namespace: self.namespace.clone(),
+        };
+        let task = client.resubmit_task(resubmit_task_req).await?;
+
+        Ok(Task::from(&task.into_inner()))
+    }
+
+    /// Resubmits the failed tasks of the session which were not resubmitted yet, so it's
+    /// safe to run it again; returns the new tasks.
+    pub async fn resubmit_failed_tasks(&self) -> Result<Vec<Task>, FlameError> {
+        trace_fn!("Session::resubmit_failed_tasks");
+        let tasks = self.list_tasks_in(None, None, false).await?;
+        let resubmitted: Vec<&TaskID> = tasks
+            .iter()
+            .filter_map(|t| t.original_task_id.as_ref())
+            .collect();
+
+        let mut new_tasks = vec![];
+        for task in &tasks {
+            if task.state == TaskState::Failed && !resubmitted.contains(&&task.id) {
+                new_tasks.push(self.resubmit_task(task.id.clone()).await?);
+            }
+        }
+
+        Ok(new_tasks)
+    }
+
+    /// Runs a task and waits for its output; the output of a failed task is taken as its
+    /// failure message.
+    ///
+    /// Dropping the returned future only stops watching the task, the task itself keeps
+    /// running in the session; so a timeout is up to the caller, e.g. `tokio::time::timeout`.
+    pub async fn run_task_output(
+        &self,
+        input: impl Into<TaskInput>,
+    ) -> Result<TaskOutput, FlameError> {
+        trace_fn!("Session::run_task_output");
+        let task = self.create_task(Some(input.into())).await?;
+        self.task_output(task).await
+    }
+
+    /// Waits for the task, and returns its output or failure.
+    pub async fn task_output(&self, task: Task) -> Result<TaskOutput, FlameError> {
+        let task = self.wait_task(task).await?;
+
+        let output = task.output.unwrap_or_default();
+        match task.state {
+            TaskState::Succeed => Ok(output),
+            _ => Err(FlameError::TaskFailed {
+                id: task.id,
+                message: String::from_utf8_lossy(&output).to_string(),
+            }),
+        }
+    }
+
+    /// Runs a task with the input and output in JSON.
+    pub async fn run_task_json<I, O>(&self, input: &I) -> Result<O, FlameError>
+    where
+        I: Serialize,
+        O: DeserializeOwned,
+    {
+        trace_fn!("Session::run_task_json");
+        let input = serde_json::to_vec(input)
+            .map_err(|e| FlameError::Internal(format!("failed to encode task input: {}", e)))?;
+        let output = self.run_task_output(input).await?;
+
+        serde_json::from_slice(&output)
+            .map_err(|e| FlameError::Internal(format!("failed to decode task output: {}", e)))
+    }
+
+    /// Runs a task with the input and output in the envelope of `message`, which are decoded
+    /// by the service with `TaskContext::input_as`.
+    pub async fn run_task_typed<I, O>(&self, input: &I) -> Result<O, FlameError>
+    where
+        I: Serialize,
+        O: DeserializeOwned,
+    {
+        trace_fn!("Session::run_task_typed");
+        let input = message::to_task_input(input)?;
+        let output = self.run_task_output(input).await?;
+
+        message::from_task_output(&output)
+    }
+
+    /// Watches the task until it's completed; the watch is re-established if it's dropped by
+    /// a transient failure, e.g. the connection was reset. The task is watched until the
+    /// deadline of the session at most, see `with_deadline`.
+    pub async fn wait_task(&self, task: Task) -> Result<Task, FlameError> {
+        if task.is_completed() {
+            return Ok(task);
+        }
+
+        let Some(deadline) = self.interceptor.deadline else {
+            return self.keep_watching(task).await;
+        };
+        let id = task.id.clone();
+        tokio::time::timeout_at(deadline.into(), self.keep_watching(task))
+            .await
+            .unwrap_or_else(|_| {
+                Err(FlameError::DeadlineExceeded(format!(
+                    "task <{}> is not completed by the deadline",
+                    id
+                )))
+            })
+    }
+
+    async fn keep_watching(&self, task: Task) -> Result<Task, FlameError> {
+        let mut client = self
+            .client
+            .clone()
+            .ok_or(FlameError::Internal("no flame client".to_string()))?;
+
+        let mut retries = 0;
+        loop {
+            let e = match watch_until_completed(&mut client, &task).await {
+                Ok(Some(task)) => return Ok(task),
+                Ok(None) => FlameError::Unavailable {
+                    message: format!(
+                        "watch of task <{}> was closed before it's completed",
+                        task.id
+                    ),
+                    retryable: true,
+                },
+                Err(e) => e,
+            };
+
+            let interval = match e.is_retryable() {
+                true => self.retry.backoff(retries),
+                false => None,
+            };
+            let Some(interval) = interval else {
+                return Err(e);
+            };
+
+            log::debug!("Re-watch task <{}> after {:?}: {}", task.id, interval, e);
+            tokio::time::sleep(interval).await;
+            retries += 1;
+        }
+    }
+
+    /// The session whose calls time out after `timeout`, see `Connection::with_timeout`.
+    pub fn with_timeout(&self, timeout: Duration) -> Session {
+        let mut interceptor = self.interceptor.clone();
+        interceptor.timeout = Some(timeout);
+        self.with_interceptor(interceptor)
+    }
+
+    /// The session whose calls fail with `DeadlineExceeded` after the deadline, see
+    /// `Connection::with_deadline`; the tasks are watched until the deadline at most, so the
+    /// results of `map` which are not ready by then are `Expired`.
+    pub fn with_deadline(&self, deadline: Instant) -> Session {
+        let mut interceptor = self.interceptor.clone();
+        interceptor.deadline = Some(deadline);
+        self.with_interceptor(interceptor)
+    }
+
+    fn with_interceptor(&self, interceptor: Interceptor) -> Session {
+        let mut ssn = self.clone();
+        if let Some(channel) = &self.channel {
+            ssn.client = Some(new_client(channel.clone(), interceptor.clone(), self.grpc));
+        }
+        ssn.interceptor = interceptor;
+
+        ssn
+    }
+
     pub async fn run_task(
         &self,
         input: Option<TaskInput>,
@@ -249,6 +1486,7 @@ impl Session {
         let watch_task_req = WatchTaskRequest {
             session_id,
             task_id,
+            include_chunks: false,
         };
         let mut task_stream = client.watch_task(watch_task_req).await?.into_inner();
         while let Some(task) = task_stream.next().await {
@@ -275,17 +1513,215 @@ impl Session {
 
         let close_ssn_req = CloseSessionRequest {
             session_id: self.id.clone(),
+            namespace: self.namespace.clone(),
         };
 
         client.close_session(close_ssn_req).await?;
 
         Ok(())
     }
+
+    /// Sets the deadline of the open session, e.g. to extend it; it requires
+    /// `capability::SESSION_DEADLINE`.
+    pub async fn set_deadline(&mut self, deadline: DateTime<Utc>) -> Result<(), FlameError> {
+        trace_fn!("Session::set_deadline");
+        let mut client = self
+            .client
+            .clone()
+            .ok_or(FlameError::Internal("no flame client".to_string()))?;
+
+        let update_ssn_req = UpdateSessionRequest {
+            session_id: self.id.clone(),
+            namespace: self.namespace.clone(),
+            deadline: deadline.timestamp(),
+        };
+
+        let ssn = client.update_session(update_ssn_req).await?.into_inner();
+        self.deadline = Session::from(&ssn).deadline;
+
+        Ok(())
+    }
+
+    /// Opens the closed session again, so new tasks can be submitted to it.
+    pub async fn reopen(&self) -> Result<(), FlameError> {
+        trace_fn!("Session::reopen");
+        let mut client = self
+            .client
+            .clone()
+            .ok_or(FlameError::Internal("no flame client".to_string()))?;
+
+        let open_ssn_req = OpenSessionRequest {
+            session_id: self.id.clone(),
+            namespace: self.namespace.clone(),
+        };
+
+        client.open_session(open_ssn_req).await?;
+
+        Ok(())
+    }
+
+    /// Returns whether the session is closed in the session manager.
+    pub async fn is_closed(&self) -> Result<bool, FlameError> {
+        trace_fn!("Session::is_closed");
+        let client = self
+            .client
+            .clone()
+            .ok_or(FlameError::Internal("no flame client".to_string()))?;
+
+        let get_ssn_req = GetSessionRequest {
+            session_id: self.id.clone(),
+            namespace: self.namespace.clone(),
+        };
+        let ssn = retry_rpc!(self.retry, client, get_session, get_ssn_req)?;
+        let ssn = Session::from(&ssn.into_inner());
+
+        Ok(ssn.state == SessionState::Closed)
+    }
+}
+
+/// Sends the spec of the task, the chunks of its input and the size of the input in order;
+/// the input is not committed by its size if it fails to be read. The sending stops once the
+/// session manager rejects the task, whose status is returned by the call.
+async fn upload_chunks<R: AsyncRead + Unpin>(
+    mut reader: R,
+    spec: TaskSpec,
+    tx: mpsc::Sender<rpc::TaskChunk>,
+) -> Result<(), FlameError> {
+    let send = |item| tx.send(rpc::TaskChunk { item: Some(item) });
+    if send(Chunk::Task(spec)).await.is_err() {
+        return Ok(());
+    }
+
+    let mut size = 0;
+    loop {
+        let mut data = Vec::with_capacity(UPLOAD_CHUNK);
+        (&mut reader)
+            .take(UPLOAD_CHUNK as u64)
+            .read_to_end(&mut data)
+            .await
+            .map_err(|e| FlameError::InvalidArgument(format!("failed to read input: {}", e)))?;
+        if data.is_empty() {
+            break;
+        }
+        size += data.len() as u64;
+        if send(Chunk::Data(data)).await.is_err() {
+            return Ok(());
+        }
+    }
+    let _ = send(Chunk::Size(size)).await;
+
+    Ok(())
+}
+
+/// Returns the completed task, or None if the stream is closed before that.
+async fn watch_until_completed(
+    client: &mut FlameClient,
+    task: &Task,
+) -> Result<Option<Task>, FlameError> {
+    let watch_task_req = WatchTaskRequest {
+        session_id: task.ssn_id.clone(),
+        task_id: task.id.clone(),
+        include_chunks: false,
+    };
+    let mut task_stream = client.watch_task(watch_task_req).await?.into_inner();
+    while let Some(t) = task_stream.next().await {
+        let t = Task::from(&t?);
+        if t.is_completed() {
+            return Ok(Some(t));
+        }
+    }
+
+    Ok(None)
+}
+
+impl From<common::FlameError> for FlameError {
+    fn from(e: common::FlameError) -> Self {
+        use common::FlameError as ServerError;
+
+        let retryable = e.is_retryable();
+        match e {
+            ServerError::NotFound(s) => FlameError::NotFound(s),
+            ServerError::Unauthenticated(_) | ServerError::PermissionDenied(_) => {
+                FlameError::PermissionDenied(e.to_string())
+            }
+            ServerError::InvalidArgument { .. }
+            | ServerError::InvalidConfig(_)
+            | ServerError::AlreadyExists(_)
+            | ServerError::FailedPrecondition(_) => FlameError::InvalidArgument(e.to_string()),
+            ServerError::ResourceExhausted {
+                message,
+                retry_after,
+            } => FlameError::ResourceExhausted {
+                message,
+                retry_after,
+            },
+            ServerError::Unavailable { .. } | ServerError::Uninitialized(_) => {
+                FlameError::Unavailable {
+                    message: e.to_string(),
+                    retryable,
+                }
+            }
+            ServerError::DeadlineExceeded(s) => FlameError::DeadlineExceeded(s),
+            _ => FlameError::Internal(e.to_string()),
+        }
+    }
 }
 
 impl From<Status> for FlameError {
     fn from(value: Status) -> Self {
-        FlameError::Network(value.code().to_string())
+        let message = match value.message().is_empty() {
+            true => value.code().to_string(),
+            false => value.message().to_string(),
+        };
+
+        match value.code() {
+            Code::NotFound => FlameError::NotFound(message),
+            // The message exceeds the limit of the receiver, i.e. the client or the server.
+            Code::OutOfRange if message.contains("message length too large") => {
+                FlameError::InvalidArgument(format!(
+                    "{}, raise grpc.max_recv_message_size of the receiver",
+                    message
+                ))
+            }
+            Code::InvalidArgument
+            | Code::FailedPrecondition
+            | Code::OutOfRange
+            | Code::AlreadyExists => FlameError::InvalidArgument(message),
+            // The connection was reset or the server is busy.
+            // The server tells whether the request could be retried, e.g. it's draining.
+            Code::Unavailable if !value.details().is_empty() => {
+                let detail = <rpc::ErrorDetail as prost::Message>::decode(value.details())
+                    .unwrap_or_default();
+                FlameError::Unavailable {
+                    message,
+                    retryable: detail.retryable,
+                }
+            }
+            Code::ResourceExhausted => {
+                let detail = <rpc::ErrorDetail as prost::Message>::decode(value.details())
+                    .unwrap_or_default();
+                FlameError::ResourceExhausted {
+                    message,
+                    retry_after: detail.retry_after_ms.map(Duration::from_millis),
+                }
+            }
+            Code::Unavailable | Code::Unknown | Code::Aborted => FlameError::Unavailable {
+                message,
+                retryable: true,
+            },
+            // The timeout of the call is enforced by the channel as well as the server.
+            Code::DeadlineExceeded => FlameError::DeadlineExceeded(message),
+            Code::Cancelled if message == "Timeout expired" => {
+                FlameError::DeadlineExceeded(message)
+            }
+            Code::Cancelled => FlameError::Unavailable {
+                message,
+                retryable: false,
+            },
+            Code::Unimplemented => FlameError::Unimplemented(message),
+            Code::Unauthenticated | Code::PermissionDenied => FlameError::PermissionDenied(message),
+            _ => FlameError::Internal(message),
+        }
     }
 }
 
@@ -297,9 +1733,53 @@ impl From<&rpc::Task> for Task {
         Task {
             id: metadata.id,
             ssn_id: spec.session_id.clone(),
+            index: task.index,
             input: spec.input.map(TaskInput::from),
             output: spec.output.map(TaskOutput::from),
             state: TaskState::try_from(status.state).unwrap_or(TaskState::default()),
+            creation_time: DateTime::<Utc>::from_timestamp(status.creation_time, 0).unwrap(),
+            completion_time: status
+                .completion_time
+                .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+            progress: status.progress.as_ref().map(TaskProgress::from),
+            failure: status.failure.as_ref().map(TaskFailure::from),
+            executor_id: status.executor_id,
+            hostname: status.hostname,
+            original_task_id: task.original_task_id.clone(),
+            idempotency_key: spec.idempotency_key,
+            locality: spec.locality,
+            env: spec.env.into_iter().collect(),
+            annotations: status.annotations.into_iter().collect(),
+            output_chunks: status.output_chunks,
+        }
+    }
+}
+
+impl From<&rpc::TaskFailure> for TaskFailure {
+    fn from(failure: &rpc::TaskFailure) -> Self {
+        TaskFailure {
+            reason: FailureReason::try_from(failure.reason).unwrap_or(FailureReason::ShimError),
+            message: failure.message.clone(),
+        }
+    }
+}
+
+impl From<&TaskFailure> for rpc::TaskFailure {
+    fn from(failure: &TaskFailure) -> Self {
+        rpc::TaskFailure {
+            reason: failure.reason as i32,
+            message: failure.message.clone(),
+        }
+    }
+}
+
+impl From<&rpc::TaskProgress> for TaskProgress {
+    fn from(progress: &rpc::TaskProgress) -> Self {
+        TaskProgress {
+            percentage: progress.percentage,
+            payload: progress.payload.clone().map(Bytes::from),
+            update_time: DateTime::<Utc>::from_timestamp(progress.update_time, 0)
+                .unwrap_or_default(),
         }
     }
 }
@@ -310,21 +1790,509 @@ impl From<&rpc::Session> for Session {
         let status = ssn.status.clone().unwrap();
         let spec = ssn.spec.clone().unwrap();
 
-        let naivedatetime_utc =
-            NaiveDateTime::from_timestamp_millis(status.creation_time * 1000).unwrap();
-        let creation_time = Utc.from_utc_datetime(&naivedatetime_utc);
+        let creation_time = DateTime::<Utc>::from_timestamp(status.creation_time, 0).unwrap();
 
         Session {
             client: None,
+            channel: None,
+            interceptor: Interceptor::default(),
+            retry: RetryPolicy::default(),
+            server: None,
+            grpc: GrpcOptions::default(),
             id: metadata.id,
+            namespace: spec.namespace,
             slots: spec.slots,
             application: spec.application,
+            labels: spec.labels.into_iter().collect(),
+            config: spec.config.into_iter().collect(),
+            max_pending_tasks: spec.max_pending_tasks,
+            max_running_tasks: spec.max_running_tasks,
+            cache_scope: CacheScope::try_from(spec.cache_scope).unwrap_or(CacheScope::None),
+            deadline: spec
+                .deadline
+                .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+            task_timeout: spec.task_timeout.map(Duration::from_secs),
+            max_task_retries: spec.max_task_retries,
+            cloned_from: spec.cloned_from,
+            priority_class: spec.priority_class,
+            application_fingerprint: spec.application_fingerprint,
             creation_time,
+            completion_time: status
+                .completion_time
+                .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
             state: SessionState::try_from(status.state).unwrap_or(SessionState::default()),
             pending: status.pending,
             running: status.running,
             succeed: status.succeed,
             failed: status.failed,
+            unschedulable: status.unschedulable,
+            annotations: status.annotations.into_iter().collect(),
+        }
+    }
+}
+
+impl From<&rpc::Executor> for Executor {
+    fn from(exe: &rpc::Executor) -> Self {
+        let metadata = exe.metadata.clone().unwrap();
+        let spec = exe.spec.clone().unwrap();
+        let status = exe.status.clone().unwrap();
+
+        Executor {
+            id: metadata.id,
+            slots: spec.slots,
+            applications: spec.applications.into_iter().map(|app| app.name).collect(),
+            labels: spec.labels.into_iter().collect(),
+            locality_keys: spec.locality_keys,
+            state: ExecutorState::try_from(status.state).unwrap_or(ExecutorState::Unknown),
+            session_id: status.session_id,
+            task_id: status.task_id,
+            creation_time: DateTime::<Utc>::from_timestamp(status.creation_time, 0).unwrap(),
+            last_heartbeat: DateTime::<Utc>::from_timestamp(status.last_heartbeat, 0).unwrap(),
+            draining: status.draining,
+            quarantined: status.quarantined,
+            host: spec.host.map(HostInfo::from).unwrap_or_default(),
+            utilization: status
+                .utilization
+                .iter()
+                .map(|u| Utilization {
+                    window: Duration::from_secs(u.window),
+                    percentage: u.percentage,
+                })
+                .collect(),
+            busy_seconds: status.busy_seconds,
+            reported_busy_seconds: status.reported_busy_seconds,
+        }
+    }
+}
+
+impl From<rpc::HostInfo> for HostInfo {
+    fn from(host: rpc::HostInfo) -> Self {
+        HostInfo {
+            hostname: host.hostname,
+            ip: host.ip,
+            os: host.os,
+            arch: host.arch,
+            version: host.version,
+        }
+    }
+}
+
+impl From<&Application> for rpc::Application {
+    fn from(app: &Application) -> Self {
+        rpc::Application {
+            name: app.name.clone(),
+            shim: app.shim as i32,
+            command: app.command.clone(),
+            arguments: app.arguments.clone(),
+            environments: app.environments.clone(),
+            working_directory: app.working_directory.clone(),
+            weight: app.weight,
+            default_slots: app.default_slots,
+            default_task_timeout: app.default_task_timeout,
+            default_max_retries: app.default_max_retries,
+            default_labels: app.default_labels.clone().into_iter().collect(),
+            input: app.input.as_ref().map(|input| rpc::InputConstraints {
+                max_bytes: input.max_bytes,
+                required_fields: input.required_fields.clone(),
+                schema: input.schema.clone(),
+                strict: input.strict,
+            }),
+        }
+    }
+}
+
+impl From<&rpc::SessionTemplate> for SessionTemplate {
+    fn from(template: &rpc::SessionTemplate) -> Self {
+        SessionTemplate {
+            name: template.name.clone(),
+            application: template.application.clone(),
+            slots: template.slots,
+            labels: template.labels.clone().into_iter().collect(),
+            max_pending_tasks: template.max_pending_tasks,
+            timeout: template.timeout.map(Duration::from_secs),
         }
     }
 }
+
+impl From<&rpc::Application> for Application {
+    fn from(app: &rpc::Application) -> Self {
+        Application {
+            name: app.name.clone(),
+            shim: Shim::try_from(app.shim).unwrap_or_default(),
+            command: app.command.clone(),
+            arguments: app.arguments.clone(),
+            environments: app.environments.clone(),
+            working_directory: app.working_directory.clone(),
+            weight: app.weight,
+            default_slots: app.default_slots,
+            default_task_timeout: app.default_task_timeout,
+            default_max_retries: app.default_max_retries,
+            default_labels: app.default_labels.clone().into_iter().collect(),
+            input: app.input.as_ref().map(|input| InputConstraints {
+                max_bytes: input.max_bytes,
+                required_fields: input.required_fields.clone(),
+                schema: input.schema.clone(),
+                strict: input.strict,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::mock::{self, MockFrontend};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Sum {
+        a: i32,
+        b: i32,
+    }
+
+    /// Echos the input, and fails the task if the input is "fail".
+    fn echo(input: &[u8]) -> (rpc::TaskState, Vec<u8>) {
+        match input {
+            b"fail" => (rpc::TaskState::TaskFailed, b"bad input".to_vec()),
+            _ => (rpc::TaskState::TaskSucceed, input.to_vec()),
+        }
+    }
+
+    fn sum(input: &[u8]) -> (rpc::TaskState, Vec<u8>) {
+        let Sum { a, b } = serde_json::from_slice(input).unwrap();
+        (
+            rpc::TaskState::TaskSucceed,
+            (a + b).to_string().into_bytes(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_run_task_output() -> Result<(), FlameError> {
+        let ssn = mock::open(MockFrontend::new(echo)).await?;
+
+        let output = ssn.run_task_output("hello").await?;
+        assert_eq!(output, Bytes::from("hello"));
+
+        match ssn.run_task_output("fail").await {
+            Err(FlameError::TaskFailed { id, message }) => {
+                assert_eq!(id, "2");
+                assert_eq!(message, "bad input");
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_task_json() -> Result<(), FlameError> {
+        let ssn = mock::open(MockFrontend::new(sum)).await?;
+
+        let output: i32 = ssn.run_task_json(&Sum { a: 1, b: 2 }).await?;
+        assert_eq!(output, 3);
+
+        let output: Result<Sum, FlameError> = ssn.run_task_json(&Sum { a: 1, b: 2 }).await;
+        assert!(matches!(output, Err(FlameError::Internal(_))));
+
+        Ok(())
+    }
+
+    /// Echos the input after the milliseconds of the input.
+    fn sleep(input: &[u8]) -> Duration {
+        let ms = String::from_utf8_lossy(input).parse().unwrap_or_default();
+        Duration::from_millis(ms)
+    }
+
+    #[tokio::test]
+    async fn test_map() -> Result<(), FlameError> {
+        let mock = MockFrontend::new(echo).with_delay(sleep);
+        let ssn = mock::open(mock.clone()).await?;
+
+        let inputs = ["300", "100", "200"].map(TaskInput::from);
+        let results = ssn.map(inputs.clone(), 1).collect_ordered().await;
+        let outputs: Vec<TaskOutput> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(outputs, inputs);
+        assert_eq!(mock.max_watching(), 1);
+
+        let results: Vec<(usize, Result<TaskOutput, FlameError>)> =
+            ssn.map(inputs, 3).collect().await;
+        let indexes: Vec<usize> = results.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indexes, vec![1, 2, 0]);
+        assert_eq!(mock.max_watching(), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_map_with_failures() -> Result<(), FlameError> {
+        let mock = MockFrontend::new(echo).with_drops(1);
+        let ssn = mock::open(mock.clone()).await?;
+
+        let inputs = ["a", "fail", "c"].map(TaskInput::from);
+        let results = ssn.map(inputs, 2).collect_ordered().await;
+
+        assert_eq!(results[0].as_ref().unwrap(), &Bytes::from("a"));
+        assert!(matches!(results[1], Err(FlameError::TaskFailed { .. })));
+        assert_eq!(results[2].as_ref().unwrap(), &Bytes::from("c"));
+        assert_eq!(mock.watches("1"), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_map_with_backpressure() -> Result<(), FlameError> {
+        let retry_after = Duration::from_millis(200);
+        let mock = MockFrontend::new(echo).with_exhaustions(2, retry_after);
+        let ssn = mock::open(mock.clone()).await?;
+
+        let Err(err) = ssn.create_task(None).await else {
+            panic!("the backlog should be full");
+        };
+        assert!(err.is_retryable());
+        assert_eq!(err.retry_after(), Some(retry_after));
+
+        let start = Instant::now();
+        let inputs = ["a", "b", "c"].map(TaskInput::from);
+        let results = ssn.map(inputs.clone(), 3).collect_ordered().await;
+        let outputs: Vec<TaskOutput> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(outputs, inputs);
+        assert!(start.elapsed() >= retry_after);
+        assert_eq!(mock.creates(), 5);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_large_payload() -> Result<(), FlameError> {
+        let options = GrpcOptions {
+            max_send_message_size: 16 << 20,
+            max_recv_message_size: 16 << 20,
+            compression: None,
+        };
+        let addr = mock::start_with_options(MockFrontend::new(echo), options).await?;
+        let attrs = SessionAttributes {
+            application: "mock".to_string(),
+            slots: 1,
+            ..Default::default()
+        };
+        let input = Bytes::from(vec![b'x'; 6 << 20]);
+
+        let mut builder = ConnectionBuilder::new(&addr)
+            .max_send_message_size(16 << 20)
+            .max_recv_message_size(16 << 20);
+        if cfg!(feature = "gzip") {
+            builder = builder.compression(Compression::Gzip);
+        }
+        let ssn = builder.connect().await?.create_session(&attrs).await?;
+        assert_eq!(ssn.run_task_output(input.clone()).await?, input);
+
+        // The input is rejected before sending by the default limit.
+        let ssn = connect(&addr).await?.create_session(&attrs).await?;
+        let Err(e) = ssn.create_task(Some(input.clone())).await else {
+            panic!("the input should be rejected");
+        };
+        assert!(matches!(e, FlameError::InvalidArgument(_)), "{:?}", e);
+        assert!(
+            e.to_string()
+                .contains("6291456 bytes exceeds the message size limit of 4194304 bytes"),
+            "{}",
+            e
+        );
+
+        // The output is rejected by the limit of the client.
+        let ssn = ConnectionBuilder::new(&addr)
+            .max_send_message_size(16 << 20)
+            .connect()
+            .await?
+            .create_session(&attrs)
+            .await?;
+        let e = ssn.run_task_output(input).await.unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("raise grpc.max_recv_message_size of the receiver"),
+            "{}",
+            e
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_server_errors() -> Result<(), FlameError> {
+        use common::FlameError as ServerError;
+
+        let cases = vec![
+            (ServerError::NotFound("ssn".to_string()), "NotFound"),
+            (
+                ServerError::InvalidConfig("slots".to_string()),
+                "InvalidArgument",
+            ),
+            (
+                ServerError::FailedPrecondition("closed".to_string()),
+                "InvalidArgument",
+            ),
+            (
+                ServerError::AlreadyExists("app".to_string()),
+                "InvalidArgument",
+            ),
+            (
+                ServerError::Unavailable {
+                    message: "reset".to_string(),
+                    retryable: true,
+                },
+                "Retryable",
+            ),
+            (
+                ServerError::Unavailable {
+                    message: "draining".to_string(),
+                    retryable: false,
+                },
+                "Unavailable",
+            ),
+            (
+                ServerError::ResourceExhausted {
+                    message: "slots".to_string(),
+                    retry_after: None,
+                },
+                "Retryable",
+            ),
+            (
+                ServerError::Uninitialized("storage".to_string()),
+                "Retryable",
+            ),
+            (ServerError::Internal("mutex".to_string()), "Internal"),
+            (ServerError::storage("sqlite"), "Internal"),
+            (
+                ServerError::DeadlineExceeded("create_task".to_string()),
+                "DeadlineExceeded",
+            ),
+        ];
+
+        for (err, expected) in cases {
+            let name = format!("{:?}", err);
+            let mock = MockFrontend::new(echo).with_error(Status::from(err));
+            let conn = ConnectionBuilder::new(&mock::start(mock).await?)
+                .retry(RetryPolicy::none())
+                .connect()
+                .await?;
+
+            let res = conn.get_session(&"1".to_string()).await;
+            let kind = match res {
+                Err(FlameError::NotFound(_)) => "NotFound",
+                Err(FlameError::InvalidArgument(_)) => "InvalidArgument",
+                Err(e) if e.is_retryable() => "Retryable",
+                Err(FlameError::Unavailable { .. }) => "Unavailable",
+                Err(FlameError::Internal(_)) => "Internal",
+                Err(FlameError::DeadlineExceeded(_)) => "DeadlineExceeded",
+                _ => "Unexpected",
+            };
+            assert_eq!(kind, expected, "{}", name);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retry() -> Result<(), FlameError> {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            interval: Duration::from_millis(10),
+            max_interval: Duration::from_millis(10),
+        };
+
+        let mock = MockFrontend::new(echo).with_failures(Status::unavailable("restarting"), 2);
+        let conn = ConnectionBuilder::new(&mock::start(mock.clone()).await?)
+            .retry(policy.clone())
+            .connect()
+            .await?;
+        conn.get_session(&"1".to_string()).await?;
+        assert_eq!(mock.gets(), 3);
+
+        let mock = MockFrontend::new(echo).with_failures(Status::unavailable("restarting"), 3);
+        let conn = ConnectionBuilder::new(&mock::start(mock.clone()).await?)
+            .retry(policy)
+            .connect()
+            .await?;
+        let res = conn.get_session(&"1".to_string()).await;
+        assert!(matches!(res, Err(e) if e.is_retryable()));
+        assert_eq!(mock.gets(), 3);
+
+        // The errors which are not retryable are returned at once.
+        let mock = MockFrontend::new(echo).with_failures(Status::not_found("ssn"), 1);
+        let conn = mock::serve(mock.clone()).await?;
+        let res = conn.get_session(&"1".to_string()).await;
+        assert!(matches!(res, Err(FlameError::NotFound(_))));
+        assert_eq!(mock.gets(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_timeout() -> Result<(), FlameError> {
+        let mock = MockFrontend::new(echo).with_latency(Duration::from_millis(500));
+        let conn = ConnectionBuilder::new(&mock::start(mock.clone()).await?)
+            .timeout(Duration::from_millis(50))
+            .retry(RetryPolicy::none())
+            .connect()
+            .await?;
+
+        let res = conn.get_session(&"1".to_string()).await;
+        assert!(matches!(res, Err(FlameError::DeadlineExceeded(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_deadline() -> Result<(), FlameError> {
+        let mock = MockFrontend::new(echo).with_latency(Duration::from_millis(500));
+        let conn = mock::serve(mock.clone()).await?;
+
+        // The call is not held by the slow server, whose retries would not complete in time.
+        let start = Instant::now();
+        let res = conn
+            .with_timeout(Duration::from_millis(50))
+            .get_session(&"1".to_string())
+            .await;
+        assert!(matches!(res, Err(FlameError::DeadlineExceeded(_))));
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert_eq!(mock.gets(), 1);
+
+        // The calls after the deadline are not sent.
+        let expired = conn.with_deadline(Instant::now());
+        let res = expired.get_session(&"1".to_string()).await;
+        assert!(matches!(res, Err(FlameError::DeadlineExceeded(_))));
+        assert_eq!(mock.gets(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_map_with_deadline() -> Result<(), FlameError> {
+        let mock = MockFrontend::new(echo).with_delay(sleep);
+        let ssn = mock::open(mock.clone()).await?;
+
+        // The results not ready by the deadline are expired, and their tasks keep running.
+        let inputs = ["10", "2000", "20"].map(TaskInput::from);
+        let start = Instant::now();
+        let budget = ssn.with_deadline(start + Duration::from_millis(300));
+        let results = budget.map(inputs.clone(), 3).collect_ordered().await;
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(results[0].as_ref().unwrap(), &inputs[0]);
+        assert!(matches!(
+            &results[1],
+            Err(FlameError::Expired { task_id: Some(id) }) if id == "2"
+        ));
+        assert_eq!(results[2].as_ref().unwrap(), &inputs[2]);
+        assert_eq!(mock.creates(), 3);
+
+        // No task is created after the deadline.
+        let results = budget.map(inputs, 3).collect_ordered().await;
+        assert!(results
+            .iter()
+            .all(|r| matches!(r, Err(FlameError::Expired { task_id: None }))));
+        assert_eq!(mock.creates(), 3);
+
+        Ok(())
+    }
+}
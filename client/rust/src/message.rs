@@ -0,0 +1,38 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The typed payloads of the tasks; the services decode them by `TaskContext::input_as`, see
+//! `common::message` for the envelope.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+pub use common::message::{Codec, VERSION};
+
+use crate::{FlameError, TaskInput, TaskOutput};
+
+pub fn encode<T: Serialize>(codec: Codec, value: &T) -> Result<TaskInput, FlameError> {
+    Ok(common::message::encode(codec, value)?)
+}
+
+pub fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, FlameError> {
+    Ok(common::message::decode(data)?)
+}
+
+pub fn to_task_input<T: Serialize>(value: &T) -> Result<TaskInput, FlameError> {
+    Ok(common::message::to_task_input(value)?)
+}
+
+pub fn from_task_output<T: DeserializeOwned>(output: &TaskOutput) -> Result<T, FlameError> {
+    Ok(common::message::from_task_output(output)?)
+}
@@ -0,0 +1,614 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! An in-process Frontend service for the unit tests of the client.
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::Stream;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+use common::grpc::GrpcOptions;
+use common::with_grpc_options;
+
+use crate::flame as rpc;
+use crate::flame::frontend_server::{Frontend, FrontendServer};
+use crate::flame::session_event::Event;
+use crate::{capability, Connection, FlameError, SessionAttributes};
+
+/// Executes the input of a task, and returns the state and output of the task.
+pub type Executor = fn(&[u8]) -> (rpc::TaskState, Vec<u8>);
+
+/// Returns how long the task of the input takes.
+pub type Delay = fn(&[u8]) -> Duration;
+
+#[derive(Clone)]
+pub struct MockFrontend {
+    executor: Executor,
+    delay: Delay,
+    /// The number of watches of each task, which are dropped before the task is completed.
+    drops: usize,
+    /// The number of session watches, which are dropped after the first event.
+    session_drops: usize,
+    /// The error returned by the first `failures` calls of get_session.
+    error: Option<Status>,
+    failures: usize,
+    /// The latency of get_session.
+    latency: Duration,
+    /// The capabilities of the server; it's an old server without GetServerInfo if none.
+    capabilities: Option<Vec<String>>,
+    /// The first `exhaustions` calls of create_task are rejected with the hint of `retry_after`.
+    exhaustions: usize,
+    retry_after: Duration,
+
+    tasks: Arc<Mutex<HashMap<String, rpc::Task>>>,
+    watches: Arc<Mutex<HashMap<String, usize>>>,
+    events: Arc<Mutex<Vec<rpc::SessionEvent>>>,
+    session_watches: Arc<AtomicUsize>,
+    gets: Arc<AtomicUsize>,
+    closes: Arc<AtomicUsize>,
+    closed: Arc<AtomicBool>,
+    watching: Arc<AtomicUsize>,
+    max_watching: Arc<AtomicUsize>,
+    creates: Arc<AtomicUsize>,
+}
+
+impl MockFrontend {
+    pub fn new(executor: Executor) -> Self {
+        MockFrontend {
+            executor,
+            delay: |_| Duration::ZERO,
+            drops: 0,
+            session_drops: 0,
+            error: None,
+            failures: 0,
+            latency: Duration::ZERO,
+            capabilities: Some(capability::ALL.iter().map(|c| c.to_string()).collect()),
+            exhaustions: 0,
+            retry_after: Duration::ZERO,
+            tasks: Arc::new(Mutex::new(HashMap::new())),
+            watches: Arc::new(Mutex::new(HashMap::new())),
+            events: Arc::new(Mutex::new(vec![])),
+            session_watches: Arc::new(AtomicUsize::new(0)),
+            gets: Arc::new(AtomicUsize::new(0)),
+            closes: Arc::new(AtomicUsize::new(0)),
+            closed: Arc::new(AtomicBool::new(false)),
+            watching: Arc::new(AtomicUsize::new(0)),
+            max_watching: Arc::new(AtomicUsize::new(0)),
+            creates: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn with_delay(mut self, delay: Delay) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn with_drops(mut self, drops: usize) -> Self {
+        self.drops = drops;
+        self
+    }
+
+    pub fn with_session_drops(mut self, drops: usize) -> Self {
+        self.session_drops = drops;
+        self
+    }
+
+    pub fn with_error(self, error: Status) -> Self {
+        self.with_failures(error, usize::MAX)
+    }
+
+    /// Fails the first `failures` calls of get_session with the error.
+    pub fn with_failures(mut self, error: Status, failures: usize) -> Self {
+        self.error = Some(error);
+        self.failures = failures;
+        self
+    }
+
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn with_capabilities(mut self, capabilities: Option<&[&str]>) -> Self {
+        self.capabilities = capabilities.map(|c| c.iter().map(|c| c.to_string()).collect());
+        self
+    }
+
+    /// Rejects the first `exhaustions` calls of create_task as the backlog of the session is full.
+    pub fn with_exhaustions(mut self, exhaustions: usize, retry_after: Duration) -> Self {
+        self.exhaustions = exhaustions;
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// The number of calls of create_task, including the rejected ones.
+    pub fn creates(&self) -> usize {
+        self.creates.load(Ordering::SeqCst)
+    }
+
+    /// The number of calls of get_session.
+    pub fn gets(&self) -> usize {
+        self.gets.load(Ordering::SeqCst)
+    }
+
+    /// The number of watches of the session.
+    pub fn session_watches(&self) -> usize {
+        self.session_watches.load(Ordering::SeqCst)
+    }
+
+    /// The number of calls of close_session.
+    pub fn closes(&self) -> usize {
+        self.closes.load(Ordering::SeqCst)
+    }
+
+    /// The maximum number of watches at the same time.
+    pub fn max_watching(&self) -> usize {
+        self.max_watching.load(Ordering::SeqCst)
+    }
+
+    /// The number of watches of the task.
+    pub fn watches(&self, id: &str) -> usize {
+        let watches = self.watches.lock().unwrap();
+        watches.get(id).cloned().unwrap_or_default()
+    }
+
+    fn task(&self, id: &str) -> Option<rpc::Task> {
+        let tasks = self.tasks.lock().unwrap();
+        tasks.get(id).cloned()
+    }
+
+    fn push_event(&self, event: Event) {
+        let mut events = self.events.lock().unwrap();
+        let sequence = events.len() as u64 + 1;
+        events.push(rpc::SessionEvent {
+            sequence,
+            event_time: 0,
+            event: Some(event),
+        });
+    }
+
+    fn events_since(&self, since: u64) -> Vec<rpc::SessionEvent> {
+        let events = self.events.lock().unwrap();
+        events
+            .iter()
+            .filter(|e| e.sequence > since)
+            .cloned()
+            .collect()
+    }
+
+    fn session(&self, spec: rpc::SessionSpec) -> rpc::Session {
+        let state = match self.closed.load(Ordering::SeqCst) {
+            true => rpc::SessionState::SessionClosed,
+            false => rpc::SessionState::SessionOpen,
+        };
+
+        rpc::Session {
+            metadata: Some(rpc::Metadata {
+                id: "1".to_string(),
+                owner: None,
+            }),
+            spec: Some(spec),
+            status: Some(rpc::SessionStatus {
+                state: state as i32,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+fn task_changed(task: &rpc::Task) -> Event {
+    Event::TaskStateChanged(rpc::TaskStateChangedEvent {
+        task_id: task.metadata.clone().unwrap_or_default().id,
+        state: task.status.clone().unwrap_or_default().state,
+    })
+}
+
+/// Starts the mock server on a random port, and returns its endpoint.
+pub async fn start(frontend: MockFrontend) -> Result<String, FlameError> {
+    start_with_options(frontend, GrpcOptions::default()).await
+}
+
+/// Starts the mock server with the options of gRPC, e.g. the size limits of the messages.
+pub async fn start_with_options(
+    frontend: MockFrontend,
+    options: GrpcOptions,
+) -> Result<String, FlameError> {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .map_err(|e| FlameError::Internal(e.to_string()))?;
+    let addr = listener
+        .local_addr()
+        .map_err(|e| FlameError::Internal(e.to_string()))?;
+
+    tokio::spawn(
+        Server::builder()
+            .add_service(with_grpc_options!(FrontendServer::new(frontend), options))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    Ok(format!("http://{}", addr))
+}
+
+/// Starts the mock server, and connects to it.
+pub async fn serve(frontend: MockFrontend) -> Result<Connection, FlameError> {
+    crate::connect(&start(frontend).await?).await
+}
+
+/// Starts the mock server, and opens a session of it.
+pub async fn open(frontend: MockFrontend) -> Result<crate::Session, FlameError> {
+    let conn = serve(frontend).await?;
+    conn.create_session(&SessionAttributes {
+        application: "mock".to_string(),
+        slots: 1,
+        ..Default::default()
+    })
+    .await
+}
+
+#[tonic::async_trait]
+impl Frontend for MockFrontend {
+    type WatchTaskStream = Pin<Box<dyn Stream<Item = Result<rpc::Task, Status>> + Send>>;
+    type WatchSessionStream = Pin<Box<dyn Stream<Item = Result<rpc::SessionEvent, Status>> + Send>>;
+    type ExportSessionStream =
+        Pin<Box<dyn Stream<Item = Result<rpc::SessionArchive, Status>> + Send>>;
+    type FetchTaskOutputsStream =
+        Pin<Box<dyn Stream<Item = Result<rpc::TaskOutputEntry, Status>> + Send>>;
+    type FetchTaskOutputChunksStream =
+        Pin<Box<dyn Stream<Item = Result<rpc::TaskOutputChunk, Status>> + Send>>;
+
+    async fn create_session(
+        &self,
+        req: Request<rpc::CreateSessionRequest>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        let spec = req.into_inner().session.unwrap_or_default();
+        Ok(Response::new(self.session(spec)))
+    }
+
+    async fn delete_session(
+        &self,
+        _: Request<rpc::DeleteSessionRequest>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        Err(Status::unimplemented("delete_session"))
+    }
+
+    async fn export_session(
+        &self,
+        _: Request<rpc::ExportSessionRequest>,
+    ) -> Result<Response<Self::ExportSessionStream>, Status> {
+        Err(Status::unimplemented("export_session"))
+    }
+
+    async fn import_session(
+        &self,
+        _: Request<Streaming<rpc::SessionArchive>>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        Err(Status::unimplemented("import_session"))
+    }
+
+    async fn open_session(
+        &self,
+        _: Request<rpc::OpenSessionRequest>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        self.closed.store(false, Ordering::SeqCst);
+        Ok(Response::new(self.session(rpc::SessionSpec::default())))
+    }
+
+    async fn close_session(
+        &self,
+        _: Request<rpc::CloseSessionRequest>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        self.closes.fetch_add(1, Ordering::SeqCst);
+        self.closed.store(true, Ordering::SeqCst);
+        self.push_event(Event::SessionClosed(rpc::SessionClosedEvent {}));
+        Ok(Response::new(self.session(rpc::SessionSpec::default())))
+    }
+
+    async fn get_session(
+        &self,
+        _: Request<rpc::GetSessionRequest>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        let calls = self.gets.fetch_add(1, Ordering::SeqCst) + 1;
+        tokio::time::sleep(self.latency).await;
+
+        if let Some(e) = &self.error {
+            if calls <= self.failures {
+                return Err(e.clone());
+            }
+        }
+
+        Ok(Response::new(self.session(rpc::SessionSpec::default())))
+    }
+
+    async fn update_session(
+        &self,
+        _: Request<rpc::UpdateSessionRequest>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        Err(Status::unimplemented("update_session"))
+    }
+
+    async fn list_session(
+        &self,
+        _: Request<rpc::ListSessionRequest>,
+    ) -> Result<Response<rpc::SessionList>, Status> {
+        Err(Status::unimplemented("list_session"))
+    }
+
+    async fn list_session_template(
+        &self,
+        _: Request<rpc::ListSessionTemplateRequest>,
+    ) -> Result<Response<rpc::SessionTemplateList>, Status> {
+        Err(Status::unimplemented("list_session_template"))
+    }
+
+    async fn clone_session(
+        &self,
+        _: Request<rpc::CloneSessionRequest>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        Err(Status::unimplemented("clone_session"))
+    }
+
+    async fn list_task(
+        &self,
+        _: Request<rpc::ListTaskRequest>,
+    ) -> Result<Response<rpc::TaskList>, Status> {
+        Err(Status::unimplemented("list_task"))
+    }
+
+    async fn fetch_task_outputs(
+        &self,
+        _: Request<rpc::FetchTaskOutputsRequest>,
+    ) -> Result<Response<Self::FetchTaskOutputsStream>, Status> {
+        Err(Status::unimplemented("fetch_task_outputs"))
+    }
+
+    async fn fetch_task_output_chunks(
+        &self,
+        _: Request<rpc::FetchTaskOutputChunksRequest>,
+    ) -> Result<Response<Self::FetchTaskOutputChunksStream>, Status> {
+        Err(Status::unimplemented("fetch_task_output_chunks"))
+    }
+
+    async fn resubmit_task(
+        &self,
+        _: Request<rpc::ResubmitTaskRequest>,
+    ) -> Result<Response<rpc::Task>, Status> {
+        Err(Status::unimplemented("resubmit_task"))
+    }
+
+    async fn create_task(
+        &self,
+        req: Request<rpc::CreateTaskRequest>,
+    ) -> Result<Response<rpc::Task>, Status> {
+        let spec = req.into_inner().task.unwrap_or_default();
+        if self.creates.fetch_add(1, Ordering::SeqCst) < self.exhaustions {
+            return Err(Status::from(common::FlameError::ResourceExhausted {
+                message: "backlog is full".to_string(),
+                retry_after: Some(self.retry_after),
+            }));
+        }
+
+        let mut tasks = self.tasks.lock().unwrap();
+        let task = rpc::Task {
+            metadata: Some(rpc::Metadata {
+                id: (tasks.len() + 1).to_string(),
+                owner: None,
+            }),
+            index: tasks.len() as u64,
+            original_task_id: None,
+            spec: Some(spec),
+            status: Some(rpc::TaskStatus::default()),
+        };
+        tasks.insert(task.metadata.clone().unwrap().id, task.clone());
+        drop(tasks);
+
+        self.push_event(task_changed(&task));
+
+        Ok(Response::new(task))
+    }
+
+    async fn create_task_stream(
+        &self,
+        _: Request<Streaming<rpc::TaskChunk>>,
+    ) -> Result<Response<rpc::Task>, Status> {
+        Err(Status::unimplemented("create_task_stream"))
+    }
+
+    async fn delete_task(
+        &self,
+        _: Request<rpc::DeleteTaskRequest>,
+    ) -> Result<Response<rpc::Task>, Status> {
+        Err(Status::unimplemented("delete_task"))
+    }
+
+    async fn get_task(
+        &self,
+        req: Request<rpc::GetTaskRequest>,
+    ) -> Result<Response<rpc::Task>, Status> {
+        let id = req.into_inner().task_id;
+        let task = self
+            .task(&id)
+            .ok_or(Status::not_found(format!("task <{}>", id)))?;
+
+        Ok(Response::new(task))
+    }
+
+    /// Sends the task as running, then executes it and sends the completed task; or sends
+    /// an error instead if the watch should be dropped.
+    async fn watch_task(
+        &self,
+        req: Request<rpc::WatchTaskRequest>,
+    ) -> Result<Response<Self::WatchTaskStream>, Status> {
+        let id = req.into_inner().task_id;
+        let mut task = self
+            .task(&id)
+            .ok_or(Status::not_found(format!("task <{}>", id)))?;
+        let mut spec = task.spec.clone().unwrap_or_default();
+        let input = spec.input.clone().unwrap_or_default();
+
+        let attempt = {
+            let mut watches = self.watches.lock().unwrap();
+            let attempt = watches.entry(id.clone()).or_default();
+            *attempt += 1;
+            *attempt
+        };
+
+        let mut running = task.clone();
+        running.status = Some(rpc::TaskStatus {
+            state: rpc::TaskState::TaskRunning as i32,
+            ..Default::default()
+        });
+
+        let watching = self.watching.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_watching.fetch_max(watching, Ordering::SeqCst);
+
+        let (tx, rx) = mpsc::channel(2);
+        let mock = self.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(Ok(running)).await;
+            tokio::time::sleep((mock.delay)(&input)).await;
+
+            if attempt <= mock.drops {
+                let _ = tx.send(Err(Status::unavailable("connection reset"))).await;
+            } else {
+                let (state, output) = (mock.executor)(&input);
+                spec.output = Some(output);
+                task.spec = Some(spec);
+                task.status = Some(rpc::TaskStatus {
+                    state: state as i32,
+                    ..Default::default()
+                });
+
+                mock.tasks.lock().unwrap().insert(id, task.clone());
+                mock.push_event(task_changed(&task));
+                let _ = tx.send(Ok(task)).await;
+            }
+
+            mock.watching.fetch_sub(1, Ordering::SeqCst);
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    /// Sends the events after `since` until the session is closed; or drops the watch after
+    /// the first event.
+    async fn watch_session(
+        &self,
+        req: Request<rpc::WatchSessionRequest>,
+    ) -> Result<Response<Self::WatchSessionStream>, Status> {
+        let mut since = req.into_inner().since;
+        let attempt = self.session_watches.fetch_add(1, Ordering::SeqCst) + 1;
+
+        let (tx, rx) = mpsc::channel(2);
+        let mock = self.clone();
+        tokio::spawn(async move {
+            loop {
+                for event in mock.events_since(since) {
+                    since = event.sequence;
+                    let closed = matches!(event.event, Some(Event::SessionClosed(_)));
+                    if tx.send(Ok(event)).await.is_err() || closed {
+                        return;
+                    }
+                    if attempt <= mock.session_drops {
+                        let _ = tx.send(Err(Status::unavailable("connection reset"))).await;
+                        return;
+                    }
+                }
+
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn get_session_events(
+        &self,
+        _: Request<rpc::GetSessionEventsRequest>,
+    ) -> Result<Response<rpc::SessionEventList>, Status> {
+        Err(Status::unimplemented("get_session_events"))
+    }
+
+    async fn get_server_info(
+        &self,
+        _: Request<rpc::GetServerInfoRequest>,
+    ) -> Result<Response<rpc::ServerInfo>, Status> {
+        let capabilities = self
+            .capabilities
+            .clone()
+            .ok_or(Status::unimplemented("get_server_info"))?;
+
+        Ok(Response::new(rpc::ServerInfo {
+            version: "0.1.0".to_string(),
+            build: "mock".to_string(),
+            capabilities,
+        }))
+    }
+
+    async fn register_application(
+        &self,
+        _: Request<rpc::RegisterApplicationRequest>,
+    ) -> Result<Response<rpc::Result>, Status> {
+        Err(Status::unimplemented("register_application"))
+    }
+
+    async fn list_application(
+        &self,
+        _: Request<rpc::ListApplicationRequest>,
+    ) -> Result<Response<rpc::ApplicationList>, Status> {
+        Err(Status::unimplemented("list_application"))
+    }
+
+    async fn delete_application(
+        &self,
+        _: Request<rpc::DeleteApplicationRequest>,
+    ) -> Result<Response<rpc::Result>, Status> {
+        Err(Status::unimplemented("delete_application"))
+    }
+
+    async fn list_executor(
+        &self,
+        _: Request<rpc::ListExecutorRequest>,
+    ) -> Result<Response<rpc::ExecutorList>, Status> {
+        Err(Status::unimplemented("list_executor"))
+    }
+
+    async fn get_executor(
+        &self,
+        _: Request<rpc::GetExecutorRequest>,
+    ) -> Result<Response<rpc::Executor>, Status> {
+        Err(Status::unimplemented("get_executor"))
+    }
+
+    async fn drain_executor(
+        &self,
+        _: Request<rpc::DrainExecutorRequest>,
+    ) -> Result<Response<rpc::Executor>, Status> {
+        Err(Status::unimplemented("drain_executor"))
+    }
+
+    async fn uncordon_executor(
+        &self,
+        _: Request<rpc::UncordonExecutorRequest>,
+    ) -> Result<Response<rpc::Executor>, Status> {
+        Err(Status::unimplemented("uncordon_executor"))
+    }
+}
@@ -0,0 +1,168 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::ops::Range;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::{Stream, StreamExt};
+use tonic::Streaming;
+
+use crate::flame as rpc;
+use crate::trace::TraceFn;
+use crate::{trace_fn, FlameError, Session, TaskID, TaskOutput, TaskState};
+
+/// The output of a task streamed by `Session::outputs`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TaskOutputEntry {
+    pub task_id: TaskID,
+    pub index: u64,
+    /// None if the task has no output, e.g. it's not completed.
+    pub output: Option<TaskOutput>,
+}
+
+/// A chunk of the output streamed by a running task, e.g. a frame of a render; see
+/// `Session::output_chunks`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputChunk {
+    /// The order of the chunk in the output of the task, from 1.
+    pub sequence: u64,
+    pub data: Bytes,
+    pub creation_time: DateTime<Utc>,
+}
+
+/// The tasks whose outputs are streamed by `Session::outputs`; all the tasks by default.
+#[derive(Clone, Debug, Default)]
+pub struct OutputFilter {
+    pub state: Option<TaskState>,
+    /// The range of the indexes of the tasks; from the start index to the last task if the
+    /// end is none.
+    pub start_index: u64,
+    pub end_index: Option<u64>,
+}
+
+impl OutputFilter {
+    pub fn state(state: TaskState) -> Self {
+        OutputFilter {
+            state: Some(state),
+            ..Default::default()
+        }
+    }
+
+    pub fn indexes(mut self, indexes: Range<u64>) -> Self {
+        self.start_index = indexes.start;
+        self.end_index = Some(indexes.end);
+        self
+    }
+}
+
+/// The outputs of the tasks of a session in the order of their indexes; the session manager
+/// sends the next outputs only as they're consumed.
+pub struct TaskOutputs {
+    inner: Streaming<rpc::TaskOutputEntry>,
+}
+
+impl Stream for TaskOutputs {
+    type Item = Result<TaskOutputEntry, FlameError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx).map(|entry| {
+            entry.map(|entry| {
+                let entry = entry?;
+                Ok(TaskOutputEntry {
+                    task_id: entry.task_id,
+                    index: entry.index,
+                    output: entry.output.map(TaskOutput::from),
+                })
+            })
+        })
+    }
+}
+
+impl Session {
+    /// Streams the outputs of the tasks of the session, e.g. to download the results of a
+    /// large session without a call per task; it requires `capability::TASK_OUTPUTS`.
+    pub async fn outputs(&self, filter: &OutputFilter) -> Result<TaskOutputs, FlameError> {
+        trace_fn!("Session::outputs");
+        let mut client = self
+            .client
+            .clone()
+            .ok_or(FlameError::Internal("no flame client".to_string()))?;
+
+        let fetch_req = rpc::FetchTaskOutputsRequest {
+            session_id: self.id.clone(),
+            namespace: self.namespace.clone(),
+            start_index: filter.start_index,
+            end_index: filter.end_index,
+            state: filter.state.map(|s| s as i32),
+        };
+        let inner = client.fetch_task_outputs(fetch_req).await?.into_inner();
+
+        Ok(TaskOutputs { inner })
+    }
+}
+
+/// The output chunks of a task in order; the stream ends once the task is completed and all
+/// of its chunks are read.
+pub struct OutputChunks {
+    inner: Streaming<rpc::TaskOutputChunk>,
+}
+
+impl Stream for OutputChunks {
+    type Item = Result<OutputChunk, FlameError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx).map(|chunk| {
+            chunk.map(|chunk| {
+                let chunk = chunk?;
+                Ok(OutputChunk {
+                    sequence: chunk.sequence,
+                    data: Bytes::from(chunk.data),
+                    creation_time: DateTime::<Utc>::from_timestamp(chunk.creation_time, 0)
+                        .unwrap_or_default(),
+                })
+            })
+        })
+    }
+}
+
+impl Session {
+    /// Streams the output chunks of the task after the `since` sequence, e.g. 0 for all of
+    /// them, while the task is running; it requires `capability::OUTPUT_CHUNKS`.
+    pub async fn output_chunks(
+        &self,
+        task_id: &TaskID,
+        since: u64,
+    ) -> Result<OutputChunks, FlameError> {
+        trace_fn!("Session::output_chunks");
+        let mut client = self
+            .client
+            .clone()
+            .ok_or(FlameError::Internal("no flame client".to_string()))?;
+
+        let fetch_req = rpc::FetchTaskOutputChunksRequest {
+            session_id: self.id.clone(),
+            task_id: task_id.clone(),
+            namespace: self.namespace.clone(),
+            since,
+        };
+        let inner = client
+            .fetch_task_output_chunks(fetch_req)
+            .await?
+            .into_inner();
+
+        Ok(OutputChunks { inner })
+    }
+}
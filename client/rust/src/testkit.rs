@@ -0,0 +1,1233 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! An in-process Flame server for the tests of applications; it keeps the sessions and tasks in
+//! memory, and the tasks are completed by the test through a fake executor, e.g.
+//!
+//! ```ignore
+//! let server = MockServer::start().await?;
+//! let ssn = server.connect().await?.create_session(&attrs).await?;
+//!
+//! let (output, _) = tokio::try_join!(
+//!     ssn.run_task_output("input"),
+//!     server.complete_next_task("output"),
+//! )?;
+//! ```
+//!
+//! The fake executor also runs a task to stream its output chunks before it's completed, e.g.
+//!
+//! ```ignore
+//! let task = server.start_next_task().await?;
+//! task.emit_chunk("frame 1");
+//! task.complete("done");
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use chrono::Utc;
+use futures::Stream;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, Notify};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::transport::{Endpoint, Server, Uri};
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::flame as rpc;
+use crate::flame::frontend_server::{Frontend, FrontendServer};
+use crate::flame::session_archive::Item;
+use crate::flame::session_event::Event;
+use crate::flame::task_chunk::Item as Chunk;
+use crate::{capability, Connection, FlameError, Interceptor, RetryPolicy, Task, TaskOutput};
+
+#[derive(Default)]
+struct Store {
+    sessions: BTreeMap<String, rpc::Session>,
+    tasks: BTreeMap<(String, String), rpc::Task>,
+    events: BTreeMap<String, Vec<rpc::SessionEvent>>,
+    applications: BTreeMap<String, rpc::Application>,
+    /// The output chunks streamed by the running tasks of the fake executor.
+    chunks: BTreeMap<(String, String), Vec<rpc::TaskOutputChunk>>,
+    /// The tasks waiting for the fake executor, in the order of submission.
+    pending: VecDeque<(String, String)>,
+    next_ssn_id: u64,
+}
+
+impl Store {
+    fn session(&self, id: &str) -> Result<rpc::Session, FlameError> {
+        let mut ssn = self
+            .sessions
+            .get(id)
+            .cloned()
+            .ok_or(FlameError::NotFound(format!("session <{}>", id)))?;
+
+        let status = ssn.status.get_or_insert_with(Default::default);
+        for task in self.tasks.values().filter(|t| session_id(t) == id) {
+            match task_state(task) {
+                rpc::TaskState::TaskPending => status.pending += 1,
+                rpc::TaskState::TaskRunning => status.running += 1,
+                rpc::TaskState::TaskSucceed => status.succeed += 1,
+                rpc::TaskState::TaskFailed => status.failed += 1,
+            }
+        }
+
+        Ok(ssn)
+    }
+
+    fn task(&self, ssn_id: &str, task_id: &str) -> Result<rpc::Task, FlameError> {
+        self.tasks
+            .get(&(ssn_id.to_string(), task_id.to_string()))
+            .cloned()
+            .ok_or(FlameError::NotFound(format!(
+                "task <{}/{}>",
+                ssn_id, task_id
+            )))
+    }
+
+    /// Takes the next pending task whose session is under its `max_running_tasks`, which is
+    /// run by the fake executor.
+    fn next_runnable(&mut self) -> Option<(String, String)> {
+        let pos = self.pending.iter().position(|(ssn_id, _)| {
+            let Ok(ssn) = self.session(ssn_id) else {
+                return true;
+            };
+            let max = ssn.spec.and_then(|s| s.max_running_tasks);
+            let running = ssn.status.unwrap_or_default().running;
+            max.is_none_or(|max| running < max as i32)
+        })?;
+
+        self.pending.remove(pos)
+    }
+
+    /// Adds a pending task to the open session, and queues it for the fake executor.
+    fn add_task(
+        &mut self,
+        spec: rpc::TaskSpec,
+        original_task_id: Option<String>,
+    ) -> Result<rpc::Task, FlameError> {
+        let ssn = self.session(&spec.session_id)?;
+        // The task of the same key is returned instead of a new one.
+        if let Some(task) = self.tasks.values().find(|t| {
+            session_id(t) == spec.session_id
+                && spec.idempotency_key.is_some()
+                && t.spec.as_ref().and_then(|s| s.idempotency_key.as_ref())
+                    == spec.idempotency_key.as_ref()
+        }) {
+            return Ok(task.clone());
+        }
+        let state = ssn.status.clone().unwrap_or_default().state;
+        if state == rpc::SessionState::SessionClosed as i32 {
+            return Err(FlameError::InvalidArgument(format!(
+                "session <{}> is closed",
+                spec.session_id
+            )));
+        }
+
+        let ssn_id = spec.session_id.clone();
+        let index = self
+            .tasks
+            .keys()
+            .filter(|(task_ssn_id, _)| task_ssn_id == &ssn_id)
+            .count();
+        let id = (index + 1).to_string();
+        let task = rpc::Task {
+            metadata: Some(rpc::Metadata {
+                id: id.clone(),
+                owner: Some(ssn_id.clone()),
+            }),
+            index: index as u64,
+            original_task_id,
+            spec: Some(spec),
+            status: Some(rpc::TaskStatus {
+                state: rpc::TaskState::TaskPending as i32,
+                creation_time: Utc::now().timestamp(),
+                completion_time: None,
+                progress: None,
+                failure: None,
+                executor_id: None,
+                hostname: None,
+                annotations: Default::default(),
+                output_chunks: 0,
+            }),
+        };
+
+        self.tasks
+            .insert((ssn_id.clone(), id.clone()), task.clone());
+        self.pending.push_back((ssn_id.clone(), id));
+        self.push_event(&ssn_id, task_changed(&task));
+
+        Ok(task)
+    }
+
+    fn push_event(&mut self, ssn_id: &str, event: Event) {
+        let events = self.events.entry(ssn_id.to_string()).or_default();
+        events.push(rpc::SessionEvent {
+            sequence: events.len() as u64 + 1,
+            event_time: Utc::now().timestamp(),
+            event: Some(event),
+        });
+    }
+}
+
+fn session_id(task: &rpc::Task) -> &str {
+    task.spec
+        .as_ref()
+        .map(|s| s.session_id.as_str())
+        .unwrap_or_default()
+}
+
+fn task_state(task: &rpc::Task) -> rpc::TaskState {
+    let state = task.status.as_ref().map(|s| s.state).unwrap_or_default();
+    rpc::TaskState::try_from(state).unwrap_or_default()
+}
+
+fn is_completed(task: &rpc::Task) -> bool {
+    matches!(
+        task_state(task),
+        rpc::TaskState::TaskSucceed | rpc::TaskState::TaskFailed
+    )
+}
+
+fn task_changed(task: &rpc::Task) -> Event {
+    Event::TaskStateChanged(rpc::TaskStateChangedEvent {
+        task_id: task.metadata.clone().unwrap_or_default().id,
+        state: task_state(task) as i32,
+    })
+}
+
+fn finish_task(
+    store: &mut Store,
+    key: &(String, String),
+    state: rpc::TaskState,
+    output: &[u8],
+) -> Option<rpc::Task> {
+    let mut task = store.tasks.get(key).cloned()?;
+    if let Some(spec) = task.spec.as_mut() {
+        spec.output = Some(output.to_vec());
+    }
+    let status = task.status.clone().unwrap_or_default();
+    task.status = Some(rpc::TaskStatus {
+        state: state as i32,
+        creation_time: status.creation_time,
+        completion_time: Some(Utc::now().timestamp()),
+        progress: None,
+        failure: None,
+        executor_id: None,
+        hostname: None,
+        annotations: Default::default(),
+        output_chunks: status.output_chunks,
+    });
+
+    store.tasks.insert(key.clone(), task.clone());
+    store.push_event(&key.0, task_changed(&task));
+
+    Some(task)
+}
+
+/// The in-process Flame server; it's cheap to clone, and all the clones share the same store.
+#[derive(Clone)]
+pub struct MockServer {
+    store: Arc<Mutex<Store>>,
+    /// Notified on every change of the store.
+    changed: Arc<Notify>,
+    endpoint: String,
+}
+
+impl MockServer {
+    /// Starts the server on a random local port.
+    pub async fn start() -> Result<MockServer, FlameError> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| FlameError::Internal(e.to_string()))?;
+        let addr = listener
+            .local_addr()
+            .map_err(|e| FlameError::Internal(e.to_string()))?;
+
+        let server = MockServer {
+            store: Arc::new(Mutex::new(Store::default())),
+            changed: Arc::new(Notify::new()),
+            endpoint: format!("http://{}", addr),
+        };
+
+        tokio::spawn(
+            Server::builder()
+                .add_service(FrontendServer::new(server.clone()))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        Ok(server)
+    }
+
+    /// The endpoint of the server, e.g. for `ConnectionBuilder`.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Connects to the server over the local socket.
+    pub async fn connect(&self) -> Result<Connection, FlameError> {
+        crate::connect(&self.endpoint).await
+    }
+
+    /// Connects to the server over an in-memory channel, without any socket.
+    pub async fn connect_in_memory(&self) -> Result<Connection, FlameError> {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+
+        tokio::spawn(
+            Server::builder()
+                .add_service(FrontendServer::new(self.clone()))
+                .serve_with_incoming(tokio_stream::once(Ok::<_, std::io::Error>(server))),
+        );
+
+        let mut client = Some(client);
+        let channel = Endpoint::from_static("http://[::]:50051")
+            .connect_with_connector(tower::service_fn(move |_: Uri| {
+                let client = client.take();
+                async move {
+                    client.ok_or(std::io::Error::other(
+                        "in-memory channel was already connected",
+                    ))
+                }
+            }))
+            .await
+            .map_err(|e| FlameError::Unavailable {
+                message: format!("failed to connect in-memory channel: {}", e),
+                retryable: false,
+            })?;
+
+        Connection {
+            channel,
+            retry: RetryPolicy::default(),
+            interceptor: Interceptor::default(),
+            namespace: String::new(),
+            server: Default::default(),
+            grpc: Default::default(),
+        }
+        .negotiate()
+        .await
+    }
+
+    /// Waits for the next pending task, and completes it with the output.
+    pub async fn complete_next_task(
+        &self,
+        output: impl Into<TaskOutput>,
+    ) -> Result<Task, FlameError> {
+        let output = output.into();
+        self.finish_next_task(rpc::TaskState::TaskSucceed, output.to_vec())
+            .await
+    }
+
+    /// Waits for the next pending task, and fails it with the message.
+    pub async fn fail_next_task(&self, message: &str) -> Result<Task, FlameError> {
+        self.finish_next_task(rpc::TaskState::TaskFailed, message.as_bytes().to_vec())
+            .await
+    }
+
+    /// The number of tasks waiting for the fake executor.
+    pub fn pending_tasks(&self) -> usize {
+        let store = self.store.lock().unwrap();
+        store.pending.len()
+    }
+
+    async fn finish_next_task(
+        &self,
+        state: rpc::TaskState,
+        output: Vec<u8>,
+    ) -> Result<Task, FlameError> {
+        loop {
+            let changed = self.changed.notified();
+            if let Some(task) = self.try_finish_next_task(state, &output) {
+                self.changed.notify_waiters();
+                return Ok(Task::from(&task));
+            }
+            changed.await;
+        }
+    }
+
+    fn try_finish_next_task(&self, state: rpc::TaskState, output: &[u8]) -> Option<rpc::Task> {
+        let mut store = self.store.lock().unwrap();
+        let key = store.pending.pop_front()?;
+
+        finish_task(&mut store, &key, state, output)
+    }
+
+    /// Waits for the next pending task and marks it running, for the in-process executor.
+    #[cfg(feature = "embedded")]
+    pub(crate) async fn run_next_task(&self) -> (rpc::Session, rpc::Task) {
+        loop {
+            let changed = self.changed.notified();
+            if let Some(next) = self.try_run_next_task() {
+                self.changed.notify_waiters();
+                return next;
+            }
+            changed.await;
+        }
+    }
+
+    fn try_run_next_task(&self) -> Option<(rpc::Session, rpc::Task)> {
+        let mut store = self.store.lock().unwrap();
+        let key = store.next_runnable()?;
+
+        let ssn = store.sessions.get(&key.0).cloned()?;
+        let mut task = store.tasks.get(&key).cloned()?;
+        if let Some(status) = task.status.as_mut() {
+            status.state = rpc::TaskState::TaskRunning as i32;
+        }
+
+        store.tasks.insert(key.clone(), task.clone());
+        store.push_event(&key.0, task_changed(&task));
+
+        Some((ssn, task))
+    }
+
+    /// Waits for the next pending task and marks it running, so the test streams its output
+    /// chunks before it's completed.
+    pub async fn start_next_task(&self) -> Result<RunningTask, FlameError> {
+        loop {
+            let changed = self.changed.notified();
+            if let Some((_, task)) = self.try_run_next_task() {
+                self.changed.notify_waiters();
+                return Ok(RunningTask {
+                    server: self.clone(),
+                    ssn_id: session_id(&task).to_string(),
+                    task_id: task.metadata.unwrap_or_default().id,
+                });
+            }
+            changed.await;
+        }
+    }
+
+    /// Completes the task which was run by `run_next_task`.
+    #[cfg(feature = "embedded")]
+    pub(crate) fn finish_task(
+        &self,
+        ssn_id: &str,
+        task_id: &str,
+        state: rpc::TaskState,
+        output: &[u8],
+    ) {
+        let key = (ssn_id.to_string(), task_id.to_string());
+        {
+            let mut store = self.store.lock().unwrap();
+            finish_task(&mut store, &key, state, output);
+        }
+        self.changed.notify_waiters();
+    }
+
+    fn update<T>(
+        &self,
+        f: impl FnOnce(&mut Store) -> Result<T, FlameError>,
+    ) -> Result<T, FlameError> {
+        let res = {
+            let mut store = self.store.lock().unwrap();
+            f(&mut store)
+        };
+        self.changed.notify_waiters();
+
+        res
+    }
+
+    fn read<T>(&self, f: impl FnOnce(&Store) -> Result<T, FlameError>) -> Result<T, FlameError> {
+        let store = self.store.lock().unwrap();
+        f(&store)
+    }
+
+    /// Sends the items returned by `next`, which is called again whenever the store is changed
+    /// until it returns that the watch is done.
+    fn watch<T, F>(&self, mut next: F) -> ReceiverStream<Result<T, Status>>
+    where
+        T: Send + 'static,
+        F: FnMut(&Store) -> (Vec<Result<T, Status>>, bool) + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel(128);
+        let server = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let changed = server.changed.notified();
+                let (items, done) = {
+                    let store = server.store.lock().unwrap();
+                    next(&store)
+                };
+                for item in items {
+                    if tx.send(item).await.is_err() {
+                        return;
+                    }
+                }
+                if done {
+                    return;
+                }
+                changed.await;
+            }
+        });
+
+        ReceiverStream::new(rx)
+    }
+}
+
+/// A task run by the fake executor, see `MockServer::start_next_task`.
+pub struct RunningTask {
+    server: MockServer,
+    ssn_id: String,
+    task_id: String,
+}
+
+impl RunningTask {
+    pub fn id(&self) -> &str {
+        &self.task_id
+    }
+
+    /// Streams a chunk of the output of the task, which is read by the clients at once.
+    pub fn emit_chunk(&self, data: impl Into<Bytes>) {
+        let key = (self.ssn_id.clone(), self.task_id.clone());
+        let data = data.into();
+        let _ = self.server.update(|store| {
+            let chunks = store.chunks.entry(key.clone()).or_default();
+            chunks.push(rpc::TaskOutputChunk {
+                sequence: chunks.len() as u64 + 1,
+                data: data.to_vec(),
+                creation_time: Utc::now().timestamp(),
+            });
+            let sequence = chunks.len() as u64;
+            if let Some(status) = store.tasks.get_mut(&key).and_then(|t| t.status.as_mut()) {
+                status.output_chunks = sequence;
+            }
+            Ok(())
+        });
+    }
+
+    /// Completes the task with the output, which ends the streams of its chunks.
+    pub fn complete(self, output: impl Into<TaskOutput>) -> Result<Task, FlameError> {
+        let key = (self.ssn_id, self.task_id);
+        let output = output.into();
+        let task = self.server.update(|store| {
+            finish_task(store, &key, rpc::TaskState::TaskSucceed, &output)
+                .ok_or(FlameError::NotFound(format!("task <{}/{}>", key.0, key.1)))
+        })?;
+
+        Ok(Task::from(&task))
+    }
+}
+
+impl From<FlameError> for Status {
+    fn from(e: FlameError) -> Self {
+        match e {
+            FlameError::NotFound(s) => Status::not_found(s),
+            FlameError::InvalidArgument(s) => Status::failed_precondition(s),
+            FlameError::Unavailable { message, .. } => Status::unavailable(message),
+            FlameError::ResourceExhausted {
+                message,
+                retry_after,
+            } => Status::from(common::FlameError::ResourceExhausted {
+                message,
+                retry_after,
+            }),
+            FlameError::PermissionDenied(s) => Status::permission_denied(s),
+            FlameError::Unimplemented(s) => Status::unimplemented(s),
+            e => Status::internal(e.to_string()),
+        }
+    }
+}
+
+type WatchStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Frontend for MockServer {
+    type WatchTaskStream = WatchStream<rpc::Task>;
+    type WatchSessionStream = WatchStream<rpc::SessionEvent>;
+    type ExportSessionStream = WatchStream<rpc::SessionArchive>;
+    type FetchTaskOutputsStream = WatchStream<rpc::TaskOutputEntry>;
+    type FetchTaskOutputChunksStream = WatchStream<rpc::TaskOutputChunk>;
+
+    async fn create_session(
+        &self,
+        req: Request<rpc::CreateSessionRequest>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        let mut spec = req.into_inner().session.unwrap_or_default();
+        if spec.namespace.is_empty() {
+            spec.namespace = common::apis::DEFAULT_NAMESPACE.to_string();
+        }
+        let ssn = self.update(|store| {
+            store.next_ssn_id += 1;
+            let id = store.next_ssn_id.to_string();
+            store.sessions.insert(
+                id.clone(),
+                rpc::Session {
+                    metadata: Some(rpc::Metadata { id, owner: None }),
+                    spec: Some(spec),
+                    status: Some(rpc::SessionStatus {
+                        state: rpc::SessionState::SessionOpen as i32,
+                        creation_time: Utc::now().timestamp(),
+                        ..Default::default()
+                    }),
+                },
+            );
+
+            store.session(&store.next_ssn_id.to_string())
+        })?;
+
+        Ok(Response::new(ssn))
+    }
+
+    async fn delete_session(
+        &self,
+        req: Request<rpc::DeleteSessionRequest>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        let id = req.into_inner().session_id;
+        let ssn = self.update(|store| {
+            let ssn = store.session(&id)?;
+            store.sessions.remove(&id);
+            store.events.remove(&id);
+            store.tasks.retain(|(ssn_id, _), _| ssn_id != &id);
+            store.pending.retain(|(ssn_id, _)| ssn_id != &id);
+
+            Ok(ssn)
+        })?;
+
+        Ok(Response::new(ssn))
+    }
+
+    async fn open_session(
+        &self,
+        req: Request<rpc::OpenSessionRequest>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        let id = req.into_inner().session_id;
+        let ssn = self.update(|store| {
+            let ssn = store
+                .sessions
+                .get_mut(&id)
+                .ok_or(FlameError::NotFound(format!("session <{}>", id)))?;
+            let status = ssn.status.get_or_insert_with(Default::default);
+            status.state = rpc::SessionState::SessionOpen as i32;
+            status.completion_time = None;
+
+            store.session(&id)
+        })?;
+
+        Ok(Response::new(ssn))
+    }
+
+    async fn close_session(
+        &self,
+        req: Request<rpc::CloseSessionRequest>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        let id = req.into_inner().session_id;
+        let ssn = self.update(|store| {
+            let ssn = store.session(&id)?;
+            let status = ssn.status.clone().unwrap_or_default();
+            if status.pending + status.running > 0 {
+                return Err(FlameError::InvalidArgument(format!(
+                    "session <{}> has open tasks",
+                    id
+                )));
+            }
+
+            if let Some(ssn) = store.sessions.get_mut(&id) {
+                let status = ssn.status.get_or_insert_with(Default::default);
+                status.state = rpc::SessionState::SessionClosed as i32;
+                status.completion_time = Some(Utc::now().timestamp());
+            }
+            store.push_event(&id, Event::SessionClosed(rpc::SessionClosedEvent {}));
+
+            store.session(&id)
+        })?;
+
+        Ok(Response::new(ssn))
+    }
+
+    async fn get_session(
+        &self,
+        req: Request<rpc::GetSessionRequest>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        let id = req.into_inner().session_id;
+        let ssn = self.read(|store| store.session(&id))?;
+
+        Ok(Response::new(ssn))
+    }
+
+    async fn update_session(
+        &self,
+        _: Request<rpc::UpdateSessionRequest>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        Err(Status::unimplemented("update_session"))
+    }
+
+    async fn list_session(
+        &self,
+        req: Request<rpc::ListSessionRequest>,
+    ) -> Result<Response<rpc::SessionList>, Status> {
+        let req = req.into_inner();
+        let mut sessions = self.read(|store| {
+            store
+                .sessions
+                .keys()
+                .map(|id| store.session(id))
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        // The sessions are paged in the order of their ids, whatever the order of the request.
+        sessions.retain(|ssn| {
+            req.state
+                .is_none_or(|state| ssn.status.as_ref().is_some_and(|s| s.state == state))
+        });
+        let limit = (req.limit > 0).then_some(req.limit as usize);
+        let sessions = sessions
+            .into_iter()
+            .skip(req.offset as usize)
+            .take(limit.unwrap_or(usize::MAX))
+            .collect();
+
+        Ok(Response::new(rpc::SessionList { sessions }))
+    }
+
+    async fn list_session_template(
+        &self,
+        _: Request<rpc::ListSessionTemplateRequest>,
+    ) -> Result<Response<rpc::SessionTemplateList>, Status> {
+        Err(Status::unimplemented("list_session_template"))
+    }
+
+    async fn clone_session(
+        &self,
+        req: Request<rpc::CloneSessionRequest>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        let req = req.into_inner();
+        let ssn = self.update(|store| {
+            let src = store.session(&req.session_id)?;
+            let mut tasks: Vec<rpc::Task> = store
+                .tasks
+                .values()
+                .filter(|t| req.with_tasks && session_id(t) == req.session_id)
+                .cloned()
+                .collect();
+            let resubmitted: Vec<String> = tasks
+                .iter()
+                .filter_map(|t| t.original_task_id.clone())
+                .collect();
+            tasks.retain(|t| !resubmitted.contains(&t.metadata.clone().unwrap_or_default().id));
+            tasks.sort_by_key(|t| t.index);
+
+            store.next_ssn_id += 1;
+            let id = store.next_ssn_id.to_string();
+            let spec = rpc::SessionSpec {
+                deadline: None,
+                timeout: None,
+                cloned_from: Some(req.session_id.clone()),
+                ..src.spec.unwrap_or_default()
+            };
+            store.sessions.insert(
+                id.clone(),
+                rpc::Session {
+                    metadata: Some(rpc::Metadata {
+                        id: id.clone(),
+                        owner: None,
+                    }),
+                    spec: Some(spec),
+                    status: Some(rpc::SessionStatus {
+                        state: rpc::SessionState::SessionOpen as i32,
+                        creation_time: Utc::now().timestamp(),
+                        ..Default::default()
+                    }),
+                },
+            );
+            for task in tasks {
+                let spec = task.spec.unwrap_or_default();
+                let spec = rpc::TaskSpec {
+                    session_id: id.clone(),
+                    input: spec.input,
+                    output: None,
+                    trace_context: None,
+                    labels: spec.labels,
+                    locality: spec.locality,
+                    env: spec.env,
+                    timeout: spec.timeout,
+                    max_retries: spec.max_retries,
+                    idempotency_key: spec.idempotency_key,
+                };
+                store.add_task(spec, None)?;
+            }
+
+            store.session(&id)
+        })?;
+
+        Ok(Response::new(ssn))
+    }
+
+    async fn export_session(
+        &self,
+        req: Request<rpc::ExportSessionRequest>,
+    ) -> Result<Response<Self::ExportSessionStream>, Status> {
+        let id = req.into_inner().session_id;
+        let items = self.read(|store| {
+            let mut tasks: Vec<rpc::Task> = store
+                .tasks
+                .iter()
+                .filter(|((ssn_id, _), _)| ssn_id == &id)
+                .map(|(_, task)| task.clone())
+                .collect();
+            tasks.sort_by_key(|t| {
+                t.metadata
+                    .clone()
+                    .unwrap_or_default()
+                    .id
+                    .parse::<u64>()
+                    .ok()
+            });
+
+            let mut items = vec![Item::Session(store.session(&id)?)];
+            items.extend(tasks.into_iter().map(Item::Task));
+            Ok(items)
+        })?;
+
+        let stream = tokio_stream::iter(
+            items
+                .into_iter()
+                .map(|item| rpc::SessionArchive { item: Some(item) })
+                .map(Ok),
+        );
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn import_session(
+        &self,
+        req: Request<Streaming<rpc::SessionArchive>>,
+    ) -> Result<Response<rpc::Session>, Status> {
+        let mut archive = req.into_inner();
+        let Some(rpc::SessionArchive {
+            item: Some(Item::Session(mut ssn)),
+        }) = archive.message().await?
+        else {
+            return Err(Status::invalid_argument(
+                "the archive must start with the session",
+            ));
+        };
+        let mut tasks = vec![];
+        while let Some(item) = archive.message().await? {
+            match item.item {
+                Some(Item::Task(task)) if is_completed(&task) => tasks.push(task),
+                _ => {
+                    return Err(Status::invalid_argument(
+                        "only the completed tasks can be imported",
+                    ))
+                }
+            }
+        }
+
+        let ssn = self.update(|store| {
+            store.next_ssn_id += 1;
+            let id = store.next_ssn_id.to_string();
+            ssn.metadata = Some(rpc::Metadata {
+                id: id.clone(),
+                owner: None,
+            });
+            let status = ssn.status.clone().unwrap_or_default();
+            ssn.status = Some(rpc::SessionStatus {
+                state: rpc::SessionState::SessionClosed as i32,
+                creation_time: status.creation_time,
+                completion_time: status.completion_time,
+                ..Default::default()
+            });
+            store.sessions.insert(id.clone(), ssn);
+
+            for mut task in tasks {
+                let task_id = task.metadata.clone().unwrap_or_default().id;
+                task.spec.get_or_insert_with(Default::default).session_id = id.clone();
+                store.tasks.insert((id.clone(), task_id), task);
+            }
+
+            store.session(&id)
+        })?;
+
+        Ok(Response::new(ssn))
+    }
+
+    async fn create_task(
+        &self,
+        req: Request<rpc::CreateTaskRequest>,
+    ) -> Result<Response<rpc::Task>, Status> {
+        let spec = req.into_inner().task.unwrap_or_default();
+        let task = self.update(|store| store.add_task(spec, None))?;
+
+        Ok(Response::new(task))
+    }
+
+    async fn create_task_stream(
+        &self,
+        req: Request<Streaming<rpc::TaskChunk>>,
+    ) -> Result<Response<rpc::Task>, Status> {
+        let mut chunks = req.into_inner();
+        let Some(Chunk::Task(mut spec)) = chunks.message().await?.and_then(|c| c.item) else {
+            return Err(Status::invalid_argument(
+                "the stream must start with the task",
+            ));
+        };
+        let mut input = vec![];
+        loop {
+            match chunks.message().await?.and_then(|c| c.item) {
+                Some(Chunk::Data(data)) => input.extend_from_slice(&data),
+                Some(Chunk::Size(size)) if size == input.len() as u64 => break,
+                _ => return Err(Status::invalid_argument("the input was not committed")),
+            }
+        }
+        spec.input = Some(input);
+        let mut task = self.update(|store| store.add_task(spec, None))?;
+        if let Some(spec) = task.spec.as_mut() {
+            spec.input = None;
+        }
+
+        Ok(Response::new(task))
+    }
+
+    async fn delete_task(
+        &self,
+        req: Request<rpc::DeleteTaskRequest>,
+    ) -> Result<Response<rpc::Task>, Status> {
+        let req = req.into_inner();
+        let task = self.update(|store| {
+            let task = store.task(&req.session_id, &req.task_id)?;
+            let key = (req.session_id.clone(), req.task_id.clone());
+            store.tasks.remove(&key);
+            store.pending.retain(|k| k != &key);
+
+            Ok(task)
+        })?;
+
+        Ok(Response::new(task))
+    }
+
+    async fn get_task(
+        &self,
+        req: Request<rpc::GetTaskRequest>,
+    ) -> Result<Response<rpc::Task>, Status> {
+        let req = req.into_inner();
+        let task = self.read(|store| store.task(&req.session_id, &req.task_id))?;
+
+        Ok(Response::new(task))
+    }
+
+    async fn list_task(
+        &self,
+        req: Request<rpc::ListTaskRequest>,
+    ) -> Result<Response<rpc::TaskList>, Status> {
+        let req = req.into_inner();
+        let mut tasks = self.read(|store| {
+            store.session(&req.session_id)?;
+            Ok(store
+                .tasks
+                .values()
+                .filter(|t| session_id(t) == req.session_id && t.index >= req.start_index)
+                .filter(|t| req.state.is_none_or(|s| s == task_state(t) as i32))
+                .cloned()
+                .collect::<Vec<_>>())
+        })?;
+        tasks.sort_by_key(|t| t.index);
+        if req.limit > 0 {
+            tasks.truncate(req.limit as usize);
+        }
+        if req.skip_payloads {
+            for spec in tasks.iter_mut().filter_map(|t| t.spec.as_mut()) {
+                spec.input = None;
+                spec.output = None;
+            }
+        }
+
+        Ok(Response::new(rpc::TaskList { tasks }))
+    }
+
+    async fn fetch_task_outputs(
+        &self,
+        req: Request<rpc::FetchTaskOutputsRequest>,
+    ) -> Result<Response<Self::FetchTaskOutputsStream>, Status> {
+        let req = req.into_inner();
+        let end_index = req.end_index.unwrap_or(u64::MAX);
+        let mut tasks = self.read(|store| {
+            store.session(&req.session_id)?;
+            Ok(store
+                .tasks
+                .values()
+                .filter(|t| session_id(t) == req.session_id)
+                .filter(|t| (req.start_index..end_index).contains(&t.index))
+                .filter(|t| req.state.is_none_or(|s| s == task_state(t) as i32))
+                .cloned()
+                .collect::<Vec<_>>())
+        })?;
+        tasks.sort_by_key(|t| t.index);
+
+        let stream = tokio_stream::iter(
+            tasks
+                .into_iter()
+                .map(|t| rpc::TaskOutputEntry {
+                    task_id: t.metadata.unwrap_or_default().id,
+                    index: t.index,
+                    output: t.spec.and_then(|spec| spec.output),
+                })
+                .map(Ok),
+        );
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    /// Sends the output chunks after `since`, until the task is completed.
+    async fn fetch_task_output_chunks(
+        &self,
+        req: Request<rpc::FetchTaskOutputChunksRequest>,
+    ) -> Result<Response<Self::FetchTaskOutputChunksStream>, Status> {
+        let req = req.into_inner();
+        self.read(|store| store.task(&req.session_id, &req.task_id))?;
+
+        let key = (req.session_id, req.task_id);
+        let mut since = req.since;
+        let stream = self.watch(move |store| {
+            let task = match store.task(&key.0, &key.1) {
+                Ok(task) => task,
+                Err(e) => return (vec![Err(Status::from(e))], true),
+            };
+            let chunks = store.chunks.get(&key).cloned().unwrap_or_default();
+            let chunks: Vec<_> = chunks.into_iter().filter(|c| c.sequence > since).collect();
+            if let Some(last) = chunks.last() {
+                since = last.sequence;
+            }
+
+            (chunks.into_iter().map(Ok).collect(), is_completed(&task))
+        });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn resubmit_task(
+        &self,
+        req: Request<rpc::ResubmitTaskRequest>,
+    ) -> Result<Response<rpc::Task>, Status> {
+        let req = req.into_inner();
+        let task = self.update(|store| {
+            let original = store.task(&req.session_id, &req.task_id)?;
+            if task_state(&original) != rpc::TaskState::TaskFailed {
+                return Err(FlameError::InvalidArgument(format!(
+                    "task <{}/{}> is not failed",
+                    req.session_id, req.task_id
+                )));
+            }
+            let resubmitted = store.tasks.values().any(|t| {
+                session_id(t) == req.session_id && t.original_task_id.as_ref() == Some(&req.task_id)
+            });
+            if resubmitted {
+                return Err(FlameError::InvalidArgument(format!(
+                    "task <{}/{}> was resubmitted",
+                    req.session_id, req.task_id
+                )));
+            }
+
+            let spec = original.spec.unwrap_or_default();
+            let spec = rpc::TaskSpec {
+                session_id: req.session_id.clone(),
+                input: spec.input,
+                output: None,
+                trace_context: spec.trace_context,
+                labels: spec.labels,
+                locality: spec.locality,
+                env: spec.env,
+                timeout: spec.timeout,
+                max_retries: spec.max_retries,
+                idempotency_key: None,
+            };
+            store.add_task(spec, Some(req.task_id.clone()))
+        })?;
+
+        Ok(Response::new(task))
+    }
+
+    /// Sends the task whenever its state is changed, or a chunk is streamed if the chunks are
+    /// included, until it's completed.
+    async fn watch_task(
+        &self,
+        req: Request<rpc::WatchTaskRequest>,
+    ) -> Result<Response<Self::WatchTaskStream>, Status> {
+        let req = req.into_inner();
+        self.read(|store| store.task(&req.session_id, &req.task_id))?;
+
+        let mut last = None;
+        let stream = self.watch(move |store| {
+            let task = match store.task(&req.session_id, &req.task_id) {
+                Ok(task) => task,
+                Err(e) => return (vec![Err(Status::from(e))], true),
+            };
+
+            let done = is_completed(&task);
+            let chunks = match req.include_chunks {
+                true => task.status.as_ref().map(|s| s.output_chunks),
+                false => None,
+            };
+            if last == Some((task_state(&task), chunks)) {
+                return (vec![], done);
+            }
+            last = Some((task_state(&task), chunks));
+
+            (vec![Ok(task)], done)
+        });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    /// Sends the events after `since`, until the session is closed or deleted.
+    async fn watch_session(
+        &self,
+        req: Request<rpc::WatchSessionRequest>,
+    ) -> Result<Response<Self::WatchSessionStream>, Status> {
+        let req = req.into_inner();
+        self.read(|store| store.session(&req.session_id))?;
+
+        let ssn_id = req.session_id;
+        let mut since = req.since;
+        let stream = self.watch(move |store| {
+            if !store.sessions.contains_key(&ssn_id) {
+                let e = Status::not_found(format!("session <{}>", ssn_id));
+                return (vec![Err(e)], true);
+            }
+
+            let events = store.events.get(&ssn_id).cloned().unwrap_or_default();
+            let events: Vec<_> = events.into_iter().filter(|e| e.sequence > since).collect();
+
+            let mut closed = false;
+            if let Some(last) = events.last() {
+                since = last.sequence;
+                closed = matches!(last.event, Some(Event::SessionClosed(_)));
+            }
+
+            (events.into_iter().map(Ok).collect(), closed)
+        });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_session_events(
+        &self,
+        req: Request<rpc::GetSessionEventsRequest>,
+    ) -> Result<Response<rpc::SessionEventList>, Status> {
+        let req = req.into_inner();
+        let events = self.read(|store| {
+            store.session(&req.session_id)?;
+            Ok(store
+                .events
+                .get(&req.session_id)
+                .cloned()
+                .unwrap_or_default())
+        })?;
+        let events = events
+            .into_iter()
+            .filter(|e| req.since.is_none_or(|t| e.event_time >= t))
+            .filter(|e| req.until.is_none_or(|t| e.event_time < t))
+            .collect();
+
+        Ok(Response::new(rpc::SessionEventList { events }))
+    }
+
+    async fn get_server_info(
+        &self,
+        _: Request<rpc::GetServerInfoRequest>,
+    ) -> Result<Response<rpc::ServerInfo>, Status> {
+        let capabilities = [
+            capability::WATCH_SESSION,
+            capability::APPLICATION_REGISTRY,
+            capability::EXECUTOR_API,
+            capability::SESSION_ARCHIVE,
+            capability::TASK_INDEX,
+            capability::SESSION_HISTORY,
+            capability::TASK_OUTPUTS,
+            capability::SESSION_CLONE,
+            capability::TASK_UPLOAD,
+            capability::OUTPUT_CHUNKS,
+            capability::LIST_PAGES,
+            capability::TASK_IDEMPOTENCY,
+            capability::SESSION_MAX_RUNNING,
+        ];
+
+        Ok(Response::new(rpc::ServerInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            build: "testkit".to_string(),
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+        }))
+    }
+
+    async fn register_application(
+        &self,
+        req: Request<rpc::RegisterApplicationRequest>,
+    ) -> Result<Response<rpc::Result>, Status> {
+        let app = req.into_inner().application.unwrap_or_default();
+        self.update(|store| {
+            store.applications.insert(app.name.clone(), app);
+            Ok(())
+        })?;
+
+        Ok(Response::new(rpc::Result {
+            return_code: 0,
+            message: None,
+        }))
+    }
+
+    async fn list_application(
+        &self,
+        _: Request<rpc::ListApplicationRequest>,
+    ) -> Result<Response<rpc::ApplicationList>, Status> {
+        let applications = self.read(|store| Ok(store.applications.values().cloned().collect()))?;
+
+        Ok(Response::new(rpc::ApplicationList { applications }))
+    }
+
+    async fn delete_application(
+        &self,
+        req: Request<rpc::DeleteApplicationRequest>,
+    ) -> Result<Response<rpc::Result>, Status> {
+        let name = req.into_inner().name;
+        self.update(|store| {
+            store
+                .applications
+                .remove(&name)
+                .ok_or(FlameError::NotFound(format!("application <{}>", name)))
+        })?;
+
+        Ok(Response::new(rpc::Result {
+            return_code: 0,
+            message: None,
+        }))
+    }
+
+    async fn list_executor(
+        &self,
+        _: Request<rpc::ListExecutorRequest>,
+    ) -> Result<Response<rpc::ExecutorList>, Status> {
+        Ok(Response::new(rpc::ExecutorList { executors: vec![] }))
+    }
+
+    async fn get_executor(
+        &self,
+        req: Request<rpc::GetExecutorRequest>,
+    ) -> Result<Response<rpc::Executor>, Status> {
+        let id = req.into_inner().executor_id;
+        Err(Status::not_found(format!("executor <{}>", id)))
+    }
+
+    async fn drain_executor(
+        &self,
+        req: Request<rpc::DrainExecutorRequest>,
+    ) -> Result<Response<rpc::Executor>, Status> {
+        let id = req.into_inner().executor_id;
+        Err(Status::not_found(format!("executor <{}>", id)))
+    }
+
+    async fn uncordon_executor(
+        &self,
+        req: Request<rpc::UncordonExecutorRequest>,
+    ) -> Result<Response<rpc::Executor>, Status> {
+        let id = req.into_inner().executor_id;
+        Err(Status::not_found(format!("executor <{}>", id)))
+    }
+}
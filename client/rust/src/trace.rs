@@ -11,6 +11,15 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use tonic::{Request, Status};
+
+/// Propagates the trace context of the current span to the session manager.
+#[allow(clippy::result_large_err)] // The signature of the interceptors of tonic.
+pub(crate) fn inject(mut req: Request<()>) -> Result<Request<()>, Status> {
+    common::trace::inject(req.metadata_mut());
+    Ok(req)
+}
+
 pub struct TraceFn {
     pub fn_name: String,
 }
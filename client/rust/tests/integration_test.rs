@@ -11,16 +11,24 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use std::collections::BTreeMap;
+use std::io::{self, Cursor};
+use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
 
 use futures::future::try_join_all;
+use futures::{stream, TryStreamExt};
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
 
-use self::flame::{lock_ptr, Task, TaskInformer, TaskState};
+use self::flame::testkit::MockServer;
+use self::flame::{capability, lock_ptr, Task, TaskInformer, TaskInput, TaskState};
 use flame_client as flame;
 
-use self::flame::{FlameError, SessionAttributes, SessionState};
-
-const FLAME_DEFAULT_ADDR: &str = "http://127.0.0.1:8080";
+use self::flame::{
+    ArchiveWriter, FlameError, SessionArchive, SessionAttributes, SessionEvent, SessionState,
+};
 
 const FLAME_DEFAULT_APP: &str = "flmexec";
 
@@ -46,12 +54,13 @@ impl TaskInformer for DefaultTaskInformer {
 
 #[tokio::test]
 async fn test_create_session() -> Result<(), FlameError> {
-    let conn = flame::connect(FLAME_DEFAULT_ADDR).await?;
+    let server = MockServer::start().await?;
+    let conn = server.connect().await?;
 
     let ssn_attr = SessionAttributes {
         application: FLAME_DEFAULT_APP.to_string(),
         slots: 1,
-        common_data: None,
+        ..Default::default()
     };
     let ssn = conn.create_session(&ssn_attr).await?;
 
@@ -64,7 +73,8 @@ async fn test_create_session() -> Result<(), FlameError> {
 
 #[tokio::test]
 async fn test_create_multiple_sessions() -> Result<(), FlameError> {
-    let conn = flame::connect(FLAME_DEFAULT_ADDR).await?;
+    let server = MockServer::start().await?;
+    let conn = server.connect().await?;
 
     let ssn_num = 10;
 
@@ -72,7 +82,7 @@ async fn test_create_multiple_sessions() -> Result<(), FlameError> {
         let ssn_attr = SessionAttributes {
             application: FLAME_DEFAULT_APP.to_string(),
             slots: 1,
-            common_data: None,
+            ..Default::default()
         };
         let ssn = conn.create_session(&ssn_attr).await?;
 
@@ -86,12 +96,13 @@ async fn test_create_multiple_sessions() -> Result<(), FlameError> {
 
 #[tokio::test]
 async fn test_create_session_with_tasks() -> Result<(), FlameError> {
-    let conn = flame::connect(FLAME_DEFAULT_ADDR).await?;
+    let server = MockServer::start().await?;
+    let conn = server.connect().await?;
 
     let ssn_attr = SessionAttributes {
         application: FLAME_DEFAULT_APP.to_string(),
         slots: 1,
-        common_data: None,
+        ..Default::default()
     };
     let ssn = conn.create_session(&ssn_attr).await?;
 
@@ -110,7 +121,13 @@ async fn test_create_session_with_tasks() -> Result<(), FlameError> {
         tasks.push(task);
     }
 
-    try_join_all(tasks).await?;
+    let executor = async {
+        for _ in 0..task_num {
+            server.complete_next_task("").await?;
+        }
+        Ok(())
+    };
+    futures::try_join!(try_join_all(tasks), executor)?;
 
     {
         let informer = lock_ptr!(informer)?;
@@ -124,12 +141,13 @@ async fn test_create_session_with_tasks() -> Result<(), FlameError> {
 
 #[tokio::test]
 async fn test_create_multiple_sessions_with_tasks() -> Result<(), FlameError> {
-    let conn = flame::connect(FLAME_DEFAULT_ADDR).await?;
+    let server = MockServer::start().await?;
+    let conn = server.connect().await?;
 
     let ssn_attr = SessionAttributes {
         application: FLAME_DEFAULT_APP.to_string(),
         slots: 1,
-        common_data: None,
+        ..Default::default()
     };
     let ssn_1 = conn.create_session(&ssn_attr).await?;
     assert_eq!(ssn_1.state, SessionState::Open);
@@ -156,7 +174,13 @@ async fn test_create_multiple_sessions_with_tasks() -> Result<(), FlameError> {
         tasks.push(task);
     }
 
-    try_join_all(tasks).await?;
+    let executor = async {
+        for _ in 0..task_num * 2 {
+            server.complete_next_task("").await?;
+        }
+        Ok(())
+    };
+    futures::try_join!(try_join_all(tasks), executor)?;
 
     {
         let informer = lock_ptr!(informer)?;
@@ -168,3 +192,348 @@ async fn test_create_multiple_sessions_with_tasks() -> Result<(), FlameError> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_run_task_in_memory() -> Result<(), FlameError> {
+    let server = MockServer::start().await?;
+    let conn = server.connect_in_memory().await?;
+
+    let ssn_attr = SessionAttributes {
+        application: FLAME_DEFAULT_APP.to_string(),
+        slots: 1,
+        ..Default::default()
+    };
+    let ssn = conn.create_session(&ssn_attr).await?;
+
+    let (output, task) = futures::try_join!(
+        ssn.run_task_output("ping"),
+        server.complete_next_task("pong")
+    )?;
+    assert_eq!(output, "pong");
+    assert_eq!(task.state, TaskState::Succeed);
+
+    let (res, _) = futures::join!(
+        ssn.run_task_output("ping"),
+        server.fail_next_task("no pong")
+    );
+    match res {
+        Err(FlameError::TaskFailed { message, .. }) => assert_eq!(message, "no pong"),
+        res => panic!("unexpected result: {:?}", res.map(|_| ())),
+    }
+
+    let ssn = conn.get_session(&ssn.id).await?;
+    assert_eq!((ssn.succeed, ssn.failed), (1, 1));
+
+    ssn.close().await?;
+    assert!(ssn.is_closed().await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_export_import_session() -> Result<(), FlameError> {
+    let server = MockServer::start().await?;
+    let conn = server.connect().await?;
+
+    let ssn_attr = SessionAttributes {
+        application: FLAME_DEFAULT_APP.to_string(),
+        slots: 1,
+        labels: BTreeMap::from([("env".to_string(), "dev".to_string())]),
+        ..Default::default()
+    };
+    let ssn = conn.create_session(&ssn_attr).await?;
+    futures::try_join!(
+        ssn.run_task_output("ping"),
+        server.complete_next_task("pong")
+    )?;
+    let _ = futures::join!(
+        ssn.run_task_output("ping"),
+        server.fail_next_task("no pong")
+    );
+    ssn.close().await?;
+
+    let export = |id: String| {
+        let conn = conn.clone();
+        async move {
+            let export = conn.export_session(&id).await?;
+            let mut buf = vec![];
+            let mut writer = ArchiveWriter::new(&mut buf, &export.session).unwrap();
+            let tasks: Vec<_> = export.try_collect().await?;
+            for task in &tasks {
+                writer.write_task(task).unwrap();
+            }
+            writer.finish().unwrap();
+
+            SessionArchive::read(buf.as_slice())
+        }
+    };
+
+    let archive = export(ssn.id.clone()).await?;
+    assert_eq!(archive.tasks.len(), 2);
+    assert_eq!(archive.tasks[0].output.as_deref(), Some(b"pong".as_slice()));
+    conn.delete_session(&ssn.id).await?;
+
+    let imported = conn
+        .import_session(&archive.session, stream::iter(archive.tasks.clone()))
+        .await?;
+    assert_ne!(imported.id, ssn.id);
+    assert_eq!(imported.state, SessionState::Closed);
+    assert_eq!((imported.succeed, imported.failed), (1, 1));
+
+    let mut reexported = export(imported.id.clone()).await?;
+    reexported.session.id = archive.session.id.clone();
+    assert_eq!(reexported, archive);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_resubmit_failed_tasks() -> Result<(), FlameError> {
+    let server = MockServer::start().await?;
+    let conn = server.connect().await?;
+
+    let ssn_attr = SessionAttributes {
+        application: FLAME_DEFAULT_APP.to_string(),
+        slots: 1,
+        ..Default::default()
+    };
+    let ssn = conn.create_session(&ssn_attr).await?;
+    futures::try_join!(ssn.run_task_output("a"), server.complete_next_task("a"))?;
+    for input in ["b", "c"] {
+        let _ = futures::join!(ssn.run_task_output(input), server.fail_next_task("oom"));
+    }
+
+    let failed = ssn.list_tasks_by_state(TaskState::Failed).await?;
+    assert_eq!(
+        failed.iter().map(|t| t.id.as_str()).collect::<Vec<_>>(),
+        ["2", "3"]
+    );
+
+    // The failed tasks are resubmitted once, even if it's run again.
+    let resubmitted = ssn.resubmit_failed_tasks().await?;
+    let lineage = resubmitted
+        .iter()
+        .map(|t| (t.id.as_str(), t.original_task_id.as_deref()))
+        .collect::<Vec<_>>();
+    assert_eq!(lineage, [("4", Some("2")), ("5", Some("3"))]);
+    assert!(ssn.resubmit_failed_tasks().await?.is_empty());
+
+    for _ in 0..2 {
+        server.complete_next_task("done").await?;
+    }
+    let task = ssn.get_task("5".to_string()).await?;
+    assert_eq!(task.state, TaskState::Succeed);
+    assert_eq!(task.input.as_deref(), Some(b"c".as_slice()));
+    assert_eq!(task.original_task_id.as_deref(), Some("3"));
+    assert_eq!(ssn.list_tasks_by_state(TaskState::Failed).await?.len(), 2);
+
+    // The succeeded task is not resubmitted.
+    match ssn.resubmit_task("1".to_string()).await {
+        Err(FlameError::InvalidArgument(_)) => {}
+        res => panic!("unexpected result: {:?}", res.map(|_| ())),
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_clone_session() -> Result<(), FlameError> {
+    let server = MockServer::start().await?;
+    let conn = server.connect().await?;
+
+    let ssn_attr = SessionAttributes {
+        application: FLAME_DEFAULT_APP.to_string(),
+        slots: 1,
+        labels: BTreeMap::from([("env".to_string(), "dev".to_string())]),
+        ..Default::default()
+    };
+    let ssn = conn.create_session(&ssn_attr).await?;
+    futures::try_join!(ssn.run_task_output("a"), server.complete_next_task("a"))?;
+    let _ = futures::join!(ssn.run_task_output("b"), server.fail_next_task("oom"));
+    ssn.resubmit_failed_tasks().await?;
+    server.complete_next_task("b").await?;
+    ssn.close().await?;
+
+    let empty = conn.clone_session(&ssn.id, false, false).await?;
+    assert_eq!((empty.state, empty.pending), (SessionState::Open, 0));
+
+    // The failed task is copied once, by its resubmitted task.
+    let clone = conn.clone_session(&ssn.id, true, false).await?;
+    assert_ne!(clone.id, ssn.id);
+    assert_eq!(clone.cloned_from.as_ref(), Some(&ssn.id));
+    assert_eq!(clone.labels, ssn_attr.labels);
+    assert_eq!(clone.state, SessionState::Open);
+    assert_eq!(clone.pending, 2);
+
+    let tasks = clone.list_tasks().await?;
+    let tasks = tasks
+        .iter()
+        .map(|t| (t.input.as_deref(), t.output.as_deref(), t.state))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        tasks,
+        [
+            (Some(b"a".as_slice()), None, TaskState::Pending),
+            (Some(b"b".as_slice()), None, TaskState::Pending),
+        ]
+    );
+    let clone = conn.get_session(&clone.id).await?;
+    assert_eq!(clone.cloned_from.as_ref(), Some(&ssn.id));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_session_history() -> Result<(), FlameError> {
+    let server = MockServer::start().await?;
+    let conn = server.connect().await?;
+
+    let ssn_attr = SessionAttributes {
+        application: FLAME_DEFAULT_APP.to_string(),
+        slots: 1,
+        ..Default::default()
+    };
+    let ssn = conn.create_session(&ssn_attr).await?;
+    futures::try_join!(ssn.run_task_output("a"), server.complete_next_task("a"))?;
+    ssn.close().await?;
+
+    let history = ssn.history(None, None).await?;
+    let events: Vec<_> = history.iter().map(|e| e.event.clone()).collect();
+    assert!(
+        matches!(
+            events.as_slice(),
+            [
+                ..,
+                SessionEvent::TaskStateChanged {
+                    state: TaskState::Succeed,
+                    ..
+                },
+                SessionEvent::SessionClosed { .. }
+            ]
+        ),
+        "{:?}",
+        events
+    );
+
+    // The events are filtered by their times.
+    let until = history[0].event_time;
+    assert!(ssn.history(None, Some(until)).await?.is_empty());
+    assert_eq!(ssn.history(Some(until), None).await?, history);
+
+    Ok(())
+}
+
+/// The reader of an input failing after some bytes, e.g. the file is on a broken disk.
+struct BrokenReader;
+
+impl AsyncRead for BrokenReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        _: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Poll::Ready(Err(io::Error::other("disk failure")))
+    }
+}
+
+#[tokio::test]
+async fn test_create_task_from_reader() -> Result<(), FlameError> {
+    let server = MockServer::start().await?;
+    let conn = server.connect().await?;
+
+    let ssn_attr = SessionAttributes {
+        application: FLAME_DEFAULT_APP.to_string(),
+        slots: 1,
+        ..Default::default()
+    };
+    let ssn = conn.create_session(&ssn_attr).await?;
+    assert!(ssn.supports(capability::TASK_UPLOAD));
+
+    // The input over the message size limit is sent in chunks.
+    let input: Vec<u8> = (0..10 << 20).map(|i| (i % 251) as u8).collect();
+    assert!(ssn.is_large_input(input.len()));
+    let (_, task) = futures::try_join!(
+        ssn.create_task_from_reader(Cursor::new(input.clone())),
+        server.complete_next_task("done")
+    )?;
+    assert_eq!(task.input.as_deref(), Some(input.as_slice()));
+
+    // No task is created if the input can't be read to the end.
+    let reader = Cursor::new(vec![1u8; 3 << 20]).chain(BrokenReader);
+    let Err(e) = ssn.create_task_from_reader(reader).await else {
+        panic!("the task should not be created");
+    };
+    assert!(e.to_string().contains("disk failure"), "{}", e);
+    assert_eq!(server.pending_tasks(), 0);
+    assert_eq!(conn.get_session(&ssn.id).await?.pending, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_output_chunks() -> Result<(), FlameError> {
+    let server = MockServer::start().await?;
+    let conn = server.connect().await?;
+
+    let ssn_attr = SessionAttributes {
+        application: FLAME_DEFAULT_APP.to_string(),
+        slots: 1,
+        ..Default::default()
+    };
+    let ssn = conn.create_session(&ssn_attr).await?;
+    assert!(ssn.supports(capability::OUTPUT_CHUNKS));
+    let task = ssn.create_task(Some(TaskInput::from("render"))).await?;
+    let running = server.start_next_task().await?;
+    assert_eq!(running.id(), task.id);
+
+    // The chunks are read one by one while the task is running.
+    let mut chunks = ssn.output_chunks(&task.id, 0).await?;
+    for i in 1..=10 {
+        running.emit_chunk(format!("frame {}", i));
+        let chunk = chunks
+            .try_next()
+            .await?
+            .expect("the chunk of a running task");
+        assert_eq!(chunk.sequence, i);
+        assert_eq!(chunk.data, format!("frame {}", i).as_bytes());
+    }
+    let task = ssn.get_task(task.id.clone()).await?;
+    assert_eq!(task.state, TaskState::Running);
+    assert_eq!(task.output_chunks, 10);
+
+    // The stream ends once the task is completed, and the chunks are read again after it.
+    running.complete("done")?;
+    assert!(chunks.try_next().await?.is_none());
+    let rest: Vec<_> = ssn.output_chunks(&task.id, 7).await?.try_collect().await?;
+    let sequences: Vec<_> = rest.iter().map(|c| c.sequence).collect();
+    assert_eq!(sequences, vec![8, 9, 10]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_max_running_tasks() -> Result<(), FlameError> {
+    let server = MockServer::start().await?;
+    let conn = server.connect().await?;
+
+    let ssn_attr = SessionAttributes {
+        application: FLAME_DEFAULT_APP.to_string(),
+        slots: 1,
+        max_running_tasks: Some(1),
+        ..Default::default()
+    };
+    let ssn = conn.create_session(&ssn_attr).await?;
+    assert_eq!(ssn.max_running_tasks, Some(1));
+    for _ in 0..2 {
+        ssn.create_task(None).await?;
+    }
+
+    // The second task stays pending until the first one is completed.
+    let running = server.start_next_task().await?;
+    let next = tokio::time::timeout(Duration::from_millis(100), server.start_next_task());
+    assert!(next.await.is_err());
+    running.complete("done")?;
+    server.start_next_task().await?;
+
+    Ok(())
+}
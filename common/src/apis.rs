@@ -11,14 +11,17 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
+use std::str::FromStr;
 
 use chrono::{DateTime, Utc};
 use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use rpc::flame as rpc;
 
+use crate::payload;
 use crate::ptr::MutexPtr;
 use crate::FlameError;
 
@@ -29,44 +32,253 @@ pub type TaskPtr = MutexPtr<Task>;
 pub type SessionPtr = MutexPtr<Session>;
 pub type ExecutorPtr = MutexPtr<Executor>;
 
+/// The namespace of the sessions which are created without one.
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+const MAX_NAMESPACE_LEN: usize = 63;
+
+/// The weight of the applications which are created without one.
+pub const DEFAULT_APPLICATION_WEIGHT: u32 = 1;
+
+/// The slots of the sessions which are created without one, and whose application has no
+/// `default_slots`.
+pub const DEFAULT_SESSION_SLOTS: i32 = 1;
+
+/// The maximum size of the payload of a task progress, which is a hint for the watchers
+/// instead of a channel for the output.
+pub const MAX_PROGRESS_PAYLOAD: usize = 4 << 10;
+
+/// The prefix of the environment variables exporting the session config, see
+/// `config_env_name`.
+pub const CONFIG_ENV_PREFIX: &str = "FLAME_SSN_";
+
+/// The maximum number of the environment variables of a task, and the maximum size of their
+/// names and values in total; they're small overrides, and the payload is for the rest.
+pub const MAX_TASK_ENV: usize = 32;
+pub const MAX_TASK_ENV_BYTES: usize = 4 << 10;
+
+/// The prefix of the environment variables set by Flame, which the tasks can not override.
+const RESERVED_ENV_PREFIX: &str = "FLAME_";
+
+/// The maximum size of the keys and values of the annotations of a task or a session in
+/// total, which keeps the rows of the storage bounded.
+pub const MAX_ANNOTATIONS_BYTES: usize = 16 << 10;
+
+/// The maximum size of a chunk of the output streamed by a task, see `OutputChunk`.
+pub const MAX_OUTPUT_CHUNK_BYTES: usize = 1 << 20;
+
 type Message = bytes::Bytes;
 pub type TaskInput = Message;
 pub type TaskOutput = Message;
 pub type CommonData = Message;
 
-#[derive(Clone, Debug, Default, Copy)]
+// The types below are serialized for the clients and the configurations, and their
+// representations are a contract: the states are lowercase strings, e.g. "open", the
+// timestamps are RFC3339 strings, and the bytes are base64 strings.
+
+/// The global id of a task, which is rendered as `<ssn_id>/<task_id>`, e.g. `12/3`.
+#[derive(Clone, Debug, Default, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct TaskGID {
     pub ssn_id: SessionID,
     pub task_id: TaskID,
 }
 
-#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, strum_macros::Display)]
+#[derive(
+    Clone, Copy, Debug, Default, Eq, PartialEq, Hash, strum_macros::Display, Deserialize, Serialize,
+)]
+#[serde(rename_all = "lowercase")]
 pub enum SessionState {
     #[default]
     Open = 0,
     Closed = 1,
 }
 
-#[derive(Clone, Debug, Default)]
+/// The order of the listed sessions; the ties are broken by the ids of the sessions, so the
+/// order is deterministic.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, strum_macros::Display)]
+pub enum SessionOrder {
+    /// The newest sessions first.
+    #[default]
+    CreationTime = 0,
+    Id = 1,
+    /// The open sessions first.
+    State = 2,
+    /// The sessions with the most pending tasks first.
+    Pending = 3,
+}
+
+impl SessionOrder {
+    pub fn compare(&self, l: &Session, r: &Session) -> std::cmp::Ordering {
+        let ordering = match self {
+            SessionOrder::CreationTime => r.creation_time.cmp(&l.creation_time),
+            SessionOrder::Id => std::cmp::Ordering::Equal,
+            SessionOrder::State => (l.status.state as i32).cmp(&(r.status.state as i32)),
+            SessionOrder::Pending => r.pending().cmp(&l.pending()),
+        };
+
+        ordering.then(l.id.cmp(&r.id))
+    }
+}
+
+/// Where the outputs of the succeeded tasks are reused by the new tasks with the same input
+/// and common data; the cache is off by default.
+#[derive(
+    Clone, Copy, Debug, Default, Eq, PartialEq, Hash, strum_macros::Display, Deserialize, Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheScope {
+    #[default]
+    None = 0,
+    /// The tasks of the same session.
+    Session = 1,
+    /// The sessions of the same application in the namespace, which also have this scope.
+    Application = 2,
+}
+
+/// The number of the tasks of a session in each state.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TaskCounts {
+    pub pending: i32,
+    pub running: i32,
+    pub succeed: i32,
+    pub failed: i32,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct SessionStatus {
     pub state: SessionState,
+    /// The number of the tasks in each state, which is kept along with the tasks of the
+    /// session, so the copies of the session have it without the tasks; it's kept in memory
+    /// only.
+    #[serde(skip)]
+    pub counts: TaskCounts,
+    /// Why no executor can ever run the pending tasks of the session, which is detected by
+    /// the scheduler in every cycle; it's kept in memory only.
+    #[serde(skip)]
+    pub unschedulable: Option<String>,
+    /// Bumped when the session is opened or closed, so the executors bound before are
+    /// detected as stale; it's kept in memory only.
+    #[serde(skip)]
+    pub generation: u64,
 }
 
-#[derive(Debug, Default)]
+/// The tasks of the session are not serialized.
+#[derive(Default, Deserialize, Serialize)]
 pub struct Session {
     pub id: SessionID,
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
     pub application: String,
     pub slots: i32,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    #[serde(default, with = "base64_bytes")]
     pub common_data: Option<CommonData>,
+    /// The settings delivered to the executors, see `config_env_name`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub config: HashMap<String, String>,
+    /// The maximum number of the pending and running tasks, which overrides the default of
+    /// the session manager.
+    #[serde(default)]
+    pub max_pending_tasks: Option<u32>,
+    /// The maximum number of the running tasks; the others stay pending until the running
+    /// ones complete.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_running_tasks: Option<u32>,
+    #[serde(default)]
+    pub cache_scope: CacheScope,
+    /// The time when the session is closed as expired.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<DateTime<Utc>>,
+    /// The settings of the tasks which are created without their own, in seconds and times;
+    /// they're resolved from the application when the session is created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_timeout: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_task_retries: Option<u32>,
+    /// The session this one was cloned from, see `Storage::clone_session`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cloned_from: Option<SessionID>,
+    /// The priority class of the session, which is validated when it's created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority_class: Option<String>,
+    /// The application when the session was created, which is run by its executors; it's
+    /// none if the session runs the latest application, e.g. the sessions created before the
+    /// applications were recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application_spec: Option<Application>,
+    /// The last call of the client on the session, which is persisted at most once every
+    /// `ACTIVITY_INTERVAL` of the storage; it's none if there was no call after the creation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_activity: Option<DateTime<Utc>>,
+    /// Set by the executors of the session, see `Annotations`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub annotations: HashMap<String, String>,
+    #[serde(skip)]
     pub tasks: HashMap<TaskID, TaskPtr>,
+    #[serde(skip)]
     pub tasks_index: HashMap<TaskState, HashMap<TaskID, TaskPtr>>,
+    /// The tasks having the idempotency keys, by their keys.
+    #[serde(skip)]
+    pub idempotency_keys: HashMap<String, TaskID>,
     pub creation_time: DateTime<Utc>,
     pub completion_time: Option<DateTime<Utc>>,
 
     pub status: SessionStatus,
 }
 
-#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, strum_macros::Display)]
+/// The attributes of a new session, which are set by the client.
+#[derive(Clone, Debug)]
+pub struct SessionAttributes {
+    pub namespace: String,
+    pub application: String,
+    pub slots: i32,
+    pub labels: HashMap<String, String>,
+    pub common_data: Option<CommonData>,
+    pub config: HashMap<String, String>,
+    pub max_pending_tasks: Option<u32>,
+    pub max_running_tasks: Option<u32>,
+    pub cache_scope: CacheScope,
+    pub deadline: Option<DateTime<Utc>>,
+    pub task_timeout: Option<u64>,
+    pub max_task_retries: Option<u32>,
+    /// The source of a cloned session, which is set by the session manager only.
+    pub cloned_from: Option<SessionID>,
+    pub priority_class: Option<String>,
+    /// The session runs the latest application instead of the one when it's created.
+    pub latest_application: bool,
+    /// The application recorded on the session, which is resolved by the session manager
+    /// when it's none and not `latest_application`, e.g. it's copied by the clones.
+    pub application_spec: Option<Application>,
+}
+
+impl Default for SessionAttributes {
+    fn default() -> Self {
+        SessionAttributes {
+            namespace: default_namespace(),
+            application: String::new(),
+            slots: DEFAULT_SESSION_SLOTS,
+            labels: HashMap::new(),
+            common_data: None,
+            config: HashMap::new(),
+            max_pending_tasks: None,
+            max_running_tasks: None,
+            cache_scope: CacheScope::None,
+            deadline: None,
+            task_timeout: None,
+            max_task_retries: None,
+            cloned_from: None,
+            priority_class: None,
+            latest_application: false,
+            application_spec: None,
+        }
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, Default, Eq, PartialEq, Hash, strum_macros::Display, Deserialize, Serialize,
+)]
+#[serde(rename_all = "lowercase")]
 pub enum TaskState {
     #[default]
     Pending = 0,
@@ -75,12 +287,95 @@ pub enum TaskState {
     Failed = 3,
 }
 
-#[derive(Clone, Debug)]
+#[derive(
+    Clone, Copy, Debug, Default, Eq, PartialEq, Hash, strum_macros::Display, Deserialize, Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureReason {
+    /// The shim failed to run the task, e.g. it crashed.
+    #[default]
+    ShimError = 0,
+    /// The task was not completed in time, e.g. its session passed the deadline.
+    Timeout = 1,
+    /// The task was aborted by the session manager or the user.
+    Aborted = 2,
+    /// The executor of the task stopped sending heartbeats.
+    ExecutorLost = 3,
+    /// The shim of the task was killed for running out of memory.
+    OomKilled = 4,
+}
+
+/// The last failure of a task, which is persisted; it's kept if the task is retried, and
+/// cleared once the task succeeds.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct TaskFailure {
+    pub reason: FailureReason,
+    pub message: String,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Task {
     pub id: TaskID,
     pub ssn_id: SessionID,
+    /// The order of the creation of the task in its session, from 0; it's never reused even
+    /// if the task is deleted, and it's kept by the retries.
+    #[serde(default)]
+    pub index: u64,
+    #[serde(default, with = "base64_bytes")]
     pub input: Option<TaskInput>,
+    #[serde(default, with = "base64_bytes")]
     pub output: Option<TaskOutput>,
+    /// The W3C trace context of the request which created the task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_context: Option<String>,
+    /// The labels of the task, e.g. the zone of its data as the locality hint of the
+    /// scheduler.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+    /// The key of the data of the task, e.g. a shard id or a hostname; it's launched on an
+    /// executor advertising the key if any takes it within the locality wait.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locality: Option<String>,
+    /// The environment variables of the task, e.g. a random seed, see `TaskContext::env`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// The seconds the task may run before it's failed as timed out; no limit if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// The times the task is requeued after its executor is lost before it's failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<u32>,
+    /// The last progress reported by the executor, which is not persisted.
+    #[serde(skip)]
+    pub progress: Option<TaskProgress>,
+    /// The last failure of the task, e.g. its shim crashed before it was retried.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<TaskFailure>,
+    /// The executor launching the task and its host; they're of the last launch if the task
+    /// was retried.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executor_id: Option<ExecutorID>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// The failed task which this one was resubmitted from, in the same session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_task_id: Option<TaskID>,
+    /// The key of the task given by its client, which is unique in the session; creating a
+    /// task with the key again returns this task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Set by the executors of the task, which are merged over its retries, see
+    /// `Annotations`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub annotations: HashMap<String, String>,
+    /// The number of the chunks of the output streamed by the task while it's running, which
+    /// is counted in memory over its retries, see `OutputChunk`.
+    #[serde(skip)]
+    pub output_chunks: u64,
+    /// The input and the output of the completed task were dropped from the memory, and
+    /// they're loaded from the storage engine when requested.
+    #[serde(skip)]
+    pub spilled: bool,
 
     pub creation_time: DateTime<Utc>,
     pub completion_time: Option<DateTime<Utc>>,
@@ -88,6 +383,53 @@ pub struct Task {
     pub state: TaskState,
 }
 
+/// The attributes of a new task, which are set by the client.
+#[derive(Clone, Default)]
+pub struct TaskAttributes {
+    pub input: Option<TaskInput>,
+    pub trace_context: Option<String>,
+    pub labels: HashMap<String, String>,
+    pub locality: Option<String>,
+    pub env: HashMap<String, String>,
+    pub timeout: Option<u64>,
+    pub max_retries: Option<u32>,
+    pub idempotency_key: Option<String>,
+}
+
+/// The annotations set by an executor when it completes a task, e.g. the rows processed by
+/// the task or the version of the model loaded for its session; they're small summaries next
+/// to the output, see `merge_annotations`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Annotations {
+    pub task: HashMap<String, String>,
+    pub session: HashMap<String, String>,
+}
+
+impl Annotations {
+    pub fn is_empty(&self) -> bool {
+        self.task.is_empty() && self.session.is_empty()
+    }
+}
+
+/// The progress of a running task reported by its executor.
+#[derive(Clone, Default, PartialEq)]
+pub struct TaskProgress {
+    pub percentage: u32,
+    pub payload: Option<Message>,
+    pub update_time: DateTime<Utc>,
+}
+
+/// A chunk of the output streamed by a running task, e.g. a frame of a render, which is
+/// read by the clients before the task is completed; the final output of the task is still
+/// its `output`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputChunk {
+    /// The order of the chunk in the output of the task, from 1.
+    pub sequence: u64,
+    pub data: Message,
+    pub creation_time: DateTime<Utc>,
+}
+
 impl Task {
     pub fn is_completed(&self) -> bool {
         self.state == TaskState::Succeed || self.state == TaskState::Failed
@@ -101,7 +443,46 @@ impl Task {
     }
 }
 
-#[derive(Clone, Copy, Default, Debug, Eq, PartialEq, Hash, strum_macros::Display)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventKind {
+    TaskStateChanged {
+        task_id: TaskID,
+        state: TaskState,
+    },
+    TaskProgressed {
+        task_id: TaskID,
+        progress: TaskProgress,
+    },
+    SessionClosed,
+    /// The session passed its deadline; it's followed by the failures of the aborted tasks
+    /// and the closing of the session.
+    SessionExpired {
+        deadline: DateTime<Utc>,
+    },
+    /// The session had no tasks nor calls of its client since `last_activity`; it's followed
+    /// by the closing of the session.
+    SessionOrphaned {
+        last_activity: DateTime<Utc>,
+    },
+    /// The executor was quarantined after failing the task.
+    ExecutorQuarantined {
+        executor_id: ExecutorID,
+        task_id: TaskID,
+    },
+}
+
+/// The change of a session, which is watched by the clients.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionEvent {
+    pub sequence: u64,
+    pub event_time: DateTime<Utc>,
+    pub kind: EventKind,
+}
+
+#[derive(
+    Clone, Copy, Debug, Default, Eq, PartialEq, Hash, strum_macros::Display, Deserialize, Serialize,
+)]
+#[serde(rename_all = "lowercase")]
 pub enum ExecutorState {
     #[default]
     Idle = 0,
@@ -110,14 +491,14 @@ pub enum ExecutorState {
     Unbinding = 3,
 }
 
-#[derive(Clone, Debug, ::prost::Enumeration, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, ::prost::Enumeration, Deserialize, Serialize)]
 pub enum Shim {
     Log = 0,
     Stdio = 1,
     Wasm = 2,
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct Application {
     pub name: String,
     pub shim: Shim,
@@ -129,34 +510,357 @@ pub struct Application {
     pub environments: Vec<String>,
     #[serde(default = "default_work_dir")]
     pub working_directory: String,
+    /// The share of the executors relative to the other applications when they're all
+    /// backlogged, e.g. 7 and 3 for 70% and 30%.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+    /// The settings of the sessions and the tasks of the application which are created
+    /// without their own; the labels are merged with the ones of the sessions, and the
+    /// sessions win on conflicts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_slots: Option<i32>,
+    /// The seconds a task may run, e.g. 600; no limit by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_task_timeout: Option<u64>,
+    /// The times a task is requeued after its executor is lost; `max_task_retries` of the
+    /// session manager by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_max_retries: Option<u32>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub default_labels: HashMap<String, String>,
+    /// The constraints of the inputs of the tasks, which are checked by the session manager
+    /// when the tasks are created; no constraint by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<InputConstraints>,
 }
 
-#[derive(Clone, Debug)]
+/// The constraints of the inputs of the tasks of an application, e.g.
+///
+/// ```yaml
+/// input:
+///   max_bytes: 1048576
+///   required_fields: [model, prompt]
+/// ```
+///
+/// The fields and the schema are checked only if the input is an envelope of the json codec,
+/// see `message::to_task_input`; other inputs pass through.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct InputConstraints {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+    /// The fields of the JSON object in the input.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_fields: Vec<String>,
+    /// The JSON schema of the value in the input, which requires the `jsonschema` feature of
+    /// the session manager.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    /// Whether the common data of the sessions is checked too when they're created.
+    #[serde(default)]
+    pub strict: bool,
+}
+
+impl Application {
+    pub fn weight(&self) -> u32 {
+        self.weight.unwrap_or(DEFAULT_APPLICATION_WEIGHT)
+    }
+
+    /// The SHA-256 of the definition of the application, which tells whether two sessions ran
+    /// the same one; every field is hashed with its length, and the labels by their keys.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        let mut field = |data: &[u8]| {
+            hasher.update((data.len() as u64).to_be_bytes());
+            hasher.update(data);
+        };
+
+        field(self.name.as_bytes());
+        field(&(self.shim.clone() as i32).to_be_bytes());
+        field(self.command.as_bytes());
+        for list in [&self.arguments, &self.environments] {
+            field(&(list.len() as u64).to_be_bytes());
+            list.iter().for_each(|s| field(s.as_bytes()));
+        }
+        field(self.working_directory.as_bytes());
+        let defaults = (
+            self.weight,
+            self.default_slots,
+            self.default_task_timeout,
+            self.default_max_retries,
+        );
+        field(format!("{:?}", defaults).as_bytes());
+        let labels: BTreeMap<_, _> = self.default_labels.iter().collect();
+        field(&(labels.len() as u64).to_be_bytes());
+        for (key, value) in labels {
+            field(key.as_bytes());
+            field(value.as_bytes());
+        }
+
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Returns all the problems of the application, e.g. the command of a process shim is empty.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.name.is_empty() {
+            problems.push("application: empty name".to_string());
+        }
+
+        if !matches!(self.shim, Shim::Log) && self.command.is_empty() {
+            problems.push(format!(
+                "application <{}>: command is required by {:?} shim",
+                self.name, self.shim
+            ));
+        }
+
+        if !self.working_directory.is_empty()
+            && !std::path::Path::new(&self.working_directory).is_absolute()
+        {
+            problems.push(format!(
+                "application <{}>: working_directory <{}> is not absolute",
+                self.name, self.working_directory
+            ));
+        }
+
+        if self.weight == Some(0) {
+            problems.push(format!(
+                "application <{}>: weight must be greater than 0",
+                self.name
+            ));
+        }
+
+        if matches!(self.default_slots, Some(slots) if slots <= 0) {
+            problems.push(format!(
+                "application <{}>: default_slots must be greater than 0",
+                self.name
+            ));
+        }
+
+        if self.default_task_timeout == Some(0) {
+            problems.push(format!(
+                "application <{}>: default_task_timeout must be greater than 0",
+                self.name
+            ));
+        }
+
+        if let Some(input) = &self.input {
+            if input.max_bytes == Some(0) {
+                problems.push(format!(
+                    "application <{}>: input.max_bytes must be greater than 0",
+                    self.name
+                ));
+            }
+            if input.required_fields.iter().any(String::is_empty) {
+                problems.push(format!(
+                    "application <{}>: empty field in input.required_fields",
+                    self.name
+                ));
+            }
+            if let Some(schema) = &input.schema {
+                if let Err(e) = serde_json::from_str::<serde_json::Value>(schema) {
+                    problems.push(format!(
+                        "application <{}>: input.schema is not JSON: {}",
+                        self.name, e
+                    ));
+                }
+            }
+        }
+
+        problems
+    }
+}
+
+/// The host of an executor, which is reported by the executor manager at registration.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct HostInfo {
+    pub hostname: String,
+    pub ip: String,
+    pub os: String,
+    pub arch: String,
+    /// The version of the executor manager.
+    pub version: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Executor {
     pub id: ExecutorID,
     pub slots: i32,
+    #[serde(default)]
     pub applications: Vec<Application>,
     pub task_id: Option<TaskID>,
     pub ssn_id: Option<SessionID>,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// The keys of the data local to the executor, e.g. the shards cached on its host; the
+    /// tasks with these localities are launched on it first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub locality_keys: Vec<String>,
+    #[serde(default)]
+    pub host: HostInfo,
 
     pub creation_time: DateTime<Utc>,
+    pub last_heartbeat: DateTime<Utc>,
     pub state: ExecutorState,
+    pub draining: bool,
+    /// The executor failed too many tasks, so it's drained until it's uncordoned.
+    #[serde(default)]
+    pub quarantined: bool,
+    /// Bumped when the executor is bound to a session, so the requests of its previous
+    /// bindings are rejected as stale.
+    #[serde(default)]
+    pub generation: u64,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct TaskContext {
     pub id: String,
     pub ssn_id: String,
     pub input: Option<TaskInput>,
     pub output: Option<TaskOutput>,
+    /// The W3C trace context of the task, which is continued by the executor.
+    pub trace_context: Option<String>,
+    /// The locality hint of the task, which the executor takes as one of its keys once it
+    /// ran the task.
+    pub locality: Option<String>,
+    /// The environment variables of the task, which are added to the environment of its
+    /// process by the stdio shim; the long-lived services read them here.
+    pub env: HashMap<String, String>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct SessionContext {
     pub ssn_id: String,
     pub application: String,
     pub slots: i32,
     pub common_data: Option<CommonData>,
+    /// The settings of the session, see `config_env_name`.
+    pub config: HashMap<String, String>,
+}
+
+// The payloads are printed by `payload::display` in the `Debug` of the types carrying them,
+// so they never leak into the logs.
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("id", &self.id)
+            .field("namespace", &self.namespace)
+            .field("application", &self.application)
+            .field("slots", &self.slots)
+            .field("labels", &self.labels)
+            .field(
+                "common_data",
+                &payload::display(self.common_data.as_deref()),
+            )
+            .field("config", &self.config)
+            .field("max_pending_tasks", &self.max_pending_tasks)
+            .field("max_running_tasks", &self.max_running_tasks)
+            .field("cache_scope", &self.cache_scope)
+            .field("deadline", &self.deadline)
+            .field("task_timeout", &self.task_timeout)
+            .field("max_task_retries", &self.max_task_retries)
+            .field("cloned_from", &self.cloned_from)
+            .field("priority_class", &self.priority_class)
+            .field(
+                "application_spec",
+                &self.application_spec.as_ref().map(Application::fingerprint),
+            )
+            .field("last_activity", &self.last_activity)
+            .field("annotations", &self.annotations)
+            .field("tasks", &self.tasks.len())
+            .field("creation_time", &self.creation_time)
+            .field("completion_time", &self.completion_time)
+            .field("status", &self.status)
+            .finish()
+    }
+}
+
+impl fmt::Debug for Task {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Task")
+            .field("id", &self.id)
+            .field("ssn_id", &self.ssn_id)
+            .field("index", &self.index)
+            .field("input", &payload::display(self.input.as_deref()))
+            .field("output", &payload::display(self.output.as_deref()))
+            .field("trace_context", &self.trace_context)
+            .field("labels", &self.labels)
+            .field("locality", &self.locality)
+            .field("env", &self.env)
+            .field("timeout", &self.timeout)
+            .field("max_retries", &self.max_retries)
+            .field("progress", &self.progress)
+            .field("failure", &self.failure)
+            .field("executor_id", &self.executor_id)
+            .field("hostname", &self.hostname)
+            .field("original_task_id", &self.original_task_id)
+            .field("idempotency_key", &self.idempotency_key)
+            .field("annotations", &self.annotations)
+            .field("spilled", &self.spilled)
+            .field("creation_time", &self.creation_time)
+            .field("completion_time", &self.completion_time)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl fmt::Debug for TaskAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TaskAttributes")
+            .field("input", &payload::display(self.input.as_deref()))
+            .field("trace_context", &self.trace_context)
+            .field("labels", &self.labels)
+            .field("locality", &self.locality)
+            .field("env", &self.env)
+            .field("timeout", &self.timeout)
+            .field("max_retries", &self.max_retries)
+            .field("idempotency_key", &self.idempotency_key)
+            .finish()
+    }
+}
+
+impl fmt::Debug for TaskProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TaskProgress")
+            .field("percentage", &self.percentage)
+            .field("payload", &payload::display(self.payload.as_deref()))
+            .field("update_time", &self.update_time)
+            .finish()
+    }
+}
+
+impl fmt::Debug for TaskContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TaskContext")
+            .field("id", &self.id)
+            .field("ssn_id", &self.ssn_id)
+            .field("input", &payload::display(self.input.as_deref()))
+            .field("output", &payload::display(self.output.as_deref()))
+            .field("trace_context", &self.trace_context)
+            .field("locality", &self.locality)
+            .field("env", &self.env)
+            .finish()
+    }
+}
+
+impl fmt::Debug for SessionContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SessionContext")
+            .field("ssn_id", &self.ssn_id)
+            .field("application", &self.application)
+            .field("slots", &self.slots)
+            .field(
+                "common_data",
+                &payload::display(self.common_data.as_deref()),
+            )
+            .field("config", &self.config)
+            .finish()
+    }
 }
 
 impl Session {
@@ -168,36 +872,116 @@ impl Session {
         let task_ptr = TaskPtr::new(task.clone().into());
 
         self.tasks.insert(task.id, task_ptr.clone());
+        for (state, tasks) in self.tasks_index.iter_mut() {
+            if *state != task.state {
+                tasks.remove(&task.id);
+            }
+        }
         self.tasks_index.entry(task.state).or_default();
         self.tasks_index
             .get_mut(&task.state)
             .unwrap()
             .insert(task.id, task_ptr);
+        if let Some(key) = &task.idempotency_key {
+            self.idempotency_keys.insert(key.clone(), task.id);
+        }
+        self.count_tasks();
+    }
+
+    /// The task created with the idempotency key, if any.
+    pub fn task_by_key(&self, key: &str) -> Option<TaskID> {
+        self.idempotency_keys.get(key).copied()
+    }
+
+    fn count_tasks(&mut self) {
+        let count = |state| {
+            self.tasks_index
+                .get(&state)
+                .map(HashMap::len)
+                .unwrap_or_default() as i32
+        };
+        self.status.counts = TaskCounts {
+            pending: count(TaskState::Pending),
+            running: count(TaskState::Running),
+            succeed: count(TaskState::Succeed),
+            failed: count(TaskState::Failed),
+        };
     }
 
-    pub fn pop_pending_task(&mut self) -> Option<TaskPtr> {
+    /// The number of the pending tasks.
+    pub fn pending(&self) -> usize {
+        self.status.counts.pending as usize
+    }
+
+    /// The number of the tasks which are not completed, i.e. pending or running.
+    pub fn backlog(&self) -> usize {
+        (self.status.counts.pending + self.status.counts.running) as usize
+    }
+
+    /// Pops the reserved task if it's still pending, or the oldest pending task which is not
+    /// reserved by others and is admitted, e.g. by the locality of the task.
+    pub fn pop_pending_task(
+        &mut self,
+        reserved: Option<TaskID>,
+        others: &HashSet<TaskID>,
+        admits: impl Fn(&Task) -> bool,
+    ) -> Option<TaskPtr> {
         let pending_tasks = self.tasks_index.get_mut(&TaskState::Pending)?;
-        if let Some((task_id, _)) = pending_tasks.clone().iter().next() {
-            return pending_tasks.remove(task_id);
-        }
+        let task_id = match reserved.filter(|id| pending_tasks.contains_key(id)) {
+            Some(id) => id,
+            None => {
+                let mut ids: Vec<_> = pending_tasks
+                    .keys()
+                    .filter(|id| !others.contains(id))
+                    .copied()
+                    .collect();
+                ids.sort_unstable();
+                ids.into_iter()
+                    .find(|id| pending_tasks[id].lock().is_ok_and(|task| admits(&task)))?
+            }
+        };
+        let task_ptr = pending_tasks.remove(&task_id);
+        self.count_tasks();
 
-        None
+        task_ptr
     }
-}
 
-impl Clone for Session {
-    fn clone(&self) -> Self {
-        let mut ssn = Session {
+    /// The copy of the session with its status, e.g. the counts of its tasks, but not its
+    /// tasks, so it takes the same time however many tasks the session has; the tasks are
+    /// listed by pages instead.
+    pub fn summary(&self) -> Self {
+        Session {
             id: self.id,
+            namespace: self.namespace.clone(),
             application: self.application.clone(),
             slots: self.slots,
+            labels: self.labels.clone(),
             common_data: self.common_data.clone(),
+            config: self.config.clone(),
+            max_pending_tasks: self.max_pending_tasks,
+            max_running_tasks: self.max_running_tasks,
+            cache_scope: self.cache_scope,
+            deadline: self.deadline,
+            task_timeout: self.task_timeout,
+            max_task_retries: self.max_task_retries,
+            cloned_from: self.cloned_from,
+            priority_class: self.priority_class.clone(),
+            application_spec: self.application_spec.clone(),
+            last_activity: self.last_activity,
+            annotations: self.annotations.clone(),
             tasks: HashMap::new(),
             tasks_index: HashMap::new(),
+            idempotency_keys: HashMap::new(),
             creation_time: self.creation_time,
             completion_time: self.completion_time,
             status: self.status.clone(),
-        };
+        }
+    }
+}
+
+impl Clone for Session {
+    fn clone(&self) -> Self {
+        let mut ssn = self.summary();
 
         for (id, t) in &self.tasks {
             match t.lock() {
@@ -230,6 +1014,9 @@ impl TryFrom<rpc::Task> for TaskContext {
             ssn_id: spec.session_id.to_string(),
             input: spec.input.map(TaskInput::from),
             output: spec.output.map(TaskOutput::from),
+            trace_context: spec.trace_context,
+            locality: spec.locality,
+            env: spec.env,
         })
     }
 }
@@ -250,6 +1037,7 @@ impl TryFrom<rpc::Session> for SessionContext {
             application: spec.application.clone(),
             slots: spec.slots,
             common_data: spec.common_data.map(CommonData::from),
+            config: spec.config,
         })
     }
 }
@@ -278,20 +1066,118 @@ impl From<&Task> for rpc::Task {
                 id: task.id.to_string(),
                 owner: Some(task.ssn_id.to_string()),
             }),
+            index: task.index,
+            original_task_id: task.original_task_id.map(|id| id.to_string()),
             spec: Some(rpc::TaskSpec {
                 session_id: task.ssn_id.to_string(),
                 input: task.input.clone().map(TaskInput::into),
                 output: task.output.clone().map(TaskOutput::into),
+                trace_context: task.trace_context.clone(),
+                labels: task.labels.clone(),
+                locality: task.locality.clone(),
+                env: task.env.clone(),
+                timeout: task.timeout,
+                max_retries: task.max_retries,
+                idempotency_key: task.idempotency_key.clone(),
             }),
             status: Some(rpc::TaskStatus {
                 state: task.state as i32,
                 creation_time: task.creation_time.timestamp(),
                 completion_time: task.completion_time.map(|s| s.timestamp()),
+                progress: task.progress.as_ref().map(rpc::TaskProgress::from),
+                failure: task.failure.as_ref().map(rpc::TaskFailure::from),
+                executor_id: task.executor_id.clone(),
+                hostname: task.hostname.clone(),
+                annotations: task.annotations.clone(),
+                output_chunks: task.output_chunks,
             }),
         }
     }
 }
 
+impl From<FailureReason> for rpc::FailureReason {
+    fn from(reason: FailureReason) -> Self {
+        match reason {
+            FailureReason::ShimError => rpc::FailureReason::FailureShimError,
+            FailureReason::Timeout => rpc::FailureReason::FailureTimeout,
+            FailureReason::Aborted => rpc::FailureReason::FailureAborted,
+            FailureReason::ExecutorLost => rpc::FailureReason::FailureExecutorLost,
+            FailureReason::OomKilled => rpc::FailureReason::FailureOomKilled,
+        }
+    }
+}
+
+impl From<&TaskFailure> for rpc::TaskFailure {
+    fn from(failure: &TaskFailure) -> Self {
+        rpc::TaskFailure {
+            reason: rpc::FailureReason::from(failure.reason) as i32,
+            message: failure.message.clone(),
+        }
+    }
+}
+
+impl TryFrom<rpc::TaskFailure> for TaskFailure {
+    type Error = FlameError;
+
+    fn try_from(failure: rpc::TaskFailure) -> Result<Self, Self::Error> {
+        Ok(TaskFailure {
+            reason: FailureReason::try_from(failure.reason)?,
+            message: failure.message,
+        })
+    }
+}
+
+impl From<&TaskProgress> for rpc::TaskProgress {
+    fn from(progress: &TaskProgress) -> Self {
+        rpc::TaskProgress {
+            percentage: progress.percentage,
+            payload: progress.payload.clone().map(Message::into),
+            update_time: progress.update_time.timestamp(),
+        }
+    }
+}
+
+impl TryFrom<rpc::TaskProgress> for TaskProgress {
+    type Error = FlameError;
+
+    fn try_from(progress: rpc::TaskProgress) -> Result<Self, Self::Error> {
+        if progress.percentage > 100 {
+            return Err(FlameError::invalid_argument(
+                "percentage",
+                format!("{} is greater than 100", progress.percentage),
+            ));
+        }
+        if let Some(payload) = &progress.payload {
+            if payload.len() > MAX_PROGRESS_PAYLOAD {
+                return Err(FlameError::invalid_argument(
+                    "payload",
+                    format!(
+                        "{} bytes is larger than {} bytes",
+                        payload.len(),
+                        MAX_PROGRESS_PAYLOAD
+                    ),
+                ));
+            }
+        }
+
+        Ok(TaskProgress {
+            percentage: progress.percentage,
+            payload: progress.payload.map(Message::from),
+            update_time: parse_timestamp("update_time", progress.update_time)?,
+        })
+    }
+}
+
+impl From<&OutputChunk> for rpc::TaskOutputChunk {
+    fn from(chunk: &OutputChunk) -> Self {
+        rpc::TaskOutputChunk {
+            sequence: chunk.sequence,
+            data: chunk.data.to_vec(),
+            creation_time: chunk.creation_time.timestamp(),
+        }
+    }
+}
+
 impl From<SessionState> for rpc::SessionState {
     fn from(state: SessionState) -> Self {
         match state {
@@ -309,23 +1195,19 @@ impl From<Session> for rpc::Session {
 
 impl From<&Session> for rpc::Session {
     fn from(ssn: &Session) -> Self {
-        let mut status = rpc::SessionStatus {
+        let counts = ssn.status.counts;
+        let status = rpc::SessionStatus {
             state: ssn.status.state as i32,
             creation_time: ssn.creation_time.timestamp(),
             completion_time: ssn.completion_time.map(|s| s.timestamp()),
-            failed: 0,
-            pending: 0,
-            running: 0,
-            succeed: 0,
+            failed: counts.failed,
+            pending: counts.pending,
+            running: counts.running,
+            succeed: counts.succeed,
+            unschedulable: ssn.status.unschedulable.clone(),
+            generation: ssn.status.generation,
+            annotations: ssn.annotations.clone(),
         };
-        for (s, v) in &ssn.tasks_index {
-            match s {
-                TaskState::Pending => status.pending = v.len() as i32,
-                TaskState::Running => status.running = v.len() as i32,
-                TaskState::Succeed => status.succeed = v.len() as i32,
-                TaskState::Failed => status.failed = v.len() as i32,
-            }
-        }
 
         rpc::Session {
             metadata: Some(rpc::Metadata {
@@ -336,12 +1218,302 @@ impl From<&Session> for rpc::Session {
                 application: ssn.application.clone(),
                 slots: ssn.slots,
                 common_data: ssn.common_data.clone().map(CommonData::into),
+                labels: ssn.labels.clone(),
+                namespace: ssn.namespace.clone(),
+                max_pending_tasks: ssn.max_pending_tasks,
+                cache_scope: rpc::CacheScope::from(ssn.cache_scope) as i32,
+                deadline: ssn.deadline.map(|t| t.timestamp()),
+                timeout: None,
+                config: ssn.config.clone(),
+                task_timeout: ssn.task_timeout,
+                max_task_retries: ssn.max_task_retries,
+                cloned_from: ssn.cloned_from.map(|id| id.to_string()),
+                priority_class: ssn.priority_class.clone(),
+                latest_application: ssn.application_spec.is_none(),
+                application_fingerprint: ssn
+                    .application_spec
+                    .as_ref()
+                    .map(Application::fingerprint),
+                max_running_tasks: ssn.max_running_tasks,
             }),
             status: Some(status),
         }
     }
 }
 
+/// The archived sessions, e.g. the ones sent by `ImportSession`; the tasks are converted
+/// separately.
+impl TryFrom<rpc::Session> for Session {
+    type Error = FlameError;
+
+    fn try_from(ssn: rpc::Session) -> Result<Self, Self::Error> {
+        let metadata = ssn
+            .metadata
+            .ok_or(FlameError::invalid_argument("session", "no metadata"))?;
+        let spec = ssn
+            .spec
+            .ok_or(FlameError::invalid_argument("session", "no spec"))?;
+        let status = ssn
+            .status
+            .ok_or(FlameError::invalid_argument("session", "no status"))?;
+
+        Ok(Session {
+            id: parse_session_id(&metadata.id)?,
+            namespace: spec.namespace,
+            application: spec.application,
+            slots: spec.slots,
+            labels: spec.labels,
+            common_data: spec.common_data.map(CommonData::from),
+            config: spec.config,
+            max_pending_tasks: spec.max_pending_tasks,
+            max_running_tasks: spec.max_running_tasks,
+            cache_scope: CacheScope::try_from(spec.cache_scope)?,
+            deadline: spec
+                .deadline
+                .map(|t| parse_timestamp("deadline", t))
+                .transpose()?,
+            task_timeout: spec.task_timeout,
+            max_task_retries: spec.max_task_retries,
+            cloned_from: spec
+                .cloned_from
+                .map(|id| parse_session_id(&id))
+                .transpose()?,
+            priority_class: spec.priority_class,
+            // The archives have the fingerprints of the applications only.
+            application_spec: None,
+            last_activity: None,
+            annotations: status.annotations,
+            tasks: HashMap::new(),
+            tasks_index: HashMap::new(),
+            idempotency_keys: HashMap::new(),
+            creation_time: parse_timestamp("creation_time", status.creation_time)?,
+            completion_time: status
+                .completion_time
+                .map(|t| parse_timestamp("completion_time", t))
+                .transpose()?,
+            status: SessionStatus {
+                state: SessionState::try_from(status.state)?,
+                counts: TaskCounts::default(),
+                unschedulable: status.unschedulable,
+                generation: status.generation,
+            },
+        })
+    }
+}
+
+impl TryFrom<rpc::Task> for Task {
+    type Error = FlameError;
+
+    fn try_from(task: rpc::Task) -> Result<Self, Self::Error> {
+        let metadata = task
+            .metadata
+            .ok_or(FlameError::invalid_argument("task", "no metadata"))?;
+        let spec = task
+            .spec
+            .ok_or(FlameError::invalid_argument("task", "no spec"))?;
+        let status = task
+            .status
+            .ok_or(FlameError::invalid_argument("task", "no status"))?;
+
+        Ok(Task {
+            id: parse_task_id(&metadata.id)?,
+            ssn_id: parse_session_id(&spec.session_id)?,
+            index: task.index,
+            input: spec.input.map(TaskInput::from),
+            output: spec.output.map(TaskOutput::from),
+            trace_context: spec.trace_context,
+            labels: spec.labels,
+            locality: spec.locality,
+            env: spec.env,
+            timeout: spec.timeout,
+            max_retries: spec.max_retries,
+            progress: status.progress.map(TaskProgress::try_from).transpose()?,
+            failure: status.failure.map(TaskFailure::try_from).transpose()?,
+            executor_id: status.executor_id,
+            hostname: status.hostname,
+            original_task_id: task
+                .original_task_id
+                .as_deref()
+                .map(parse_task_id)
+                .transpose()?,
+            idempotency_key: spec.idempotency_key,
+            annotations: status.annotations,
+            output_chunks: status.output_chunks,
+            spilled: false,
+            creation_time: parse_timestamp("creation_time", status.creation_time)?,
+            completion_time: status
+                .completion_time
+                .map(|t| parse_timestamp("completion_time", t))
+                .transpose()?,
+            state: TaskState::try_from(status.state)?,
+        })
+    }
+}
+
+fn parse_timestamp(field: &str, secs: i64) -> Result<DateTime<Utc>, FlameError> {
+    DateTime::<Utc>::from_timestamp(secs, 0)
+        .ok_or(FlameError::invalid_argument(field, "invalid timestamp"))
+}
+
+impl From<&SessionEvent> for rpc::SessionEvent {
+    fn from(event: &SessionEvent) -> Self {
+        let kind = match &event.kind {
+            EventKind::TaskStateChanged { task_id, state } => {
+                rpc::session_event::Event::TaskStateChanged(rpc::TaskStateChangedEvent {
+                    task_id: task_id.to_string(),
+                    state: rpc::TaskState::from(*state) as i32,
+                })
+            }
+            EventKind::TaskProgressed { task_id, progress } => {
+                rpc::session_event::Event::TaskProgress(rpc::TaskProgressEvent {
+                    task_id: task_id.to_string(),
+                    progress: Some(rpc::TaskProgress::from(progress)),
+                })
+            }
+            EventKind::SessionClosed => {
+                rpc::session_event::Event::SessionClosed(rpc::SessionClosedEvent {})
+            }
+            EventKind::SessionExpired { deadline } => {
+                rpc::session_event::Event::SessionExpired(rpc::SessionExpiredEvent {
+                    deadline: deadline.timestamp(),
+                })
+            }
+            EventKind::SessionOrphaned { last_activity } => {
+                rpc::session_event::Event::SessionOrphaned(rpc::SessionOrphanedEvent {
+                    last_activity: last_activity.timestamp(),
+                })
+            }
+            EventKind::ExecutorQuarantined {
+                executor_id,
+                task_id,
+            } => rpc::session_event::Event::ExecutorQuarantined(rpc::ExecutorQuarantinedEvent {
+                executor_id: executor_id.clone(),
+                task_id: task_id.to_string(),
+            }),
+        };
+
+        rpc::SessionEvent {
+            sequence: event.sequence,
+            event_time: event.event_time.timestamp(),
+            event: Some(kind),
+        }
+    }
+}
+
+impl TryFrom<rpc::SessionEvent> for SessionEvent {
+    type Error = FlameError;
+
+    fn try_from(event: rpc::SessionEvent) -> Result<Self, Self::Error> {
+        let kind = match event
+            .event
+            .ok_or(FlameError::invalid_argument("event", "no event"))?
+        {
+            rpc::session_event::Event::TaskStateChanged(e) => EventKind::TaskStateChanged {
+                task_id: parse_task_id(&e.task_id)?,
+                state: TaskState::try_from(e.state)?,
+            },
+            rpc::session_event::Event::TaskProgress(e) => EventKind::TaskProgressed {
+                task_id: parse_task_id(&e.task_id)?,
+                progress: e
+                    .progress
+                    .ok_or(FlameError::invalid_argument("progress", "no progress"))?
+                    .try_into()?,
+            },
+            rpc::session_event::Event::SessionClosed(_) => EventKind::SessionClosed,
+            rpc::session_event::Event::SessionExpired(e) => EventKind::SessionExpired {
+                deadline: parse_timestamp("deadline", e.deadline)?,
+            },
+            rpc::session_event::Event::SessionOrphaned(e) => EventKind::SessionOrphaned {
+                last_activity: parse_timestamp("last_activity", e.last_activity)?,
+            },
+            rpc::session_event::Event::ExecutorQuarantined(e) => EventKind::ExecutorQuarantined {
+                executor_id: e.executor_id,
+                task_id: parse_task_id(&e.task_id)?,
+            },
+        };
+
+        Ok(SessionEvent {
+            sequence: event.sequence,
+            event_time: parse_timestamp("event_time", event.event_time)?,
+            kind,
+        })
+    }
+}
+
+impl From<ExecutorState> for rpc::ExecutorState {
+    fn from(state: ExecutorState) -> Self {
+        match state {
+            ExecutorState::Idle => rpc::ExecutorState::ExecutorIdle,
+            ExecutorState::Binding => rpc::ExecutorState::ExecutorBinding,
+            ExecutorState::Bound => rpc::ExecutorState::ExecutorBound,
+            ExecutorState::Unbinding => rpc::ExecutorState::ExecutorUnbinding,
+        }
+    }
+}
+
+impl From<Executor> for rpc::Executor {
+    fn from(exe: Executor) -> Self {
+        rpc::Executor::from(&exe)
+    }
+}
+
+impl From<&Executor> for rpc::Executor {
+    fn from(exe: &Executor) -> Self {
+        rpc::Executor {
+            metadata: Some(rpc::Metadata {
+                id: exe.id.clone(),
+                owner: None,
+            }),
+            spec: Some(rpc::ExecutorSpec {
+                slots: exe.slots,
+                applications: exe
+                    .applications
+                    .iter()
+                    .map(rpc::Application::from)
+                    .collect(),
+                labels: exe.labels.clone(),
+                locality_keys: exe.locality_keys.clone(),
+                host: Some(rpc::HostInfo::from(&exe.host)),
+            }),
+            status: Some(rpc::ExecutorStatus {
+                state: rpc::ExecutorState::from(exe.state) as i32,
+                session_id: exe.ssn_id.map(|id| id.to_string()),
+                task_id: exe.task_id.map(|id| id.to_string()),
+                creation_time: exe.creation_time.timestamp(),
+                last_heartbeat: exe.last_heartbeat.timestamp(),
+                draining: exe.draining,
+                quarantined: exe.quarantined,
+                generation: exe.generation,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+impl From<&HostInfo> for rpc::HostInfo {
+    fn from(host: &HostInfo) -> Self {
+        rpc::HostInfo {
+            hostname: host.hostname.clone(),
+            ip: host.ip.clone(),
+            os: host.os.clone(),
+            arch: host.arch.clone(),
+            version: host.version.clone(),
+        }
+    }
+}
+
+impl From<rpc::HostInfo> for HostInfo {
+    fn from(host: rpc::HostInfo) -> Self {
+        HostInfo {
+            hostname: host.hostname,
+            ip: host.ip,
+            os: host.os,
+            arch: host.arch,
+            version: host.version,
+        }
+    }
+}
+
 impl From<rpc::Application> for Application {
     fn from(app: rpc::Application) -> Self {
         Application::from(&app)
@@ -357,6 +1529,12 @@ impl From<&rpc::Application> for Application {
             arguments: app.arguments.to_vec(),
             environments: app.environments.to_vec(),
             working_directory: app.working_directory.to_string(),
+            weight: app.weight,
+            default_slots: app.default_slots,
+            default_task_timeout: app.default_task_timeout,
+            default_max_retries: app.default_max_retries,
+            default_labels: app.default_labels.clone(),
+            input: app.input.as_ref().map(InputConstraints::from),
         }
     }
 }
@@ -376,6 +1554,34 @@ impl From<&Application> for rpc::Application {
             arguments: app.arguments.to_vec(),
             environments: app.environments.to_vec(),
             working_directory: app.working_directory.to_string(),
+            weight: app.weight,
+            default_slots: app.default_slots,
+            default_task_timeout: app.default_task_timeout,
+            default_max_retries: app.default_max_retries,
+            default_labels: app.default_labels.clone(),
+            input: app.input.as_ref().map(rpc::InputConstraints::from),
+        }
+    }
+}
+
+impl From<&rpc::InputConstraints> for InputConstraints {
+    fn from(input: &rpc::InputConstraints) -> Self {
+        InputConstraints {
+            max_bytes: input.max_bytes,
+            required_fields: input.required_fields.to_vec(),
+            schema: input.schema.clone(),
+            strict: input.strict,
+        }
+    }
+}
+
+impl From<&InputConstraints> for rpc::InputConstraints {
+    fn from(input: &InputConstraints) -> Self {
+        rpc::InputConstraints {
+            max_bytes: input.max_bytes,
+            required_fields: input.required_fields.to_vec(),
+            schema: input.schema.clone(),
+            strict: input.strict,
         }
     }
 }
@@ -386,8 +1592,34 @@ impl TryFrom<i32> for SessionState {
         match s {
             0 => Ok(SessionState::Open),
             1 => Ok(SessionState::Closed),
-            _ => Err(FlameError::InvalidState(
-                "invalid session state".to_string(),
+            _ => Err(FlameError::invalid_argument(
+                "state",
+                "invalid session state",
+            )),
+        }
+    }
+}
+
+impl From<CacheScope> for rpc::CacheScope {
+    fn from(scope: CacheScope) -> Self {
+        match scope {
+            CacheScope::None => rpc::CacheScope::CacheNone,
+            CacheScope::Session => rpc::CacheScope::CacheSession,
+            CacheScope::Application => rpc::CacheScope::CacheApplication,
+        }
+    }
+}
+
+impl TryFrom<i32> for CacheScope {
+    type Error = FlameError;
+    fn try_from(s: i32) -> Result<Self, Self::Error> {
+        match s {
+            0 => Ok(CacheScope::None),
+            1 => Ok(CacheScope::Session),
+            2 => Ok(CacheScope::Application),
+            _ => Err(FlameError::invalid_argument(
+                "cache_scope",
+                "invalid cache scope",
             )),
         }
     }
@@ -401,7 +1633,40 @@ impl TryFrom<i32> for TaskState {
             1 => Ok(TaskState::Running),
             2 => Ok(TaskState::Succeed),
             3 => Ok(TaskState::Failed),
-            _ => Err(FlameError::InvalidState("invalid task state".to_string())),
+            _ => Err(FlameError::invalid_argument("state", "invalid task state")),
+        }
+    }
+}
+
+impl TryFrom<i32> for SessionOrder {
+    type Error = FlameError;
+    fn try_from(o: i32) -> Result<Self, Self::Error> {
+        match o {
+            0 => Ok(SessionOrder::CreationTime),
+            1 => Ok(SessionOrder::Id),
+            2 => Ok(SessionOrder::State),
+            3 => Ok(SessionOrder::Pending),
+            _ => Err(FlameError::invalid_argument(
+                "order_by",
+                "invalid session order",
+            )),
+        }
+    }
+}
+
+impl TryFrom<i32> for FailureReason {
+    type Error = FlameError;
+    fn try_from(r: i32) -> Result<Self, Self::Error> {
+        match r {
+            0 => Ok(FailureReason::ShimError),
+            1 => Ok(FailureReason::Timeout),
+            2 => Ok(FailureReason::Aborted),
+            3 => Ok(FailureReason::ExecutorLost),
+            4 => Ok(FailureReason::OomKilled),
+            _ => Err(FlameError::invalid_argument(
+                "reason",
+                "invalid failure reason",
+            )),
         }
     }
 }
@@ -412,6 +1677,891 @@ impl fmt::Display for TaskGID {
     }
 }
 
+impl TaskGID {
+    /// Parses the ids of the requests, which are passed as separate strings.
+    pub fn parse(ssn_id: &str, task_id: &str) -> Result<Self, FlameError> {
+        Ok(TaskGID {
+            ssn_id: parse_session_id(ssn_id)?,
+            task_id: parse_task_id(task_id)?,
+        })
+    }
+}
+
+pub fn parse_session_id(id: &str) -> Result<SessionID, FlameError> {
+    id.parse::<SessionID>().map_err(|_| {
+        FlameError::invalid_argument("session_id", format!("invalid session id <{}>", id))
+    })
+}
+
+fn default_namespace() -> String {
+    DEFAULT_NAMESPACE.to_string()
+}
+
+/// Parses the namespace of a request; it's the default namespace if empty. The namespaces are
+/// DNS labels, e.g. `team-a`.
+pub fn parse_namespace(namespace: &str) -> Result<String, FlameError> {
+    if namespace.is_empty() {
+        return Ok(default_namespace());
+    }
+
+    let valid = namespace.len() <= MAX_NAMESPACE_LEN
+        && namespace
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !namespace.starts_with('-')
+        && !namespace.ends_with('-');
+    if !valid {
+        return Err(FlameError::invalid_argument(
+            "namespace",
+            format!(
+                "invalid namespace <{}>, expect lowercase letters, digits and '-'",
+                namespace
+            ),
+        ));
+    }
+
+    Ok(namespace.to_string())
+}
+
+/// The environment variable exporting the key of the session config to the workers, e.g.
+/// `FLAME_SSN_MODEL_VERSION` of `model-version`; the letters are uppercased, and the other
+/// characters are replaced by '_'.
+pub fn config_env_name(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect();
+
+    format!("{}{}", CONFIG_ENV_PREFIX, name)
+}
+
+/// Parses the config of a new session; the keys must not be empty, nor collide with each
+/// other as environment variables, see `config_env_name`.
+pub fn parse_config(
+    config: HashMap<String, String>,
+) -> Result<HashMap<String, String>, FlameError> {
+    let mut names = HashMap::new();
+    let mut keys: Vec<&String> = config.keys().collect();
+    keys.sort();
+
+    for key in keys {
+        if key.is_empty() {
+            return Err(FlameError::invalid_argument("config", "empty key"));
+        }
+        if let Some(other) = names.insert(config_env_name(key), key) {
+            return Err(FlameError::invalid_argument(
+                "config",
+                format!(
+                    "keys <{}> and <{}> are both exported as <{}>",
+                    other,
+                    key,
+                    config_env_name(key)
+                ),
+            ));
+        }
+    }
+
+    Ok(config)
+}
+
+/// Checks the environment variables of a new task; the names are the portable ones, e.g.
+/// `RANDOM_SEED`, and must not start with `FLAME_`.
+pub fn check_task_env(env: &HashMap<String, String>) -> Result<(), FlameError> {
+    if env.len() > MAX_TASK_ENV {
+        return Err(FlameError::invalid_argument(
+            "env",
+            format!("{} variables, expect at most {}", env.len(), MAX_TASK_ENV),
+        ));
+    }
+    let size: usize = env
+        .iter()
+        .map(|(name, value)| name.len() + value.len())
+        .sum();
+    if size > MAX_TASK_ENV_BYTES {
+        return Err(FlameError::invalid_argument(
+            "env",
+            format!("{} bytes, expect at most {}", size, MAX_TASK_ENV_BYTES),
+        ));
+    }
+
+    for (name, value) in env {
+        let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(FlameError::invalid_argument(
+                "env",
+                format!("invalid name <{}>, expect letters, digits and '_'", name),
+            ));
+        }
+        if name.to_ascii_uppercase().starts_with(RESERVED_ENV_PREFIX) {
+            return Err(FlameError::invalid_argument(
+                "env",
+                format!("<{}> is reserved by Flame", name),
+            ));
+        }
+        if value.contains('\0') {
+            return Err(FlameError::invalid_argument(
+                "env",
+                format!("the value of <{}> contains NUL", name),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Merges the annotations into the current ones, where an empty value removes its key; the
+/// merged ones are rejected if their keys and values exceed `MAX_ANNOTATIONS_BYTES`.
+pub fn merge_annotations(
+    field: &str,
+    current: &HashMap<String, String>,
+    update: &HashMap<String, String>,
+) -> Result<HashMap<String, String>, FlameError> {
+    let mut merged = current.clone();
+    for (key, value) in update {
+        if key.is_empty() {
+            return Err(FlameError::invalid_argument(field, "empty key"));
+        }
+        match value.is_empty() {
+            true => merged.remove(key),
+            false => merged.insert(key.clone(), value.clone()),
+        };
+    }
+
+    let size: usize = merged
+        .iter()
+        .map(|(key, value)| key.len() + value.len())
+        .sum();
+    if size > MAX_ANNOTATIONS_BYTES {
+        return Err(FlameError::invalid_argument(
+            field,
+            format!("{} bytes, expect at most {}", size, MAX_ANNOTATIONS_BYTES),
+        ));
+    }
+
+    Ok(merged)
+}
+
+pub fn parse_task_id(id: &str) -> Result<TaskID, FlameError> {
+    id.parse::<TaskID>()
+        .map_err(|_| FlameError::invalid_argument("task_id", format!("invalid task id <{}>", id)))
+}
+
+impl FromStr for TaskGID {
+    type Err = FlameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ssn_id, task_id) = s.split_once('/').ok_or_else(|| {
+            FlameError::invalid_argument(
+                "task_id",
+                format!("invalid task id <{}>, expect <ssn/task>", s),
+            )
+        })?;
+
+        TaskGID::parse(ssn_id, task_id)
+    }
+}
+
+impl serde::Serialize for TaskGID {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for TaskGID {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serializes the optional bytes as a base64 string.
+pub mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use bytes::Bytes;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        data: &Option<Bytes>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match data {
+            Some(data) => serializer.serialize_str(&STANDARD.encode(data)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Bytes>, D::Error> {
+        let data = Option::<String>::deserialize(deserializer)?;
+        data.map(|s| STANDARD.decode(s).map(Bytes::from))
+            .transpose()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
 fn default_work_dir() -> String {
     String::from("/tmp")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_gid_round_trip() {
+        let gid = TaskGID {
+            ssn_id: 12,
+            task_id: 3,
+        };
+        assert_eq!(gid.to_string(), "12/3");
+        assert_eq!("12/3".parse::<TaskGID>().unwrap(), gid);
+        assert_eq!(TaskGID::parse("12", "3").unwrap(), gid);
+
+        let json = serde_json::to_string(&gid).unwrap();
+        assert_eq!(json, "\"12/3\"");
+        assert_eq!(serde_json::from_str::<TaskGID>(&json).unwrap(), gid);
+
+        let mut gids = [
+            "2/1".parse::<TaskGID>().unwrap(),
+            "1/10".parse().unwrap(),
+            "1/2".parse().unwrap(),
+        ];
+        gids.sort();
+        let gids: Vec<String> = gids.iter().map(TaskGID::to_string).collect();
+        assert_eq!(gids, vec!["1/2", "1/10", "2/1"]);
+    }
+
+    #[test]
+    fn test_task_gid_parse_failure() {
+        for s in ["12", "12/", "/3", "a/3", "12/b", "12/3/4", ""] {
+            let e = s.parse::<TaskGID>().unwrap_err();
+            assert!(
+                matches!(e, FlameError::InvalidArgument { .. }),
+                "{}: {:?}",
+                s,
+                e
+            );
+        }
+
+        let e = TaskGID::parse("12", "x").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "invalid argument <task_id>: invalid task id <x>"
+        );
+        let e = TaskGID::parse("y", "3").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "invalid argument <session_id>: invalid session id <y>"
+        );
+
+        assert!(serde_json::from_str::<TaskGID>("\"12\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_namespace() {
+        assert_eq!(parse_namespace("").unwrap(), DEFAULT_NAMESPACE);
+        for ns in ["team-a", "a", "0x1"] {
+            assert_eq!(parse_namespace(ns).unwrap(), ns);
+        }
+
+        let long = "a".repeat(MAX_NAMESPACE_LEN + 1);
+        for ns in ["Team", "team_a", "-a", "a-", "a/b", long.as_str()] {
+            let e = parse_namespace(ns).unwrap_err();
+            assert!(
+                matches!(e, FlameError::InvalidArgument { ref field, .. } if field == "namespace"),
+                "{}: {:?}",
+                ns,
+                e
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_config() {
+        assert_eq!(config_env_name("model-version"), "FLAME_SSN_MODEL_VERSION");
+        assert_eq!(config_env_name("output.bucket"), "FLAME_SSN_OUTPUT_BUCKET");
+        assert_eq!(config_env_name("3d mode"), "FLAME_SSN_3D_MODE");
+
+        let config = HashMap::from([
+            ("model_version".to_string(), "v2".to_string()),
+            ("output-bucket".to_string(), "s3://out".to_string()),
+        ]);
+        assert_eq!(parse_config(config.clone()).unwrap(), config);
+
+        for keys in [vec!["model-version", "MODEL_VERSION"], vec![""]] {
+            let config = keys
+                .into_iter()
+                .map(|k| (k.to_string(), String::new()))
+                .collect();
+            let e = parse_config(config).unwrap_err();
+            assert!(
+                matches!(e, FlameError::InvalidArgument { ref field, .. } if field == "config"),
+                "{:?}",
+                e
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_task_env() {
+        let env = |vars: &[(&str, &str)]| -> HashMap<String, String> {
+            vars.iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+        assert!(check_task_env(&env(&[])).is_ok());
+        assert!(check_task_env(&env(&[("RANDOM_SEED", "42"), ("_out", "/tmp/1")])).is_ok());
+
+        let too_many: Vec<_> = (0..=MAX_TASK_ENV)
+            .map(|i| (format!("VAR_{}", i), String::new()))
+            .collect();
+        let too_large = "x".repeat(MAX_TASK_ENV_BYTES);
+        for env in [
+            env(&[("1ST", "a")]),
+            env(&[("OUTPUT-PATH", "a")]),
+            env(&[("", "a")]),
+            env(&[("FLAME_TASK_ID", "1")]),
+            env(&[("flame_ssn_model", "v1")]),
+            env(&[("SEED", "4\x002")]),
+            env(&[("SEED", too_large.as_str())]),
+            too_many.into_iter().collect(),
+        ] {
+            let e = check_task_env(&env).unwrap_err();
+            assert!(
+                matches!(e, FlameError::InvalidArgument { ref field, .. } if field == "env"),
+                "{:?}",
+                e
+            );
+        }
+    }
+
+    #[test]
+    fn test_merge_annotations() {
+        let annotations = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        let current = annotations(&[("rows", "100"), ("score", "0.9")]);
+
+        // The values are replaced, and the empty ones remove their keys.
+        let merged = merge_annotations(
+            "annotations",
+            &current,
+            &annotations(&[("rows", "200"), ("score", ""), ("model", "v2")]),
+        )
+        .unwrap();
+        assert_eq!(merged, annotations(&[("rows", "200"), ("model", "v2")]));
+
+        // The size is of the merged annotations.
+        let half = "x".repeat(MAX_ANNOTATIONS_BYTES / 2);
+        let current = annotations(&[("a", half.as_str())]);
+        let e = merge_annotations("annotations", &current, &annotations(&[("b", &half)]));
+        assert!(
+            matches!(e, Err(FlameError::InvalidArgument { ref field, .. }) if field == "annotations"),
+            "{:?}",
+            e
+        );
+        let replaced = merge_annotations("annotations", &current, &annotations(&[("a", &half)]));
+        assert_eq!(replaced.unwrap(), current);
+        assert!(merge_annotations("annotations", &current, &annotations(&[("", "1")])).is_err());
+    }
+
+    fn timestamp(secs: i64) -> DateTime<Utc> {
+        DateTime::<Utc>::from_timestamp(secs, 0).unwrap()
+    }
+
+    fn task() -> Task {
+        Task {
+            id: 3,
+            ssn_id: 12,
+            index: 2,
+            input: Some(TaskInput::from("pi")),
+            output: None,
+            trace_context: None,
+            labels: HashMap::from([("zone".to_string(), "a".to_string())]),
+            locality: None,
+            env: HashMap::new(),
+            timeout: None,
+            max_retries: None,
+            progress: None,
+            failure: None,
+            executor_id: None,
+            hostname: None,
+            original_task_id: None,
+            idempotency_key: None,
+            annotations: HashMap::new(),
+            output_chunks: 0,
+            spilled: false,
+            creation_time: timestamp(1_700_000_000),
+            completion_time: Some(timestamp(1_700_000_060)),
+            state: TaskState::Succeed,
+        }
+    }
+
+    fn session() -> Session {
+        let mut ssn = Session {
+            id: 12,
+            namespace: "team-a".to_string(),
+            application: "flmexec".to_string(),
+            slots: 2,
+            labels: HashMap::from([("env".to_string(), "dev".to_string())]),
+            common_data: Some(CommonData::from(vec![0x00, 0xff])),
+            creation_time: timestamp(1_700_000_000),
+            ..Default::default()
+        };
+        ssn.update_task(&task());
+
+        ssn
+    }
+
+    fn executor() -> Executor {
+        Executor {
+            id: "exe-1".to_string(),
+            slots: 1,
+            applications: vec![Application {
+                name: "flmexec".to_string(),
+                shim: Shim::Stdio,
+                command: "/usr/bin/flmexec".to_string(),
+                ..Default::default()
+            }],
+            task_id: Some(3),
+            ssn_id: Some(12),
+            labels: HashMap::new(),
+            locality_keys: vec![],
+            host: HostInfo::default(),
+            creation_time: timestamp(1_700_000_000),
+            last_heartbeat: timestamp(1_700_000_030),
+            state: ExecutorState::Bound,
+            draining: false,
+            quarantined: false,
+            generation: 1,
+        }
+    }
+
+    #[test]
+    fn test_backlog() {
+        let mut ssn = session();
+        assert_eq!(ssn.backlog(), 0);
+
+        let mut task = task();
+        for (id, state) in [(4, TaskState::Pending), (5, TaskState::Running)] {
+            task.id = id;
+            task.state = state;
+            ssn.update_task(&task);
+        }
+        assert_eq!(ssn.backlog(), 2);
+
+        // The task is moved out of the index of its previous state.
+        task.state = TaskState::Succeed;
+        ssn.update_task(&task);
+        assert_eq!(ssn.backlog(), 1);
+        assert_eq!(ssn.tasks_index[&TaskState::Running].len(), 0);
+        assert_eq!(ssn.tasks_index[&TaskState::Succeed].len(), 2);
+    }
+
+    #[test]
+    fn test_task_by_key() {
+        let mut ssn = session();
+        let mut task = task();
+        task.id = 4;
+        ssn.update_task(&task);
+        assert_eq!(ssn.task_by_key("a.mp4"), None);
+
+        task.id = 5;
+        task.idempotency_key = Some("a.mp4".to_string());
+        ssn.update_task(&task);
+        task.state = TaskState::Succeed;
+        ssn.update_task(&task);
+        assert_eq!(ssn.task_by_key("a.mp4"), Some(5));
+        assert_eq!(ssn.clone().task_by_key("a.mp4"), Some(5));
+        assert_eq!(ssn.summary().task_by_key("a.mp4"), None);
+    }
+
+    #[test]
+    fn test_session_summary() {
+        let mut ssn = session();
+        let mut task = task();
+        for id in 100..1100 {
+            task.id = id;
+            task.state = match id % 2 {
+                0 => TaskState::Pending,
+                _ => TaskState::Failed,
+            };
+            ssn.update_task(&task);
+        }
+        let mut others = HashSet::new();
+        others.insert(100);
+        assert!(ssn.pop_pending_task(None, &others, |_| true).is_some());
+
+        // The summary has the counts of the tasks, but not the tasks.
+        let summary = ssn.summary();
+        assert!(summary.tasks.is_empty() && summary.tasks_index.is_empty());
+        assert_eq!(summary.status.counts, ssn.status.counts);
+        assert_eq!(summary.pending(), 499);
+
+        // The clone has the tasks as well.
+        assert_eq!(ssn.clone().tasks.len(), ssn.tasks.len());
+
+        let status = rpc::Session::from(&summary).status.unwrap();
+        assert_eq!((status.pending, status.failed), (499, 500));
+        assert_eq!(status.succeed, ssn.status.counts.succeed);
+    }
+
+    #[test]
+    fn test_task_progress() {
+        let mut task = task();
+        task.progress = Some(TaskProgress {
+            percentage: 25,
+            payload: Some(Message::from("step 1/4")),
+            update_time: timestamp(1_700_000_030),
+        });
+        let t = Task::try_from(rpc::Task::from(&task)).unwrap();
+        assert_eq!(t.progress, task.progress);
+
+        let progress = |percentage, size| rpc::TaskProgress {
+            percentage,
+            payload: Some(vec![0; size]),
+            update_time: 0,
+        };
+        assert!(TaskProgress::try_from(progress(100, MAX_PROGRESS_PAYLOAD)).is_ok());
+        for (p, field) in [
+            (progress(101, 0), "percentage"),
+            (progress(50, MAX_PROGRESS_PAYLOAD + 1), "payload"),
+        ] {
+            let e = TaskProgress::try_from(p).unwrap_err();
+            assert!(
+                matches!(e, FlameError::InvalidArgument { field: ref f, .. } if f == field),
+                "{:?}",
+                e
+            );
+        }
+
+        // The progress is not persisted with the task.
+        let json = serde_json::to_string(&task).unwrap();
+        let t: Task = serde_json::from_str(&json).unwrap();
+        assert_eq!(t.progress, None);
+    }
+
+    #[test]
+    fn test_session_event_round_trip() {
+        let progress = TaskProgress {
+            percentage: 25,
+            payload: Some(Message::from("step 1/4")),
+            update_time: timestamp(1_700_000_030),
+        };
+        for kind in [
+            EventKind::TaskStateChanged {
+                task_id: 3,
+                state: TaskState::Running,
+            },
+            EventKind::TaskProgressed {
+                task_id: 3,
+                progress,
+            },
+            EventKind::SessionExpired {
+                deadline: timestamp(1_700_000_060),
+            },
+            EventKind::SessionOrphaned {
+                last_activity: timestamp(1_700_000_000),
+            },
+            EventKind::ExecutorQuarantined {
+                executor_id: "exec-1".to_string(),
+                task_id: 3,
+            },
+            EventKind::SessionClosed,
+        ] {
+            let event = SessionEvent {
+                sequence: 7,
+                event_time: timestamp(1_700_000_090),
+                kind,
+            };
+            let e = SessionEvent::try_from(rpc::SessionEvent::from(&event)).unwrap();
+            assert_eq!(e, event);
+        }
+
+        let e = SessionEvent::try_from(rpc::SessionEvent::default()).unwrap_err();
+        assert!(matches!(e, FlameError::InvalidArgument { .. }), "{:?}", e);
+    }
+
+    #[test]
+    fn test_task_failure() {
+        let mut task = task();
+        task.failure = Some(TaskFailure {
+            reason: FailureReason::OomKilled,
+            message: "killed by signal 9".to_string(),
+        });
+        let t = Task::try_from(rpc::Task::from(&task)).unwrap();
+        assert_eq!(t.failure, task.failure);
+
+        let mut t = rpc::Task::from(&task);
+        t.status.as_mut().unwrap().failure.as_mut().unwrap().reason = 10;
+        assert!(Task::try_from(t).is_err());
+    }
+
+    #[test]
+    fn test_application_defaults_problems() {
+        let mut app = Application {
+            name: "flmexec".to_string(),
+            shim: Shim::Log,
+            default_slots: Some(2),
+            default_task_timeout: Some(60),
+            ..Default::default()
+        };
+        assert!(app.problems().is_empty(), "{:?}", app.problems());
+
+        app.default_slots = Some(0);
+        app.default_task_timeout = Some(0);
+        assert_eq!(
+            app.problems(),
+            vec![
+                "application <flmexec>: default_slots must be greater than 0".to_string(),
+                "application <flmexec>: default_task_timeout must be greater than 0".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_application_input_problems() {
+        let app: Application = serde_yaml::from_str(
+            r#"
+name: model
+shim: Log
+input:
+  max_bytes: 0
+  required_fields: [prompt, ""]
+  schema: "{not json"
+"#,
+        )
+        .unwrap();
+
+        let problems = app.problems();
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert_eq!(
+            problems[..2],
+            [
+                "application <model>: input.max_bytes must be greater than 0".to_string(),
+                "application <model>: empty field in input.required_fields".to_string(),
+            ]
+        );
+        assert!(problems[2].starts_with("application <model>: input.schema is not JSON"));
+
+        // The constraints survive the round trip of the rpc.
+        let input = app.input.clone().unwrap();
+        assert!(!input.strict);
+        let app = Application::from(rpc::Application::from(&app));
+        assert_eq!(app.input, Some(input));
+    }
+
+    #[test]
+    fn test_application_fingerprint() {
+        let labels = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>()
+        };
+        let app = Application {
+            name: "model".to_string(),
+            shim: Shim::Stdio,
+            command: "/bin/serve".to_string(),
+            arguments: vec!["--model".to_string(), "/models/v1".to_string()],
+            default_labels: labels(&[("zone", "a"), ("gpu", "a100"), ("tier", "1")]),
+            ..Default::default()
+        };
+        let fingerprint = app.fingerprint();
+        assert_eq!(fingerprint.len(), 64);
+
+        // The labels are hashed by their keys, not by the order of the map.
+        let same = Application {
+            default_labels: labels(&[("tier", "1"), ("gpu", "a100"), ("zone", "a")]),
+            ..app.clone()
+        };
+        assert_eq!(same.fingerprint(), fingerprint);
+
+        let changed = Application {
+            arguments: vec!["--model".to_string(), "/models/v2".to_string()],
+            ..app.clone()
+        };
+        assert_ne!(changed.fingerprint(), fingerprint);
+        // The fields are hashed with their lengths, so the bytes can't move between them.
+        let moved = Application {
+            arguments: vec!["--model/models/v1".to_string()],
+            ..app.clone()
+        };
+        assert_ne!(moved.fingerprint(), fingerprint);
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let task = task();
+        let json = serde_json::to_string(&task).unwrap();
+        let t: Task = serde_json::from_str(&json).unwrap();
+        assert_eq!(t.gid(), task.gid());
+        assert_eq!(t.input, task.input);
+        assert_eq!(t.output, None);
+        assert_eq!(t.creation_time, task.creation_time);
+        assert_eq!(t.completion_time, task.completion_time);
+        assert_eq!(t.state, task.state);
+
+        let ssn = session();
+        let json = serde_json::to_string(&ssn).unwrap();
+        let s: Session = serde_json::from_str(&json).unwrap();
+        assert_eq!(s.id, ssn.id);
+        assert_eq!(s.labels, ssn.labels);
+        assert_eq!(s.common_data, ssn.common_data);
+        assert_eq!(s.status.state, ssn.status.state);
+        assert!(s.tasks.is_empty());
+
+        let exe = executor();
+        let json = serde_json::to_string(&exe).unwrap();
+        let e: Executor = serde_json::from_str(&json).unwrap();
+        assert_eq!(e.id, exe.id);
+        assert_eq!(e.applications[0].name, "flmexec");
+        assert_eq!(e.state, exe.state);
+        assert_eq!(e.last_heartbeat, exe.last_heartbeat);
+
+        for state in [
+            TaskState::Pending,
+            TaskState::Running,
+            TaskState::Succeed,
+            TaskState::Failed,
+        ] {
+            let json = serde_json::to_string(&state).unwrap();
+            assert_eq!(json, format!("\"{}\"", state.to_string().to_lowercase()));
+            assert_eq!(serde_json::from_str::<TaskState>(&json).unwrap(), state);
+        }
+
+        for reason in [
+            FailureReason::ShimError,
+            FailureReason::Timeout,
+            FailureReason::Aborted,
+            FailureReason::ExecutorLost,
+            FailureReason::OomKilled,
+        ] {
+            let json = serde_json::to_string(&reason).unwrap();
+            assert_eq!(json, format!("\"{}\"", reason.to_string().to_lowercase()));
+            assert_eq!(
+                serde_json::from_str::<FailureReason>(&json).unwrap(),
+                reason
+            );
+        }
+
+        for scope in [
+            CacheScope::None,
+            CacheScope::Session,
+            CacheScope::Application,
+        ] {
+            let json = serde_json::to_string(&scope).unwrap();
+            assert_eq!(json, format!("\"{}\"", scope.to_string().to_lowercase()));
+            assert_eq!(serde_json::from_str::<CacheScope>(&json).unwrap(), scope);
+        }
+
+        assert!(serde_json::from_str::<SessionState>("0").is_err());
+        assert!(serde_json::from_str::<Task>(r#"{"input": "!"}"#).is_err());
+    }
+
+    #[test]
+    fn test_serde_snapshot() {
+        insta::assert_json_snapshot!(task(), @r#"
+        {
+          "id": 3,
+          "ssn_id": 12,
+          "index": 2,
+          "input": "cGk=",
+          "output": null,
+          "labels": {
+            "zone": "a"
+          },
+          "creation_time": "2023-11-14T22:13:20Z",
+          "completion_time": "2023-11-14T22:14:20Z",
+          "state": "succeed"
+        }
+        "#);
+        let failed = Task {
+            failure: Some(TaskFailure {
+                reason: FailureReason::OomKilled,
+                message: "killed by signal 9".to_string(),
+            }),
+            state: TaskState::Failed,
+            ..task()
+        };
+        insta::assert_json_snapshot!(failed, @r#"
+        {
+          "id": 3,
+          "ssn_id": 12,
+          "index": 2,
+          "input": "cGk=",
+          "output": null,
+          "labels": {
+            "zone": "a"
+          },
+          "failure": {
+            "reason": "oomkilled",
+            "message": "killed by signal 9"
+          },
+          "creation_time": "2023-11-14T22:13:20Z",
+          "completion_time": "2023-11-14T22:14:20Z",
+          "state": "failed"
+        }
+        "#);
+        insta::assert_json_snapshot!(session(), @r#"
+        {
+          "id": 12,
+          "namespace": "team-a",
+          "application": "flmexec",
+          "slots": 2,
+          "labels": {
+            "env": "dev"
+          },
+          "common_data": "AP8=",
+          "max_pending_tasks": null,
+          "cache_scope": "none",
+          "creation_time": "2023-11-14T22:13:20Z",
+          "completion_time": null,
+          "status": {
+            "state": "open"
+          }
+        }
+        "#);
+        insta::assert_json_snapshot!(executor(), @r#"
+        {
+          "id": "exe-1",
+          "slots": 1,
+          "applications": [
+            {
+              "name": "flmexec",
+              "shim": "Stdio",
+              "command": "/usr/bin/flmexec",
+              "arguments": [],
+              "environments": [],
+              "working_directory": ""
+            }
+          ],
+          "task_id": 3,
+          "ssn_id": 12,
+          "labels": {},
+          "host": {
+            "hostname": "",
+            "ip": "",
+            "os": "",
+            "arch": "",
+            "version": ""
+          },
+          "creation_time": "2023-11-14T22:13:20Z",
+          "last_heartbeat": "2023-11-14T22:13:50Z",
+          "state": "bound",
+          "draining": false,
+          "quarantined": false,
+          "generation": 1
+        }
+        "#);
+    }
+}
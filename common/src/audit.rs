@@ -0,0 +1,264 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The audit log of the mutating API calls. Each entry is a line of JSON in the file, e.g.
+//!
+//! ```text
+//! {"timestamp":"2024-07-01T08:00:00Z","method":"CloseSession","caller":"anonymous","targets":{"session_id":"1"},"outcome":{"status":"ok"}}
+//! ```
+//!
+//! and the file is rotated to `<path>.1`, `<path>.2`, ... when it exceeds the maximum size.
+//! The fields are a contract with the tools reading the log, e.g. `flmctl audit tail`, so
+//! only add optional fields.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::ctx::FlameAuditConf;
+use crate::FlameError;
+
+/// The caller of the API calls before the authentication is enabled.
+pub const ANONYMOUS: &str = "anonymous";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub method: String,
+    pub caller: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub targets: BTreeMap<String, String>,
+    pub outcome: AuditOutcome,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Ok,
+    Failed { code: String, message: String },
+}
+
+pub type AuditLogPtr = Arc<AuditLog>;
+
+/// The append-only writer of the audit log; the failures of writing are logged instead of
+/// returned, so they never fail the API calls.
+pub struct AuditLog {
+    path: PathBuf,
+    max_size: u64,
+    max_files: u32,
+    file: Mutex<Option<(File, u64)>>,
+    failures: AtomicU64,
+}
+
+impl AuditLog {
+    pub fn new(conf: &FlameAuditConf) -> Result<Self, FlameError> {
+        Ok(AuditLog {
+            path: PathBuf::from(&conf.path),
+            max_size: conf.max_size()?,
+            max_files: conf.max_files(),
+            file: Mutex::new(None),
+            failures: AtomicU64::new(0),
+        })
+    }
+
+    pub fn record(&self, entry: &AuditEntry) {
+        if let Err(e) = self.write(entry) {
+            let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+            log::warn!(
+                "Failed to write audit entry of <{}> to <{}> ({} failures): {}",
+                entry.method,
+                self.path.display(),
+                failures,
+                e
+            );
+        }
+    }
+
+    /// The number of the entries which failed to be written.
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    fn write(&self, entry: &AuditEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| io::Error::other("audit log is poisoned"))?;
+
+        if file.is_none() {
+            *file = Some(self.open()?);
+        }
+
+        if let Some((_, size)) = file.as_ref() {
+            if *size > 0 && size + line.len() as u64 > self.max_size {
+                *file = None;
+                self.rotate()?;
+                *file = Some(self.open()?);
+            }
+        }
+
+        if let Some((f, size)) = file.as_mut() {
+            // Reopen the file on the next write if it failed, e.g. the file was removed.
+            if let Err(e) = f.write_all(&line) {
+                *file = None;
+                return Err(e);
+            }
+            *size += line.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    /// Opens the file for appending, and returns it with its current size.
+    fn open(&self) -> io::Result<(File, u64)> {
+        let f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let size = f.metadata()?.len();
+
+        Ok((f, size))
+    }
+
+    /// Shifts `<path>.n` to `<path>.n+1` and `<path>` to `<path>.1`; the oldest one is removed.
+    fn rotate(&self) -> io::Result<()> {
+        let rotated = |n: u32| PathBuf::from(format!("{}.{}", self.path.display(), n));
+
+        if self.max_files == 0 {
+            return fs::remove_file(&self.path);
+        }
+
+        for n in (1..self.max_files).rev() {
+            if rotated(n).exists() {
+                fs::rename(rotated(n), rotated(n + 1))?;
+            }
+        }
+
+        fs::rename(&self.path, rotated(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn entry(method: &str, ssn_id: &str, outcome: AuditOutcome) -> AuditEntry {
+        AuditEntry {
+            timestamp: Utc.with_ymd_and_hms(2024, 7, 1, 8, 0, 0).unwrap(),
+            method: method.to_string(),
+            caller: ANONYMOUS.to_string(),
+            peer: Some("127.0.0.1:50051".to_string()),
+            targets: BTreeMap::from([("session_id".to_string(), ssn_id.to_string())]),
+            outcome,
+        }
+    }
+
+    fn audit_log(name: &str, max_size: &str, max_files: u32) -> AuditLog {
+        let dir = std::env::temp_dir().join(format!("flame-audit-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        AuditLog::new(&FlameAuditConf {
+            path: dir.join("audit.jsonl").to_string_lossy().to_string(),
+            max_size: Some(max_size.to_string()),
+            max_files: Some(max_files),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_format() {
+        let log = audit_log("format", "1m", 1);
+        log.record(&entry("CloseSession", "1", AuditOutcome::Ok));
+        log.record(&entry(
+            "DeleteSession",
+            "2",
+            AuditOutcome::Failed {
+                code: "FailedPrecondition".to_string(),
+                message: "session <2> is not closed".to_string(),
+            },
+        ));
+
+        let contents = fs::read_to_string(&log.path).unwrap();
+        assert_eq!(
+            contents,
+            concat!(
+                r#"{"timestamp":"2024-07-01T08:00:00Z","method":"CloseSession","caller":"anonymous","peer":"127.0.0.1:50051","targets":{"session_id":"1"},"outcome":{"status":"ok"}}"#,
+                "\n",
+                r#"{"timestamp":"2024-07-01T08:00:00Z","method":"DeleteSession","caller":"anonymous","peer":"127.0.0.1:50051","targets":{"session_id":"2"},"outcome":{"status":"failed","code":"FailedPrecondition","message":"session <2> is not closed"}}"#,
+                "\n",
+            )
+        );
+
+        for line in contents.lines() {
+            let e: AuditEntry = serde_json::from_str(line).unwrap();
+            assert_eq!(serde_json::to_string(&e).unwrap(), line);
+        }
+    }
+
+    #[test]
+    fn test_rotate() {
+        let line_len = serde_json::to_vec(&entry("CloseSession", "1", AuditOutcome::Ok))
+            .unwrap()
+            .len() as u64
+            + 1;
+        // Two entries per file.
+        let log = audit_log("rotate", &(line_len * 2).to_string(), 2);
+
+        for id in 1..=7 {
+            log.record(&entry("CloseSession", &id.to_string(), AuditOutcome::Ok));
+        }
+        assert_eq!(log.failures(), 0);
+
+        let ids = |suffix: &str| -> Vec<String> {
+            let path = format!("{}{}", log.path.display(), suffix);
+            fs::read_to_string(path)
+                .unwrap()
+                .lines()
+                .map(|l| {
+                    serde_json::from_str::<AuditEntry>(l).unwrap().targets["session_id"].clone()
+                })
+                .collect()
+        };
+        assert_eq!(ids(""), vec!["7"]);
+        assert_eq!(ids(".1"), vec!["5", "6"]);
+        assert_eq!(ids(".2"), vec!["3", "4"]);
+        assert!(!PathBuf::from(format!("{}.3", log.path.display())).exists());
+    }
+
+    #[test]
+    fn test_write_failure() {
+        let log = AuditLog::new(&FlameAuditConf {
+            path: "/nonexistent/flame/audit.jsonl".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        log.record(&entry("CloseSession", "1", AuditOutcome::Ok));
+        log.record(&entry("CloseSession", "2", AuditOutcome::Ok));
+        assert_eq!(log.failures(), 2);
+    }
+}
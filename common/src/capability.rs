@@ -0,0 +1,115 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The capabilities of the session manager, which are reported by `GetServerInfo`; the
+//! clients check them instead of probing the RPCs with failed calls. The tokens are a
+//! contract with the released clients, so never rename or reuse them.
+
+/// `WatchSession` streams the events of a session.
+pub const WATCH_SESSION: &str = "watch-session";
+/// `RegisterApplication`, `ListApplication` and `DeleteApplication`.
+pub const APPLICATION_REGISTRY: &str = "application-registry";
+/// `ListExecutor`, `GetExecutor` and `DrainExecutor`.
+pub const EXECUTOR_API: &str = "executor-api";
+/// The sessions are isolated by namespaces.
+pub const NAMESPACES: &str = "namespaces";
+/// The `Admin` service, e.g. pause the scheduling.
+pub const ADMIN: &str = "admin";
+/// `ExportSession` and `ImportSession`.
+pub const SESSION_ARCHIVE: &str = "session-archive";
+/// The outputs of the tasks are cached by the `cache_scope` of the sessions.
+pub const TASK_CACHE: &str = "task-cache";
+/// The sessions are closed at their deadlines, which are extended by `UpdateSession`.
+pub const SESSION_DEADLINE: &str = "session-deadline";
+/// `GetClusterStats` of the `Admin` service, i.e. the health of the queue.
+pub const CLUSTER_STATS: &str = "cluster-stats";
+/// `ListSessionTemplate`, and the sessions are created by the templates in the configuration.
+pub const SESSION_TEMPLATES: &str = "session-templates";
+/// The tasks have the indexes of their creation in the session, and `ListTask` lists them
+/// in that order.
+pub const TASK_INDEX: &str = "task-index";
+/// The executors failing too many tasks are quarantined, and `UncordonExecutor` restores them.
+pub const EXECUTOR_QUARANTINE: &str = "executor-quarantine";
+/// `ResubmitTask`, and `ListTask` filters the tasks by their state.
+pub const TASK_RESUBMIT: &str = "task-resubmit";
+/// The sessions have the config delivered to the executors, e.g. `FLAME_SSN_<KEY>`.
+pub const SESSION_CONFIG: &str = "session-config";
+/// `GetSessionEvents` returns the history of the events of a session, which is persisted.
+pub const SESSION_HISTORY: &str = "session-history";
+/// `FetchTaskOutputs` streams the outputs of the tasks of a session.
+pub const TASK_OUTPUTS: &str = "task-outputs";
+/// The tasks have timeouts and retries, which default to the ones of their sessions, and
+/// then of their applications.
+pub const TASK_SETTINGS: &str = "task-settings";
+/// `CloneSession` creates a session with the settings, and optionally the tasks, of another.
+pub const SESSION_CLONE: &str = "session-clone";
+/// `GetEffectiveConfig` of the `Admin` service, i.e. the configuration the server runs with.
+pub const EFFECTIVE_CONFIG: &str = "effective-config";
+/// `CreateTaskStream` creates a task with its input sent in chunks, e.g. the large ones.
+pub const TASK_UPLOAD: &str = "task-upload";
+/// The sessions reference the priority classes in the configuration, which have executors
+/// reserved for them.
+pub const PRIORITY_CLASSES: &str = "priority-classes";
+/// The sessions record their applications when they're created, which are run by their
+/// executors unless `latest_application`.
+pub const APPLICATION_SNAPSHOT: &str = "application-snapshot";
+/// `GetAllocationReport` of the `Admin` service, i.e. the fair shares of the applications
+/// and the namespaces.
+pub const ALLOCATION_REPORT: &str = "allocation-report";
+/// The executors set the annotations of the tasks and their sessions when they complete the
+/// tasks, and `ListTask` filters the tasks by the keys of their annotations.
+pub const TASK_ANNOTATIONS: &str = "task-annotations";
+
+/// The tasks stream the chunks of their outputs while they're running, which are read by
+/// `FetchTaskOutputChunks` and notified by `WatchTask` with `include_chunks`.
+pub const OUTPUT_CHUNKS: &str = "output-chunks";
+/// `ListSession` filters the sessions by their state, and `ListExecutor` returns the
+/// executors in pages.
+pub const LIST_PAGES: &str = "list-pages";
+/// The tasks are created with the idempotency keys of their clients, and creating a task with
+/// the key of an existing task of the session returns that task.
+pub const TASK_IDEMPOTENCY: &str = "task-idempotency";
+/// The sessions limit their running tasks by `max_running_tasks`.
+pub const SESSION_MAX_RUNNING: &str = "session-max-running";
+
+/// The capabilities of this version.
+pub const ALL: &[&str] = &[
+    WATCH_SESSION,
+    APPLICATION_REGISTRY,
+    EXECUTOR_API,
+    NAMESPACES,
+    ADMIN,
+    SESSION_ARCHIVE,
+    TASK_CACHE,
+    SESSION_DEADLINE,
+    CLUSTER_STATS,
+    SESSION_TEMPLATES,
+    TASK_INDEX,
+    EXECUTOR_QUARANTINE,
+    TASK_RESUBMIT,
+    SESSION_CONFIG,
+    SESSION_HISTORY,
+    TASK_OUTPUTS,
+    TASK_SETTINGS,
+    SESSION_CLONE,
+    EFFECTIVE_CONFIG,
+    TASK_UPLOAD,
+    PRIORITY_CLASSES,
+    APPLICATION_SNAPSHOT,
+    ALLOCATION_REPORT,
+    TASK_ANNOTATIONS,
+    OUTPUT_CHUNKS,
+    LIST_PAGES,
+    TASK_IDEMPOTENCY,
+    SESSION_MAX_RUNNING,
+];
@@ -11,35 +11,1553 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::net::SocketAddr;
 use std::path::Path;
+use std::time::Duration;
 
 use serde_derive::{Deserialize, Serialize};
+use url::Url;
 
-use crate::apis::Application;
+use crate::apis::{parse_namespace, Application};
+use crate::endpoint;
+use crate::grpc::{Compression, GrpcOptions, DEFAULT_MAX_MESSAGE_SIZE};
+use crate::resources::{parse_memory, ResourceVector};
 use crate::FlameError;
 
+/// The placeholder of the secrets in the redacted configuration.
+pub const REDACTED: &str = "******";
+const SECRET_KEYS: [&str; 4] = ["PASSWORD", "SECRET", "TOKEN", "KEY"];
+
 const DEFAULT_FLAME_CONF: &str = "flame-conf.yaml";
+const FLAME_CONF_ENV: &str = "FLAME_CONF";
+/// The prefix of the environments overriding the nested fields, e.g. `FLAME__CLIENT__TIMEOUT`.
+const NESTED_ENV_PREFIX: &str = "FLAME__";
+const NESTED_ENV_SEPARATOR: &str = "__";
+/// The environments overriding the top-level fields; they take precedence over the nested ones.
+const ENV_OVERRIDES: [(&str, &str); 4] = [
+    ("FLAME_ENDPOINT", "endpoint"),
+    ("FLAME_STORAGE", "storage"),
+    ("FLAME_POLICY", "policy"),
+    ("FLAME_SLOT", "slot"),
+];
 const DEFAULT_CONTEXT_NAME: &str = "flame";
 const DEFAULT_FLAME_ENDPOINT: &str = "http://127.0.0.1:8080";
 const DEFAULT_SLOT: &str = "cpu=1,mem=2g";
 const DEFAULT_POLICY: &str = "proportion";
 const DEFAULT_STORAGE: &str = "sqlite://flame.db";
+const DEFAULT_AUDIT_MAX_SIZE: u64 = 100 << 20;
+const DEFAULT_AUDIT_MAX_FILES: u32 = 5;
+const DEFAULT_SOCKET_MODE: u32 = 0o600;
+const DEFAULT_CACHE_MAX_ENTRIES: usize = 10_000;
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const DEFAULT_WARM_POOL_MAX_SIZE: usize = 1;
+const DEFAULT_WARM_POOL_TTL: Duration = Duration::from_secs(10 * 60);
+const DEFAULT_SCALE_UP_AFTER: Duration = Duration::from_secs(30);
+const DEFAULT_SCALE_DOWN_AFTER: Duration = Duration::from_secs(10 * 60);
+const DEFAULT_AUTOSCALER_MIN_INTERVAL: Duration = Duration::from_secs(30);
+const DEFAULT_AUTOSCALER_REPEAT_AFTER: Duration = Duration::from_secs(10 * 60);
+const DEFAULT_QUARANTINE_FAILURES: u32 = 5;
+const DEFAULT_PRIORITY_CLASS_WEIGHT: u32 = 1;
+const DEFAULT_QUARANTINE_WINDOW: Duration = Duration::from_secs(10 * 60);
+const DEFAULT_UTILIZATION_WINDOWS: [Duration; 2] =
+    [Duration::from_secs(5 * 60), Duration::from_secs(60 * 60)];
+const DEFAULT_SCHEDULE_INTERVAL: Duration = Duration::from_millis(500);
+const DEFAULT_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_EVALUATE_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_EVICT_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_LEASE: Duration = Duration::from_secs(30);
+const DEFAULT_WRITE_BEHIND_MAX_BATCH: usize = 512;
+const DEFAULT_WRITE_BEHIND_FLUSH_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long a task with a locality waits for an executor having its key, if it's not
+/// configured.
+pub const DEFAULT_LOCALITY_WAIT: Duration = Duration::from_secs(3);
+
+/// The policies known to the scheduler.
+pub const POLICIES: [&str; 3] = ["proportion", "priority", "fairshare"];
+/// The storage schemes known to the engines of the session manager.
+pub const STORAGE_SCHEMES: [&str; 2] = ["sqlite", "memory"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlameContext {
+    pub name: String,
+    /// The endpoint of the session manager, e.g. http://127.0.0.1:8080 or
+    /// unix:///run/flame/flame.sock
+    pub endpoint: String,
+    /// The endpoint of the Backend service for the executor managers, e.g. a unix socket while
+    /// the Frontend is on TCP; it's the same as `endpoint` if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend_endpoint: Option<String>,
+    /// The permissions of the unix sockets created by the session manager, e.g. 0660; 0600 by
+    /// default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socket_mode: Option<String>,
+    pub slot: String,
+    pub policy: String,
+    pub storage: String,
+    /// The maximum number of the pending and running tasks of a session, which could be
+    /// overridden by the session; it's unlimited by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_pending_tasks: Option<u32>,
+    /// The bounds of the cached task outputs of the sessions with a `cache_scope`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<FlameCacheConf>,
+    /// Whether the inputs and outputs of the completed tasks are dropped from the memory of
+    /// the session manager once they're persisted, so the memory does not grow with the
+    /// completed tasks; they're loaded from the storage when requested. It's on by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spill_completed_tasks: Option<bool>,
+    /// The times a task is requeued after its executor failed it or was lost, before the
+    /// task is failed; 3 by default. It's the last default of the precedence: the task, its
+    /// session, and its application, e.g. `default_max_retries`, override it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_task_retries: Option<u32>,
+    /// Whether the sessions which no registered executor could ever run, e.g. their slots
+    /// don't fit any executor, are rejected when they're created; they're only reported as
+    /// unschedulable by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reject_unschedulable: Option<bool>,
+    /// The applications run by the executors; they may be omitted by the executor managers
+    /// using the applications of the session manager, see `FlameExecutorConf`.
+    #[serde(default)]
+    pub applications: Vec<Application>,
+    /// The default namespace of the sessions of flmctl and the clients, e.g. team-a
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// The options of the connections to the session manager, shared by flmctl and clients.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<FlameClientConf>,
+    /// The audit log of the mutating API calls of the session manager; it's off by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit: Option<FlameAuditConf>,
+    /// The exporter of the spans to OpenTelemetry; it's off by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub telemetry: Option<FlameTelemetryConf>,
+    /// The tokens accepted by the session manager; the Frontend is open if it's not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<FlameAuthConf>,
+    /// The options of the gRPC channels, shared by the session manager, the executor managers
+    /// and the clients.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grpc: Option<FlameGrpcConf>,
+    /// The warm shims kept by the executor manager after unbinding, so the next session of
+    /// the same application is bound faster; it's off by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warm_pool: Option<FlameWarmPoolConf>,
+    /// The hook signaling the provisioner of the executors to scale up or down; it's off by
+    /// default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub autoscaler: Option<FlameAutoscalerConf>,
+    /// The executors failing too many tasks are quarantined; it's on with the defaults if
+    /// not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quarantine: Option<FlameQuarantineConf>,
+    /// The slice of the executors bound to a session, after which they're rebound to the
+    /// most underserved session; it's off by default, so an executor is kept by its session
+    /// until the session has no more pending tasks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_slice: Option<FlameTimeSliceConf>,
+    /// The windows of the utilization of the executors; 5m and 1h if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utilization: Option<FlameUtilizationConf>,
+    /// The intervals of the background loops and the lease of the executors; the defaults
+    /// are used if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timings: Option<FlameTimingsConf>,
+    /// The self-monitoring of the session manager, which logs the slow locks and engine
+    /// calls; it's off by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitor: Option<FlameMonitorConf>,
+    /// The retries of the transient failures of the storage engine, e.g. the database is
+    /// busy; the defaults are used if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engine_retry: Option<FlameEngineRetryConf>,
+    /// How the state changes of the tasks are persisted: `strict` writes each change to the
+    /// storage before it's acknowledged, and `batched` writes them behind in batches bounded
+    /// by `write_behind`, so a crash of the session manager loses the changes of the last
+    /// batch. It's strict by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub durability: Option<Durability>,
+    /// The bounds of the batches of `durability: batched`; the defaults are used if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write_behind: Option<FlameWriteBehindConf>,
+    /// The switches for the development environments; all of them are off by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug: Option<FlameDebugConf>,
+    /// The rate limit of the mutating Frontend calls of each caller; the Backend calls of
+    /// the executors are not limited. It's off by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<FlameRateLimitConf>,
+    /// The defaults of the sessions created by the templates, so the clients only give the
+    /// name of the template.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub session_templates: Vec<FlameSessionTemplate>,
+    /// The hard caps of the slots and the running tasks of the sessions in a namespace or of
+    /// an application; the sessions are not capped by default.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quotas: Vec<FlameQuotaConf>,
+    /// The classes of the sessions, which are referenced by name in their specs; the
+    /// executors reserved for a class are lent to the others only while the class has no
+    /// pending work. The sessions have no class by default.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub priority_classes: Vec<FlamePriorityClassConf>,
+    /// The settings of the executor managers, which are usually set by the file of each
+    /// host layered over the shared one; the defaults are used if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executor: Option<FlameExecutorConf>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameExecutorConf {
+    /// The labels of the executor, e.g. `zone: a`; the tasks with labels prefer the executors
+    /// having all of them
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+    /// The slots of the executor, i.e. how many slots of the sessions it runs; 1 by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slots: Option<i32>,
+    /// The root of the working directories of the applications without one, e.g. /scratch;
+    /// each application works in the sub-directory of its name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scratch_root: Option<String>,
+    /// The address of the local metrics and status of the executor manager, e.g.
+    /// 127.0.0.1:9100; it's off by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics_address: Option<String>,
+    /// Whether the executor runs the applications delivered by the session manager, so the
+    /// `applications` may be omitted on the host; off by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_applications: Option<bool>,
+    /// The keys of the data local to the executor, e.g. the shards cached on its host; the
+    /// tasks with these localities are launched on it first, and the localities of the tasks
+    /// it ran are added to them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub locality_keys: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameGrpcConf {
+    /// The maximum size of a sent message, e.g. 16m; 4m by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_send_message_size: Option<String>,
+    /// The maximum size of a received message, e.g. 16m; 4m by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_recv_message_size: Option<String>,
+    /// The compression of the sent messages: none, gzip or zstd; the received messages are
+    /// decompressed by the encoding of the peer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameCacheConf {
+    /// The maximum number of the cached outputs, the oldest ones are evicted; 10000 by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_entries: Option<usize>,
+    /// How long an output is cached, e.g. 1h; 24h by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameWarmPoolConf {
+    /// The maximum number of the warm shims, the oldest ones are evicted; 1 by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<usize>,
+    /// How long a shim is kept warm, e.g. 30m; 10m by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<String>,
+    /// All the warm shims are evicted if the available memory of the host is less than it,
+    /// e.g. 1g; it's not checked by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_available_memory: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameAutoscalerConf {
+    /// The URL the signals are posted to as JSON, e.g. http://127.0.0.1:9090/scale
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The command run by `sh -c` with the signal as JSON in its stdin, if there's no url
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// How long the pending tasks exceed the idle executors before scaling up, e.g. 1m;
+    /// 30s by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale_up_after: Option<String>,
+    /// How long an executor is idle before it's a candidate of scaling down, e.g. 30m; 10m
+    /// by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale_down_after: Option<String>,
+    /// The minimum interval between the signals of the same kind, e.g. 1m; 30s by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_interval: Option<String>,
+    /// How long a delivered signal is not repeated if nothing changed, e.g. 1h; 10m by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_after: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameQuarantineConf {
+    /// The number of the different tasks failed by an executor in a row before it's
+    /// quarantined; 5 by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failures: Option<u32>,
+    /// The failures older than it are forgotten, e.g. 30m; 10m by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window: Option<String>,
+    /// How long an executor is quarantined, e.g. 1h; it's until `flmctl executors uncordon`
+    /// by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameTimeSliceConf {
+    /// The tasks launched by an executor before its slice is used up, e.g. 100
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tasks: Option<u32>,
+    /// How long an executor is bound before its slice is used up, e.g. 5m; it should be
+    /// much longer than binding a session, which is paid again by every slice
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameUtilizationConf {
+    /// The rolling windows the busy time of the executors is reported over, e.g. [1m, 1d];
+    /// [5m, 1h] by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub windows: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameTimingsConf {
+    /// The interval between the cycles of the scheduler, e.g. 200ms; 500ms by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule_interval: Option<String>,
+    /// The interval between the sweeps of the expired sessions and the quarantined
+    /// executors, and between the writes of the history of the sessions, e.g. 5s; 1s by
+    /// default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sweep_interval: Option<String>,
+    /// The interval between the evaluations of the autoscaler, e.g. 5s; 1s by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub evaluate_interval: Option<String>,
+    /// The interval between the evictions of the expired warm shims, e.g. 10s; 1s by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub evict_interval: Option<String>,
+    /// How often the executors are expected to call the session manager, e.g. 5s; 10s by
+    /// default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heartbeat_interval: Option<String>,
+    /// An executor without heartbeats within it is stale, e.g. 1m; 30s by default; it has
+    /// to be longer than `heartbeat_interval`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease: Option<String>,
+    /// The fraction the intervals of the background loops are randomly spread by, so the
+    /// loops of a large fleet do not run at once, e.g. 0.1 spreads 1s to 0.9s..1.1s; 0 by
+    /// default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jitter: Option<f64>,
+    /// How long a task with a locality waits for an executor having its key before it's
+    /// launched on any executor, e.g. 10s; 3s by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locality_wait: Option<String>,
+    /// The open sessions without tasks are closed as orphaned once their clients made no
+    /// call on them within it, e.g. 30m, as the clients crashed after creating them; it's
+    /// off by default. It's not a deadline, so the sessions with tasks are never orphaned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orphan_timeout: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameMonitorConf {
+    /// The acquisitions of the locks waiting longer than it are logged with their call-sites,
+    /// e.g. 100ms; they're not timed by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock_threshold: Option<String>,
+    /// The calls of the storage engine taking longer than it are logged with their ids,
+    /// e.g. 500ms; they're not timed by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engine_budget: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameEngineRetryConf {
+    /// The attempts of a call of the storage engine, including the first one, before the
+    /// request fails as unavailable; 3 by default, and 1 disables the retries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attempts: Option<u32>,
+    /// The delay before the first retry, which is doubled by every retry, e.g. 100ms; 50ms
+    /// by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoff: Option<String>,
+}
+
+/// How the state changes of the tasks are persisted, see `FlameContext::durability`.
+#[derive(
+    Clone, Copy, Debug, Default, Eq, PartialEq, strum_macros::Display, Deserialize, Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Durability {
+    #[default]
+    Strict,
+    Batched,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameWriteBehindConf {
+    /// The most changes written in a batch; a change is written at once if the batch is
+    /// full, so it's the most changes lost by a crash. 512 by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_batch: Option<usize>,
+    /// How long a change waits for its batch before it's written, e.g. 50ms; 20ms by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flush_interval: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameDebugConf {
+    /// The leading bytes of the task inputs, outputs and common data printed in hex by the
+    /// logs, e.g. 16; only their lengths and digests are printed by default, as the payloads
+    /// may hold personal data, so keep it off in production
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_preview: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameRateLimitConf {
+    /// The mutating Frontend calls allowed per second of each caller, e.g. 10 or 0.5; the
+    /// callers are identified by their tokens, or by their addresses if the authentication
+    /// is disabled
+    pub rate: f64,
+    /// The calls a caller can make at once after being idle; `rate` rounded up by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FlameSessionTemplate {
+    /// The name given by the clients, e.g. nightly-batch
+    pub name: String,
+    /// The application of the sessions, which has to be one of `applications`
+    pub application: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slots: Option<i32>,
+    /// The labels merged with the ones of the session; the session wins on conflicts
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_pending_tasks: Option<u32>,
+    /// The sessions are closed as expired after it, e.g. 8h; no deadline by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+}
+
+/// The quota of the sessions matching both of the namespace and the application; either of
+/// them is required, and the other one matches all.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FlameQuotaConf {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application: Option<String>,
+    /// The slots of the executors bound to the sessions at most, e.g. 40
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_slots: Option<u32>,
+    /// The running tasks of the sessions at most; the pending tasks are accepted, but they're
+    /// launched only when the running ones complete
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_running_tasks: Option<u32>,
+}
+
+/// The class of the sessions with the executors reserved for them, e.g. the interactive
+/// sessions which must start within a cycle of the scheduler.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FlamePriorityClassConf {
+    pub name: String,
+    /// The precedence of the class, 1 by default; the reservations of the heavier classes
+    /// are met first, and the executors are only reclaimed from the lighter classes and the
+    /// sessions without class.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+    /// The executors kept idle or quickly preemptible for the sessions of the class, e.g. 2;
+    /// none by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserved_executors: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameAuthConf {
+    pub tokens: Vec<FlameTokenConf>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameTokenConf {
+    /// The name of the holder, which is the caller in the audit log
+    pub name: String,
+    /// The bearer token sent by the holder
+    pub token: String,
+    /// The only namespace the holder can access; all namespaces if it's not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameTelemetryConf {
+    /// The gRPC endpoint of the OTLP collector, e.g. http://127.0.0.1:4317
+    pub otlp_endpoint: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameAuditConf {
+    /// The JSONL file of the audit log, e.g. /var/log/flame/audit.jsonl
+    pub path: String,
+    /// The maximum size of the file before rotation, e.g. 100m
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<String>,
+    /// The number of rotated files to keep
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_files: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameClientConf {
+    /// The timeout of each RPC, e.g. 30s
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+    /// The timeout of connecting to the session manager, e.g. 5s
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<String>,
+    /// The interval of HTTP/2 keepalive pings, e.g. 60s
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keepalive: Option<String>,
+    /// The maximum retries of the idempotent RPCs if the session manager is unavailable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<FlameTlsConf>,
+    /// The bearer token to authenticate to the session manager
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlameTlsConf {
+    /// The CA bundle to verify the session manager
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_file: Option<String>,
+    /// The certificate and key of the client, for mutual TLS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<String>,
+    /// The domain name to verify, the host of endpoint by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+}
+
+impl FlameClientConf {
+    pub fn timeout(&self) -> Result<Option<Duration>, FlameError> {
+        parse_duration("timeout", &self.timeout)
+    }
+
+    pub fn connect_timeout(&self) -> Result<Option<Duration>, FlameError> {
+        parse_duration("connect_timeout", &self.connect_timeout)
+    }
+
+    pub fn keepalive(&self) -> Result<Option<Duration>, FlameError> {
+        parse_duration("keepalive", &self.keepalive)
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        for res in [self.timeout(), self.connect_timeout(), self.keepalive()] {
+            if let Err(e) = res {
+                problems.push(e.to_string());
+            }
+        }
+
+        if let Some(tls) = &self.tls {
+            if tls.cert_file.is_some() != tls.key_file.is_some() {
+                problems
+                    .push("client.tls: cert_file and key_file must be set together".to_string());
+            }
+        }
+
+        problems
+    }
+}
+
+/// Parses the value of an environment as a YAML scalar, so numbers and bools keep their types.
+fn parse_env_value(value: &str) -> serde_yaml::Value {
+    match serde_yaml::from_str::<serde_yaml::Value>(value) {
+        Ok(v @ (serde_yaml::Value::Bool(_) | serde_yaml::Value::Number(_))) => v,
+        _ => serde_yaml::Value::String(value.to_string()),
+    }
+}
+
+/// Whether the field or the environment is named like a secret, e.g. `API_TOKEN`.
+fn is_secret(name: &str) -> bool {
+    let upper = name.to_uppercase();
+    SECRET_KEYS.iter().any(|s| upper.contains(s))
+}
+
+/// Replaces the secrets in the serialized configuration by `REDACTED`, see
+/// `FlameContext::redacted`.
+fn redact(value: &mut serde_yaml::Value) {
+    match value {
+        serde_yaml::Value::Mapping(fields) => {
+            for (k, v) in fields.iter_mut() {
+                match (k.as_str(), v) {
+                    (Some(k), v @ serde_yaml::Value::String(_)) if is_secret(k) => {
+                        *v = serde_yaml::Value::String(REDACTED.to_string());
+                    }
+                    (_, v) => redact(v),
+                }
+            }
+        }
+        serde_yaml::Value::Sequence(items) => items.iter_mut().for_each(redact),
+        serde_yaml::Value::String(s) => {
+            if let Some((k, _)) = s.split_once('=').filter(|(k, _)| is_secret(k)) {
+                *s = format!("{}={}", k, REDACTED);
+            } else if let Ok(mut url) = Url::parse(s) {
+                if url.password().is_some() && url.set_password(Some(REDACTED)).is_ok() {
+                    *s = url.to_string();
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Sets the value at the path of keys or list indexes; the missing sections are created.
+fn set_value(
+    root: &mut serde_yaml::Value,
+    path: &[String],
+    value: serde_yaml::Value,
+) -> Result<(), String> {
+    let Some((key, rest)) = path.split_first() else {
+        *root = value;
+        return Ok(());
+    };
+    if key.is_empty() {
+        return Err("empty key".to_string());
+    }
+
+    if root.is_null() {
+        *root = serde_yaml::Value::Mapping(Default::default());
+    }
+
+    let next = match root {
+        serde_yaml::Value::Mapping(map) => map
+            .entry(serde_yaml::Value::String(key.clone()))
+            .or_insert(serde_yaml::Value::Null),
+        serde_yaml::Value::Sequence(seq) => {
+            let len = seq.len();
+            key.parse::<usize>()
+                .ok()
+                .and_then(|i| seq.get_mut(i))
+                .ok_or(format!("invalid index <{}> of {} item(s)", key, len))?
+        }
+        _ => return Err(format!("<{}> is not a section", key)),
+    };
+
+    set_value(next, rest, value)
+}
+
+/// Layers the overlay over the base: the sections are merged by their keys, and the other
+/// values, e.g. the lists, are replaced; a null overlay, e.g. an empty file, keeps the base.
+fn merge_value(base: &mut serde_yaml::Value, overlay: serde_yaml::Value) {
+    match (base, overlay) {
+        (_, serde_yaml::Value::Null) => {}
+        (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(overlay)) => {
+            for (k, v) in overlay {
+                match base.get_mut(&k) {
+                    Some(b) => merge_value(b, v),
+                    None => {
+                        base.insert(k, v);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Reads the configuration file as YAML, so it could be layered before it's deserialized.
+fn read_value(fp: &str) -> Result<serde_yaml::Value, FlameError> {
+    if !Path::new(fp).is_file() {
+        return Err(FlameError::InvalidConfig(format!("<{}> is not a file", fp)));
+    }
+
+    let contents = fs::read_to_string(fp).map_err(|e| FlameError::Internal(e.to_string()))?;
+    serde_yaml::from_str(&contents)
+        .map_err(|e| FlameError::InvalidConfig(format!("<{}>: {}", fp, e)))
+}
+
+/// Picks the configuration file: the given path, the one of the environment, or the first
+/// existing candidate; the second candidate is the default.
+fn find_conf(
+    fp: Option<String>,
+    env: Option<String>,
+    candidates: &[String],
+    exists: impl Fn(&str) -> bool,
+) -> String {
+    if let Some(path) = fp.or(env.filter(|p| !p.is_empty())) {
+        return path;
+    }
+
+    candidates
+        .iter()
+        .find(|p| exists(p))
+        .or(candidates.get(1))
+        .cloned()
+        .unwrap_or(DEFAULT_FLAME_CONF.to_string())
+}
+
+impl FlameExecutorConf {
+    pub fn slots(&self) -> i32 {
+        self.slots.unwrap_or(1)
+    }
+
+    pub fn server_applications(&self) -> bool {
+        self.server_applications.unwrap_or_default()
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.labels.keys().any(String::is_empty) {
+            problems.push("executor.labels: empty key".to_string());
+        }
+
+        if self.locality_keys.iter().any(|k| k.trim().is_empty()) {
+            problems.push("executor.locality_keys: empty key".to_string());
+        }
+
+        if self.slots.is_some_and(|s| s <= 0) {
+            problems.push("executor.slots: must be greater than 0".to_string());
+        }
+
+        if let Some(root) = &self.scratch_root {
+            if !Path::new(root).is_absolute() {
+                problems.push(format!("executor.scratch_root <{}>: is not absolute", root));
+            }
+        }
+
+        if let Some(addr) = &self.metrics_address {
+            if let Err(e) = addr.parse::<SocketAddr>() {
+                problems.push(format!("executor.metrics_address <{}>: {}", addr, e));
+            }
+        }
+
+        problems
+    }
+}
+
+impl FlameAuditConf {
+    pub fn max_size(&self) -> Result<u64, FlameError> {
+        match &self.max_size {
+            None => Ok(DEFAULT_AUDIT_MAX_SIZE),
+            Some(v) => parse_memory(v)
+                .map_err(|e| FlameError::InvalidConfig(format!("audit.max_size <{}>: {}", v, e))),
+        }
+    }
+
+    pub fn max_files(&self) -> u32 {
+        self.max_files.unwrap_or(DEFAULT_AUDIT_MAX_FILES)
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.path.is_empty() {
+            problems.push("audit.path: empty path".to_string());
+        }
+
+        match self.max_size() {
+            Ok(0) => problems.push("audit.max_size: must be greater than 0".to_string()),
+            Ok(_) => {}
+            Err(e) => problems.push(e.to_string()),
+        }
+
+        problems
+    }
+}
+
+impl FlameCacheConf {
+    pub fn max_entries(&self) -> usize {
+        self.max_entries.unwrap_or(DEFAULT_CACHE_MAX_ENTRIES)
+    }
+
+    pub fn ttl(&self) -> Result<Duration, FlameError> {
+        match &self.ttl {
+            None => Ok(DEFAULT_CACHE_TTL),
+            Some(v) => humantime::parse_duration(v)
+                .map_err(|e| FlameError::InvalidConfig(format!("cache.ttl <{}>: {}", v, e))),
+        }
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.max_entries == Some(0) {
+            problems.push("cache.max_entries: must be greater than 0".to_string());
+        }
+
+        if let Err(e) = self.ttl() {
+            problems.push(e.to_string());
+        }
+
+        problems
+    }
+}
+
+impl FlameWarmPoolConf {
+    pub fn max_size(&self) -> usize {
+        self.max_size.unwrap_or(DEFAULT_WARM_POOL_MAX_SIZE)
+    }
+
+    pub fn ttl(&self) -> Result<Duration, FlameError> {
+        match &self.ttl {
+            None => Ok(DEFAULT_WARM_POOL_TTL),
+            Some(v) => humantime::parse_duration(v)
+                .map_err(|e| FlameError::InvalidConfig(format!("warm_pool.ttl <{}>: {}", v, e))),
+        }
+    }
+
+    pub fn min_available_memory(&self) -> Result<Option<u64>, FlameError> {
+        self.min_available_memory
+            .as_deref()
+            .map(|v| {
+                parse_memory(v).map_err(|e| {
+                    FlameError::InvalidConfig(format!(
+                        "warm_pool.min_available_memory <{}>: {}",
+                        v, e
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.max_size == Some(0) {
+            problems.push("warm_pool.max_size: must be greater than 0".to_string());
+        }
+
+        if let Err(e) = self.ttl() {
+            problems.push(e.to_string());
+        }
+
+        if let Err(e) = self.min_available_memory() {
+            problems.push(e.to_string());
+        }
+
+        problems
+    }
+}
+
+impl FlameAuthConf {
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        let mut tokens = HashSet::new();
+        for (i, t) in self.tokens.iter().enumerate() {
+            if t.name.is_empty() {
+                problems.push(format!("auth.tokens[{}]: empty name", i));
+            }
+            if t.token.is_empty() {
+                problems.push(format!("auth.tokens[{}]: empty token", i));
+            } else if !tokens.insert(t.token.as_str()) {
+                problems.push(format!("auth.tokens[{}]: duplicated token", i));
+            }
+            if let Some(Err(e)) = t.namespace.as_deref().map(parse_namespace) {
+                problems.push(format!("auth.tokens[{}]: {}", i, e));
+            }
+        }
+
+        problems
+    }
+}
+
+impl FlameGrpcConf {
+    pub fn max_send_message_size(&self) -> Result<usize, FlameError> {
+        parse_size("max_send_message_size", &self.max_send_message_size)
+    }
+
+    pub fn max_recv_message_size(&self) -> Result<usize, FlameError> {
+        parse_size("max_recv_message_size", &self.max_recv_message_size)
+    }
+
+    pub fn compression(&self) -> Result<Compression, FlameError> {
+        match &self.compression {
+            Some(c) => c.parse(),
+            None => Ok(Compression::None),
+        }
+    }
+
+    pub fn options(&self) -> Result<GrpcOptions, FlameError> {
+        Ok(GrpcOptions {
+            max_send_message_size: self.max_send_message_size()?,
+            max_recv_message_size: self.max_recv_message_size()?,
+            compression: self.compression()?.encoding()?,
+        })
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        for res in [self.max_send_message_size(), self.max_recv_message_size()] {
+            if let Err(e) = res {
+                problems.push(e.to_string());
+            }
+        }
+
+        if let Err(e) = self.compression().and_then(|c| c.encoding()) {
+            problems.push(e.to_string());
+        }
+
+        problems
+    }
+}
+
+fn parse_size(name: &str, value: &Option<String>) -> Result<usize, FlameError> {
+    let Some(v) = value else {
+        return Ok(DEFAULT_MAX_MESSAGE_SIZE);
+    };
+
+    let size = parse_memory(v)
+        .and_then(|n| usize::try_from(n).map_err(|e| e.to_string()))
+        .map_err(|e| FlameError::InvalidConfig(format!("grpc.{} <{}>: {}", name, v, e)))?;
+    if size == 0 {
+        return Err(FlameError::InvalidConfig(format!(
+            "grpc.{} <{}>: must be greater than 0",
+            name, v
+        )));
+    }
+
+    Ok(size)
+}
+
+impl FlameTelemetryConf {
+    pub fn problems(&self) -> Vec<String> {
+        match url::Url::parse(&self.otlp_endpoint) {
+            Ok(_) => vec![],
+            Err(e) => vec![format!(
+                "telemetry.otlp_endpoint <{}>: {}",
+                self.otlp_endpoint, e
+            )],
+        }
+    }
+}
+
+fn parse_duration(name: &str, value: &Option<String>) -> Result<Option<Duration>, FlameError> {
+    value
+        .as_ref()
+        .map(|v| {
+            humantime::parse_duration(v)
+                .map_err(|e| FlameError::InvalidConfig(format!("client.{} <{}>: {}", name, v, e)))
+        })
+        .transpose()
+}
+
+/// The overrides of the flags of the command line, e.g. `--endpoint`; they take precedence
+/// over the environments.
+#[derive(Clone, Debug, Default)]
+pub struct FlameOverrides {
+    pub endpoint: Option<String>,
+    pub storage: Option<String>,
+    pub policy: Option<String>,
+}
+
+impl Display for FlameContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "name: {}, endpoint: {}", self.name, self.endpoint)
+    }
+}
+
+impl FlameAutoscalerConf {
+    fn duration(name: &str, v: &Option<String>, default: Duration) -> Result<Duration, FlameError> {
+        match v {
+            None => Ok(default),
+            Some(v) => humantime::parse_duration(v).map_err(|e| {
+                FlameError::InvalidConfig(format!("autoscaler.{} <{}>: {}", name, v, e))
+            }),
+        }
+    }
+
+    pub fn scale_up_after(&self) -> Result<Duration, FlameError> {
+        Self::duration(
+            "scale_up_after",
+            &self.scale_up_after,
+            DEFAULT_SCALE_UP_AFTER,
+        )
+    }
+
+    pub fn scale_down_after(&self) -> Result<Duration, FlameError> {
+        Self::duration(
+            "scale_down_after",
+            &self.scale_down_after,
+            DEFAULT_SCALE_DOWN_AFTER,
+        )
+    }
+
+    pub fn min_interval(&self) -> Result<Duration, FlameError> {
+        Self::duration(
+            "min_interval",
+            &self.min_interval,
+            DEFAULT_AUTOSCALER_MIN_INTERVAL,
+        )
+    }
+
+    pub fn repeat_after(&self) -> Result<Duration, FlameError> {
+        Self::duration(
+            "repeat_after",
+            &self.repeat_after,
+            DEFAULT_AUTOSCALER_REPEAT_AFTER,
+        )
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        match (&self.url, &self.command) {
+            (Some(_), Some(_)) => {
+                problems.push("autoscaler: only one of url and command is allowed".to_string())
+            }
+            (None, None) => problems.push("autoscaler: url or command is required".to_string()),
+            (Some(url), None) => match Url::parse(url) {
+                Ok(u) if u.scheme() == "http" => {}
+                Ok(u) => problems.push(format!(
+                    "autoscaler.url <{}>: unsupported scheme <{}>",
+                    url,
+                    u.scheme()
+                )),
+                Err(e) => problems.push(format!("autoscaler.url <{}>: {}", url, e)),
+            },
+            (None, Some(_)) => {}
+        }
+
+        for res in [
+            self.scale_up_after(),
+            self.scale_down_after(),
+            self.min_interval(),
+            self.repeat_after(),
+        ] {
+            if let Err(e) = res {
+                problems.push(e.to_string());
+            }
+        }
+
+        problems
+    }
+}
+
+impl FlameQuarantineConf {
+    pub fn failures(&self) -> u32 {
+        self.failures.unwrap_or(DEFAULT_QUARANTINE_FAILURES)
+    }
+
+    fn duration(name: &str, v: &Option<String>) -> Result<Option<Duration>, FlameError> {
+        v.as_deref()
+            .map(|v| {
+                humantime::parse_duration(v).map_err(|e| {
+                    FlameError::InvalidConfig(format!("quarantine.{} <{}>: {}", name, v, e))
+                })
+            })
+            .transpose()
+    }
+
+    pub fn window(&self) -> Result<Duration, FlameError> {
+        Ok(Self::duration("window", &self.window)?.unwrap_or(DEFAULT_QUARANTINE_WINDOW))
+    }
+
+    pub fn cooldown(&self) -> Result<Option<Duration>, FlameError> {
+        Self::duration("cooldown", &self.cooldown)
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.failures == Some(0) {
+            problems.push("quarantine.failures: must be greater than 0".to_string());
+        }
+
+        if let Err(e) = self.window() {
+            problems.push(e.to_string());
+        }
+
+        if let Err(e) = self.cooldown() {
+            problems.push(e.to_string());
+        }
+
+        problems
+    }
+}
+
+impl FlameTimeSliceConf {
+    pub fn duration(&self) -> Result<Option<Duration>, FlameError> {
+        let d = match &self.duration {
+            None => return Ok(None),
+            Some(v) => humantime::parse_duration(v).map_err(|e| {
+                FlameError::InvalidConfig(format!("time_slice.duration <{}>: {}", v, e))
+            })?,
+        };
+
+        match d.is_zero() {
+            true => Err(FlameError::InvalidConfig(
+                "time_slice.duration: must be greater than 0".to_string(),
+            )),
+            false => Ok(Some(d)),
+        }
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.tasks.is_none() && self.duration.is_none() {
+            problems.push("time_slice: either tasks or duration is required".to_string());
+        }
+
+        if self.tasks == Some(0) {
+            problems.push("time_slice.tasks: must be greater than 0".to_string());
+        }
+
+        if let Err(e) = self.duration() {
+            problems.push(e.to_string());
+        }
+
+        problems
+    }
+}
+
+impl FlameUtilizationConf {
+    pub fn windows(&self) -> Result<Vec<Duration>, FlameError> {
+        let Some(windows) = &self.windows else {
+            return Ok(DEFAULT_UTILIZATION_WINDOWS.to_vec());
+        };
+
+        windows
+            .iter()
+            .map(|v| {
+                let d = humantime::parse_duration(v).map_err(|e| {
+                    FlameError::InvalidConfig(format!("utilization.windows <{}>: {}", v, e))
+                })?;
+                match d.is_zero() {
+                    true => Err(FlameError::InvalidConfig(format!(
+                        "utilization.windows <{}>: must be greater than 0",
+                        v
+                    ))),
+                    false => Ok(d),
+                }
+            })
+            .collect()
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.windows.as_ref().is_some_and(|w| w.is_empty()) {
+            problems.push("utilization.windows: at least one window is required".to_string());
+        }
+
+        if let Err(e) = self.windows() {
+            problems.push(e.to_string());
+        }
+
+        problems
+    }
+}
+
+impl FlameMonitorConf {
+    fn duration(name: &str, v: &Option<String>) -> Result<Option<Duration>, FlameError> {
+        v.as_deref()
+            .map(|v| {
+                humantime::parse_duration(v).map_err(|e| {
+                    FlameError::InvalidConfig(format!("monitor.{} <{}>: {}", name, v, e))
+                })
+            })
+            .transpose()
+    }
+
+    pub fn lock_threshold(&self) -> Result<Option<Duration>, FlameError> {
+        Self::duration("lock_threshold", &self.lock_threshold)
+    }
+
+    pub fn engine_budget(&self) -> Result<Option<Duration>, FlameError> {
+        Self::duration("engine_budget", &self.engine_budget)
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if let Err(e) = self.lock_threshold() {
+            problems.push(e.to_string());
+        }
+
+        if let Err(e) = self.engine_budget() {
+            problems.push(e.to_string());
+        }
+
+        problems
+    }
+}
+
+impl FlameEngineRetryConf {
+    pub fn backoff(&self) -> Result<Option<Duration>, FlameError> {
+        self.backoff
+            .as_deref()
+            .map(|v| {
+                humantime::parse_duration(v).map_err(|e| {
+                    FlameError::InvalidConfig(format!("engine_retry.backoff <{}>: {}", v, e))
+                })
+            })
+            .transpose()
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.attempts == Some(0) {
+            problems.push("engine_retry.attempts: must be greater than 0".to_string());
+        }
+
+        if let Err(e) = self.backoff() {
+            problems.push(e.to_string());
+        }
+
+        problems
+    }
+}
+
+impl FlameWriteBehindConf {
+    pub fn max_batch(&self) -> usize {
+        self.max_batch.unwrap_or(DEFAULT_WRITE_BEHIND_MAX_BATCH)
+    }
+
+    pub fn flush_interval(&self) -> Result<Duration, FlameError> {
+        let d = match &self.flush_interval {
+            None => return Ok(DEFAULT_WRITE_BEHIND_FLUSH_INTERVAL),
+            Some(v) => humantime::parse_duration(v).map_err(|e| {
+                FlameError::InvalidConfig(format!("write_behind.flush_interval <{}>: {}", v, e))
+            })?,
+        };
+
+        match d.is_zero() {
+            true => Err(FlameError::InvalidConfig(
+                "write_behind.flush_interval: must be greater than 0".to_string(),
+            )),
+            false => Ok(d),
+        }
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.max_batch == Some(0) {
+            problems.push("write_behind.max_batch: must be greater than 0".to_string());
+        }
+
+        if let Err(e) = self.flush_interval() {
+            problems.push(e.to_string());
+        }
+
+        problems
+    }
+}
+
+impl FlameRateLimitConf {
+    pub fn burst(&self) -> u32 {
+        self.burst
+            .unwrap_or(self.rate.ceil().clamp(1.0, u32::MAX as f64) as u32)
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if !(self.rate.is_finite() && self.rate > 0.0) {
+            problems.push(format!(
+                "rate_limit.rate <{}>: must be greater than 0",
+                self.rate
+            ));
+        }
+
+        if self.burst == Some(0) {
+            problems.push("rate_limit.burst: must be greater than 0".to_string());
+        }
+
+        problems
+    }
+}
+
+impl FlameTimingsConf {
+    fn duration(name: &str, v: &Option<String>, default: Duration) -> Result<Duration, FlameError> {
+        let d = match v {
+            None => return Ok(default),
+            Some(v) => humantime::parse_duration(v).map_err(|e| {
+                FlameError::InvalidConfig(format!("timings.{} <{}>: {}", name, v, e))
+            })?,
+        };
+
+        match d.is_zero() {
+            true => Err(FlameError::InvalidConfig(format!(
+                "timings.{}: must be greater than 0",
+                name
+            ))),
+            false => Ok(d),
+        }
+    }
+
+    pub fn schedule_interval(&self) -> Result<Duration, FlameError> {
+        Self::duration(
+            "schedule_interval",
+            &self.schedule_interval,
+            DEFAULT_SCHEDULE_INTERVAL,
+        )
+    }
+
+    pub fn sweep_interval(&self) -> Result<Duration, FlameError> {
+        Self::duration(
+            "sweep_interval",
+            &self.sweep_interval,
+            DEFAULT_SWEEP_INTERVAL,
+        )
+    }
+
+    pub fn evaluate_interval(&self) -> Result<Duration, FlameError> {
+        Self::duration(
+            "evaluate_interval",
+            &self.evaluate_interval,
+            DEFAULT_EVALUATE_INTERVAL,
+        )
+    }
+
+    pub fn evict_interval(&self) -> Result<Duration, FlameError> {
+        Self::duration(
+            "evict_interval",
+            &self.evict_interval,
+            DEFAULT_EVICT_INTERVAL,
+        )
+    }
+
+    pub fn heartbeat_interval(&self) -> Result<Duration, FlameError> {
+        Self::duration(
+            "heartbeat_interval",
+            &self.heartbeat_interval,
+            DEFAULT_HEARTBEAT_INTERVAL,
+        )
+    }
+
+    pub fn lease(&self) -> Result<Duration, FlameError> {
+        Self::duration("lease", &self.lease, DEFAULT_LEASE)
+    }
+
+    pub fn locality_wait(&self) -> Result<Duration, FlameError> {
+        Self::duration("locality_wait", &self.locality_wait, DEFAULT_LOCALITY_WAIT)
+    }
+
+    pub fn orphan_timeout(&self) -> Result<Option<Duration>, FlameError> {
+        match &self.orphan_timeout {
+            None => Ok(None),
+            v => Self::duration("orphan_timeout", v, Duration::ZERO).map(Some),
+        }
+    }
+
+    pub fn jitter(&self) -> f64 {
+        self.jitter.unwrap_or_default()
+    }
+
+    /// Spreads the interval randomly by the jitter, e.g. 1s is 0.9s..1.1s if the jitter is
+    /// 0.1; the interval is kept if there's no jitter.
+    pub fn jittered(&self, interval: Duration) -> Duration {
+        let jitter = self.jitter();
+        if jitter <= 0.0 || jitter >= 1.0 {
+            return interval;
+        }
+
+        // There's no random generator in the dependencies, and the random keys of the hasher
+        // are good enough for spreading the loops.
+        let r = RandomState::new().build_hasher().finish();
+        let r = (r >> 11) as f64 / (1u64 << 53) as f64;
+
+        interval.mul_f64(1.0 + jitter * (2.0 * r - 1.0))
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        for res in [
+            self.schedule_interval(),
+            self.sweep_interval(),
+            self.evaluate_interval(),
+            self.evict_interval(),
+            self.locality_wait(),
+        ] {
+            if let Err(e) = res {
+                problems.push(e.to_string());
+            }
+        }
+        if let Err(e) = self.orphan_timeout() {
+            problems.push(e.to_string());
+        }
+
+        match (self.heartbeat_interval(), self.lease()) {
+            (Ok(heartbeat), Ok(lease)) if lease <= heartbeat => problems.push(format!(
+                "timings.lease <{}>: must be longer than timings.heartbeat_interval <{}>",
+                humantime::format_duration(lease),
+                humantime::format_duration(heartbeat)
+            )),
+            (heartbeat, lease) => {
+                problems.extend(heartbeat.err().map(|e| e.to_string()));
+                problems.extend(lease.err().map(|e| e.to_string()));
+            }
+        }
+
+        let jitter = self.jitter();
+        if !(0.0..1.0).contains(&jitter) {
+            problems.push(format!("timings.jitter <{}>: must be in [0, 1)", jitter));
+        }
+
+        problems
+    }
+}
+
+impl FlameSessionTemplate {
+    pub fn timeout(&self) -> Result<Option<Duration>, FlameError> {
+        self.timeout
+            .as_deref()
+            .map(|v| {
+                humantime::parse_duration(v).map_err(|e| {
+                    FlameError::InvalidConfig(format!(
+                        "session_templates <{}>: timeout <{}>: {}",
+                        self.name, v, e
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.name.is_empty() {
+            problems.push("session_templates: name is required".to_string());
+        }
+
+        if matches!(self.slots, Some(slots) if slots <= 0) {
+            problems.push(format!(
+                "session_templates <{}>: slots must be greater than 0",
+                self.name
+            ));
+        }
+
+        if self.max_pending_tasks == Some(0) {
+            problems.push(format!(
+                "session_templates <{}>: max_pending_tasks must be greater than 0",
+                self.name
+            ));
+        }
+
+        if let Err(e) = self.timeout() {
+            problems.push(e.to_string());
+        }
+
+        problems
+    }
+}
+
+impl FlameQuotaConf {
+    /// Whether the quota applies to the sessions of the application in the namespace.
+    pub fn matches(&self, namespace: &str, application: &str) -> bool {
+        self.namespace.as_deref().is_none_or(|n| n == namespace)
+            && self.application.as_deref().is_none_or(|a| a == application)
+    }
+
+    /// The name of the quota in the messages, e.g. `namespace=research,application=pi`.
+    pub fn name(&self) -> String {
+        let mut parts = vec![];
+        if let Some(namespace) = &self.namespace {
+            parts.push(format!("namespace={}", namespace));
+        }
+        if let Some(application) = &self.application {
+            parts.push(format!("application={}", application));
+        }
+
+        parts.join(",")
+    }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct FlameContext {
-    pub name: String,
-    pub endpoint: String,
-    pub slot: String,
-    pub policy: String,
-    pub storage: String,
-    pub applications: Vec<Application>,
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.namespace.is_none() && self.application.is_none() {
+            problems.push("quotas: either namespace or application is required".to_string());
+        }
+
+        if let Some(Err(e)) = self.namespace.as_deref().map(parse_namespace) {
+            problems.push(format!("quotas <{}>: {}", self.name(), e));
+        }
+
+        if self.max_slots.is_none() && self.max_running_tasks.is_none() {
+            problems.push(format!(
+                "quotas <{}>: either max_slots or max_running_tasks is required",
+                self.name()
+            ));
+        }
+
+        if self.max_slots == Some(0) {
+            problems.push(format!(
+                "quotas <{}>: max_slots must be greater than 0",
+                self.name()
+            ));
+        }
+
+        if self.max_running_tasks == Some(0) {
+            problems.push(format!(
+                "quotas <{}>: max_running_tasks must be greater than 0",
+                self.name()
+            ));
+        }
+
+        problems
+    }
 }
 
-impl Display for FlameContext {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "name: {}, endpoint: {}", self.name, self.endpoint)
+impl FlamePriorityClassConf {
+    pub fn weight(&self) -> u32 {
+        self.weight.unwrap_or(DEFAULT_PRIORITY_CLASS_WEIGHT)
+    }
+
+    pub fn reserved_executors(&self) -> u32 {
+        self.reserved_executors.unwrap_or_default()
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.name.is_empty() {
+            problems.push("priority_classes: name is required".to_string());
+        }
+
+        if self.weight == Some(0) {
+            problems.push(format!(
+                "priority_classes <{}>: weight must be greater than 0",
+                self.name
+            ));
+        }
+
+        problems
     }
 }
 
@@ -48,39 +1566,390 @@ impl Default for FlameContext {
         FlameContext {
             name: DEFAULT_CONTEXT_NAME.to_string(),
             endpoint: DEFAULT_FLAME_ENDPOINT.to_string(),
+            backend_endpoint: None,
+            socket_mode: None,
             slot: DEFAULT_SLOT.to_string(),
             policy: DEFAULT_POLICY.to_string(),
             storage: DEFAULT_STORAGE.to_string(),
+            max_pending_tasks: None,
+            cache: None,
+            spill_completed_tasks: None,
+            max_task_retries: None,
+            reject_unschedulable: None,
             applications: vec![Application::default()],
+            client: None,
+            namespace: None,
+            audit: None,
+            telemetry: None,
+            auth: None,
+            grpc: None,
+            warm_pool: None,
+            autoscaler: None,
+            quarantine: None,
+            time_slice: None,
+            utilization: None,
+            timings: None,
+            monitor: None,
+            engine_retry: None,
+            durability: None,
+            write_behind: None,
+            debug: None,
+            rate_limit: None,
+            session_templates: vec![],
+            quotas: vec![],
+            priority_classes: vec![],
+            executor: None,
         }
     }
 }
 
 impl FlameContext {
+    /// Returns the path of the configuration file: the given path, `$FLAME_CONF`, or the first
+    /// existing one of `./flame-conf.yaml`, `$HOME/.flame/flame-conf.yaml` and
+    /// `/etc/flame/flame-conf.yaml`; it's the one in `$HOME` if none of them exists.
+    pub fn conf_path(fp: Option<String>) -> String {
+        let env = std::env::var(FLAME_CONF_ENV).ok();
+        let candidates = Self::search_paths();
+
+        find_conf(fp, env, &candidates, |p| Path::new(p).is_file())
+    }
+
+    /// The paths to search the configuration file, in order.
+    pub fn search_paths() -> Vec<String> {
+        let home = std::env::var("HOME").unwrap_or(".".to_string());
+
+        vec![
+            DEFAULT_FLAME_CONF.to_string(),
+            format!("{}/.flame/{}", home, DEFAULT_FLAME_CONF),
+            format!("/etc/flame/{}", DEFAULT_FLAME_CONF),
+        ]
+    }
+
+    /// Loads the configuration file, applies the overrides of the environments and validates
+    /// it; the precedence is environments > file > defaults.
     pub fn from_file(fp: Option<String>) -> Result<Self, FlameError> {
-        let fp = match fp {
-            None => {
-                format!("{}/.flame/{}", env!("HOME", "."), DEFAULT_FLAME_CONF)
-            }
-            Some(path) => path,
+        Self::resolve(fp, std::env::vars(), &FlameOverrides::default())
+    }
+
+    /// Resolves the configuration of the binaries: loads the configuration file, applies the
+    /// overrides of the environments and the flags, and validates it with all the problems;
+    /// the precedence is flags > environments > file > defaults.
+    pub fn resolve(
+        fp: Option<String>,
+        vars: impl IntoIterator<Item = (String, String)>,
+        flags: &FlameOverrides,
+    ) -> Result<Self, FlameError> {
+        let fp = Self::conf_path(fp);
+        let ctx = Self::parse_file(&fp)?.with_env(vars)?.with_overrides(flags);
+
+        log::debug!("Load FrameContext from <{}>: {}", fp, ctx);
+
+        ctx.validate()?;
+
+        Ok(ctx)
+    }
+
+    /// Overrides the fields by the flags of the command line.
+    pub fn with_overrides(mut self, flags: &FlameOverrides) -> Self {
+        if let Some(endpoint) = &flags.endpoint {
+            self.endpoint = endpoint.clone();
+        }
+        if let Some(storage) = &flags.storage {
+            self.storage = storage.clone();
+        }
+        if let Some(policy) = &flags.policy {
+            self.policy = policy.clone();
+        }
+
+        self
+    }
+
+    /// Hides the secrets, e.g. to print or log the configuration: the values of the fields
+    /// named like secrets, e.g. `token` or `key_file`, the passwords of the URLs, and the
+    /// secret-like environments of the applications. They're found by the names in the
+    /// serialized configuration, so the new fields are covered once they're named so.
+    pub fn redacted(self) -> Self {
+        let Ok(mut value) = serde_yaml::to_value(&self) else {
+            return FlameContext::default();
         };
+        redact(&mut value);
+
+        // Only the strings are replaced, so it's always deserialized; nothing is kept rather
+        // than the secrets if it's not.
+        serde_yaml::from_value(value).unwrap_or_default()
+    }
+
+    /// Overrides the fields by the environments, e.g. `FLAME_ENDPOINT` or
+    /// `FLAME__CLIENT__TIMEOUT`; the index of a list is a key too, e.g.
+    /// `FLAME__APPLICATIONS__0__COMMAND`.
+    pub fn with_env(
+        self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, FlameError> {
+        let mut overrides = vec![];
+        let mut shortcuts = vec![];
+        for (k, v) in vars {
+            if let Some(path) = k.strip_prefix(NESTED_ENV_PREFIX) {
+                let path = path
+                    .split(NESTED_ENV_SEPARATOR)
+                    .map(|p| p.to_lowercase())
+                    .collect();
+                overrides.push((k, path, v));
+            } else if let Some((_, field)) = ENV_OVERRIDES.iter().find(|(env, _)| *env == k) {
+                shortcuts.push((k, vec![field.to_string()], v));
+            }
+        }
+        if overrides.is_empty() && shortcuts.is_empty() {
+            return Ok(self);
+        }
+
+        overrides.sort();
+        shortcuts.sort();
+
+        let mut root = serde_yaml::to_value(&self)
+            .map_err(|e| FlameError::Internal(format!("failed to convert context: {}", e)))?;
+        for (env, path, value) in overrides.into_iter().chain(shortcuts) {
+            set_value(&mut root, &path, parse_env_value(&value))
+                .map_err(|e| FlameError::InvalidConfig(format!("${}: {}", env, e)))?;
+
+            log::debug!("Override <{}> by ${}", path.join("."), env);
+        }
 
-        if !Path::new(&fp).is_file() {
+        serde_yaml::from_value(root).map_err(|e| {
+            FlameError::InvalidConfig(format!("invalid overrides of environments: {}", e))
+        })
+    }
+
+    /// Loads the configuration file without validation, e.g. for `flmctl config`.
+    pub fn parse_file(fp: &str) -> Result<Self, FlameError> {
+        if !Path::new(fp).is_file() {
             return Err(FlameError::InvalidConfig(format!("<{}> is not a file", fp)));
         }
 
-        let contents =
-            fs::read_to_string(fp.clone()).map_err(|e| FlameError::Internal(e.to_string()))?;
-        let ctx: FlameContext =
-            serde_yaml::from_str(&contents).map_err(|e| FlameError::Internal(e.to_string()))?;
+        let contents = fs::read_to_string(fp).map_err(|e| FlameError::Internal(e.to_string()))?;
+        serde_yaml::from_str(&contents).map_err(|e| FlameError::InvalidConfig(e.to_string()))
+    }
 
-        log::debug!("Load FrameContext from <{}>: {}", fp, ctx);
+    /// Loads the configuration files without validation, each of them layered over the
+    /// previous ones, e.g. the file of a host over the shared one: the sections are merged
+    /// by their keys, and the other values, e.g. `applications`, are replaced.
+    pub fn parse_files(fps: &[String]) -> Result<Self, FlameError> {
+        let mut root = serde_yaml::Value::Null;
+        for fp in fps {
+            merge_value(&mut root, read_value(fp)?);
+        }
+
+        serde_yaml::from_value(root).map_err(|e| FlameError::InvalidConfig(e.to_string()))
+    }
 
-        if ctx.applications.is_empty() {
-            return Err(FlameError::InvalidConfig("no application".to_string()));
+    pub fn validate(&self) -> Result<(), FlameError> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            return Ok(());
         }
 
-        Ok(ctx)
+        Err(FlameError::InvalidConfig(problems.join("; ")))
+    }
+
+    /// Returns all the problems of the configuration instead of the first one.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if let Err(e) = endpoint::validate(&self.endpoint) {
+            problems.push(format!("endpoint <{}>: {}", self.endpoint, e));
+        }
+        if let Some(backend) = &self.backend_endpoint {
+            if let Err(e) = endpoint::validate(backend) {
+                problems.push(format!("backend_endpoint <{}>: {}", backend, e));
+            }
+        }
+        if let Err(e) = self.socket_mode() {
+            problems.push(e.to_string());
+        }
+
+        if !POLICIES.contains(&self.policy.as_str()) {
+            problems.push(format!(
+                "policy <{}>: unknown policy, expect one of {}",
+                self.policy,
+                POLICIES.join(", ")
+            ));
+        }
+
+        let scheme = self.storage.split_once("://").map(|(scheme, _)| scheme);
+        if !scheme.is_some_and(|s| STORAGE_SCHEMES.contains(&s)) {
+            problems.push(format!(
+                "storage <{}>: unknown storage, expect one of {}",
+                self.storage,
+                STORAGE_SCHEMES
+                    .iter()
+                    .map(|s| format!("{}://", s))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
+        if let Err(e) = self.slot.parse::<ResourceVector>() {
+            problems.push(format!("slot <{}>: {}", self.slot, e));
+        }
+
+        if self.max_pending_tasks == Some(0) {
+            problems.push("max_pending_tasks: must be greater than 0".to_string());
+        }
+
+        if let Some(cache) = &self.cache {
+            problems.extend(cache.problems());
+        }
+
+        // The executors using the applications of the session manager need none of their own.
+        let executor = self.executor.clone().unwrap_or_default();
+        if self.applications.is_empty() && !executor.server_applications() {
+            problems.push("no application".to_string());
+        }
+
+        let mut names = HashSet::new();
+        for app in &self.applications {
+            if !app.name.is_empty() && !names.insert(app.name.clone()) {
+                problems.push(format!("application <{}>: duplicated name", app.name));
+            }
+            problems.extend(app.problems());
+        }
+
+        if let Some(Err(e)) = self.namespace.as_deref().map(parse_namespace) {
+            problems.push(e.to_string());
+        }
+
+        if let Some(client) = &self.client {
+            problems.extend(client.problems());
+        }
+
+        if let Some(audit) = &self.audit {
+            problems.extend(audit.problems());
+        }
+
+        if let Some(telemetry) = &self.telemetry {
+            problems.extend(telemetry.problems());
+        }
+
+        if let Some(auth) = &self.auth {
+            problems.extend(auth.problems());
+        }
+
+        if let Some(grpc) = &self.grpc {
+            problems.extend(grpc.problems());
+        }
+
+        if let Some(warm_pool) = &self.warm_pool {
+            problems.extend(warm_pool.problems());
+        }
+
+        if let Some(autoscaler) = &self.autoscaler {
+            problems.extend(autoscaler.problems());
+        }
+
+        if let Some(quarantine) = &self.quarantine {
+            problems.extend(quarantine.problems());
+        }
+
+        if let Some(time_slice) = &self.time_slice {
+            problems.extend(time_slice.problems());
+        }
+
+        if let Some(utilization) = &self.utilization {
+            problems.extend(utilization.problems());
+        }
+
+        if let Some(timings) = &self.timings {
+            problems.extend(timings.problems());
+        }
+
+        if let Some(monitor) = &self.monitor {
+            problems.extend(monitor.problems());
+        }
+
+        if let Some(engine_retry) = &self.engine_retry {
+            problems.extend(engine_retry.problems());
+        }
+
+        if let Some(write_behind) = &self.write_behind {
+            problems.extend(write_behind.problems());
+        }
+
+        if let Some(rate_limit) = &self.rate_limit {
+            problems.extend(rate_limit.problems());
+        }
+
+        let mut templates = HashSet::new();
+        for template in &self.session_templates {
+            if !template.name.is_empty() && !templates.insert(template.name.clone()) {
+                problems.push(format!(
+                    "session_templates <{}>: duplicated name",
+                    template.name
+                ));
+            }
+            if !names.contains(&template.application) {
+                problems.push(format!(
+                    "session_templates <{}>: unknown application <{}>",
+                    template.name, template.application
+                ));
+            }
+            problems.extend(template.problems());
+        }
+
+        let mut quotas = HashSet::new();
+        for quota in &self.quotas {
+            if !quotas.insert((&quota.namespace, &quota.application)) {
+                problems.push(format!("quotas <{}>: duplicated quota", quota.name()));
+            }
+            problems.extend(quota.problems());
+        }
+
+        let mut classes = HashSet::new();
+        for class in &self.priority_classes {
+            if !class.name.is_empty() && !classes.insert(&class.name) {
+                problems.push(format!(
+                    "priority_classes <{}>: duplicated class",
+                    class.name
+                ));
+            }
+            problems.extend(class.problems());
+        }
+
+        problems.extend(executor.problems());
+
+        problems
+    }
+
+    /// The endpoint of the Backend service, see `backend_endpoint`.
+    pub fn backend_endpoint(&self) -> &str {
+        self.backend_endpoint.as_deref().unwrap_or(&self.endpoint)
+    }
+
+    /// The permissions of the unix sockets, which are parsed from octal, e.g. 0660.
+    pub fn socket_mode(&self) -> Result<u32, FlameError> {
+        let Some(mode) = &self.socket_mode else {
+            return Ok(DEFAULT_SOCKET_MODE);
+        };
+
+        match u32::from_str_radix(mode, 8) {
+            Ok(m) if m <= 0o777 => Ok(m),
+            _ => Err(FlameError::InvalidConfig(format!(
+                "socket_mode <{}>: expect octal permissions, e.g. 0660",
+                mode
+            ))),
+        }
+    }
+
+    /// The options of the gRPC channels, which were validated when the configuration was loaded.
+    pub fn grpc_options(&self) -> Result<GrpcOptions, FlameError> {
+        match &self.grpc {
+            Some(grpc) => grpc.options(),
+            None => Ok(GrpcOptions::default()),
+        }
+    }
+
+    /// The resources of a slot, which were validated when the configuration was loaded.
+    pub fn slot_resources(&self) -> Result<ResourceVector, FlameError> {
+        self.slot.parse()
     }
 
     pub fn get_application(&self, n: &String) -> Option<Application> {
@@ -96,3 +1965,703 @@ impl FlameContext {
         application
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_conf() {
+        let candidates = FlameContext::search_paths();
+        let home = candidates[1].clone();
+
+        let path = find_conf(
+            Some("a.yaml".to_string()),
+            Some("b.yaml".to_string()),
+            &candidates,
+            |_| true,
+        );
+        assert_eq!(path, "a.yaml");
+
+        let path = find_conf(None, Some("b.yaml".to_string()), &candidates, |_| true);
+        assert_eq!(path, "b.yaml");
+
+        let path = find_conf(None, Some(String::new()), &candidates, |_| true);
+        assert_eq!(path, "flame-conf.yaml");
+
+        let path = find_conf(None, None, &candidates, |p| p.starts_with("/etc"));
+        assert_eq!(path, "/etc/flame/flame-conf.yaml");
+
+        let path = find_conf(None, None, &candidates, |_| false);
+        assert_eq!(path, home);
+    }
+
+    #[test]
+    fn test_with_env() {
+        let vars = |vars: &[(&str, &str)]| -> Vec<(String, String)> {
+            vars.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        let ctx = FlameContext::default()
+            .with_env(vars(&[
+                ("FLAME_ENDPOINT", "http://flame:8080"),
+                ("FLAME__ENDPOINT", "http://ignored:8080"),
+                ("FLAME_SLOT", "cpu=2,mem=4g"),
+                ("FLAME__CLIENT__TIMEOUT", "30s"),
+                ("FLAME__CLIENT__RETRIES", "5"),
+                ("FLAME__CLIENT__TLS__DOMAIN", "flame.local"),
+                ("FLAME__APPLICATIONS__0__NAME", "pi"),
+                ("FLAME_CONF", "/etc/flame/flame-conf.yaml"),
+                ("HOME", "/root"),
+            ]))
+            .unwrap();
+
+        assert_eq!(ctx.endpoint, "http://flame:8080");
+        assert_eq!(ctx.slot, "cpu=2,mem=4g");
+        assert_eq!(ctx.storage, DEFAULT_STORAGE);
+        assert_eq!(ctx.applications[0].name, "pi");
+
+        let client = ctx.client.unwrap();
+        assert_eq!(client.timeout, Some("30s".to_string()));
+        assert_eq!(client.retries, Some(5));
+        assert_eq!(client.tls.unwrap().domain, Some("flame.local".to_string()));
+
+        for var in [
+            ("FLAME__APPLICATIONS__1__NAME", "pi"),
+            ("FLAME__NAME__FIRST", "flame"),
+            ("FLAME__CLIENT__RETRIES", "many"),
+            ("FLAME__", "flame"),
+        ] {
+            let res = FlameContext::default().with_env(vars(&[var]));
+            assert!(
+                matches!(res, Err(FlameError::InvalidConfig(_))),
+                "{:?} should be invalid",
+                var
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_file_with_env() {
+        let path = std::env::temp_dir().join(format!("flame-conf-{}.yaml", std::process::id()));
+        let ctx = FlameContext {
+            applications: vec![Application {
+                name: "flmexec".to_string(),
+                shim: crate::apis::Shim::Log,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        fs::write(&path, serde_yaml::to_string(&ctx).unwrap()).unwrap();
+
+        std::env::set_var("FLAME_POLICY", "fairshare");
+        std::env::set_var("FLAME__STORAGE", "sqlite:///var/lib/flame.db");
+        let res = FlameContext::from_file(Some(path.to_string_lossy().to_string()));
+        std::env::remove_var("FLAME_POLICY");
+        std::env::remove_var("FLAME__STORAGE");
+        fs::remove_file(&path).unwrap();
+
+        let ctx = res.unwrap();
+        assert_eq!(ctx.policy, "fairshare");
+        assert_eq!(ctx.storage, "sqlite:///var/lib/flame.db");
+        assert_eq!(ctx.endpoint, DEFAULT_FLAME_ENDPOINT);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let path = std::env::temp_dir().join(format!("flame-resolve-{}.yaml", std::process::id()));
+        let ctx = FlameContext {
+            endpoint: "http://file:8080".to_string(),
+            storage: "sqlite://file.db".to_string(),
+            applications: vec![Application {
+                name: "flmexec".to_string(),
+                shim: crate::apis::Shim::Log,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        fs::write(&path, serde_yaml::to_string(&ctx).unwrap()).unwrap();
+        let fp = Some(path.to_string_lossy().to_string());
+
+        let env = vec![
+            ("FLAME_ENDPOINT".to_string(), "http://env:8080".to_string()),
+            ("FLAME_STORAGE".to_string(), "sqlite://env.db".to_string()),
+        ];
+        let flags = FlameOverrides {
+            endpoint: Some("http://flag:8080".to_string()),
+            storage: Some("sqlite://flag.db".to_string()),
+            ..Default::default()
+        };
+
+        let resolve = |env: &[(String, String)], flags: &FlameOverrides| {
+            FlameContext::resolve(fp.clone(), env.to_vec(), flags).unwrap()
+        };
+
+        let ctx = resolve(&[], &FlameOverrides::default());
+        assert_eq!(ctx.endpoint, "http://file:8080");
+        assert_eq!(ctx.storage, "sqlite://file.db");
+
+        let ctx = resolve(&env, &FlameOverrides::default());
+        assert_eq!(ctx.endpoint, "http://env:8080");
+        assert_eq!(ctx.storage, "sqlite://env.db");
+
+        let ctx = resolve(&env, &flags);
+        assert_eq!(ctx.endpoint, "http://flag:8080");
+        assert_eq!(ctx.storage, "sqlite://flag.db");
+
+        // All the problems are reported, including the ones of the flags.
+        let flags = FlameOverrides {
+            endpoint: Some("flag:8080".to_string()),
+            policy: Some("unknown".to_string()),
+            ..Default::default()
+        };
+        let res = FlameContext::resolve(fp.clone(), env.clone(), &flags);
+        fs::remove_file(&path).unwrap();
+
+        let Err(FlameError::InvalidConfig(msg)) = res else {
+            panic!("the flags should be invalid");
+        };
+        assert!(msg.contains("endpoint <flag:8080>"), "{}", msg);
+        assert!(msg.contains("policy <unknown>"), "{}", msg);
+    }
+
+    #[test]
+    fn test_problems() {
+        let mut ctx = FlameContext {
+            applications: vec![Application {
+                name: "flmexec".to_string(),
+                shim: crate::apis::Shim::Log,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(ctx.problems().is_empty(), "{:?}", ctx.problems());
+
+        ctx.endpoint = "127.0.0.1:8080".to_string();
+        ctx.backend_endpoint = Some("unix://flame.sock".to_string());
+        ctx.socket_mode = Some("rw".to_string());
+        ctx.policy = "fifo".to_string();
+        ctx.storage = "mem".to_string();
+        ctx.slot = "cpu=1,mem=2x".to_string();
+        ctx.max_pending_tasks = Some(0);
+        ctx.cache = Some(FlameCacheConf {
+            max_entries: Some(0),
+            ttl: Some("1x".to_string()),
+        });
+        ctx.applications = vec![Application {
+            name: "pi".to_string(),
+            shim: crate::apis::Shim::Stdio,
+            working_directory: "tmp".to_string(),
+            ..Default::default()
+        }];
+        ctx.audit = Some(FlameAuditConf {
+            path: String::new(),
+            max_size: Some("10x".to_string()),
+            max_files: None,
+        });
+        ctx.telemetry = Some(FlameTelemetryConf {
+            otlp_endpoint: "127.0.0.1:4317".to_string(),
+        });
+        ctx.grpc = Some(FlameGrpcConf {
+            max_send_message_size: Some("0".to_string()),
+            max_recv_message_size: Some("16m".to_string()),
+            compression: Some("lz4".to_string()),
+        });
+        ctx.warm_pool = Some(FlameWarmPoolConf {
+            max_size: Some(0),
+            ttl: Some("10".to_string()),
+            min_available_memory: Some("1x".to_string()),
+        });
+        ctx.autoscaler = Some(FlameAutoscalerConf {
+            url: Some("https://127.0.0.1:9090/scale".to_string()),
+            scale_up_after: Some("1x".to_string()),
+            ..Default::default()
+        });
+        ctx.quarantine = Some(FlameQuarantineConf {
+            failures: Some(0),
+            window: None,
+            cooldown: Some("1x".to_string()),
+        });
+        ctx.timings = Some(FlameTimingsConf {
+            schedule_interval: Some("500x".to_string()),
+            ..Default::default()
+        });
+        ctx.monitor = Some(FlameMonitorConf {
+            lock_threshold: None,
+            engine_budget: Some("1x".to_string()),
+        });
+        ctx.engine_retry = Some(FlameEngineRetryConf {
+            attempts: Some(0),
+            backoff: Some("50x".to_string()),
+        });
+        ctx.rate_limit = Some(FlameRateLimitConf {
+            rate: 0.0,
+            burst: Some(0),
+        });
+
+        // Each problem is checked by its key, so that the order of the checks does not matter.
+        let problems = ctx.problems();
+        let expected = [
+            "endpoint <127.0.0.1:8080>",
+            "backend_endpoint <unix://flame.sock>",
+            "socket_mode <rw>",
+            "policy <fifo>",
+            "storage <mem>",
+            "slot <cpu=1,mem=2x>",
+            "max_pending_tasks: must be greater than 0",
+            "cache.max_entries: must be greater than 0",
+            "cache.ttl <1x>",
+            "application <pi>: command is required",
+            "application <pi>: working_directory <tmp> is not absolute",
+            "audit.path: empty path",
+            "audit.max_size <10x>",
+            "telemetry.otlp_endpoint <127.0.0.1:4317>",
+            "grpc.max_send_message_size <0>: must be greater than 0",
+            "grpc.compression <lz4>",
+            "warm_pool.max_size: must be greater than 0",
+            "warm_pool.ttl <10>",
+            "warm_pool.min_available_memory <1x>",
+            "autoscaler.url <https://127.0.0.1:9090/scale>: unsupported scheme <https>",
+            "autoscaler.scale_up_after <1x>",
+            "quarantine.failures: must be greater than 0",
+            "quarantine.cooldown <1x>",
+            "timings.schedule_interval <500x>",
+            "monitor.engine_budget <1x>",
+            "engine_retry.attempts: must be greater than 0",
+            "engine_retry.backoff <50x>",
+            "rate_limit.rate <0>: must be greater than 0",
+            "rate_limit.burst: must be greater than 0",
+        ];
+        for key in expected {
+            assert!(
+                problems
+                    .iter()
+                    .any(|p| p.trim_start_matches('\'').starts_with(key)),
+                "{} not in {:?}",
+                key,
+                problems
+            );
+        }
+        assert_eq!(problems.len(), expected.len(), "{:?}", problems);
+
+        let msg = ctx.validate().unwrap_err().to_string();
+        assert_eq!(msg.matches("; ").count(), expected.len() - 1);
+    }
+
+    #[test]
+    fn test_utilization_windows() {
+        let conf = |windows: Option<Vec<&str>>| FlameUtilizationConf {
+            windows: windows.map(|w| w.into_iter().map(str::to_string).collect()),
+        };
+        assert_eq!(
+            conf(None).windows().unwrap(),
+            vec![Duration::from_secs(300), Duration::from_secs(3600)]
+        );
+        assert_eq!(
+            conf(Some(vec!["1m", "1d"])).windows().unwrap(),
+            vec![Duration::from_secs(60), Duration::from_secs(86400)]
+        );
+        assert!(conf(None).problems().is_empty());
+
+        assert_eq!(
+            conf(Some(vec![])).problems(),
+            vec!["utilization.windows: at least one window is required"]
+        );
+        assert!(conf(Some(vec!["1m", "0s"])).problems()[0].contains("must be greater than 0"));
+        assert!(conf(Some(vec!["5x"])).problems()[0].contains("utilization.windows <5x>"));
+    }
+
+    #[test]
+    fn test_time_slice_problems() {
+        let slice = |tasks, duration: Option<&str>| FlameTimeSliceConf {
+            tasks,
+            duration: duration.map(str::to_string),
+        };
+        assert!(slice(Some(100), None).problems().is_empty());
+        assert!(slice(None, Some("5m")).problems().is_empty());
+        assert_eq!(
+            slice(Some(10), Some("5m")).duration().unwrap(),
+            Some(Duration::from_secs(300))
+        );
+
+        assert_eq!(
+            slice(None, None).problems(),
+            vec!["time_slice: either tasks or duration is required"]
+        );
+        let problems = slice(Some(0), Some("0s")).problems();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert_eq!(problems[0], "time_slice.tasks: must be greater than 0");
+        assert!(problems[1].contains("time_slice.duration: must be greater than 0"));
+        assert!(slice(None, Some("5x")).problems()[0].contains("time_slice.duration <5x>"));
+    }
+
+    #[test]
+    fn test_timings_problems() {
+        let timings = |heartbeat: &str, lease: &str| FlameTimingsConf {
+            heartbeat_interval: Some(heartbeat.to_string()),
+            lease: Some(lease.to_string()),
+            ..Default::default()
+        };
+        assert!(FlameTimingsConf::default().problems().is_empty());
+        assert!(timings("5s", "15s").problems().is_empty());
+
+        assert_eq!(
+            timings("30s", "10s").problems(),
+            vec!["timings.lease <10s>: must be longer than timings.heartbeat_interval <30s>"]
+        );
+        assert_eq!(
+            timings("10s", "10s").problems(),
+            vec!["timings.lease <10s>: must be longer than timings.heartbeat_interval <10s>"]
+        );
+        // The lease is checked against the default heartbeat interval too.
+        let lease = FlameTimingsConf {
+            lease: Some("5s".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            lease.problems(),
+            vec!["timings.lease <5s>: must be longer than timings.heartbeat_interval <10s>"]
+        );
+
+        let zero = FlameTimingsConf {
+            schedule_interval: Some("0s".to_string()),
+            sweep_interval: Some("1x".to_string()),
+            heartbeat_interval: Some("0ms".to_string()),
+            jitter: Some(1.5),
+            locality_wait: Some("3x".to_string()),
+            orphan_timeout: Some("0m".to_string()),
+            ..Default::default()
+        };
+        let problems = zero.problems();
+        assert_eq!(problems.len(), 6, "{:?}", problems);
+        assert!(problems[0].contains("timings.schedule_interval: must be greater than 0"));
+        assert!(problems[1].contains("timings.sweep_interval <1x>"));
+        assert!(problems[2].contains("timings.locality_wait <3x>"));
+        assert!(problems[3].contains("timings.orphan_timeout: must be greater than 0"));
+        assert!(problems[4].contains("timings.heartbeat_interval: must be greater than 0"));
+        assert_eq!(problems[5], "timings.jitter <1.5>: must be in [0, 1)");
+        assert_eq!(FlameTimingsConf::default().orphan_timeout().unwrap(), None);
+    }
+
+    #[test]
+    fn test_jittered() {
+        let interval = Duration::from_secs(1);
+        assert_eq!(FlameTimingsConf::default().jittered(interval), interval);
+
+        let timings = FlameTimingsConf {
+            jitter: Some(0.1),
+            ..Default::default()
+        };
+        let spread = (0..100)
+            .map(|_| timings.jittered(interval))
+            .collect::<HashSet<_>>();
+        assert!(spread.len() > 1);
+        for d in spread {
+            assert!(d >= Duration::from_millis(900) && d <= Duration::from_millis(1100));
+        }
+    }
+
+    #[test]
+    fn test_auth_problems() {
+        let token = |name: &str, token: &str, namespace: Option<&str>| FlameTokenConf {
+            name: name.to_string(),
+            token: token.to_string(),
+            namespace: namespace.map(str::to_string),
+        };
+        let mut ctx = FlameContext {
+            applications: vec![Application {
+                name: "flmexec".to_string(),
+                shim: crate::apis::Shim::Log,
+                ..Default::default()
+            }],
+            namespace: Some("team-a".to_string()),
+            auth: Some(FlameAuthConf {
+                tokens: vec![
+                    token("admin", "t0", None),
+                    token("team-a", "t1", Some("team-a")),
+                ],
+            }),
+            ..Default::default()
+        };
+        assert!(ctx.problems().is_empty(), "{:?}", ctx.problems());
+
+        ctx.namespace = Some("Team-A".to_string());
+        ctx.auth = Some(FlameAuthConf {
+            tokens: vec![
+                token("", "t0", None),
+                token("team-a", "t0", Some("team_a")),
+                token("team-b", "", None),
+            ],
+        });
+
+        let problems = ctx.problems();
+        assert_eq!(problems.len(), 5, "{:?}", problems);
+        assert!(problems[0].contains("invalid namespace <Team-A>"));
+        assert_eq!(problems[1], "auth.tokens[0]: empty name");
+        assert_eq!(problems[2], "auth.tokens[1]: duplicated token");
+        assert!(problems[3].starts_with("auth.tokens[1]: invalid argument <namespace>"));
+        assert_eq!(problems[4], "auth.tokens[2]: empty token");
+    }
+
+    #[test]
+    fn test_session_template_problems() {
+        let template = |name: &str, application: &str| FlameSessionTemplate {
+            name: name.to_string(),
+            application: application.to_string(),
+            slots: Some(2),
+            timeout: Some("8h".to_string()),
+            ..Default::default()
+        };
+        let mut ctx = FlameContext {
+            applications: vec![Application {
+                name: "flmexec".to_string(),
+                shim: crate::apis::Shim::Log,
+                ..Default::default()
+            }],
+            session_templates: vec![template("nightly-batch", "flmexec")],
+            ..Default::default()
+        };
+        assert!(ctx.problems().is_empty(), "{:?}", ctx.problems());
+
+        ctx.session_templates = vec![
+            template("nightly-batch", "flmexec"),
+            FlameSessionTemplate {
+                slots: Some(0),
+                timeout: Some("8x".to_string()),
+                ..template("nightly-batch", "pi")
+            },
+        ];
+
+        let problems = ctx.problems();
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert_eq!(
+            problems[0],
+            "session_templates <nightly-batch>: duplicated name"
+        );
+        assert_eq!(
+            problems[1],
+            "session_templates <nightly-batch>: unknown application <pi>"
+        );
+        assert!(problems[2].contains("slots must be greater than 0"));
+        assert!(problems[3].contains("timeout <8x>"));
+    }
+
+    #[test]
+    fn test_quota_problems() {
+        let quota = |namespace: Option<&str>, application: Option<&str>| FlameQuotaConf {
+            namespace: namespace.map(String::from),
+            application: application.map(String::from),
+            max_slots: Some(40),
+            ..Default::default()
+        };
+
+        let research = quota(Some("research"), None);
+        assert!(research.problems().is_empty(), "{:?}", research.problems());
+        assert!(research.matches("research", "pi"));
+        assert!(!research.matches("default", "pi"));
+        let pi = quota(Some("research"), Some("pi"));
+        assert!(pi.matches("research", "pi"));
+        assert!(!pi.matches("research", "flmexec"));
+        assert_eq!(pi.name(), "namespace=research,application=pi");
+
+        let ctx = FlameContext {
+            applications: vec![Application {
+                name: "flmexec".to_string(),
+                shim: crate::apis::Shim::Log,
+                ..Default::default()
+            }],
+            quotas: vec![
+                research.clone(),
+                research,
+                quota(None, None),
+                FlameQuotaConf {
+                    max_slots: Some(0),
+                    max_running_tasks: Some(0),
+                    ..quota(Some("Team-A"), None)
+                },
+            ],
+            ..Default::default()
+        };
+
+        let problems = ctx.problems();
+        assert_eq!(problems.len(), 5, "{:?}", problems);
+        assert_eq!(problems[0], "quotas <namespace=research>: duplicated quota");
+        assert_eq!(
+            problems[1],
+            "quotas: either namespace or application is required"
+        );
+        assert!(problems[2].starts_with("quotas <namespace=Team-A>: invalid"));
+        assert!(problems[3].contains("max_slots must be greater than 0"));
+        assert!(problems[4].contains("max_running_tasks must be greater than 0"));
+    }
+
+    #[test]
+    fn test_priority_class_problems() {
+        let class = |name: &str, weight| FlamePriorityClassConf {
+            name: name.to_string(),
+            weight,
+            reserved_executors: Some(2),
+        };
+
+        let interactive = class("interactive", Some(10));
+        assert!(interactive.problems().is_empty());
+        assert_eq!(class("batch", None).weight(), 1);
+        assert_eq!(FlamePriorityClassConf::default().reserved_executors(), 0);
+
+        let ctx = FlameContext {
+            applications: vec![Application {
+                name: "flmexec".to_string(),
+                shim: crate::apis::Shim::Log,
+                ..Default::default()
+            }],
+            priority_classes: vec![
+                interactive.clone(),
+                interactive,
+                class("", None),
+                class("batch", Some(0)),
+            ],
+            ..Default::default()
+        };
+
+        let problems = ctx.problems();
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert_eq!(
+            problems[0],
+            "priority_classes <interactive>: duplicated class"
+        );
+        assert_eq!(problems[1], "priority_classes: name is required");
+        assert_eq!(
+            problems[2],
+            "priority_classes <batch>: weight must be greater than 0"
+        );
+    }
+
+    #[test]
+    fn test_parse_files() {
+        let dir = std::env::temp_dir().join(format!("flame-layers-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("base.yaml");
+        let host = dir.join("host.yaml");
+        let empty = dir.join("empty.yaml");
+        fs::write(
+            &base,
+            serde_yaml::to_string(&FlameContext::default()).unwrap(),
+        )
+        .unwrap();
+        fs::write(
+            &host,
+            "endpoint: http://host:8080\napplications: []\nexecutor:\n  server_applications: true\n",
+        )
+        .unwrap();
+        fs::write(&empty, "").unwrap();
+
+        let files = [base, host, empty].map(|p| p.display().to_string());
+        let ctx = FlameContext::parse_files(&files).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(ctx.endpoint, "http://host:8080");
+        assert_eq!(ctx.storage, DEFAULT_STORAGE);
+        // The lists are replaced instead of merged.
+        assert!(ctx.applications.is_empty());
+        assert!(ctx.problems().is_empty(), "{:?}", ctx.problems());
+    }
+
+    #[test]
+    fn test_write_behind_problems() {
+        let conf: FlameContext = serde_yaml::from_str(
+            r#"
+name: flame
+endpoint: http://127.0.0.1:8080
+slot: cpu=1,mem=2g
+policy: proportion
+storage: sqlite://flame.db
+durability: batched
+write_behind:
+  max_batch: 0
+  flush_interval: 0s
+"#,
+        )
+        .unwrap();
+        assert_eq!(conf.durability, Some(Durability::Batched));
+
+        let problems = conf.write_behind.clone().unwrap_or_default().problems();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert_eq!(
+            problems[0],
+            "write_behind.max_batch: must be greater than 0"
+        );
+        assert!(problems[1].contains("write_behind.flush_interval: must be greater than 0"));
+
+        let defaults = FlameWriteBehindConf::default();
+        assert_eq!(defaults.max_batch(), 512);
+        assert_eq!(
+            defaults.flush_interval().unwrap(),
+            Duration::from_millis(20)
+        );
+    }
+
+    #[test]
+    fn test_executor_problems() {
+        let mut ctx = FlameContext {
+            executor: Some(FlameExecutorConf {
+                labels: HashMap::from([(String::new(), "a".to_string())]),
+                slots: Some(0),
+                scratch_root: Some("scratch".to_string()),
+                metrics_address: Some("localhost".to_string()),
+                server_applications: None,
+                locality_keys: vec!["shard-1".to_string(), " ".to_string()],
+            }),
+            ..Default::default()
+        };
+        ctx.applications.clear();
+
+        let problems = ctx.problems();
+        assert_eq!(problems.len(), 6, "{:?}", problems);
+        assert_eq!(problems[0], "no application");
+        assert_eq!(problems[1], "executor.labels: empty key");
+        assert_eq!(problems[2], "executor.locality_keys: empty key");
+        assert_eq!(problems[3], "executor.slots: must be greater than 0");
+        assert!(problems[4].starts_with("executor.scratch_root <scratch>"));
+        assert!(problems[5].starts_with("executor.metrics_address <localhost>"));
+    }
+
+    #[test]
+    fn test_redacted() {
+        let mut ctx = FlameContext {
+            storage: "postgres://flame:passwd@db:5432/flame".to_string(),
+            client: Some(FlameClientConf {
+                token: Some("abc".to_string()),
+                tls: Some(FlameTlsConf {
+                    ca_file: Some("/etc/flame/ca.pem".to_string()),
+                    key_file: Some("/etc/flame/client.key".to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            auth: Some(FlameAuthConf {
+                tokens: vec![FlameTokenConf {
+                    name: "team-a".to_string(),
+                    token: "def".to_string(),
+                    namespace: None,
+                }],
+            }),
+            ..Default::default()
+        };
+        ctx.applications[0].environments = vec!["DB_PASSWORD=ghi".to_string()];
+
+        let ctx = ctx.redacted();
+        assert_eq!(ctx.storage, "postgres://flame:******@db:5432/flame");
+        let client = ctx.client.unwrap();
+        assert_eq!(client.token.as_deref(), Some(REDACTED));
+        let tls = client.tls.unwrap();
+        assert_eq!(tls.key_file.as_deref(), Some(REDACTED));
+        assert_eq!(tls.ca_file.as_deref(), Some("/etc/flame/ca.pem"));
+        let auth = ctx.auth.unwrap();
+        assert_eq!(auth.tokens[0].token, REDACTED);
+        assert_eq!(auth.tokens[0].name, "team-a");
+        assert_eq!(ctx.applications[0].environments, vec!["DB_PASSWORD=******"]);
+        assert_eq!(ctx.slot, DEFAULT_SLOT);
+    }
+}
@@ -0,0 +1,98 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The endpoints of the session manager: `http(s)://host:port` over TCP, or
+//! `unix:///path/to/flame.sock` over a unix domain socket, e.g. for single-node deployments.
+
+use std::path::PathBuf;
+
+use tokio::net::UnixStream;
+use tonic::transport::{Channel, Endpoint, Error, Uri};
+
+use crate::FlameError;
+
+const UNIX_SCHEME: &str = "unix://";
+/// The placeholder of the unix sockets, which is only used as the authority of the requests.
+const UNIX_AUTHORITY: &str = "http://localhost";
+
+/// Returns the path of the socket if the endpoint is a unix domain socket.
+pub fn socket_path(endpoint: &str) -> Option<PathBuf> {
+    endpoint.strip_prefix(UNIX_SCHEME).map(PathBuf::from)
+}
+
+/// Returns the problem of the endpoint, if any.
+pub fn validate(endpoint: &str) -> Result<(), String> {
+    if let Some(path) = socket_path(endpoint) {
+        return match path.is_absolute() {
+            true => Ok(()),
+            false => Err("the path of the socket must be absolute".to_string()),
+        };
+    }
+
+    match url::Url::parse(endpoint) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Ok(()),
+        Ok(url) => Err(format!("unsupported scheme <{}>", url.scheme())),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// The endpoint of tonic to configure, e.g. the timeouts; it's a placeholder for the unix
+/// sockets, which are connected by `connect`.
+pub fn to_endpoint(endpoint: &str) -> Result<Endpoint, FlameError> {
+    let addr = match socket_path(endpoint) {
+        Some(_) => UNIX_AUTHORITY.to_string(),
+        None => endpoint.to_string(),
+    };
+
+    Endpoint::from_shared(addr)
+        .map_err(|_| FlameError::invalid_argument("endpoint", format!("invalid <{}>", endpoint)))
+}
+
+/// Connects the endpoint of tonic, which was built by `to_endpoint`, over TCP or the unix socket.
+pub async fn connect(endpoint: &str, channel: Endpoint) -> Result<Channel, Error> {
+    match socket_path(endpoint) {
+        Some(path) => {
+            channel
+                .connect_with_connector(tower::service_fn(move |_: Uri| {
+                    UnixStream::connect(path.clone())
+                }))
+                .await
+        }
+        None => channel.connect().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("http://127.0.0.1:8080").is_ok());
+        assert!(validate("unix:///run/flame/flame.sock").is_ok());
+        assert_eq!(
+            socket_path("unix:///run/flame/flame.sock"),
+            Some(PathBuf::from("/run/flame/flame.sock"))
+        );
+        assert_eq!(socket_path("http://127.0.0.1:8080"), None);
+
+        for (endpoint, expected) in [
+            ("unix://flame.sock", "must be absolute"),
+            ("tcp://127.0.0.1:8080", "unsupported scheme <tcp>"),
+            ("127.0.0.1:8080", "relative URL without a base"),
+        ] {
+            let e = validate(endpoint).unwrap_err();
+            assert!(e.contains(expected), "{}", e);
+        }
+    }
+}
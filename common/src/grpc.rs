@@ -0,0 +1,177 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The options of the gRPC channels between the clients, the session manager and the
+//! executor managers, e.g. the compression and the size limits of the messages.
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use tonic::codec::CompressionEncoding;
+
+use crate::FlameError;
+
+/// The maximum size of the messages by default, which is the default of tonic.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 << 20;
+
+/// The encodings accepted from the peers, i.e. all the ones built in.
+pub const ACCEPTED_ENCODINGS: &[CompressionEncoding] = &[
+    #[cfg(feature = "gzip")]
+    CompressionEncoding::Gzip,
+    #[cfg(feature = "zstd")]
+    CompressionEncoding::Zstd,
+];
+
+/// The compression of the sent messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Gzip => write!(f, "gzip"),
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = FlameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(FlameError::InvalidConfig(format!(
+                "grpc.compression <{}>: expect one of none, gzip, zstd",
+                s
+            ))),
+        }
+    }
+}
+
+impl Compression {
+    /// The encoding of tonic; it fails if the codec is not built in.
+    pub fn encoding(&self) -> Result<Option<CompressionEncoding>, FlameError> {
+        match self {
+            Compression::None => Ok(None),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => Ok(Some(CompressionEncoding::Gzip)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(Some(CompressionEncoding::Zstd)),
+            #[allow(unreachable_patterns)]
+            _ => Err(FlameError::InvalidConfig(format!(
+                "grpc.compression <{}> is not enabled, rebuild with feature <{}>",
+                self, self
+            ))),
+        }
+    }
+}
+
+/// The resolved options of a channel, see `FlameGrpcConf`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GrpcOptions {
+    pub max_send_message_size: usize,
+    pub max_recv_message_size: usize,
+    pub compression: Option<CompressionEncoding>,
+}
+
+impl Default for GrpcOptions {
+    fn default() -> Self {
+        GrpcOptions {
+            max_send_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_recv_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            compression: None,
+        }
+    }
+}
+
+impl GrpcOptions {
+    /// Checks the payload before sending it, so it's rejected with the limit instead of a
+    /// reset of the transport; the payload is compressed after the check if enabled.
+    pub fn check_size(&self, field: &str, size: usize) -> Result<(), FlameError> {
+        if size <= self.max_send_message_size {
+            return Ok(());
+        }
+
+        Err(FlameError::invalid_argument(
+            field,
+            format!(
+                "{} bytes exceeds the message size limit of {} bytes, raise grpc.max_send_message_size",
+                size, self.max_send_message_size
+            ),
+        ))
+    }
+}
+
+/// Applies the options to a client or a server generated by tonic.
+#[macro_export]
+macro_rules! with_grpc_options {
+    ( $svc:expr, $options:expr ) => {{
+        let options: &$crate::grpc::GrpcOptions = &$options;
+        let mut svc = $svc
+            .max_encoding_message_size(options.max_send_message_size)
+            .max_decoding_message_size(options.max_recv_message_size);
+        if let Some(encoding) = options.compression {
+            svc = svc.send_compressed(encoding);
+        }
+        for encoding in $crate::grpc::ACCEPTED_ENCODINGS {
+            svc = svc.accept_compressed(*encoding);
+        }
+        svc
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression() {
+        for c in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            assert_eq!(c.to_string().parse::<Compression>().unwrap(), c);
+        }
+        assert!("lz4".parse::<Compression>().is_err());
+        assert_eq!(Compression::None.encoding().unwrap(), None);
+
+        #[cfg(not(feature = "gzip"))]
+        assert!(Compression::Gzip
+            .encoding()
+            .unwrap_err()
+            .to_string()
+            .contains("rebuild with feature <gzip>"));
+    }
+
+    #[test]
+    fn test_check_size() {
+        let options = GrpcOptions {
+            max_send_message_size: 1024,
+            ..Default::default()
+        };
+        assert!(options.check_size("input", 1024).is_ok());
+
+        let e = options.check_size("input", 1025).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "invalid argument <input>: 1025 bytes exceeds the message size limit of 1024 bytes, \
+             raise grpc.max_send_message_size"
+        );
+    }
+}
@@ -12,23 +12,60 @@ limitations under the License.
 */
 
 pub mod apis;
+pub mod audit;
+pub mod capability;
 pub mod ctx;
+pub mod endpoint;
+pub mod grpc;
+pub mod message;
+pub mod monitor;
+pub mod payload;
 pub mod ptr;
+pub mod resources;
 pub mod trace;
 
+use std::error::Error as StdError;
+use std::time::Duration;
+
+use bytes::Bytes;
+use prost::Message;
 use thiserror::Error;
-use tonic::Status;
+use tonic::{Code, Status};
+
+use rpc::flame::ErrorDetail;
+
+pub type BoxError = Box<dyn StdError + Send + Sync>;
 
 #[derive(Error, Debug)]
 pub enum FlameError {
     #[error("'{0}' not found")]
     NotFound(String),
 
+    #[error("'{0}' already exists")]
+    AlreadyExists(String),
+
+    #[error("invalid argument <{field}>: {message}")]
+    InvalidArgument { field: String, message: String },
+
     #[error("'{0}'")]
-    Internal(String),
+    FailedPrecondition(String),
+
+    /// The request is rejected until the resource is released, e.g. the backlog of the
+    /// session is full; the caller should wait `retry_after` before retrying it.
+    #[error("'{message}'")]
+    ResourceExhausted {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+
+    #[error("'{message}'")]
+    Unavailable { message: String, retryable: bool },
+
+    #[error("storage: {0}")]
+    Storage(#[source] BoxError),
 
     #[error("'{0}'")]
-    Network(String),
+    Internal(String),
 
     #[error("'{0}'")]
     InvalidConfig(String),
@@ -36,36 +73,176 @@ pub enum FlameError {
     #[error("'{0}' is not initialized")]
     Uninitialized(String),
 
-    #[error("'{0}'")]
-    InvalidState(String),
+    #[error("unauthenticated: {0}")]
+    Unauthenticated(String),
 
-    #[error("'{0}'")]
-    Storage(String),
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+
+    /// The deadline of the caller passed, so the call was not done.
+    #[error("deadline exceeded: {0}")]
+    DeadlineExceeded(String),
+}
+
+impl FlameError {
+    pub fn invalid_argument(field: &str, message: impl Into<String>) -> Self {
+        FlameError::InvalidArgument {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+
+    /// The failure of the storage, e.g. `.map_err(FlameError::storage)`.
+    pub fn storage(e: impl Into<BoxError>) -> Self {
+        FlameError::Storage(e.into())
+    }
+
+    /// Whether the request may succeed if it's retried later.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            FlameError::Unavailable {
+                retryable: true,
+                ..
+            } | FlameError::ResourceExhausted { .. }
+                | FlameError::Uninitialized(_)
+        )
+    }
+
+    /// How long to wait before retrying the request, if the server hints it.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            FlameError::ResourceExhausted { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            FlameError::NotFound(_) => "NotFound",
+            FlameError::AlreadyExists(_) => "AlreadyExists",
+            FlameError::InvalidArgument { .. } => "InvalidArgument",
+            FlameError::FailedPrecondition(_) => "FailedPrecondition",
+            FlameError::ResourceExhausted { .. } => "ResourceExhausted",
+            FlameError::Unavailable { .. } => "Unavailable",
+            FlameError::Storage(_) => "Storage",
+            FlameError::Internal(_) => "Internal",
+            FlameError::InvalidConfig(_) => "InvalidConfig",
+            FlameError::Uninitialized(_) => "Uninitialized",
+            FlameError::Unauthenticated(_) => "Unauthenticated",
+            FlameError::PermissionDenied(_) => "PermissionDenied",
+            FlameError::DeadlineExceeded(_) => "DeadlineExceeded",
+        }
+    }
+
+    fn code(&self) -> Code {
+        match self {
+            FlameError::NotFound(_) => Code::NotFound,
+            FlameError::AlreadyExists(_) => Code::AlreadyExists,
+            FlameError::InvalidArgument { .. } | FlameError::InvalidConfig(_) => {
+                Code::InvalidArgument
+            }
+            FlameError::FailedPrecondition(_) => Code::FailedPrecondition,
+            FlameError::ResourceExhausted { .. } => Code::ResourceExhausted,
+            FlameError::Unavailable { .. } | FlameError::Uninitialized(_) => Code::Unavailable,
+            FlameError::Storage(_) | FlameError::Internal(_) => Code::Internal,
+            FlameError::Unauthenticated(_) => Code::Unauthenticated,
+            FlameError::PermissionDenied(_) => Code::PermissionDenied,
+            FlameError::DeadlineExceeded(_) => Code::DeadlineExceeded,
+        }
+    }
 }
 
 impl From<FlameError> for Status {
     fn from(value: FlameError) -> Self {
-        match value {
-            FlameError::NotFound(s) => Status::not_found(s),
-            FlameError::Internal(s) => Status::internal(s),
-            _ => Status::unknown("unknown"),
-        }
+        let detail = ErrorDetail {
+            kind: value.kind().to_string(),
+            field: match &value {
+                FlameError::InvalidArgument { field, .. } => Some(field.clone()),
+                _ => None,
+            },
+            retryable: value.is_retryable(),
+            retry_after_ms: value.retry_after().map(|d| d.as_millis() as u64),
+        };
+
+        let message = match &value {
+            FlameError::NotFound(s)
+            | FlameError::AlreadyExists(s)
+            | FlameError::FailedPrecondition(s)
+            | FlameError::Internal(s)
+            | FlameError::InvalidConfig(s)
+            | FlameError::Uninitialized(s)
+            | FlameError::Unauthenticated(s)
+            | FlameError::PermissionDenied(s)
+            | FlameError::DeadlineExceeded(s) => s.clone(),
+            FlameError::InvalidArgument { message, .. } => message.clone(),
+            FlameError::Unavailable { message, .. }
+            | FlameError::ResourceExhausted { message, .. } => message.clone(),
+            FlameError::Storage(e) => e.to_string(),
+        };
+
+        Status::with_details(value.code(), message, Bytes::from(detail.encode_to_vec()))
     }
 }
 
 impl From<Status> for FlameError {
     fn from(value: Status) -> Self {
-        FlameError::Network(value.code().to_string())
+        let message = value.message().to_string();
+        let detail = ErrorDetail::decode(value.details()).unwrap_or_default();
+
+        match (detail.kind.as_str(), value.code()) {
+            ("NotFound", _) | ("", Code::NotFound) => FlameError::NotFound(message),
+            ("AlreadyExists", _) | ("", Code::AlreadyExists) => FlameError::AlreadyExists(message),
+            ("InvalidArgument", _) | ("", Code::InvalidArgument) => FlameError::InvalidArgument {
+                field: detail.field.unwrap_or_default(),
+                message,
+            },
+            ("FailedPrecondition", _) | ("", Code::FailedPrecondition) => {
+                FlameError::FailedPrecondition(message)
+            }
+            ("ResourceExhausted", _) | ("", Code::ResourceExhausted) => {
+                FlameError::ResourceExhausted {
+                    message,
+                    retry_after: detail.retry_after_ms.map(Duration::from_millis),
+                }
+            }
+            ("Storage", _) => FlameError::Storage(message.into()),
+            ("InvalidConfig", _) => FlameError::InvalidConfig(message),
+            ("Uninitialized", _) => FlameError::Uninitialized(message),
+            ("Unauthenticated", _) | ("", Code::Unauthenticated) => {
+                FlameError::Unauthenticated(message)
+            }
+            ("PermissionDenied", _) | ("", Code::PermissionDenied) => {
+                FlameError::PermissionDenied(message)
+            }
+            ("DeadlineExceeded", _) => FlameError::DeadlineExceeded(message),
+            ("Unavailable", _) => FlameError::Unavailable {
+                message,
+                retryable: detail.retryable,
+            },
+            ("", Code::Unavailable | Code::DeadlineExceeded | Code::Aborted) => {
+                FlameError::Unavailable {
+                    message,
+                    retryable: true,
+                }
+            }
+            _ => FlameError::Internal(message),
+        }
     }
 }
 
+/// Locks the mutex; the acquisitions waiting longer than the threshold of `monitor` are
+/// logged with the call-site.
 #[macro_export]
 macro_rules! lock_ptr {
-    ( $mutex_arc:expr ) => {
-        $mutex_arc
+    ( $mutex_arc:expr ) => {{
+        let start = $crate::monitor::lock_start();
+        let guard = $mutex_arc
             .lock()
-            .map_err(|_| FlameError::Internal("mutex ptr".to_string()))
-    };
+            .map_err(|_| FlameError::Internal("mutex ptr".to_string()));
+        $crate::monitor::lock_acquired(start, concat!(file!(), ":", line!()));
+        guard
+    }};
 }
 
 #[macro_export]
@@ -124,3 +301,225 @@ macro_rules! lock_async_ptr {
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rpc::flame::backend_client::BackendClient;
+    use rpc::flame::backend_server::{Backend, BackendServer};
+    use rpc::flame::{
+        Application, BindExecutorCompletedRequest, BindExecutorRequest, BindExecutorResponse,
+        CompleteTaskRequest, GetApplicationRequest, HeartbeatRequest, LaunchTaskRequest,
+        LaunchTaskResponse, RegisterExecutorRequest, ReportTaskProgressRequest,
+        Result as RpcResult, StreamTaskOutputRequest, UnbindExecutorCompletedRequest,
+        UnbindExecutorRequest, UnregisterExecutorRequest,
+    };
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::{Request, Response, Streaming};
+
+    fn errors() -> Vec<(FlameError, Code)> {
+        vec![
+            (FlameError::NotFound("ssn".to_string()), Code::NotFound),
+            (
+                FlameError::AlreadyExists("app".to_string()),
+                Code::AlreadyExists,
+            ),
+            (
+                FlameError::invalid_argument("session_id", "not a number"),
+                Code::InvalidArgument,
+            ),
+            (
+                FlameError::FailedPrecondition("closed".to_string()),
+                Code::FailedPrecondition,
+            ),
+            (
+                FlameError::ResourceExhausted {
+                    message: "slots".to_string(),
+                    retry_after: None,
+                },
+                Code::ResourceExhausted,
+            ),
+            (
+                FlameError::ResourceExhausted {
+                    message: "backlog".to_string(),
+                    retry_after: Some(Duration::from_millis(1500)),
+                },
+                Code::ResourceExhausted,
+            ),
+            (
+                FlameError::Unavailable {
+                    message: "busy".to_string(),
+                    retryable: true,
+                },
+                Code::Unavailable,
+            ),
+            (
+                FlameError::Unavailable {
+                    message: "draining".to_string(),
+                    retryable: false,
+                },
+                Code::Unavailable,
+            ),
+            (FlameError::storage("disk I/O error"), Code::Internal),
+            (FlameError::Internal("bug".to_string()), Code::Internal),
+            (
+                FlameError::InvalidConfig("slot".to_string()),
+                Code::InvalidArgument,
+            ),
+            (
+                FlameError::Uninitialized("client".to_string()),
+                Code::Unavailable,
+            ),
+            (
+                FlameError::Unauthenticated("invalid token".to_string()),
+                Code::Unauthenticated,
+            ),
+            (
+                FlameError::PermissionDenied("namespace <team-b>".to_string()),
+                Code::PermissionDenied,
+            ),
+            (
+                FlameError::DeadlineExceeded("create_task".to_string()),
+                Code::DeadlineExceeded,
+            ),
+        ]
+    }
+
+    /// Returns the error at the index of the executor id.
+    fn error(executor_id: String) -> Status {
+        let index: usize = executor_id.parse().unwrap();
+        let (e, _) = errors().swap_remove(index);
+        Status::from(e)
+    }
+
+    struct ErrorServer;
+
+    #[tonic::async_trait]
+    impl Backend for ErrorServer {
+        async fn register_executor(
+            &self,
+            req: Request<RegisterExecutorRequest>,
+        ) -> Result<Response<RpcResult>, Status> {
+            Err(error(req.into_inner().executor_id))
+        }
+        async fn unregister_executor(
+            &self,
+            req: Request<UnregisterExecutorRequest>,
+        ) -> Result<Response<RpcResult>, Status> {
+            Err(error(req.into_inner().executor_id))
+        }
+        async fn bind_executor(
+            &self,
+            req: Request<BindExecutorRequest>,
+        ) -> Result<Response<BindExecutorResponse>, Status> {
+            Err(error(req.into_inner().executor_id))
+        }
+        async fn bind_executor_completed(
+            &self,
+            req: Request<BindExecutorCompletedRequest>,
+        ) -> Result<Response<RpcResult>, Status> {
+            Err(error(req.into_inner().executor_id))
+        }
+        async fn unbind_executor(
+            &self,
+            req: Request<UnbindExecutorRequest>,
+        ) -> Result<Response<RpcResult>, Status> {
+            Err(error(req.into_inner().executor_id))
+        }
+        async fn unbind_executor_completed(
+            &self,
+            req: Request<UnbindExecutorCompletedRequest>,
+        ) -> Result<Response<RpcResult>, Status> {
+            Err(error(req.into_inner().executor_id))
+        }
+        async fn launch_task(
+            &self,
+            req: Request<LaunchTaskRequest>,
+        ) -> Result<Response<LaunchTaskResponse>, Status> {
+            Err(error(req.into_inner().executor_id))
+        }
+        async fn complete_task(
+            &self,
+            req: Request<CompleteTaskRequest>,
+        ) -> Result<Response<RpcResult>, Status> {
+            Err(error(req.into_inner().executor_id))
+        }
+        async fn report_task_progress(
+            &self,
+            req: Request<ReportTaskProgressRequest>,
+        ) -> Result<Response<RpcResult>, Status> {
+            Err(error(req.into_inner().executor_id))
+        }
+        async fn stream_task_output(
+            &self,
+            req: Request<Streaming<StreamTaskOutputRequest>>,
+        ) -> Result<Response<RpcResult>, Status> {
+            let req = req.into_inner().message().await?.unwrap_or_default();
+            Err(error(req.executor_id))
+        }
+        async fn heartbeat(
+            &self,
+            req: Request<HeartbeatRequest>,
+        ) -> Result<Response<RpcResult>, Status> {
+            Err(error(req.into_inner().executor_id))
+        }
+        async fn get_application(
+            &self,
+            req: Request<GetApplicationRequest>,
+        ) -> Result<Response<Application>, Status> {
+            Err(error(req.into_inner().name))
+        }
+    }
+
+    #[test]
+    fn test_source() {
+        let e = FlameError::storage(std::io::Error::other("disk I/O error"));
+        assert_eq!(e.source().unwrap().to_string(), "disk I/O error");
+        assert_eq!(e.to_string(), "storage: disk I/O error");
+
+        assert!(FlameError::NotFound("ssn".to_string()).source().is_none());
+    }
+
+    #[test]
+    fn test_status_without_detail() {
+        let e = FlameError::from(Status::unavailable("connection refused"));
+        assert!(matches!(e, FlameError::Unavailable { .. }));
+        assert!(e.is_retryable());
+
+        let e = FlameError::from(Status::permission_denied("denied"));
+        assert!(matches!(e, FlameError::PermissionDenied(_)));
+
+        let e = FlameError::from(Status::data_loss("lost"));
+        assert!(matches!(e, FlameError::Internal(_)));
+    }
+
+    #[tokio::test]
+    async fn test_status_round_trip() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(BackendServer::new(ErrorServer))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let mut client = BackendClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap();
+
+        for (index, (expected, code)) in errors().into_iter().enumerate() {
+            let req = LaunchTaskRequest {
+                executor_id: index.to_string(),
+            };
+            let status = client.launch_task(req).await.unwrap_err();
+            assert_eq!(status.code(), code, "{:?}", expected);
+
+            let e = FlameError::from(status);
+            assert_eq!(e.to_string(), expected.to_string());
+            assert_eq!(e.kind(), expected.kind());
+            assert_eq!(e.is_retryable(), expected.is_retryable());
+            assert_eq!(e.retry_after(), expected.retry_after());
+        }
+    }
+}
@@ -0,0 +1,257 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The typed payloads of the tasks. A payload is an envelope of the encoded value:
+//!
+//! ```text
+//! +---------+-------+-------------------+
+//! | version | codec | the encoded value |
+//! +---------+-------+-------------------+
+//! ```
+//!
+//! so the executors could detect the payloads of other versions or codecs, instead of
+//! decoding them as garbage.
+
+use std::fmt::{Display, Formatter};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::apis::{TaskContext, TaskInput, TaskOutput};
+use crate::FlameError;
+
+/// The version of the envelope.
+pub const VERSION: u8 = 1;
+
+const HEADER_LEN: usize = 2;
+
+/// The codec of the value in the envelope.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Codec {
+    #[default]
+    Json = 1,
+    #[cfg(feature = "bincode")]
+    Bincode = 2,
+}
+
+impl Display for Codec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Codec::Json => write!(f, "json"),
+            #[cfg(feature = "bincode")]
+            Codec::Bincode => write!(f, "bincode"),
+        }
+    }
+}
+
+impl Codec {
+    fn from_tag(tag: u8) -> Result<Self, FlameError> {
+        match tag {
+            1 => Ok(Codec::Json),
+            #[cfg(feature = "bincode")]
+            2 => Ok(Codec::Bincode),
+            #[cfg(not(feature = "bincode"))]
+            2 => Err(invalid(
+                "codec <bincode> is not enabled, rebuild with feature <bincode>",
+            )),
+            _ => Err(invalid(format!("unknown codec <{}>", tag))),
+        }
+    }
+
+    fn encode<T: Serialize>(&self, value: &T, buf: &mut BytesMut) -> Result<(), FlameError> {
+        match self {
+            Codec::Json => serde_json::to_writer(buf.writer(), value)
+                .map_err(|e| FlameError::Internal(format!("failed to encode by json: {}", e))),
+            #[cfg(feature = "bincode")]
+            Codec::Bincode => bincode::serialize_into(buf.writer(), value)
+                .map_err(|e| FlameError::Internal(format!("failed to encode by bincode: {}", e))),
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, FlameError> {
+        match self {
+            Codec::Json => serde_json::from_slice(data)
+                .map_err(|e| invalid(format!("failed to decode by json: {}", e))),
+            #[cfg(feature = "bincode")]
+            Codec::Bincode => bincode::deserialize(data)
+                .map_err(|e| invalid(format!("failed to decode by bincode: {}", e))),
+        }
+    }
+}
+
+fn invalid(message: impl Into<String>) -> FlameError {
+    FlameError::invalid_argument("payload", message)
+}
+
+/// Encodes the value into an envelope by the codec.
+pub fn encode<T: Serialize>(codec: Codec, value: &T) -> Result<Bytes, FlameError> {
+    let mut buf = BytesMut::new();
+    buf.put_u8(VERSION);
+    buf.put_u8(codec as u8);
+    codec.encode(value, &mut buf)?;
+
+    Ok(buf.freeze())
+}
+
+/// Decodes the value from the envelope by the codec in it.
+pub fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, FlameError> {
+    if data.len() < HEADER_LEN {
+        return Err(invalid(format!(
+            "the payload is too short ({} bytes) for an envelope",
+            data.len()
+        )));
+    }
+
+    let (version, codec) = (data[0], data[1]);
+    if version != VERSION {
+        return Err(invalid(format!(
+            "unsupported envelope version <{}>, expect <{}>",
+            version, VERSION
+        )));
+    }
+
+    Codec::from_tag(codec)?.decode(&data[HEADER_LEN..])
+}
+
+/// The codec of the envelope, or None if the data is not an envelope of this version, e.g.
+/// the raw bytes of the clients which don't use the typed payloads.
+pub fn codec_of(data: &[u8]) -> Option<Codec> {
+    match data {
+        [VERSION, codec, ..] => Codec::from_tag(*codec).ok(),
+        _ => None,
+    }
+}
+
+pub fn to_task_input<T: Serialize>(value: &T) -> Result<TaskInput, FlameError> {
+    encode(Codec::default(), value)
+}
+
+pub fn from_task_input<T: DeserializeOwned>(input: &TaskInput) -> Result<T, FlameError> {
+    decode(input)
+}
+
+pub fn to_task_output<T: Serialize>(value: &T) -> Result<TaskOutput, FlameError> {
+    encode(Codec::default(), value)
+}
+
+pub fn from_task_output<T: DeserializeOwned>(output: &TaskOutput) -> Result<T, FlameError> {
+    decode(output)
+}
+
+impl TaskContext {
+    /// Decodes the input of the task, which was encoded by `to_task_input`.
+    pub fn input_as<T: DeserializeOwned>(&self) -> Result<T, FlameError> {
+        let input = self
+            .input
+            .as_ref()
+            .ok_or_else(|| invalid(format!("no input in task <{}/{}>", self.ssn_id, self.id)))?;
+
+        decode(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Sum {
+        a: i64,
+        b: i64,
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let sum = Sum { a: 1, b: 2 };
+
+        let input = to_task_input(&sum).unwrap();
+        assert_eq!(&input[..HEADER_LEN], &[VERSION, Codec::Json as u8]);
+        assert_eq!(&input[HEADER_LEN..], br#"{"a":1,"b":2}"#);
+        assert_eq!(from_task_input::<Sum>(&input).unwrap(), sum);
+
+        let ctx = TaskContext {
+            id: "1".to_string(),
+            ssn_id: "2".to_string(),
+            input: Some(input),
+            output: None,
+            trace_context: None,
+            locality: None,
+            env: Default::default(),
+        };
+        assert_eq!(ctx.input_as::<Sum>().unwrap(), sum);
+
+        let output = to_task_output(&3).unwrap();
+        assert_eq!(from_task_output::<i64>(&output).unwrap(), 3);
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_bincode() {
+        let sum = Sum { a: 1, b: 2 };
+        let data = encode(Codec::Bincode, &sum).unwrap();
+        assert_eq!(data[1], Codec::Bincode as u8);
+        assert_eq!(decode::<Sum>(&data).unwrap(), sum);
+    }
+
+    #[test]
+    fn test_corrupt_payload() {
+        let cases: Vec<(&[u8], &str)> = vec![
+            (b"", "too short"),
+            (br#"{"a":1,"b":2}"#, "unsupported envelope version <123>"),
+            (&[VERSION, 9, b'1'], "unknown codec <9>"),
+            (
+                &[VERSION, Codec::Json as u8, b'{'],
+                "failed to decode by json",
+            ),
+            (
+                &[VERSION, Codec::Json as u8, b'1'],
+                "failed to decode by json",
+            ),
+        ];
+
+        for (data, expected) in cases {
+            let e = decode::<Sum>(data).unwrap_err();
+            assert!(
+                matches!(e, FlameError::InvalidArgument { ref field, .. } if field == "payload"),
+                "{:?}",
+                e
+            );
+            assert!(e.to_string().contains(expected), "{}", e);
+        }
+
+        let ctx = TaskContext {
+            id: "1".to_string(),
+            ssn_id: "2".to_string(),
+            input: None,
+            output: None,
+            trace_context: None,
+            locality: None,
+            env: Default::default(),
+        };
+        let e = ctx.input_as::<Sum>().unwrap_err();
+        assert!(e.to_string().contains("no input in task <2/1>"), "{}", e);
+    }
+
+    #[test]
+    fn test_codec_of() {
+        let input = to_task_input(&Sum { a: 1, b: 2 }).unwrap();
+        assert_eq!(codec_of(&input), Some(Codec::Json));
+
+        for data in [&b""[..], b"a b c", &[VERSION], &[VERSION, 9, b'1']] {
+            assert_eq!(codec_of(data), None);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// The threshold of the wait for a lock in nanoseconds; the waits are not timed if it's 0.
+static LOCK_THRESHOLD: AtomicU64 = AtomicU64::new(0);
+/// The number of the acquisitions of the locks which waited longer than the threshold.
+static SLOW_LOCKS: AtomicU64 = AtomicU64::new(0);
+
+/// Sets the threshold of the wait for the locks taken by `lock_ptr!`, or disables the
+/// monitoring of the locks if none.
+pub fn set_lock_threshold(threshold: Option<Duration>) {
+    let nanos = threshold.map(|t| t.as_nanos().clamp(1, u64::MAX as u128) as u64);
+    LOCK_THRESHOLD.store(nanos.unwrap_or_default(), Ordering::Relaxed);
+}
+
+/// The start of the wait for a lock, none if the locks are not monitored.
+#[inline]
+pub fn lock_start() -> Option<Instant> {
+    match LOCK_THRESHOLD.load(Ordering::Relaxed) {
+        0 => None,
+        _ => Some(Instant::now()),
+    }
+}
+
+/// Logs and counts the acquisition of the lock at the call-site if it waited longer than the
+/// threshold since `start`.
+#[inline]
+pub fn lock_acquired(start: Option<Instant>, site: &'static str) {
+    if let Some(start) = start {
+        record_lock_wait(site, start.elapsed());
+    }
+}
+
+fn record_lock_wait(site: &'static str, waited: Duration) {
+    let threshold = LOCK_THRESHOLD.load(Ordering::Relaxed);
+    if threshold == 0 || waited < Duration::from_nanos(threshold) {
+        return;
+    }
+
+    SLOW_LOCKS.fetch_add(1, Ordering::Relaxed);
+    tracing::warn!(
+        site,
+        waited_ms = waited.as_millis() as u64,
+        "The lock was acquired after waiting {:?} at <{}>.",
+        waited,
+        site
+    );
+}
+
+/// The number of the acquisitions of the locks which waited longer than the threshold.
+pub fn slow_locks() -> u64 {
+    SLOW_LOCKS.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use tracing::field::{Field, Visit};
+    use tracing::{Event, Subscriber};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::Layer;
+
+    use super::*;
+    use crate::{lock_ptr, FlameError};
+
+    /// Captures the call-sites of the warnings.
+    #[derive(Clone, Default)]
+    struct Sites(Arc<Mutex<Vec<String>>>);
+
+    impl Visit for Sites {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            if field.name() == "site" {
+                self.0.lock().unwrap().push(value.to_string());
+            }
+        }
+
+        fn record_debug(&mut self, _: &Field, _: &dyn std::fmt::Debug) {}
+    }
+
+    impl<S: Subscriber> Layer<S> for Sites {
+        fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+            event.record(&mut self.clone());
+        }
+    }
+
+    #[test]
+    fn test_slow_lock() -> Result<(), FlameError> {
+        let sites = Sites::default();
+        let subscriber = tracing_subscriber::registry().with(sites.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let data = Arc::new(Mutex::new(0));
+        let hold = |data: Arc<Mutex<i32>>| {
+            let (tx, rx) = std::sync::mpsc::channel();
+            let holder = thread::spawn(move || {
+                let _held = data.lock().unwrap();
+                tx.send(()).unwrap();
+                thread::sleep(Duration::from_millis(100));
+            });
+            rx.recv().unwrap();
+            holder
+        };
+
+        // The locks are not timed by default.
+        let holder = hold(data.clone());
+        *lock_ptr!(data)? += 1;
+        holder.join().unwrap();
+        assert!(sites.0.lock().unwrap().is_empty());
+
+        set_lock_threshold(Some(Duration::from_millis(50)));
+        let before = slow_locks();
+
+        // The lock is not contended.
+        *lock_ptr!(data)? += 1;
+        assert!(sites.0.lock().unwrap().is_empty());
+
+        let holder = hold(data.clone());
+        let line = line!() + 1;
+        *lock_ptr!(data)? += 1;
+        holder.join().unwrap();
+
+        set_lock_threshold(None);
+        assert_eq!(
+            *sites.0.lock().unwrap(),
+            vec![format!("{}:{}", file!(), line)]
+        );
+        assert!(slow_locks() > before);
+        assert_eq!(*lock_ptr!(data)?, 3);
+
+        Ok(())
+    }
+}
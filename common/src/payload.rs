@@ -0,0 +1,103 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The display of the payloads of the tasks in the logs, e.g. the inputs, the outputs and the
+//! common data, which may carry personal data; they're printed by their lengths and digests
+//! only, unless the previews are enabled by `debug.payload_preview` in a dev environment.
+
+use std::fmt::{self, Debug, Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use sha2::{Digest, Sha256};
+
+/// The bytes of the digest printed, which is enough to tell the payloads apart.
+const DIGEST_LEN: usize = 8;
+
+/// The first bytes of the payloads previewed in hex; nothing is previewed if it's 0.
+static PREVIEW: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the first bytes of the payloads previewed in hex by the logs, or disables the
+/// previews if it's 0.
+pub fn set_preview(bytes: usize) {
+    PREVIEW.store(bytes, Ordering::Relaxed);
+}
+
+/// A payload displayed in the logs, e.g. `<5 bytes, sha256:2cf24dba5fb0a30e>`, or
+/// `<5 bytes, sha256:2cf24dba5fb0a30e, 6865..>` with a preview of 2 bytes.
+#[derive(Clone, Copy)]
+pub struct Payload<'a>(Option<&'a [u8]>);
+
+/// Displays the payload, e.g. `payload::display(task.input.as_deref())`.
+pub fn display(data: Option<&[u8]>) -> Payload<'_> {
+    Payload(data)
+}
+
+impl Display for Payload<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Some(data) = self.0 else {
+            return write!(f, "<none>");
+        };
+
+        write!(f, "<{} bytes, sha256:", data.len())?;
+        for b in &Sha256::digest(data)[..DIGEST_LEN] {
+            write!(f, "{:02x}", b)?;
+        }
+
+        let preview = PREVIEW.load(Ordering::Relaxed);
+        if preview > 0 && !data.is_empty() {
+            write!(f, ", ")?;
+            for b in data.iter().take(preview) {
+                write!(f, "{:02x}", b)?;
+            }
+            if data.len() > preview {
+                write!(f, "..")?;
+            }
+        }
+
+        write!(f, ">")
+    }
+}
+
+// The payloads in the structs deriving `Debug` are redacted the same way.
+impl Debug for Payload<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(display(None).to_string(), "<none>");
+        assert_eq!(
+            display(Some(b"hello")).to_string(),
+            "<5 bytes, sha256:2cf24dba5fb0a30e>"
+        );
+        assert_eq!(
+            format!("{:?}", display(Some(b""))),
+            "<0 bytes, sha256:e3b0c44298fc1c14>"
+        );
+
+        set_preview(2);
+        let previews = (
+            display(Some(b"hello")).to_string(),
+            display(Some(b"h")).to_string(),
+        );
+        set_preview(0);
+        assert_eq!(previews.0, "<5 bytes, sha256:2cf24dba5fb0a30e, 6865..>");
+        assert_eq!(previews.1, "<1 bytes, sha256:aaa9402664f1a41f, 68>");
+    }
+}
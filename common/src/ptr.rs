@@ -11,7 +11,12 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::sync::Arc;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{monitor, FlameError};
 
 pub type MutexPtr<T> = Arc<std::sync::Mutex<T>>;
 pub type AsyncPtr<T> = Arc<tokio::sync::Mutex<T>>;
@@ -24,6 +29,111 @@ pub fn new_async_ptr<T>(t: T) -> AsyncPtr<T> {
     Arc::new(tokio::sync::Mutex::new(t))
 }
 
+/// The number of the shards of a `ShardedMap`.
+const SHARDS: usize = 16;
+
+/// The map split into the shards by the hashes of the keys, so the lookups of different keys
+/// rarely wait for each other, and the lookups of the same shard share its lock.
+pub struct ShardedMap<K, V> {
+    hasher: RandomState,
+    shards: Vec<RwLock<HashMap<K, V>>>,
+}
+
+impl<K: Eq + Hash, V: Clone> Default for ShardedMap<K, V> {
+    fn default() -> Self {
+        ShardedMap::new()
+    }
+}
+
+impl<K: Eq + Hash, V: Clone> ShardedMap<K, V> {
+    pub fn new() -> Self {
+        ShardedMap {
+            hasher: RandomState::new(),
+            shards: (0..SHARDS).map(|_| RwLock::new(HashMap::new())).collect(),
+        }
+    }
+
+    fn shard(&self, k: &K) -> &RwLock<HashMap<K, V>> {
+        &self.shards[self.hasher.hash_one(k) as usize % SHARDS]
+    }
+
+    pub fn get(&self, k: &K) -> Result<Option<V>, FlameError> {
+        Ok(read(self.shard(k), "ShardedMap::get")?.get(k).cloned())
+    }
+
+    pub fn insert(&self, k: K, v: V) -> Result<Option<V>, FlameError> {
+        Ok(write(self.shard(&k), "ShardedMap::insert")?.insert(k, v))
+    }
+
+    /// Gets the value, or inserts the one of `f` if there's none; the concurrent callers get
+    /// the same value, as `f` is called with the shard locked.
+    pub fn get_or_insert_with<F>(&self, k: K, f: F) -> Result<V, FlameError>
+    where
+        F: FnOnce() -> V,
+    {
+        if let Some(v) = self.get(&k)? {
+            return Ok(v);
+        }
+        let mut shard = write(self.shard(&k), "ShardedMap::get_or_insert_with")?;
+        Ok(shard.entry(k).or_insert_with(f).clone())
+    }
+
+    pub fn remove(&self, k: &K) -> Result<Option<V>, FlameError> {
+        Ok(write(self.shard(k), "ShardedMap::remove")?.remove(k))
+    }
+
+    /// Removes the value if `check` passes; the value is not found by the others while it's
+    /// checked.
+    pub fn remove_with<F>(&self, k: &K, check: F) -> Result<Option<V>, FlameError>
+    where
+        F: FnOnce(&V) -> Result<(), FlameError>,
+    {
+        let mut shard = write(self.shard(k), "ShardedMap::remove_with")?;
+        match shard.get(k) {
+            Some(v) => {
+                check(v)?;
+                Ok(shard.remove(k))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The values of the map, which are copied one shard at a time; the values inserted or
+    /// removed meanwhile may be missed, but the map is never iterated while it's changed.
+    pub fn values(&self) -> Result<Vec<V>, FlameError> {
+        let mut values = vec![];
+        for shard in &self.shards {
+            values.extend(read(shard, "ShardedMap::values")?.values().cloned());
+        }
+
+        Ok(values)
+    }
+}
+
+fn read<'a, T>(
+    lock: &'a RwLock<T>,
+    site: &'static str,
+) -> Result<RwLockReadGuard<'a, T>, FlameError> {
+    let start = monitor::lock_start();
+    let guard = lock
+        .read()
+        .map_err(|_| FlameError::Internal("rwlock ptr".to_string()));
+    monitor::lock_acquired(start, site);
+    guard
+}
+
+fn write<'a, T>(
+    lock: &'a RwLock<T>,
+    site: &'static str,
+) -> Result<RwLockWriteGuard<'a, T>, FlameError> {
+    let start = monitor::lock_start();
+    let guard = lock
+        .write()
+        .map_err(|_| FlameError::Internal("rwlock ptr".to_string()));
+    monitor::lock_acquired(start, site);
+    guard
+}
+
 // pub type AsyncPtr<T> = Arc<tokio::sync::Mutex<T>>;
 
 // #[derive(Clone, Debug)]
@@ -76,3 +186,50 @@ pub fn new_async_ptr<T>(t: T) -> AsyncPtr<T> {
 //         Ok(ptr)
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn test_sharded_map() -> Result<(), FlameError> {
+        let map = Arc::new(ShardedMap::new());
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let map = map.clone();
+                thread::spawn(move || {
+                    for k in (0..1000).filter(|k| k % 8 == i) {
+                        map.insert(k, k * 2)?;
+                        // The values are copied while the others are inserting.
+                        assert!(map.values()?.len() <= 1000);
+                    }
+                    Ok::<_, FlameError>(())
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        let mut values = map.values()?;
+        values.sort();
+        assert_eq!(values, (0..1000).map(|k| k * 2).collect::<Vec<_>>());
+        assert_eq!(map.get(&21)?, Some(42));
+        assert_eq!(map.get(&1000)?, None);
+        assert_eq!(map.get_or_insert_with(21, || 0)?, 42);
+        assert_eq!(map.get_or_insert_with(1000, || 2000)?, 2000);
+        assert_eq!(map.remove(&1000)?, Some(2000));
+
+        // The value is kept if the check fails.
+        let busy = || FlameError::FailedPrecondition("busy".to_string());
+        assert!(map.remove_with(&21, |_| Err(busy())).is_err());
+        assert_eq!(map.remove_with(&21, |_| Ok(()))?, Some(42));
+        assert_eq!(map.remove_with(&21, |_| Err(busy()))?, None);
+        assert_eq!(map.remove(&22)?, Some(44));
+        assert_eq!(map.values()?.len(), 998);
+
+        Ok(())
+    }
+}
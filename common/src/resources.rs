@@ -0,0 +1,380 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::FlameError;
+
+const CPU: &str = "cpu";
+const MEMORY: &str = "mem";
+const MILLIS_PER_CPU: u64 = 1000;
+const MEMORY_UNITS: [(char, u64); 4] = [
+    ('t', 1 << 40),
+    ('g', 1 << 30),
+    ('m', 1 << 20),
+    ('k', 1 << 10),
+];
+
+/// The resources of a slot or an executor, e.g. `cpu=1.5,mem=2g,gpu=1`; the cpu is in millis,
+/// the memory is in bytes, and the other resources are counted.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ResourceVector {
+    pub cpu: u64,
+    pub memory: u64,
+    pub others: BTreeMap<String, u64>,
+}
+
+impl ResourceVector {
+    pub fn new(cpu: u64, memory: u64) -> Self {
+        ResourceVector {
+            cpu,
+            memory,
+            others: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a named resource, e.g. `gpu`.
+    pub fn with(mut self, name: &str, count: u64) -> Self {
+        self.others.insert(name.to_string(), count);
+        self
+    }
+
+    /// Whether all the resources are no more than the ones of `total`; a named resource which
+    /// is not in `total` counts as zero.
+    pub fn fits_within(&self, total: &ResourceVector) -> bool {
+        self.cpu <= total.cpu
+            && self.memory <= total.memory
+            && self
+                .others
+                .iter()
+                .all(|(k, v)| *v <= total.others.get(k).copied().unwrap_or_default())
+    }
+
+    /// Subtracts each resource, stopping at zero; the named resources of `self` are kept.
+    pub fn saturating_sub(&self, other: &ResourceVector) -> ResourceVector {
+        ResourceVector {
+            cpu: self.cpu.saturating_sub(other.cpu),
+            memory: self.memory.saturating_sub(other.memory),
+            others: self
+                .others
+                .iter()
+                .map(|(k, v)| {
+                    let used = other.others.get(k).copied().unwrap_or_default();
+                    (k.clone(), v.saturating_sub(used))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Parses the cpu in cores, e.g. `1`, `0.5`, or in millis, e.g. `500m`.
+fn parse_cpu(v: &str) -> Result<u64, String> {
+    if let Some(millis) = v.strip_suffix('m') {
+        return parse_integer(millis);
+    }
+
+    let (int, frac) = v.split_once('.').unwrap_or((v, ""));
+    if frac.len() > 3 || (v.contains('.') && frac.is_empty()) {
+        return Err(format!(
+            "invalid cpu <{}>, expect at most 3 decimal places",
+            v
+        ));
+    }
+
+    let int = match int {
+        "" if frac.is_empty() => return Err(format!("invalid number <{}>", v)),
+        "" => 0,
+        _ => parse_integer(int)?,
+    };
+    let frac = match frac {
+        "" => 0,
+        _ => parse_integer(&format!("{:0<3}", frac))?,
+    };
+
+    int.checked_mul(MILLIS_PER_CPU)
+        .and_then(|millis| millis.checked_add(frac))
+        .ok_or(format!("cpu <{}> is too large", v))
+}
+
+/// Parses the memory in bytes with an optional suffix of k, m, g or t, e.g. `512m`.
+pub(crate) fn parse_memory(v: &str) -> Result<u64, String> {
+    let lower = v.to_lowercase();
+    for (unit, size) in MEMORY_UNITS {
+        if let Some(n) = lower.strip_suffix(unit) {
+            return parse_integer(n)?
+                .checked_mul(size)
+                .ok_or(format!("memory <{}> is too large", v));
+        }
+    }
+
+    parse_integer(&lower)
+}
+
+fn parse_integer(v: &str) -> Result<u64, String> {
+    if v.is_empty() || !v.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("invalid number <{}>", v));
+    }
+
+    v.parse::<u64>()
+        .map_err(|_| format!("number <{}> is too large", v))
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_' || b == b'-')
+}
+
+impl FromStr for ResourceVector {
+    type Err = FlameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Err(FlameError::InvalidConfig("no resource".to_string()));
+        }
+
+        let mut res = ResourceVector::default();
+        let mut names = vec![];
+        for item in s.split(',') {
+            let Some((k, v)) = item.split_once('=') else {
+                return Err(FlameError::InvalidConfig(format!(
+                    "invalid resource <{}>, expect <name=value>",
+                    item
+                )));
+            };
+
+            let (k, v) = (k.trim(), v.trim());
+            if !is_valid_name(k) {
+                return Err(FlameError::InvalidConfig(format!(
+                    "invalid resource name <{}>",
+                    k
+                )));
+            }
+            if names.contains(&k) {
+                return Err(FlameError::InvalidConfig(format!(
+                    "duplicated resource <{}>",
+                    k
+                )));
+            }
+            names.push(k);
+
+            match k {
+                CPU => res.cpu = parse_cpu(v).map_err(FlameError::InvalidConfig)?,
+                MEMORY => res.memory = parse_memory(v).map_err(FlameError::InvalidConfig)?,
+                _ => {
+                    let count = parse_integer(v).map_err(FlameError::InvalidConfig)?;
+                    res.others.insert(k.to_string(), count);
+                }
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+impl Display for ResourceVector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (cores, millis) = (self.cpu / MILLIS_PER_CPU, self.cpu % MILLIS_PER_CPU);
+        match millis {
+            0 => write!(f, "{}={}", CPU, cores)?,
+            _ => {
+                let frac = format!("{:03}", millis);
+                write!(f, "{}={}.{}", CPU, cores, frac.trim_end_matches('0'))?
+            }
+        }
+
+        let unit = MEMORY_UNITS
+            .iter()
+            .find(|(_, size)| self.memory > 0 && self.memory.is_multiple_of(*size));
+        match unit {
+            Some((unit, size)) => write!(f, ",{}={}{}", MEMORY, self.memory / size, unit)?,
+            None => write!(f, ",{}={}", MEMORY, self.memory)?,
+        }
+
+        for (k, v) in &self.others {
+            write!(f, ",{}={}", k, v)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Serialize for ResourceVector {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ResourceVector {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parse() {
+        let res: ResourceVector = "cpu=1,mem=2g".parse().unwrap();
+        assert_eq!(res, ResourceVector::new(1000, 2 << 30));
+
+        let res: ResourceVector = " cpu = 0.25 , mem=512M, gpu=2".parse().unwrap();
+        assert_eq!(res, ResourceVector::new(250, 512 << 20).with("gpu", 2));
+
+        let res: ResourceVector = "cpu=1500m,mem=1024".parse().unwrap();
+        assert_eq!(res, ResourceVector::new(1500, 1024));
+
+        let res: ResourceVector = "cpu=.5".parse().unwrap();
+        assert_eq!(res.cpu, 500);
+
+        let res: ResourceVector = "gpu=1".parse().unwrap();
+        assert_eq!(res, ResourceVector::new(0, 0).with("gpu", 1));
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        let cases = [
+            "",
+            " ",
+            ",",
+            "cpu",
+            "cpu=",
+            "=1",
+            "cpu=1,",
+            "cpu=1,,mem=1g",
+            "cpu=-1",
+            "cpu=1.",
+            "cpu=.",
+            "cpu=0.0001",
+            "cpu=1.2.3",
+            "cpu=1e3",
+            "cpu=abc",
+            "cpu=1,cpu=2",
+            "cpu=1;mem=1g",
+            "cpu=18446744073709551615",
+            "mem=1x",
+            "mem=g",
+            "mem=1.5g",
+            "mem=-1g",
+            "mem=18446744073709551615g",
+            "mem=99999999999999999999",
+            "gpu=1.5",
+            "gpu=one",
+            "GPU=1",
+            "g pu=1",
+            "cpu=1=2",
+        ];
+
+        for case in cases {
+            let res = case.parse::<ResourceVector>();
+            assert!(
+                matches!(res, Err(FlameError::InvalidConfig(_))),
+                "<{}> should be invalid",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let res = ResourceVector::new(1500, 2 << 30).with("gpu", 1);
+        assert_eq!(res.to_string(), "cpu=1.5,mem=2g,gpu=1");
+
+        let res = ResourceVector::new(1, (1 << 20) + 1);
+        assert_eq!(res.to_string(), "cpu=0.001,mem=1048577");
+
+        assert_eq!(ResourceVector::default().to_string(), "cpu=0,mem=0");
+    }
+
+    #[test]
+    fn test_fits_within() {
+        let total: ResourceVector = "cpu=4,mem=8g,gpu=1".parse().unwrap();
+
+        assert!(ResourceVector::new(4000, 8 << 30).fits_within(&total));
+        assert!(!ResourceVector::new(4001, 0).fits_within(&total));
+        assert!(ResourceVector::new(1, 1).with("gpu", 1).fits_within(&total));
+        assert!(!ResourceVector::new(1, 1).with("gpu", 2).fits_within(&total));
+        assert!(!ResourceVector::new(1, 1)
+            .with("fpga", 1)
+            .fits_within(&total));
+    }
+
+    #[test]
+    fn test_saturating_sub() {
+        let total: ResourceVector = "cpu=4,mem=8g,gpu=1".parse().unwrap();
+        let used: ResourceVector = "cpu=1.5,mem=10g,gpu=1,fpga=1".parse().unwrap();
+
+        assert_eq!(
+            total.saturating_sub(&used),
+            ResourceVector::new(2500, 0).with("gpu", 0)
+        );
+    }
+
+    #[test]
+    fn test_serde() {
+        let res: ResourceVector = serde_yaml::from_str("\"cpu=2,mem=1g\"").unwrap();
+        assert_eq!(res, ResourceVector::new(2000, 1 << 30));
+        assert_eq!(serde_yaml::to_string(&res).unwrap(), "cpu=2,mem=1g\n");
+
+        assert!(serde_yaml::from_str::<ResourceVector>("\"cpu=x\"").is_err());
+    }
+
+    fn resources() -> impl Strategy<Value = ResourceVector> {
+        (
+            any::<u64>(),
+            any::<u64>(),
+            prop::collection::btree_map("[a-z][a-z0-9_-]{0,7}", any::<u64>(), 0..4),
+        )
+            .prop_map(|(cpu, memory, mut others)| {
+                others.remove(CPU);
+                others.remove(MEMORY);
+                ResourceVector {
+                    cpu,
+                    memory,
+                    others,
+                }
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn test_display_round_trip(res in resources()) {
+            let parsed: ResourceVector = res.to_string().parse().unwrap();
+            prop_assert_eq!(parsed, res);
+        }
+
+        #[test]
+        fn test_parse_round_trip(cores in 0..1_000_000u64, millis in 0..1000u64, mem in 0..1_000_000u64) {
+            let s = format!("cpu={}.{:03},mem={}m", cores, millis, mem);
+            let res: ResourceVector = s.parse().unwrap();
+            prop_assert_eq!(res.cpu, cores * 1000 + millis);
+            prop_assert_eq!(res.memory, mem << 20);
+            prop_assert_eq!(res.to_string().parse::<ResourceVector>().unwrap(), res);
+        }
+
+        #[test]
+        fn test_parse_no_panic(s in "\\PC*") {
+            let _ = s.parse::<ResourceVector>();
+        }
+    }
+}
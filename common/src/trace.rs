@@ -11,20 +11,216 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use std::env;
+use std::str::FromStr;
+
+use tonic::metadata::MetadataMap;
+use tracing::Span;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+use crate::ctx::FlameTelemetryConf;
+use crate::FlameError;
+
+/// The environment variable of the log format, e.g. `FLAME_LOG_FORMAT=json`.
+pub const LOG_FORMAT_ENV: &str = "FLAME_LOG_FORMAT";
+
+/// The key of the W3C trace context in the metadata of the requests.
+pub const TRACEPARENT: &str = "traceparent";
+/// The environment variable of the W3C trace context for the workers of the shims.
+pub const TRACEPARENT_ENV: &str = "TRACEPARENT";
+
+type BoxLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// The format of the logs, e.g. JSON for the log collectors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    /// The format set by `FLAME_LOG_FORMAT`, which is text by default.
+    pub fn from_env() -> Self {
+        env::var(LOG_FORMAT_ENV)
+            .ok()
+            .and_then(|f| f.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = FlameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(FlameError::invalid_argument(
+                "log format",
+                format!("<{}>, expect text or json", s),
+            )),
+        }
+    }
+}
+
+/// Initializes the tracing subscriber of the binaries. The filter is set by `RUST_LOG`, e.g.
+/// `RUST_LOG=flame_session_manager=debug`, and the records of `log` are forwarded to the
+/// subscriber too. The spans are exported by OTLP if the telemetry is configured and the
+/// binary is built with feature `otel`.
+pub fn init(
+    service: &str,
+    telemetry: Option<&FlameTelemetryConf>,
+    format: LogFormat,
+) -> Result<(), FlameError> {
+    let fmt = tracing_subscriber::fmt::layer();
+    let fmt = match format {
+        LogFormat::Json => fmt
+            .json()
+            .with_filter(EnvFilter::from_default_env())
+            .boxed(),
+        LogFormat::Text => fmt.with_filter(EnvFilter::from_default_env()).boxed(),
+    };
+
+    let mut layers: Vec<BoxLayer> = vec![fmt];
+    if let Some(conf) = telemetry {
+        layers.extend(otel::layer(service, conf)?);
+    }
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .try_init()
+        .map_err(|e| FlameError::Internal(format!("failed to init tracing: {}", e)))?;
+
+    if telemetry.is_some() && !cfg!(feature = "otel") {
+        log::warn!("The telemetry is ignored, rebuild with feature <otel> to export the spans.");
+    }
+
+    Ok(())
+}
+
+/// Injects the trace context of the current span into the metadata of a request.
+pub fn inject(metadata: &mut MetadataMap) {
+    if let Some(value) = current().and_then(|tp| tp.parse().ok()) {
+        metadata.insert(TRACEPARENT, value);
+    }
+}
+
+/// Returns the trace context in the metadata of a request, if any.
+pub fn extract(metadata: &MetadataMap) -> Option<String> {
+    metadata
+        .get(TRACEPARENT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+/// Returns the W3C trace context of the current span; it's none if the spans are not exported.
+pub fn current() -> Option<String> {
+    otel::traceparent(&Span::current())
+}
+
+/// Continues the trace of the parent, e.g. the trace context of a request or a task.
+pub fn set_parent(span: &Span, traceparent: Option<&str>) {
+    if let Some(traceparent) = traceparent {
+        otel::set_parent(span, traceparent);
+    }
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use std::collections::HashMap;
+
+    use opentelemetry::propagation::TextMapPropagator;
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use opentelemetry_sdk::{runtime, Resource};
+    use tracing::Span;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::filter::LevelFilter;
+    use tracing_subscriber::Layer;
+
+    use super::{BoxLayer, TRACEPARENT};
+    use crate::ctx::FlameTelemetryConf;
+    use crate::FlameError;
+
+    pub fn layer(service: &str, conf: &FlameTelemetryConf) -> Result<Option<BoxLayer>, FlameError> {
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let resource = Resource::new([KeyValue::new("service.name", service.to_string())]);
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(&conf.otlp_endpoint),
+            )
+            .with_trace_config(opentelemetry_sdk::trace::config().with_resource(resource))
+            .install_batch(runtime::Tokio)
+            .map_err(|e| {
+                FlameError::InvalidConfig(format!(
+                    "telemetry.otlp_endpoint <{}>: {}",
+                    conf.otlp_endpoint, e
+                ))
+            })?;
+
+        let layer = tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(LevelFilter::INFO);
+
+        Ok(Some(layer.boxed()))
+    }
+
+    pub fn traceparent(span: &Span) -> Option<String> {
+        let mut carrier = HashMap::new();
+        TraceContextPropagator::new().inject_context(&span.context(), &mut carrier);
+
+        carrier.remove(TRACEPARENT)
+    }
+
+    pub fn set_parent(span: &Span, traceparent: &str) {
+        let carrier = HashMap::from([(TRACEPARENT.to_string(), traceparent.to_string())]);
+        span.set_parent(TraceContextPropagator::new().extract(&carrier));
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+mod otel {
+    use tracing::Span;
+
+    use super::BoxLayer;
+    use crate::ctx::FlameTelemetryConf;
+    use crate::FlameError;
+
+    pub fn layer(_: &str, _: &FlameTelemetryConf) -> Result<Option<BoxLayer>, FlameError> {
+        Ok(None)
+    }
+
+    pub fn traceparent(_: &Span) -> Option<String> {
+        None
+    }
+
+    pub fn set_parent(_: &Span, _: &str) {}
+}
+
+/// Logs the entry and exit of a function; the requests are traced by the spans of
+/// `tracing::instrument` instead.
 pub struct TraceFn {
     pub fn_name: String,
 }
 
 impl TraceFn {
     pub fn new(n: String) -> Self {
-        log::debug!("{} Enter", n);
+        tracing::debug!("{} Enter", n);
         TraceFn { fn_name: n }
     }
 }
 
 impl Drop for TraceFn {
     fn drop(&mut self) {
-        log::debug!("{} Leaving", self.fn_name);
+        tracing::debug!("{} Leaving", self.fn_name);
     }
 }
 
@@ -35,3 +231,85 @@ macro_rules! trace_fn {
         // let _scope_call = TraceFn { fn_name: $e.to_string() };
     };
 }
+
+#[cfg(all(test, feature = "otel"))]
+mod tests {
+    use super::*;
+
+    use std::future::{self, Future};
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+    use opentelemetry_sdk::trace::TracerProvider;
+
+    /// Keeps the exported spans in memory.
+    #[derive(Clone, Debug, Default)]
+    struct Exporter(Arc<Mutex<Vec<SpanData>>>);
+
+    impl SpanExporter for Exporter {
+        fn export(
+            &mut self,
+            batch: Vec<SpanData>,
+        ) -> Pin<Box<dyn Future<Output = ExportResult> + Send>> {
+            self.0.lock().unwrap().extend(batch);
+            Box::pin(future::ready(Ok(())))
+        }
+    }
+
+    #[test]
+    fn test_propagation() {
+        let exporter = Exporter::default();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("flame"));
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        // The client injects the trace context into the request.
+        let mut metadata = MetadataMap::new();
+        let client = tracing::info_span!("client");
+        client.in_scope(|| inject(&mut metadata));
+
+        // The session manager continues the trace, and persists it in the task.
+        let server = tracing::info_span!("server");
+        set_parent(&server, extract(&metadata).as_deref());
+        let task_trace = server.in_scope(current);
+
+        // The executor continues the trace of the task, and passes it to the worker.
+        let executor = tracing::info_span!("executor");
+        set_parent(&executor, task_trace.as_deref());
+        let worker_trace = executor.in_scope(current).unwrap();
+
+        drop((client, server, executor));
+        provider.force_flush();
+
+        let spans = exporter.0.lock().unwrap();
+        assert_eq!(spans.len(), 3);
+
+        let trace_id = spans[0].span_context.trace_id();
+        assert!(spans.iter().all(|s| s.span_context.trace_id() == trace_id));
+        assert_eq!(
+            worker_trace.split('-').nth(1),
+            Some(trace_id.to_string().as_str())
+        );
+
+        let span_id = |name: &str| {
+            spans
+                .iter()
+                .find(|s| s.name == name)
+                .map(|s| s.span_context.span_id())
+                .unwrap()
+        };
+        let parent = |name: &str| {
+            spans
+                .iter()
+                .find(|s| s.name == name)
+                .unwrap()
+                .parent_span_id
+        };
+        assert_eq!(parent("server"), span_id("client"));
+        assert_eq!(parent("executor"), span_id("server"));
+    }
+}
@@ -122,6 +122,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
             application: app,
             slots,
             common_data: Some(common_data.into()),
+            ..Default::default()
         })
         .await?;
 
@@ -110,6 +110,7 @@ async fn main() -> Result<(), LocalError> {
     let ssn_ctx = SessionContext {
         session_id: args.session.clone(),
         common_data: Some(common_data.into()),
+        config: vec![],
     };
 
     instance
@@ -0,0 +1,207 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use common::apis::{Application, Shim};
+use common::ctx::FlameContext;
+use common::FlameError;
+
+use crate::executor::Executor;
+use crate::{client, shims};
+
+/// How long to wait for the session manager, so an unreachable one fails the check instead
+/// of hanging it.
+const SERVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The result of checking one thing, e.g. an application; it passes if there's no problem.
+#[derive(Clone, Debug)]
+pub struct Check {
+    pub name: String,
+    pub problems: Vec<String>,
+}
+
+impl Check {
+    pub fn passed(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Checks whether the executor can serve its applications without binding any session:
+/// the configuration, every application and the session manager.
+pub async fn run(ctx: &FlameContext) -> Vec<Check> {
+    let mut checks = vec![Check {
+        name: "configuration".to_string(),
+        problems: ctx.problems(),
+    }];
+
+    for app in &ctx.applications {
+        checks.push(Check {
+            name: format!("application <{}>", app.name),
+            problems: check_application(app).await,
+        });
+    }
+
+    checks.push(Check {
+        name: format!("session manager <{}>", ctx.backend_endpoint()),
+        problems: check_session_manager(ctx).await,
+    });
+
+    checks
+}
+
+/// Prints the report of the checks, and returns whether all of them passed.
+pub fn report(checks: &[Check]) -> bool {
+    for check in checks {
+        match check.passed() {
+            true => println!("PASS  {}", check.name),
+            false => {
+                println!("FAIL  {}", check.name);
+                for p in &check.problems {
+                    println!("        - {}", p);
+                }
+            }
+        }
+    }
+
+    checks.iter().all(Check::passed)
+}
+
+async fn check_application(app: &Application) -> Vec<String> {
+    let mut problems = vec![];
+
+    if matches!(app.shim, Shim::Stdio) {
+        if let Err(e) = check_command(&app.command) {
+            problems.push(format!("command <{}>: {}", app.command, e));
+        }
+    }
+
+    if let Err(e) = check_working_directory(&app.working_directory) {
+        problems.push(format!(
+            "working_directory <{}>: {}",
+            app.working_directory, e
+        ));
+    }
+
+    if let Err(e) = shims::from(app).await {
+        problems.push(format!("{:?} shim: {}", app.shim, e));
+    }
+
+    problems
+}
+
+/// Checks the command as the stdio shim runs it, i.e. a relative one is in the current
+/// directory of the executor instead of `$PATH`.
+fn check_command(command: &str) -> Result<(), FlameError> {
+    let mut path = PathBuf::from(command);
+    if !path.has_root() {
+        let cwd = env::current_dir().map_err(|e| FlameError::Internal(e.to_string()))?;
+        path = cwd.join(path);
+    }
+
+    let metadata = fs::metadata(&path)
+        .map_err(|e| FlameError::NotFound(format!("{}: {}", path.display(), e)))?;
+    if !metadata.is_file() {
+        return Err(FlameError::InvalidConfig(format!(
+            "{} is not a file",
+            path.display()
+        )));
+    }
+    if metadata.permissions().mode() & 0o111 == 0 {
+        return Err(FlameError::InvalidConfig(format!(
+            "{} is not executable",
+            path.display()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Creates the working directory if it does not exist, as the tasks can not run without it.
+fn check_working_directory(dir: &str) -> Result<(), FlameError> {
+    if dir.is_empty() {
+        return Ok(());
+    }
+
+    let path = Path::new(dir);
+    if path.exists() && !path.is_dir() {
+        return Err(FlameError::InvalidConfig("not a directory".to_string()));
+    }
+
+    fs::create_dir_all(path).map_err(|e| FlameError::Internal(e.to_string()))
+}
+
+/// Registers a new executor and unregisters it at once, so the session manager is reachable
+/// and accepts the executor.
+async fn check_session_manager(ctx: &FlameContext) -> Vec<String> {
+    let dry_run = async {
+        client::install(ctx).await?;
+
+        let exe = Executor::from_context(ctx).await?;
+        client::register_executor(ctx, &exe).await?;
+        client::unregister_executor(ctx, &exe).await
+    };
+
+    match tokio::time::timeout(SERVER_TIMEOUT, dry_run).await {
+        Ok(Ok(())) => vec![],
+        Ok(Err(e)) => vec![e.to_string()],
+        Err(_) => vec![format!("no response in {}s", SERVER_TIMEOUT.as_secs())],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_missing_binary() {
+        let app = Application {
+            name: "pi".to_string(),
+            shim: Shim::Stdio,
+            command: "/nonexistent/bin/pi".to_string(),
+            working_directory: env::temp_dir().display().to_string(),
+            ..Default::default()
+        };
+        let problems = check_application(&app).await;
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("command </nonexistent/bin/pi>"));
+
+        let app = Application {
+            command: "/bin/sh".to_string(),
+            ..app
+        };
+        assert!(check_application(&app).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_server() {
+        // Nothing listens on the port 1 of the loopback.
+        let ctx = FlameContext {
+            endpoint: "http://127.0.0.1:1".to_string(),
+            ..Default::default()
+        };
+
+        let problems = check_session_manager(&ctx).await;
+        assert_eq!(problems.len(), 1);
+
+        let checks = run(&ctx).await;
+        let server = checks.last().unwrap();
+        assert_eq!(server.name, "session manager <http://127.0.0.1:1>");
+        assert!(!server.passed());
+        assert!(!report(&checks));
+    }
+}
@@ -13,21 +13,29 @@ limitations under the License.
 
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+use bytes::Bytes;
 use lazy_static::lazy_static;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
 use tonic::transport::Channel;
 
 use self::rpc::backend_client::BackendClient as FlameBackendClient;
 use self::rpc::{
-    BindExecutorCompletedRequest, BindExecutorRequest, CompleteTaskRequest, LaunchTaskRequest,
-    RegisterExecutorRequest, UnbindExecutorCompletedRequest, UnbindExecutorRequest,
+    BindExecutorCompletedRequest, BindExecutorRequest, CompleteTaskRequest, GetApplicationRequest,
+    HeartbeatRequest, LaunchTaskRequest, LocalityKeys, RegisterExecutorRequest,
+    ReportTaskProgressRequest, StreamTaskOutputRequest, UnbindExecutorCompletedRequest,
+    UnbindExecutorRequest, UnregisterExecutorRequest,
 };
 use ::rpc::flame as rpc;
 
 use crate::executor::Executor;
-use common::apis::{self, SessionContext, TaskContext};
+use common::apis::{self, Annotations, SessionContext, TaskContext, TaskProgress};
 use common::ctx::FlameContext;
-use common::{lock_ptr, FlameError};
+use common::endpoint;
+use common::{lock_ptr, with_grpc_options, FlameError};
 
 type FlameClient = FlameBackendClient<Channel>;
 
@@ -43,9 +51,14 @@ lazy_static! {
 }
 
 pub async fn install(ctx: &FlameContext) -> Result<(), FlameError> {
-    let client = FlameBackendClient::connect(ctx.endpoint.clone())
+    let addr = ctx.backend_endpoint();
+    let channel = endpoint::connect(addr, endpoint::to_endpoint(addr)?)
         .await
-        .map_err(|_e| FlameError::Network("tonic connection".to_string()))?;
+        .map_err(|e| FlameError::Unavailable {
+            message: e.to_string(),
+            retryable: true,
+        })?;
+    let client = with_grpc_options!(FlameBackendClient::new(channel), ctx.grpc_options()?);
 
     let mut cs = lock_ptr!(INSTANCE.client_pool)?;
     cs.insert(ctx.name.clone(), client);
@@ -76,18 +89,44 @@ pub async fn register_executor(ctx: &FlameContext, exe: &Executor) -> Result<(),
     Ok(())
 }
 
-pub async fn bind_executor(
-    ctx: &FlameContext,
-    exe: &Executor,
-) -> Result<SessionContext, FlameError> {
+/// The session the executor is bound to by the session manager.
+pub struct Binding {
+    pub session: SessionContext,
+    /// The application of the session in the session manager; none if it's not delivered.
+    pub application: Option<apis::Application>,
+    /// The generations of the executor and the session when it was bound, which are echoed
+    /// by the requests of the binding; none for the older session managers.
+    pub generation: Option<u64>,
+    pub session_generation: Option<u64>,
+}
+
+/// Binds the executor to a session, and returns the session with its application in the
+/// session manager.
+pub async fn bind_executor(ctx: &FlameContext, exe: &Executor) -> Result<Binding, FlameError> {
     let mut ins = get_client(ctx)?;
 
     let req = BindExecutorRequest {
         executor_id: exe.id.clone(),
     };
 
-    let ssn = ins.bind_executor(req).await.map_err(FlameError::from)?;
-    SessionContext::try_from(ssn.into_inner())
+    let resp = ins
+        .bind_executor(req)
+        .await
+        .map_err(FlameError::from)?
+        .into_inner();
+    let session_generation = resp.status.as_ref().map(|s| s.generation);
+    let session = SessionContext::try_from(rpc::Session {
+        metadata: resp.metadata,
+        spec: resp.spec,
+        status: resp.status,
+    })?;
+
+    Ok(Binding {
+        session,
+        application: resp.application.map(apis::Application::from),
+        generation: resp.generation,
+        session_generation: resp.generation.and(session_generation),
+    })
 }
 
 pub async fn bind_executor_completed(ctx: &FlameContext, exe: &Executor) -> Result<(), FlameError> {
@@ -95,6 +134,8 @@ pub async fn bind_executor_completed(ctx: &FlameContext, exe: &Executor) -> Resu
 
     let req = BindExecutorCompletedRequest {
         executor_id: exe.id.clone(),
+        generation: exe.generation,
+        session_generation: exe.session_generation,
     };
 
     ins.bind_executor_completed(req)
@@ -104,15 +145,51 @@ pub async fn bind_executor_completed(ctx: &FlameContext, exe: &Executor) -> Resu
     Ok(())
 }
 
-//
-// rpc UnregisterExecutor (UnregisterExecutorRequest) returns (Result) {}
-//
+pub async fn unregister_executor(ctx: &FlameContext, exe: &Executor) -> Result<(), FlameError> {
+    let mut ins = get_client(ctx)?;
+
+    let req = UnregisterExecutorRequest {
+        executor_id: exe.id.clone(),
+        force: false,
+    };
+
+    ins.unregister_executor(req)
+        .await
+        .map_err(FlameError::from)?;
+
+    Ok(())
+}
+
+/// Tells the session manager that the executor is alive, so its running task is not
+/// requeued after the lease, how long it ran tasks since it was registered, and its
+/// locality keys.
+pub async fn heartbeat(
+    ctx: &FlameContext,
+    executor_id: &str,
+    busy_time: Duration,
+    locality_keys: Vec<String>,
+) -> Result<(), FlameError> {
+    let mut ins = get_client(ctx)?;
+
+    let req = HeartbeatRequest {
+        executor_id: executor_id.to_string(),
+        busy_seconds: Some(busy_time.as_secs_f64()),
+        locality_keys: Some(LocalityKeys {
+            keys: locality_keys,
+        }),
+    };
+
+    ins.heartbeat(req).await.map_err(FlameError::from)?;
+
+    Ok(())
+}
 
 pub async fn unbind_executor(ctx: &FlameContext, exe: &Executor) -> Result<(), FlameError> {
     let mut ins = get_client(ctx)?;
 
     let req = UnbindExecutorRequest {
         executor_id: exe.id.clone(),
+        generation: exe.generation,
     };
 
     ins.unbind_executor(req).await.map_err(FlameError::from)?;
@@ -127,6 +204,7 @@ pub async fn unbind_executor_completed(
 
     let req = UnbindExecutorCompletedRequest {
         executor_id: exe.id.clone(),
+        generation: exe.generation,
     };
 
     ins.unbind_executor_completed(req)
@@ -155,24 +233,129 @@ pub async fn launch_task(
     Ok(None)
 }
 
-pub async fn complete_task(ctx: &FlameContext, exe: &Executor) -> Result<(), FlameError> {
+/// Completes the task of the executor, or reports its failure so it's requeued, with the
+/// annotations set by the task. The annotations rejected by the session manager, e.g. over
+/// its size limit, are dropped, so the task is completed anyway.
+pub async fn complete_task(
+    ctx: &FlameContext,
+    exe: &Executor,
+    failure: Option<&FlameError>,
+    annotations: &Annotations,
+) -> Result<(), FlameError> {
     let mut ins = get_client(ctx)?;
 
-    let task = exe
-        .task
-        .clone()
-        .ok_or(FlameError::InvalidState("no task in executor".to_string()))?;
+    let task = exe.task.clone().ok_or(FlameError::FailedPrecondition(
+        "no task in executor".to_string(),
+    ))?;
 
     let req = CompleteTaskRequest {
         executor_id: exe.id.clone(),
         task_output: task.output.map(apis::TaskOutput::into),
+        session_id: task.ssn_id,
+        task_id: task.id,
+        failure: failure.map(FlameError::to_string),
+        failure_reason: failure.map(|_| rpc::FailureReason::FailureShimError as i32),
+        generation: exe.generation,
+        annotations: annotations.task.clone(),
+        session_annotations: annotations.session.clone(),
     };
 
-    ins.complete_task(req).await.map_err(FlameError::from)?;
+    let res = ins
+        .complete_task(req.clone())
+        .await
+        .map_err(FlameError::from);
+    if let Err(FlameError::InvalidArgument { field, message }) = &res {
+        if !annotations.is_empty() {
+            log::warn!(
+                "The annotations of task <{}/{}> are dropped: invalid <{}>, {}",
+                req.session_id,
+                req.task_id,
+                field,
+                message
+            );
+            let req = CompleteTaskRequest {
+                annotations: HashMap::new(),
+                session_annotations: HashMap::new(),
+                ..req
+            };
+            ins.complete_task(req).await.map_err(FlameError::from)?;
+            return Ok(());
+        }
+    }
+    res?;
 
     Ok(())
 }
 
+pub async fn report_task_progress(
+    ctx: &FlameContext,
+    executor_id: &str,
+    task: &TaskContext,
+    progress: &TaskProgress,
+) -> Result<(), FlameError> {
+    let mut ins = get_client(ctx)?;
+
+    let req = ReportTaskProgressRequest {
+        executor_id: executor_id.to_string(),
+        session_id: task.ssn_id.clone(),
+        task_id: task.id.clone(),
+        progress: Some(rpc::TaskProgress::from(progress)),
+    };
+
+    ins.report_task_progress(req)
+        .await
+        .map_err(FlameError::from)?;
+
+    Ok(())
+}
+
+/// Streams the output chunks of the task to the session manager until all the senders of
+/// the chunks are dropped; the stream is opened by the first chunk, so the tasks streaming
+/// nothing make no call.
+pub async fn stream_task_output(
+    ctx: &FlameContext,
+    executor_id: &str,
+    task: &TaskContext,
+    mut chunks: mpsc::Receiver<Bytes>,
+) -> Result<(), FlameError> {
+    let Some(first) = chunks.recv().await else {
+        return Ok(());
+    };
+    let mut ins = get_client(ctx)?;
+
+    let executor_id = executor_id.to_string();
+    let (session_id, task_id) = (task.ssn_id.clone(), task.id.clone());
+    let reqs = tokio_stream::once(first)
+        .chain(ReceiverStream::new(chunks))
+        .map(move |data| StreamTaskOutputRequest {
+            executor_id: executor_id.clone(),
+            session_id: session_id.clone(),
+            task_id: task_id.clone(),
+            data: data.to_vec(),
+        });
+
+    ins.stream_task_output(reqs)
+        .await
+        .map_err(FlameError::from)?;
+
+    Ok(())
+}
+
+pub async fn get_application(
+    ctx: &FlameContext,
+    name: &str,
+) -> Result<apis::Application, FlameError> {
+    let mut ins = get_client(ctx)?;
+
+    let req = GetApplicationRequest {
+        name: name.to_string(),
+    };
+
+    let app = ins.get_application(req).await.map_err(FlameError::from)?;
+
+    Ok(apis::Application::from(app.into_inner()))
+}
+
 // rpc UnbindExecutor (UnbindExecutorRequest) returns (Result) {}
 //
 // rpc LaunchTask (LaunchTaskRequest) returns (Task) {}
@@ -0,0 +1,257 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::path::Path;
+
+use common::ctx::FlameContext;
+use common::FlameError;
+
+const DEFAULT_EXECUTOR_CONF: &str = "executor-conf.yaml";
+const EXECUTOR_CONF_ENV: &str = "FLAME_EXECUTOR_CONF";
+
+/// The flags of the executor manager overriding the `executor` section of the files.
+#[derive(Clone, Debug, Default)]
+pub struct ExecutorFlags {
+    pub slots: Option<i32>,
+    /// The labels as `key=value`, which are merged over the ones of the files.
+    pub labels: Vec<String>,
+    pub scratch_root: Option<String>,
+    pub metrics_address: Option<String>,
+}
+
+/// Returns the files of the executor manager: the shared configuration, and the one of the
+/// host layered over it if any, i.e. the given path, `$FLAME_EXECUTOR_CONF`, or
+/// `executor-conf.yaml` beside the shared one if it exists.
+fn conf_files(
+    base: String,
+    fp: Option<String>,
+    env: Option<String>,
+    exists: impl Fn(&str) -> bool,
+) -> Vec<String> {
+    let host = fp.or(env.filter(|p| !p.is_empty())).or_else(|| {
+        let path = Path::new(&base).with_file_name(DEFAULT_EXECUTOR_CONF);
+        Some(path.display().to_string()).filter(|p| exists(p))
+    });
+
+    let mut files = vec![base];
+    files.extend(host);
+
+    files
+}
+
+/// Loads the configuration files of the executor manager and applies the overrides of the
+/// environments and the flags without validation, e.g. for `--check`; the precedence is
+/// flags > environments > file of the host > shared file > defaults.
+pub fn parse(
+    flame_conf: Option<String>,
+    executor_conf: Option<String>,
+    vars: impl IntoIterator<Item = (String, String)>,
+    flags: &ExecutorFlags,
+) -> Result<FlameContext, FlameError> {
+    let files = conf_files(
+        FlameContext::conf_path(flame_conf),
+        executor_conf,
+        std::env::var(EXECUTOR_CONF_ENV).ok(),
+        |p| Path::new(p).is_file(),
+    );
+    let ctx = FlameContext::parse_files(&files)?.with_env(vars)?;
+
+    log::debug!("Load FrameContext from <{}>", files.join(", "));
+
+    with_flags(ctx, flags)
+}
+
+/// Loads and validates the configuration of the executor manager, see `parse`.
+pub fn load(
+    flame_conf: Option<String>,
+    executor_conf: Option<String>,
+    vars: impl IntoIterator<Item = (String, String)>,
+    flags: &ExecutorFlags,
+) -> Result<FlameContext, FlameError> {
+    let ctx = parse(flame_conf, executor_conf, vars, flags)?;
+    ctx.validate()?;
+
+    Ok(ctx)
+}
+
+fn with_flags(mut ctx: FlameContext, flags: &ExecutorFlags) -> Result<FlameContext, FlameError> {
+    let executor = ctx.executor.get_or_insert_with(Default::default);
+
+    if let Some(slots) = flags.slots {
+        executor.slots = Some(slots);
+    }
+    for label in &flags.labels {
+        let (k, v) = label
+            .split_once('=')
+            .ok_or(FlameError::InvalidConfig(format!(
+                "label <{}>: expect key=value",
+                label
+            )))?;
+        executor.labels.insert(k.to_string(), v.to_string());
+    }
+    if let Some(root) = &flags.scratch_root {
+        executor.scratch_root = Some(root.clone());
+    }
+    if let Some(addr) = &flags.metrics_address {
+        executor.metrics_address = Some(addr.clone());
+    }
+
+    Ok(ctx)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_conf_files() {
+        let base = "/etc/flame/flame-conf.yaml".to_string();
+        let host = "/etc/flame/executor-conf.yaml".to_string();
+
+        let files = conf_files(base.clone(), None, None, |_| false);
+        assert_eq!(files, vec![base.clone()]);
+
+        let files = conf_files(base.clone(), None, None, |p| p == host);
+        assert_eq!(files, vec![base.clone(), host.clone()]);
+
+        let files = conf_files(
+            base.clone(),
+            None,
+            Some("/tmp/env.yaml".to_string()),
+            |_| true,
+        );
+        assert_eq!(files, vec![base.clone(), "/tmp/env.yaml".to_string()]);
+
+        let files = conf_files(
+            base.clone(),
+            Some("/tmp/flag.yaml".to_string()),
+            Some("/tmp/env.yaml".to_string()),
+            |_| true,
+        );
+        assert_eq!(files, vec![base, "/tmp/flag.yaml".to_string()]);
+    }
+
+    #[test]
+    fn test_layered_load() {
+        let dir = std::env::temp_dir().join(format!("flame-executor-conf-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("flame-conf.yaml");
+        let host = dir.join("host.yaml");
+        fs::write(
+            &base,
+            r#"
+name: flame
+endpoint: http://flame:8080
+slot: cpu=1,mem=1g
+policy: proportion
+storage: sqlite://flame.db
+applications:
+  - name: pi
+    shim: Log
+executor:
+  slots: 2
+  labels:
+    zone: a
+"#,
+        )
+        .unwrap();
+        fs::write(
+            &host,
+            r#"
+executor:
+  scratch_root: /scratch
+  labels:
+    gpu: "true"
+"#,
+        )
+        .unwrap();
+
+        let flags = ExecutorFlags {
+            slots: Some(4),
+            labels: vec!["zone=b".to_string()],
+            ..Default::default()
+        };
+        let load = |flags: &ExecutorFlags| {
+            load(
+                Some(base.display().to_string()),
+                Some(host.display().to_string()),
+                std::iter::empty(),
+                flags,
+            )
+        };
+
+        let ctx = load(&ExecutorFlags::default()).unwrap();
+        assert_eq!(ctx.endpoint, "http://flame:8080");
+        assert_eq!(ctx.applications.len(), 1);
+        let executor = ctx.executor.unwrap();
+        assert_eq!(executor.slots(), 2);
+        assert_eq!(executor.labels["zone"], "a");
+        assert_eq!(executor.labels["gpu"], "true");
+        assert_eq!(executor.scratch_root.as_deref(), Some("/scratch"));
+
+        let executor = load(&flags).unwrap().executor.unwrap();
+        assert_eq!(executor.slots(), 4);
+        assert_eq!(executor.labels["zone"], "b");
+        assert_eq!(executor.labels["gpu"], "true");
+
+        let flags = ExecutorFlags {
+            labels: vec!["zone".to_string()],
+            ..Default::default()
+        };
+        assert!(load(&flags).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_omit_applications() {
+        let dir = std::env::temp_dir().join(format!("flame-executor-apps-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("flame-conf.yaml");
+        let host = dir.join("executor-conf.yaml");
+        fs::write(
+            &base,
+            r#"
+name: flame
+endpoint: http://flame:8080
+slot: cpu=1,mem=1g
+policy: proportion
+storage: sqlite://flame.db
+"#,
+        )
+        .unwrap();
+        let load = || {
+            load(
+                Some(base.display().to_string()),
+                None,
+                std::iter::empty(),
+                &ExecutorFlags::default(),
+            )
+        };
+
+        // The applications are required unless the ones of the session manager are used.
+        let Err(FlameError::InvalidConfig(msg)) = load() else {
+            panic!("the applications should be required");
+        };
+        assert!(msg.contains("no application"), "{}", msg);
+
+        fs::write(&host, "executor:\n  server_applications: true\n").unwrap();
+        let ctx = load().unwrap();
+        assert!(ctx.applications.is_empty());
+        assert!(ctx.executor.unwrap().server_applications());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -11,18 +11,32 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::Notify;
 use uuid::Uuid;
 
+use crate::host;
+use crate::shims::pool::{WarmPool, WarmPoolPtr};
 use crate::shims::ShimPtr;
+use crate::status::{DrainPtr, StatusPtr, StatusView};
 use ::rpc::flame as rpc;
 
-use common::apis::{Application, SessionContext, TaskContext};
+use common::apis::{Application, HostInfo, SessionContext, TaskContext};
 use common::ctx::FlameContext;
 use common::FlameError;
 
-#[derive(Clone, Copy, Debug)]
+/// The most localities the executor learns from the tasks it ran; the oldest one is
+/// forgotten first.
+const MAX_LEARNED_LOCALITIES: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
 pub enum ExecutorState {
     Init = 0,
     Idle = 1,
@@ -47,14 +61,38 @@ pub struct Executor {
     pub id: String,
     pub slots: i32,
     pub applications: Vec<Application>,
+    pub labels: HashMap<String, String>,
+    /// The locality keys of the configuration, and the ones learned from the tasks it ran,
+    /// e.g. the shards cached on the host.
+    pub locality_keys: Vec<String>,
+    pub learned_localities: Vec<String>,
+    pub host: HostInfo,
+    /// The root of the working directories of the applications without one.
+    pub scratch_root: Option<String>,
 
     pub session: Option<SessionContext>,
     pub task: Option<TaskContext>,
 
     pub shim: Option<ShimPtr>,
+    /// The application run by the shim, which is kept warm with it after unbinding.
+    pub application: Option<Application>,
+    /// The warm shims of the applications, which is shared by the states.
+    pub warm_pool: Option<WarmPoolPtr>,
 
     pub start_time: DateTime<Utc>,
     pub state: ExecutorState,
+    /// The time the executor ran tasks since it was registered, which is reported in the
+    /// heartbeats, so the session manager can cross-check its own measure.
+    pub busy_time: Duration,
+    /// The generations of the executor and its session in the session manager when it was
+    /// bound, which are echoed by the requests of the binding.
+    pub generation: Option<u64>,
+    pub session_generation: Option<u64>,
+
+    /// The local status of the executor, which is shared with its status endpoint.
+    pub status: StatusPtr,
+    /// Notified by the status endpoint to drain the executor.
+    pub drain: DrainPtr,
 }
 
 impl From<&Executor> for rpc::Executor {
@@ -64,13 +102,20 @@ impl From<&Executor> for rpc::Executor {
             owner: None,
         });
 
-        let spec = Some(rpc::ExecutorSpec {
-            slots: e.slots,
-            applications: e.applications.iter().map(rpc::Application::from).collect(),
-        });
+        let spec = Some(rpc::ExecutorSpec::from(e));
 
         let status = Some(rpc::ExecutorStatus {
             state: rpc::ExecutorState::from(e.state) as i32,
+            session_id: e.session.as_ref().map(|s| s.ssn_id.clone()),
+            task_id: e.task.as_ref().map(|t| t.id.clone()),
+            creation_time: e.start_time.timestamp(),
+            last_heartbeat: Utc::now().timestamp(),
+            draining: false,
+            quarantined: false,
+            utilization: vec![],
+            busy_seconds: e.busy_time.as_secs_f64(),
+            reported_busy_seconds: None,
+            generation: e.generation.unwrap_or_default(),
         });
 
         rpc::Executor {
@@ -86,6 +131,9 @@ impl From<&Executor> for rpc::ExecutorSpec {
         rpc::ExecutorSpec {
             slots: e.slots,
             applications: e.applications.iter().map(rpc::Application::from).collect(),
+            labels: e.labels.clone(),
+            locality_keys: e.locality_keys(),
+            host: Some(rpc::HostInfo::from(&e.host)),
         }
     }
 }
@@ -94,22 +142,104 @@ impl Executor {
     pub fn update_state(&mut self, next: &Executor) {
         self.state = next.state;
         self.shim = next.shim.clone();
+        self.session = next.session.clone();
+        self.application = next.application.clone();
+        self.busy_time = next.busy_time;
+        self.learned_localities = next.learned_localities.clone();
+        self.generation = next.generation;
+        self.session_generation = next.session_generation;
     }
 
-    pub async fn from_context(ctx: &FlameContext, slots: Option<i32>) -> Result<Self, FlameError> {
-        // let applications = ctx.applications.iter().map(Application::from).collect();
+    pub async fn from_context(ctx: &FlameContext) -> Result<Self, FlameError> {
+        let conf = ctx.executor.clone().unwrap_or_default();
+        let warm_pool = match &ctx.warm_pool {
+            Some(conf) => Some(WarmPool::new_ptr(conf)?),
+            None => None,
+        };
 
+        let id = Uuid::new_v4().to_string();
         let exec = Executor {
-            id: Uuid::new_v4().to_string(),
-            slots: slots.unwrap_or(1),
+            status: StatusView::new_ptr(&id),
+            drain: Arc::new(Notify::new()),
+            id,
+            slots: conf.slots(),
             applications: ctx.applications.clone(),
+            labels: conf.labels,
+            locality_keys: conf.locality_keys,
+            learned_localities: vec![],
+            host: host::local(ctx.backend_endpoint()),
+            scratch_root: conf.scratch_root,
             session: None,
             task: None,
             shim: None,
+            application: None,
+            warm_pool,
             start_time: Utc::now(),
             state: ExecutorState::Init,
+            busy_time: Duration::ZERO,
+            generation: None,
+            session_generation: None,
         };
 
         Ok(exec)
     }
+
+    /// The locality keys advertised to the session manager.
+    pub fn locality_keys(&self) -> Vec<String> {
+        let mut keys = self.locality_keys.clone();
+        for locality in &self.learned_localities {
+            if !keys.contains(locality) {
+                keys.push(locality.clone());
+            }
+        }
+
+        keys
+    }
+
+    /// Takes the locality of the task as one of the keys, as its data is cached by running
+    /// it; returns whether the keys changed.
+    pub fn learn_locality(&mut self, task: &TaskContext) -> bool {
+        let Some(locality) = &task.locality else {
+            return false;
+        };
+        if self.locality_keys.contains(locality) {
+            return false;
+        }
+
+        // The recent locality is moved to the end, so it's forgotten last.
+        match self.learned_localities.iter().position(|l| l == locality) {
+            Some(pos) => {
+                let locality = self.learned_localities.remove(pos);
+                self.learned_localities.push(locality);
+                false
+            }
+            None => {
+                self.learned_localities.push(locality.clone());
+                if self.learned_localities.len() > MAX_LEARNED_LOCALITIES {
+                    self.learned_localities.remove(0);
+                }
+                true
+            }
+        }
+    }
+
+    /// Runs the application in its sub-directory of the scratch root if it has no working
+    /// directory of its own; the sub-directory is created if it does not exist.
+    pub fn in_scratch(&self, mut app: Application) -> Result<Application, FlameError> {
+        let Some(root) = self
+            .scratch_root
+            .as_ref()
+            .filter(|_| app.working_directory.is_empty())
+        else {
+            return Ok(app);
+        };
+
+        let dir = Path::new(root).join(&app.name);
+        fs::create_dir_all(&dir).map_err(|e| {
+            FlameError::Internal(format!("failed to create <{}>: {}", dir.display(), e))
+        })?;
+        app.working_directory = dir.display().to_string();
+
+        Ok(app)
+    }
 }
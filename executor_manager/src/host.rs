@@ -0,0 +1,73 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::env;
+use std::fs;
+use std::net::UdpSocket;
+
+use tonic::transport::Uri;
+
+use common::apis::HostInfo;
+
+/// The host of the executor, which is reported to the session manager at the endpoint; a
+/// field is empty if it's unknown.
+pub fn local(endpoint: &str) -> HostInfo {
+    HostInfo {
+        hostname: hostname().unwrap_or_default(),
+        ip: local_ip(endpoint).unwrap_or_default(),
+        os: env::consts::OS.to_string(),
+        arch: env::consts::ARCH.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
+
+fn hostname() -> Option<String> {
+    let name = fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| fs::read_to_string("/etc/hostname"))
+        .ok()
+        .or(env::var("HOSTNAME").ok())?;
+
+    Some(name.trim().to_string()).filter(|n| !n.is_empty())
+}
+
+/// The address of the interface routing to the session manager; connecting an UDP socket
+/// sends nothing, it only picks the route. None for the unix sockets.
+fn local_ip(endpoint: &str) -> Option<String> {
+    let uri = endpoint.parse::<Uri>().ok()?;
+    let port = match uri.scheme_str() {
+        Some("http") => uri.port_u16().unwrap_or(80),
+        Some("https") => uri.port_u16().unwrap_or(443),
+        _ => return None,
+    };
+    let host = uri.host()?.trim_start_matches('[').trim_end_matches(']');
+
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).ok()?;
+    socket.connect((host, port)).ok()?;
+
+    Some(socket.local_addr().ok()?.ip().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_host() {
+        let host = local("http://127.0.0.1:8080");
+        assert_eq!(host.ip, "127.0.0.1");
+        assert_eq!(host.os, env::consts::OS);
+        assert!(!host.version.is_empty());
+
+        assert_eq!(local("unix:///var/run/flame.sock").ip, "");
+    }
+}
@@ -0,0 +1,99 @@
+/*
+Copyright 2023 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The executor manager, which is run by `flame-executor-manager` or in process by the tests,
+//! e.g. the end-to-end harness.
+
+use std::net::TcpListener;
+
+use common::ctx::FlameContext;
+use common::FlameError;
+
+use crate::executor::{Executor, ExecutorState};
+
+pub mod check;
+mod client;
+pub mod conf;
+mod executor;
+mod host;
+mod shims;
+mod states;
+mod status;
+
+/// Runs an executor with the loaded configuration; it only returns if the executor can not be
+/// started, e.g. the session manager is unavailable, or it's drained by its status endpoint.
+/// The clients to the session manager are shared by the executors of the same `ctx.name`.
+pub async fn run(ctx: &FlameContext) -> Result<(), FlameError> {
+    let debug = ctx.debug.clone().unwrap_or_default();
+    common::payload::set_preview(debug.payload_preview.unwrap_or_default());
+
+    // Setup Flame backend client.
+    client::install(ctx).await?;
+
+    // Run executor.
+    // TODO(k82cn): 1. enable gracefully exit, 2. build ExecutorManager for multiple executors.
+    let mut exec = Executor::from_context(ctx).await?;
+    if let Some(pool) = exec.warm_pool.clone() {
+        let timings = ctx.timings.clone().unwrap_or_default();
+        tokio::spawn(shims::pool::run_evictor(pool, timings));
+    }
+    let conf = ctx.executor.clone().unwrap_or_default();
+    if let Some(addr) = &conf.metrics_address {
+        let listener = TcpListener::bind(addr).map_err(|e| {
+            FlameError::InvalidConfig(format!("executor.metrics_address <{}>: {}", addr, e))
+        })?;
+        let (status, drain) = (exec.status.clone(), exec.drain.clone());
+        tokio::spawn(async move {
+            if let Err(e) = status::serve(listener, status, drain).await {
+                log::error!("Failed to serve the status of the executor: {}", e);
+            }
+        });
+    }
+
+    loop {
+        if matches!(exec.state, ExecutorState::Idle) && is_draining(&exec) {
+            // The executor may be bound meanwhile, so it's unregistered after the session.
+            match client::unregister_executor(ctx, &exec).await {
+                Ok(()) => {
+                    log::info!("Executor <{}> was drained.", exec.id);
+                    return Ok(());
+                }
+                Err(e) => log::warn!("Failed to unregister the draining executor: {}", e),
+            }
+        }
+
+        let mut state = states::from(exec.clone()).await;
+        let res = match exec.state {
+            // The idle executor waits for a session until it's drained.
+            ExecutorState::Idle if !is_draining(&exec) => tokio::select! {
+                res = state.execute(ctx) => res,
+                _ = exec.drain.notified() => continue,
+            },
+            _ => state.execute(ctx).await,
+        };
+        match res {
+            Ok(next_state) => {
+                exec.update_state(&next_state);
+                status::update(&exec.status, |s| s.transit(&exec));
+            }
+            Err(e) => {
+                log::error!("Failed to execute: {}", e);
+                status::update(&exec.status, |s| s.failed(&e));
+            }
+        }
+    }
+}
+
+fn is_draining(exec: &Executor) -> bool {
+    exec.status.read().is_ok_and(|s| s.draining)
+}
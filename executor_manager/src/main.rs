@@ -13,14 +13,10 @@ limitations under the License.
 
 use std::error::Error;
 
-use crate::executor::Executor;
 use clap::Parser;
-use common::ctx::FlameContext;
-
-mod client;
-mod executor;
-mod shims;
-mod states;
+use common::trace::LogFormat;
+use flame_executor_manager::check;
+use flame_executor_manager::conf::{self, ExecutorFlags};
 
 #[derive(Parser)]
 #[command(name = "flame-executor-manager")]
@@ -30,34 +26,50 @@ mod states;
 struct Cli {
     #[arg(long)]
     flame_conf: Option<String>,
+    /// The configuration of this host layered over the flame_conf, e.g. its labels; it's
+    /// executor-conf.yaml beside the flame_conf if it exists
+    #[arg(long)]
+    executor_conf: Option<String>,
     #[arg(long)]
     slots: Option<i32>,
+    /// The label of the executor as key=value, e.g. zone=a; it could be repeated
+    #[arg(long = "label")]
+    labels: Vec<String>,
+    #[arg(long)]
+    scratch_root: Option<String>,
+    #[arg(long)]
+    metrics_address: Option<String>,
+    /// Check whether the applications can be served, and exit without running the executor
+    #[arg(long)]
+    check: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    env_logger::init();
-
     let cli = Cli::parse();
-    let ctx = FlameContext::from_file(cli.flame_conf)?;
-
-    // Setup Flame backend client.
-    client::install(&ctx).await?;
-
-    // Run executor.
-    // TODO(k82cn): 1. enable gracefully exit, 2. build ExecutorManager for multiple executors.
-    let mut exec = Executor::from_context(&ctx, cli.slots).await?;
-    // let mut exec_ptr = ExecutorPtr::new(exec);
-
-    loop {
-        let mut state = states::from(exec.clone()).await;
-        match state.execute(&ctx).await {
-            Ok(next_state) => {
-                exec.update_state(&next_state);
-            }
-            Err(e) => {
-                log::error!("Failed to execute: {}", e);
-            }
-        }
+    let flags = ExecutorFlags {
+        slots: cli.slots,
+        labels: cli.labels,
+        scratch_root: cli.scratch_root,
+        metrics_address: cli.metrics_address,
+    };
+    if cli.check {
+        // Report all the problems of the configuration instead of failing on loading it.
+        let ctx = conf::parse(cli.flame_conf, cli.executor_conf, std::env::vars(), &flags)?;
+
+        let checks = check::run(&ctx).await;
+        std::process::exit(if check::report(&checks) { 0 } else { 1 });
     }
+
+    let ctx = conf::load(cli.flame_conf, cli.executor_conf, std::env::vars(), &flags)?;
+
+    common::trace::init(
+        "flame-executor-manager",
+        ctx.telemetry.as_ref(),
+        LogFormat::from_env(),
+    )?;
+
+    flame_executor_manager::run(&ctx).await?;
+
+    Ok(())
 }
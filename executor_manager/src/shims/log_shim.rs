@@ -18,6 +18,7 @@ use tokio::sync::Mutex;
 
 use crate::shims::{Shim, ShimPtr};
 use common::apis::{Application, SessionContext, TaskContext, TaskOutput};
+use common::payload;
 use common::FlameError;
 
 #[derive(Clone)]
@@ -47,14 +48,27 @@ impl Shim for LogShim {
         Ok(())
     }
 
+    async fn on_session_rebind(&mut self, ctx: &SessionContext) -> Result<(), FlameError> {
+        log::info!(
+            "on_session_rebind: Session: <{}>, Application: <{}>, Slots: <{}>",
+            ctx.ssn_id,
+            ctx.application,
+            ctx.slots
+        );
+        self.session_context = Some(ctx.clone());
+
+        Ok(())
+    }
+
     async fn on_task_invoke(
         &mut self,
         ctx: &TaskContext,
     ) -> Result<Option<TaskOutput>, FlameError> {
         log::info!(
-            "on_task_invoke: Task: <{}>, Session: <{}>",
+            "on_task_invoke: Task: <{}>, Session: <{}>, Input: {}",
             ctx.id,
-            ctx.ssn_id
+            ctx.ssn_id,
+            payload::display(ctx.input.as_deref())
         );
         Ok(None)
     }
@@ -12,24 +12,36 @@ limitations under the License.
 */
 
 mod log_shim;
+pub mod pool;
 mod stdio_shim;
 mod wasm_shim;
 
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use tokio::sync::Mutex;
+use bytes::Bytes;
+use chrono::Utc;
+use tokio::sync::{mpsc, watch, Mutex};
 
 use self::log_shim::LogShim;
 use self::stdio_shim::StdioShim;
 use self::wasm_shim::WasmShim;
+use crate::status::StatusPtr;
 
-use common::apis::{Application, SessionContext, Shim as ShimType, TaskContext, TaskOutput};
+use common::apis::{
+    Annotations, Application, SessionContext, Shim as ShimType, TaskContext, TaskOutput,
+    TaskProgress, MAX_OUTPUT_CHUNK_BYTES, MAX_PROGRESS_PAYLOAD,
+};
+use common::ptr::MutexPtr;
 
-use common::FlameError;
+use common::{lock_ptr, FlameError};
 
 pub type ShimPtr = Arc<Mutex<dyn Shim>>;
 
+/// The output chunks queued for the session manager; the task waits for the queue if it
+/// streams faster than they're sent.
+const OUTPUT_CHUNK_QUEUE: usize = 64;
+
 pub async fn from(app: &Application) -> Result<ShimPtr, FlameError> {
     match app.shim {
         ShimType::Stdio => Ok(StdioShim::new_ptr(app)),
@@ -41,7 +53,113 @@ pub async fn from(app: &Application) -> Result<ShimPtr, FlameError> {
 #[async_trait]
 pub trait Shim: Send + Sync + 'static {
     async fn on_session_enter(&mut self, ctx: &SessionContext) -> Result<(), FlameError>;
+    /// Moves a warm shim to a new session of the same common data, so the application is not
+    /// entered again; see `pool::WarmPool`.
+    async fn on_session_rebind(&mut self, ctx: &SessionContext) -> Result<(), FlameError>;
     async fn on_task_invoke(&mut self, ctx: &TaskContext)
         -> Result<Option<TaskOutput>, FlameError>;
     async fn on_session_leave(&mut self) -> Result<(), FlameError>;
+
+    /// Sets the reporter of the progress of the next invoked task; it's ignored by the shims
+    /// which can not get the progress from the application.
+    fn set_progress_reporter(&mut self, _reporter: ProgressReporter) {}
+
+    /// Sets the collector of the annotations of the next invoked task; it's ignored by the
+    /// shims which can not get the annotations from the application.
+    fn set_annotator(&mut self, _annotator: Annotator) {}
+
+    /// Sets the streamer of the output chunks of the next invoked task; it's ignored by the
+    /// shims which can not get the chunks from the application.
+    fn set_output_streamer(&mut self, _streamer: OutputStreamer) {}
+
+    /// Sets the status of the executor, in which the shim records its process, e.g. the child
+    /// of the task; it's ignored by the shims running in the executor.
+    fn set_status(&mut self, _status: StatusPtr) {}
+}
+
+/// Reports the progress of a task to the executor; the reports are coalesced, so only the
+/// latest one is sent if they're reported faster than they're sent to the session manager.
+#[derive(Clone)]
+pub struct ProgressReporter {
+    tx: Arc<watch::Sender<Option<TaskProgress>>>,
+}
+
+impl ProgressReporter {
+    pub fn new() -> (Self, watch::Receiver<Option<TaskProgress>>) {
+        let (tx, rx) = watch::channel(None);
+        (Self { tx: Arc::new(tx) }, rx)
+    }
+
+    /// Reports the percentage of the task, which is at most 100; the payload is truncated to
+    /// `MAX_PROGRESS_PAYLOAD` bytes.
+    pub fn report(&self, percentage: u32, payload: Option<Bytes>) {
+        let payload = payload.map(|p| p.slice(..p.len().min(MAX_PROGRESS_PAYLOAD)));
+        self.tx.send_replace(Some(TaskProgress {
+            percentage: percentage.min(100),
+            payload,
+            update_time: Utc::now(),
+        }));
+    }
+}
+
+/// Collects the annotations set by a task, which are sent to the session manager with the
+/// completion of the task; the later value of a key overrides the earlier one, and an empty
+/// value removes the key.
+#[derive(Clone, Default)]
+pub struct Annotator {
+    annotations: MutexPtr<Annotations>,
+}
+
+impl Annotator {
+    pub fn annotate_task(&self, key: &str, value: &str) -> Result<(), FlameError> {
+        let mut annotations = lock_ptr!(self.annotations)?;
+        annotations.task.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    pub fn annotate_session(&self, key: &str, value: &str) -> Result<(), FlameError> {
+        let mut annotations = lock_ptr!(self.annotations)?;
+        annotations
+            .session
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Takes the annotations collected so far.
+    pub fn take(&self) -> Result<Annotations, FlameError> {
+        let mut annotations = lock_ptr!(self.annotations)?;
+        Ok(std::mem::take(&mut *annotations))
+    }
+}
+
+/// Streams the output chunks of a task to the executor, which sends them to the session
+/// manager in order before the task is completed.
+#[derive(Clone)]
+pub struct OutputStreamer {
+    tx: mpsc::Sender<Bytes>,
+}
+
+impl OutputStreamer {
+    pub fn new() -> (Self, mpsc::Receiver<Bytes>) {
+        let (tx, rx) = mpsc::channel(OUTPUT_CHUNK_QUEUE);
+        (Self { tx }, rx)
+    }
+
+    /// Queues the chunk, which is at most `MAX_OUTPUT_CHUNK_BYTES`; it blocks while the queue
+    /// is full, so it's called by the threads of the shims rather than the runtime.
+    pub fn stream(&self, data: Bytes) -> Result<(), FlameError> {
+        if data.len() > MAX_OUTPUT_CHUNK_BYTES {
+            return Err(FlameError::invalid_argument(
+                "data",
+                format!(
+                    "{} bytes are over the limit of {} bytes of a chunk",
+                    data.len(),
+                    MAX_OUTPUT_CHUNK_BYTES
+                ),
+            ));
+        }
+        self.tx
+            .blocking_send(data)
+            .map_err(|_| FlameError::Internal("the output stream is closed".to_string()))
+    }
 }
@@ -0,0 +1,324 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::apis::{Application, CommonData, SessionContext};
+use common::ctx::{FlameTimingsConf, FlameWarmPoolConf};
+use common::{lock_ptr, FlameError};
+
+use crate::shims::{self, ShimPtr};
+
+pub type WarmPoolPtr = Arc<std::sync::Mutex<WarmPool>>;
+
+/// The shims kept alive after their sessions were unbound, so binding to a new session of
+/// the same application skips the expensive `on_session_enter`, e.g. loading a model.
+pub struct WarmPool {
+    max_size: usize,
+    ttl: Duration,
+    min_available_memory: Option<u64>,
+    /// The warm shims, the oldest first.
+    shims: Vec<WarmShim>,
+}
+
+struct WarmShim {
+    application: Application,
+    /// The common data and config the shim entered with; it's entered again if the new
+    /// session has different ones.
+    common_data: Option<CommonData>,
+    config: HashMap<String, String>,
+    shim: ShimPtr,
+    since: Instant,
+}
+
+impl WarmPool {
+    pub fn new_ptr(conf: &FlameWarmPoolConf) -> Result<WarmPoolPtr, FlameError> {
+        Ok(Arc::new(std::sync::Mutex::new(WarmPool {
+            max_size: conf.max_size(),
+            ttl: conf.ttl()?,
+            min_available_memory: conf.min_available_memory()?,
+            shims: vec![],
+        })))
+    }
+
+    /// Keeps the shim warm, and returns the oldest shims evicted for it.
+    fn put(&mut self, shim: WarmShim) -> Vec<ShimPtr> {
+        self.shims.push(shim);
+
+        let n = self.shims.len().saturating_sub(self.max_size);
+        self.shims.drain(..n).map(|w| w.shim).collect()
+    }
+
+    /// Takes the latest warm shim of the application; the application has to be the same,
+    /// e.g. the command was not changed by the session manager.
+    fn take(&mut self, app: &Application) -> Option<WarmShim> {
+        let i = self.shims.iter().rposition(|w| &w.application == app)?;
+        Some(self.shims.remove(i))
+    }
+
+    /// Evicts the shims kept longer than the TTL, or all of them if the available memory of
+    /// the host is less than the minimum.
+    fn expire(&mut self, now: Instant, available_memory: Option<u64>) -> Vec<ShimPtr> {
+        let pressure = matches!(
+            (self.min_available_memory, available_memory),
+            (Some(min), Some(available)) if available < min
+        );
+
+        let (expired, warm) = self
+            .shims
+            .drain(..)
+            .partition(|w| pressure || now.duration_since(w.since) >= self.ttl);
+        self.shims = warm;
+
+        expired.into_iter().map(|w: WarmShim| w.shim).collect()
+    }
+}
+
+/// Enters the session by a warm shim of the application if any, or a new one; the warm
+/// shim enters again only if the common data or config of the session is different.
+pub async fn enter(
+    pool: Option<&WarmPoolPtr>,
+    app: &Application,
+    ssn: &SessionContext,
+) -> Result<ShimPtr, FlameError> {
+    let warm = match pool {
+        Some(pool) => lock_ptr!(pool)?.take(app),
+        None => None,
+    };
+
+    let Some(warm) = warm else {
+        let shim_ptr = shims::from(app).await?;
+        shim_ptr.lock().await.on_session_enter(ssn).await?;
+        return Ok(shim_ptr);
+    };
+
+    {
+        let mut shim = warm.shim.lock().await;
+        match warm.common_data == ssn.common_data && warm.config == ssn.config {
+            true => shim.on_session_rebind(ssn).await?,
+            false => shim.on_session_enter(ssn).await?,
+        }
+    }
+    log::debug!(
+        "Session <{}> was entered by a warm shim of <{}>.",
+        ssn.ssn_id,
+        app.name
+    );
+
+    Ok(warm.shim)
+}
+
+/// Leaves the session: the shim is kept warm if there's a pool, otherwise it leaves at once.
+pub async fn leave(
+    pool: Option<&WarmPoolPtr>,
+    app: &Application,
+    ssn: &SessionContext,
+    shim: ShimPtr,
+) -> Result<(), FlameError> {
+    let Some(pool) = pool else {
+        return shim.lock().await.on_session_leave().await;
+    };
+
+    let evicted = lock_ptr!(pool)?.put(WarmShim {
+        application: app.clone(),
+        common_data: ssn.common_data.clone(),
+        config: ssn.config.clone(),
+        shim,
+        since: Instant::now(),
+    });
+    evict(evicted).await;
+
+    Ok(())
+}
+
+/// Evicts the expired warm shims in the background every `timings.evict_interval`.
+pub async fn run_evictor(pool: WarmPoolPtr, timings: FlameTimingsConf) {
+    let interval = match timings.evict_interval() {
+        Ok(interval) => interval,
+        Err(e) => {
+            log::error!("Failed to run the evictor of the warm pool: {}", e);
+            return;
+        }
+    };
+
+    loop {
+        tokio::time::sleep(timings.jittered(interval)).await;
+
+        let expired = match lock_ptr!(pool) {
+            Ok(mut pool) => pool.expire(Instant::now(), available_memory()),
+            Err(e) => {
+                log::error!("Failed to lock the warm pool: {}", e);
+                continue;
+            }
+        };
+        evict(expired).await;
+    }
+}
+
+async fn evict(shims: Vec<ShimPtr>) {
+    for shim in shims {
+        if let Err(e) = shim.lock().await.on_session_leave().await {
+            log::warn!("Failed to evict a warm shim: {}", e);
+        }
+    }
+}
+
+/// The available memory of the host in bytes, i.e. `MemAvailable` of `/proc/meminfo`.
+fn available_memory() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+    let kb = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use tokio::sync::Mutex;
+
+    use common::apis::{TaskContext, TaskOutput};
+
+    use crate::shims::Shim;
+
+    /// Counts the calls of the shim, e.g. the expensive `on_session_enter`.
+    #[derive(Default)]
+    struct Calls {
+        enter: AtomicUsize,
+        rebind: AtomicUsize,
+        leave: AtomicUsize,
+    }
+
+    struct CountingShim(Arc<Calls>);
+
+    #[async_trait]
+    impl Shim for CountingShim {
+        async fn on_session_enter(&mut self, _: &SessionContext) -> Result<(), FlameError> {
+            self.0.enter.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+        async fn on_session_rebind(&mut self, _: &SessionContext) -> Result<(), FlameError> {
+            self.0.rebind.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+        async fn on_task_invoke(
+            &mut self,
+            _: &TaskContext,
+        ) -> Result<Option<TaskOutput>, FlameError> {
+            Ok(None)
+        }
+        async fn on_session_leave(&mut self) -> Result<(), FlameError> {
+            self.0.leave.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn session(id: &str, common_data: &'static str) -> SessionContext {
+        SessionContext {
+            ssn_id: id.to_string(),
+            application: "pi".to_string(),
+            slots: 1,
+            common_data: Some(Bytes::from(common_data)),
+            config: HashMap::new(),
+        }
+    }
+
+    fn pool(max_size: usize, ttl: &str) -> WarmPoolPtr {
+        WarmPool::new_ptr(&FlameWarmPoolConf {
+            max_size: Some(max_size),
+            ttl: Some(ttl.to_string()),
+            min_available_memory: Some("1g".to_string()),
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rebind_warm_shim() -> Result<(), FlameError> {
+        let app = Application {
+            name: "pi".to_string(),
+            ..Default::default()
+        };
+        let pool = pool(1, "10m");
+        let calls = Arc::new(Calls::default());
+        let shim: ShimPtr = Arc::new(Mutex::new(CountingShim(calls.clone())));
+
+        // The shim was entered by the first session.
+        leave(Some(&pool), &app, &session("1", "model"), shim).await?;
+        assert_eq!(calls.leave.load(Ordering::SeqCst), 0);
+
+        // The second session of the same common data skips the enter path.
+        let shim = enter(Some(&pool), &app, &session("2", "model")).await?;
+        assert_eq!(calls.enter.load(Ordering::SeqCst), 0);
+        assert_eq!(calls.rebind.load(Ordering::SeqCst), 1);
+
+        // The common data is replayed if it changed.
+        leave(Some(&pool), &app, &session("2", "model"), shim).await?;
+        let shim = enter(Some(&pool), &app, &session("3", "model-v2")).await?;
+        assert_eq!(calls.enter.load(Ordering::SeqCst), 1);
+        assert_eq!(calls.rebind.load(Ordering::SeqCst), 1);
+
+        // The warm shim of a changed application is not reused, but evicted for the new one.
+        leave(Some(&pool), &app, &session("3", "model-v2"), shim).await?;
+        let changed = Application {
+            command: "/usr/bin/pi".to_string(),
+            ..app.clone()
+        };
+        let shim = enter(Some(&pool), &changed, &session("4", "model-v2")).await?;
+        assert_eq!(calls.rebind.load(Ordering::SeqCst), 1);
+        leave(Some(&pool), &changed, &session("4", "model-v2"), shim).await?;
+        assert_eq!(calls.leave.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expire_warm_shims() -> Result<(), FlameError> {
+        let app = Application {
+            name: "pi".to_string(),
+            ..Default::default()
+        };
+        let pool = pool(2, "10m");
+        let calls = Arc::new(Calls::default());
+        for id in ["1", "2"] {
+            let shim: ShimPtr = Arc::new(Mutex::new(CountingShim(calls.clone())));
+            leave(Some(&pool), &app, &session(id, "model"), shim).await?;
+        }
+
+        let now = Instant::now();
+        let mut pool = lock_ptr!(pool)?;
+        assert!(pool.expire(now, Some(2 << 30)).is_empty());
+        // The memory pressure evicts all of them.
+        assert_eq!(pool.expire(now, Some(512 << 20)).len(), 2);
+
+        let shim: ShimPtr = Arc::new(Mutex::new(CountingShim(calls.clone())));
+        pool.put(WarmShim {
+            application: app,
+            common_data: None,
+            config: HashMap::new(),
+            shim,
+            since: now,
+        });
+        assert!(pool.expire(now + Duration::from_secs(60), None).is_empty());
+        assert_eq!(pool.expire(now + Duration::from_secs(600), None).len(), 1);
+
+        Ok(())
+    }
+}
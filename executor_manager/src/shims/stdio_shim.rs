@@ -11,26 +11,45 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, MAIN_SEPARATOR};
 use std::process::{Command, Stdio};
 use std::sync::Arc;
 use std::{env, thread};
 
 use async_trait::async_trait;
+use bytes::Bytes;
 use tokio::sync::Mutex;
 
-use crate::shims::{Shim, ShimPtr};
-use common::apis::{Application, SessionContext, TaskContext, TaskOutput};
+use crate::shims::{Annotator, OutputStreamer, ProgressReporter, Shim, ShimPtr};
+use crate::status::{self, StatusPtr};
+use common::apis::{self, Application, SessionContext, TaskContext, TaskOutput};
+use common::payload;
+use common::trace::{self, TRACEPARENT_ENV};
 use common::FlameError;
 
 const FLAME_TASK_ID: &str = "FLAME_TASK_ID";
 const FLAME_SESSION_ID: &str = "FLAME_SESSION_ID";
 
+/// The prefix of the lines in stderr by which the task reports its progress, e.g.
+/// `FLAME_PROGRESS 25 loading data`; the other lines are forwarded to the stderr.
+const PROGRESS_PREFIX: &str = "FLAME_PROGRESS ";
+/// The prefixes of the lines in stderr by which the task annotates itself and its session,
+/// e.g. `FLAME_ANNOTATION rows=100`; an empty value removes the annotation.
+const ANNOTATION_PREFIX: &str = "FLAME_ANNOTATION ";
+const SESSION_ANNOTATION_PREFIX: &str = "FLAME_SESSION_ANNOTATION ";
+/// The prefix of the lines in stderr by which the task streams a chunk of its output, e.g.
+/// `FLAME_OUTPUT_CHUNK frame 1 rendered`; the rest of the line is the chunk.
+const OUTPUT_CHUNK_PREFIX: &str = "FLAME_OUTPUT_CHUNK ";
+
 #[derive(Clone)]
 pub struct StdioShim {
     application: Application,
     session_context: Option<SessionContext>,
+    progress: Option<ProgressReporter>,
+    annotator: Option<Annotator>,
+    streamer: Option<OutputStreamer>,
+    status: Option<StatusPtr>,
 }
 
 impl StdioShim {
@@ -38,10 +57,55 @@ impl StdioShim {
         Arc::new(Mutex::new(Self {
             application: app.clone(),
             session_context: None,
+            progress: None,
+            annotator: None,
+            streamer: None,
+            status: None,
         }))
     }
 }
 
+/// Parses the progress reported by the task, i.e. `<percentage> [payload]`.
+fn parse_progress(line: &str) -> Option<(u32, Option<Bytes>)> {
+    let (percentage, payload) = match line.split_once(' ') {
+        Some((percentage, payload)) => (percentage, Some(Bytes::from(payload.to_string()))),
+        None => (line, None),
+    };
+
+    Some((percentage.trim().parse().ok()?, payload))
+}
+
+/// Parses the annotation set by the task, i.e. `<key>=<value>`.
+fn parse_annotation(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once('=')?;
+    let key = key.trim();
+    if key.is_empty() {
+        return None;
+    }
+
+    Some((key, value.trim()))
+}
+
+/// Collects the annotation of the line into the annotator if any.
+fn annotate(
+    annotator: &Option<Annotator>,
+    line: &str,
+    set: fn(&Annotator, &str, &str) -> Result<(), FlameError>,
+) {
+    let Some((key, value)) = parse_annotation(line) else {
+        log::warn!(
+            "Invalid annotation {} of the task.",
+            payload::display(Some(line.as_bytes()))
+        );
+        return;
+    };
+    if let Some(annotator) = annotator {
+        if let Err(e) = set(annotator, key, value) {
+            log::warn!("Failed to collect the annotation <{}>: {}", key, e);
+        }
+    }
+}
+
 #[async_trait]
 impl Shim for StdioShim {
     async fn on_session_enter(&mut self, ctx: &SessionContext) -> Result<(), FlameError> {
@@ -50,10 +114,22 @@ impl Shim for StdioShim {
         Ok(())
     }
 
+    async fn on_session_rebind(&mut self, ctx: &SessionContext) -> Result<(), FlameError> {
+        self.session_context = Some(ctx.clone());
+
+        Ok(())
+    }
+
+    /// Runs the command of the application in a process for the task: the input is written
+    /// to its stdin, and its stdout is the output if it exits successfully; the task fails
+    /// by the exit status otherwise, e.g. `/usr/bin/rev` reverses the lines of the input.
     async fn on_task_invoke(
         &mut self,
         ctx: &TaskContext,
     ) -> Result<Option<TaskOutput>, FlameError> {
+        // The streamer is dropped once the stderr is read to the end or the task fails to
+        // start, which ends the stream of the task.
+        let streamer = self.streamer.take();
         let mut cmd = self.application.command.clone();
         let path = Path::new(&cmd);
         if !path.has_root() {
@@ -76,27 +152,106 @@ impl Shim for StdioShim {
             }
         }
 
-        let mut child = Command::new(&cmd)
+        let mut command = Command::new(&cmd);
+        command
+            .args(&self.application.arguments)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
-            .current_dir(&self.application.working_directory)
+            .stderr(Stdio::piped())
+            .current_dir(&self.application.working_directory);
+        // The environments of the application, then the config of the session, and then the
+        // environments of the task, so the task overrides the others; the ones of Flame are
+        // set at last, which the task can not override.
+        for env in &self.application.environments {
+            match env.split_once('=') {
+                Some((name, value)) => command.env(name, value),
+                None => {
+                    log::warn!("Invalid environment <{}> of the application.", env);
+                    continue;
+                }
+            };
+        }
+        // The config of the session is exported by the sanitized keys, e.g.
+        // `FLAME_SSN_MODEL_VERSION` of `model-version`.
+        if let Some(ssn) = &self.session_context {
+            command.envs(
+                ssn.config
+                    .iter()
+                    .map(|(key, value)| (apis::config_env_name(key), value)),
+            );
+        }
+        command
+            .envs(&ctx.env)
             .env(FLAME_TASK_ID, &ctx.id)
-            .env(FLAME_SESSION_ID, &ctx.ssn_id)
-            .spawn()
-            .map_err(|_| FlameError::Internal("failed to start subprocess".to_string()))?;
+            .env(FLAME_SESSION_ID, &ctx.ssn_id);
+        // The worker continues the trace of the task by the W3C trace context.
+        if let Some(traceparent) = trace::current().or(ctx.trace_context.clone()) {
+            command.env(TRACEPARENT_ENV, traceparent);
+        }
 
+        let mut child = command.spawn().map_err(|e| {
+            FlameError::Internal(format!("failed to start <{}> for the task: {}", cmd, e))
+        })?;
+        if let Some(status) = &self.status {
+            status::update(status, |s| s.shim_started(child.id()));
+        }
+
+        // The stdin is closed once the input is written, or at once if there's no input, so
+        // the process reading to the end of it is not blocked.
         let mut stdin = child.stdin.take().unwrap();
-        if let Some(input) = &ctx.input {
-            let input = input.clone();
-            let _handler = thread::spawn(move || {
-                match stdin.write_all(&input) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        log::error!("Failed to send input into shim instance: {}.", e);
+        let input = ctx.input.clone();
+        let _handler = thread::spawn(move || {
+            if let Some(input) = input {
+                if let Err(e) = stdin.write_all(&input) {
+                    log::error!("Failed to send input into shim instance: {}.", e);
+                }
+            }
+        });
+
+        // The last line of the stderr is the message of the failure, if the process fails.
+        let stderr = child.stderr.take().unwrap();
+        let progress = self.progress.clone();
+        let annotator = self.annotator.clone();
+        let stderr_handler = thread::spawn(move || {
+            let mut last_line = None;
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                if let Some(chunk) = line.strip_prefix(OUTPUT_CHUNK_PREFIX) {
+                    if let Some(streamer) = &streamer {
+                        if let Err(e) = streamer.stream(Bytes::from(chunk.to_string())) {
+                            log::warn!("Failed to stream the output chunk of the task: {}", e);
+                        }
+                    }
+                    continue;
+                }
+                if let Some(annotation) = line.strip_prefix(ANNOTATION_PREFIX) {
+                    annotate(&annotator, annotation, Annotator::annotate_task);
+                    continue;
+                }
+                if let Some(annotation) = line.strip_prefix(SESSION_ANNOTATION_PREFIX) {
+                    annotate(&annotator, annotation, Annotator::annotate_session);
+                    continue;
+                }
+                let Some(reported) = line.strip_prefix(PROGRESS_PREFIX) else {
+                    eprintln!("{}", line);
+                    if !line.trim().is_empty() {
+                        last_line = Some(line);
                     }
+                    continue;
                 };
-            });
-        }
+                match (parse_progress(reported), &progress) {
+                    (Some((percentage, payload)), Some(progress)) => {
+                        progress.report(percentage, payload)
+                    }
+                    (Some(_), None) => {}
+                    (None, _) => log::warn!(
+                        "Invalid progress {} of the task.",
+                        payload::display(Some(reported.as_bytes()))
+                    ),
+                }
+            }
+
+            last_line
+        });
 
         let mut stdout = child.stdout.take().unwrap();
         let mut data = vec![];
@@ -104,18 +259,27 @@ impl Shim for StdioShim {
             .read_to_end(&mut data)
             .map_err(|_| FlameError::Internal("failed to read task output".to_string()))?;
 
-        log::debug!("Read <{}> data from child process.", n);
+        log::debug!(
+            "Read {} from child process.",
+            payload::display(Some(&data[..n]))
+        );
 
-        match child.wait() {
-            Ok(es) => {
-                if !es.success() {
-                    log::info!("Child process exist with error: {}", es);
-                }
-            }
-            Err(e) => {
-                log::error!("Failed to wait child process: {}", e)
+        let exit_status = child.wait();
+        if let Some(status) = &self.status {
+            status::update(status, |s| s.shim_exited());
+        }
+        let exit_status = exit_status.map_err(|e| {
+            FlameError::Internal(format!("failed to wait for the process of the task: {}", e))
+        })?;
+        // The stderr is read to the end, so all the annotations of the task are collected.
+        let last_line = stderr_handler.join().ok().flatten();
+        if !exit_status.success() {
+            let mut message = format!("the process of the task exited with {}", exit_status);
+            if let Some(line) = last_line {
+                message = format!("{}: {}", message, line);
             }
-        };
+            return Err(FlameError::Internal(message));
+        }
 
         Ok(Some(TaskOutput::from(data)))
     }
@@ -123,4 +287,279 @@ impl Shim for StdioShim {
     async fn on_session_leave(&mut self) -> Result<(), FlameError> {
         Ok(())
     }
+
+    fn set_progress_reporter(&mut self, reporter: ProgressReporter) {
+        self.progress = Some(reporter);
+    }
+
+    fn set_annotator(&mut self, annotator: Annotator) {
+        self.annotator = Some(annotator);
+    }
+
+    fn set_output_streamer(&mut self, streamer: OutputStreamer) {
+        self.streamer = Some(streamer);
+    }
+
+    fn set_status(&mut self, status: StatusPtr) {
+        self.status = Some(status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use common::apis::TaskInput;
+
+    #[tokio::test]
+    async fn test_session_config_env() -> Result<(), FlameError> {
+        let app = Application {
+            name: "env".to_string(),
+            command: "/usr/bin/env".to_string(),
+            working_directory: "/tmp".to_string(),
+            ..Default::default()
+        };
+        let shim = StdioShim::new_ptr(&app);
+        let mut shim = shim.lock().await;
+
+        shim.on_session_enter(&SessionContext {
+            ssn_id: "1".to_string(),
+            application: app.name.clone(),
+            slots: 1,
+            common_data: None,
+            config: HashMap::from([
+                ("model-version".to_string(), "v2".to_string()),
+                ("output_bucket".to_string(), "s3://out".to_string()),
+            ]),
+        })
+        .await?;
+        let output = shim
+            .on_task_invoke(&TaskContext {
+                id: "1".to_string(),
+                ssn_id: "1".to_string(),
+                input: None,
+                output: None,
+                trace_context: None,
+                locality: None,
+                env: Default::default(),
+            })
+            .await?
+            .unwrap();
+
+        let env = String::from_utf8_lossy(&output);
+        let vars: Vec<&str> = env.lines().collect();
+        assert!(vars.contains(&"FLAME_SSN_MODEL_VERSION=v2"), "{}", env);
+        assert!(
+            vars.contains(&"FLAME_SSN_OUTPUT_BUCKET=s3://out"),
+            "{}",
+            env
+        );
+        assert!(vars.contains(&"FLAME_SESSION_ID=1"), "{}", env);
+
+        Ok(())
+    }
+
+    /// Runs a task of the input and the environments by the command in a new session.
+    async fn run_task(
+        command: &str,
+        arguments: &[&str],
+        input: Option<&str>,
+        env: &[(&str, &str)],
+    ) -> Result<Option<TaskOutput>, FlameError> {
+        let app = Application {
+            name: "cli".to_string(),
+            command: command.to_string(),
+            arguments: arguments.iter().map(|a| a.to_string()).collect(),
+            environments: vec!["MODE=fast".to_string(), "OUTPUT_PATH=/tmp".to_string()],
+            working_directory: "/tmp".to_string(),
+            ..Default::default()
+        };
+        let shim = StdioShim::new_ptr(&app);
+        let mut shim = shim.lock().await;
+        shim.on_session_enter(&SessionContext {
+            ssn_id: "1".to_string(),
+            application: app.name.clone(),
+            slots: 1,
+            common_data: None,
+            config: HashMap::new(),
+        })
+        .await?;
+
+        shim.on_task_invoke(&TaskContext {
+            id: "1".to_string(),
+            ssn_id: "1".to_string(),
+            input: input.map(|i| TaskInput::from(i.to_string())),
+            output: None,
+            trace_context: None,
+            locality: None,
+            env: env
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_task_process() -> Result<(), FlameError> {
+        // The input is written to the stdin, and the stdout is the output.
+        let output = run_task("/usr/bin/rev", &[], Some("hello\nflame\n"), &[]).await?;
+        assert_eq!(output, Some(TaskOutput::from("olleh\nemalf\n")));
+
+        // The stdin is closed at once if there's no input.
+        let output = run_task("/usr/bin/rev", &[], None, &[]).await?;
+        assert_eq!(output, Some(TaskOutput::new()));
+
+        // The task fails by the exit status, with the last line of the stderr.
+        let script = "echo working >&2; echo 'no such file' >&2; exit 3";
+        let e = run_task("/bin/sh", &["-c", script], None, &[])
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("exit status: 3"), "{}", e);
+        assert!(e.to_string().contains("no such file"), "{}", e);
+
+        let e = run_task("/no/such/command", &[], None, &[])
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("/no/such/command"), "{}", e);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_task_env() -> Result<(), FlameError> {
+        let env = [("RANDOM_SEED", "42"), ("OUTPUT_PATH", "/tmp/task-1")];
+        let output = run_task("/usr/bin/env", &[], None, &env).await?.unwrap();
+
+        // The environments of the task override the ones of the application.
+        let output = String::from_utf8_lossy(&output);
+        let vars: Vec<&str> = output.lines().collect();
+        for var in [
+            "RANDOM_SEED=42",
+            "OUTPUT_PATH=/tmp/task-1",
+            "MODE=fast",
+            "FLAME_TASK_ID=1",
+        ] {
+            assert!(vars.contains(&var), "{}", output);
+        }
+        assert!(!vars.contains(&"OUTPUT_PATH=/tmp"), "{}", output);
+
+        // They're only set for their own task.
+        let output = run_task("/usr/bin/env", &[], None, &[]).await?.unwrap();
+        let output = String::from_utf8_lossy(&output);
+        assert!(!output.contains("RANDOM_SEED"), "{}", output);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_annotation() {
+        assert_eq!(parse_annotation("rows=100"), Some(("rows", "100")));
+        assert_eq!(parse_annotation(" score = 0.9 "), Some(("score", "0.9")));
+        assert_eq!(
+            parse_annotation("url=s3://a?b=c"),
+            Some(("url", "s3://a?b=c"))
+        );
+        assert_eq!(parse_annotation("score="), Some(("score", "")));
+        assert_eq!(parse_annotation("=100"), None);
+        assert_eq!(parse_annotation("rows"), None);
+    }
+
+    #[tokio::test]
+    async fn test_task_annotations() -> Result<(), FlameError> {
+        let script = concat!(
+            "echo 'FLAME_ANNOTATION rows=100' >&2; ",
+            "echo 'FLAME_ANNOTATION rows=200' >&2; ",
+            "echo 'FLAME_ANNOTATION score=' >&2; ",
+            "echo 'FLAME_ANNOTATION invalid' >&2; ",
+            "echo 'FLAME_SESSION_ANNOTATION model=v2' >&2; ",
+            "echo done",
+        );
+        let app = Application {
+            name: "sh".to_string(),
+            command: "/bin/sh".to_string(),
+            arguments: vec!["-c".to_string(), script.to_string()],
+            working_directory: "/tmp".to_string(),
+            ..Default::default()
+        };
+        let shim = StdioShim::new_ptr(&app);
+        let mut shim = shim.lock().await;
+        let annotator = Annotator::default();
+        shim.set_annotator(annotator.clone());
+
+        let output = shim
+            .on_task_invoke(&TaskContext {
+                id: "1".to_string(),
+                ssn_id: "1".to_string(),
+                input: None,
+                output: None,
+                trace_context: None,
+                locality: None,
+                env: Default::default(),
+            })
+            .await?;
+        assert_eq!(output, Some(TaskOutput::from("done\n")));
+
+        // The later value overrides the earlier one, and the empty one is kept to remove
+        // the key in the session manager.
+        let annotations = annotator.take()?;
+        let pairs = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert_eq!(annotations.task, pairs(&[("rows", "200"), ("score", "")]));
+        assert_eq!(annotations.session, pairs(&[("model", "v2")]));
+        assert!(annotator.take()?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_output_chunks() -> Result<(), FlameError> {
+        let script = concat!(
+            "echo 'FLAME_OUTPUT_CHUNK frame 1' >&2; ",
+            "echo 'rendering' >&2; ",
+            "echo 'FLAME_OUTPUT_CHUNK frame 2' >&2; ",
+            "echo done",
+        );
+        let app = Application {
+            name: "sh".to_string(),
+            command: "/bin/sh".to_string(),
+            arguments: vec!["-c".to_string(), script.to_string()],
+            working_directory: "/tmp".to_string(),
+            ..Default::default()
+        };
+        let shim = StdioShim::new_ptr(&app);
+        let mut shim = shim.lock().await;
+        let (streamer, mut chunks) = OutputStreamer::new();
+        shim.set_output_streamer(streamer);
+
+        let output = shim
+            .on_task_invoke(&TaskContext {
+                id: "1".to_string(),
+                ssn_id: "1".to_string(),
+                input: None,
+                output: None,
+                trace_context: None,
+                locality: None,
+                env: Default::default(),
+            })
+            .await?;
+        assert_eq!(output, Some(TaskOutput::from("done\n")));
+
+        // The stream is ended once the task is completed.
+        let mut streamed = vec![];
+        while let Some(chunk) = chunks.recv().await {
+            streamed.push(chunk);
+        }
+        assert_eq!(
+            streamed,
+            vec![Bytes::from("frame 1"), Bytes::from("frame 2")]
+        );
+
+        Ok(())
+    }
 }
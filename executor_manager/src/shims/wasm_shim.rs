@@ -76,6 +76,7 @@ impl Shim for WasmShim {
         let ssn_ctx = service::SessionContext {
             session_id: ctx.ssn_id.clone(),
             common_data: ctx.common_data.clone().map(apis::CommonData::into),
+            config: ctx.config.clone().into_iter().collect(),
         };
 
         let _ = self
@@ -91,6 +92,15 @@ impl Shim for WasmShim {
         Ok(())
     }
 
+    async fn on_session_rebind(
+        &mut self,
+        ctx: &apis::SessionContext,
+    ) -> Result<(), common::FlameError> {
+        self.session_context = Some(ctx.clone());
+
+        Ok(())
+    }
+
     async fn on_task_invoke(
         &mut self,
         ctx: &apis::TaskContext,
@@ -119,6 +129,7 @@ impl Shim for WasmShim {
         let ssn_ctx = service::SessionContext {
             session_id: self.session_context.clone().unwrap().ssn_id.clone(),
             common_data: None,
+            config: vec![],
         };
 
         let _ = self
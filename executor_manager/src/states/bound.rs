@@ -11,13 +11,25 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use std::time::{Duration, Instant};
+
 use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::sync::{mpsc, watch};
+use tracing::Instrument;
 
 use crate::client;
 use crate::executor::{Executor, ExecutorState};
+use crate::shims::{Annotator, OutputStreamer, ProgressReporter};
 use crate::states::State;
+use crate::status;
+use common::apis::{TaskContext, TaskProgress};
 use common::ctx::FlameContext;
-use common::{trace::TraceFn, trace_fn, FlameError};
+use common::trace::{self, TraceFn};
+use common::{trace_fn, FlameError};
+
+/// The minimal interval between the progress reports of a task sent to the session manager.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Clone)]
 pub struct BoundState {
@@ -34,24 +46,15 @@ impl State for BoundState {
 
         match task {
             Some(task_ctx) => {
-                let shim_ptr = &mut self.executor.shim.clone().ok_or(FlameError::InvalidState(
-                    "no shim in bound state".to_string(),
-                ))?;
-                {
-                    let mut shim = shim_ptr.lock().await;
-                    let output = shim.on_task_invoke(&task_ctx).await?;
-                    if let Some(task_ctx) = &mut self.executor.task {
-                        task_ctx.output = output;
-                    }
-                };
-
-                client::complete_task(ctx, &self.executor.clone()).await?;
-
-                let (ssn_id, task_id) = {
-                    let task = &self.executor.task.clone().unwrap();
-                    (task.ssn_id.clone(), task.id.clone())
-                };
-                log::debug!("Complete task <{}/{}>", ssn_id, task_id)
+                // Continue the trace of the request which created the task.
+                let span = tracing::info_span!(
+                    "Executor::invoke_task",
+                    session_id = %task_ctx.ssn_id,
+                    task_id = %task_ctx.id
+                );
+                trace::set_parent(&span, task_ctx.trace_context.as_deref());
+
+                self.invoke_task(ctx, &task_ctx).instrument(span).await?;
             }
             None => {
                 self.executor.state = ExecutorState::Unbound;
@@ -63,3 +66,169 @@ impl State for BoundState {
         Ok(self.executor.clone())
     }
 }
+
+impl BoundState {
+    async fn invoke_task(
+        &mut self,
+        ctx: &FlameContext,
+        task_ctx: &TaskContext,
+    ) -> Result<(), FlameError> {
+        let shim_ptr = &mut self
+            .executor
+            .shim
+            .clone()
+            .ok_or(FlameError::Internal("no shim in bound state".to_string()))?;
+        let (reporter, progress) = ProgressReporter::new();
+        let annotator = Annotator::default();
+        let forwarder = tokio::spawn(forward_progress(
+            ctx.clone(),
+            self.executor.id.clone(),
+            task_ctx.clone(),
+            progress,
+        ));
+        let (streamer, chunks) = OutputStreamer::new();
+        let streaming = tokio::spawn(forward_output(
+            ctx.clone(),
+            self.executor.id.clone(),
+            task_ctx.clone(),
+            chunks,
+        ));
+        let started = Instant::now();
+        status::update(&self.executor.status, |s| s.task_started(task_ctx));
+        // A new locality is sent at once, as the task may end before the first heartbeat.
+        let learned = self.executor.learn_locality(task_ctx);
+        let heartbeats = tokio::spawn(send_heartbeats(
+            ctx.clone(),
+            self.executor.id.clone(),
+            self.executor.busy_time,
+            self.executor.locality_keys(),
+            started,
+            learned,
+        ));
+        let output = {
+            let mut shim = shim_ptr.lock().await;
+            shim.set_progress_reporter(reporter);
+            shim.set_annotator(annotator.clone());
+            shim.set_output_streamer(streamer);
+            shim.set_status(self.executor.status.clone());
+            shim.on_task_invoke(task_ctx).await
+        };
+        status::update(&self.executor.status, |s| s.task_completed());
+        // No progress is reported after the task is completed.
+        forwarder.abort();
+        heartbeats.abort();
+        self.executor.busy_time += started.elapsed();
+        let annotations = annotator.take()?;
+        // The chunks streamed by the task are sent before it's completed, so the clients
+        // following them read all of them.
+        let _ = streaming.await;
+
+        // The failed task is requeued by the session manager, which quarantines the executor
+        // if it fails too many tasks.
+        let output = match output {
+            Ok(output) => output,
+            Err(e) => {
+                log::warn!("Task <{}/{}> failed: {}", task_ctx.ssn_id, task_ctx.id, e);
+                status::update(&self.executor.status, |s| s.failed(&e));
+                return client::complete_task(ctx, &self.executor.clone(), Some(&e), &annotations)
+                    .await;
+            }
+        };
+        if let Some(task_ctx) = &mut self.executor.task {
+            task_ctx.output = output;
+        }
+
+        client::complete_task(ctx, &self.executor.clone(), None, &annotations).await?;
+
+        log::debug!("Complete task <{}/{}>", task_ctx.ssn_id, task_ctx.id);
+
+        Ok(())
+    }
+}
+
+/// Sends a heartbeat every `timings.heartbeat_interval` while the task is running, so the
+/// session manager does not take the executor as lost during a long task; the failures are
+/// only logged. The busy time includes the running task since `started`; the first heartbeat
+/// is sent at once if `eager`.
+async fn send_heartbeats(
+    ctx: FlameContext,
+    executor_id: String,
+    busy_time: Duration,
+    locality_keys: Vec<String>,
+    started: Instant,
+    mut eager: bool,
+) {
+    let timings = ctx.timings.clone().unwrap_or_default();
+    let interval = match timings.heartbeat_interval() {
+        Ok(interval) => interval,
+        Err(e) => {
+            log::error!(
+                "Failed to send the heartbeats of executor <{}>: {}",
+                executor_id,
+                e
+            );
+            return;
+        }
+    };
+
+    loop {
+        if !eager {
+            tokio::time::sleep(timings.jittered(interval)).await;
+        }
+        eager = false;
+        let busy_time = busy_time + started.elapsed();
+        let keys = locality_keys.clone();
+        if let Err(e) = client::heartbeat(&ctx, &executor_id, busy_time, keys).await {
+            log::warn!(
+                "Failed to send the heartbeat of executor <{}>: {}",
+                executor_id,
+                e
+            );
+        }
+    }
+}
+
+/// Sends the progress of the task to the session manager at most once per
+/// `PROGRESS_INTERVAL`; the failures are only logged, as the task goes on anyway.
+async fn forward_progress(
+    ctx: FlameContext,
+    executor_id: String,
+    task_ctx: TaskContext,
+    mut progress: watch::Receiver<Option<TaskProgress>>,
+) {
+    while progress.changed().await.is_ok() {
+        let latest = progress.borrow_and_update().clone();
+        if let Some(latest) = latest {
+            if let Err(e) =
+                client::report_task_progress(&ctx, &executor_id, &task_ctx, &latest).await
+            {
+                log::warn!(
+                    "Failed to report the progress of task <{}/{}>: {}",
+                    task_ctx.ssn_id,
+                    task_ctx.id,
+                    e
+                );
+            }
+        }
+
+        tokio::time::sleep(PROGRESS_INTERVAL).await;
+    }
+}
+
+/// Sends the output chunks of the task to the session manager in order; a failure is only
+/// logged, and the rest of the chunks are dropped, as the task goes on anyway.
+async fn forward_output(
+    ctx: FlameContext,
+    executor_id: String,
+    task_ctx: TaskContext,
+    chunks: mpsc::Receiver<Bytes>,
+) {
+    if let Err(e) = client::stream_task_output(&ctx, &executor_id, &task_ctx, chunks).await {
+        log::warn!(
+            "Failed to stream the output of task <{}/{}>: {}",
+            task_ctx.ssn_id,
+            task_ctx.id,
+            e
+        );
+    }
+}
@@ -13,26 +13,51 @@ limitations under the License.
 
 use async_trait::async_trait;
 
+use crate::client;
 use crate::executor::{Executor, ExecutorState};
+use crate::shims::pool;
 use crate::states::State;
-use crate::{client, shims};
+use common::apis::Application;
 use common::ctx::FlameContext;
 use common::{trace::TraceFn, trace_fn, FlameError};
-// use common::apis::Application;
 
 #[derive(Clone)]
 pub struct IdleState {
     pub executor: Executor,
 }
 
+async fn fallback_application(ctx: &FlameContext, name: &str) -> Option<Application> {
+    // The application may be registered after the executor started, so fetch it from
+    // the session manager first.
+    match client::get_application(ctx, name).await {
+        Ok(app) => Some(app),
+        Err(e) => {
+            log::warn!(
+                "Failed to get application <{}> from session manager, fallback to local config: {}",
+                name,
+                e
+            );
+            ctx.get_application(&name.to_string())
+        }
+    }
+}
+
 #[async_trait]
 impl State for IdleState {
     async fn execute(&mut self, ctx: &FlameContext) -> Result<Executor, FlameError> {
         trace_fn!("IdleState::execute");
 
-        let ssn = client::bind_executor(ctx, &self.executor.clone()).await?;
+        let binding = client::bind_executor(ctx, &self.executor.clone()).await?;
+        let ssn = binding.session;
+        self.executor.generation = binding.generation;
+        self.executor.session_generation = binding.session_generation;
 
-        let app = ctx.get_application(&ssn.application);
+        // Run the application of the session manager, so all executors run the same one even
+        // if their local configurations drift; fall back for the older session managers.
+        let app = match binding.application {
+            Some(app) => Some(app),
+            None => fallback_application(ctx, &ssn.application).await,
+        };
         match app {
             None => {
                 log::error!("Failed to find Application in Executor.");
@@ -42,20 +67,16 @@ impl State for IdleState {
                 )))
             }
             Some(app) => {
-                // let app = Application::from(&app);
-                let shim_ptr = shims::from(&app).await?;
-
-                {
-                    // TODO(k82cn): if on_session_enter failed, add retry limits.
-                    let mut shim = shim_ptr.lock().await;
-                    shim.on_session_enter(&ssn).await?;
-                };
+                let app = self.executor.in_scratch(app)?;
+                // TODO(k82cn): if on_session_enter failed, add retry limits.
+                let shim_ptr = pool::enter(self.executor.warm_pool.as_ref(), &app, &ssn).await?;
 
                 client::bind_executor_completed(ctx, &self.executor.clone()).await?;
 
                 // Own the shim.
                 self.executor.shim = Some(shim_ptr.clone());
                 self.executor.session = Some(ssn.clone());
+                self.executor.application = Some(app);
                 self.executor.state = ExecutorState::Bound;
 
                 log::debug!(
@@ -11,6 +11,8 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use std::time::Duration;
+
 use async_trait::async_trait;
 
 use crate::client;
@@ -30,6 +32,8 @@ impl State for InitState {
         trace_fn!("InitState::execute");
 
         client::register_executor(ctx, &self.executor.clone()).await?;
+        // The session manager measures the busy time from the registration.
+        self.executor.busy_time = Duration::ZERO;
 
         self.executor.state = ExecutorState::Idle;
 
@@ -15,6 +15,7 @@ use async_trait::async_trait;
 
 use crate::client;
 use crate::executor::{Executor, ExecutorState};
+use crate::shims::pool;
 use crate::states::State;
 use common::ctx::FlameContext;
 use common::{trace::TraceFn, trace_fn, FlameError};
@@ -30,13 +31,20 @@ impl State for UnboundState {
         trace_fn!("UnboundState::execute");
 
         client::unbind_executor(ctx, &self.executor.clone()).await?;
-        let shim_ptr = &mut self.executor.shim.clone().ok_or(FlameError::InvalidState(
-            "no shim in bound state".to_string(),
-        ))?;
-
-        {
-            let mut shim = shim_ptr.lock().await;
-            shim.on_session_leave().await?;
+        let shim_ptr = &mut self
+            .executor
+            .shim
+            .clone()
+            .ok_or(FlameError::Internal("no shim in bound state".to_string()))?;
+
+        match (&self.executor.application, &self.executor.session) {
+            (Some(app), Some(ssn)) => {
+                pool::leave(self.executor.warm_pool.as_ref(), app, ssn, shim_ptr.clone()).await?;
+            }
+            _ => {
+                let mut shim = shim_ptr.lock().await;
+                shim.on_session_leave().await?;
+            }
         }
 
         client::unbind_executor_completed(ctx, &self.executor.clone()).await?;
@@ -44,6 +52,7 @@ impl State for UnboundState {
         self.executor.task = None;
         self.executor.session = None;
         self.executor.shim = None;
+        self.executor.application = None;
 
         // After unbound from session, the executor is idle now.
         self.executor.state = ExecutorState::Idle;
@@ -0,0 +1,376 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The local status of the executor for the tooling on its host, which is served on the
+//! metrics address of the executor:
+//!
+//! * `GET /status`: the status of the executor as JSON, see `StatusView`;
+//! * `GET /healthz`: 200 while the executor is running;
+//! * `POST /drain`: the executor binds no new session, and it's unregistered and exits once
+//!   it's idle.
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::net::TcpListener;
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use crate::executor::{Executor, ExecutorState};
+use common::apis::TaskContext;
+use common::FlameError;
+
+/// How many of the latest state transitions are kept in the status.
+const MAX_TRANSITIONS: usize = 16;
+
+/// The status of the executor, which is updated by the state machine at the transition
+/// points; it's only locked to be updated or copied, so serving it never blocks the state
+/// machine for long.
+pub type StatusPtr = Arc<RwLock<StatusView>>;
+
+/// Tells the state machine to drain the executor; see `serve`.
+pub type DrainPtr = Arc<Notify>;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Transition {
+    pub state: ExecutorState,
+    pub time: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Failure {
+    pub message: String,
+    pub time: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StatusView {
+    pub id: String,
+    pub state: ExecutorState,
+    pub session_id: Option<String>,
+    pub task_id: Option<String>,
+    pub task_start_time: Option<DateTime<Utc>>,
+    /// How long the current task has been running; it's set by `snapshot`.
+    pub task_running_seconds: Option<f64>,
+    /// The process of the shim running the current task, e.g. the child of the stdio shim.
+    pub shim_pid: Option<u32>,
+    /// How many times the process of the shim was started again, e.g. for each task of the
+    /// stdio shim after the first one.
+    pub shim_restarts: u32,
+    /// The latest transitions of the state, the oldest first.
+    pub transitions: VecDeque<Transition>,
+    pub last_error: Option<Failure>,
+    pub draining: bool,
+
+    #[serde(skip)]
+    shim_starts: u32,
+}
+
+impl StatusView {
+    pub fn new_ptr(id: &str) -> StatusPtr {
+        Arc::new(RwLock::new(StatusView {
+            id: id.to_string(),
+            state: ExecutorState::Init,
+            session_id: None,
+            task_id: None,
+            task_start_time: None,
+            task_running_seconds: None,
+            shim_pid: None,
+            shim_restarts: 0,
+            transitions: VecDeque::from([Transition {
+                state: ExecutorState::Init,
+                time: Utc::now(),
+            }]),
+            last_error: None,
+            draining: false,
+            shim_starts: 0,
+        }))
+    }
+
+    /// Records the state of the executor after a transition.
+    pub fn transit(&mut self, exec: &Executor) {
+        self.session_id = exec.session.as_ref().map(|s| s.ssn_id.clone());
+        if self.state == exec.state {
+            return;
+        }
+
+        self.state = exec.state;
+        if self.transitions.len() >= MAX_TRANSITIONS {
+            self.transitions.pop_front();
+        }
+        self.transitions.push_back(Transition {
+            state: exec.state,
+            time: Utc::now(),
+        });
+    }
+
+    pub fn task_started(&mut self, task: &TaskContext) {
+        self.task_id = Some(task.id.clone());
+        self.task_start_time = Some(Utc::now());
+    }
+
+    pub fn task_completed(&mut self) {
+        self.task_id = None;
+        self.task_start_time = None;
+    }
+
+    pub fn shim_started(&mut self, pid: u32) {
+        self.shim_pid = Some(pid);
+        self.shim_starts += 1;
+    }
+
+    pub fn shim_exited(&mut self) {
+        self.shim_pid = None;
+    }
+
+    pub fn failed(&mut self, e: &FlameError) {
+        self.last_error = Some(Failure {
+            message: e.to_string(),
+            time: Utc::now(),
+        });
+    }
+
+    /// Copies the status with the fields derived at the time of the copy.
+    pub fn snapshot(&self) -> StatusView {
+        let mut view = self.clone();
+        view.task_running_seconds = self
+            .task_start_time
+            .map(|start| (Utc::now() - start).num_milliseconds().max(0) as f64 / 1000.0);
+        view.shim_restarts = self.shim_starts.saturating_sub(1);
+
+        view
+    }
+}
+
+/// Updates the status; the status is only for the tooling, so the executor keeps running if
+/// it can not be updated.
+pub fn update(status: &StatusPtr, f: impl FnOnce(&mut StatusView)) {
+    match status.write() {
+        Ok(mut view) => f(&mut view),
+        Err(_) => log::warn!("Failed to update the status of the executor."),
+    }
+}
+
+pub fn snapshot(status: &StatusPtr) -> Result<StatusView, FlameError> {
+    let view = status
+        .read()
+        .map_err(|_| FlameError::Internal("rwlock ptr".to_string()))?;
+
+    Ok(view.snapshot())
+}
+
+/// Serves the status of the executor on the listener until the executor exits.
+pub async fn serve(
+    listener: TcpListener,
+    status: StatusPtr,
+    drain: DrainPtr,
+) -> Result<(), FlameError> {
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| FlameError::Internal(e.to_string()))?;
+    let make_svc = make_service_fn(move |_| {
+        let status = status.clone();
+        let drain = drain.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let resp = handle(&req, &status, &drain);
+                async move { Ok::<_, Infallible>(resp) }
+            }))
+        }
+    });
+
+    Server::from_tcp(listener)
+        .map_err(|e| FlameError::Internal(e.to_string()))?
+        .serve(make_svc)
+        .await
+        .map_err(|e| FlameError::Internal(e.to_string()))
+}
+
+fn handle(req: &Request<Body>, status: &StatusPtr, drain: &DrainPtr) -> Response<Body> {
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/status") => json(status),
+        (&Method::GET, "/healthz") => match snapshot(status) {
+            Ok(_) => reply(StatusCode::OK, "ok"),
+            Err(e) => reply(StatusCode::SERVICE_UNAVAILABLE, &e.to_string()),
+        },
+        (&Method::POST, "/drain") => {
+            update(status, |view| view.draining = true);
+            drain.notify_one();
+            log::info!("The executor is draining.");
+
+            let mut resp = json(status);
+            *resp.status_mut() = StatusCode::ACCEPTED;
+            resp
+        }
+        (_, "/status" | "/healthz" | "/drain") => reply(StatusCode::METHOD_NOT_ALLOWED, ""),
+        _ => reply(StatusCode::NOT_FOUND, ""),
+    }
+}
+
+fn json(status: &StatusPtr) -> Response<Body> {
+    let body = snapshot(status).and_then(|view| {
+        serde_json::to_vec(&view).map_err(|e| FlameError::Internal(e.to_string()))
+    });
+    match body {
+        Ok(body) => Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap_or_default(),
+        Err(e) => reply(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+}
+
+fn reply(code: StatusCode, body: &str) -> Response<Body> {
+    let mut resp = Response::new(Body::from(body.to_string()));
+    *resp.status_mut() = code;
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::net::SocketAddr;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::time::Duration;
+
+    use hyper::Client;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::shims;
+    use common::apis::{Application, SessionContext, Shim as ShimType};
+    use common::ctx::FlameContext;
+
+    async fn call(addr: SocketAddr, method: Method, path: &str) -> (StatusCode, Value) {
+        let req = Request::builder()
+            .method(method)
+            .uri(format!("http://{}{}", addr, path))
+            .body(Body::empty())
+            .unwrap();
+        let resp = Client::new().request(req).await.unwrap();
+        let code = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+
+        (code, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_status_of_running_task() -> Result<(), FlameError> {
+        // The fake task runs until it's read by the test.
+        let dir = std::env::temp_dir().join(format!("flame-status-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("task.sh");
+        fs::write(&script, "#!/bin/sh\nsleep 2\necho done\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let app = Application {
+            name: "sleep".to_string(),
+            shim: ShimType::Stdio,
+            command: script.display().to_string(),
+            working_directory: dir.display().to_string(),
+            ..Default::default()
+        };
+
+        let mut exec = Executor::from_context(&FlameContext::default()).await?;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, exec.status.clone(), exec.drain.clone()));
+
+        // Bind the executor and invoke the task as the bound state does.
+        exec.state = ExecutorState::Idle;
+        update(&exec.status, |s| s.transit(&exec));
+        exec.state = ExecutorState::Bound;
+        exec.session = Some(SessionContext {
+            ssn_id: "ssn-1".to_string(),
+            application: app.name.clone(),
+            slots: 1,
+            common_data: None,
+            config: HashMap::new(),
+        });
+        update(&exec.status, |s| s.transit(&exec));
+
+        let task = TaskContext {
+            id: "task-1".to_string(),
+            ssn_id: "ssn-1".to_string(),
+            input: None,
+            output: None,
+            trace_context: None,
+            locality: None,
+            env: Default::default(),
+        };
+        let shim = shims::from(&app).await?;
+        let status = exec.status.clone();
+        let running = tokio::spawn(async move {
+            update(&status, |s| s.task_started(&task));
+            let mut shim = shim.lock().await;
+            shim.set_status(status.clone());
+            let output = shim.on_task_invoke(&task).await;
+            update(&status, |s| s.task_completed());
+            output
+        });
+
+        let mut view = Value::Null;
+        for _ in 0..50 {
+            let (code, body) = call(addr, Method::GET, "/status").await;
+            assert_eq!(code, StatusCode::OK);
+            view = body;
+            if !view["shim_pid"].is_null() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(view["id"], exec.id.as_str());
+        assert_eq!(view["state"], "Bound");
+        assert_eq!(view["session_id"], "ssn-1");
+        assert_eq!(view["task_id"], "task-1");
+        assert!(view["task_running_seconds"].as_f64().unwrap() >= 0.0);
+        assert_eq!(view["shim_restarts"], 0);
+        assert!(view["last_error"].is_null());
+        let pid = view["shim_pid"].as_u64().unwrap();
+        assert!(Path::new(&format!("/proc/{}", pid)).exists());
+        let states: Vec<&str> = view["transitions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|t| t["state"].as_str())
+            .collect();
+        assert_eq!(states, vec!["Init", "Idle", "Bound"]);
+
+        assert_eq!(call(addr, Method::GET, "/healthz").await.0, StatusCode::OK);
+        assert_eq!(
+            call(addr, Method::GET, "/drain").await.0,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        let (code, view) = call(addr, Method::POST, "/drain").await;
+        assert_eq!(code, StatusCode::ACCEPTED);
+        assert_eq!(view["draining"], true);
+        tokio::time::timeout(Duration::from_secs(1), exec.drain.notified())
+            .await
+            .unwrap();
+
+        let output = running.await.unwrap()?;
+        assert_eq!(output.as_deref(), Some(&b"done\n"[..]));
+        let (_, view) = call(addr, Method::GET, "/status").await;
+        assert!(view["task_id"].is_null() && view["shim_pid"].is_null());
+        assert!(view["task_running_seconds"].is_null());
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+use std::fmt::Write;
+
+use common::ctx::FlameContext;
+use flame_client::{self as flame, capability, EffectiveConfig, SchedulerStatus};
+
+use crate::helper;
+use crate::output::{self, OutputFormat, TableRow};
+
+impl TableRow for SchedulerStatus {
+    fn headers() -> Vec<&'static str> {
+        vec![
+            "Scheduling",
+            "Last Cycle",
+            "Decisions",
+            "Total Decisions",
+            "Cycles",
+        ]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            match self.paused {
+                true => "Paused".to_string(),
+                false => "Running".to_string(),
+            },
+            self.last_cycle_time
+                .map(|t| t.format("%F %T").to_string())
+                .unwrap_or("-".to_string()),
+            self.last_decisions.to_string(),
+            self.total_decisions.to_string(),
+            self.cycles.to_string(),
+        ]
+    }
+
+    /// The health of the background loops is only shown in the detail, e.g. `3/4 healthy,
+    /// sweeper restarting (1 restarts): 'failed to flush'`.
+    fn detail(&self) -> Vec<(&'static str, String)> {
+        let mut detail: Vec<_> = Self::headers().into_iter().zip(self.row()).collect();
+        if !self.loops.is_empty() {
+            let healthy = self.loops.iter().filter(|l| l.is_healthy()).count();
+            let mut loops = vec![format!("{}/{} healthy", healthy, self.loops.len())];
+            for l in self.loops.iter().filter(|l| !l.is_healthy()) {
+                let mut health = format!("{} {} ({} restarts)", l.name, l.state, l.restarts);
+                if let Some(e) = &l.last_error {
+                    health.push_str(&format!(": {}", e));
+                }
+                loops.push(health);
+            }
+            detail.push(("Loops", loops.join(", ")));
+        }
+
+        detail
+    }
+}
+
+async fn connect(ctx: &FlameContext) -> Result<flame::Connection, Box<dyn Error>> {
+    Ok(helper::connect(ctx).await?)
+}
+
+pub async fn pause(ctx: &FlameContext) -> Result<(), Box<dyn Error>> {
+    connect(ctx).await?.pause_scheduling().await?;
+    println!("Scheduling was paused; no sessions will be bound or unbound until resumed.");
+
+    Ok(())
+}
+
+pub async fn resume(ctx: &FlameContext) -> Result<(), Box<dyn Error>> {
+    connect(ctx).await?.resume_scheduling().await?;
+    println!("Scheduling was resumed.");
+
+    Ok(())
+}
+
+pub async fn status(ctx: &FlameContext, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let status = connect(ctx).await?.get_scheduler_status().await?;
+    print!("{}", output::render_one(&status, format)?);
+
+    Ok(())
+}
+
+pub async fn config(ctx: &FlameContext) -> Result<(), Box<dyn Error>> {
+    let conn = connect(ctx).await?;
+    if !conn.supports(capability::EFFECTIVE_CONFIG) {
+        return Err("the Flame server does not report its configuration, please upgrade it".into());
+    }
+
+    let conf = conn.get_effective_config().await?;
+    print!("{}", render_config(&conf)?);
+
+    Ok(())
+}
+
+/// The build and the storage of the server, followed by its configuration in YAML.
+fn render_config(conf: &EffectiveConfig) -> Result<String, Box<dyn Error>> {
+    let mut res = String::new();
+    let _ = writeln!(
+        res,
+        "# Version:  {} ({}, {})",
+        conf.build.version, conf.build.profile, conf.build.target
+    );
+    let _ = writeln!(
+        res,
+        "# Storage:  {} {}",
+        conf.storage_kind, conf.storage_version
+    );
+    let _ = writeln!(
+        res,
+        "# Features: {}",
+        match conf.features.is_empty() {
+            true => "none".to_string(),
+            false => conf.features.join(", "),
+        }
+    );
+    res.push_str(&serde_yaml::to_string(&conf.config)?);
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+    use flame_client::LoopHealth;
+
+    #[test]
+    fn test_status() {
+        let mut status = SchedulerStatus {
+            paused: true,
+            last_cycle_time: None,
+            last_decisions: 0,
+            total_decisions: 0,
+            cycles: 0,
+            loops: vec![],
+        };
+        assert_eq!(
+            output::render_one(&status, OutputFormat::Table).unwrap(),
+            concat!(
+                "Scheduling:      Paused\n",
+                "Last Cycle:      -\n",
+                "Decisions:       0\n",
+                "Total Decisions: 0\n",
+                "Cycles:          0\n",
+            )
+        );
+
+        status.paused = false;
+        status.last_cycle_time = Some(Utc.with_ymd_and_hms(2024, 7, 1, 8, 0, 0).unwrap());
+        status.last_decisions = 2;
+        status.total_decisions = 42;
+        status.cycles = 100;
+        assert_eq!(
+            output::render_list(&[status], OutputFormat::Table).unwrap(),
+            concat!(
+                "Scheduling  Last Cycle           Decisions  Total Decisions  Cycles\n",
+                "Running     2024-07-01 08:00:00  2          42               100\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_status_loops() {
+        let health = |name: &str, state: &str, restarts, last_error: Option<&str>| LoopHealth {
+            name: name.to_string(),
+            state: state.to_string(),
+            restarts,
+            last_error: last_error.map(str::to_string),
+            last_error_time: None,
+        };
+        let status = SchedulerStatus {
+            paused: false,
+            last_cycle_time: None,
+            last_decisions: 0,
+            total_decisions: 0,
+            cycles: 0,
+            loops: vec![
+                health("scheduler", "running", 0, None),
+                health("apiserver", "running", 1, Some("'bind failed'")),
+                health("sweeper", "failed", 5, Some("panicked: oops")),
+            ],
+        };
+
+        let res = output::render_one(&status, OutputFormat::Table).unwrap();
+        assert!(
+            res.ends_with(concat!(
+                "Loops:           1/3 healthy, apiserver running (1 restarts): 'bind failed', ",
+                "sweeper failed (5 restarts): panicked: oops\n"
+            )),
+            "{}",
+            res
+        );
+    }
+
+    #[test]
+    fn test_render_config() {
+        let conf = EffectiveConfig {
+            config: FlameContext {
+                policy: "fairshare".to_string(),
+                ..Default::default()
+            },
+            storage_kind: "sqlite".to_string(),
+            storage_version: "3.45.1".to_string(),
+            features: vec![],
+            build: flame::BuildInfo {
+                version: "0.3.0".to_string(),
+                target: "x86_64-linux".to_string(),
+                profile: "release".to_string(),
+            },
+        };
+
+        let res = render_config(&conf).unwrap();
+        assert!(res.starts_with(concat!(
+            "# Version:  0.3.0 (release, x86_64-linux)\n",
+            "# Storage:  sqlite 3.45.1\n",
+            "# Features: none\n",
+            "name: flame\n",
+        )));
+        assert!(res.contains("\npolicy: fairshare\n"), "{}", res);
+
+        // The configuration is still valid YAML.
+        let ctx: FlameContext = serde_yaml::from_str(&res).unwrap();
+        assert_eq!(ctx.policy, "fairshare");
+    }
+}
@@ -0,0 +1,328 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+
+use common::apis;
+use common::ctx::FlameContext;
+use flame_client::{
+    capability, Application, Connection, InputConstraints, Session, SessionState, Shim,
+};
+
+use crate::helper;
+use crate::output::{self, OutputFormat, TableRow};
+
+impl TableRow for Application {
+    fn headers() -> Vec<&'static str> {
+        vec!["Name", "Shim", "Command"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        let command = match self.command.is_empty() {
+            true => "-".to_string(),
+            false => [vec![self.command.clone()], self.arguments.clone()]
+                .concat()
+                .join(" "),
+        };
+
+        vec![self.name.clone(), self.shim.to_string(), command]
+    }
+}
+
+fn to_client(app: &apis::Application) -> Application {
+    Application {
+        name: app.name.clone(),
+        shim: Shim::try_from(app.shim.clone() as i32).unwrap_or_default(),
+        command: app.command.clone(),
+        arguments: app.arguments.clone(),
+        environments: app.environments.clone(),
+        working_directory: app.working_directory.clone(),
+        weight: app.weight,
+        default_slots: app.default_slots,
+        default_task_timeout: app.default_task_timeout,
+        default_max_retries: app.default_max_retries,
+        default_labels: app.default_labels.clone().into_iter().collect(),
+        input: app.input.as_ref().map(|input| InputConstraints {
+            max_bytes: input.max_bytes,
+            required_fields: input.required_fields.clone(),
+            schema: input.schema.clone(),
+            strict: input.strict,
+        }),
+    }
+}
+
+/// Parses and validates the application spec before sending it to the server.
+fn load_application(contents: &str) -> Result<Application, Box<dyn Error>> {
+    let app: apis::Application =
+        serde_yaml::from_str(contents).map_err(|e| format!("invalid application spec: {}", e))?;
+
+    let problems = app.problems();
+    if !problems.is_empty() {
+        return Err(problems.join("; ").into());
+    }
+
+    Ok(to_client(&app))
+}
+
+/// Returns the open sessions of the application.
+fn open_sessions(ssn_list: &[Session], name: &str) -> Vec<String> {
+    ssn_list
+        .iter()
+        .filter(|ssn| ssn.application == name && ssn.state == SessionState::Open)
+        .map(|ssn| ssn.id.clone())
+        .collect()
+}
+
+const UNSUPPORTED: &str = "the Flame server does not support application registry";
+
+fn check_supported(conn: &Connection) -> Result<(), Box<dyn Error>> {
+    match conn.supports(capability::APPLICATION_REGISTRY) {
+        true => Ok(()),
+        false => Err(format!("{}, please upgrade it", UNSUPPORTED).into()),
+    }
+}
+
+pub async fn list(ctx: &FlameContext, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+
+    list_applications(&conn, &ctx.applications, format, &mut io::stdout()).await
+}
+
+/// Lists the registered applications, or the ones in the configuration if the server has
+/// no application registry.
+async fn list_applications(
+    conn: &Connection,
+    configured: &[apis::Application],
+    format: OutputFormat,
+    out: &mut impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let app_list = match conn.supports(capability::APPLICATION_REGISTRY) {
+        true => conn.list_application().await?,
+        false => {
+            eprintln!(
+                "Warning: {}, list the applications in configuration.",
+                UNSUPPORTED
+            );
+            configured.iter().map(to_client).collect()
+        }
+    };
+
+    write!(out, "{}", output::render_list(&app_list, format)?)?;
+
+    Ok(())
+}
+
+pub async fn register(ctx: &FlameContext, file: &String) -> Result<(), Box<dyn Error>> {
+    let contents = fs::read_to_string(file)?;
+    let conn = helper::connect(ctx).await?;
+
+    register_application(&conn, &contents, &mut io::stdout()).await
+}
+
+/// Registers the application of the spec, which is validated before it's sent.
+async fn register_application(
+    conn: &Connection,
+    contents: &str,
+    out: &mut impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let app = load_application(contents)?;
+
+    check_supported(conn)?;
+    let defaults = app.default_slots.is_some()
+        || app.default_task_timeout.is_some()
+        || app.default_max_retries.is_some()
+        || !app.default_labels.is_empty();
+    if defaults && !conn.supports(capability::TASK_SETTINGS) {
+        return Err(
+            "the Flame server does not support the defaults of the tasks, please upgrade it".into(),
+        );
+    }
+    conn.register_application(&app).await?;
+
+    writeln!(out, "Application <{}> was registered.", app.name)?;
+
+    Ok(())
+}
+
+pub async fn delete(ctx: &FlameContext, name: &String, force: bool) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+
+    delete_application(&conn, name, force, &mut io::stdout()).await
+}
+
+/// Deletes the application, which must not be used by open sessions unless `force`.
+async fn delete_application(
+    conn: &Connection,
+    name: &String,
+    force: bool,
+    out: &mut impl Write,
+) -> Result<(), Box<dyn Error>> {
+    check_supported(conn)?;
+
+    let ssn_ids = open_sessions(&conn.list_session().await?, name);
+    if !ssn_ids.is_empty() {
+        let msg = format!(
+            "application <{}> is used by open sessions: {}",
+            name,
+            ssn_ids.join(", ")
+        );
+        if !force {
+            return Err(format!("{}; close them or use --force", msg).into());
+        }
+        eprintln!("Warning: {}.", msg);
+    }
+
+    conn.delete_application(name, force).await?;
+
+    writeln!(out, "Application <{}> was deleted.", name)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use flame_client::testkit::MockServer;
+    use flame_client::{FlameError, SessionAttributes};
+
+    use super::*;
+
+    const PI: &str = "name: pi\nshim: Stdio\ncommand: /opt/pi-server\n";
+
+    async fn registered(conn: &Connection) -> Vec<String> {
+        let mut out = vec![];
+        list_applications(conn, &[], OutputFormat::Json, &mut out)
+            .await
+            .unwrap();
+        let apps: Vec<Application> = serde_json::from_slice(&out).unwrap();
+
+        apps.into_iter().map(|app| app.name).collect()
+    }
+
+    #[tokio::test]
+    async fn test_register_application() -> Result<(), FlameError> {
+        let server = MockServer::start().await?;
+        let conn = server.connect().await?;
+
+        let mut out = vec![];
+        register_application(&conn, PI, &mut out).await.unwrap();
+        assert_eq!(out, b"Application <pi> was registered.\n");
+        assert_eq!(registered(&conn).await, vec!["pi"]);
+
+        let mut out = vec![];
+        list_applications(&conn, &[], OutputFormat::Table, &mut out)
+            .await
+            .unwrap();
+        let table = String::from_utf8(out).unwrap();
+        assert!(table.contains("/opt/pi-server"), "{}", table);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_register_rejected_application() -> Result<(), FlameError> {
+        let server = MockServer::start().await?;
+        let conn = server.connect().await?;
+
+        // The invalid specs are rejected before they're sent.
+        let mut out = vec![];
+        let err = register_application(&conn, "name: pi\nshim: Stdio\n", &mut out).await;
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "application <pi>: command is required by Stdio shim"
+        );
+
+        // The server does not support the defaults of the tasks.
+        let err = register_application(&conn, &format!("{}default_slots: 2\n", PI), &mut out).await;
+        assert!(err
+            .unwrap_err()
+            .to_string()
+            .contains("does not support the defaults of the tasks"));
+
+        assert!(out.is_empty());
+        assert!(registered(&conn).await.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_delete_application() -> Result<(), FlameError> {
+        let server = MockServer::start().await?;
+        let conn = server.connect().await?;
+        register_application(&conn, PI, &mut vec![]).await.unwrap();
+
+        let ssn = conn
+            .create_session(&SessionAttributes {
+                application: "pi".to_string(),
+                slots: 1,
+                ..Default::default()
+            })
+            .await?;
+
+        // The application of the open sessions is only deleted by force.
+        let mut out = vec![];
+        let err = delete_application(&conn, &"pi".to_string(), false, &mut out).await;
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            format!(
+                "application <pi> is used by open sessions: {}; close them or use --force",
+                ssn.id
+            )
+        );
+        assert_eq!(registered(&conn).await, vec!["pi"]);
+
+        delete_application(&conn, &"pi".to_string(), true, &mut out)
+            .await
+            .unwrap();
+        assert_eq!(out, b"Application <pi> was deleted.\n");
+        assert!(registered(&conn).await.is_empty());
+
+        // The deleted application is not found any more.
+        let err = delete_application(&conn, &"pi".to_string(), true, &mut out).await;
+        assert!(err.unwrap_err().to_string().contains("application <pi>"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_application() {
+        let app = load_application("name: pi\nshim: Stdio\ncommand: /opt/pi-server\n").unwrap();
+        assert_eq!(app.name, "pi");
+        assert_eq!(app.shim, Shim::Stdio);
+
+        let app = load_application("name: flmexec\nshim: Log\n").unwrap();
+        assert_eq!(app.shim, Shim::Log);
+
+        let err = load_application("name: pi\nshim: Grpc\ncommand: /opt/pi-server\n");
+        assert!(err
+            .unwrap_err()
+            .to_string()
+            .contains("unknown variant `Grpc`"));
+
+        let err = load_application("name: pi\nshim: Stdio\n");
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "application <pi>: command is required by Stdio shim"
+        );
+    }
+
+    #[test]
+    fn test_open_sessions() {
+        let ssn_list: Vec<Session> =
+            serde_json::from_str(include_str!("../tests/golden/list.json")).unwrap();
+
+        assert_eq!(open_sessions(&ssn_list, "flmping"), vec!["1"]);
+        assert!(open_sessions(&ssn_list, "pi").is_empty());
+    }
+}
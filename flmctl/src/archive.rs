@@ -0,0 +1,84 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+
+use futures::{stream, StreamExt};
+
+use common::ctx::FlameContext;
+use flame_client::{capability, ArchiveWriter, Connection, SessionArchive, SessionExport};
+
+use crate::helper;
+
+const UNSUPPORTED: &str = "the Flame server does not support session archives";
+
+async fn connect(ctx: &FlameContext) -> Result<Connection, Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+
+    match conn.supports(capability::SESSION_ARCHIVE) {
+        true => Ok(conn),
+        false => Err(format!("{}, please upgrade it", UNSUPPORTED).into()),
+    }
+}
+
+async fn write_archive(export: &mut SessionExport, file: &str) -> Result<usize, Box<dyn Error>> {
+    let mut writer = ArchiveWriter::new(BufWriter::new(File::create(file)?), &export.session)?;
+    while let Some(task) = export.next().await {
+        writer.write_task(&task?)?;
+    }
+
+    Ok(writer.finish()?)
+}
+
+pub async fn export(
+    ctx: &FlameContext,
+    session: &String,
+    file: &String,
+) -> Result<(), Box<dyn Error>> {
+    let conn = connect(ctx).await?;
+    let mut export = conn.export_session(session).await?;
+
+    // Do not leave a truncated archive behind, it can not be imported anyway.
+    let tasks = match write_archive(&mut export, file).await {
+        Ok(tasks) => tasks,
+        Err(e) => {
+            let _ = fs::remove_file(file);
+            return Err(e);
+        }
+    };
+
+    println!(
+        "Session <{}> was exported to <{}> with {} tasks.",
+        session, file, tasks
+    );
+
+    Ok(())
+}
+
+pub async fn import(ctx: &FlameContext, file: &String) -> Result<(), Box<dyn Error>> {
+    let archive = SessionArchive::read(BufReader::new(File::open(file)?))?;
+
+    let conn = connect(ctx).await?;
+    let ssn = conn
+        .import_session(&archive.session, stream::iter(archive.tasks))
+        .await?;
+
+    println!(
+        "Session <{}> was imported as <{}> in the state of Closed.",
+        archive.session.id, ssn.id
+    );
+
+    Ok(())
+}
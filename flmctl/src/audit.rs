@@ -0,0 +1,133 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+use std::fs;
+
+use common::audit::{AuditEntry, AuditOutcome};
+use common::ctx::FlameContext;
+
+use crate::output::{self, OutputFormat, TableRow};
+
+impl TableRow for AuditEntry {
+    fn headers() -> Vec<&'static str> {
+        vec!["Time", "Method", "Caller", "Targets", "Outcome"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        let targets = match self.targets.is_empty() {
+            true => "-".to_string(),
+            false => self
+                .targets
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join(","),
+        };
+        let outcome = match &self.outcome {
+            AuditOutcome::Ok => "ok".to_string(),
+            AuditOutcome::Failed { code, .. } => format!("failed: {}", code),
+        };
+
+        vec![
+            self.timestamp.format("%F %T").to_string(),
+            self.method.clone(),
+            self.caller.clone(),
+            targets,
+            outcome,
+        ]
+    }
+}
+
+/// Parses the last `n` entries of the audit log, and the number of the invalid lines.
+fn tail(contents: &str, n: usize) -> (Vec<AuditEntry>, usize) {
+    let mut invalid = 0;
+    let entries: Vec<AuditEntry> = contents
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .filter_map(|l| match serde_json::from_str(l) {
+            Ok(entry) => Some(entry),
+            Err(_) => {
+                invalid += 1;
+                None
+            }
+        })
+        .collect();
+
+    let skip = entries.len().saturating_sub(n);
+    (entries.into_iter().skip(skip).collect(), invalid)
+}
+
+pub fn run(
+    ctx: &FlameContext,
+    file: &Option<String>,
+    lines: usize,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let path = match (file, &ctx.audit) {
+        (Some(file), _) => file.clone(),
+        (None, Some(audit)) => audit.path.clone(),
+        (None, None) => {
+            return Err("the audit log is not enabled in the configuration, use --file".into())
+        }
+    };
+
+    let contents =
+        fs::read_to_string(&path).map_err(|e| format!("failed to read <{}>: {}", path, e))?;
+    let (entries, invalid) = tail(&contents, lines);
+    if invalid > 0 {
+        eprintln!(
+            "Warning: skipped {} invalid line(s) in <{}>.",
+            invalid, path
+        );
+    }
+
+    print!("{}", output::render_list(&entries, format)?);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUDIT_LOG: &str = include_str!("../tests/golden/audit.jsonl");
+
+    #[test]
+    fn test_format() {
+        // The format of the entries is stable: they're written back as the same lines.
+        for line in AUDIT_LOG.lines() {
+            let entry: AuditEntry = serde_json::from_str(line).unwrap();
+            assert_eq!(serde_json::to_string(&entry).unwrap(), line);
+        }
+    }
+
+    #[test]
+    fn test_tail() {
+        let (entries, invalid) = tail(AUDIT_LOG, 10);
+        assert_eq!(invalid, 0);
+        assert_eq!(
+            output::render_list(&entries, OutputFormat::Table).unwrap(),
+            include_str!("../tests/golden/audit.table")
+        );
+
+        let (entries, _) = tail(AUDIT_LOG, 2);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].method, "DeleteApplication");
+
+        let contents = format!("{}not json\n\n", AUDIT_LOG);
+        let (entries, invalid) = tail(&contents, 100);
+        assert_eq!(entries.len(), AUDIT_LOG.lines().count());
+        assert_eq!(invalid, 1);
+    }
+}
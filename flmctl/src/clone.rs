@@ -0,0 +1,42 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+
+use common::ctx::FlameContext;
+use flame_client::capability;
+
+use crate::helper;
+
+pub async fn run(
+    ctx: &FlameContext,
+    session: &String,
+    with_tasks: bool,
+    latest_application: bool,
+) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+    if !conn.supports(capability::SESSION_CLONE) {
+        return Err("the Flame server does not support cloning sessions, please upgrade it".into());
+    }
+
+    let ssn = conn
+        .clone_session(session, with_tasks, latest_application)
+        .await?;
+
+    println!(
+        "Session <{}> was cloned from <{}> with {} pending tasks.",
+        ssn.id, session, ssn.pending
+    );
+
+    Ok(())
+}
@@ -0,0 +1,28 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+
+use common::ctx::FlameContext;
+
+use crate::helper;
+
+pub async fn run(ctx: &FlameContext, session: &String) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+    let ssn = conn.get_session(session).await?;
+    ssn.close().await?;
+
+    println!("Session <{}> was closed.", ssn.id);
+
+    Ok(())
+}
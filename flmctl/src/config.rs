@@ -0,0 +1,189 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+use std::fs;
+
+use clap::ValueEnum;
+
+use common::apis;
+use common::ctx::FlameContext;
+use common::endpoint;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ConfigKey {
+    Endpoint,
+    Policy,
+    Namespace,
+}
+
+impl ConfigKey {
+    fn name(&self) -> &'static str {
+        match self {
+            ConfigKey::Endpoint => "endpoint",
+            ConfigKey::Policy => "policy",
+            ConfigKey::Namespace => "namespace",
+        }
+    }
+}
+
+pub fn view(path: &str) -> Result<(), Box<dyn Error>> {
+    let ctx = FlameContext::parse_file(path)?;
+
+    println!("# {}", path);
+    print!("{}", serde_yaml::to_string(&ctx.redacted())?);
+
+    Ok(())
+}
+
+pub fn set(path: &str, key: ConfigKey, value: &str) -> Result<(), Box<dyn Error>> {
+    match key {
+        ConfigKey::Endpoint => {
+            endpoint::validate(value)
+                .map_err(|e| format!("invalid endpoint <{}>: {}", value, e))?;
+        }
+        ConfigKey::Namespace => {
+            apis::parse_namespace(value)?;
+        }
+        ConfigKey::Policy => {}
+    }
+
+    let contents = fs::read_to_string(path)?;
+    fs::write(path, set_value(&contents, key.name(), value))?;
+
+    println!("Set {} to <{}> in <{}>.", key.name(), value, path);
+
+    Ok(())
+}
+
+pub fn validate(path: &str) -> Result<i32, Box<dyn Error>> {
+    let problems = match FlameContext::parse_file(path) {
+        Ok(ctx) => ctx.problems(),
+        Err(e) => vec![e.to_string()],
+    };
+
+    if problems.is_empty() {
+        println!("<{}> is valid.", path);
+        return Ok(0);
+    }
+
+    eprintln!("<{}> has {} problem(s):", path, problems.len());
+    for p in problems {
+        eprintln!("  - {}", p);
+    }
+
+    Ok(1)
+}
+
+/// Replaces the value of a top-level key in place, so the other fields and the comments of
+/// the file are kept; the key is appended if it's not found.
+fn set_value(contents: &str, key: &str, value: &str) -> String {
+    let prefix = format!("{}:", key);
+    let line = format!("{}: \"{}\"", key, value);
+
+    let mut found = false;
+    let mut lines: Vec<String> = contents
+        .lines()
+        .map(|l| {
+            if !found && l.starts_with(&prefix) {
+                found = true;
+                line.clone()
+            } else {
+                l.to_string()
+            }
+        })
+        .collect();
+
+    if !found {
+        lines.push(line);
+    }
+
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use common::ctx::REDACTED;
+
+    const CONF: &str = r#"---
+# The endpoint of session manager
+name: flame
+endpoint: "http://127.0.0.1:8080"
+slot: "cpu=1,mem=2g"
+policy: priority
+storage: sqlite://flame.db
+client:
+  token: "abc"
+auth:
+  tokens:
+    - name: "team-a"
+      token: "def"
+      namespace: "team-a"
+applications:
+  - name: "pi"
+    shim: Stdio
+    command: "/opt/pi-server"
+    environments:
+      - "API_TOKEN=abc"
+      - "RUST_LOG=info"
+  # The log application
+  - name: "flmexec"
+    shim: Log
+"#;
+
+    #[test]
+    fn test_set_value() {
+        let res = set_value(CONF, "endpoint", "http://flame:8080");
+        assert!(res.contains("endpoint: \"http://flame:8080\"\n"));
+        assert!(res.contains("# The endpoint of session manager\n"));
+        assert!(res.contains("  # The log application\n"));
+        assert_eq!(res.lines().count(), CONF.lines().count());
+
+        let res = set_value("name: flame\n", "policy", "fairshare");
+        assert_eq!(res, "name: flame\npolicy: \"fairshare\"\n");
+    }
+
+    #[test]
+    fn test_redact() {
+        let mut ctx: FlameContext = serde_yaml::from_str(CONF).unwrap();
+        ctx.storage = "postgres://flame:passwd@db:5432/flame".to_string();
+
+        let ctx = ctx.redacted();
+        assert_eq!(ctx.storage, "postgres://flame:******@db:5432/flame");
+        assert_eq!(ctx.client.unwrap().token.as_deref(), Some(REDACTED));
+        assert_eq!(ctx.auth.unwrap().tokens[0].token, REDACTED);
+        assert_eq!(
+            ctx.applications[0].environments,
+            vec!["API_TOKEN=******", "RUST_LOG=info"]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let ctx: FlameContext = serde_yaml::from_str(CONF).unwrap();
+        assert!(ctx.problems().is_empty());
+
+        let mut ctx = ctx;
+        ctx.endpoint = "flame:8080".to_string();
+        ctx.slot = "cpu=1,mem".to_string();
+        ctx.applications[0].command = String::new();
+
+        let problems = ctx.problems();
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].starts_with("endpoint <flame:8080>"));
+        assert!(problems[1].starts_with("slot <cpu=1,mem>"));
+        assert!(problems[2].starts_with("application <pi>"));
+    }
+}
@@ -13,20 +13,65 @@ limitations under the License.
 
 use std::error::Error;
 
+use chrono::{DateTime, Utc};
+
 use common::ctx::FlameContext;
 
-use self::flame::SessionAttributes;
+use self::flame::{CacheScope, Deadline, SessionAttributes};
 use flame_client as flame;
 
-pub async fn run(ctx: &FlameContext, app: &str, slots: &i32) -> Result<(), Box<dyn Error>> {
-    let conn = flame::connect(&ctx.endpoint).await?;
-    let attr = SessionAttributes {
-        application: app.to_owned(),
-        slots: *slots,
-        common_data: None,
-    };
+use crate::helper;
+
+/// Parses a label of the session, e.g. `env=dev`.
+pub fn parse_label(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.is_empty() => Ok((k.to_string(), v.to_string())),
+        _ => Err(format!("invalid label <{}>, expect <key=value>", s)),
+    }
+}
+
+/// Parses a setting of the session config, e.g. `model_version=v2`.
+pub fn parse_config(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.is_empty() => Ok((k.to_string(), v.to_string())),
+        _ => Err(format!("invalid config <{}>, expect <key=value>", s)),
+    }
+}
+
+/// Parses the cache scope of the session, e.g. `session`.
+pub fn parse_cache_scope(s: &str) -> Result<CacheScope, String> {
+    match s.to_lowercase().as_str() {
+        "none" => Ok(CacheScope::None),
+        "session" => Ok(CacheScope::Session),
+        "application" => Ok(CacheScope::Application),
+        _ => Err(format!(
+            "invalid cache scope <{}>, expect None, Session or Application",
+            s
+        )),
+    }
+}
+
+/// Parses the deadline of the session, which is a duration from now, e.g. `30m`, or a RFC3339
+/// time, e.g. `2024-09-01T08:00:00Z`.
+pub fn parse_deadline(s: &str) -> Result<Deadline, String> {
+    if let Ok(d) = humantime::parse_duration(s) {
+        return Ok(Deadline::After(d));
+    }
+
+    match DateTime::parse_from_rfc3339(s) {
+        Ok(t) => Ok(Deadline::At(t.with_timezone(&Utc))),
+        Err(_) => Err(format!(
+            "invalid deadline <{}>, expect a duration, e.g. 30m, or a RFC3339 time",
+            s
+        )),
+    }
+}
+
+/// Creates a session; the application and slots are given by the template if not set.
+pub async fn run(ctx: &FlameContext, attr: &SessionAttributes) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
 
-    let ssn = conn.create_session(&attr).await?;
+    let ssn = conn.create_session(attr).await?;
 
     println!("Session <{}> was created.", ssn.id);
 
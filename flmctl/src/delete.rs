@@ -0,0 +1,328 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::future::Future;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+
+use common::ctx::FlameContext;
+use flame_client::{FlameError, Session, SessionState};
+
+use crate::helper;
+use crate::watch::{EXIT_FAILED, EXIT_SUCCEED};
+
+/// The maximum number of sessions deleted at the same time.
+const MAX_CONCURRENT_DELETES: usize = 8;
+
+/// The label selector of sessions, e.g. `env=dev,team=ml`; all labels must match.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Selector(BTreeMap<String, String>);
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut labels = BTreeMap::new();
+        for item in s.split(',').filter(|i| !i.trim().is_empty()) {
+            match item.split_once('=') {
+                Some((k, v)) if !k.trim().is_empty() => {
+                    labels.insert(k.trim().to_string(), v.trim().to_string());
+                }
+                _ => return Err(format!("invalid selector <{}>, expect <key=value>", item)),
+            }
+        }
+
+        Ok(Selector(labels))
+    }
+}
+
+impl Selector {
+    fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.0.iter().all(|(k, v)| labels.get(k) == Some(v))
+    }
+}
+
+pub fn parse_state(s: &str) -> Result<SessionState, String> {
+    match s.to_lowercase().as_str() {
+        "open" => Ok(SessionState::Open),
+        "closed" => Ok(SessionState::Closed),
+        _ => Err(format!(
+            "invalid session state <{}>, expect Open or Closed",
+            s
+        )),
+    }
+}
+
+pub struct DeleteArgs {
+    pub sessions: Vec<String>,
+    pub selector: Option<Selector>,
+    pub state: Option<SessionState>,
+    pub older_than: Option<Duration>,
+    pub yes: bool,
+    pub force: bool,
+}
+
+impl DeleteArgs {
+    fn has_filter(&self) -> bool {
+        self.selector.is_some() || self.state.is_some() || self.older_than.is_some()
+    }
+
+    fn matches(&self, ssn: &Session, now: DateTime<Utc>) -> bool {
+        if !self.sessions.is_empty() && !self.sessions.contains(&ssn.id) {
+            return false;
+        }
+        if let Some(selector) = &self.selector {
+            if !selector.matches(&ssn.labels) {
+                return false;
+            }
+        }
+        if let Some(state) = self.state {
+            if ssn.state != state {
+                return false;
+            }
+        }
+        if let Some(older_than) = self.older_than {
+            // The age of closed sessions is counted from their completion.
+            let since = ssn.completion_time.unwrap_or(ssn.creation_time);
+            let age = (now - since).to_std().unwrap_or_default();
+            if age < older_than {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// The sessions to delete, expanded from the ids and filters.
+#[derive(Debug, Default, PartialEq)]
+struct Selection {
+    sessions: Vec<String>,
+    /// The sessions with running tasks, which are skipped unless --force.
+    busy: Vec<String>,
+    /// The ids which do not match any session.
+    missing: Vec<String>,
+}
+
+fn select(ssn_list: &[Session], args: &DeleteArgs, now: DateTime<Utc>) -> Selection {
+    let mut selection = Selection::default();
+
+    for ssn in ssn_list.iter().filter(|ssn| args.matches(ssn, now)) {
+        match ssn.running > 0 && !args.force {
+            true => selection.busy.push(ssn.id.clone()),
+            false => selection.sessions.push(ssn.id.clone()),
+        }
+    }
+
+    selection.missing = args
+        .sessions
+        .iter()
+        .filter(|id| !ssn_list.iter().any(|ssn| &ssn.id == *id))
+        .cloned()
+        .collect();
+
+    selection
+}
+
+/// Deletes the sessions with bounded concurrency; the results keep the order of the sessions.
+async fn delete_all<F, Fut>(
+    ssn_ids: Vec<String>,
+    concurrency: usize,
+    delete: F,
+) -> Vec<(String, Result<(), FlameError>)>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<(), FlameError>>,
+{
+    stream::iter(ssn_ids)
+        .map(|id| {
+            let res = delete(id.clone());
+            async move { (id, res.await) }
+        })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+fn exit_code(failed: usize) -> i32 {
+    match failed {
+        0 => EXIT_SUCCEED,
+        _ => EXIT_FAILED,
+    }
+}
+
+fn confirm(ssn_ids: &[String]) -> Result<bool, Box<dyn Error>> {
+    print!(
+        "Delete {} session(s): {}? [y/N] ",
+        ssn_ids.len(),
+        ssn_ids.join(", ")
+    );
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+pub async fn run(ctx: &FlameContext, args: &DeleteArgs) -> Result<i32, Box<dyn Error>> {
+    if args.sessions.is_empty() && !args.has_filter() {
+        return Err("no session selected, specify session ids or filters".into());
+    }
+
+    let conn = helper::connect(ctx).await?;
+    let selection = select(&conn.list_session().await?, args, Utc::now());
+
+    let mut failed = 0;
+    for id in &selection.missing {
+        eprintln!("Session <{}> was not found.", id);
+        failed += 1;
+    }
+    for id in &selection.busy {
+        eprintln!(
+            "Session <{}> has running tasks, skip it; use --force to delete it anyway.",
+            id
+        );
+        failed += 1;
+    }
+
+    if selection.sessions.is_empty() {
+        println!("No session to delete.");
+        return Ok(exit_code(failed));
+    }
+
+    if !args.yes && !confirm(&selection.sessions)? {
+        println!("Cancelled.");
+        return Ok(EXIT_FAILED);
+    }
+
+    let results = delete_all(selection.sessions, MAX_CONCURRENT_DELETES, |id| {
+        let conn = conn.clone();
+        async move { conn.delete_session(&id).await.map(|_| ()) }
+    })
+    .await;
+
+    let mut deleted = 0;
+    for (id, res) in results {
+        match res {
+            Ok(_) => {
+                println!("Session <{}> was deleted.", id);
+                deleted += 1;
+            }
+            Err(e) => {
+                eprintln!("Failed to delete session <{}>: {}", id, e);
+                failed += 1;
+            }
+        }
+    }
+
+    println!("{} session(s) deleted, {} failed.", deleted, failed);
+
+    Ok(exit_code(failed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sessions() -> Vec<Session> {
+        serde_json::from_str(include_str!("../tests/golden/list.json")).unwrap()
+    }
+
+    fn default_args() -> DeleteArgs {
+        DeleteArgs {
+            sessions: vec![],
+            selector: None,
+            state: None,
+            older_than: None,
+            yes: true,
+            force: false,
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        "2024-03-01T10:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn test_selector() {
+        let selector: Selector = "env=dev, team=ml".parse().unwrap();
+        assert_eq!(selector.0.len(), 2);
+        assert!("env".parse::<Selector>().is_err());
+        assert!("=dev".parse::<Selector>().is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let ssn_list = sessions();
+
+        let args = DeleteArgs {
+            selector: Some("env=dev".parse().unwrap()),
+            ..default_args()
+        };
+        let selection = select(&ssn_list, &args, now());
+        assert_eq!(selection.busy, vec!["1"]);
+        assert_eq!(selection.sessions, vec!["2"]);
+
+        let args = DeleteArgs {
+            state: Some(SessionState::Closed),
+            older_than: Some(Duration::from_secs(30 * 60)),
+            ..default_args()
+        };
+        assert_eq!(select(&ssn_list, &args, now()).sessions, vec!["2"]);
+
+        let args = DeleteArgs {
+            state: Some(SessionState::Closed),
+            older_than: Some(Duration::from_secs(60 * 60)),
+            ..default_args()
+        };
+        assert!(select(&ssn_list, &args, now()).sessions.is_empty());
+
+        let args = DeleteArgs {
+            sessions: vec!["1".to_string(), "3".to_string()],
+            force: true,
+            ..default_args()
+        };
+        let selection = select(&ssn_list, &args, now());
+        assert_eq!(selection.sessions, vec!["1"]);
+        assert_eq!(selection.missing, vec!["3"]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_all() {
+        let ssn_ids = (1..=5).map(|i| i.to_string()).collect();
+
+        let results = delete_all(ssn_ids, 2, |id| async move {
+            match id.as_str() {
+                "2" | "4" => Err(FlameError::Internal("storage".to_string())),
+                _ => Ok(()),
+            }
+        })
+        .await;
+
+        let failed: Vec<&str> = results
+            .iter()
+            .filter(|(_, res)| res.is_err())
+            .map(|(id, _)| id.as_str())
+            .collect();
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].0, "1");
+        assert_eq!(failed, vec!["2", "4"]);
+    }
+}
@@ -0,0 +1,288 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use common::ctx::FlameContext;
+use flame_client::{capability, Connection, Executor, Utilization};
+
+use crate::helper;
+use crate::output::{self, OutputFormat, TableRow};
+
+/// The executor with the lease of the context, which is considered as stale if there's no
+/// heartbeat within the lease; only the executor is serialized.
+#[derive(Serialize)]
+#[serde(transparent)]
+struct ExecutorRow<'a> {
+    exe: &'a Executor,
+    #[serde(skip)]
+    lease: Duration,
+}
+
+impl TableRow for ExecutorRow<'_> {
+    fn headers() -> Vec<&'static str> {
+        vec![
+            "ID",
+            "State",
+            "Session",
+            "Labels",
+            "Utilization",
+            "Heartbeat",
+        ]
+    }
+
+    fn row(&self) -> Vec<String> {
+        let exe = self.exe;
+        vec![
+            exe.id.clone(),
+            state(exe),
+            exe.session_id.clone().unwrap_or("-".to_string()),
+            labels(exe),
+            utilization(&exe.utilization),
+            heartbeat(exe.last_heartbeat, Utc::now(), self.lease),
+        ]
+    }
+
+    fn detail(&self) -> Vec<(&'static str, String)> {
+        let exe = self.exe;
+        vec![
+            ("ID", exe.id.clone()),
+            ("State", state(exe)),
+            ("Slots", exe.slots.to_string()),
+            ("Applications", exe.applications.join(",")),
+            ("Labels", labels(exe)),
+            ("Locality", locality_keys(exe)),
+            ("Session", exe.session_id.clone().unwrap_or("-".to_string())),
+            ("Task", exe.task_id.clone().unwrap_or("-".to_string())),
+            ("Host", host(exe)),
+            ("Version", exe.host.version.clone()),
+            ("Created", exe.creation_time.format("%F %T").to_string()),
+            ("Utilization", utilization(&exe.utilization)),
+            ("Busy", busy(exe)),
+            (
+                "Heartbeat",
+                heartbeat(exe.last_heartbeat, Utc::now(), self.lease),
+            ),
+        ]
+    }
+}
+
+fn state(exe: &Executor) -> String {
+    match (exe.quarantined, exe.draining) {
+        (true, _) => format!("{} (quarantined)", exe.state),
+        (false, true) => format!("{} (draining)", exe.state),
+        (false, false) => exe.state.to_string(),
+    }
+}
+
+fn labels(exe: &Executor) -> String {
+    if exe.labels.is_empty() {
+        return "-".to_string();
+    }
+
+    exe.labels
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn locality_keys(exe: &Executor) -> String {
+    match exe.locality_keys.is_empty() {
+        true => "-".to_string(),
+        false => exe.locality_keys.join(","),
+    }
+}
+
+/// The host of the executor, e.g. "node-a (10.0.0.4, linux/x86_64)"; `-` if it's unknown.
+fn host(exe: &Executor) -> String {
+    let host = &exe.host;
+    match host.hostname.is_empty() {
+        true => "-".to_string(),
+        false => format!("{} ({}, {}/{})", host.hostname, host.ip, host.os, host.arch),
+    }
+}
+
+/// The utilization over each window, e.g. "12% (5m), 40% (1h)"; `-` if it's not tracked.
+fn utilization(windows: &[Utilization]) -> String {
+    if windows.is_empty() {
+        return "-".to_string();
+    }
+
+    windows
+        .iter()
+        .map(|u| {
+            format!(
+                "{:.0}% ({})",
+                u.percentage,
+                humantime::format_duration(u.window)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The busy time measured by the session manager, and the one reported by the executor if
+/// it's known, e.g. "1h 5m (reported 1h 4m 58s)".
+fn busy(exe: &Executor) -> String {
+    let format = |secs: f64| humantime::format_duration(Duration::from_secs(secs as u64));
+    match exe.reported_busy_seconds {
+        Some(reported) => format!(
+            "{} (reported {})",
+            format(exe.busy_seconds),
+            format(reported)
+        ),
+        None => format(exe.busy_seconds).to_string(),
+    }
+}
+
+/// The age of the last heartbeat, e.g. "5s ago"; stale executors are flagged.
+fn heartbeat(last_heartbeat: DateTime<Utc>, now: DateTime<Utc>, lease: Duration) -> String {
+    let age = (now - last_heartbeat).to_std().unwrap_or_default();
+    let age = humantime::format_duration(Duration::from_secs(age.as_secs()));
+
+    match age.get_ref() > &lease {
+        true => format!("{} ago (stale)", age),
+        false => format!("{} ago", age),
+    }
+}
+
+fn lease(ctx: &FlameContext) -> Result<Duration, Box<dyn Error>> {
+    Ok(ctx.timings.clone().unwrap_or_default().lease()?)
+}
+
+/// Returns a clear message for servers which do not provide the executor APIs yet.
+fn check_supported(conn: &Connection) -> Result<(), Box<dyn Error>> {
+    match conn.supports(capability::EXECUTOR_API) {
+        true => Ok(()),
+        false => Err(
+            "the Flame server does not support executor APIs, please upgrade the session manager"
+                .into(),
+        ),
+    }
+}
+
+pub async fn list(ctx: &FlameContext, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+    check_supported(&conn)?;
+    let mut exe_list = conn.list_executor().await?;
+    exe_list.sort_by(|l, r| l.id.cmp(&r.id));
+
+    let lease = lease(ctx)?;
+    let rows: Vec<_> = exe_list
+        .iter()
+        .map(|exe| ExecutorRow { exe, lease })
+        .collect();
+    print!("{}", output::render_list(&rows, format)?);
+
+    Ok(())
+}
+
+pub async fn describe(
+    ctx: &FlameContext,
+    id: &String,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+    check_supported(&conn)?;
+    let exe = conn.get_executor(id).await?;
+
+    let row = ExecutorRow {
+        exe: &exe,
+        lease: lease(ctx)?,
+    };
+    print!("{}", output::render_one(&row, format)?);
+
+    Ok(())
+}
+
+pub async fn drain(ctx: &FlameContext, id: &String) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+    check_supported(&conn)?;
+    let exe = conn.drain_executor(id).await?;
+
+    match exe.session_id {
+        Some(ssn_id) => println!(
+            "Executor <{}> is draining, it will be released after session <{}>.",
+            exe.id, ssn_id
+        ),
+        None => println!("Executor <{}> is drained.", exe.id),
+    }
+
+    Ok(())
+}
+
+pub async fn uncordon(ctx: &FlameContext, id: &String) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+    if !conn.supports(capability::EXECUTOR_QUARANTINE) {
+        return Err(
+            "the Flame server does not support uncordoning executors, please upgrade the session manager"
+                .into(),
+        );
+    }
+    let exe = conn.uncordon_executor(id).await?;
+
+    println!("Executor <{}> is uncordoned.", exe.id);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utilization() {
+        assert_eq!(utilization(&[]), "-");
+
+        let windows = [
+            Utilization {
+                window: Duration::from_secs(300),
+                percentage: 12.4,
+            },
+            Utilization {
+                window: Duration::from_secs(3600),
+                percentage: 40.0,
+            },
+        ];
+        assert_eq!(utilization(&windows), "12% (5m), 40% (1h)");
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let (now, lease) = (Utc::now(), Duration::from_secs(30));
+
+        assert_eq!(heartbeat(now, now, lease), "0s ago");
+        assert_eq!(
+            heartbeat(now - chrono::Duration::seconds(5), now, lease),
+            "5s ago"
+        );
+        assert_eq!(
+            heartbeat(now - chrono::Duration::seconds(90), now, lease),
+            "1m 30s ago (stale)"
+        );
+        // The lease of the context may be longer.
+        assert_eq!(
+            heartbeat(
+                now - chrono::Duration::seconds(90),
+                now,
+                Duration::from_secs(120)
+            ),
+            "1m 30s ago"
+        );
+    }
+}
@@ -0,0 +1,51 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+
+use chrono::Utc;
+
+use common::ctx::FlameContext;
+use flame_client::{capability, Deadline};
+
+use crate::helper;
+
+pub async fn run(
+    ctx: &FlameContext,
+    session: &String,
+    deadline: Deadline,
+) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+    if !conn.supports(capability::SESSION_DEADLINE) {
+        return Err(
+            "the Flame server does not support session deadlines, please upgrade it".into(),
+        );
+    }
+
+    // The durations are from now, instead of the creation of the session.
+    let deadline = match deadline {
+        Deadline::At(time) => time,
+        Deadline::After(d) => Utc::now() + chrono::Duration::from_std(d)?,
+    };
+
+    let mut ssn = conn.get_session(session).await?;
+    ssn.set_deadline(deadline).await?;
+
+    println!(
+        "The deadline of session <{}> was set to <{}>.",
+        ssn.id,
+        deadline.to_rfc3339()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,182 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+use std::fmt::Write;
+use std::time::Duration;
+
+use common::ctx::FlameContext;
+use flame_client::{capability, Allocation, AllocationReport, StarvedSession};
+
+use crate::helper;
+use crate::output::{self, OutputFormat, TableRow};
+
+impl TableRow for Allocation {
+    fn headers() -> Vec<&'static str> {
+        vec!["Name", "Desired", "Allocated", "Target", "Deviation"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            slots(self.desired),
+            slots(self.allocated),
+            slots(self.target),
+            format!("{:+.1}", self.deviation),
+        ]
+    }
+}
+
+impl TableRow for StarvedSession {
+    fn headers() -> Vec<&'static str> {
+        vec![
+            "ID",
+            "Namespace",
+            "App",
+            "Pending",
+            "Allocated",
+            "Target",
+            "Wait",
+        ]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.namespace.clone(),
+            self.application.clone(),
+            self.pending.to_string(),
+            slots(self.allocated),
+            slots(self.target),
+            humantime::format_duration(Duration::from_secs(self.wait.as_secs())).to_string(),
+        ]
+    }
+}
+
+fn slots(n: f64) -> String {
+    format!("{:.1}", n)
+}
+
+pub async fn run(ctx: &FlameContext, top: u32, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+    if !conn.supports(capability::ALLOCATION_REPORT) {
+        return Err(
+            "the Flame server does not report the allocation of the slots, please upgrade it"
+                .into(),
+        );
+    }
+
+    let report = conn.get_allocation_report(top).await?;
+    print!("{}", render(&report, format)?);
+
+    Ok(())
+}
+
+/// The applications, the namespaces and the starved sessions in their own tables.
+fn render(report: &AllocationReport, format: OutputFormat) -> Result<String, Box<dyn Error>> {
+    match format {
+        OutputFormat::Json => return Ok(format!("{}\n", serde_json::to_string_pretty(report)?)),
+        OutputFormat::Yaml => return Ok(serde_yaml::to_string(report)?),
+        OutputFormat::Table => {}
+    }
+
+    let mut res = String::new();
+    let _ = writeln!(
+        res,
+        "Slots: {} total, updated at {}",
+        slots(report.total_slots),
+        report
+            .update_time
+            .map(|t| t.format("%F %T").to_string())
+            .unwrap_or("-".to_string())
+    );
+    let _ = writeln!(res, "\nApplications:");
+    res.push_str(&output::render_list(&report.applications, format)?);
+    let _ = writeln!(res, "\nNamespaces:");
+    res.push_str(&output::render_list(&report.namespaces, format)?);
+    if !report.starved_sessions.is_empty() {
+        let _ = writeln!(res, "\nStarved sessions:");
+        res.push_str(&output::render_list(&report.starved_sessions, format)?);
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_render() {
+        let allocation = |name: &str, desired, allocated, target| Allocation {
+            name: name.to_string(),
+            desired,
+            allocated,
+            target,
+            deviation: allocated - target,
+        };
+        let mut report = AllocationReport {
+            update_time: Some(Utc.with_ymd_and_hms(2024, 7, 1, 8, 0, 0).unwrap()),
+            total_slots: 10.0,
+            applications: vec![
+                allocation("preprocessing", 1.0, 0.0, 1.0),
+                allocation("training", 40.0, 7.0, 9.0),
+            ],
+            namespaces: vec![
+                allocation("team-a", 21.0, 2.0, 5.5),
+                allocation("team-b", 20.0, 5.0, 4.5),
+            ],
+            starved_sessions: vec![StarvedSession {
+                id: "3".to_string(),
+                application: "preprocessing".to_string(),
+                namespace: "team-a".to_string(),
+                pending: 1,
+                allocated: 0.0,
+                target: 1.0,
+                wait: Duration::from_millis(60_500),
+            }],
+        };
+
+        assert_eq!(
+            render(&report, OutputFormat::Table).unwrap(),
+            concat!(
+                "Slots: 10.0 total, updated at 2024-07-01 08:00:00\n",
+                "\n",
+                "Applications:\n",
+                "Name           Desired  Allocated  Target  Deviation\n",
+                "preprocessing  1.0      0.0        1.0     -1.0\n",
+                "training       40.0     7.0        9.0     -2.0\n",
+                "\n",
+                "Namespaces:\n",
+                "Name    Desired  Allocated  Target  Deviation\n",
+                "team-a  21.0     2.0        5.5     -3.5\n",
+                "team-b  20.0     5.0        4.5     +0.5\n",
+                "\n",
+                "Starved sessions:\n",
+                "ID  Namespace  App            Pending  Allocated  Target  Wait\n",
+                "3   team-a     preprocessing  1        0.0        1.0     1m\n",
+            )
+        );
+
+        // The starved sessions are omitted if there's none.
+        report.starved_sessions.clear();
+        let res = render(&report, OutputFormat::Table).unwrap();
+        assert!(
+            res.ends_with("team-b  20.0     5.0        4.5     +0.5\n"),
+            "{}",
+            res
+        );
+    }
+}
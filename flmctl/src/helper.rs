@@ -11,7 +11,64 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use std::collections::HashMap;
 use std::error::Error;
+use std::io::Cursor;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use lazy_static::lazy_static;
+
+use common::ctx::FlameContext;
+use common::{capability, lock_ptr};
+use flame_client::{self as flame, Connection, FlameError, Session, Task};
+
+lazy_static! {
+    /// The connections of the commands by the endpoints and the namespaces, which are reused
+    /// by the following commands, e.g. in `flmctl shell`.
+    static ref CONNECTIONS: Mutex<HashMap<String, Connection>> = Mutex::new(HashMap::new());
+}
+
+/// Connects to the session manager of the context, or reuses the connection of the previous
+/// command.
+pub async fn connect(ctx: &FlameContext) -> Result<Connection, FlameError> {
+    let key = format!(
+        "{}/{}",
+        ctx.endpoint,
+        ctx.namespace.as_deref().unwrap_or_default()
+    );
+    if let Some(conn) = lock_ptr!(CONNECTIONS)?.get(&key) {
+        return Ok(conn.clone());
+    }
+
+    let conn = flame::ConnectionBuilder::from_context(ctx)?
+        .connect()
+        .await?;
+    lock_ptr!(CONNECTIONS)?.insert(key, conn.clone());
+
+    Ok(conn)
+}
+
+/// Creates a task with the input, which is sent in chunks if it exceeds the message size
+/// limit and the session manager supports it, e.g. a large file. The task of the key is
+/// returned if the session already has one, see `Session::create_task_with_key`.
+pub async fn create_task(
+    ssn: &Session,
+    input: Bytes,
+    key: Option<String>,
+) -> Result<Task, FlameError> {
+    let upload = ssn.is_large_input(input.len()) && ssn.supports(capability::TASK_UPLOAD);
+
+    match (upload, key) {
+        (true, Some(key)) => {
+            ssn.create_task_from_reader_with_key(Cursor::new(input), key)
+                .await
+        }
+        (true, None) => ssn.create_task_from_reader(Cursor::new(input)).await,
+        (false, Some(key)) => ssn.create_task_with_key(Some(input), key).await,
+        (false, None) => ssn.create_task(Some(input)).await,
+    }
+}
 
 pub async fn run() -> Result<(), Box<dyn Error>> {
     todo!()
@@ -11,46 +11,49 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::cmp::Ordering;
 use std::error::Error;
 
+use clap::ValueEnum;
+
 use common::ctx::FlameContext;
-use flame_client as flame;
-use flame_client::SessionState;
-
-pub async fn run(ctx: &FlameContext) -> Result<(), Box<dyn Error>> {
-    let conn = flame::connect(&ctx.endpoint).await?;
-    let mut ssn_list = conn.list_session().await?;
-
-    println!(
-        "{:<10}{:<10}{:<15}{:<10}{:<10}{:<10}{:<10}{:<10}{:<10}",
-        "ID", "State", "App", "Slots", "Pending", "Running", "Succeed", "Failed", "Created"
-    );
-
-    ssn_list.sort_by(|l, r| {
-        if l.state == r.state {
-            l.creation_time.cmp(&r.creation_time)
-        } else if l.state == SessionState::Open {
-            Ordering::Less
-        } else {
-            Ordering::Greater
+use flame_client::SessionOrder;
+
+use crate::helper;
+use crate::output::{self, OutputFormat};
+
+/// The order of the listed sessions, which is computed by the session manager.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OrderBy {
+    /// The newest sessions first
+    #[default]
+    CreationTime,
+    Id,
+    /// The open sessions first
+    State,
+    /// The sessions with the most pending tasks first
+    Pending,
+}
+
+impl From<OrderBy> for SessionOrder {
+    fn from(o: OrderBy) -> Self {
+        match o {
+            OrderBy::CreationTime => SessionOrder::CreationTime,
+            OrderBy::Id => SessionOrder::Id,
+            OrderBy::State => SessionOrder::State,
+            OrderBy::Pending => SessionOrder::Pending,
         }
-    });
-
-    for ssn in &ssn_list {
-        println!(
-            "{:<10}{:<10}{:<15}{:<10}{:<10}{:<10}{:<10}{:<10}{:<10}",
-            ssn.id,
-            ssn.state,
-            ssn.application,
-            ssn.slots,
-            ssn.pending,
-            ssn.running,
-            ssn.succeed,
-            ssn.failed,
-            ssn.creation_time.format("%T")
-        );
     }
+}
+
+pub async fn run(
+    ctx: &FlameContext,
+    order_by: OrderBy,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+    let ssn_list = conn.list_session_by(order_by.into()).await?;
+
+    print!("{}", output::render_list(&ssn_list, format)?);
 
     Ok(())
 }
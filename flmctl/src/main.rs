@@ -12,25 +12,55 @@ limitations under the License.
 */
 
 use std::error::Error;
+use std::time::Duration;
 
 use clap::{Parser, Subcommand};
+use common::apis::{self, TaskGID};
 use common::ctx::FlameContext;
 
+use crate::output::OutputFormat;
+
+mod admin;
+mod app;
+mod archive;
+mod audit;
+mod clone;
+mod close;
+mod config;
 mod create;
+mod delete;
+mod executors;
+mod extend;
+mod fairness;
 mod helper;
 mod list;
 mod migrate;
+mod output;
+mod run;
+mod session;
+mod shell;
+mod task;
+mod template;
+mod top;
+mod version;
 mod view;
+mod wait;
+mod watch;
 
 #[derive(Parser)]
 #[command(name = "flmctl")]
 #[command(author = "Klaus Ma <klaus@xflops.cn>")]
-#[command(version = "0.1.0")]
+#[command(version)]
 #[command(about = "Flame command line", long_about = None)]
 struct Cli {
-    #[arg(long)]
+    /// The path of the Flame configuration file
+    #[arg(long, global = true)]
     flame_conf: Option<String>,
 
+    /// The namespace of the sessions; the one in the configuration by default
+    #[arg(long, global = true)]
+    namespace: Option<String>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -40,17 +70,89 @@ enum Commands {
     View {
         #[arg(short, long)]
         session: String,
+        /// Print the timeline of the events of the session instead
+        #[arg(long)]
+        events: bool,
+        /// The output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
     },
-    List,
+    List {
+        /// The order of the sessions
+        #[arg(long, value_enum, default_value_t = list::OrderBy::CreationTime)]
+        order_by: list::OrderBy,
+        /// The output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Close a session, which aborts its pending and running tasks
+    #[command(visible_alias = "cancel")]
     Close {
         #[arg(short, long)]
         session: String,
     },
     Create {
+        #[arg(short, long, required_unless_present = "template")]
+        app: Option<String>,
+        #[arg(short, long, required_unless_present = "template")]
+        slots: Option<i32>,
+        /// The session template in the configuration of the session manager, which gives the
+        /// options not set by the flags
         #[arg(short, long)]
-        app: String,
+        template: Option<String>,
+        /// The labels of the session, e.g. --label env=dev
+        #[arg(short, long = "label", value_parser = create::parse_label)]
+        labels: Vec<(String, String)>,
+        /// The settings of the session exported to the workers as FLAME_SSN_<KEY>, e.g.
+        /// --config model_version=v2
+        #[arg(long = "config", value_parser = create::parse_config)]
+        config: Vec<(String, String)>,
+        /// The maximum number of pending tasks of the session; the default of the cluster if not set
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        max_pending_tasks: Option<u32>,
+        /// Reuse the outputs of the succeeded tasks with the same input: None, Session, or
+        /// Application to share them with the other sessions of the application
+        #[arg(long, default_value = "none", value_parser = create::parse_cache_scope)]
+        cache_scope: flame_client::CacheScope,
+        /// Close the session and abort its tasks after the duration, e.g. 30m, or at the
+        /// RFC3339 time
+        #[arg(long, value_parser = create::parse_deadline)]
+        deadline: Option<flame_client::Deadline>,
+        /// The priority class of the session in the configuration of the session manager,
+        /// e.g. interactive
+        #[arg(long)]
+        priority_class: Option<String>,
+        /// Run the latest application instead of the one when the session is created
+        #[arg(long)]
+        latest_application: bool,
+    },
+    /// Set the deadline of an open session, e.g. to extend it
+    Extend {
         #[arg(short, long)]
-        slots: i32,
+        session: String,
+        /// The new deadline, which is a duration from now, e.g. 30m, or a RFC3339 time
+        #[arg(long, value_parser = create::parse_deadline)]
+        deadline: flame_client::Deadline,
+    },
+    /// Delete the closed sessions by ids or filters
+    Delete {
+        /// The ids of the sessions to delete
+        sessions: Vec<String>,
+        /// Only delete the sessions with all of the labels, e.g. env=dev,team=ml
+        #[arg(short = 'l', long)]
+        selector: Option<delete::Selector>,
+        /// Only delete the sessions in the state, e.g. Closed
+        #[arg(long, value_parser = delete::parse_state)]
+        state: Option<flame_client::SessionState>,
+        /// Only delete the sessions completed (or created) before the duration, e.g. 7d
+        #[arg(long, value_parser = humantime::parse_duration)]
+        older_than: Option<Duration>,
+        /// Delete without confirmation
+        #[arg(short, long)]
+        yes: bool,
+        /// Also delete the sessions with running tasks
+        #[arg(long)]
+        force: bool,
     },
     Migrate {
         #[arg(short, long)]
@@ -58,6 +160,322 @@ enum Commands {
         #[arg(short, long)]
         sql: String,
     },
+    /// Follow a session until all of its tasks are completed
+    Watch {
+        /// The id of the session to watch
+        session: String,
+        /// The maximum time to wait, e.g. 30s, 10m, 1h
+        #[arg(long, value_parser = humantime::parse_duration)]
+        timeout: Option<Duration>,
+    },
+    /// Export a session with its tasks to an archive, e.g. before it's deleted
+    Export {
+        /// The id of the session to export
+        session: String,
+        /// The file to write the archive to
+        #[arg(short, long)]
+        file: String,
+    },
+    /// Import an archived session as a closed session for inspection
+    Import {
+        /// The archive to import
+        #[arg(short, long)]
+        file: String,
+    },
+    /// Create an open session with the settings of another, e.g. to run a batch again
+    Clone {
+        /// The id of the session to clone
+        session: String,
+        /// Also copy the inputs of its tasks as pending tasks; their outputs are not copied
+        #[arg(long)]
+        with_tasks: bool,
+        /// Run the latest application instead of the one of the session
+        #[arg(long)]
+        latest_application: bool,
+    },
+    /// Run the tasks of an input file in a new session and download their outputs
+    #[command(visible_alias = "submit")]
+    Run {
+        /// The application of the session
+        #[arg(long)]
+        app: String,
+        /// The slots of each task
+        #[arg(long, default_value_t = 1)]
+        slots: i32,
+        /// The file of task inputs, one task per line
+        #[arg(long)]
+        input_file: String,
+        /// The directory to save task outputs, named by the line number of the input
+        #[arg(long)]
+        output_dir: String,
+        /// The maximum number of tasks running at the same time, which is the limit of the
+        /// session
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        max_parallelism: Option<u32>,
+    },
+    /// Wait for a session without printing progress, e.g. in CI
+    Wait {
+        /// The id of the session to wait for
+        session: String,
+        /// The condition to wait for
+        #[arg(long = "for", value_enum, default_value_t = wait::WaitFor::Completed)]
+        wait_for: wait::WaitFor,
+        /// The maximum time to wait, e.g. 30s, 10m, 1h
+        #[arg(long, value_parser = humantime::parse_duration)]
+        timeout: Option<Duration>,
+        /// Print the status of the session on every check
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Resubmit the failed tasks of a session which were not resubmitted yet
+    ResubmitFailed {
+        /// The id of the session
+        session: String,
+    },
+    /// Download the results of a session
+    Session {
+        #[command(subcommand)]
+        command: SessionCommands,
+    },
+    /// Inspect a single task
+    Task {
+        #[command(subcommand)]
+        command: TaskCommands,
+    },
+    /// Inspect and manage the executors
+    Executors {
+        #[command(subcommand)]
+        command: ExecutorCommands,
+    },
+    /// Show the utilization of the cluster, refreshed periodically
+    Top {
+        /// The refresh interval, e.g. 2s
+        #[arg(long, default_value = "2s", value_parser = humantime::parse_duration)]
+        interval: Duration,
+        /// The number of sessions to show
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// Print once and exit
+        #[arg(long)]
+        once: bool,
+    },
+    /// Show the fair shares of the slots by application and namespace, and the starved
+    /// sessions
+    Fairness {
+        /// The number of starved sessions to show
+        #[arg(long, default_value_t = 10)]
+        top: u32,
+        /// The output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// List the session templates of the session manager
+    Templates {
+        #[command(subcommand)]
+        command: TemplateCommands,
+    },
+    /// List and register applications
+    App {
+        #[command(subcommand)]
+        command: AppCommands,
+    },
+    /// View and update the Flame configuration file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Inspect the audit log of the session manager
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommands,
+    },
+    /// Control the scheduler of the session manager, e.g. during incidents
+    Admin {
+        #[command(subcommand)]
+        command: AdminCommands,
+    },
+    /// Print the versions of flmctl and the Flame server, and the capabilities of the server
+    Version,
+    /// Run the commands interactively over one connection, with the history of the commands
+    /// and the completion of the session ids
+    Shell,
+}
+
+#[derive(Subcommand)]
+enum AdminCommands {
+    /// Stop binding and unbinding sessions; the running tasks are not affected
+    Pause,
+    /// Resume the scheduling
+    Resume,
+    /// Show the status of the scheduler
+    Status {
+        /// The output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Print the configuration the session manager runs with, with secrets redacted
+    Config,
+}
+
+#[derive(Subcommand)]
+enum AuditCommands {
+    /// Print the last entries of the audit log
+    Tail {
+        /// The audit log file; the one in the configuration by default
+        #[arg(short, long)]
+        file: Option<String>,
+        /// The number of entries to print
+        #[arg(short = 'n', long, default_value_t = 10)]
+        lines: usize,
+        /// The output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+}
+
+#[derive(Subcommand)]
+enum AppCommands {
+    /// List all applications
+    List {
+        /// The output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Register an application from a yaml file
+    Register {
+        /// The yaml file of the application spec
+        #[arg(short, long)]
+        file: String,
+    },
+    /// Delete an application
+    Delete {
+        /// The name of the application
+        name: String,
+        /// Delete the application even if it's used by open sessions
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print the configuration with secrets redacted
+    View,
+    /// Update a field of the configuration
+    Set {
+        #[arg(value_enum)]
+        key: config::ConfigKey,
+        value: String,
+    },
+    /// Report all the problems of the configuration
+    Validate,
+}
+
+#[derive(Subcommand)]
+enum TemplateCommands {
+    /// List all session templates
+    List {
+        /// The output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExecutorCommands {
+    /// List all executors
+    List {
+        /// The output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Show the detail of an executor
+    Describe {
+        /// The id of the executor
+        id: String,
+        /// The output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Stop binding new sessions to an executor
+    Drain {
+        /// The id of the executor
+        id: String,
+    },
+    /// Bind new sessions to a drained or quarantined executor again
+    Uncordon {
+        /// The id of the executor
+        id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum SessionCommands {
+    /// Download the outputs of the tasks of a session, one file per task named by its index
+    Outputs {
+        /// The id of the session
+        session: String,
+        /// The directory to save the outputs
+        #[arg(long)]
+        dir: String,
+        /// Only download the outputs of the tasks in the state, e.g. Succeed
+        #[arg(long, value_parser = task::parse_state)]
+        state: Option<flame_client::TaskState>,
+        /// The index of the first task to download
+        #[arg(long, default_value_t = 0)]
+        start: u64,
+        /// The index after the last task to download; to the last task by default
+        #[arg(long)]
+        end: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TaskCommands {
+    /// Show the detail of a task
+    Get {
+        /// The id of the task, e.g. <ssn/task>
+        task: TaskGID,
+        /// The output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Download the output of a task
+    Output {
+        /// The id of the task, e.g. <ssn/task>
+        task: TaskGID,
+        /// The file to save the output; print to stdout by default
+        #[arg(short, long)]
+        file: Option<String>,
+    },
+    /// List the tasks of a session, e.g. the failed ones by --state failed
+    List {
+        /// The id of the session
+        session: String,
+        /// Only list the tasks in the state, e.g. Failed
+        #[arg(long, value_parser = task::parse_state)]
+        state: Option<flame_client::TaskState>,
+        /// The output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Resubmit a failed task as a new task with the same input
+    Resubmit {
+        /// The id of the failed task, e.g. <ssn/task>
+        task: TaskGID,
+    },
+    /// Run a task in a session, wait for it and write its output to stdout; it exits with 1
+    /// if the task failed, 4 if it was aborted, 5 if its session expired, and 124 on timeout
+    Run {
+        /// The id of the session
+        session: String,
+        /// The file of the task input, which is sent as is
+        #[arg(long)]
+        input_file: String,
+        /// The maximum time to wait, e.g. 30s, 10m, 1h
+        #[arg(long, value_parser = humantime::parse_duration)]
+        timeout: Option<Duration>,
+    },
 }
 
 #[tokio::main]
@@ -65,18 +483,221 @@ async fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
 
     let cli = Cli::parse();
-    let ctx = FlameContext::from_file(cli.flame_conf)?;
-
-    match &cli.command {
-        Some(Commands::List) => list::run(&ctx).await?,
-        Some(Commands::Close { .. }) => {
-            todo!()
+    let code = match &cli.command {
+        // The config commands work on the file directly, so an invalid configuration can be fixed.
+        Some(Commands::Config { command }) => config(&cli.flame_conf, command)?,
+        Some(Commands::Shell) => {
+            let ctx = context(&cli.flame_conf, &cli.namespace)?;
+            shell::run(&ctx, &cli).await?
+        }
+        Some(command) => {
+            let ctx = context(&cli.flame_conf, &cli.namespace)?;
+            execute(&ctx, &cli.flame_conf, command).await?
+        }
+        None => {
+            helper::run().await?;
+            0
         }
-        Some(Commands::Create { app, slots }) => create::run(&ctx, app, slots).await?,
-        Some(Commands::View { session }) => view::run(&ctx, session).await?,
-        Some(Commands::Migrate { url, sql }) => migrate::run(&ctx, url, sql).await?,
-        _ => helper::run().await?,
     };
+    if code != 0 {
+        std::process::exit(code);
+    }
 
     Ok(())
 }
+
+/// The context of the configuration file, with the namespace of `--namespace` if any.
+fn context(
+    flame_conf: &Option<String>,
+    namespace: &Option<String>,
+) -> Result<FlameContext, Box<dyn Error>> {
+    let mut ctx = FlameContext::from_file(flame_conf.clone())?;
+    if let Some(namespace) = namespace {
+        ctx.namespace = Some(apis::parse_namespace(namespace)?);
+    }
+
+    Ok(ctx)
+}
+
+fn config(flame_conf: &Option<String>, command: &ConfigCommands) -> Result<i32, Box<dyn Error>> {
+    let path = FlameContext::conf_path(flame_conf.clone());
+    match command {
+        ConfigCommands::View => config::view(&path)?,
+        ConfigCommands::Set { key, value } => config::set(&path, *key, value)?,
+        ConfigCommands::Validate => return config::validate(&path),
+    };
+
+    Ok(0)
+}
+
+/// Runs the command, and returns the exit code of flmctl.
+async fn execute(
+    ctx: &FlameContext,
+    flame_conf: &Option<String>,
+    command: &Commands,
+) -> Result<i32, Box<dyn Error>> {
+    match command {
+        Commands::List { order_by, output } => list::run(ctx, *order_by, *output).await?,
+        Commands::Close { session } => close::run(ctx, session).await?,
+        Commands::Create {
+            app,
+            slots,
+            template,
+            labels,
+            config,
+            max_pending_tasks,
+            cache_scope,
+            deadline,
+            priority_class,
+            latest_application,
+        } => {
+            let attr = flame_client::SessionAttributes {
+                application: app.clone().unwrap_or_default(),
+                slots: slots.unwrap_or_default(),
+                labels: labels.iter().cloned().collect(),
+                config: config.iter().cloned().collect(),
+                max_pending_tasks: *max_pending_tasks,
+                cache_scope: *cache_scope,
+                deadline: *deadline,
+                template: template.clone(),
+                priority_class: priority_class.clone(),
+                latest_application: *latest_application,
+                ..Default::default()
+            };
+            create::run(ctx, &attr).await?
+        }
+        Commands::Extend { session, deadline } => extend::run(ctx, session, *deadline).await?,
+        Commands::Delete {
+            sessions,
+            selector,
+            state,
+            older_than,
+            yes,
+            force,
+        } => {
+            let args = delete::DeleteArgs {
+                sessions: sessions.clone(),
+                selector: selector.clone(),
+                state: *state,
+                older_than: *older_than,
+                yes: *yes,
+                force: *force,
+            };
+            return delete::run(ctx, &args).await;
+        }
+        Commands::View {
+            session,
+            events,
+            output,
+        } => view::run(ctx, session, *events, *output).await?,
+        Commands::Migrate { url, sql } => migrate::run(ctx, url, sql).await?,
+        Commands::Watch { session, timeout } => {
+            return watch::run(ctx, session, timeout).await;
+        }
+        Commands::Wait {
+            session,
+            wait_for,
+            timeout,
+            verbose,
+        } => {
+            return wait::run(ctx, session, *wait_for, timeout, *verbose).await;
+        }
+        Commands::Export { session, file } => archive::export(ctx, session, file).await?,
+        Commands::Import { file } => archive::import(ctx, file).await?,
+        Commands::Clone {
+            session,
+            with_tasks,
+            latest_application,
+        } => clone::run(ctx, session, *with_tasks, *latest_application).await?,
+        Commands::Run {
+            app,
+            slots,
+            input_file,
+            output_dir,
+            max_parallelism,
+        } => {
+            let args = run::RunArgs {
+                app: app.clone(),
+                slots: *slots,
+                input_file: input_file.clone(),
+                output_dir: output_dir.clone(),
+                max_parallelism: *max_parallelism,
+            };
+            return run::run(ctx, &args).await;
+        }
+        Commands::ResubmitFailed { session } => task::resubmit_failed(ctx, session).await?,
+        Commands::Session { command } => match command {
+            SessionCommands::Outputs {
+                session: ssn_id,
+                dir,
+                state,
+                start,
+                end,
+            } => {
+                let filter = flame_client::OutputFilter {
+                    state: *state,
+                    start_index: *start,
+                    end_index: *end,
+                };
+                session::outputs(ctx, ssn_id, dir, &filter).await?
+            }
+        },
+        Commands::Task { command } => match command {
+            TaskCommands::Get { task, output } => task::get(ctx, task, *output).await?,
+            TaskCommands::Output { task, file } => task::output(ctx, task, file).await?,
+            TaskCommands::List {
+                session,
+                state,
+                output,
+            } => task::list(ctx, session, *state, *output).await?,
+            TaskCommands::Resubmit { task } => task::resubmit(ctx, task).await?,
+            TaskCommands::Run {
+                session,
+                input_file,
+                timeout,
+            } => {
+                return task::run(ctx, session, input_file, timeout).await;
+            }
+        },
+        Commands::Top {
+            interval,
+            top,
+            once,
+        } => top::run(ctx, *interval, *top, *once).await?,
+        Commands::Fairness { top, output } => fairness::run(ctx, *top, *output).await?,
+        Commands::Templates { command } => match command {
+            TemplateCommands::List { output } => template::list(ctx, *output).await?,
+        },
+        Commands::App { command } => match command {
+            AppCommands::List { output } => app::list(ctx, *output).await?,
+            AppCommands::Register { file } => app::register(ctx, file).await?,
+            AppCommands::Delete { name, force } => app::delete(ctx, name, *force).await?,
+        },
+        Commands::Executors { command } => match command {
+            ExecutorCommands::List { output } => executors::list(ctx, *output).await?,
+            ExecutorCommands::Describe { id, output } => {
+                executors::describe(ctx, id, *output).await?
+            }
+            ExecutorCommands::Drain { id } => executors::drain(ctx, id).await?,
+            ExecutorCommands::Uncordon { id } => executors::uncordon(ctx, id).await?,
+        },
+        Commands::Audit { command } => match command {
+            AuditCommands::Tail {
+                file,
+                lines,
+                output,
+            } => audit::run(ctx, file, *lines, *output)?,
+        },
+        Commands::Admin { command } => match command {
+            AdminCommands::Pause => admin::pause(ctx).await?,
+            AdminCommands::Resume => admin::resume(ctx).await?,
+            AdminCommands::Status { output } => admin::status(ctx, *output).await?,
+            AdminCommands::Config => admin::config(ctx).await?,
+        },
+        Commands::Version => version::run(ctx).await?,
+        Commands::Config { command } => return config(flame_conf, command),
+        Commands::Shell => return Err("flmctl shell can not be nested".into()),
+    };
+
+    Ok(0)
+}
@@ -0,0 +1,380 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use flame_client::{Session, SessionState, Task};
+
+const COLUMN_PADDING: usize = 2;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    Yaml,
+}
+
+/// The objects which can be printed as a row of a table by flmctl.
+pub trait TableRow {
+    fn headers() -> Vec<&'static str>;
+    fn row(&self) -> Vec<String>;
+
+    /// The fields printed by `render_one`; it's the same as the row by default.
+    fn detail(&self) -> Vec<(&'static str, String)> {
+        Self::headers().into_iter().zip(self.row()).collect()
+    }
+}
+
+impl TableRow for Session {
+    fn headers() -> Vec<&'static str> {
+        vec![
+            "ID",
+            "Namespace",
+            "State",
+            "App",
+            "Slots",
+            "Pending",
+            "Running",
+            "Succeed",
+            "Failed",
+            "Created",
+            "Remaining",
+        ]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.namespace.clone(),
+            self.state.to_string(),
+            self.application.clone(),
+            self.slots.to_string(),
+            self.pending.to_string(),
+            self.running.to_string(),
+            self.succeed.to_string(),
+            self.failed.to_string(),
+            self.creation_time.format("%T").to_string(),
+            remaining(self, Utc::now()),
+        ]
+    }
+
+    fn detail(&self) -> Vec<(&'static str, String)> {
+        let mut detail: Vec<_> = Self::headers().into_iter().zip(self.row()).collect();
+        if let Some(src) = &self.cloned_from {
+            detail.push(("Cloned from", src.clone()));
+        }
+        if let Some(fingerprint) = &self.application_fingerprint {
+            detail.push(("App fingerprint", fingerprint.clone()));
+        }
+        if !self.annotations.is_empty() {
+            detail.push(("Annotations", annotations(&self.annotations)));
+        }
+        // The sessions no executor can run are only warned in the detail, as it's rare.
+        if let Some(reason) = &self.unschedulable {
+            detail.push(("Warning", format!("unschedulable, {}", reason)));
+        }
+
+        detail
+    }
+}
+
+/// The time before the open session expires, e.g. `4m 10s`; `-` if it has no deadline.
+fn remaining(ssn: &Session, now: DateTime<Utc>) -> String {
+    match ssn.deadline {
+        Some(deadline) if ssn.state == SessionState::Open => match (deadline - now).to_std() {
+            Ok(d) if d.as_secs() > 0 => {
+                humantime::format_duration(Duration::from_secs(d.as_secs())).to_string()
+            }
+            _ => "expired".to_string(),
+        },
+        _ => "-".to_string(),
+    }
+}
+
+impl TableRow for Task {
+    fn headers() -> Vec<&'static str> {
+        vec!["ID", "Session", "State", "Created", "Completed", "Failure"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.ssn_id.clone(),
+            self.state.to_string(),
+            self.creation_time.format("%T").to_string(),
+            self.completion_time
+                .map(|t| t.format("%T").to_string())
+                .unwrap_or("-".to_string()),
+            self.failure
+                .as_ref()
+                .map(|f| f.reason.to_string())
+                .unwrap_or("-".to_string()),
+        ]
+    }
+
+    fn detail(&self) -> Vec<(&'static str, String)> {
+        let mut detail: Vec<_> = Self::headers().into_iter().zip(self.row()).collect();
+        // The message of the failure is only shown in the detail, as it may be long.
+        if let Some((_, failure)) = detail.last_mut() {
+            if let Some(f) = &self.failure {
+                *failure = format!("{}: {}", f.reason, f.message);
+            }
+        }
+        detail.push((
+            "Executor",
+            self.executor_id.clone().unwrap_or("-".to_string()),
+        ));
+        detail.push(("Host", self.hostname.clone().unwrap_or("-".to_string())));
+        detail.push((
+            "Original",
+            self.original_task_id.clone().unwrap_or("-".to_string()),
+        ));
+        if !self.annotations.is_empty() {
+            detail.push(("Annotations", annotations(&self.annotations)));
+        }
+
+        detail
+    }
+}
+
+/// The annotations in the order of their keys, e.g. `rows=100, score=0.9`.
+fn annotations(annotations: &BTreeMap<String, String>) -> String {
+    annotations
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Renders a list of objects, e.g. `flmctl list`.
+pub fn render_list<T>(items: &[T], format: OutputFormat) -> Result<String, Box<dyn Error>>
+where
+    T: Serialize + TableRow,
+{
+    match format {
+        OutputFormat::Table => Ok(table(items)),
+        OutputFormat::Json => Ok(format!("{}\n", serde_json::to_string_pretty(items)?)),
+        OutputFormat::Yaml => Ok(serde_yaml::to_string(items)?),
+    }
+}
+
+/// Renders a single object, e.g. `flmctl view`; the table format prints one field per line.
+pub fn render_one<T>(item: &T, format: OutputFormat) -> Result<String, Box<dyn Error>>
+where
+    T: Serialize + TableRow,
+{
+    match format {
+        OutputFormat::Table => {
+            let detail = item.detail();
+            let width = detail.iter().map(|(h, _)| h.len()).max().unwrap_or(0) + 1;
+
+            let mut res = String::new();
+            for (h, v) in detail {
+                res.push_str(&format!("{:<width$} {}\n", format!("{}:", h), v));
+            }
+            Ok(res)
+        }
+        OutputFormat::Json => Ok(format!("{}\n", serde_json::to_string_pretty(item)?)),
+        OutputFormat::Yaml => Ok(serde_yaml::to_string(item)?),
+    }
+}
+
+fn table<T: TableRow>(items: &[T]) -> String {
+    let headers = T::headers();
+    let rows: Vec<Vec<String>> = items.iter().map(|i| i.row()).collect();
+
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in &rows {
+        for (w, v) in widths.iter_mut().zip(row) {
+            *w = (*w).max(v.len());
+        }
+    }
+
+    let mut res = String::new();
+    let headers = headers.iter().map(|h| h.to_string()).collect::<Vec<_>>();
+    for row in std::iter::once(&headers).chain(rows.iter()) {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(v, w)| format!("{:<width$}", v, width = w + COLUMN_PADDING))
+            .collect::<String>();
+        res.push_str(line.trim_end());
+        res.push('\n');
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sessions() -> Vec<Session> {
+        serde_json::from_str(include_str!("../tests/golden/list.json")).unwrap()
+    }
+
+    #[test]
+    fn test_render_list() {
+        let ssn_list = sessions();
+
+        assert_eq!(
+            render_list(&ssn_list, OutputFormat::Table).unwrap(),
+            include_str!("../tests/golden/list.table")
+        );
+        assert_eq!(
+            render_list(&ssn_list, OutputFormat::Json).unwrap(),
+            include_str!("../tests/golden/list.json")
+        );
+        assert_eq!(
+            render_list(&ssn_list, OutputFormat::Yaml).unwrap(),
+            include_str!("../tests/golden/list.yaml")
+        );
+    }
+
+    #[test]
+    fn test_remaining() {
+        let mut ssn = sessions().remove(0);
+        let now = ssn.creation_time;
+        assert_eq!(remaining(&ssn, now), "-");
+
+        ssn.deadline = Some(now + chrono::Duration::seconds(250));
+        assert_eq!(remaining(&ssn, now), "4m 10s");
+        assert_eq!(
+            remaining(&ssn, now + chrono::Duration::seconds(250)),
+            "expired"
+        );
+
+        ssn.state = SessionState::Closed;
+        assert_eq!(remaining(&ssn, now), "-");
+    }
+
+    #[test]
+    fn test_render_task_placement() {
+        let mut task: Task = serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "ssn_id": "2",
+            "state": "running",
+            "creation_time": "2024-10-01T08:00:00Z",
+            "completion_time": null,
+        }))
+        .unwrap();
+
+        let table = render_one(&task, OutputFormat::Table).unwrap();
+        assert!(
+            table.ends_with("Executor:  -\nHost:      -\nOriginal:  -\n"),
+            "{}",
+            table
+        );
+
+        task.executor_id = Some("exec-2".to_string());
+        task.hostname = Some("node-b".to_string());
+        task.original_task_id = Some("1".to_string());
+        let table = render_one(&task, OutputFormat::Table).unwrap();
+        assert!(
+            table.ends_with("Executor:  exec-2\nHost:      node-b\nOriginal:  1\n"),
+            "{}",
+            table
+        );
+    }
+
+    #[test]
+    fn test_render_task_failure() {
+        let mut task: Task = serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "ssn_id": "2",
+            "state": "pending",
+            "creation_time": "2024-10-01T08:00:00Z",
+            "completion_time": null,
+            "failure": {"reason": "oomkilled", "message": "killed by signal 9"},
+        }))
+        .unwrap();
+
+        assert_eq!(task.row().last().unwrap(), "OomKilled");
+        let table = render_one(&task, OutputFormat::Table).unwrap();
+        assert!(
+            table.contains("Failure:   OomKilled: killed by signal 9\n"),
+            "{}",
+            table
+        );
+
+        task.failure = None;
+        assert_eq!(task.row().last().unwrap(), "-");
+    }
+
+    #[test]
+    fn test_render_task_annotations() {
+        let mut task: Task = serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "ssn_id": "2",
+            "state": "succeed",
+            "creation_time": "2024-10-01T08:00:00Z",
+            "completion_time": "2024-10-01T08:01:00Z",
+        }))
+        .unwrap();
+        assert!(!render_one(&task, OutputFormat::Table)
+            .unwrap()
+            .contains("Annotations"));
+
+        task.annotations = BTreeMap::from([
+            ("score".to_string(), "0.9".to_string()),
+            ("rows".to_string(), "100".to_string()),
+        ]);
+        let table = render_one(&task, OutputFormat::Table).unwrap();
+        assert!(
+            table.ends_with("Annotations: rows=100, score=0.9\n"),
+            "{}",
+            table
+        );
+    }
+
+    #[test]
+    fn test_render_unschedulable() {
+        let mut ssn = sessions().remove(0);
+        assert!(!render_one(&ssn, OutputFormat::Table)
+            .unwrap()
+            .contains("Warning"));
+
+        ssn.unschedulable = Some("no executor offers application <pi>".to_string());
+        let detail = render_one(&ssn, OutputFormat::Table).unwrap();
+        assert!(
+            detail.ends_with("Warning:   unschedulable, no executor offers application <pi>\n"),
+            "{}",
+            detail
+        );
+    }
+
+    #[test]
+    fn test_render_one() {
+        let ssn = sessions().remove(0);
+
+        assert_eq!(
+            render_one(&ssn, OutputFormat::Table).unwrap(),
+            include_str!("../tests/golden/view.table")
+        );
+        assert_eq!(
+            render_one(&ssn, OutputFormat::Json).unwrap(),
+            include_str!("../tests/golden/view.json")
+        );
+        assert_eq!(
+            render_one(&ssn, OutputFormat::Yaml).unwrap(),
+            include_str!("../tests/golden/view.yaml")
+        );
+    }
+}
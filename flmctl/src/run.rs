@@ -0,0 +1,349 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+
+use common::capability;
+use common::ctx::FlameContext;
+use flame_client::{Connection, OutputFilter, Session, SessionAttributes, SessionState, TaskState};
+
+use crate::helper;
+use crate::watch::{self, EXIT_CLOSED, EXIT_FAILED, EXIT_SUCCEED};
+
+const STATE_FILE: &str = ".flmctl-run.yaml";
+
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+pub struct RunArgs {
+    pub app: String,
+    pub slots: i32,
+    pub input_file: String,
+    pub output_dir: String,
+    pub max_parallelism: Option<u32>,
+}
+
+/// The session of `flmctl run` kept in the output directory, so an interrupted run can be
+/// resumed by re-running the same command. The tasks are submitted again with the indexes of
+/// their inputs as the keys, so the session manager returns the tasks submitted before.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct RunState {
+    session: String,
+}
+
+impl RunState {
+    fn load(dir: &Path) -> Option<RunState> {
+        let data = fs::read_to_string(dir.join(STATE_FILE)).ok()?;
+        serde_yaml::from_str(&data).ok()
+    }
+
+    fn save(&self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(dir.join(STATE_FILE), serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    fn remove(dir: &Path) -> Result<(), Box<dyn Error>> {
+        fs::remove_file(dir.join(STATE_FILE))?;
+        Ok(())
+    }
+}
+
+/// Reads the inputs of the tasks, one task per non-empty line; the index is the line number.
+fn read_inputs(data: &str) -> Vec<(usize, Bytes)> {
+    data.lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| (i, Bytes::from(l.to_string())))
+        .collect()
+}
+
+fn output_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(index.to_string())
+}
+
+pub async fn run(ctx: &FlameContext, args: &RunArgs) -> Result<i32, Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+
+    run_in(&conn, args, &mut io::stdout()).await
+}
+
+async fn run_in(
+    conn: &Connection,
+    args: &RunArgs,
+    out: &mut impl Write,
+) -> Result<i32, Box<dyn Error>> {
+    if !conn.supports(capability::TASK_IDEMPOTENCY) || !conn.supports(capability::TASK_OUTPUTS) {
+        return Err(
+            "the Flame server does not support resuming the tasks of a run, please upgrade it"
+                .into(),
+        );
+    }
+
+    let output_dir = Path::new(&args.output_dir);
+    fs::create_dir_all(output_dir)?;
+    let inputs = read_inputs(&fs::read_to_string(&args.input_file)?);
+
+    let (ssn, state) = open_session(conn, args, output_dir).await?;
+    state.save(output_dir)?;
+
+    // The tasks whose output was downloaded by a previous run are skipped, and the others are
+    // submitted at once, as the session limits the running ones.
+    let mut tasks = HashMap::new();
+    for (index, input) in inputs {
+        if output_path(output_dir, index).exists() {
+            continue;
+        }
+        let task = helper::create_task(&ssn, input, Some(index.to_string())).await?;
+        tasks.insert(task.id, index);
+    }
+
+    let code = match tasks.is_empty() {
+        true => Some(EXIT_SUCCEED),
+        false => tokio::select! {
+            code = watch::watch(conn, &ssn.id, None, out) => Some(code?),
+            _ = tokio::signal::ctrl_c() => None,
+        },
+    };
+    let Some(code) = code else {
+        writeln!(out)?;
+        eprintln!(
+            "Interrupted; session <{}> is kept open, re-run the same command to resume.",
+            ssn.id
+        );
+        return Ok(EXIT_INTERRUPTED);
+    };
+    // The session was closed or deleted by others, which is reported by the watch.
+    if code == EXIT_CLOSED {
+        RunState::remove(output_dir)?;
+        return Ok(code);
+    }
+
+    download(&ssn, &tasks, output_dir).await?;
+    ssn.close().await?;
+    RunState::remove(output_dir)?;
+
+    if code == EXIT_FAILED {
+        let failed = conn.get_session(&ssn.id).await?.failed;
+        eprintln!("{} task(s) of session <{}> failed.", failed, ssn.id);
+    }
+
+    Ok(code)
+}
+
+/// Writes the outputs of the succeeded tasks by the indexes of their inputs; the outputs are
+/// streamed instead of getting the tasks one by one.
+async fn download(
+    ssn: &Session,
+    tasks: &HashMap<String, usize>,
+    dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut outputs = ssn
+        .outputs(&OutputFilter::state(TaskState::Succeed))
+        .await?;
+    while let Some(entry) = outputs.try_next().await? {
+        if let Some(index) = tasks.get(&entry.task_id) {
+            fs::write(output_path(dir, *index), entry.output.unwrap_or_default())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Resumes the session of the previous run if it's still open, otherwise creates a new one.
+async fn open_session(
+    conn: &Connection,
+    args: &RunArgs,
+    output_dir: &Path,
+) -> Result<(Session, RunState), Box<dyn Error>> {
+    if let Some(state) = RunState::load(output_dir) {
+        if let Ok(ssn) = conn.get_session(&state.session).await {
+            if ssn.state == SessionState::Open && ssn.application == args.app {
+                println!("Resume session <{}>.", ssn.id);
+                return Ok((ssn, state));
+            }
+        }
+    }
+
+    let attr = SessionAttributes {
+        application: args.app.clone(),
+        slots: args.slots,
+        max_running_tasks: args.max_parallelism,
+        ..Default::default()
+    };
+    let ssn = conn.create_session(&attr).await?;
+    println!("Session <{}> was created.", ssn.id);
+
+    let state = RunState {
+        session: ssn.id.clone(),
+    };
+
+    Ok((ssn, state))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+
+    use flame_client::testkit::MockServer;
+    use flame_client::FlameError;
+
+    use super::*;
+
+    /// The arguments of a run in a new directory, with the input file of the lines.
+    fn run_args(name: &str, lines: &str, max_parallelism: Option<u32>) -> RunArgs {
+        let dir = std::env::temp_dir().join(format!("flmctl-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let input_file = dir.join("inputs.txt");
+        fs::write(&input_file, lines).unwrap();
+
+        RunArgs {
+            app: "flmexec".to_string(),
+            slots: 1,
+            input_file: input_file.to_string_lossy().to_string(),
+            output_dir: dir.join("outputs").to_string_lossy().to_string(),
+            max_parallelism,
+        }
+    }
+
+    /// Runs while the driver completes the tasks, and returns the exit code.
+    async fn run_while(
+        server: &MockServer,
+        args: &RunArgs,
+        driver: impl Future<Output = Result<(), FlameError>>,
+    ) -> i32 {
+        let conn = server.connect().await.unwrap();
+        let mut out = vec![];
+        let (code, driven) = tokio::join!(run_in(&conn, args, &mut out), driver);
+        driven.unwrap();
+
+        code.unwrap()
+    }
+
+    fn outputs(args: &RunArgs) -> Vec<(String, String)> {
+        let mut outputs: Vec<_> = fs::read_dir(&args.output_dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.file_name() != Some(STATE_FILE.as_ref()))
+            .map(|p| {
+                let name = p.file_name().unwrap().to_string_lossy().to_string();
+                (name, fs::read_to_string(&p).unwrap())
+            })
+            .collect();
+        outputs.sort();
+        outputs
+    }
+
+    #[tokio::test]
+    async fn test_run() -> Result<(), FlameError> {
+        let server = MockServer::start().await?;
+        let args = run_args("run", "a\nb\n\nc\n", Some(2));
+
+        // The tasks are completed in the order of their inputs.
+        let driver = async {
+            for output in ["A", "B", "C"] {
+                server.complete_next_task(output).await?;
+            }
+            Ok(())
+        };
+        assert_eq!(run_while(&server, &args, driver).await, EXIT_SUCCEED);
+
+        let expected =
+            [("0", "A"), ("1", "B"), ("3", "C")].map(|(i, o)| (i.to_string(), o.to_string()));
+        assert_eq!(outputs(&args), expected);
+        assert_eq!(RunState::load(Path::new(&args.output_dir)), None);
+
+        let ssns = server.connect().await?.list_session().await?;
+        assert_eq!(ssns.len(), 1);
+        assert_eq!(ssns[0].state, SessionState::Closed);
+        assert_eq!(ssns[0].max_running_tasks, Some(2));
+        assert_eq!(ssns[0].succeed, 3);
+
+        fs::remove_dir_all(Path::new(&args.output_dir).parent().unwrap()).unwrap();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_resumed() -> Result<(), FlameError> {
+        let server = MockServer::start().await?;
+        let args = run_args("run-resumed", "a\nb\nc\n", None);
+
+        // The run is interrupted once the first task is completed.
+        let conn = server.connect().await?;
+        let mut out = vec![];
+        tokio::select! {
+            code = run_in(&conn, &args, &mut out) => panic!("run exited with {:?}", code),
+            done = server.complete_next_task("A") => {
+                done?;
+            }
+        }
+        let state = RunState::load(Path::new(&args.output_dir)).expect("the state of the run");
+
+        // The same run resumes the session without submitting the tasks again, and fails
+        // with the failed task after downloading the others.
+        let driver = async {
+            server.complete_next_task("B").await?;
+            server.fail_next_task("bad input").await?;
+            Ok(())
+        };
+        assert_eq!(run_while(&server, &args, driver).await, EXIT_FAILED);
+
+        let expected = [("0", "A"), ("1", "B")].map(|(i, o)| (i.to_string(), o.to_string()));
+        assert_eq!(outputs(&args), expected);
+        assert_eq!(RunState::load(Path::new(&args.output_dir)), None);
+
+        let ssn = conn.get_session(&state.session).await?;
+        assert_eq!(ssn.state, SessionState::Closed);
+        assert_eq!((ssn.pending, ssn.running), (0, 0));
+        assert_eq!((ssn.succeed, ssn.failed), (2, 1));
+        assert_eq!(conn.list_session().await?.len(), 1);
+
+        fs::remove_dir_all(Path::new(&args.output_dir).parent().unwrap()).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_inputs() {
+        let inputs = read_inputs("a.mp4\n\nb.mp4\n  \nc.mp4");
+        let indexes: Vec<usize> = inputs.iter().map(|(i, _)| *i).collect();
+
+        assert_eq!(indexes, vec![0, 2, 4]);
+        assert_eq!(inputs[1].1, Bytes::from("b.mp4"));
+    }
+
+    #[test]
+    fn test_run_state() {
+        let dir = std::env::temp_dir().join(format!("flmctl-run-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        assert_eq!(RunState::load(&dir), None);
+
+        let state = RunState {
+            session: "3".to_string(),
+        };
+        state.save(&dir).unwrap();
+        assert_eq!(RunState::load(&dir), Some(state));
+
+        RunState::remove(&dir).unwrap();
+        assert_eq!(RunState::load(&dir), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
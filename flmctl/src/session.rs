@@ -0,0 +1,126 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use futures::StreamExt;
+
+use common::capability;
+use common::ctx::FlameContext;
+use flame_client::{OutputFilter, Session};
+
+use crate::helper;
+
+/// Downloads the outputs of the tasks of the session to the directory, one file per task
+/// named by its index as `flmctl run` does.
+pub async fn outputs(
+    ctx: &FlameContext,
+    ssn_id: &str,
+    dir: &str,
+    filter: &OutputFilter,
+) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+    if !conn.supports(capability::TASK_OUTPUTS) {
+        return Err(
+            "the Flame server does not support streaming task outputs, please upgrade the session manager"
+                .into(),
+        );
+    }
+    let ssn = conn.get_session(&ssn_id.to_string()).await?;
+
+    let (written, skipped) = download(&ssn, filter, Path::new(dir)).await?;
+    println!(
+        "Downloaded {} outputs of Session <{}> to <{}>, {} tasks without output.",
+        written, ssn.id, dir, skipped
+    );
+
+    Ok(())
+}
+
+/// Writes the outputs as they're streamed, so only one output is held at a time; returns the
+/// number of the outputs written and of the tasks without output.
+async fn download(
+    ssn: &Session,
+    filter: &OutputFilter,
+    dir: &Path,
+) -> Result<(usize, usize), Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+
+    let mut outputs = ssn.outputs(filter).await?;
+    let (mut written, mut skipped) = (0, 0);
+    while let Some(entry) = outputs.next().await {
+        let entry = entry?;
+        match entry.output {
+            Some(output) => {
+                fs::write(dir.join(entry.index.to_string()), &output)?;
+                written += 1;
+            }
+            None => skipped += 1,
+        }
+    }
+
+    Ok((written, skipped))
+}
+
+#[cfg(test)]
+mod tests {
+    use flame_client::testkit::MockServer;
+    use flame_client::{FlameError, SessionAttributes, TaskState};
+
+    use super::*;
+
+    async fn open_session(server: &MockServer) -> Result<Session, FlameError> {
+        let attr = SessionAttributes {
+            application: "flmexec".to_string(),
+            slots: 1,
+            ..Default::default()
+        };
+
+        server.connect().await?.create_session(&attr).await
+    }
+
+    #[tokio::test]
+    async fn test_download_outputs() -> Result<(), Box<dyn Error>> {
+        let server = MockServer::start().await?;
+        let ssn = open_session(&server).await?;
+
+        for i in 0..5 {
+            ssn.create_task(Some(format!("input-{}", i).into())).await?;
+        }
+        for i in 0..3 {
+            server
+                .complete_next_task(format!("output-{}", i).into_bytes())
+                .await?;
+        }
+
+        let dir = std::env::temp_dir().join(format!("flmctl-outputs-{}", std::process::id()));
+        let (written, skipped) = download(&ssn, &OutputFilter::default(), &dir).await?;
+        assert_eq!((written, skipped), (3, 2));
+        let mut files: Vec<_> = fs::read_dir(&dir)?
+            .map(|f| f.map(|f| f.file_name().to_string_lossy().to_string()))
+            .collect::<Result<_, _>>()?;
+        files.sort();
+        assert_eq!(files, vec!["0", "1", "2"]);
+        assert_eq!(fs::read(dir.join("1"))?, b"output-1");
+
+        // Only the tasks in the state and the range are downloaded.
+        fs::remove_dir_all(&dir)?;
+        let filter = OutputFilter::state(TaskState::Succeed).indexes(1..10);
+        assert_eq!(download(&ssn, &filter, &dir).await?, (2, 0));
+        fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+}
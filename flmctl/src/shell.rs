@@ -0,0 +1,336 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, IsTerminal};
+use std::path::Path;
+use std::sync::Mutex;
+
+use clap::{CommandFactory, Parser};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use tokio::runtime::Handle;
+
+use common::ctx::FlameContext;
+use common::{lock_ptr, FlameError};
+
+use crate::{helper, Cli};
+
+const PROMPT: &str = "flmctl> ";
+
+/// The history of the commands, which is kept next to the configuration file.
+const HISTORY_FILE: &str = "flmctl_history";
+
+/// The exit code of the commands cancelled by Ctrl-C.
+const EXIT_CANCELLED: i32 = 130;
+
+/// Completes the subcommands, and the session ids which are listed on the first completion
+/// after each command.
+struct Completion {
+    ctx: FlameContext,
+    commands: Vec<String>,
+    ssn_ids: Mutex<Option<Vec<String>>>,
+}
+
+impl Completion {
+    fn new(ctx: &FlameContext) -> Self {
+        let commands = Cli::command()
+            .get_subcommands()
+            .flat_map(|c| std::iter::once(c.get_name()).chain(c.get_visible_aliases()))
+            .map(String::from)
+            .collect();
+
+        Completion {
+            ctx: ctx.clone(),
+            commands,
+            ssn_ids: Mutex::new(None),
+        }
+    }
+
+    /// Forgets the session ids, as the command may have created or deleted some sessions.
+    fn reset(&self) {
+        if let Ok(mut ssn_ids) = lock_ptr!(self.ssn_ids) {
+            *ssn_ids = None;
+        }
+    }
+
+    fn ssn_ids(&self) -> Vec<String> {
+        let Ok(mut ssn_ids) = lock_ptr!(self.ssn_ids) else {
+            return vec![];
+        };
+
+        if ssn_ids.is_none() {
+            // The editor is run out of the runtime, see `Input::read_line`.
+            let ssn_list = Handle::current().block_on(async {
+                let conn = helper::connect(&self.ctx).await?;
+                Ok::<_, Box<dyn Error>>(conn.list_session().await?)
+            });
+            match ssn_list {
+                Ok(ssn_list) => *ssn_ids = Some(ssn_list.into_iter().map(|s| s.id).collect()),
+                Err(e) => log::debug!("Failed to list the sessions to complete: {}", e),
+            }
+        }
+
+        ssn_ids.clone().unwrap_or_default()
+    }
+}
+
+/// The candidates of the word before `pos`: the subcommands for the first word, and the
+/// session ids for the others except the flags.
+fn complete_word(
+    line: &str,
+    pos: usize,
+    commands: &[String],
+    ssn_ids: impl FnOnce() -> Vec<String>,
+) -> (usize, Vec<String>) {
+    let start = line[..pos]
+        .rfind(char::is_whitespace)
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let word = &line[start..pos];
+
+    let words = if line[..start].trim().is_empty() {
+        commands.to_vec()
+    } else if word.starts_with('-') {
+        vec![]
+    } else {
+        ssn_ids()
+    };
+
+    (
+        start,
+        words.into_iter().filter(|w| w.starts_with(word)).collect(),
+    )
+}
+
+impl Completer for Completion {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete_word(line, pos, &self.commands, || self.ssn_ids()))
+    }
+}
+
+impl Hinter for Completion {
+    type Hint = String;
+}
+
+impl Highlighter for Completion {}
+
+impl Validator for Completion {}
+
+impl Helper for Completion {}
+
+/// The lines of the commands, from the terminal with the history and the completion, or from
+/// a script, e.g. piped to stdin.
+enum Input {
+    Terminal(Box<Editor<Completion, DefaultHistory>>),
+    Script(Box<dyn BufRead>),
+}
+
+impl Input {
+    /// The next line, or none at the end of the input.
+    fn read_line(&mut self) -> Result<Option<String>, Box<dyn Error>> {
+        match self {
+            Input::Terminal(editor) => loop {
+                // The editor blocks the thread, and the completion lists the sessions by the
+                // runtime meanwhile.
+                match tokio::task::block_in_place(|| editor.readline(PROMPT)) {
+                    Ok(line) => {
+                        editor.add_history_entry(line.as_str())?;
+                        return Ok(Some(line));
+                    }
+                    // Ctrl-C clears the line, and Ctrl-D exits the shell.
+                    Err(ReadlineError::Interrupted) => continue,
+                    Err(ReadlineError::Eof) => return Ok(None),
+                    Err(e) => return Err(e.into()),
+                }
+            },
+            Input::Script(reader) => {
+                let mut line = String::new();
+                match reader.read_line(&mut line)? {
+                    0 => Ok(None),
+                    _ => Ok(Some(line)),
+                }
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        if let Some(completion) = match self {
+            Input::Terminal(editor) => editor.helper_mut(),
+            Input::Script(_) => None,
+        } {
+            completion.reset();
+        }
+    }
+}
+
+/// Runs the commands of flmctl read from the terminal, or from stdin if it's not a terminal;
+/// it returns the exit code of the last command.
+pub async fn run(ctx: &FlameContext, cli: &Cli) -> Result<i32, Box<dyn Error>> {
+    if !io::stdin().is_terminal() {
+        let mut input = Input::Script(Box::new(BufReader::new(io::stdin())));
+        return repl(ctx, cli, &mut input).await;
+    }
+
+    let history =
+        Path::new(&FlameContext::conf_path(cli.flame_conf.clone())).with_file_name(HISTORY_FILE);
+    let mut editor = Editor::new()?;
+    editor.set_helper(Some(Completion::new(ctx)));
+    if let Err(e) = editor.load_history(&history) {
+        log::debug!("Failed to load the history <{}>: {}", history.display(), e);
+    }
+
+    let mut input = Input::Terminal(Box::new(editor));
+    let code = repl(ctx, cli, &mut input).await;
+    if let Input::Terminal(editor) = &mut input {
+        if let Err(e) = editor.save_history(&history) {
+            log::warn!("Failed to save the history <{}>: {}", history.display(), e);
+        }
+    }
+
+    code
+}
+
+/// Runs the lines of the input as the arguments of flmctl until the end of the input or
+/// `exit`; the errors of the commands are printed instead of exiting the shell.
+async fn repl(ctx: &FlameContext, shell: &Cli, input: &mut Input) -> Result<i32, Box<dyn Error>> {
+    let mut code = 0;
+
+    while let Some(line) = input.read_line()? {
+        let Some(words) = shlex::split(&line) else {
+            eprintln!("Error: unbalanced quotes in <{}>", line.trim());
+            code = 2;
+            continue;
+        };
+        match words.first().map(String::as_str) {
+            None => continue,
+            Some("exit" | "quit") => break,
+            Some(_) => {}
+        }
+
+        let cli = match Cli::try_parse_from(std::iter::once("flmctl".to_string()).chain(words)) {
+            Ok(cli) => cli,
+            Err(e) => {
+                // The help is also printed by the errors of clap.
+                e.print()?;
+                code = e.exit_code();
+                continue;
+            }
+        };
+
+        // Ctrl-C cancels the command by dropping it, instead of exiting the shell.
+        code = tokio::select! {
+            res = execute(ctx, shell, &cli) => res.unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                1
+            }),
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("Cancelled.");
+                EXIT_CANCELLED
+            }
+        };
+        input.reset();
+    }
+
+    Ok(code)
+}
+
+/// Runs the command of a line, with the flags of the shell unless the line overrides them.
+async fn execute(ctx: &FlameContext, shell: &Cli, cli: &Cli) -> Result<i32, Box<dyn Error>> {
+    let Some(command) = &cli.command else {
+        return Ok(0);
+    };
+
+    let flame_conf = cli.flame_conf.clone().or(shell.flame_conf.clone());
+    let ctx = match (&cli.flame_conf, &cli.namespace) {
+        (None, None) => ctx.clone(),
+        _ => crate::context(
+            &flame_conf,
+            &cli.namespace.clone().or(shell.namespace.clone()),
+        )?,
+    };
+
+    crate::execute(&ctx, &flame_conf, command).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use flame_client::testkit::MockServer;
+    use flame_client::SessionState;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_script() -> Result<(), Box<dyn Error>> {
+        let server = MockServer::start().await?;
+        let ctx = FlameContext {
+            endpoint: server.endpoint().to_string(),
+            ..Default::default()
+        };
+        let shell = Cli::try_parse_from(["flmctl", "shell"])?;
+
+        let script = "create --app flmexec --slots 1\n\
+                      \n\
+                      list --output json\n\
+                      view --session 404\n\
+                      bogus\n\
+                      cancel --session 1\n";
+        let mut input = Input::Script(Box::new(Cursor::new(script)));
+        assert_eq!(repl(&ctx, &shell, &mut input).await?, 0);
+
+        let ssn_list = server.connect().await?.list_session().await?;
+        assert_eq!(ssn_list.len(), 1);
+        assert_eq!(ssn_list[0].state, SessionState::Closed);
+
+        // The shell stops at `exit` with the exit code of the last command.
+        let script = "view --session 404\nexit\ncreate --app flmexec --slots 1\n";
+        let mut input = Input::Script(Box::new(Cursor::new(script)));
+        assert_eq!(repl(&ctx, &shell, &mut input).await?, 1);
+        assert_eq!(server.connect().await?.list_session().await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_complete_word() {
+        let commands = vec!["list".to_string(), "view".to_string(), "watch".to_string()];
+        let ssn_ids = || vec!["12".to_string(), "13".to_string(), "2".to_string()];
+
+        assert_eq!(
+            complete_word("w", 1, &commands, ssn_ids),
+            (0, vec!["watch".to_string()])
+        );
+        assert_eq!(
+            complete_word("view --session 1", 16, &commands, ssn_ids),
+            (15, vec!["12".to_string(), "13".to_string()])
+        );
+        assert_eq!(
+            complete_word("view --s", 8, &commands, || unreachable!()),
+            (5, vec![])
+        );
+    }
+}
@@ -0,0 +1,412 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Write};
+use std::time::Duration;
+
+use common::apis::TaskGID;
+use common::capability;
+use common::ctx::FlameContext;
+use flame_client::{Connection, FailureReason, FlameError, Session, Task, TaskState};
+
+use crate::helper;
+use crate::output::{self, OutputFormat};
+use crate::watch::{EXIT_FAILED, EXIT_SUCCEED, EXIT_TIMEOUT};
+
+/// The task was aborted, e.g. its session was deleted.
+pub const EXIT_ABORTED: i32 = 4;
+/// The task was not completed before its session expired.
+pub const EXIT_EXPIRED: i32 = 5;
+
+pub async fn get(
+    ctx: &FlameContext,
+    gid: &TaskGID,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let task = get_task(ctx, gid).await?;
+
+    print!("{}", output::render_one(&task, format)?);
+
+    Ok(())
+}
+
+pub async fn output(
+    ctx: &FlameContext,
+    gid: &TaskGID,
+    file: &Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    let task = get_task(ctx, gid).await?;
+    let data = task.output.unwrap_or_default();
+
+    match file {
+        Some(path) => write_output(&data, &mut File::create(path)?, false)?,
+        None => {
+            let mut stdout = io::stdout().lock();
+            let is_tty = stdout.is_terminal();
+            write_output(&data, &mut stdout, is_tty)?
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists the tasks of the session in the order of their indexes; the failed ones are the
+/// dead letters to resubmit.
+pub async fn list(
+    ctx: &FlameContext,
+    ssn_id: &str,
+    state: Option<TaskState>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let conn = connect(ctx).await?;
+    let ssn = conn.get_session(&ssn_id.to_string()).await?;
+    let tasks = match state {
+        Some(state) => ssn.list_tasks_by_state(state).await?,
+        None => ssn.list_tasks().await?,
+    };
+
+    print!("{}", output::render_list(&tasks, format)?);
+
+    Ok(())
+}
+
+pub async fn resubmit(ctx: &FlameContext, gid: &TaskGID) -> Result<(), Box<dyn Error>> {
+    let conn = connect(ctx).await?;
+    let task = conn
+        .get_session(&gid.ssn_id.to_string())
+        .await?
+        .resubmit_task(gid.task_id.to_string())
+        .await
+        .map_err(|e| not_found(gid, e))?;
+
+    println!(
+        "Task <{}> is resubmitted as <{}/{}>.",
+        gid, task.ssn_id, task.id
+    );
+
+    Ok(())
+}
+
+/// Runs a task with the input of the file in the session, and writes its output to stdout
+/// as raw bytes; the failure of the task is written to stderr.
+pub async fn run(
+    ctx: &FlameContext,
+    ssn_id: &str,
+    input_file: &str,
+    timeout: &Option<Duration>,
+) -> Result<i32, Box<dyn Error>> {
+    let input = fs::read(input_file)?;
+    let conn = helper::connect(ctx).await?;
+    let ssn = conn.get_session(&ssn_id.to_string()).await?;
+
+    let mut stdout = io::stdout().lock();
+    let is_tty = stdout.is_terminal();
+    let code = run_task(&ssn, input, *timeout, &mut stdout, is_tty).await?;
+
+    Ok(code)
+}
+
+async fn run_task(
+    ssn: &Session,
+    input: Vec<u8>,
+    timeout: Option<Duration>,
+    w: &mut dyn Write,
+    is_tty: bool,
+) -> Result<i32, FlameError> {
+    let task = helper::create_task(ssn, input.into(), None).await?;
+    let id = task.id.clone();
+
+    let wait = ssn.wait_task(task);
+    let task = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, wait).await {
+            Ok(task) => task?,
+            Err(_) => {
+                eprintln!(
+                    "Timed out after {}s waiting for task <{}/{}>.",
+                    timeout.as_secs(),
+                    ssn.id,
+                    id
+                );
+                return Ok(EXIT_TIMEOUT);
+            }
+        },
+        None => wait.await?,
+    };
+
+    let code = exit_code(&task);
+    let output = task.output.unwrap_or_default();
+    if code == EXIT_SUCCEED {
+        write_output(&output, w, is_tty)?;
+        return Ok(code);
+    }
+
+    // The output of a failed task is taken as its failure message, e.g. by an older server.
+    let message = match task.failure {
+        Some(failure) if !failure.message.is_empty() => failure.message,
+        _ => String::from_utf8_lossy(&output).to_string(),
+    };
+    eprintln!("Task <{}/{}> failed: {}", task.ssn_id, task.id, message);
+
+    Ok(code)
+}
+
+/// The exit code of `flmctl task run` for the completed task.
+fn exit_code(task: &Task) -> i32 {
+    if task.state == TaskState::Succeed {
+        return EXIT_SUCCEED;
+    }
+
+    match task.failure.as_ref().map(|f| f.reason) {
+        Some(FailureReason::Aborted) => EXIT_ABORTED,
+        Some(FailureReason::Timeout) => EXIT_EXPIRED,
+        _ => EXIT_FAILED,
+    }
+}
+
+pub async fn resubmit_failed(ctx: &FlameContext, ssn_id: &str) -> Result<(), Box<dyn Error>> {
+    let conn = connect(ctx).await?;
+    let tasks = conn
+        .get_session(&ssn_id.to_string())
+        .await?
+        .resubmit_failed_tasks()
+        .await?;
+
+    for task in &tasks {
+        println!(
+            "Task <{}/{}> is resubmitted as <{}/{}>.",
+            task.ssn_id,
+            task.original_task_id.as_deref().unwrap_or_default(),
+            task.ssn_id,
+            task.id
+        );
+    }
+    println!("{} failed tasks are resubmitted.", tasks.len());
+
+    Ok(())
+}
+
+/// Connects to the session manager, which has to support resubmitting the tasks.
+async fn connect(ctx: &FlameContext) -> Result<Connection, Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+    if !conn.supports(capability::TASK_RESUBMIT) {
+        return Err(
+            "the Flame server does not support resubmitting tasks, please upgrade the session manager"
+                .into(),
+        );
+    }
+
+    Ok(conn)
+}
+
+pub fn parse_state(s: &str) -> Result<TaskState, String> {
+    match s.to_lowercase().as_str() {
+        "pending" => Ok(TaskState::Pending),
+        "running" => Ok(TaskState::Running),
+        "succeed" => Ok(TaskState::Succeed),
+        "failed" => Ok(TaskState::Failed),
+        _ => Err(format!(
+            "invalid task state <{}>, expect Pending, Running, Succeed or Failed",
+            s
+        )),
+    }
+}
+
+async fn get_task(ctx: &FlameContext, gid: &TaskGID) -> Result<Task, FlameError> {
+    let conn = helper::connect(ctx).await?;
+    let task = conn
+        .get_session(&gid.ssn_id.to_string())
+        .await?
+        .get_task(gid.task_id.to_string())
+        .await;
+
+    task.map_err(|e| not_found(gid, e))
+}
+
+fn not_found(gid: &TaskGID, e: FlameError) -> FlameError {
+    match e {
+        FlameError::NotFound(_) => FlameError::NotFound(format!("task <{}>", gid)),
+        _ => e,
+    }
+}
+
+/// Writes the task output as raw bytes; binary data is not dumped to a terminal.
+fn write_output(data: &[u8], w: &mut dyn Write, is_tty: bool) -> Result<(), FlameError> {
+    if is_tty && std::str::from_utf8(data).is_err() {
+        return Err(FlameError::InvalidArgument(
+            "the task output is binary, use -f to save it into a file".to_string(),
+        ));
+    }
+
+    w.write_all(data)
+        .and_then(|_| w.flush())
+        .map_err(|e| FlameError::Internal(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use flame_client::testkit::MockServer;
+    use flame_client::{SessionAttributes, TaskFailure};
+
+    use super::*;
+
+    async fn open_session(server: &MockServer) -> Result<Session, FlameError> {
+        let attr = SessionAttributes {
+            application: "flmexec".to_string(),
+            slots: 1,
+            ..Default::default()
+        };
+
+        server.connect().await?.create_session(&attr).await
+    }
+
+    #[tokio::test]
+    async fn test_run_task() -> Result<(), FlameError> {
+        let server = MockServer::start().await?;
+        let ssn = open_session(&server).await?;
+
+        let output = vec![0x00, 0xff, 0x10, 0x80];
+        let mut buf = Vec::new();
+        let (code, _) = tokio::try_join!(
+            run_task(&ssn, b"input".to_vec(), None, &mut buf, false),
+            server.complete_next_task(output.clone())
+        )?;
+        assert_eq!(code, EXIT_SUCCEED);
+        assert_eq!(buf, output);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_failed_task() -> Result<(), FlameError> {
+        let server = MockServer::start().await?;
+        let ssn = open_session(&server).await?;
+
+        let mut buf = Vec::new();
+        let (code, _) = tokio::try_join!(
+            run_task(&ssn, b"input".to_vec(), None, &mut buf, false),
+            server.fail_next_task("division by zero")
+        )?;
+        assert_eq!(code, EXIT_FAILED);
+        assert!(buf.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_task_timeout() -> Result<(), FlameError> {
+        let server = MockServer::start().await?;
+        let ssn = open_session(&server).await?;
+
+        let mut buf = Vec::new();
+        let timeout = Some(Duration::from_millis(100));
+        let code = run_task(&ssn, b"input".to_vec(), timeout, &mut buf, false).await?;
+        assert_eq!(code, EXIT_TIMEOUT);
+        assert!(buf.is_empty());
+        // The task is still there, only the wait is given up.
+        assert_eq!(server.pending_tasks(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let mut task = Task {
+            id: "1".to_string(),
+            ssn_id: "1".to_string(),
+            index: 0,
+            input: None,
+            output: None,
+            state: TaskState::Failed,
+            progress: None,
+            failure: None,
+            executor_id: None,
+            hostname: None,
+            original_task_id: None,
+            idempotency_key: None,
+            locality: None,
+            env: Default::default(),
+            annotations: Default::default(),
+            output_chunks: 0,
+            creation_time: chrono::Utc::now(),
+            completion_time: None,
+        };
+        assert_eq!(exit_code(&task), EXIT_FAILED);
+
+        for (reason, code) in [
+            (FailureReason::Aborted, EXIT_ABORTED),
+            (FailureReason::Timeout, EXIT_EXPIRED),
+            (FailureReason::ShimError, EXIT_FAILED),
+        ] {
+            task.failure = Some(TaskFailure {
+                reason,
+                message: String::new(),
+            });
+            assert_eq!(exit_code(&task), code);
+        }
+
+        task.state = TaskState::Succeed;
+        assert_eq!(exit_code(&task), EXIT_SUCCEED);
+    }
+
+    #[test]
+    fn test_parse_task_gid() {
+        let gid = "12/3".parse::<TaskGID>().unwrap();
+        assert_eq!(gid.ssn_id, 12);
+        assert_eq!(gid.task_id, 3);
+        assert_eq!(gid.to_string(), "12/3");
+
+        assert!("12".parse::<TaskGID>().is_err());
+        assert!("12/".parse::<TaskGID>().is_err());
+        assert!("a/3".parse::<TaskGID>().is_err());
+    }
+
+    #[test]
+    fn test_write_binary_output() {
+        let data = vec![0x00, 0xff, 0x10, 0x80, b'\n'];
+
+        let mut buf = Vec::new();
+        write_output(&data, &mut buf, false).unwrap();
+        assert_eq!(buf, data);
+
+        let mut buf = Vec::new();
+        assert!(write_output(&data, &mut buf, true).is_err());
+        assert!(buf.is_empty());
+
+        let mut buf = Vec::new();
+        write_output(b"pi=3.14\n", &mut buf, true).unwrap();
+        assert_eq!(buf, b"pi=3.14\n");
+    }
+
+    #[test]
+    fn test_parse_state() {
+        assert_eq!(parse_state("Failed"), Ok(TaskState::Failed));
+        assert_eq!(parse_state("pending"), Ok(TaskState::Pending));
+        assert!(parse_state("aborted").is_err());
+    }
+
+    #[test]
+    fn test_missing_task() {
+        let gid = TaskGID {
+            ssn_id: 1,
+            task_id: 7,
+        };
+
+        let err = not_found(&gid, FlameError::NotFound("7".to_string()));
+        assert_eq!(err.to_string(), "'task <1/7>' not found");
+
+        let err = not_found(&gid, FlameError::Internal("storage".to_string()));
+        assert!(matches!(err, FlameError::Internal(_)));
+    }
+}
@@ -0,0 +1,86 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+
+use common::ctx::FlameContext;
+use flame_client::{capability, SessionTemplate};
+
+use crate::helper;
+use crate::output::{self, OutputFormat, TableRow};
+
+impl TableRow for SessionTemplate {
+    fn headers() -> Vec<&'static str> {
+        vec!["Name", "App", "Slots", "Labels", "Timeout"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        let labels = match self.labels.is_empty() {
+            true => "-".to_string(),
+            false => self
+                .labels
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join(","),
+        };
+
+        vec![
+            self.name.clone(),
+            self.application.clone(),
+            self.slots.map_or("-".to_string(), |s| s.to_string()),
+            labels,
+            self.timeout.map_or("-".to_string(), |t| {
+                humantime::format_duration(t).to_string()
+            }),
+        ]
+    }
+}
+
+pub async fn list(ctx: &FlameContext, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+    if !conn.supports(capability::SESSION_TEMPLATES) {
+        return Err(
+            "the Flame server does not support session templates, please upgrade it".into(),
+        );
+    }
+
+    let templates = conn.list_session_templates().await?;
+    print!("{}", output::render_list(&templates, format)?);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_template_row() {
+        let template = SessionTemplate {
+            name: "nightly-batch".to_string(),
+            application: "flmexec".to_string(),
+            slots: Some(2),
+            labels: BTreeMap::from([("tier".to_string(), "batch".to_string())]),
+            max_pending_tasks: None,
+            timeout: Some(Duration::from_secs(8 * 3600)),
+        };
+        assert_eq!(
+            template.row(),
+            vec!["nightly-batch", "flmexec", "2", "tier=batch", "8h"]
+        );
+    }
+}
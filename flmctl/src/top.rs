@@ -0,0 +1,464 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::Write;
+use std::time::Duration;
+
+use chrono::Local;
+
+use common::ctx::FlameContext;
+use flame_client::{
+    capability, ClusterStats, Connection, Executor, FlameError, Session, SessionState,
+};
+
+use crate::helper;
+
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct SessionUsage {
+    id: String,
+    application: String,
+    executors: i32,
+    slots: i32,
+    pending: i32,
+    running: i32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Stats {
+    executors: BTreeMap<String, i32>,
+    capacity: i32,
+    allocated: i32,
+
+    open_sessions: i32,
+    backlog_sessions: i32,
+    pending: i32,
+
+    /// The health of the queue, if the caller is allowed to get it.
+    cluster: Option<ClusterStats>,
+
+    top: Vec<SessionUsage>,
+}
+
+impl Stats {
+    fn new(ssn_list: &[Session], exe_list: &[Executor], top_n: usize) -> Self {
+        let mut stats = Stats::default();
+        let mut usage: HashMap<&String, (i32, i32)> = HashMap::new();
+
+        for exe in exe_list {
+            *stats.executors.entry(exe.state.to_string()).or_default() += 1;
+            stats.capacity += exe.slots;
+
+            if let Some(ssn_id) = &exe.session_id {
+                stats.allocated += exe.slots;
+                let u = usage.entry(ssn_id).or_default();
+                u.0 += 1;
+                u.1 += exe.slots;
+            }
+        }
+
+        for ssn in ssn_list.iter().filter(|s| s.state == SessionState::Open) {
+            stats.open_sessions += 1;
+            stats.pending += ssn.pending;
+            if ssn.pending > 0 {
+                stats.backlog_sessions += 1;
+            }
+
+            let (executors, slots) = usage.get(&ssn.id).cloned().unwrap_or_default();
+            stats.top.push(SessionUsage {
+                id: ssn.id.clone(),
+                application: ssn.application.clone(),
+                executors,
+                slots,
+                pending: ssn.pending,
+                running: ssn.running,
+            });
+        }
+
+        stats
+            .top
+            .sort_by(|l, r| r.slots.cmp(&l.slots).then(r.pending.cmp(&l.pending)));
+        stats.top.truncate(top_n);
+
+        stats
+    }
+
+    fn with_cluster_stats(self, cluster: Option<ClusterStats>) -> Self {
+        Stats { cluster, ..self }
+    }
+
+    fn render(&self) -> String {
+        let mut res = String::new();
+
+        let executors = self
+            .executors
+            .iter()
+            .map(|(k, v)| format!("{} {}", v, k))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let total: i32 = self.executors.values().sum();
+
+        let _ = writeln!(res, "Executors: {} total, {}", total, executors);
+        let _ = writeln!(
+            res,
+            "Slots:     {}/{} allocated",
+            self.allocated, self.capacity
+        );
+        let _ = writeln!(
+            res,
+            "Sessions:  {} open, {} with backlog, {} pending tasks",
+            self.open_sessions, self.backlog_sessions, self.pending
+        );
+        if let Some(cluster) = &self.cluster {
+            let queue = cluster
+                .applications
+                .iter()
+                .filter(|app| app.pending > 0)
+                .map(|app| {
+                    format!(
+                        "{} {} pending (oldest {}s)",
+                        app.name,
+                        app.pending,
+                        app.oldest_pending_age.as_secs()
+                    )
+                })
+                .collect::<Vec<_>>();
+            let _ = writeln!(
+                res,
+                "Queue:     {}",
+                match queue.is_empty() {
+                    true => "empty".to_string(),
+                    false => queue.join(", "),
+                }
+            );
+            if let Some(mean) = cluster.bind_latency.mean() {
+                let _ = writeln!(
+                    res,
+                    "Binding:   {} sessions bound, {:.1}s on average",
+                    cluster.bind_latency.count,
+                    mean.as_secs_f64()
+                );
+            }
+            if cluster.quarantined_executors > 0 {
+                let _ = writeln!(
+                    res,
+                    "Failing:   {} executors quarantined",
+                    cluster.quarantined_executors
+                );
+            }
+            if cluster.slow_locks + cluster.slow_engine_calls > 0 {
+                let _ = writeln!(
+                    res,
+                    "Slow:      {} lock acquisitions, {} engine calls",
+                    cluster.slow_locks, cluster.slow_engine_calls
+                );
+            }
+            if cluster.throttled_calls > 0 {
+                let _ = writeln!(res, "Throttled: {} Frontend calls", cluster.throttled_calls);
+            }
+            if cluster.loop_restarts > 0 || cluster.failed_loops > 0 {
+                let _ = writeln!(
+                    res,
+                    "Loops:     {} restarts, {} failed",
+                    cluster.loop_restarts, cluster.failed_loops
+                );
+            }
+            if cluster.affinity_hits + cluster.affinity_misses > 0 {
+                let _ = writeln!(
+                    res,
+                    "Affinity:  {} hits, {} misses",
+                    cluster.affinity_hits, cluster.affinity_misses
+                );
+            }
+            if cluster.locality_hits + cluster.locality_misses > 0 {
+                let _ = writeln!(
+                    res,
+                    "Locality:  {} hits, {} misses",
+                    cluster.locality_hits, cluster.locality_misses
+                );
+            }
+            for quota in &cluster.quotas {
+                let mut usage = vec![];
+                if let Some(max) = quota.max_slots {
+                    usage.push(format!("{}/{} slots", quota.slots, max));
+                }
+                if let Some(max) = quota.max_running_tasks {
+                    usage.push(format!("{}/{} running tasks", quota.running_tasks, max));
+                }
+                let _ = writeln!(res, "Quota:     {} {}", quota.name(), usage.join(", "));
+            }
+        }
+        let _ = writeln!(res);
+        let _ = writeln!(
+            res,
+            "{:<10}{:<15}{:<10}{:<10}{:<10}{:<10}",
+            "ID", "App", "Executors", "Slots", "Pending", "Running"
+        );
+        for u in &self.top {
+            let _ = writeln!(
+                res,
+                "{:<10}{:<15}{:<10}{:<10}{:<10}{:<10}",
+                u.id, u.application, u.executors, u.slots, u.pending, u.running
+            );
+        }
+
+        res
+    }
+}
+
+/// Fetches the open sessions and the executors a page at a time, so a refresh of a cluster
+/// with thousands of closed sessions doesn't list them all.
+async fn fetch(conn: &Connection, top_n: usize) -> Result<Stats, FlameError> {
+    let ssn_list = conn.list_session_by_state(SessionState::Open).await?;
+    let exe_list = conn.list_executor().await?;
+    // The health of the queue is only for the callers of all namespaces.
+    let cluster = match conn.supports(capability::CLUSTER_STATS) {
+        true => conn.get_cluster_stats().await.ok(),
+        false => None,
+    };
+
+    Ok(Stats::new(&ssn_list, &exe_list, top_n).with_cluster_stats(cluster))
+}
+
+pub async fn run(
+    ctx: &FlameContext,
+    interval: Duration,
+    top_n: usize,
+    once: bool,
+) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+
+    loop {
+        let stats = fetch(&conn, top_n).await?;
+
+        if once {
+            print!("{}", stats.render());
+            return Ok(());
+        }
+
+        print!(
+            "{}flmctl top - {}\n\n{}",
+            CLEAR_SCREEN,
+            Local::now().format("%T"),
+            stats.render()
+        );
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flame_client::testkit::MockServer;
+    use flame_client::{QuotaStats, SessionAttributes};
+
+    #[test]
+    fn test_stats() {
+        let ssn_list: Vec<Session> =
+            serde_json::from_str(include_str!("../tests/golden/list.json")).unwrap();
+        let exe_list: Vec<Executor> = serde_json::from_str(
+            r#"[
+                {"id": "e1", "slots": 1, "applications": [], "labels": {}, "state": "bound",
+                 "session_id": "1", "task_id": "3", "creation_time": "2024-03-01T08:00:00Z",
+                 "last_heartbeat": "2024-03-01T08:30:00Z", "draining": false},
+                {"id": "e2", "slots": 2, "applications": [], "labels": {}, "state": "idle",
+                 "session_id": null, "task_id": null, "creation_time": "2024-03-01T08:00:00Z",
+                 "last_heartbeat": "2024-03-01T08:30:00Z", "draining": false}
+            ]"#,
+        )
+        .unwrap();
+
+        let stats = Stats::new(&ssn_list, &exe_list, 10);
+        assert_eq!(stats.capacity, 3);
+        assert_eq!(stats.allocated, 1);
+        assert_eq!(stats.open_sessions, 1);
+        assert_eq!(stats.backlog_sessions, 1);
+        assert_eq!(stats.pending, 2);
+        assert_eq!(
+            stats.top,
+            vec![SessionUsage {
+                id: "1".to_string(),
+                application: "flmping".to_string(),
+                executors: 1,
+                slots: 1,
+                pending: 2,
+                running: 1,
+            }]
+        );
+
+        let res = stats.render();
+        assert!(res.starts_with("Executors: 2 total, 1 Bound, 1 Idle\n"));
+        assert!(res.contains("Slots:     1/3 allocated\n"));
+        assert!(!res.contains("Queue:"));
+
+        let cluster: ClusterStats = serde_json::from_str(
+            r#"{"update_time": "2024-03-01T08:30:00Z", "idle_executors": 1, "bound_executors": 1,
+                "applications": [
+                    {"name": "flmping", "pending": 2, "oldest_pending_age": {"secs": 42, "nanos": 0}}
+                ],
+                "bind_latency": {"bounds": [1.0], "counts": [1, 1], "sum": 3.0, "count": 2}}"#,
+        )
+        .unwrap();
+        let res = stats
+            .clone()
+            .with_cluster_stats(Some(cluster.clone()))
+            .render();
+        assert!(res.contains("Queue:     flmping 2 pending (oldest 42s)\n"));
+        assert!(res.contains("Binding:   2 sessions bound, 1.5s on average\n"));
+        assert!(!res.contains("Failing:"));
+
+        let cluster = ClusterStats {
+            quarantined_executors: 1,
+            ..cluster
+        };
+        let res = stats
+            .clone()
+            .with_cluster_stats(Some(cluster.clone()))
+            .render();
+        assert!(res.contains("Failing:   1 executors quarantined\n"));
+        assert!(!res.contains("Slow:"));
+
+        let cluster = ClusterStats {
+            slow_locks: 2,
+            slow_engine_calls: 1,
+            ..cluster
+        };
+        let res = stats
+            .clone()
+            .with_cluster_stats(Some(cluster.clone()))
+            .render();
+        assert!(res.contains("Slow:      2 lock acquisitions, 1 engine calls\n"));
+        assert!(!res.contains("Throttled:"));
+
+        let cluster = ClusterStats {
+            throttled_calls: 3,
+            ..cluster
+        };
+        let res = stats
+            .clone()
+            .with_cluster_stats(Some(cluster.clone()))
+            .render();
+        assert!(res.contains("Throttled: 3 Frontend calls\n"));
+        assert!(!res.contains("Loops:"));
+
+        let cluster = ClusterStats {
+            loop_restarts: 2,
+            failed_loops: 1,
+            ..cluster
+        };
+        let res = stats
+            .clone()
+            .with_cluster_stats(Some(cluster.clone()))
+            .render();
+        assert!(res.contains("Loops:     2 restarts, 1 failed\n"));
+        assert!(!res.contains("Affinity:"));
+
+        let cluster = ClusterStats {
+            affinity_hits: 5,
+            affinity_misses: 2,
+            ..cluster
+        };
+        let res = stats
+            .clone()
+            .with_cluster_stats(Some(cluster.clone()))
+            .render();
+        assert!(res.contains("Affinity:  5 hits, 2 misses\n"));
+        assert!(!res.contains("Locality:"));
+
+        let cluster = ClusterStats {
+            locality_hits: 9,
+            locality_misses: 1,
+            ..cluster
+        };
+        let res = stats
+            .clone()
+            .with_cluster_stats(Some(cluster.clone()))
+            .render();
+        assert!(res.contains("Locality:  9 hits, 1 misses\n"));
+        assert!(!res.contains("Quota:"));
+
+        let cluster = ClusterStats {
+            quotas: vec![
+                QuotaStats {
+                    namespace: Some("research".to_string()),
+                    max_slots: Some(40),
+                    slots: 12,
+                    ..Default::default()
+                },
+                QuotaStats {
+                    application: Some("pi".to_string()),
+                    max_slots: Some(8),
+                    slots: 8,
+                    max_running_tasks: Some(4),
+                    running_tasks: 3,
+                    ..Default::default()
+                },
+            ],
+            ..cluster
+        };
+        let res = stats.with_cluster_stats(Some(cluster)).render();
+        assert!(res.contains("Quota:     namespace=research 12/40 slots\n"));
+        assert!(res.contains("Quota:     application=pi 8/8 slots, 3/4 running tasks\n"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch() -> Result<(), FlameError> {
+        let server = MockServer::start().await?;
+        let conn = server.connect().await?;
+        assert!(conn.supports(capability::LIST_PAGES));
+
+        let attr = SessionAttributes {
+            application: "flmexec".to_string(),
+            slots: 1,
+            ..Default::default()
+        };
+        let mut ssn_list = vec![];
+        for pending in 0..3 {
+            let ssn = conn.create_session(&attr).await?;
+            for _ in 0..pending {
+                ssn.create_task(None).await?;
+            }
+            ssn_list.push(ssn);
+        }
+        // The closed sessions are filtered by the session manager.
+        ssn_list[0].close().await?;
+        assert_eq!(
+            conn.list_session_by_state(SessionState::Closed)
+                .await?
+                .iter()
+                .map(|ssn| ssn.id.clone())
+                .collect::<Vec<_>>(),
+            vec![ssn_list[0].id.clone()]
+        );
+
+        let stats = fetch(&conn, 10).await?;
+        assert_eq!(stats.open_sessions, 2);
+        assert_eq!(stats.backlog_sessions, 2);
+        assert_eq!(stats.pending, 3);
+        assert_eq!(
+            stats.top.iter().map(|u| u.id.clone()).collect::<Vec<_>>(),
+            vec![ssn_list[2].id.clone(), ssn_list[1].id.clone()]
+        );
+        assert!(stats.executors.is_empty());
+        assert!(stats.cluster.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+
+use common::ctx::FlameContext;
+use flame_client::ServerInfo;
+
+use crate::helper;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn major(version: &str) -> Option<&str> {
+    version.split('.').next().filter(|v| !v.is_empty())
+}
+
+/// Whether the major versions of the client and the server are different; the server of an
+/// unknown version is not reported.
+fn is_mismatched(client: &str, server: &str) -> bool {
+    match (major(client), major(server)) {
+        (Some(c), Some(s)) => c != s,
+        _ => false,
+    }
+}
+
+fn render(server: &ServerInfo) -> String {
+    let (version, capabilities) = match server.version.is_empty() {
+        true => ("unknown".to_string(), "-".to_string()),
+        false => (
+            format!("{} ({})", server.version, server.build),
+            server.capabilities.join(", "),
+        ),
+    };
+
+    format!(
+        "Client:       {}\nServer:       {}\nCapabilities: {}\n",
+        VERSION, version, capabilities
+    )
+}
+
+pub async fn run(ctx: &FlameContext) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+    let server = conn.server_info();
+
+    print!("{}", render(server));
+    if is_mismatched(VERSION, &server.version) {
+        eprintln!(
+            "Warning: the major versions of flmctl <{}> and the Flame server <{}> are different.",
+            VERSION, server.version
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        assert!(!is_mismatched("0.3.0", "0.4.1"));
+        assert!(is_mismatched("0.3.0", "1.0.0"));
+        assert!(!is_mismatched("0.3.0", ""));
+
+        let server = ServerInfo {
+            version: "0.3.0".to_string(),
+            build: "release".to_string(),
+            capabilities: vec!["watch-session".to_string(), "admin".to_string()],
+        };
+        assert_eq!(
+            render(&server),
+            format!(
+                "Client:       {}\nServer:       0.3.0 (release)\nCapabilities: watch-session, admin\n",
+                VERSION
+            )
+        );
+        assert!(render(&ServerInfo::default()).contains("Server:       unknown\n"));
+    }
+}
@@ -13,8 +13,154 @@ limitations under the License.
 
 use std::error::Error;
 
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
 use common::ctx::FlameContext;
+use flame_client::{SessionEvent, SessionHistoryEvent};
+
+use crate::helper;
+use crate::output::{self, OutputFormat, TableRow};
+
+/// An event in the timeline of the session printed by `flmctl view --events`.
+#[derive(Clone, Debug, Serialize)]
+struct TimelineRow {
+    time: DateTime<Utc>,
+    sequence: u64,
+    event: &'static str,
+    detail: String,
+}
+
+impl From<&SessionHistoryEvent> for TimelineRow {
+    fn from(e: &SessionHistoryEvent) -> Self {
+        let (sequence, event, detail) = match &e.event {
+            SessionEvent::TaskStateChanged {
+                sequence,
+                task_id,
+                state,
+            } => (
+                *sequence,
+                "TaskStateChanged",
+                format!("{}: {}", task_id, state),
+            ),
+            SessionEvent::TaskProgress {
+                sequence,
+                task_id,
+                progress,
+            } => (
+                *sequence,
+                "TaskProgress",
+                format!("{}: {}%", task_id, progress.percentage),
+            ),
+            SessionEvent::SessionExpired { sequence, deadline } => (
+                *sequence,
+                "SessionExpired",
+                format!("deadline {}", deadline.format("%T")),
+            ),
+            SessionEvent::SessionOrphaned {
+                sequence,
+                last_activity,
+            } => (
+                *sequence,
+                "SessionOrphaned",
+                format!("last activity {}", last_activity.format("%T")),
+            ),
+            SessionEvent::ExecutorQuarantined {
+                sequence,
+                executor_id,
+                task_id,
+            } => (
+                *sequence,
+                "ExecutorQuarantined",
+                format!("{} after {}", executor_id, task_id),
+            ),
+            SessionEvent::SessionClosed { sequence } => (*sequence, "SessionClosed", String::new()),
+            SessionEvent::Overflow { dropped } => (0, "Overflow", format!("{} dropped", dropped)),
+        };
+
+        TimelineRow {
+            time: e.event_time,
+            sequence,
+            event,
+            detail,
+        }
+    }
+}
+
+impl TableRow for TimelineRow {
+    fn headers() -> Vec<&'static str> {
+        vec!["Time", "Seq", "Event", "Detail"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.time.format("%T").to_string(),
+            self.sequence.to_string(),
+            self.event.to_string(),
+            self.detail.clone(),
+        ]
+    }
+}
+
+pub async fn run(
+    ctx: &FlameContext,
+    ssn_id: &String,
+    events: bool,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+    let ssn = conn.get_session(ssn_id).await?;
+
+    if !events {
+        print!("{}", output::render_one(&ssn, format)?);
+        return Ok(());
+    }
+
+    let timeline: Vec<TimelineRow> = ssn
+        .history(None, None)
+        .await?
+        .iter()
+        .map(Into::into)
+        .collect();
+    print!("{}", output::render_list(&timeline, format)?);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flame_client::TaskState;
+
+    #[test]
+    fn test_render_timeline() {
+        let time = DateTime::parse_from_rfc3339("2024-12-15T08:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let events = [
+            SessionEvent::TaskStateChanged {
+                sequence: 1,
+                task_id: "1".to_string(),
+                state: TaskState::Running,
+            },
+            SessionEvent::SessionClosed { sequence: 2 },
+        ];
+        let timeline: Vec<TimelineRow> = events
+            .into_iter()
+            .map(|event| {
+                TimelineRow::from(&SessionHistoryEvent {
+                    event_time: time,
+                    event,
+                })
+            })
+            .collect();
 
-pub async fn run(_: &FlameContext, _: &String) -> Result<(), Box<dyn Error>> {
-    todo!()
+        assert_eq!(
+            output::render_list(&timeline, OutputFormat::Table).unwrap(),
+            "Time      Seq  Event             Detail\n\
+             08:30:00  1    TaskStateChanged  1: Running\n\
+             08:30:00  2    SessionClosed\n"
+        );
+    }
 }
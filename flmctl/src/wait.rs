@@ -0,0 +1,237 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::error::Error;
+use std::future::Future;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use tokio::time::Instant;
+
+use common::ctx::FlameContext;
+use flame_client::{FlameError, SessionState};
+
+use crate::helper;
+use crate::watch::{Progress, EXIT_CLOSED, EXIT_FAILED, EXIT_SUCCEED, EXIT_TIMEOUT};
+
+pub const EXIT_DELETED: i32 = 3;
+
+const MIN_WAIT_INTERVAL: Duration = Duration::from_millis(500);
+const MAX_WAIT_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum WaitFor {
+    /// All tasks of the session are completed
+    #[default]
+    Completed,
+    /// The session is closed
+    Closed,
+}
+
+impl WaitFor {
+    /// Returns the exit code if the condition holds or can never hold.
+    fn check(&self, progress: &Progress) -> Option<i32> {
+        if progress.state.is_none() {
+            return Some(EXIT_DELETED);
+        }
+
+        let result = || match progress.failed > 0 {
+            true => EXIT_FAILED,
+            false => EXIT_SUCCEED,
+        };
+
+        match self {
+            WaitFor::Completed => match progress.exit_code() {
+                // The session was closed with unfinished tasks, they'll never be completed.
+                Some(EXIT_CLOSED) => Some(EXIT_FAILED),
+                code => code,
+            },
+            WaitFor::Closed if progress.state == Some(SessionState::Closed) => Some(result()),
+            WaitFor::Closed => None,
+        }
+    }
+}
+
+pub async fn run(
+    ctx: &FlameContext,
+    ssn_id: &String,
+    cond: WaitFor,
+    timeout: &Option<Duration>,
+    verbose: bool,
+) -> Result<i32, Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+
+    let fetch = || async {
+        match conn.get_session(ssn_id).await {
+            Ok(ssn) => Ok(Progress::from(&ssn)),
+            Err(FlameError::NotFound(_)) => Ok(Progress::default()),
+            Err(e) => Err(e),
+        }
+    };
+
+    let code = wait_until(fetch, cond, timeout, verbose).await?;
+    if verbose {
+        match code {
+            EXIT_DELETED => eprintln!("Session <{}> was deleted.", ssn_id),
+            EXIT_TIMEOUT => eprintln!("Timed out waiting for session <{}>.", ssn_id),
+            _ => {}
+        }
+    }
+
+    Ok(code)
+}
+
+/// Polls the progress with exponential backoff until the condition holds or timeout.
+async fn wait_until<F, Fut>(
+    fetch: F,
+    cond: WaitFor,
+    timeout: &Option<Duration>,
+    verbose: bool,
+) -> Result<i32, FlameError>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<Progress, FlameError>>,
+{
+    let start_time = Instant::now();
+    let mut interval = MIN_WAIT_INTERVAL;
+
+    loop {
+        let progress = fetch().await?;
+        let elapsed = start_time.elapsed();
+        if verbose {
+            eprintln!("{}", progress.line(elapsed));
+        }
+
+        if let Some(code) = cond.check(&progress) {
+            return Ok(code);
+        }
+
+        let mut sleep = interval;
+        if let Some(timeout) = timeout {
+            if elapsed >= *timeout {
+                return Ok(EXIT_TIMEOUT);
+            }
+            sleep = sleep.min(*timeout - elapsed);
+        }
+
+        tokio::time::sleep(sleep).await;
+        interval = (interval * 2).min(MAX_WAIT_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    fn progress(state: SessionState, pending: i32, succeed: i32, failed: i32) -> Progress {
+        Progress {
+            state: Some(state),
+            pending,
+            running: 0,
+            succeed,
+            failed,
+        }
+    }
+
+    /// Replays the scripted progress, and repeats the last one when the script is over.
+    async fn replay(
+        script: Vec<Progress>,
+        cond: WaitFor,
+        timeout: Option<Duration>,
+    ) -> (i32, usize) {
+        let script = Mutex::new(script.into_iter().rev().collect::<Vec<_>>());
+        let calls = Mutex::new(0);
+
+        let fetch = || async {
+            *calls.lock().unwrap() += 1;
+            let mut script = script.lock().unwrap();
+            match script.len() {
+                1 => Ok(script[0]),
+                _ => Ok(script.pop().unwrap()),
+            }
+        };
+
+        let code = wait_until(fetch, cond, &timeout, false).await.unwrap();
+        let calls = *calls.lock().unwrap();
+        (code, calls)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_wait_for_completed() {
+        let script = vec![
+            progress(SessionState::Open, 3, 0, 0),
+            progress(SessionState::Open, 1, 2, 0),
+            progress(SessionState::Open, 0, 3, 0),
+        ];
+        assert_eq!(
+            replay(script, WaitFor::Completed, None).await,
+            (EXIT_SUCCEED, 3)
+        );
+
+        let script = vec![
+            progress(SessionState::Open, 3, 0, 0),
+            progress(SessionState::Open, 0, 2, 1),
+        ];
+        assert_eq!(
+            replay(script, WaitFor::Completed, None).await,
+            (EXIT_FAILED, 2)
+        );
+
+        let script = vec![progress(SessionState::Closed, 3, 0, 0)];
+        assert_eq!(
+            replay(script, WaitFor::Completed, None).await,
+            (EXIT_FAILED, 1)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_wait_for_closed() {
+        let script = vec![
+            progress(SessionState::Open, 0, 3, 0),
+            progress(SessionState::Closed, 0, 3, 0),
+        ];
+        assert_eq!(
+            replay(script, WaitFor::Closed, None).await,
+            (EXIT_SUCCEED, 2)
+        );
+
+        let script = vec![progress(SessionState::Closed, 0, 2, 1)];
+        assert_eq!(
+            replay(script, WaitFor::Closed, None).await,
+            (EXIT_FAILED, 1)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_wait_deleted() {
+        let script = vec![progress(SessionState::Open, 3, 0, 0), Progress::default()];
+        assert_eq!(
+            replay(script, WaitFor::Completed, None).await,
+            (EXIT_DELETED, 2)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_wait_timeout() {
+        let script = vec![progress(SessionState::Open, 3, 0, 0)];
+        let start_time = Instant::now();
+
+        // Polls at 0s, 0.5s, 1.5s, 3.5s, 7.5s, 15.5s, 31.5s, 61.5s (capped at 30s) and 90s.
+        let (code, calls) = replay(script, WaitFor::Completed, Some(Duration::from_secs(90))).await;
+        assert_eq!(code, EXIT_TIMEOUT);
+        assert_eq!(calls, 9);
+        assert_eq!(start_time.elapsed(), Duration::from_secs(90));
+    }
+}
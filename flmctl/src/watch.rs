@@ -0,0 +1,485 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+
+use common::ctx::FlameContext;
+use flame_client::{
+    Connection, FlameError, Session, SessionEvent, SessionEvents, SessionState, TaskState,
+};
+
+use crate::helper;
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The maximum number of running tasks whose progress is shown.
+const MAX_PROGRESS_TASKS: usize = 4;
+
+pub const EXIT_SUCCEED: i32 = 0;
+pub const EXIT_FAILED: i32 = 1;
+pub const EXIT_CLOSED: i32 = 2;
+pub const EXIT_TIMEOUT: i32 = 124;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub state: Option<SessionState>,
+    pub pending: i32,
+    pub running: i32,
+    pub succeed: i32,
+    pub failed: i32,
+}
+
+impl Progress {
+    pub fn total(&self) -> i32 {
+        self.pending + self.running + self.succeed + self.failed
+    }
+
+    pub fn done(&self) -> i32 {
+        self.succeed + self.failed
+    }
+
+    /// Returns the exit code of `flmctl watch` if the session reached a terminal condition;
+    /// `None` means the session is still in progress.
+    pub fn exit_code(&self) -> Option<i32> {
+        let unfinished = self.pending + self.running > 0;
+
+        match self.state {
+            // The session was deleted while watching it.
+            None => Some(EXIT_CLOSED),
+            Some(SessionState::Closed) if unfinished => Some(EXIT_CLOSED),
+            Some(SessionState::Open) if unfinished || self.total() == 0 => None,
+            _ if self.failed > 0 => Some(EXIT_FAILED),
+            _ => Some(EXIT_SUCCEED),
+        }
+    }
+
+    pub fn line(&self, elapsed: Duration) -> String {
+        format!(
+            "{}/{} done, {} running, {} failed, elapsed {}s",
+            self.done(),
+            self.total(),
+            self.running,
+            self.failed,
+            elapsed.as_secs()
+        )
+    }
+}
+
+/// The progress of the running tasks reported by their executors, e.g. `3: 25%`.
+#[derive(Clone, Debug, Default)]
+pub struct TaskProgress {
+    tasks: HashMap<String, u32>,
+}
+
+impl TaskProgress {
+    pub fn update(&mut self, event: &SessionEvent) {
+        match event {
+            SessionEvent::TaskProgress {
+                task_id, progress, ..
+            } => {
+                self.tasks.insert(task_id.clone(), progress.percentage);
+            }
+            SessionEvent::TaskStateChanged { task_id, state, .. }
+                if *state != TaskState::Running =>
+            {
+                self.tasks.remove(task_id);
+            }
+            _ => {}
+        }
+    }
+
+    /// The progress of the first running tasks ordered by their ids, or empty if no task
+    /// reported its progress.
+    pub fn line(&self) -> String {
+        let mut tasks: Vec<_> = self.tasks.iter().collect();
+        tasks.sort_by_key(|(id, _)| (id.len(), *id));
+
+        let mut items: Vec<_> = tasks
+            .iter()
+            .take(MAX_PROGRESS_TASKS)
+            .map(|(id, percentage)| format!("{}: {}%", id, percentage))
+            .collect();
+        if tasks.len() > MAX_PROGRESS_TASKS {
+            items.push(format!("{} more", tasks.len() - MAX_PROGRESS_TASKS));
+        }
+
+        match items.is_empty() {
+            true => String::new(),
+            false => format!(" [{}]", items.join(", ")),
+        }
+    }
+}
+
+impl From<&Session> for Progress {
+    fn from(ssn: &Session) -> Self {
+        Progress {
+            state: Some(ssn.state),
+            pending: ssn.pending,
+            running: ssn.running,
+            succeed: ssn.succeed,
+            failed: ssn.failed,
+        }
+    }
+}
+
+pub async fn run(
+    ctx: &FlameContext,
+    ssn_id: &String,
+    timeout: &Option<Duration>,
+) -> Result<i32, Box<dyn Error>> {
+    let conn = helper::connect(ctx).await?;
+
+    watch(&conn, ssn_id, *timeout, &mut io::stdout()).await
+}
+
+/// Shows the progress of the session until it's completed, closed or deleted, or the timeout.
+/// The progress is refreshed by the events of the session; it's polled every `WATCH_INTERVAL`
+/// instead if the events are not available.
+pub async fn watch(
+    conn: &Connection,
+    ssn_id: &String,
+    timeout: Option<Duration>,
+    out: &mut impl Write,
+) -> Result<i32, Box<dyn Error>> {
+    let start_time = Instant::now();
+    let ssn = fetch(conn, ssn_id).await?;
+    let mut events = ssn.as_ref().map(Session::events);
+    let mut progress = ssn.as_ref().map(Progress::from).unwrap_or_default();
+    let mut tasks = TaskProgress::default();
+
+    loop {
+        let elapsed = start_time.elapsed();
+        // The line is cleared to its end, as the progress of the tasks may be shorter.
+        write!(out, "\r{}{}\x1b[K", progress.line(elapsed), tasks.line())?;
+        out.flush()?;
+
+        if let Some(code) = progress.exit_code() {
+            writeln!(out)?;
+            if progress.state.is_none() {
+                eprintln!("Session <{}> was deleted before completion.", ssn_id);
+            } else if code == EXIT_CLOSED {
+                eprintln!("Session <{}> was closed before completion.", ssn_id);
+            }
+            return Ok(code);
+        }
+
+        let mut interval = WATCH_INTERVAL;
+        if let Some(timeout) = timeout {
+            if elapsed >= timeout {
+                writeln!(out)?;
+                eprintln!(
+                    "Timed out after {}s waiting for session <{}>.",
+                    timeout.as_secs(),
+                    ssn_id
+                );
+                return Ok(EXIT_TIMEOUT);
+            }
+            interval = interval.min(timeout - elapsed);
+        }
+
+        // The elapsed time is shown every interval, but the session is only got again if it
+        // was changed, or if it's polled.
+        let mut changed = events.is_none();
+        let tick = tokio::time::sleep(interval);
+        tokio::pin!(tick);
+        loop {
+            tokio::select! {
+                _ = &mut tick => break,
+                event = next_event(&mut events) => match event {
+                    Some(Ok(event)) => {
+                        tasks.update(&event);
+                        match event {
+                            SessionEvent::TaskProgress { .. } => {}
+                            SessionEvent::SessionClosed { .. }
+                            | SessionEvent::SessionExpired { .. }
+                            | SessionEvent::SessionOrphaned { .. } => {
+                                changed = true;
+                                break;
+                            }
+                            _ => changed = true,
+                        }
+                    }
+                    // The session is polled from now on, e.g. it was deleted.
+                    _ => {
+                        events = None;
+                        changed = true;
+                        break;
+                    }
+                },
+            }
+        }
+
+        if changed {
+            progress = fetch(conn, ssn_id)
+                .await?
+                .as_ref()
+                .map(Progress::from)
+                .unwrap_or_default();
+        }
+    }
+}
+
+/// The session, or `None` if it's deleted.
+async fn fetch(conn: &Connection, ssn_id: &String) -> Result<Option<Session>, FlameError> {
+    match conn.get_session(ssn_id).await {
+        Ok(ssn) => Ok(Some(ssn)),
+        Err(FlameError::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The next event of the session; it never returns if the events are not available.
+async fn next_event(
+    events: &mut Option<SessionEvents>,
+) -> Option<Result<SessionEvent, FlameError>> {
+    match events {
+        Some(events) => events.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+
+    use flame_client::testkit::MockServer;
+    use flame_client::SessionAttributes;
+
+    use super::*;
+
+    async fn open_session(server: &MockServer) -> Result<Session, FlameError> {
+        let attr = SessionAttributes {
+            application: "flmexec".to_string(),
+            slots: 1,
+            ..Default::default()
+        };
+
+        server.connect().await?.create_session(&attr).await
+    }
+
+    /// Watches the session while the driver changes it, and returns the exit code with the
+    /// last progress line.
+    async fn watch_while(
+        server: &MockServer,
+        ssn: &Session,
+        timeout: Option<Duration>,
+        driver: impl Future<Output = Result<(), FlameError>>,
+    ) -> (i32, String) {
+        let conn = server.connect().await.unwrap();
+        let mut out = vec![];
+        let (code, driven) = tokio::join!(watch(&conn, &ssn.id, timeout, &mut out), driver);
+        driven.unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let line = out.rsplit('\r').next().unwrap_or_default();
+        (
+            code.unwrap(),
+            line.replace("\x1b[K", "").trim_end().to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_watch_succeed() -> Result<(), FlameError> {
+        let server = MockServer::start().await?;
+        let ssn = open_session(&server).await?;
+        for _ in 0..2 {
+            ssn.create_task(None).await?;
+        }
+
+        let driver = async {
+            server.complete_next_task("1").await?;
+            server.complete_next_task("2").await?;
+            Ok(())
+        };
+        let (code, line) = watch_while(&server, &ssn, None, driver).await;
+        assert_eq!(code, EXIT_SUCCEED);
+        assert!(
+            line.starts_with("2/2 done, 0 running, 0 failed"),
+            "{}",
+            line
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watch_failed() -> Result<(), FlameError> {
+        let server = MockServer::start().await?;
+        let ssn = open_session(&server).await?;
+        for _ in 0..2 {
+            ssn.create_task(None).await?;
+        }
+
+        let driver = async {
+            server.complete_next_task("1").await?;
+            server.fail_next_task("division by zero").await?;
+            Ok(())
+        };
+        let (code, line) = watch_while(&server, &ssn, None, driver).await;
+        assert_eq!(code, EXIT_FAILED);
+        assert!(
+            line.starts_with("2/2 done, 0 running, 1 failed"),
+            "{}",
+            line
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watch_closed() -> Result<(), FlameError> {
+        let server = MockServer::start().await?;
+        let ssn = open_session(&server).await?;
+
+        // The empty session is watched until it's closed, which is pushed by its events.
+        let driver = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            ssn.close().await
+        };
+        let (code, line) = watch_while(&server, &ssn, None, driver).await;
+        assert_eq!(code, EXIT_SUCCEED);
+        assert!(line.starts_with("0/0 done"), "{}", line);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watch_deleted() -> Result<(), FlameError> {
+        let server = MockServer::start().await?;
+        let ssn = open_session(&server).await?;
+        ssn.create_task(None).await?;
+
+        // The events end with the session, so it's polled to find out it's deleted.
+        let driver = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            server.connect().await?.delete_session(&ssn.id).await?;
+            Ok(())
+        };
+        let (code, line) = watch_while(&server, &ssn, None, driver).await;
+        assert_eq!(code, EXIT_CLOSED);
+        assert!(line.starts_with("0/0 done"), "{}", line);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watch_timeout() -> Result<(), FlameError> {
+        let server = MockServer::start().await?;
+        let ssn = open_session(&server).await?;
+        ssn.create_task(None).await?;
+
+        let timeout = Some(Duration::from_millis(200));
+        let (code, line) = watch_while(&server, &ssn, timeout, async { Ok(()) }).await;
+        assert_eq!(code, EXIT_TIMEOUT);
+        assert!(
+            line.starts_with("0/1 done, 0 running, 0 failed"),
+            "{}",
+            line
+        );
+
+        Ok(())
+    }
+
+    fn progress(
+        state: SessionState,
+        pending: i32,
+        running: i32,
+        succeed: i32,
+        failed: i32,
+    ) -> Progress {
+        Progress {
+            state: Some(state),
+            pending,
+            running,
+            succeed,
+            failed,
+        }
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(progress(SessionState::Open, 0, 0, 0, 0).exit_code(), None);
+        assert_eq!(progress(SessionState::Open, 2, 1, 3, 0).exit_code(), None);
+        assert_eq!(
+            progress(SessionState::Open, 0, 0, 6, 0).exit_code(),
+            Some(EXIT_SUCCEED)
+        );
+        assert_eq!(
+            progress(SessionState::Open, 0, 0, 5, 1).exit_code(),
+            Some(EXIT_FAILED)
+        );
+        assert_eq!(
+            progress(SessionState::Closed, 0, 0, 6, 0).exit_code(),
+            Some(EXIT_SUCCEED)
+        );
+        assert_eq!(
+            progress(SessionState::Closed, 0, 0, 0, 0).exit_code(),
+            Some(EXIT_SUCCEED)
+        );
+        assert_eq!(
+            progress(SessionState::Closed, 1, 0, 5, 0).exit_code(),
+            Some(EXIT_CLOSED)
+        );
+        assert_eq!(Progress::default().exit_code(), Some(EXIT_CLOSED));
+    }
+
+    #[test]
+    fn test_task_progress_line() {
+        let progressed = |task_id: &str, percentage| SessionEvent::TaskProgress {
+            sequence: 0,
+            task_id: task_id.to_string(),
+            progress: flame_client::TaskProgress {
+                percentage,
+                payload: None,
+                update_time: Default::default(),
+            },
+        };
+        let changed = |task_id: &str, state| SessionEvent::TaskStateChanged {
+            sequence: 0,
+            task_id: task_id.to_string(),
+            state,
+        };
+
+        let mut tasks = TaskProgress::default();
+        assert_eq!(tasks.line(), "");
+
+        for (id, percentage) in [("10", 75), ("2", 25), ("3", 50)] {
+            tasks.update(&changed(id, TaskState::Running));
+            tasks.update(&progressed(id, percentage));
+        }
+        assert_eq!(tasks.line(), " [2: 25%, 3: 50%, 10: 75%]");
+
+        tasks.update(&progressed("2", 50));
+        tasks.update(&changed("3", TaskState::Succeed));
+        assert_eq!(tasks.line(), " [2: 50%, 10: 75%]");
+
+        for id in 11..15 {
+            tasks.update(&progressed(&id.to_string(), 0));
+        }
+        assert_eq!(tasks.line(), " [2: 50%, 10: 75%, 11: 0%, 12: 0%, 2 more]");
+    }
+
+    #[test]
+    fn test_progress_line() {
+        let p = progress(SessionState::Open, 3, 2, 4, 1);
+        assert_eq!(
+            p.line(Duration::from_secs(42)),
+            "5/10 done, 2 running, 1 failed, elapsed 42s"
+        );
+    }
+}
@@ -54,13 +54,15 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let slots = cli.slots.unwrap_or(DEFAULT_SLOTS);
     let task_num = cli.task_num.unwrap_or(DEFAULT_TASK_NUM);
 
-    let conn = flame::connect(&ctx.endpoint).await?;
+    let conn = flame::ConnectionBuilder::from_context(&ctx)?
+        .connect()
+        .await?;
 
     let ssn_creation_start_time = Local::now();
     let ssn_attr = flame::SessionAttributes {
         application: app.clone(),
         slots,
-        common_data: None,
+        ..Default::default()
     };
     let ssn = conn.create_session(&ssn_attr).await?;
     let ssn_creation_end_time = Local::now();
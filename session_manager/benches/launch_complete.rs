@@ -0,0 +1,57 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The throughput of the launch and complete calls of the Backend with 64 executors bound
+//! to 8 sessions; run with `cargo bench -p flame-session-manager --bench launch_complete`.
+
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use flame_session_manager::bench::LaunchComplete;
+
+const EXECUTORS: usize = 64;
+const SESSIONS: usize = 8;
+const TASKS: usize = 2_500;
+
+fn bench_launch_complete(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(8)
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("launch_complete");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(10));
+    group.throughput(Throughput::Elements((SESSIONS * TASKS) as u64));
+    group.bench_function("memory", |b| {
+        b.to_async(&rt).iter_custom(|iters| async move {
+            let mut elapsed = Duration::ZERO;
+            for _ in 0..iters {
+                let workload = LaunchComplete::new(EXECUTORS, SESSIONS, TASKS)
+                    .await
+                    .unwrap();
+                let start = Instant::now();
+                let completed = workload.run().await.unwrap();
+                elapsed += start.elapsed();
+                assert_eq!(completed, SESSIONS * TASKS);
+            }
+            elapsed
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_launch_complete);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The throughput of the completions of the tasks with sqlite in the strict and the batched
+//! durability; run with `cargo bench -p flame-session-manager --bench write_behind`.
+
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use common::ctx::Durability;
+use flame_session_manager::bench::CompleteTasks;
+
+const WORKERS: usize = 16;
+const TASKS: usize = 2_000;
+
+fn bench_write_behind(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(8)
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("complete_tasks");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(10));
+    group.throughput(Throughput::Elements(TASKS as u64));
+    for durability in [Durability::Strict, Durability::Batched] {
+        group.bench_function(durability.to_string(), |b| {
+            b.to_async(&rt).iter_custom(|iters| async move {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let workload = CompleteTasks::new(durability, TASKS).await.unwrap();
+                    let start = Instant::now();
+                    workload.run(WORKERS).await.unwrap();
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_write_behind);
+criterion_main!(benches);
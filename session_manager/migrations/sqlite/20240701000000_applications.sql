@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS applications (
+    name            TEXT PRIMARY KEY,
+    spec            TEXT NOT NULL,
+
+    creation_time   INTEGER NOT NULL
+);
@@ -0,0 +1,3 @@
+ALTER TABLE sessions ADD COLUMN namespace TEXT NOT NULL DEFAULT 'default';
+
+CREATE INDEX IF NOT EXISTS sessions_namespace ON sessions (namespace);
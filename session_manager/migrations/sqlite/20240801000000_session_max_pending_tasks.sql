@@ -0,0 +1 @@
+ALTER TABLE sessions ADD COLUMN max_pending_tasks INTEGER;
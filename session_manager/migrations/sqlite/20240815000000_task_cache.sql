@@ -0,0 +1,16 @@
+ALTER TABLE sessions ADD COLUMN cache_scope INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS task_cache (
+    id              INTEGER PRIMARY KEY AUTOINCREMENT,
+    digest          TEXT NOT NULL,
+    namespace       TEXT NOT NULL,
+    application     TEXT NOT NULL,
+    ssn_id          INTEGER NOT NULL,
+    shared          INTEGER NOT NULL,
+
+    output          BLOB,
+
+    creation_time   INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS task_cache_digest ON task_cache (digest);
@@ -0,0 +1,6 @@
+ALTER TABLE tasks ADD COLUMN idx INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE sessions ADD COLUMN next_task_index INTEGER NOT NULL DEFAULT 0;
+
+-- The ids of the existing tasks are dense from 1 in their sessions.
+UPDATE tasks SET idx = id - 1;
+UPDATE sessions SET next_task_index = COALESCE((SELECT MAX(id) FROM tasks WHERE tasks.ssn_id = sessions.id), 0);
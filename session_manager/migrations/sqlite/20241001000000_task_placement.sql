@@ -0,0 +1,2 @@
+ALTER TABLE tasks ADD COLUMN executor_id TEXT;
+ALTER TABLE tasks ADD COLUMN hostname TEXT;
@@ -0,0 +1 @@
+ALTER TABLE tasks ADD COLUMN original_task_id INTEGER;
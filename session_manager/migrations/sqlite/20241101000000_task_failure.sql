@@ -0,0 +1,2 @@
+ALTER TABLE tasks ADD COLUMN failure_reason INTEGER;
+ALTER TABLE tasks ADD COLUMN failure_message TEXT;
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS session_events (
+    id              INTEGER PRIMARY KEY AUTOINCREMENT,
+    ssn_id          INTEGER NOT NULL,
+    sequence        INTEGER NOT NULL,
+    event_time      INTEGER NOT NULL,
+
+    event           BLOB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS session_events_ssn_id ON session_events (ssn_id, id);
@@ -0,0 +1,4 @@
+ALTER TABLE sessions ADD COLUMN task_timeout INTEGER;
+ALTER TABLE sessions ADD COLUMN max_task_retries INTEGER;
+ALTER TABLE tasks ADD COLUMN timeout INTEGER;
+ALTER TABLE tasks ADD COLUMN max_retries INTEGER;
@@ -0,0 +1 @@
+ALTER TABLE sessions ADD COLUMN application_spec TEXT;
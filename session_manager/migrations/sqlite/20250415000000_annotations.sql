@@ -0,0 +1,2 @@
+ALTER TABLE sessions ADD COLUMN annotations TEXT;
+ALTER TABLE tasks ADD COLUMN annotations TEXT;
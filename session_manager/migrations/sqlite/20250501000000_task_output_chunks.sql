@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS task_output_chunks (
+    ssn_id          INTEGER NOT NULL,
+    task_id         INTEGER NOT NULL,
+    sequence        INTEGER NOT NULL,
+
+    data            BLOB NOT NULL,
+
+    creation_time   INTEGER NOT NULL,
+
+    PRIMARY KEY (ssn_id, task_id, sequence)
+);
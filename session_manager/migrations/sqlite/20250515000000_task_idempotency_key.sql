@@ -0,0 +1,2 @@
+ALTER TABLE tasks ADD COLUMN idempotency_key TEXT;
+CREATE UNIQUE INDEX IF NOT EXISTS tasks_idempotency_key ON tasks (ssn_id, idempotency_key);
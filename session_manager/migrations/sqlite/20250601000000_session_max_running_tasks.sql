@@ -0,0 +1 @@
+ALTER TABLE sessions ADD COLUMN max_running_tasks INTEGER;
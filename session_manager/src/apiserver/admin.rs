@@ -0,0 +1,545 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use async_trait::async_trait;
+use tonic::{Request, Response, Status};
+
+use self::rpc::admin_server;
+use self::rpc::{
+    FlushStateRequest, FlushStateResponse, GetAllocationReportRequest, GetClusterStatsRequest,
+    GetEffectiveConfigRequest, GetSchedulerStatusRequest, PauseSchedulingRequest,
+    ResumeSchedulingRequest,
+};
+use ::rpc::flame as rpc;
+use common::ctx::FlameContext;
+use common::FlameError;
+
+use crate::apiserver::auth::AuthPtr;
+use crate::effective::EffectiveConfig;
+use crate::scheduler::{
+    Allocation, AllocationReport, ClusterStats, Histogram, SchedulerStatePtr, SchedulerStatus,
+};
+use crate::storage::StoragePtr;
+use crate::supervisor::{LoopHealth, LoopState, SupervisorPtr};
+
+/// The number of the starved sessions in the allocation report if not specified.
+const DEFAULT_STARVED_SESSIONS: usize = 10;
+
+/// The admin service of the session manager, for the operators.
+pub struct Admin {
+    pub storage: StoragePtr,
+    pub scheduler: SchedulerStatePtr,
+    /// The background loops, whose health is reported with the status of the scheduler.
+    pub supervisor: SupervisorPtr,
+    /// The tokens of the callers, if the authentication is enabled.
+    pub auth: Option<AuthPtr>,
+    /// The resolved configuration the session manager runs with.
+    pub ctx: FlameContext,
+}
+
+impl Admin {
+    /// Only the callers of all namespaces can call the admin service.
+    fn authorize<T>(&self, req: &Request<T>) -> Result<(), FlameError> {
+        match &self.auth {
+            Some(auth) => auth.authenticate(req)?.check_cluster_scope(),
+            None => Ok(()),
+        }
+    }
+
+    fn status(&self, status: SchedulerStatus) -> Result<rpc::SchedulerStatus, FlameError> {
+        let mut status = rpc::SchedulerStatus::from(status);
+        status.loops = self
+            .supervisor
+            .health()?
+            .iter()
+            .map(rpc::LoopHealth::from)
+            .collect();
+
+        Ok(status)
+    }
+}
+
+impl From<&LoopHealth> for rpc::LoopHealth {
+    fn from(health: &LoopHealth) -> Self {
+        rpc::LoopHealth {
+            name: health.name.clone(),
+            state: health.state.to_string(),
+            restarts: health.restarts,
+            last_error: health.last_error.clone(),
+            last_error_time: health.last_error_time.map(|t| t.timestamp()),
+        }
+    }
+}
+
+impl From<SchedulerStatus> for rpc::SchedulerStatus {
+    fn from(status: SchedulerStatus) -> Self {
+        rpc::SchedulerStatus {
+            paused: status.paused,
+            last_cycle_time: status.last_cycle_time.map(|t| t.timestamp()),
+            last_decisions: status.last_decisions,
+            total_decisions: status.total_decisions,
+            cycles: status.cycles,
+            loops: vec![],
+        }
+    }
+}
+
+impl From<Histogram> for rpc::Histogram {
+    fn from(h: Histogram) -> Self {
+        rpc::Histogram {
+            bounds: h.bounds,
+            counts: h.counts,
+            sum: h.sum,
+            count: h.count,
+        }
+    }
+}
+
+impl From<ClusterStats> for rpc::ClusterStats {
+    fn from(stats: ClusterStats) -> Self {
+        rpc::ClusterStats {
+            update_time: stats.update_time.map(|t| t.timestamp()),
+            applications: stats
+                .applications
+                .into_iter()
+                .map(|(name, app)| rpc::ApplicationStats {
+                    name,
+                    pending: app.pending,
+                    oldest_pending_age: app.oldest_pending_age,
+                })
+                .collect(),
+            idle_executors: stats.idle_executors,
+            bound_executors: stats.bound_executors,
+            quarantined_executors: stats.quarantined_executors,
+            bind_latency: Some(stats.bind_latency.into()),
+            autoscaler_deliveries: stats.autoscaler_deliveries,
+            autoscaler_failures: stats.autoscaler_failures,
+            slow_locks: stats.slow_locks,
+            slow_engine_calls: stats.slow_engine_calls,
+            throttled_calls: stats.throttled_calls,
+            affinity_hits: stats.affinity_hits,
+            affinity_misses: stats.affinity_misses,
+            locality_hits: stats.locality_hits,
+            locality_misses: stats.locality_misses,
+            loop_restarts: 0,
+            failed_loops: 0,
+            quotas: stats
+                .quotas
+                .into_iter()
+                .map(|q| rpc::QuotaStats {
+                    namespace: q.quota.namespace,
+                    application: q.quota.application,
+                    max_slots: q.quota.max_slots,
+                    slots: q.usage.slots,
+                    max_running_tasks: q.quota.max_running_tasks,
+                    running_tasks: q.usage.running_tasks,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn allocation(name: String, alloc: Allocation) -> rpc::Allocation {
+    rpc::Allocation {
+        name,
+        desired: alloc.desired,
+        allocated: alloc.allocated,
+        target: alloc.target,
+        deviation: alloc.deviation(),
+    }
+}
+
+impl From<AllocationReport> for rpc::AllocationReport {
+    fn from(report: AllocationReport) -> Self {
+        rpc::AllocationReport {
+            update_time: report.update_time.map(|t| t.timestamp()),
+            total_slots: report.total_slots,
+            applications: report
+                .applications
+                .into_iter()
+                .map(|(name, alloc)| allocation(name, alloc))
+                .collect(),
+            namespaces: report
+                .namespaces
+                .into_iter()
+                .map(|(name, alloc)| allocation(name, alloc))
+                .collect(),
+            starved_sessions: report
+                .starved
+                .into_iter()
+                .map(|ssn| rpc::StarvedSession {
+                    id: ssn.id.to_string(),
+                    application: ssn.application,
+                    namespace: ssn.namespace,
+                    pending: ssn.pending,
+                    allocated: ssn.allocated,
+                    target: ssn.target,
+                    wait: ssn.wait,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<EffectiveConfig> for rpc::EffectiveConfig {
+    type Error = FlameError;
+
+    fn try_from(conf: EffectiveConfig) -> Result<Self, Self::Error> {
+        Ok(rpc::EffectiveConfig {
+            config: serde_json::to_string(&conf.config)
+                .map_err(|e| FlameError::Internal(e.to_string()))?,
+            storage_kind: conf.storage.kind,
+            storage_version: conf.storage.version,
+            features: conf.features,
+            build: Some(rpc::BuildInfo {
+                version: conf.build.version,
+                target: conf.build.target,
+                profile: conf.build.profile,
+            }),
+        })
+    }
+}
+
+#[async_trait]
+impl admin_server::Admin for Admin {
+    #[tracing::instrument(name = "Admin::pause_scheduling", skip_all)]
+    async fn pause_scheduling(
+        &self,
+        req: Request<PauseSchedulingRequest>,
+    ) -> Result<Response<rpc::SchedulerStatus>, Status> {
+        self.authorize(&req)?;
+        let status = self.scheduler.set_paused(true)?;
+
+        Ok(Response::new(self.status(status)?))
+    }
+
+    #[tracing::instrument(name = "Admin::resume_scheduling", skip_all)]
+    async fn resume_scheduling(
+        &self,
+        req: Request<ResumeSchedulingRequest>,
+    ) -> Result<Response<rpc::SchedulerStatus>, Status> {
+        self.authorize(&req)?;
+        let status = self.scheduler.set_paused(false)?;
+
+        Ok(Response::new(self.status(status)?))
+    }
+
+    #[tracing::instrument(name = "Admin::get_scheduler_status", skip_all)]
+    async fn get_scheduler_status(
+        &self,
+        req: Request<GetSchedulerStatusRequest>,
+    ) -> Result<Response<rpc::SchedulerStatus>, Status> {
+        self.authorize(&req)?;
+        let status = self.scheduler.status()?;
+
+        Ok(Response::new(self.status(status)?))
+    }
+
+    #[tracing::instrument(name = "Admin::get_cluster_stats", skip_all)]
+    async fn get_cluster_stats(
+        &self,
+        req: Request<GetClusterStatsRequest>,
+    ) -> Result<Response<rpc::ClusterStats>, Status> {
+        self.authorize(&req)?;
+        let mut stats = rpc::ClusterStats::from(self.scheduler.cluster_stats()?);
+        let loops = self.supervisor.health()?;
+        stats.loop_restarts = loops.iter().map(|l| l.restarts as u64).sum();
+        stats.failed_loops = loops
+            .iter()
+            .filter(|l| l.state == LoopState::Failed)
+            .count() as u32;
+
+        Ok(Response::new(stats))
+    }
+
+    #[tracing::instrument(name = "Admin::get_effective_config", skip_all)]
+    async fn get_effective_config(
+        &self,
+        req: Request<GetEffectiveConfigRequest>,
+    ) -> Result<Response<rpc::EffectiveConfig>, Status> {
+        self.authorize(&req)?;
+        let conf = EffectiveConfig::new(&self.ctx, &self.storage);
+
+        Ok(Response::new(conf.try_into()?))
+    }
+
+    #[tracing::instrument(name = "Admin::get_allocation_report", skip_all)]
+    async fn get_allocation_report(
+        &self,
+        req: Request<GetAllocationReportRequest>,
+    ) -> Result<Response<rpc::AllocationReport>, Status> {
+        self.authorize(&req)?;
+        let top = match req.get_ref().top {
+            0 => DEFAULT_STARVED_SESSIONS,
+            n => n as usize,
+        };
+        let mut report = self.scheduler.allocation_report()?;
+        report.starved.truncate(top);
+
+        Ok(Response::new(report.into()))
+    }
+
+    #[tracing::instrument(name = "Admin::flush_state", skip_all)]
+    async fn flush_state(
+        &self,
+        req: Request<FlushStateRequest>,
+    ) -> Result<Response<FlushStateResponse>, Status> {
+        self.authorize(&req)?;
+        let (sessions, tasks) = self.storage.flush().await?;
+
+        Ok(Response::new(FlushStateResponse { sessions, tasks }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use common::apis::{self, SessionState, TaskAttributes, TaskState};
+    use common::ctx::{
+        FlameAuthConf, FlameClientConf, FlameOverrides, FlameTlsConf, FlameTokenConf, REDACTED,
+    };
+
+    use self::admin_server::Admin as _;
+    use crate::apiserver::auth::{Auth, AUTHORIZATION};
+    use crate::scheduler::SchedulerState;
+    use crate::storage;
+    use crate::supervisor::{RestartPolicy, StopSignal, Supervisor};
+    use crate::FlameThread;
+
+    #[tokio::test]
+    async fn test_admin() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_admin_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let token = |name: &str, namespace: Option<&str>| FlameTokenConf {
+            name: name.to_string(),
+            token: format!("{}-token", name),
+            namespace: namespace.map(str::to_string),
+        };
+        let admin = Admin {
+            storage: storage::new_ptr(&url).await?,
+            scheduler: SchedulerState::new_ptr(),
+            supervisor: Supervisor::new_ptr(Default::default()),
+            auth: Some(Arc::new(Auth::new(&FlameAuthConf {
+                tokens: vec![token("admin", None), token("team-a", Some("team-a"))],
+            }))),
+            ctx: FlameContext::default(),
+        };
+        fn request<T>(msg: T, name: &str) -> Request<T> {
+            let mut req = Request::new(msg);
+            let token = format!("Bearer {}-token", name).parse().unwrap();
+            req.metadata_mut().insert(AUTHORIZATION, token);
+            req
+        }
+
+        // Only the callers of all namespaces are allowed.
+        let e = admin
+            .pause_scheduling(Request::new(PauseSchedulingRequest {}))
+            .await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::Unauthenticated);
+        let e = admin
+            .pause_scheduling(request(PauseSchedulingRequest {}, "team-a"))
+            .await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::PermissionDenied);
+        assert!(!admin.scheduler.status()?.paused);
+
+        let status = admin
+            .pause_scheduling(request(PauseSchedulingRequest {}, "admin"))
+            .await?
+            .into_inner();
+        assert!(status.paused);
+        let status = admin
+            .get_scheduler_status(request(GetSchedulerStatusRequest {}, "admin"))
+            .await?
+            .into_inner();
+        assert!(status.paused);
+        assert_eq!(status.last_cycle_time, None);
+        let stats = admin
+            .get_cluster_stats(request(GetClusterStatsRequest {}, "admin"))
+            .await?
+            .into_inner();
+        assert_eq!(stats.update_time, None);
+        assert_eq!(stats.bind_latency.map(|h| h.counts.len()), Some(10));
+        let report = admin
+            .get_allocation_report(request(GetAllocationReportRequest { top: 0 }, "admin"))
+            .await?
+            .into_inner();
+        assert_eq!(report.update_time, None);
+        assert!(report.applications.is_empty());
+        let status = admin
+            .resume_scheduling(request(ResumeSchedulingRequest {}, "admin"))
+            .await?
+            .into_inner();
+        assert!(!status.paused);
+
+        // Nothing is flushed, as the storage writes through.
+        let flush = || request(FlushStateRequest {}, "admin");
+        let resp = admin.flush_state(flush()).await?.into_inner();
+        assert_eq!((resp.sessions, resp.tasks), (0, 0));
+
+        // The states of the tasks and sessions are flushed, if the engine is behind.
+        let storage = &admin.storage;
+        storage.set_config_applications(&[apis::Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }])?;
+        let ssn = storage
+            .create_session(apis::SessionAttributes {
+                application: "flmexec".to_string(),
+                ..Default::default()
+            })
+            .await?;
+        let task = storage
+            .create_task(ssn.id, TaskAttributes::default())
+            .await?;
+
+        storage.get_task_ptr(task.gid())?.lock().unwrap().state = TaskState::Succeed;
+        let resp = admin.flush_state(flush()).await?.into_inner();
+        assert_eq!((resp.sessions, resp.tasks), (0, 1));
+
+        storage
+            .get_session_ptr(ssn.id)?
+            .lock()
+            .unwrap()
+            .status
+            .state = SessionState::Closed;
+        let resp = admin.flush_state(flush()).await?.into_inner();
+        assert_eq!((resp.sessions, resp.tasks), (1, 0));
+        let resp = admin.flush_state(flush()).await?.into_inner();
+        assert_eq!((resp.sessions, resp.tasks), (0, 0));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_effective_config() -> Result<(), FlameError> {
+        let auth = FlameAuthConf {
+            tokens: vec![FlameTokenConf {
+                name: "admin".to_string(),
+                token: "admin-secret".to_string(),
+                namespace: None,
+            }],
+        };
+        let ctx = FlameContext {
+            auth: Some(auth.clone()),
+            client: Some(FlameClientConf {
+                tls: Some(FlameTlsConf {
+                    cert_file: Some("/etc/flame/client.pem".to_string()),
+                    key_file: Some("/etc/flame/client.key".to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+        .with_overrides(&FlameOverrides {
+            storage: Some("memory://".to_string()),
+            policy: Some("fairshare".to_string()),
+            ..Default::default()
+        });
+        let admin = Admin {
+            storage: storage::new_ptr(&ctx.storage).await?,
+            scheduler: SchedulerState::new_ptr(),
+            supervisor: Supervisor::new_ptr(Default::default()),
+            auth: Some(Arc::new(Auth::new(&auth))),
+            ctx,
+        };
+
+        let mut req = Request::new(GetEffectiveConfigRequest {});
+        let token = "Bearer admin-secret".parse().unwrap();
+        req.metadata_mut().insert(AUTHORIZATION, token);
+        let conf = admin.get_effective_config(req).await?.into_inner();
+
+        // The flags are applied, and the secrets are redacted.
+        assert!(!conf.config.contains("admin-secret"), "{}", conf.config);
+        assert!(!conf.config.contains("client.key"), "{}", conf.config);
+        let ctx: FlameContext = serde_json::from_str(&conf.config).unwrap();
+        assert_eq!(ctx.policy, "fairshare");
+        assert_eq!(ctx.storage, "memory://");
+        assert_eq!(ctx.auth.unwrap().tokens[0].token, REDACTED);
+        let tls = ctx.client.unwrap().tls.unwrap();
+        assert_eq!(tls.key_file.as_deref(), Some(REDACTED));
+        assert_eq!(tls.cert_file.as_deref(), Some("/etc/flame/client.pem"));
+
+        assert_eq!(conf.storage_kind, "memory");
+        assert_eq!(
+            conf.build.map(|b| b.version),
+            Some(env!("CARGO_PKG_VERSION").to_string())
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_loop_health() -> Result<(), FlameError> {
+        /// Panics in its first run, and fails in the others.
+        struct Panicking {
+            runs: std::sync::atomic::AtomicU32,
+        }
+        impl FlameThread for Panicking {
+            fn run(&self, _: FlameContext, _: &StopSignal) -> Result<(), FlameError> {
+                match self.runs.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                    0 => panic!("injected panic"),
+                    _ => Err(FlameError::Internal("injected error".to_string())),
+                }
+            }
+        }
+
+        let supervisor = Supervisor::new_ptr(RestartPolicy {
+            max_restarts: 2,
+            initial_backoff: std::time::Duration::from_millis(10),
+            max_backoff: std::time::Duration::from_millis(10),
+        });
+        let admin = Admin {
+            storage: storage::new_ptr("memory://").await?,
+            scheduler: SchedulerState::new_ptr(),
+            supervisor: supervisor.clone(),
+            auth: None,
+            ctx: FlameContext::default(),
+        };
+        let panicking = Panicking {
+            runs: Default::default(),
+        };
+        supervisor.spawn("panicking", Box::new(panicking), FlameContext::default())?;
+
+        // The loop is restarted after the panic, and given up after the errors.
+        let status = loop {
+            let status = admin
+                .get_scheduler_status(Request::new(GetSchedulerStatusRequest {}))
+                .await?
+                .into_inner();
+            if status.loops[0].state == "failed" {
+                break status;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        };
+        let health = &status.loops[0];
+        assert_eq!(health.name, "panicking");
+        assert_eq!(health.restarts, 2);
+        assert_eq!(health.last_error.as_deref(), Some("'injected error'"));
+        assert!(health.last_error_time.is_some());
+
+        let stats = admin
+            .get_cluster_stats(Request::new(GetClusterStatsRequest {}))
+            .await?
+            .into_inner();
+        assert_eq!((stats.loop_restarts, stats.failed_loops), (2, 1));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Instant;
+
+use chrono::Utc;
+use tonic::{Request, Response, Status};
+
+use ::rpc::flame as rpc;
+use common::audit::{AuditEntry, AuditOutcome, ANONYMOUS};
+use common::FlameError;
+
+use crate::apiserver::{call_deadline, Flame};
+use crate::storage;
+
+/// The objects returned by the mutating calls, whose ids are recorded in the audit log.
+pub trait Audited {
+    fn targets(&self) -> Vec<(&'static str, String)>;
+}
+
+impl Audited for rpc::Session {
+    fn targets(&self) -> Vec<(&'static str, String)> {
+        self.metadata
+            .iter()
+            .map(|m| ("session_id", m.id.clone()))
+            .collect()
+    }
+}
+
+impl Audited for rpc::Task {
+    fn targets(&self) -> Vec<(&'static str, String)> {
+        let ssn_id = self
+            .spec
+            .iter()
+            .map(|s| ("session_id", s.session_id.clone()));
+        let task_id = self.metadata.iter().map(|m| ("task_id", m.id.clone()));
+
+        ssn_id.chain(task_id).collect()
+    }
+}
+
+impl Audited for rpc::Executor {
+    fn targets(&self) -> Vec<(&'static str, String)> {
+        self.metadata
+            .iter()
+            .map(|m| ("executor_id", m.id.clone()))
+            .collect()
+    }
+}
+
+impl Audited for rpc::Result {
+    fn targets(&self) -> Vec<(&'static str, String)> {
+        vec![]
+    }
+}
+
+/// The pending entry of a call; it's empty if the audit log is disabled.
+pub struct Audit {
+    entry: Option<AuditEntry>,
+    /// The rejection of the call by the rate limiter; the call is not run.
+    throttled: Option<FlameError>,
+    /// The deadline of the caller, after which the call does not write the storage.
+    deadline: Option<Instant>,
+}
+
+impl Audit {
+    pub fn target(mut self, name: &str, id: impl ToString) -> Self {
+        if let Some(entry) = self.entry.as_mut() {
+            entry.targets.insert(name.to_string(), id.to_string());
+        }
+        self
+    }
+}
+
+impl Flame {
+    /// Starts the entry of a mutating call, which is throttled if the caller exceeded the
+    /// rate limit.
+    pub fn audit<T>(&self, method: &str, req: &Request<T>) -> Audit {
+        let throttled = self.throttle(req).err();
+        let deadline = call_deadline(req);
+        if self.audit.is_none() {
+            return Audit {
+                entry: None,
+                throttled,
+                deadline,
+            };
+        }
+
+        let entry = AuditEntry {
+            timestamp: Utc::now(),
+            method: method.to_string(),
+            // The failure of the authentication is recorded as the outcome of the call.
+            caller: self
+                .identity(req)
+                .map(|identity| identity.name)
+                .unwrap_or(ANONYMOUS.to_string()),
+            peer: req.remote_addr().map(|addr| addr.to_string()),
+            targets: BTreeMap::new(),
+            outcome: AuditOutcome::Ok,
+        };
+
+        Audit {
+            entry: Some(entry),
+            throttled,
+            deadline,
+        }
+    }
+
+    /// Runs the call unless it's throttled, and records its outcome in the audit log; the call
+    /// fails without writing the storage if the deadline of its caller passed.
+    pub async fn audited<T, F>(&self, audit: Audit, call: F) -> Result<Response<T>, Status>
+    where
+        T: Audited,
+        F: Future<Output = Result<Response<T>, Status>>,
+    {
+        let res = match audit.throttled {
+            Some(e) => Err(Status::from(e)),
+            None => storage::with_deadline(audit.deadline, call).await,
+        };
+
+        if let (Some(log), Some(mut entry)) = (&self.audit, audit.entry) {
+            match &res {
+                Ok(resp) => {
+                    for (name, id) in resp.get_ref().targets() {
+                        entry.targets.insert(name.to_string(), id);
+                    }
+                }
+                Err(status) => {
+                    entry.outcome = AuditOutcome::Failed {
+                        code: format!("{:?}", status.code()),
+                        message: status.message().to_string(),
+                    };
+                }
+            }
+            log.record(&entry);
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use ::rpc::flame::frontend_server::Frontend;
+    use ::rpc::flame::{
+        CloseSessionRequest, CreateSessionRequest, RegisterApplicationRequest, SessionSpec,
+    };
+    use common::audit::AuditLog;
+    use common::ctx::FlameAuditConf;
+
+    use crate::storage;
+
+    #[tokio::test]
+    async fn test_audit_frontend() -> Result<(), FlameError> {
+        let ts = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        let path = std::env::temp_dir().join(format!("flame_test_audit_{}.jsonl", ts));
+        let flame = Flame {
+            storage: storage::new_ptr(&format!("sqlite:///tmp/flame_test_audit_{}.db", ts)).await?,
+            audit: Some(Arc::new(AuditLog::new(&FlameAuditConf {
+                path: path.to_string_lossy().to_string(),
+                ..Default::default()
+            })?)),
+            auth: None,
+            limiter: None,
+        };
+
+        let create_session = || {
+            Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: "flmexec".to_string(),
+                    slots: 1,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        };
+        assert!(flame.create_session(create_session()).await.is_err());
+
+        let req = RegisterApplicationRequest {
+            application: Some(rpc::Application {
+                name: "flmexec".to_string(),
+                ..Default::default()
+            }),
+        };
+        flame.register_application(Request::new(req)).await?;
+        let ssn = flame.create_session(create_session()).await?.into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+
+        let req = CloseSessionRequest {
+            session_id: ssn_id.clone(),
+            namespace: String::new(),
+        };
+        flame.close_session(Request::new(req)).await?;
+
+        let entries: Vec<AuditEntry> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| (e.method.as_str(), e.targets.clone(), e.outcome.clone()))
+            .collect();
+        let targets = |name: &str, id: &str| BTreeMap::from([(name.to_string(), id.to_string())]);
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "CreateSession",
+                    BTreeMap::new(),
+                    AuditOutcome::Failed {
+                        code: "NotFound".to_string(),
+                        message: "application <flmexec>".to_string(),
+                    }
+                ),
+                (
+                    "RegisterApplication",
+                    targets("application", "flmexec"),
+                    AuditOutcome::Ok
+                ),
+                (
+                    "CreateSession",
+                    targets("session_id", &ssn_id),
+                    AuditOutcome::Ok
+                ),
+                (
+                    "CloseSession",
+                    targets("session_id", &ssn_id),
+                    AuditOutcome::Ok
+                ),
+            ]
+        );
+        assert!(entries.iter().all(|e| e.caller == ANONYMOUS));
+
+        Ok(())
+    }
+}
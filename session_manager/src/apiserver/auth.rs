@@ -0,0 +1,151 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::Utc;
+use tonic::Request;
+
+use common::apis::{self, SessionID};
+use common::audit::ANONYMOUS;
+use common::ctx::FlameAuthConf;
+use common::FlameError;
+
+use crate::apiserver::Flame;
+
+/// The key of the bearer token in the metadata of the requests.
+pub const AUTHORIZATION: &str = "authorization";
+const BEARER: &str = "Bearer ";
+
+pub type AuthPtr = Arc<Auth>;
+
+/// The caller of a Frontend call.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identity {
+    pub name: String,
+    /// The only namespace the caller can access; all namespaces if it's none.
+    pub namespace: Option<String>,
+}
+
+/// The tokens accepted by the Frontend.
+pub struct Auth {
+    tokens: HashMap<String, Identity>,
+}
+
+impl Auth {
+    pub fn new(conf: &FlameAuthConf) -> Self {
+        let tokens = conf
+            .tokens
+            .iter()
+            .map(|t| {
+                let identity = Identity {
+                    name: t.name.clone(),
+                    namespace: t.namespace.clone(),
+                };
+                (t.token.clone(), identity)
+            })
+            .collect();
+
+        Auth { tokens }
+    }
+
+    pub fn authenticate<T>(&self, req: &Request<T>) -> Result<Identity, FlameError> {
+        let token = req
+            .metadata()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix(BEARER))
+            .ok_or(FlameError::Unauthenticated("no bearer token".to_string()))?;
+
+        self.tokens
+            .get(token)
+            .cloned()
+            .ok_or(FlameError::Unauthenticated("invalid token".to_string()))
+    }
+}
+
+impl Identity {
+    fn anonymous() -> Self {
+        Identity {
+            name: ANONYMOUS.to_string(),
+            namespace: None,
+        }
+    }
+
+    /// Resolves the namespace of a request: the given one, the one of the caller, or the
+    /// default namespace.
+    pub fn namespace(&self, namespace: &str) -> Result<String, FlameError> {
+        let namespace = match (namespace.is_empty(), &self.namespace) {
+            (true, Some(ns)) => ns.clone(),
+            _ => apis::parse_namespace(namespace)?,
+        };
+
+        match &self.namespace {
+            Some(ns) if *ns != namespace => Err(FlameError::PermissionDenied(format!(
+                "<{}> can not access namespace <{}>",
+                self.name, namespace
+            ))),
+            _ => Ok(namespace),
+        }
+    }
+
+    /// The resources shared by the namespaces, e.g. applications and executors, are only
+    /// changed by the callers of all namespaces.
+    pub fn check_cluster_scope(&self) -> Result<(), FlameError> {
+        match &self.namespace {
+            Some(ns) => Err(FlameError::PermissionDenied(format!(
+                "<{}> is bound to namespace <{}>",
+                self.name, ns
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Flame {
+    /// The caller of the request; everyone is anonymous if the authentication is disabled.
+    pub fn identity<T>(&self, req: &Request<T>) -> Result<Identity, FlameError> {
+        match &self.auth {
+            Some(auth) => auth.authenticate(req),
+            None => Ok(Identity::anonymous()),
+        }
+    }
+
+    /// Parses the id of a session in the namespace of the request, and records the call on
+    /// the session, so it's not orphaned; the sessions of other namespaces are not found.
+    pub fn session_id(
+        &self,
+        identity: &Identity,
+        namespace: &str,
+        id: &str,
+    ) -> Result<SessionID, FlameError> {
+        let namespace = identity.namespace(namespace)?;
+        let ssn_id = apis::parse_session_id(id)?;
+        self.storage.get_namespaced_session(&namespace, ssn_id)?;
+        self.storage.touch_session(ssn_id, Utc::now())?;
+
+        Ok(ssn_id)
+    }
+
+    /// Checks the caller can access the session, and records the call on the session like
+    /// `session_id`; the sessions of other namespaces are not found, instead of denied.
+    pub fn check_session(&self, identity: &Identity, ssn_id: SessionID) -> Result<(), FlameError> {
+        if let Some(ns) = &identity.namespace {
+            self.storage.get_namespaced_session(ns, ssn_id)?;
+        }
+        self.storage.touch_session(ssn_id, Utc::now())?;
+
+        Ok(())
+    }
+}
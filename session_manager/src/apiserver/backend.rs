@@ -13,14 +13,15 @@ limitations under the License.
 
 use async_trait::async_trait;
 use chrono::Utc;
-use common::{trace::TraceFn, trace_fn, FlameError};
-use tonic::{Request, Response, Status};
+use common::FlameError;
+use tonic::{Request, Response, Status, Streaming};
 
 use self::rpc::backend_server::Backend;
 use self::rpc::{
-    BindExecutorCompletedRequest, BindExecutorRequest, CompleteTaskRequest, LaunchTaskRequest,
-    LaunchTaskResponse, RegisterExecutorRequest, Session, UnbindExecutorCompletedRequest,
-    UnbindExecutorRequest, UnregisterExecutorRequest,
+    BindExecutorCompletedRequest, BindExecutorRequest, BindExecutorResponse, CompleteTaskRequest,
+    GetApplicationRequest, HeartbeatRequest, LaunchTaskRequest, LaunchTaskResponse,
+    RegisterExecutorRequest, ReportTaskProgressRequest, Session, StreamTaskOutputRequest,
+    UnbindExecutorCompletedRequest, UnbindExecutorRequest, UnregisterExecutorRequest,
 };
 use ::rpc::flame as rpc;
 
@@ -30,11 +31,15 @@ use common::apis::TaskOutput;
 
 #[async_trait]
 impl Backend for Flame {
+    #[tracing::instrument(
+        name = "Backend::register_executor",
+        skip_all,
+        fields(executor_id = %req.get_ref().executor_id)
+    )]
     async fn register_executor(
         &self,
         req: Request<RegisterExecutorRequest>,
     ) -> Result<Response<rpc::Result>, Status> {
-        trace_fn!("Backend::register_executor");
         let req = req.into_inner();
         let spec = req
             .executor_spec
@@ -51,75 +56,159 @@ impl Backend for Flame {
             applications,
             task_id: None,
             ssn_id: None,
+            labels: spec.labels,
+            locality_keys: spec.locality_keys,
+            host: spec.host.map(apis::HostInfo::from).unwrap_or_default(),
             creation_time: Utc::now(),
+            last_heartbeat: Utc::now(),
             state: apis::ExecutorState::Idle,
+            draining: false,
+            quarantined: false,
+            generation: 0,
         };
 
         self.storage.register_executor(&e).map_err(Status::from)?;
 
         Ok(Response::new(rpc::Result::default()))
     }
+
+    #[tracing::instrument(
+        name = "Backend::unregister_executor",
+        skip_all,
+        fields(executor_id = %req.get_ref().executor_id)
+    )]
     async fn unregister_executor(
         &self,
-        _: Request<UnregisterExecutorRequest>,
+        req: Request<UnregisterExecutorRequest>,
     ) -> Result<Response<rpc::Result>, Status> {
-        todo!()
+        let req = req.into_inner();
+        match req.force {
+            true => {
+                let failure = apis::TaskFailure {
+                    reason: apis::FailureReason::ExecutorLost,
+                    message: format!("executor <{}> was unregistered", req.executor_id),
+                };
+                self.storage
+                    .remove_executor(req.executor_id, &failure)
+                    .await?;
+            }
+            false => self.storage.unregister_executor(req.executor_id)?,
+        }
+
+        Ok(Response::new(rpc::Result::default()))
     }
 
+    #[tracing::instrument(
+        name = "Backend::bind_executor",
+        skip_all,
+        fields(executor_id = %req.get_ref().executor_id)
+    )]
     async fn bind_executor(
         &self,
         req: Request<BindExecutorRequest>,
-    ) -> Result<Response<Session>, Status> {
-        trace_fn!("Backend::bind_executor");
+    ) -> Result<Response<BindExecutorResponse>, Status> {
         let req = req.into_inner();
 
         let ssn = self
             .storage
             .wait_for_session(req.executor_id.to_string())
             .await?;
+        let generation = self
+            .storage
+            .get_executor(req.executor_id.clone())?
+            .generation;
 
-        Ok(Response::new(Session::from(&ssn)))
+        // The executor runs the application recorded on the session, or the latest one; it falls
+        // back to its local application if neither is found here.
+        let application = match self.storage.session_application(&ssn) {
+            Ok(app) => Some(rpc::Application::from(&app)),
+            Err(e) => {
+                log::warn!(
+                    "Failed to get application <{}> of session <{}>: {}",
+                    ssn.application,
+                    ssn.id,
+                    e
+                );
+                None
+            }
+        };
+
+        let ssn = Session::from(&ssn);
+        Ok(Response::new(BindExecutorResponse {
+            metadata: ssn.metadata,
+            spec: ssn.spec,
+            status: ssn.status,
+            application,
+            generation: Some(generation),
+        }))
     }
 
+    #[tracing::instrument(
+        name = "Backend::bind_executor_completed",
+        skip_all,
+        fields(executor_id = %req.get_ref().executor_id)
+    )]
     async fn bind_executor_completed(
         &self,
         req: Request<BindExecutorCompletedRequest>,
     ) -> Result<Response<rpc::Result>, Status> {
-        trace_fn!("Backend::bind_executor_completed");
         let req = req.into_inner();
+        self.storage.heartbeat(req.executor_id.clone())?;
 
-        self.storage.bind_session_completed(req.executor_id).await?;
+        self.storage
+            .bind_session_completed(req.executor_id, req.generation, req.session_generation)
+            .await?;
 
         Ok(Response::new(rpc::Result::default()))
     }
 
+    #[tracing::instrument(
+        name = "Backend::unbind_executor",
+        skip_all,
+        fields(executor_id = %req.get_ref().executor_id)
+    )]
     async fn unbind_executor(
         &self,
         req: Request<UnbindExecutorRequest>,
     ) -> Result<Response<rpc::Result>, Status> {
         let req = req.into_inner();
-        self.storage.unbind_executor(req.executor_id).await?;
+        self.storage.heartbeat(req.executor_id.clone())?;
+        self.storage
+            .unbind_executor(req.executor_id, req.generation)
+            .await?;
 
         Ok(Response::new(rpc::Result::default()))
     }
 
+    #[tracing::instrument(
+        name = "Backend::unbind_executor_completed",
+        skip_all,
+        fields(executor_id = %req.get_ref().executor_id)
+    )]
     async fn unbind_executor_completed(
         &self,
         req: Request<UnbindExecutorCompletedRequest>,
     ) -> Result<Response<rpc::Result>, Status> {
         let req = req.into_inner();
+        self.storage.heartbeat(req.executor_id.clone())?;
         self.storage
-            .unbind_executor_completed(req.executor_id)
+            .unbind_executor_completed(req.executor_id, req.generation)
             .await?;
 
         Ok(Response::new(rpc::Result::default()))
     }
 
+    #[tracing::instrument(
+        name = "Backend::launch_task",
+        skip_all,
+        fields(executor_id = %req.get_ref().executor_id)
+    )]
     async fn launch_task(
         &self,
         req: Request<LaunchTaskRequest>,
     ) -> Result<Response<LaunchTaskResponse>, Status> {
         let req = req.into_inner();
+        self.storage.heartbeat(req.executor_id.clone())?;
         let task = self.storage.launch_task(req.executor_id).await?;
         if let Some(task) = task {
             return Ok(Response::new(LaunchTaskResponse {
@@ -130,19 +219,151 @@ impl Backend for Flame {
         Ok(Response::new(LaunchTaskResponse { task: None }))
     }
 
+    #[tracing::instrument(
+        name = "Backend::complete_task",
+        skip_all,
+        fields(executor_id = %req.get_ref().executor_id)
+    )]
     async fn complete_task(
         &self,
         req: Request<CompleteTaskRequest>,
     ) -> Result<Response<rpc::Result>, Status> {
         let req = req.into_inner();
+        self.storage.heartbeat(req.executor_id.clone())?;
+
+        let gid = match (req.session_id.is_empty(), req.task_id.is_empty()) {
+            (true, true) => {
+                log::debug!(
+                    "Executor <{}> completed a task without its identity, which is deprecated.",
+                    req.executor_id
+                );
+                None
+            }
+            _ => Some(apis::TaskGID::parse(&req.session_id, &req.task_id)?),
+        };
+
+        // The annotations are merged before the task is released from the executor, even if
+        // it failed, so those of the retries are merged too.
+        let annotations = apis::Annotations {
+            task: req.annotations,
+            session: req.session_annotations,
+        };
+        if !annotations.is_empty() {
+            self.storage
+                .annotate_task(req.executor_id.clone(), gid, &annotations)
+                .await?;
+        }
+
+        match req.failure {
+            Some(message) => {
+                let failure = apis::TaskFailure {
+                    reason: match req.failure_reason {
+                        Some(reason) => apis::FailureReason::try_from(reason)?,
+                        None => apis::FailureReason::ShimError,
+                    },
+                    message,
+                };
+                self.storage
+                    .fail_task(req.executor_id.clone(), gid, &failure, req.generation)
+                    .await?
+            }
+            None => {
+                self.storage
+                    .complete_task(
+                        req.executor_id.clone(),
+                        gid,
+                        req.task_output.map(TaskOutput::from),
+                        req.generation,
+                    )
+                    .await?
+            }
+        }
+
+        Ok(Response::new(rpc::Result::default()))
+    }
+
+    #[tracing::instrument(
+        name = "Backend::report_task_progress",
+        skip_all,
+        fields(executor_id = %req.get_ref().executor_id, task_id = %req.get_ref().task_id)
+    )]
+    async fn report_task_progress(
+        &self,
+        req: Request<ReportTaskProgressRequest>,
+    ) -> Result<Response<rpc::Result>, Status> {
+        let req = req.into_inner();
+        self.storage.heartbeat(req.executor_id.clone())?;
+
+        let gid = apis::TaskGID::parse(&req.session_id, &req.task_id)?;
+        let progress = req
+            .progress
+            .ok_or(FlameError::invalid_argument("progress", "no progress"))?;
+        // The time of the executor may be skewed, so the progress is stamped on receipt.
+        let progress = apis::TaskProgress {
+            update_time: Utc::now(),
+            ..apis::TaskProgress::try_from(progress)?
+        };
 
         self.storage
-            .complete_task(
-                req.executor_id.clone(),
-                req.task_output.map(TaskOutput::from),
-            )
-            .await?;
+            .report_task_progress(req.executor_id, gid, progress)?;
 
         Ok(Response::new(rpc::Result::default()))
     }
+
+    /// Appends the chunks of the stream to the output chunks of their tasks in order; the
+    /// stream is aborted by the first chunk rejected.
+    #[tracing::instrument(name = "Backend::stream_task_output", skip_all)]
+    async fn stream_task_output(
+        &self,
+        req: Request<Streaming<StreamTaskOutputRequest>>,
+    ) -> Result<Response<rpc::Result>, Status> {
+        let mut stream = req.into_inner();
+        while let Some(req) = stream.message().await? {
+            self.storage.heartbeat(req.executor_id.clone())?;
+
+            let gid = apis::TaskGID::parse(&req.session_id, &req.task_id)?;
+            self.storage
+                .append_output_chunk(req.executor_id, gid, TaskOutput::from(req.data))
+                .await?;
+        }
+
+        Ok(Response::new(rpc::Result::default()))
+    }
+
+    #[tracing::instrument(
+        name = "Backend::heartbeat",
+        skip_all,
+        fields(executor_id = %req.get_ref().executor_id)
+    )]
+    async fn heartbeat(
+        &self,
+        req: Request<HeartbeatRequest>,
+    ) -> Result<Response<rpc::Result>, Status> {
+        let req = req.into_inner();
+        self.storage.heartbeat(req.executor_id.clone())?;
+        if let Some(locality_keys) = req.locality_keys {
+            self.storage
+                .set_locality_keys(req.executor_id.clone(), locality_keys.keys)?;
+        }
+        if let Some(busy_seconds) = req.busy_seconds {
+            self.storage
+                .report_busy_seconds(req.executor_id, busy_seconds)?;
+        }
+
+        Ok(Response::new(rpc::Result::default()))
+    }
+
+    #[tracing::instrument(
+        name = "Backend::get_application",
+        skip_all,
+        fields(application = %req.get_ref().name)
+    )]
+    async fn get_application(
+        &self,
+        req: Request<GetApplicationRequest>,
+    ) -> Result<Response<rpc::Application>, Status> {
+        let app = self.storage.get_application(&req.into_inner().name)?;
+
+        Ok(Response::new(rpc::Application::from(&app)))
+    }
 }
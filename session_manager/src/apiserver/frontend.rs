@@ -10,112 +10,248 @@ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 See the License for the specific language governing permissions and
 limitations under the License.
 */
+use std::ops::Range;
 use std::pin::Pin;
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use futures::Stream;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
-use tonic::{Request, Response, Status};
+use tonic::{Request, Response, Status, Streaming};
 
 use self::rpc::frontend_server::Frontend;
+use self::rpc::session_archive::Item;
+use self::rpc::task_chunk::Item as Chunk;
 use self::rpc::{
-    CloseSessionRequest, CreateSessionRequest, CreateTaskRequest, DeleteSessionRequest,
-    DeleteTaskRequest, GetSessionRequest, GetTaskRequest, ListSessionRequest, OpenSessionRequest,
-    Session, SessionList, Task, WatchTaskRequest,
+    ApplicationList, CloneSessionRequest, CloseSessionRequest, CreateSessionRequest,
+    CreateTaskRequest, DeleteApplicationRequest, DeleteSessionRequest, DeleteTaskRequest,
+    DrainExecutorRequest, Executor, ExecutorList, ExportSessionRequest,
+    FetchTaskOutputChunksRequest, FetchTaskOutputsRequest, GetExecutorRequest,
+    GetServerInfoRequest, GetSessionEventsRequest, GetSessionRequest, GetTaskRequest,
+    ListApplicationRequest, ListExecutorRequest, ListSessionRequest, ListSessionTemplateRequest,
+    ListTaskRequest, OpenSessionRequest, RegisterApplicationRequest, ResubmitTaskRequest,
+    ServerInfo, Session, SessionArchive, SessionEvent, SessionEventList, SessionList, SessionSpec,
+    SessionTemplateList, Task, TaskChunk, TaskList, TaskOutputChunk, TaskOutputEntry, TaskSpec,
+    UncordonExecutorRequest, UpdateSessionRequest, WatchSessionRequest, WatchTaskRequest,
 };
 use rpc::flame as rpc;
 
-use common::apis;
-use common::{trace::TraceFn, trace_fn};
+use common::apis::{self, SessionID};
+use common::capability;
+use common::ctx::FlameSessionTemplate;
+use common::{lock_ptr, FlameError};
 
-use crate::apiserver::Flame;
+use crate::apiserver::auth::Identity;
+use crate::apiserver::spool::{Spool, SPOOL_THRESHOLD};
+use crate::apiserver::validator;
+use crate::apiserver::{continue_trace, Flame};
+use crate::storage::StoragePtr;
+
+/// The tasks read from the storage at a time by `FetchTaskOutputs`, and the outputs buffered
+/// for the client; they bound the memory of a stream whatever the size of the session.
+const OUTPUT_PAGE: usize = 32;
+const OUTPUT_BUFFER: usize = 16;
+
+/// The output chunks read from the storage at a time by `FetchTaskOutputChunks`.
+const OUTPUT_CHUNK_PAGE: usize = 16;
+
+/// The most tasks in a page of `ListTask`, which is also the page of the requests without a
+/// limit, so a huge session is never listed in one message.
+const MAX_TASK_PAGE: u32 = 1000;
+
+/// The most bytes of the idempotency key of a task, e.g. a path or a digest of its input.
+const MAX_IDEMPOTENCY_KEY: usize = 256;
 
 #[async_trait]
 impl Frontend for Flame {
     type WatchTaskStream = Pin<Box<dyn Stream<Item = Result<Task, Status>> + Send>>;
+    type WatchSessionStream = Pin<Box<dyn Stream<Item = Result<SessionEvent, Status>> + Send>>;
+    type ExportSessionStream = Pin<Box<dyn Stream<Item = Result<SessionArchive, Status>> + Send>>;
+    type FetchTaskOutputsStream =
+        Pin<Box<dyn Stream<Item = Result<TaskOutputEntry, Status>> + Send>>;
+    type FetchTaskOutputChunksStream =
+        Pin<Box<dyn Stream<Item = Result<TaskOutputChunk, Status>> + Send>>;
 
+    #[tracing::instrument(name = "Frontend::create_session", skip_all)]
     async fn create_session(
         &self,
         req: Request<CreateSessionRequest>,
     ) -> Result<Response<Session>, Status> {
-        trace_fn!("Frontend::create_session");
-        let ssn_spec = req
-            .into_inner()
-            .session
-            .ok_or(Status::invalid_argument("session spec"))?;
+        continue_trace(&req);
+        let audit = self.audit("CreateSession", &req);
+        self.audited(audit, async move {
+            let identity = self.identity(&req)?;
+            let req = req.into_inner();
+            let mut ssn_spec = req
+                .session
+                .ok_or(FlameError::invalid_argument("spec", "session spec"))?;
+            if !req.template.is_empty() {
+                let template = self.storage.get_session_template(&req.template)?;
+                ssn_spec = expand_template(ssn_spec, &template)?;
+            }
+            let namespace = identity.namespace(&ssn_spec.namespace)?;
+            if ssn_spec.max_pending_tasks == Some(0) {
+                return Err(Status::from(FlameError::invalid_argument(
+                    "max_pending_tasks",
+                    "must be greater than 0",
+                )));
+            }
+            if ssn_spec.max_running_tasks == Some(0) {
+                return Err(Status::from(FlameError::invalid_argument(
+                    "max_running_tasks",
+                    "must be greater than 0",
+                )));
+            }
+            if ssn_spec.slots < 0 {
+                return Err(Status::from(FlameError::invalid_argument(
+                    "slots",
+                    "must not be negative",
+                )));
+            }
+            if ssn_spec.task_timeout == Some(0) {
+                return Err(Status::from(FlameError::invalid_argument(
+                    "task_timeout",
+                    "must be greater than 0",
+                )));
+            }
 
-        let ssn = self
-            .storage
-            .create_session(
-                ssn_spec.application,
-                ssn_spec.slots,
-                ssn_spec.common_data.map(apis::CommonData::from),
-            )
-            .await
-            .map(Session::from)
-            .map_err(Status::from)?;
+            let attrs = apis::SessionAttributes {
+                namespace,
+                application: ssn_spec.application,
+                slots: ssn_spec.slots,
+                labels: ssn_spec.labels,
+                common_data: ssn_spec.common_data.map(apis::CommonData::from),
+                config: apis::parse_config(ssn_spec.config)?,
+                max_pending_tasks: ssn_spec.max_pending_tasks,
+                max_running_tasks: ssn_spec.max_running_tasks,
+                cache_scope: apis::CacheScope::try_from(ssn_spec.cache_scope)?,
+                deadline: session_deadline(ssn_spec.deadline, ssn_spec.timeout)?,
+                task_timeout: ssn_spec.task_timeout,
+                max_task_retries: ssn_spec.max_task_retries,
+                cloned_from: None,
+                priority_class: ssn_spec.priority_class.filter(|c| !c.is_empty()),
+                latest_application: ssn_spec.latest_application,
+                application_spec: None,
+            };
+            if let Some(data) = &attrs.common_data {
+                self.check_common_data(&attrs.application, data)?;
+            }
 
-        Ok(Response::new(ssn))
+            let ssn = self
+                .storage
+                .create_session(attrs)
+                .await
+                .map(Session::from)
+                .map_err(Status::from)?;
+
+            Ok(Response::new(ssn))
+        })
+        .await
     }
 
+    #[tracing::instrument(
+        name = "Frontend::delete_session",
+        skip_all,
+        fields(session_id = %req.get_ref().session_id)
+    )]
     async fn delete_session(
         &self,
         req: Request<DeleteSessionRequest>,
     ) -> Result<Response<rpc::Session>, Status> {
-        let ssn_id = req
-            .into_inner()
-            .session_id
-            .parse::<apis::SessionID>()
-            .map_err(|_| Status::invalid_argument("invalid session id"))?;
+        continue_trace(&req);
+        let audit = self
+            .audit("DeleteSession", &req)
+            .target("session_id", &req.get_ref().session_id);
+        self.audited(audit, async move {
+            let identity = self.identity(&req)?;
+            let req = req.into_inner();
+            let ssn_id = self.session_id(&identity, &req.namespace, &req.session_id)?;
 
-        let ssn = self
-            .storage
-            .delete_session(ssn_id)
-            .await
-            .map(Session::from)?;
+            let ssn = self
+                .storage
+                .delete_session(ssn_id)
+                .await
+                .map(Session::from)?;
 
-        Ok(Response::new(ssn))
+            Ok(Response::new(ssn))
+        })
+        .await
     }
 
+    #[tracing::instrument(
+        name = "Frontend::open_session",
+        skip_all,
+        fields(session_id = %req.get_ref().session_id)
+    )]
     async fn open_session(
         &self,
-        _: Request<OpenSessionRequest>,
+        req: Request<OpenSessionRequest>,
     ) -> Result<Response<rpc::Session>, Status> {
-        todo!()
+        continue_trace(&req);
+        let audit = self
+            .audit("OpenSession", &req)
+            .target("session_id", &req.get_ref().session_id);
+        self.audited(audit, async move {
+            let identity = self.identity(&req)?;
+            let req = req.into_inner();
+            let ssn_id = self.session_id(&identity, &req.namespace, &req.session_id)?;
+
+            let ssn = self
+                .storage
+                .open_session(ssn_id)
+                .await
+                .map(rpc::Session::from)
+                .map_err(Status::from)?;
+
+            Ok(Response::new(ssn))
+        })
+        .await
     }
 
+    #[tracing::instrument(
+        name = "Frontend::close_session",
+        skip_all,
+        fields(session_id = %req.get_ref().session_id)
+    )]
     async fn close_session(
         &self,
         req: Request<CloseSessionRequest>,
     ) -> Result<Response<rpc::Session>, Status> {
-        trace_fn!("Frontend::close_session");
-        let ssn_id = req
-            .into_inner()
-            .session_id
-            .parse::<apis::SessionID>()
-            .map_err(|_| Status::invalid_argument("invalid session id"))?;
+        continue_trace(&req);
+        let audit = self
+            .audit("CloseSession", &req)
+            .target("session_id", &req.get_ref().session_id);
+        self.audited(audit, async move {
+            let identity = self.identity(&req)?;
+            let req = req.into_inner();
+            let ssn_id = self.session_id(&identity, &req.namespace, &req.session_id)?;
 
-        let ssn = self
-            .storage
-            .close_session(ssn_id)
-            .await
-            .map(rpc::Session::from)
-            .map_err(Status::from)?;
+            let ssn = self
+                .storage
+                .close_session(ssn_id)
+                .await
+                .map(rpc::Session::from)
+                .map_err(Status::from)?;
 
-        Ok(Response::new(ssn))
+            Ok(Response::new(ssn))
+        })
+        .await
     }
 
+    #[tracing::instrument(
+        name = "Frontend::get_session",
+        skip_all,
+        fields(session_id = %req.get_ref().session_id)
+    )]
     async fn get_session(
         &self,
         req: Request<GetSessionRequest>,
     ) -> Result<Response<Session>, Status> {
-        trace_fn!("Frontend::get_session");
-        let ssn_id = req
-            .into_inner()
-            .session_id
-            .parse::<apis::SessionID>()
-            .map_err(|_| Status::invalid_argument("invalid session id"))?;
+        continue_trace(&req);
+        let identity = self.identity(&req)?;
+        let req = req.into_inner();
+        let ssn_id = self.session_id(&identity, &req.namespace, &req.session_id)?;
 
         let ssn = self
             .storage
@@ -125,38 +261,358 @@ impl Frontend for Flame {
 
         Ok(Response::new(ssn))
     }
+
+    #[tracing::instrument(
+        name = "Frontend::update_session",
+        skip_all,
+        fields(session_id = %req.get_ref().session_id)
+    )]
+    async fn update_session(
+        &self,
+        req: Request<UpdateSessionRequest>,
+    ) -> Result<Response<Session>, Status> {
+        continue_trace(&req);
+        let audit = self
+            .audit("UpdateSession", &req)
+            .target("session_id", &req.get_ref().session_id);
+        self.audited(audit, async move {
+            let identity = self.identity(&req)?;
+            let req = req.into_inner();
+            let ssn_id = self.session_id(&identity, &req.namespace, &req.session_id)?;
+            let deadline = session_deadline(Some(req.deadline), None)?;
+
+            let ssn = self
+                .storage
+                .update_session_deadline(ssn_id, deadline)
+                .await
+                .map(Session::from)
+                .map_err(Status::from)?;
+
+            Ok(Response::new(ssn))
+        })
+        .await
+    }
+
+    #[tracing::instrument(name = "Frontend::list_session", skip_all)]
     async fn list_session(
         &self,
-        _: Request<ListSessionRequest>,
+        req: Request<ListSessionRequest>,
     ) -> Result<Response<SessionList>, Status> {
-        trace_fn!("Frontend::list_session");
-        let ssn_list = self.storage.list_session().map_err(Status::from)?;
+        continue_trace(&req);
+        let identity = self.identity(&req)?;
+        let req = req.into_inner();
+        // All the namespaces of the caller, if no namespace is given.
+        let namespace = match (req.namespace.is_empty(), &identity.namespace) {
+            (true, None) => None,
+            _ => Some(identity.namespace(&req.namespace)?),
+        };
+
+        let order = apis::SessionOrder::try_from(req.order_by)?;
+        let state = req.state.map(apis::SessionState::try_from).transpose()?;
+
+        let mut ssn_list = self.storage.list_session().map_err(Status::from)?;
+        ssn_list.retain(|ssn| {
+            namespace.as_ref().is_none_or(|ns| ssn.namespace == *ns)
+                && state.is_none_or(|state| ssn.status.state == state)
+        });
+        // The sessions are ordered before they're paged, so the pages do not overlap.
+        ssn_list.sort_by(|l, r| order.compare(l, r));
 
-        let sessions = ssn_list.iter().map(Session::from).collect();
+        let limit = (req.limit > 0).then_some(req.limit as usize);
+        let sessions = ssn_list
+            .iter()
+            .skip(req.offset as usize)
+            .take(limit.unwrap_or(ssn_list.len()))
+            .map(Session::from)
+            .collect();
 
         Ok(Response::new(SessionList { sessions }))
     }
 
+    #[tracing::instrument(name = "Frontend::list_session_template", skip_all)]
+    async fn list_session_template(
+        &self,
+        req: Request<ListSessionTemplateRequest>,
+    ) -> Result<Response<SessionTemplateList>, Status> {
+        continue_trace(&req);
+        self.identity(&req)?;
+        let templates = self
+            .storage
+            .list_session_templates()?
+            .iter()
+            .map(session_template)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Response::new(SessionTemplateList { templates }))
+    }
+
+    #[tracing::instrument(
+        name = "Frontend::clone_session",
+        skip_all,
+        fields(session_id = %req.get_ref().session_id)
+    )]
+    async fn clone_session(
+        &self,
+        req: Request<CloneSessionRequest>,
+    ) -> Result<Response<Session>, Status> {
+        continue_trace(&req);
+        let audit = self
+            .audit("CloneSession", &req)
+            .target("session_id", &req.get_ref().session_id);
+        self.audited(audit, async move {
+            let identity = self.identity(&req)?;
+            let req = req.into_inner();
+            let ssn_id = self.session_id(&identity, &req.namespace, &req.session_id)?;
+
+            let ssn = self
+                .storage
+                .clone_session(ssn_id, req.with_tasks, req.latest_application)
+                .await
+                .map(Session::from)?;
+
+            Ok(Response::new(ssn))
+        })
+        .await
+    }
+
+    /// Sends the session, then its tasks in the order of their ids; the tasks are read one by
+    /// one, so only a few outputs are copied at the same time.
+    #[tracing::instrument(
+        name = "Frontend::export_session",
+        skip_all,
+        fields(session_id = %req.get_ref().session_id)
+    )]
+    async fn export_session(
+        &self,
+        req: Request<ExportSessionRequest>,
+    ) -> Result<Response<Self::ExportSessionStream>, Status> {
+        continue_trace(&req);
+        let identity = self.identity(&req)?;
+        let req = req.into_inner();
+        let ssn_id = self.session_id(&identity, &req.namespace, &req.session_id)?;
+
+        let (ssn, mut task_ids) = {
+            let ssn_ptr = self.storage.get_session_ptr(ssn_id)?;
+            let ssn = lock_ptr!(ssn_ptr)?;
+            (
+                Session::from(&*ssn),
+                ssn.tasks.keys().cloned().collect::<Vec<_>>(),
+            )
+        };
+        task_ids.sort();
+
+        let (tx, rx) = mpsc::channel(16);
+
+        let storage = self.storage.clone();
+        tokio::spawn(async move {
+            let item = Item::Session(ssn);
+            if tx
+                .send(Ok(SessionArchive { item: Some(item) }))
+                .await
+                .is_err()
+            {
+                log::debug!("Export of Session <{}> was cancelled.", ssn_id);
+                return;
+            }
+
+            for task_id in task_ids {
+                let gid = apis::TaskGID { ssn_id, task_id };
+                let item = match storage.get_task(gid).await {
+                    Ok(task) => Ok(SessionArchive {
+                        item: Some(Item::Task(Task::from(&task))),
+                    }),
+                    Err(e) => Err(Status::from(e)),
+                };
+                let failed = item.is_err();
+                if tx.send(item).await.is_err() {
+                    log::debug!("Export of Session <{}> was cancelled.", ssn_id);
+                    return;
+                }
+                if failed {
+                    return;
+                }
+            }
+        });
+
+        let output_stream = ReceiverStream::new(rx);
+        Ok(Response::new(
+            Box::pin(output_stream) as Self::ExportSessionStream
+        ))
+    }
+
+    /// Recreates an archived session as closed; the archive starts with the session, and is
+    /// followed by its completed tasks.
+    #[tracing::instrument(name = "Frontend::import_session", skip_all)]
+    async fn import_session(
+        &self,
+        req: Request<Streaming<SessionArchive>>,
+    ) -> Result<Response<Session>, Status> {
+        continue_trace(&req);
+        let audit = self.audit("ImportSession", &req);
+        self.audited(audit, async move {
+            let identity = self.identity(&req)?;
+            let mut archive = req.into_inner();
+
+            let ssn = match archive.message().await? {
+                Some(SessionArchive {
+                    item: Some(Item::Session(ssn)),
+                }) => apis::Session::try_from(ssn)?,
+                _ => {
+                    return Err(Status::from(FlameError::invalid_argument(
+                        "session",
+                        "the archive must start with the session",
+                    )))
+                }
+            };
+            let namespace = identity.namespace(&ssn.namespace)?;
+
+            let mut tasks = vec![];
+            while let Some(item) = archive.message().await? {
+                match item.item {
+                    Some(Item::Task(task)) => tasks.push(apis::Task::try_from(task)?),
+                    _ => {
+                        return Err(Status::from(FlameError::invalid_argument(
+                            "session",
+                            "the archive must have only one session",
+                        )))
+                    }
+                }
+            }
+
+            let ssn = self
+                .storage
+                .import_session(namespace, ssn, tasks)
+                .await
+                .map(Session::from)?;
+
+            Ok(Response::new(ssn))
+        })
+        .await
+    }
+
+    #[tracing::instrument(
+        name = "Frontend::create_task",
+        skip_all,
+        fields(session_id = tracing::field::Empty)
+    )]
     async fn create_task(&self, req: Request<CreateTaskRequest>) -> Result<Response<Task>, Status> {
-        trace_fn!("Frontend::create_task");
-        let task_spec = req
-            .into_inner()
-            .task
-            .ok_or(Status::invalid_argument("session spec"))?;
-        let ssn_id = task_spec
-            .session_id
-            .parse::<apis::SessionID>()
-            .map_err(|_| Status::invalid_argument("invalid session id"))?;
+        let trace_context = continue_trace(&req);
+        let audit = self.audit("CreateTask", &req);
+        self.audited(audit, async move {
+            let identity = self.identity(&req)?;
+            let task_spec = req
+                .into_inner()
+                .task
+                .ok_or(FlameError::invalid_argument("spec", "task spec"))?;
+            let ssn_id = self.check_task_spec(&identity, &task_spec)?;
+            self.check_input(ssn_id, task_spec.input.as_deref())?;
 
-        let task = self
-            .storage
-            .create_task(ssn_id, task_spec.input.map(apis::TaskInput::from))
-            .await
-            .map(Task::from)
-            .map_err(Status::from)?;
+            let attrs = apis::TaskAttributes {
+                input: task_spec.input.map(apis::TaskInput::from),
+                trace_context,
+                labels: task_spec.labels,
+                locality: task_spec.locality,
+                env: task_spec.env,
+                timeout: task_spec.timeout,
+                max_retries: task_spec.max_retries,
+                idempotency_key: task_spec.idempotency_key,
+            };
+            let task = self
+                .storage
+                .create_task(ssn_id, attrs)
+                .await
+                .map(Task::from)
+                .map_err(Status::from)?;
 
-        Ok(Response::new(task))
+            Ok(Response::new(task))
+        })
+        .await
+    }
+
+    /// Creates a task whose input is received in chunks, and spooled past the threshold; the
+    /// stream starts with the spec of the task, and is committed by the size of the input.
+    #[tracing::instrument(
+        name = "Frontend::create_task_stream",
+        skip_all,
+        fields(session_id = tracing::field::Empty)
+    )]
+    async fn create_task_stream(
+        &self,
+        req: Request<Streaming<TaskChunk>>,
+    ) -> Result<Response<Task>, Status> {
+        let trace_context = continue_trace(&req);
+        let audit = self.audit("CreateTaskStream", &req);
+        self.audited(audit, async move {
+            let identity = self.identity(&req)?;
+            let mut chunks = req.into_inner();
+
+            let task_spec = match chunks.message().await?.and_then(|c| c.item) {
+                Some(Chunk::Task(spec)) if spec.input.is_none() => spec,
+                _ => {
+                    return Err(Status::from(FlameError::invalid_argument(
+                        "task",
+                        "the stream must start with the task without its input",
+                    )))
+                }
+            };
+            let ssn_id = self.check_task_spec(&identity, &task_spec)?;
+
+            // The spool is dropped with its file if the stream is aborted.
+            let mut spool = Spool::new(SPOOL_THRESHOLD);
+            let size = loop {
+                match chunks.message().await?.and_then(|c| c.item) {
+                    Some(Chunk::Data(data)) => spool.write(&data).await?,
+                    Some(Chunk::Size(size)) => break size,
+                    Some(Chunk::Task(_)) => {
+                        return Err(Status::from(FlameError::invalid_argument(
+                            "task",
+                            "the stream must have only one task",
+                        )))
+                    }
+                    None => {
+                        return Err(Status::from(FlameError::invalid_argument(
+                            "size",
+                            "the stream ended before the input was committed",
+                        )))
+                    }
+                }
+            };
+            if size != spool.len() as u64 {
+                return Err(Status::from(FlameError::invalid_argument(
+                    "size",
+                    format!("{} bytes committed, but {} received", size, spool.len()),
+                )));
+            }
+
+            let input = spool.into_bytes().await?;
+            self.check_input(ssn_id, Some(&input[..]))?;
+
+            let attrs = apis::TaskAttributes {
+                input: Some(input),
+                trace_context,
+                labels: task_spec.labels,
+                locality: task_spec.locality,
+                env: task_spec.env,
+                timeout: task_spec.timeout,
+                max_retries: task_spec.max_retries,
+                idempotency_key: task_spec.idempotency_key,
+            };
+            let mut task = self
+                .storage
+                .create_task(ssn_id, attrs)
+                .await
+                .map(Task::from)
+                .map_err(Status::from)?;
+            // The input is not sent back, as it may not fit in a message.
+            if let Some(spec) = task.spec.as_mut() {
+                spec.input = None;
+            }
+
+            Ok(Response::new(task))
+        })
+        .await
     }
+
     async fn delete_task(
         &self,
         _: Request<DeleteTaskRequest>,
@@ -164,29 +620,27 @@ impl Frontend for Flame {
         todo!()
     }
 
+    #[tracing::instrument(
+        name = "Frontend::watch_task",
+        skip_all,
+        fields(session_id = %req.get_ref().session_id, task_id = %req.get_ref().task_id)
+    )]
     async fn watch_task(
         &self,
         req: Request<WatchTaskRequest>,
     ) -> Result<Response<Self::WatchTaskStream>, Status> {
+        continue_trace(&req);
+        let identity = self.identity(&req)?;
         let req = req.into_inner();
-        let gid = apis::TaskGID {
-            ssn_id: req
-                .session_id
-                .parse::<apis::SessionID>()
-                .map_err(|_| Status::invalid_argument("invalid session id"))?,
-
-            task_id: req
-                .task_id
-                .parse::<apis::SessionID>()
-                .map_err(|_| Status::invalid_argument("invalid task id"))?,
-        };
+        let gid = apis::TaskGID::parse(&req.session_id, &req.task_id)?;
+        self.check_session(&identity, gid.ssn_id)?;
 
         let (tx, rx) = mpsc::channel(128);
 
         let storage = self.storage.clone();
         tokio::spawn(async move {
             loop {
-                match storage.watch_task(gid).await {
+                match storage.watch_task(gid, req.include_chunks).await {
                     Ok(task) => {
                         log::debug!("Task <{}> state is <{}>", task.id, task.state as i32);
                         if let Err(e) = tx.send(Result::<_, Status>::Ok(Task::from(&task))).await {
@@ -212,24 +666,4145 @@ impl Frontend for Flame {
         ))
     }
 
-    async fn get_task(&self, req: Request<GetTaskRequest>) -> Result<Response<Task>, Status> {
+    /// Sends the events of the session after the `since` sequence, until the session is closed.
+    #[tracing::instrument(
+        name = "Frontend::watch_session",
+        skip_all,
+        fields(session_id = %req.get_ref().session_id)
+    )]
+    async fn watch_session(
+        &self,
+        req: Request<WatchSessionRequest>,
+    ) -> Result<Response<Self::WatchSessionStream>, Status> {
+        continue_trace(&req);
+        let identity = self.identity(&req)?;
+        let req = req.into_inner();
+        let ssn_id = self.session_id(&identity, &req.namespace, &req.session_id)?;
+        let mut since = req.since;
+
+        let (tx, rx) = mpsc::channel(128);
+
+        let storage = self.storage.clone();
+        tokio::spawn(async move {
+            loop {
+                let events = match storage.watch_session(ssn_id, since).await {
+                    Ok(events) => events,
+                    Err(e) => {
+                        log::debug!("Failed to watch Session <{}>: {}", ssn_id, e);
+                        let _ = tx.send(Err(Status::from(e))).await;
+                        break;
+                    }
+                };
+                if events.is_empty() {
+                    log::debug!("Session <{}> is closed, exit.", ssn_id);
+                    break;
+                }
+
+                for event in &events {
+                    since = event.sequence;
+                    if let Err(e) = tx.send(Ok(SessionEvent::from(event))).await {
+                        log::debug!("Failed to send event of Session <{}>: {}", ssn_id, e);
+                        return;
+                    }
+                    if event.kind == apis::EventKind::SessionClosed {
+                        log::debug!("Session <{}> is closed, exit.", ssn_id);
+                        return;
+                    }
+                }
+            }
+        });
+
+        let output_stream = ReceiverStream::new(rx);
+        Ok(Response::new(
+            Box::pin(output_stream) as Self::WatchSessionStream
+        ))
+    }
+
+    /// Sends the output chunks of the task after the `since` sequence, until the task is
+    /// completed and all of its chunks are sent.
+    #[tracing::instrument(
+        name = "Frontend::fetch_task_output_chunks",
+        skip_all,
+        fields(session_id = %req.get_ref().session_id, task_id = %req.get_ref().task_id)
+    )]
+    async fn fetch_task_output_chunks(
+        &self,
+        req: Request<FetchTaskOutputChunksRequest>,
+    ) -> Result<Response<Self::FetchTaskOutputChunksStream>, Status> {
+        continue_trace(&req);
+        let identity = self.identity(&req)?;
+        let req = req.into_inner();
+        let ssn_id = self.session_id(&identity, &req.namespace, &req.session_id)?;
+        let gid = apis::TaskGID {
+            ssn_id,
+            task_id: apis::parse_task_id(&req.task_id)?,
+        };
+        self.storage.get_task_ptr(gid)?;
+        let mut since = req.since;
+
+        let (tx, rx) = mpsc::channel(128);
+
+        let storage = self.storage.clone();
+        tokio::spawn(async move {
+            loop {
+                let chunks = match storage
+                    .watch_output_chunks(gid, since, OUTPUT_CHUNK_PAGE)
+                    .await
+                {
+                    Ok(chunks) => chunks,
+                    Err(e) => {
+                        log::debug!("Failed to watch the output of Task <{}>: {}", gid, e);
+                        let _ = tx.send(Err(Status::from(e))).await;
+                        break;
+                    }
+                };
+                if chunks.is_empty() {
+                    log::debug!("Task <{}> is completed, exit.", gid);
+                    break;
+                }
+
+                for chunk in &chunks {
+                    since = chunk.sequence;
+                    if let Err(e) = tx.send(Ok(TaskOutputChunk::from(chunk))).await {
+                        log::debug!("Failed to send the output of Task <{}>: {}", gid, e);
+                        return;
+                    }
+                }
+            }
+        });
+
+        let output_stream = ReceiverStream::new(rx);
+        Ok(Response::new(
+            Box::pin(output_stream) as Self::FetchTaskOutputChunksStream
+        ))
+    }
+
+    /// Returns the persisted events of the session in the time range, e.g. for post-mortems
+    /// of the closed sessions.
+    #[tracing::instrument(
+        name = "Frontend::get_session_events",
+        skip_all,
+        fields(session_id = %req.get_ref().session_id)
+    )]
+    async fn get_session_events(
+        &self,
+        req: Request<GetSessionEventsRequest>,
+    ) -> Result<Response<SessionEventList>, Status> {
+        continue_trace(&req);
+        let identity = self.identity(&req)?;
         let req = req.into_inner();
-        let ssn_id = req
-            .session_id
-            .parse::<apis::SessionID>()
-            .map_err(|_| Status::invalid_argument("invalid session id"))?;
+        let ssn_id = self.session_id(&identity, &req.namespace, &req.session_id)?;
+        let since = req.since.map(|t| event_time("since", t)).transpose()?;
+        let until = req.until.map(|t| event_time("until", t)).transpose()?;
+
+        let events = self
+            .storage
+            .get_session_events(ssn_id, since, until)
+            .await
+            .map_err(Status::from)?;
+
+        Ok(Response::new(SessionEventList {
+            events: events.iter().map(SessionEvent::from).collect(),
+        }))
+    }
 
-        let task_id = req
-            .task_id
-            .parse::<apis::SessionID>()
-            .map_err(|_| Status::invalid_argument("invalid task id"))?;
+    #[tracing::instrument(
+        name = "Frontend::get_task",
+        skip_all,
+        fields(session_id = %req.get_ref().session_id, task_id = %req.get_ref().task_id)
+    )]
+    async fn get_task(&self, req: Request<GetTaskRequest>) -> Result<Response<Task>, Status> {
+        continue_trace(&req);
+        let identity = self.identity(&req)?;
+        let req = req.into_inner();
+        let gid = apis::TaskGID::parse(&req.session_id, &req.task_id)?;
+        self.check_session(&identity, gid.ssn_id)?;
 
         let task = self
             .storage
-            .get_task(ssn_id, task_id)
-            .map(Task::from)
+            .get_task(gid)
+            .await
+            .map(|t| Task::from(&t))
             .map_err(Status::from)?;
 
         Ok(Response::new(task))
     }
+
+    #[tracing::instrument(
+        name = "Frontend::list_task",
+        skip_all,
+        fields(session_id = %req.get_ref().session_id)
+    )]
+    async fn list_task(&self, req: Request<ListTaskRequest>) -> Result<Response<TaskList>, Status> {
+        continue_trace(&req);
+        let identity = self.identity(&req)?;
+        let req = req.into_inner();
+        let ssn_id = self.session_id(&identity, &req.namespace, &req.session_id)?;
+
+        let limit = match req.limit {
+            0 => MAX_TASK_PAGE,
+            limit => limit.min(MAX_TASK_PAGE),
+        };
+        let state = req.state.map(apis::TaskState::try_from).transpose()?;
+        let tasks = self
+            .storage
+            .list_tasks(
+                ssn_id,
+                req.start_index,
+                Some(limit as usize),
+                state,
+                req.annotation.as_deref(),
+                !req.skip_payloads,
+            )
+            .await?
+            .iter()
+            .map(Task::from)
+            .collect();
+
+        Ok(Response::new(TaskList { tasks }))
+    }
+
+    /// Streams the outputs in the order of the indexes of the tasks, see `send_outputs`.
+    #[tracing::instrument(
+        name = "Frontend::fetch_task_outputs",
+        skip_all,
+        fields(session_id = %req.get_ref().session_id)
+    )]
+    async fn fetch_task_outputs(
+        &self,
+        req: Request<FetchTaskOutputsRequest>,
+    ) -> Result<Response<Self::FetchTaskOutputsStream>, Status> {
+        continue_trace(&req);
+        let identity = self.identity(&req)?;
+        let req = req.into_inner();
+        let ssn_id = self.session_id(&identity, &req.namespace, &req.session_id)?;
+        let state = req.state.map(apis::TaskState::try_from).transpose()?;
+        let indexes = req.start_index..req.end_index.unwrap_or(u64::MAX);
+
+        let (tx, rx) = mpsc::channel(OUTPUT_BUFFER);
+        tokio::spawn(send_outputs(
+            self.storage.clone(),
+            ssn_id,
+            indexes,
+            state,
+            tx,
+        ));
+
+        let output_stream = ReceiverStream::new(rx);
+        Ok(Response::new(
+            Box::pin(output_stream) as Self::FetchTaskOutputsStream
+        ))
+    }
+
+    #[tracing::instrument(
+        name = "Frontend::resubmit_task",
+        skip_all,
+        fields(session_id = %req.get_ref().session_id, task_id = %req.get_ref().task_id)
+    )]
+    async fn resubmit_task(
+        &self,
+        req: Request<ResubmitTaskRequest>,
+    ) -> Result<Response<Task>, Status> {
+        continue_trace(&req);
+        let audit = self
+            .audit("ResubmitTask", &req)
+            .target("original_task_id", &req.get_ref().task_id);
+        self.audited(audit, async move {
+            let identity = self.identity(&req)?;
+            let req = req.into_inner();
+            let ssn_id = self.session_id(&identity, &req.namespace, &req.session_id)?;
+            let gid = apis::TaskGID {
+                ssn_id,
+                task_id: apis::parse_task_id(&req.task_id)?,
+            };
+
+            let task = self.storage.resubmit_task(gid).await.map(Task::from)?;
+
+            Ok(Response::new(task))
+        })
+        .await
+    }
+
+    #[tracing::instrument(name = "Frontend::register_application", skip_all)]
+    async fn register_application(
+        &self,
+        req: Request<RegisterApplicationRequest>,
+    ) -> Result<Response<rpc::Result>, Status> {
+        continue_trace(&req);
+        let audit = self.audit("RegisterApplication", &req).target(
+            "application",
+            req.get_ref()
+                .application
+                .as_ref()
+                .map(|app| app.name.clone())
+                .unwrap_or_default(),
+        );
+        self.audited(audit, async move {
+            self.identity(&req)?.check_cluster_scope()?;
+            let app = req
+                .into_inner()
+                .application
+                .ok_or(FlameError::invalid_argument(
+                    "application",
+                    "no application",
+                ))?;
+            let app = apis::Application::from(app);
+            if let Some(input) = &app.input {
+                validator::validators(input).map_err(|e| {
+                    FlameError::invalid_argument(
+                        "application",
+                        format!("application <{}>: {}", app.name, e),
+                    )
+                })?;
+            }
+
+            self.storage.register_application(app).await?;
+
+            Ok(Response::new(rpc::Result::default()))
+        })
+        .await
+    }
+
+    #[tracing::instrument(name = "Frontend::list_application", skip_all)]
+    async fn list_application(
+        &self,
+        req: Request<ListApplicationRequest>,
+    ) -> Result<Response<ApplicationList>, Status> {
+        continue_trace(&req);
+        self.identity(&req)?;
+        let app_list = self.storage.list_application()?;
+
+        let applications = app_list.iter().map(rpc::Application::from).collect();
+
+        Ok(Response::new(ApplicationList { applications }))
+    }
+
+    #[tracing::instrument(
+        name = "Frontend::delete_application",
+        skip_all,
+        fields(application = %req.get_ref().name)
+    )]
+    async fn delete_application(
+        &self,
+        req: Request<DeleteApplicationRequest>,
+    ) -> Result<Response<rpc::Result>, Status> {
+        continue_trace(&req);
+        let audit = self
+            .audit("DeleteApplication", &req)
+            .target("application", &req.get_ref().name);
+        self.audited(audit, async move {
+            self.identity(&req)?.check_cluster_scope()?;
+            let req = req.into_inner();
+            self.storage.delete_application(req.name, req.force).await?;
+
+            Ok(Response::new(rpc::Result::default()))
+        })
+        .await
+    }
+
+    #[tracing::instrument(name = "Frontend::list_executor", skip_all)]
+    async fn list_executor(
+        &self,
+        req: Request<ListExecutorRequest>,
+    ) -> Result<Response<ExecutorList>, Status> {
+        continue_trace(&req);
+        self.identity(&req)?;
+        let req = req.into_inner();
+        let mut exe_list = self.storage.list_executor().map_err(Status::from)?;
+        // The executors are ordered by their ids before they're paged, so the pages do not
+        // overlap.
+        exe_list.sort_by(|l, r| l.id.cmp(&r.id));
+
+        let limit = (req.limit > 0).then_some(req.limit as usize);
+        let executors = exe_list
+            .iter()
+            .skip(req.offset as usize)
+            .take(limit.unwrap_or(exe_list.len()))
+            .map(|exe| executor(&self.storage, exe))
+            .collect::<Result<_, _>>()
+            .map_err(Status::from)?;
+
+        Ok(Response::new(ExecutorList { executors }))
+    }
+
+    #[tracing::instrument(
+        name = "Frontend::get_executor",
+        skip_all,
+        fields(executor_id = %req.get_ref().executor_id)
+    )]
+    async fn get_executor(
+        &self,
+        req: Request<GetExecutorRequest>,
+    ) -> Result<Response<Executor>, Status> {
+        continue_trace(&req);
+        self.identity(&req)?;
+        let exe = self
+            .storage
+            .get_executor(req.into_inner().executor_id)
+            .and_then(|exe| executor(&self.storage, &exe))
+            .map_err(Status::from)?;
+
+        Ok(Response::new(exe))
+    }
+
+    #[tracing::instrument(
+        name = "Frontend::drain_executor",
+        skip_all,
+        fields(executor_id = %req.get_ref().executor_id)
+    )]
+    async fn drain_executor(
+        &self,
+        req: Request<DrainExecutorRequest>,
+    ) -> Result<Response<Executor>, Status> {
+        continue_trace(&req);
+        let audit = self
+            .audit("DrainExecutor", &req)
+            .target("executor_id", &req.get_ref().executor_id);
+        self.audited(audit, async move {
+            self.identity(&req)?.check_cluster_scope()?;
+            let exe = self
+                .storage
+                .drain_executor(req.into_inner().executor_id)
+                .and_then(|exe| executor(&self.storage, &exe))
+                .map_err(Status::from)?;
+
+            Ok(Response::new(exe))
+        })
+        .await
+    }
+
+    #[tracing::instrument(
+        name = "Frontend::uncordon_executor",
+        skip_all,
+        fields(executor_id = %req.get_ref().executor_id)
+    )]
+    async fn uncordon_executor(
+        &self,
+        req: Request<UncordonExecutorRequest>,
+    ) -> Result<Response<Executor>, Status> {
+        continue_trace(&req);
+        let audit = self
+            .audit("UncordonExecutor", &req)
+            .target("executor_id", &req.get_ref().executor_id);
+        self.audited(audit, async move {
+            self.identity(&req)?.check_cluster_scope()?;
+            let exe = self
+                .storage
+                .uncordon_executor(req.into_inner().executor_id)
+                .and_then(|exe| executor(&self.storage, &exe))
+                .map_err(Status::from)?;
+
+            Ok(Response::new(exe))
+        })
+        .await
+    }
+
+    /// The information of the server is public, so the clients can check it before
+    /// authentication.
+    #[tracing::instrument(name = "Frontend::get_server_info", skip_all)]
+    async fn get_server_info(
+        &self,
+        _: Request<GetServerInfoRequest>,
+    ) -> Result<Response<ServerInfo>, Status> {
+        Ok(Response::new(ServerInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            build: build_info(),
+            capabilities: capability::ALL.iter().map(|c| c.to_string()).collect(),
+        }))
+    }
+}
+
+impl Flame {
+    /// Checks the spec of a task to create, and returns the id of its session.
+    fn check_task_spec(
+        &self,
+        identity: &Identity,
+        spec: &TaskSpec,
+    ) -> Result<SessionID, FlameError> {
+        let ssn_id = apis::parse_session_id(&spec.session_id)?;
+        tracing::Span::current().record("session_id", ssn_id);
+        self.check_session(identity, ssn_id)?;
+        if spec.timeout == Some(0) {
+            return Err(FlameError::invalid_argument(
+                "timeout",
+                "must be greater than 0",
+            ));
+        }
+        if spec
+            .locality
+            .as_deref()
+            .is_some_and(|l| l.trim().is_empty())
+        {
+            return Err(FlameError::invalid_argument(
+                "locality",
+                "must not be empty",
+            ));
+        }
+        apis::check_task_env(&spec.env)?;
+        if let Some(key) = &spec.idempotency_key {
+            if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY {
+                return Err(FlameError::invalid_argument(
+                    "idempotency_key",
+                    format!("expect 1 to {} bytes", MAX_IDEMPOTENCY_KEY),
+                ));
+            }
+        }
+
+        Ok(ssn_id)
+    }
+
+    /// Checks the input of a task by the constraints of the application of the session; it's
+    /// not checked if the application is not found here, as the executors may have it.
+    fn check_input(&self, ssn_id: SessionID, input: Option<&[u8]>) -> Result<(), FlameError> {
+        let ssn = self.storage.get_session(ssn_id)?;
+        match self.storage.session_application(&ssn) {
+            Ok(apis::Application {
+                input: Some(constraints),
+                ..
+            }) => validator::check("input", &constraints, input.unwrap_or_default()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the common data of a new session, if the constraints of its application are
+    /// strict.
+    fn check_common_data(&self, application: &str, data: &[u8]) -> Result<(), FlameError> {
+        match self.storage.get_application(application) {
+            Ok(apis::Application {
+                input: Some(constraints),
+                ..
+            }) if constraints.strict => validator::check("common_data", &constraints, data),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The executor with its utilization, which is tracked by the storage apart from it.
+fn executor(storage: &StoragePtr, exe: &apis::Executor) -> Result<Executor, FlameError> {
+    let usage = storage.executor_usage(&exe.id)?;
+    let mut exe = Executor::from(exe);
+    if let Some(status) = exe.status.as_mut() {
+        status.utilization = usage
+            .windows
+            .iter()
+            .map(|(window, percentage)| rpc::Utilization {
+                window: window.as_secs(),
+                percentage: *percentage,
+            })
+            .collect();
+        status.busy_seconds = usage.busy_seconds;
+        status.reported_busy_seconds = usage.reported_busy_seconds;
+    }
+
+    Ok(exe)
+}
+
+fn session_template(template: &FlameSessionTemplate) -> Result<rpc::SessionTemplate, FlameError> {
+    Ok(rpc::SessionTemplate {
+        name: template.name.clone(),
+        application: template.application.clone(),
+        slots: template.slots,
+        labels: template.labels.clone(),
+        max_pending_tasks: template.max_pending_tasks,
+        timeout: template.timeout()?.map(|d| d.as_secs()),
+    })
+}
+
+/// Fills the fields not set in the spec by the template; the labels are merged, and the
+/// ones of the spec win on conflicts.
+fn expand_template(
+    spec: SessionSpec,
+    template: &FlameSessionTemplate,
+) -> Result<SessionSpec, FlameError> {
+    let mut labels = template.labels.clone();
+    labels.extend(spec.labels);
+
+    Ok(SessionSpec {
+        application: match spec.application.is_empty() {
+            true => template.application.clone(),
+            false => spec.application,
+        },
+        slots: match spec.slots {
+            0 => template.slots.unwrap_or_default(),
+            slots => slots,
+        },
+        labels,
+        max_pending_tasks: spec.max_pending_tasks.or(template.max_pending_tasks),
+        timeout: match spec.timeout {
+            Some(timeout) => Some(timeout),
+            None => template.timeout()?.map(|d| d.as_secs()),
+        },
+        ..spec
+    })
+}
+
+/// The deadline of a session, which is either the time in seconds since epoch or the seconds
+/// from now; it has to be in the future.
+fn session_deadline(
+    deadline: Option<i64>,
+    timeout: Option<u64>,
+) -> Result<Option<DateTime<Utc>>, FlameError> {
+    let now = Utc::now();
+    let secs = match (deadline, timeout) {
+        (Some(deadline), _) => deadline,
+        (None, Some(timeout)) => i64::try_from(timeout)
+            .ok()
+            .and_then(|timeout| now.timestamp().checked_add(timeout))
+            .ok_or(FlameError::invalid_argument("timeout", "too large"))?,
+        (None, None) => return Ok(None),
+    };
+
+    match DateTime::<Utc>::from_timestamp(secs, 0) {
+        Some(deadline) if deadline > now => Ok(Some(deadline)),
+        Some(_) => Err(FlameError::invalid_argument(
+            "deadline",
+            "must be in the future",
+        )),
+        None => Err(FlameError::invalid_argument(
+            "deadline",
+            "invalid timestamp",
+        )),
+    }
+}
+
+/// Sends the outputs of the tasks of the session whose indexes are in the range, reading a
+/// page of the tasks at a time; the payloads of the spilled tasks are loaded one by one when
+/// they're sent. Only a page and the outputs buffered by the channel are kept in memory, and
+/// the reads wait for a slow client.
+async fn send_outputs(
+    storage: StoragePtr,
+    ssn_id: apis::SessionID,
+    indexes: Range<u64>,
+    state: Option<apis::TaskState>,
+    tx: mpsc::Sender<Result<TaskOutputEntry, Status>>,
+) {
+    let mut start = indexes.start;
+    loop {
+        let page = match storage
+            .list_tasks(ssn_id, start, Some(OUTPUT_PAGE), state, None, false)
+            .await
+        {
+            Ok(page) => page,
+            Err(e) => {
+                let _ = tx.send(Err(Status::from(e))).await;
+                return;
+            }
+        };
+
+        let n = page.len();
+        for task in page {
+            if !indexes.contains(&task.index) {
+                return;
+            }
+            start = task.index + 1;
+
+            let task = match task.spilled {
+                true => storage.get_task(task.gid()).await,
+                false => Ok(task),
+            };
+            let entry = task.map_err(Status::from).map(|task| TaskOutputEntry {
+                task_id: task.id.to_string(),
+                index: task.index,
+                output: task.output.map(apis::TaskOutput::into),
+            });
+            let failed = entry.is_err();
+            if tx.send(entry).await.is_err() {
+                log::debug!(
+                    "Fetch of the outputs of Session <{}> was cancelled.",
+                    ssn_id
+                );
+                return;
+            }
+            if failed {
+                return;
+            }
+        }
+
+        if n < OUTPUT_PAGE {
+            return;
+        }
+    }
+}
+
+/// The bound of the time range of the events, in seconds since epoch.
+fn event_time(field: &str, secs: i64) -> Result<DateTime<Utc>, FlameError> {
+    DateTime::<Utc>::from_timestamp(secs, 0)
+        .ok_or(FlameError::invalid_argument(field, "invalid timestamp"))
+}
+
+/// The profile of the build, with the commit set by `FLAME_BUILD` at build time if any.
+fn build_info() -> String {
+    let profile = match cfg!(debug_assertions) {
+        true => "debug",
+        false => "release",
+    };
+
+    match option_env!("FLAME_BUILD") {
+        Some(build) => format!("{} ({})", build, profile),
+        None => profile.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use common::ctx::FlameQuarantineConf;
+    use common::trace;
+    use tokio_stream::StreamExt;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::Subscriber;
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+    use tracing_subscriber::Layer;
+
+    use common::ctx::{FlameAuthConf, FlameRateLimitConf, FlameTokenConf};
+
+    use self::rpc::backend_server::Backend;
+    use self::rpc::{
+        BindExecutorCompletedRequest, BindExecutorRequest, CompleteTaskRequest, ExecutorSpec,
+        GetApplicationRequest, LaunchTaskRequest, RegisterExecutorRequest,
+        ReportTaskProgressRequest, SessionSpec, TaskProgress, TaskSpec, UnregisterExecutorRequest,
+    };
+    use crate::apiserver::auth::{Auth, AUTHORIZATION};
+    use crate::apiserver::limiter::Limiter;
+    use crate::apiserver::throttled_calls;
+    use crate::storage;
+
+    #[derive(Debug, Default)]
+    struct SpanRecord {
+        name: &'static str,
+        parent: Option<&'static str>,
+        fields: HashMap<String, String>,
+    }
+
+    impl Visit for SpanRecord {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.fields
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    /// Captures the spans and their fields.
+    #[derive(Clone, Default)]
+    struct Spans(Arc<Mutex<Vec<SpanRecord>>>);
+
+    impl<S> Layer<S> for Spans
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            let span = ctx.span(id).unwrap();
+            let mut record = SpanRecord {
+                name: span.name(),
+                parent: span.parent().map(|p| p.name()),
+                ..Default::default()
+            };
+            attrs.record(&mut record);
+
+            let mut spans = self.0.lock().unwrap();
+            span.extensions_mut().insert(spans.len());
+            spans.push(record);
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+            let span = ctx.span(id).unwrap();
+            let index = *span.extensions().get::<usize>().unwrap();
+            values.record(&mut self.0.lock().unwrap()[index]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_task_spans() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_create_task_spans_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        flame.storage.set_config_applications(&[apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        }])?;
+
+        let spans = Spans::default();
+        let subscriber = tracing_subscriber::registry().with(spans.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let req = CreateSessionRequest {
+            session: Some(SessionSpec {
+                application: "flmexec".to_string(),
+                slots: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let ssn = flame.create_session(Request::new(req)).await?.into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let mut req = Request::new(CreateTaskRequest {
+            task: Some(TaskSpec {
+                session_id: ssn_id.clone(),
+                ..Default::default()
+            }),
+        });
+        req.metadata_mut()
+            .insert(trace::TRACEPARENT, traceparent.parse().unwrap());
+        let task = flame.create_task(req).await?.into_inner();
+
+        // The trace context of the caller is persisted for the executor.
+        let gid = apis::TaskGID::parse(&ssn_id, &task.metadata.unwrap().id)?;
+        let task = flame.storage.get_task(gid).await?;
+        assert_eq!(task.trace_context.as_deref(), Some(traceparent));
+
+        let spans = spans.0.lock().unwrap();
+        let expected = [
+            ("Frontend::create_task", None),
+            ("Storage::create_task", Some("Frontend::create_task")),
+            ("SqliteEngine::create_task", Some("Storage::create_task")),
+        ];
+        for (name, parent) in expected {
+            let span = spans
+                .iter()
+                .find(|s| s.name == name)
+                .unwrap_or_else(|| panic!("no span <{}> in {:?}", name, spans));
+            assert_eq!(span.parent, parent, "{}", name);
+            assert_eq!(span.fields.get("session_id"), Some(&ssn_id), "{}", name);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_application_registry() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_application_registry_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+
+        let create_session = |application: &str| CreateSessionRequest {
+            session: Some(SessionSpec {
+                application: application.to_string(),
+                slots: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let e = flame
+            .create_session(Request::new(create_session("pi")))
+            .await
+            .unwrap_err();
+        assert_eq!(e.code(), tonic::Code::NotFound);
+
+        let app = rpc::Application {
+            name: "pi".to_string(),
+            shim: rpc::Shim::StdioShim as i32,
+            command: "/usr/bin/pi".to_string(),
+            working_directory: "/tmp".to_string(),
+            ..Default::default()
+        };
+        let req = RegisterApplicationRequest {
+            application: Some(app.clone()),
+        };
+        flame
+            .register_application(Request::new(req.clone()))
+            .await?;
+        let e = flame.register_application(Request::new(req)).await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::AlreadyExists);
+
+        let app_list = flame
+            .list_application(Request::new(ListApplicationRequest::default()))
+            .await?
+            .into_inner();
+        assert_eq!(app_list.applications, vec![app.clone()]);
+
+        let ssn = flame
+            .create_session(Request::new(create_session("pi")))
+            .await?
+            .into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+
+        // The executor does not have the application in its configuration.
+        let req = RegisterExecutorRequest {
+            executor_id: "exec-1".to_string(),
+            executor_spec: Some(ExecutorSpec {
+                slots: 1,
+                ..Default::default()
+            }),
+        };
+        flame.register_executor(Request::new(req)).await?;
+        assert!(flame
+            .storage
+            .snapshot()?
+            .borrow()
+            .applications
+            .contains("pi"));
+
+        let id = apis::parse_session_id(&ssn_id)?;
+        flame.storage.bind_session("exec-1".to_string(), id).await?;
+        let req = BindExecutorRequest {
+            executor_id: "exec-1".to_string(),
+        };
+        let ssn = flame.bind_executor(Request::new(req)).await?.into_inner();
+        let application = ssn.spec.unwrap().application;
+
+        let req = GetApplicationRequest { name: application };
+        let bound = flame.get_application(Request::new(req)).await?.into_inner();
+        assert_eq!(bound, app);
+
+        let delete_application = |force| DeleteApplicationRequest {
+            name: "pi".to_string(),
+            force,
+        };
+        let e = flame
+            .delete_application(Request::new(delete_application(false)))
+            .await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::FailedPrecondition);
+
+        flame
+            .delete_application(Request::new(delete_application(true)))
+            .await?;
+        let app_list = flame
+            .list_application(Request::new(ListApplicationRequest::default()))
+            .await?
+            .into_inner();
+        assert!(app_list.applications.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unregister_executor() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_unregister_executor_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        flame.storage.set_config_applications(&[apis::Application {
+            name: "pi".to_string(),
+            ..Default::default()
+        }])?;
+
+        let register_executor = |id: &str| RegisterExecutorRequest {
+            executor_id: id.to_string(),
+            executor_spec: Some(ExecutorSpec {
+                slots: 1,
+                ..Default::default()
+            }),
+        };
+        let unregister_executor = |id: &str| UnregisterExecutorRequest {
+            executor_id: id.to_string(),
+            force: false,
+        };
+
+        flame
+            .register_executor(Request::new(register_executor("exec-1")))
+            .await?;
+        flame
+            .unregister_executor(Request::new(unregister_executor("exec-1")))
+            .await?;
+        assert!(flame.storage.list_executor()?.is_empty());
+
+        let e = flame
+            .unregister_executor(Request::new(unregister_executor("exec-1")))
+            .await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::NotFound);
+
+        // The executor bound to a session has to be unbound first.
+        let ssn = flame
+            .create_session(Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: "pi".to_string(),
+                    slots: 1,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner();
+        let id = apis::parse_session_id(&ssn.metadata.unwrap().id)?;
+        flame
+            .register_executor(Request::new(register_executor("exec-2")))
+            .await?;
+        flame.storage.bind_session("exec-2".to_string(), id).await?;
+        let e = flame
+            .unregister_executor(Request::new(unregister_executor("exec-2")))
+            .await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::FailedPrecondition);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_executor_pages() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_list_executor_pages_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+
+        for id in ["exec-3", "exec-1", "exec-4", "exec-2", "exec-0"] {
+            let req = RegisterExecutorRequest {
+                executor_id: id.to_string(),
+                executor_spec: Some(ExecutorSpec {
+                    slots: 1,
+                    ..Default::default()
+                }),
+            };
+            flame.register_executor(Request::new(req)).await?;
+        }
+
+        let list_executor = |offset: u32, limit: u32| {
+            let req = ListExecutorRequest { offset, limit };
+            let flame = &flame;
+            async move {
+                let exe_list = flame.list_executor(Request::new(req)).await?.into_inner();
+                Ok::<_, Status>(
+                    exe_list
+                        .executors
+                        .into_iter()
+                        .map(|exe| exe.metadata.unwrap().id)
+                        .collect::<Vec<_>>(),
+                )
+            }
+        };
+
+        // The executors are ordered by their ids, so the pages do not overlap.
+        let all = list_executor(0, 0).await?;
+        assert_eq!(all, vec!["exec-0", "exec-1", "exec-2", "exec-3", "exec-4"]);
+        let mut pages = vec![];
+        for offset in (0..all.len() as u32 + 2).step_by(2) {
+            pages.extend(list_executor(offset, 2).await?);
+        }
+        assert_eq!(pages, all);
+        assert_eq!(list_executor(4, 2).await?, vec!["exec-4"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bind_executor_with_application() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_bind_executor_with_application_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+
+        let app = rpc::Application {
+            name: "pi".to_string(),
+            shim: rpc::Shim::StdioShim as i32,
+            command: "/usr/bin/pi".to_string(),
+            arguments: vec!["--server".to_string()],
+            environments: vec!["PI_MODE=server".to_string()],
+            working_directory: "/tmp".to_string(),
+            ..Default::default()
+        };
+        flame
+            .register_application(Request::new(RegisterApplicationRequest {
+                application: Some(app.clone()),
+            }))
+            .await?;
+
+        let ssn = flame
+            .create_session(Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: "pi".to_string(),
+                    slots: 1,
+                    common_data: Some(b"common".to_vec()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+
+        // The local configuration of the executor drifted from the session manager.
+        let local = rpc::Application {
+            command: "/opt/pi/bin/pi".to_string(),
+            arguments: vec![],
+            environments: vec![],
+            ..app.clone()
+        };
+        flame
+            .register_executor(Request::new(RegisterExecutorRequest {
+                executor_id: "exec-1".to_string(),
+                executor_spec: Some(ExecutorSpec {
+                    slots: 1,
+                    applications: vec![local],
+                    ..Default::default()
+                }),
+            }))
+            .await?;
+
+        let id = apis::parse_session_id(&ssn_id)?;
+        flame.storage.bind_session("exec-1".to_string(), id).await?;
+        let bound = flame
+            .bind_executor(Request::new(BindExecutorRequest {
+                executor_id: "exec-1".to_string(),
+            }))
+            .await?
+            .into_inner();
+
+        assert_eq!(bound.metadata.unwrap().id, ssn_id);
+        assert_eq!(bound.spec.unwrap().common_data, Some(b"common".to_vec()));
+        assert_eq!(bound.application, Some(app));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bind_executor_with_recorded_application() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_bind_executor_with_recorded_application_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+
+        let v1 = rpc::Application {
+            name: "pi".to_string(),
+            shim: rpc::Shim::StdioShim as i32,
+            command: "/usr/bin/pi".to_string(),
+            arguments: vec!["--model".to_string(), "/models/v1".to_string()],
+            working_directory: "/tmp".to_string(),
+            ..Default::default()
+        };
+        let v2 = rpc::Application {
+            arguments: vec!["--model".to_string(), "/models/v2".to_string()],
+            ..v1.clone()
+        };
+        flame
+            .register_application(Request::new(RegisterApplicationRequest {
+                application: Some(v1.clone()),
+            }))
+            .await?;
+
+        let create_session = |latest_application| {
+            Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: "pi".to_string(),
+                    slots: 1,
+                    latest_application,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        };
+        let pinned = flame
+            .create_session(create_session(false))
+            .await?
+            .into_inner();
+        let latest = flame
+            .create_session(create_session(true))
+            .await?
+            .into_inner();
+        let fingerprint = apis::Application::from(v1.clone()).fingerprint();
+        let spec = pinned.spec.clone().unwrap();
+        assert!(!spec.latest_application);
+        assert_eq!(spec.application_fingerprint, Some(fingerprint.clone()));
+        let spec = latest.spec.clone().unwrap();
+        assert!(spec.latest_application);
+        assert_eq!(spec.application_fingerprint, None);
+
+        // The application is changed after the sessions were created.
+        flame
+            .delete_application(Request::new(DeleteApplicationRequest {
+                name: "pi".to_string(),
+                force: true,
+            }))
+            .await?;
+        flame
+            .register_application(Request::new(RegisterApplicationRequest {
+                application: Some(v2.clone()),
+            }))
+            .await?;
+
+        // The clones run the application of the source, unless they run the latest one.
+        let pinned_id = pinned.metadata.unwrap().id;
+        let clone_session = |latest_application| {
+            Request::new(CloneSessionRequest {
+                session_id: pinned_id.clone(),
+                latest_application,
+                ..Default::default()
+            })
+        };
+        let clone = flame
+            .clone_session(clone_session(false))
+            .await?
+            .into_inner();
+        let latest_clone = flame.clone_session(clone_session(true)).await?.into_inner();
+        assert_eq!(
+            clone.spec.unwrap().application_fingerprint,
+            Some(fingerprint.clone())
+        );
+        assert_eq!(latest_clone.spec.unwrap().application_fingerprint, None);
+
+        let bind = |i: usize, ssn_id: String| {
+            let flame = &flame;
+            async move {
+                let executor_id = format!("exec-{}", i);
+                flame
+                    .register_executor(Request::new(RegisterExecutorRequest {
+                        executor_id: executor_id.clone(),
+                        executor_spec: Some(ExecutorSpec {
+                            slots: 1,
+                            ..Default::default()
+                        }),
+                    }))
+                    .await?;
+                let id = apis::parse_session_id(&ssn_id)?;
+                flame.storage.bind_session(executor_id.clone(), id).await?;
+                let bound = flame
+                    .bind_executor(Request::new(BindExecutorRequest { executor_id }))
+                    .await?
+                    .into_inner();
+                Ok::<_, FlameError>(bound.application)
+            }
+        };
+        assert_eq!(bind(1, pinned_id.clone()).await?, Some(v1.clone()));
+        assert_eq!(
+            bind(2, latest.metadata.unwrap().id).await?,
+            Some(v2.clone())
+        );
+        assert_eq!(bind(3, clone.metadata.unwrap().id).await?, Some(v1.clone()));
+        assert_eq!(
+            bind(4, latest_clone.metadata.unwrap().id).await?,
+            Some(v2.clone())
+        );
+
+        // The recorded application survives the restart of the session manager.
+        let restarted = storage::new_ptr(&url).await?;
+        restarted.load_data().await?;
+        let ssn = restarted.get_session(apis::parse_session_id(&pinned_id)?)?;
+        assert_eq!(ssn.application_spec, Some(apis::Application::from(v1)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_pending_tasks() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_max_pending_tasks_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        flame.storage.set_config_applications(&[apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        }])?;
+        flame.storage.set_max_pending_tasks(Some(3))?;
+
+        let create_session = |max_pending_tasks: Option<u32>| {
+            Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: "flmexec".to_string(),
+                    slots: 1,
+                    max_pending_tasks,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        };
+        let create_task = |ssn_id: &str| {
+            Request::new(CreateTaskRequest {
+                task: Some(TaskSpec {
+                    session_id: ssn_id.to_string(),
+                    ..Default::default()
+                }),
+            })
+        };
+
+        let e = flame.create_session(create_session(Some(0))).await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+        // The session is full with the default limit.
+        let ssn = flame
+            .create_session(create_session(None))
+            .await?
+            .into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+        let mut tasks = vec![];
+        for _ in 0..3 {
+            let task = flame.create_task(create_task(&ssn_id)).await?.into_inner();
+            tasks.push(apis::TaskGID::parse(&ssn_id, &task.metadata.unwrap().id)?);
+        }
+        let status = flame.create_task(create_task(&ssn_id)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        let e = FlameError::from(status);
+        assert!(e.is_retryable());
+        assert_eq!(e.retry_after(), Some(Duration::from_secs(1)));
+
+        // The running tasks are counted, but the completed ones are not.
+        let ssn_ptr = flame.storage.get_session_ptr(tasks[0].ssn_id)?;
+        for (gid, state) in tasks.iter().zip([
+            apis::TaskState::Running,
+            apis::TaskState::Succeed,
+            apis::TaskState::Failed,
+        ]) {
+            let task_ptr = flame.storage.get_task_ptr(*gid)?;
+            flame
+                .storage
+                .update_task_state(ssn_ptr.clone(), task_ptr, state)
+                .await?;
+        }
+        for _ in 0..2 {
+            flame.create_task(create_task(&ssn_id)).await?;
+        }
+        let e = flame.create_task(create_task(&ssn_id)).await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::ResourceExhausted);
+
+        // The limit of the session overrides the default one.
+        let ssn = flame
+            .create_session(create_session(Some(5)))
+            .await?
+            .into_inner();
+        assert_eq!(ssn.spec.unwrap().max_pending_tasks, Some(5));
+        let ssn_id = ssn.metadata.unwrap().id;
+        for _ in 0..5 {
+            flame.create_task(create_task(&ssn_id)).await?;
+        }
+        let e = flame.create_task(create_task(&ssn_id)).await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::ResourceExhausted);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_task_cache() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_task_cache_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        flame.storage.set_config_applications(&[apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        }])?;
+
+        let create_session = |scope: apis::CacheScope, common_data: &str| {
+            let flame = &flame;
+            let req = Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: "flmexec".to_string(),
+                    slots: 1,
+                    common_data: Some(common_data.as_bytes().to_vec()),
+                    cache_scope: rpc::CacheScope::from(scope) as i32,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+            async move {
+                let ssn = flame.create_session(req).await?.into_inner();
+                Ok::<_, FlameError>(ssn.metadata.unwrap().id)
+            }
+        };
+        let create_task = |ssn_id: &str, input: &str| {
+            let flame = &flame;
+            let req = Request::new(CreateTaskRequest {
+                task: Some(TaskSpec {
+                    session_id: ssn_id.to_string(),
+                    input: Some(input.as_bytes().to_vec()),
+                    ..Default::default()
+                }),
+            });
+            async move {
+                let task = flame.create_task(req).await?.into_inner();
+                Ok::<_, FlameError>((
+                    task.metadata.unwrap().id,
+                    task.status.unwrap().state,
+                    task.spec.unwrap().output,
+                ))
+            }
+        };
+        let complete_task = |ssn_id: &str, task_id: &str, output: &str| {
+            let storage = flame.storage.clone();
+            let gid = apis::TaskGID::parse(ssn_id, task_id);
+            let output = apis::TaskOutput::from(output.to_string());
+            async move {
+                let gid = gid?;
+                let task_ptr = storage.get_task_ptr(gid)?;
+                task_ptr.lock().unwrap().output = Some(output);
+                let ssn_ptr = storage.get_session_ptr(gid.ssn_id)?;
+                storage
+                    .update_task_state(ssn_ptr, task_ptr, apis::TaskState::Succeed)
+                    .await
+            }
+        };
+        let pending = rpc::TaskState::TaskPending as i32;
+        let succeed = rpc::TaskState::TaskSucceed as i32;
+
+        // The output is reused by the same input of the session, but not by the others.
+        let ssn_1 = create_session(apis::CacheScope::Session, "model-1").await?;
+        let (task_id, state, _) = create_task(&ssn_1, "x").await?;
+        assert_eq!(state, pending);
+        complete_task(&ssn_1, &task_id, "output of x").await?;
+
+        let (_, state, output) = create_task(&ssn_1, "x").await?;
+        assert_eq!(state, succeed);
+        assert_eq!(output, Some(b"output of x".to_vec()));
+        let (_, state, _) = create_task(&ssn_1, "y").await?;
+        assert_eq!(state, pending);
+
+        let ssn_2 = create_session(apis::CacheScope::Session, "model-1").await?;
+        assert_eq!(create_task(&ssn_2, "x").await?.1, pending);
+        let ssn_3 = create_session(apis::CacheScope::None, "model-1").await?;
+        assert_eq!(create_task(&ssn_3, "x").await?.1, pending);
+
+        // The outputs are only shared by the sessions which opt in.
+        let ssn_4 = create_session(apis::CacheScope::Application, "model-1").await?;
+        let (task_id, state, _) = create_task(&ssn_4, "x").await?;
+        assert_eq!(state, pending);
+        complete_task(&ssn_4, &task_id, "shared output of x").await?;
+
+        let ssn_5 = create_session(apis::CacheScope::Application, "model-1").await?;
+        let (_, state, output) = create_task(&ssn_5, "x").await?;
+        assert_eq!(state, succeed);
+        assert_eq!(output, Some(b"shared output of x".to_vec()));
+        assert_eq!(create_task(&ssn_2, "x").await?.1, pending);
+
+        // The changed common data, e.g. a new model, invalidates the outputs.
+        let ssn_6 = create_session(apis::CacheScope::Application, "model-2").await?;
+        assert_eq!(create_task(&ssn_6, "x").await?.1, pending);
+
+        let ssn = flame.storage.get_session(apis::parse_session_id(&ssn_5)?)?;
+        assert_eq!(ssn.cache_scope, apis::CacheScope::Application);
+        assert_eq!(ssn.status.counts.succeed, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_task_progress() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_task_progress_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        let app = apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        };
+        flame
+            .storage
+            .set_config_applications(std::slice::from_ref(&app))?;
+
+        let ssn = flame
+            .create_session(Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: app.name.clone(),
+                    slots: 1,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+        let task = flame
+            .create_task(Request::new(CreateTaskRequest {
+                task: Some(TaskSpec {
+                    session_id: ssn_id.clone(),
+                    ..Default::default()
+                }),
+            }))
+            .await?
+            .into_inner();
+        let task_id = task.metadata.unwrap().id;
+
+        for executor_id in ["exec-1", "exec-2"] {
+            let req = RegisterExecutorRequest {
+                executor_id: executor_id.to_string(),
+                executor_spec: Some(ExecutorSpec {
+                    slots: 1,
+                    applications: vec![rpc::Application::from(&app)],
+                    ..Default::default()
+                }),
+            };
+            flame.register_executor(Request::new(req)).await?;
+        }
+        flame
+            .storage
+            .bind_session("exec-1".to_string(), apis::parse_session_id(&ssn_id)?)
+            .await?;
+        let executor_id = "exec-1".to_string();
+        flame
+            .bind_executor(Request::new(BindExecutorRequest {
+                executor_id: executor_id.clone(),
+            }))
+            .await?;
+        flame
+            .bind_executor_completed(Request::new(BindExecutorCompletedRequest {
+                executor_id: executor_id.clone(),
+                ..Default::default()
+            }))
+            .await?;
+        flame
+            .launch_task(Request::new(LaunchTaskRequest {
+                executor_id: executor_id.clone(),
+            }))
+            .await?;
+
+        // The fake shim of the executor reports the progress of the task.
+        let report = |executor_id: &str, percentage: u32| {
+            Request::new(ReportTaskProgressRequest {
+                executor_id: executor_id.to_string(),
+                session_id: ssn_id.clone(),
+                task_id: task_id.clone(),
+                progress: Some(TaskProgress {
+                    percentage,
+                    payload: Some(format!("{}%", percentage).into_bytes()),
+                    update_time: 0,
+                }),
+            })
+        };
+        let get_task = || {
+            Request::new(GetTaskRequest {
+                session_id: ssn_id.clone(),
+                task_id: task_id.clone(),
+            })
+        };
+        for percentage in [25, 50, 75] {
+            flame
+                .report_task_progress(report("exec-1", percentage))
+                .await?;
+
+            let task = flame.get_task(get_task()).await?.into_inner();
+            let progress = task.status.unwrap().progress.unwrap();
+            assert_eq!(progress.percentage, percentage);
+            assert_eq!(
+                progress.payload,
+                Some(format!("{}%", percentage).into_bytes())
+            );
+            assert!(progress.update_time > 0);
+        }
+
+        let e = flame.report_task_progress(report("exec-2", 100)).await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::PermissionDenied);
+        let e = flame.report_task_progress(report("exec-1", 101)).await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+        let watch = WatchSessionRequest {
+            session_id: ssn_id.clone(),
+            since: 0,
+            ..Default::default()
+        };
+        let events = flame
+            .watch_session(Request::new(watch))
+            .await?
+            .into_inner()
+            .take(5)
+            .collect::<Result<Vec<_>, _>>()
+            .await?;
+        let progress = events
+            .into_iter()
+            .filter_map(|e| match e.event {
+                Some(rpc::session_event::Event::TaskProgress(p)) => Some(p),
+                _ => None,
+            })
+            .map(|p| (p.task_id, p.progress.unwrap().percentage))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            progress,
+            vec![
+                (task_id.clone(), 25),
+                (task_id.clone(), 50),
+                (task_id.clone(), 75)
+            ]
+        );
+
+        // The last progress is kept with the completed task, and the executor does not own the
+        // task any more.
+        flame
+            .complete_task(Request::new(CompleteTaskRequest {
+                executor_id,
+                task_output: None,
+                ..Default::default()
+            }))
+            .await?;
+        let task = flame.get_task(get_task()).await?.into_inner();
+        let status = task.status.unwrap();
+        assert_eq!(status.state, rpc::TaskState::TaskSucceed as i32);
+        assert_eq!(status.progress.unwrap().percentage, 75);
+        let e = flame.report_task_progress(report("exec-1", 100)).await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::PermissionDenied);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stale_complete_task() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_stale_complete_task_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        let app = apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        };
+        flame
+            .storage
+            .set_config_applications(std::slice::from_ref(&app))?;
+
+        let ssn = flame
+            .create_session(Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: app.name.clone(),
+                    slots: 1,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+        for _ in 0..3 {
+            flame
+                .create_task(Request::new(CreateTaskRequest {
+                    task: Some(TaskSpec {
+                        session_id: ssn_id.clone(),
+                        ..Default::default()
+                    }),
+                }))
+                .await?;
+        }
+
+        // Both executors are bound to the session.
+        for executor_id in ["exec-1", "exec-2"] {
+            let executor_id = executor_id.to_string();
+            flame
+                .register_executor(Request::new(RegisterExecutorRequest {
+                    executor_id: executor_id.clone(),
+                    executor_spec: Some(ExecutorSpec {
+                        slots: 1,
+                        applications: vec![rpc::Application::from(&app)],
+                        ..Default::default()
+                    }),
+                }))
+                .await?;
+            flame
+                .storage
+                .bind_session(executor_id.clone(), apis::parse_session_id(&ssn_id)?)
+                .await?;
+            flame
+                .bind_executor(Request::new(BindExecutorRequest {
+                    executor_id: executor_id.clone(),
+                }))
+                .await?;
+            flame
+                .bind_executor_completed(Request::new(BindExecutorCompletedRequest {
+                    executor_id,
+                    ..Default::default()
+                }))
+                .await?;
+        }
+
+        let launch = |executor_id: &str| {
+            let req = LaunchTaskRequest {
+                executor_id: executor_id.to_string(),
+            };
+            async {
+                let task = flame.launch_task(Request::new(req)).await?.into_inner();
+                Ok::<_, Status>(task.task.unwrap().metadata.unwrap().id)
+            }
+        };
+        let complete = |executor_id: &str, task_id: &str, output: &str| {
+            flame.complete_task(Request::new(CompleteTaskRequest {
+                executor_id: executor_id.to_string(),
+                task_output: Some(output.as_bytes().to_vec()),
+                session_id: ssn_id.clone(),
+                task_id: task_id.to_string(),
+                failure: None,
+                failure_reason: None,
+                generation: None,
+                annotations: Default::default(),
+                session_annotations: Default::default(),
+            }))
+        };
+        let get_task = |task_id: &str| {
+            flame.get_task(Request::new(GetTaskRequest {
+                session_id: ssn_id.clone(),
+                task_id: task_id.to_string(),
+            }))
+        };
+
+        let task_1 = launch("exec-1").await?;
+        let task_2 = launch("exec-2").await?;
+
+        // The completion of the task of another executor is rejected, while the executors
+        // complete their own tasks concurrently.
+        let (stolen, own_1, own_2) = tokio::join!(
+            complete("exec-1", &task_2, "stolen"),
+            complete("exec-1", &task_1, "output-1"),
+            complete("exec-2", &task_2, "output-2"),
+        );
+        assert_eq!(stolen.unwrap_err().code(), tonic::Code::FailedPrecondition);
+        own_1?;
+        own_2?;
+
+        // The stale completion of the first task, e.g. a retried request, is rejected instead
+        // of completing the third task of the executor.
+        let task_3 = launch("exec-1").await?;
+        let stale = complete("exec-1", &task_1, "stale").await;
+        assert_eq!(stale.unwrap_err().code(), tonic::Code::FailedPrecondition);
+
+        let task = get_task(&task_1).await?.into_inner();
+        assert_eq!(task.spec.unwrap().output, Some(b"output-1".to_vec()));
+        let task = get_task(&task_3).await?.into_inner();
+        let status = task.status.unwrap();
+        assert_eq!(status.state, rpc::TaskState::TaskRunning as i32);
+
+        // The legacy completion without the identity completes the assigned task.
+        flame
+            .complete_task(Request::new(CompleteTaskRequest {
+                executor_id: "exec-1".to_string(),
+                task_output: Some(b"output-3".to_vec()),
+                ..Default::default()
+            }))
+            .await?;
+        let task = get_task(&task_3).await?.into_inner();
+        assert_eq!(task.spec.unwrap().output, Some(b"output-3".to_vec()));
+
+        Ok(())
+    }
+
+    /// Registers the executor on the host, and binds it to the session.
+    async fn bind_executor(
+        flame: &Flame,
+        app: &apis::Application,
+        executor_id: &str,
+        hostname: &str,
+        ssn_id: &str,
+    ) -> Result<(), Status> {
+        let executor_id = executor_id.to_string();
+        let spec = ExecutorSpec {
+            slots: 1,
+            applications: vec![rpc::Application::from(app)],
+            host: Some(rpc::HostInfo {
+                hostname: hostname.to_string(),
+                ip: "10.0.0.1".to_string(),
+                os: "linux".to_string(),
+                arch: "x86_64".to_string(),
+                version: "0.3.0".to_string(),
+            }),
+            ..Default::default()
+        };
+        flame
+            .register_executor(Request::new(RegisterExecutorRequest {
+                executor_id: executor_id.clone(),
+                executor_spec: Some(spec),
+            }))
+            .await?;
+        flame
+            .storage
+            .bind_session(executor_id.clone(), apis::parse_session_id(ssn_id)?)
+            .await?;
+        flame
+            .bind_executor(Request::new(BindExecutorRequest {
+                executor_id: executor_id.clone(),
+            }))
+            .await?;
+        flame
+            .bind_executor_completed(Request::new(BindExecutorCompletedRequest {
+                executor_id,
+                ..Default::default()
+            }))
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_task_placement() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_task_placement_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let app = apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        };
+        let connect = || async {
+            let flame = Flame {
+                storage: storage::new_ptr(&url).await?,
+                audit: None,
+                auth: None,
+                limiter: None,
+            };
+            flame.storage.load_data().await?;
+            flame
+                .storage
+                .set_config_applications(std::slice::from_ref(&app))?;
+            Ok::<_, FlameError>(flame)
+        };
+        // Registers the executor on the host, and launches the next task of the session.
+        async fn launch(
+            flame: &Flame,
+            app: &apis::Application,
+            executor_id: &str,
+            hostname: &str,
+            ssn_id: &str,
+        ) -> Result<Task, Status> {
+            bind_executor(flame, app, executor_id, hostname, ssn_id).await?;
+            let req = LaunchTaskRequest {
+                executor_id: executor_id.to_string(),
+            };
+            let task = flame.launch_task(Request::new(req)).await?.into_inner();
+            Ok(task.task.unwrap())
+        }
+        let placement = |task: &Task| {
+            let status = task.status.clone().unwrap();
+            (status.executor_id, status.hostname)
+        };
+
+        let flame = connect().await?;
+        let ssn_id = flame
+            .create_session(Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: app.name.clone(),
+                    slots: 1,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner()
+            .metadata
+            .unwrap()
+            .id;
+        flame
+            .create_task(Request::new(CreateTaskRequest {
+                task: Some(TaskSpec {
+                    session_id: ssn_id.clone(),
+                    ..Default::default()
+                }),
+            }))
+            .await?;
+
+        let task = launch(&flame, &app, "exec-1", "node-a", &ssn_id).await?;
+        let expected = (Some("exec-1".to_string()), Some("node-a".to_string()));
+        assert_eq!(placement(&task), expected);
+        let executor = flame
+            .get_executor(Request::new(GetExecutorRequest {
+                executor_id: "exec-1".to_string(),
+            }))
+            .await?
+            .into_inner();
+        let host = executor.spec.unwrap().host.unwrap();
+        assert_eq!(
+            (host.hostname.as_str(), host.os.as_str()),
+            ("node-a", "linux")
+        );
+
+        // The running task is retried after a restart, and launched by another executor.
+        let task_id = task.metadata.unwrap().id;
+        let flame = connect().await?;
+        let task = launch(&flame, &app, "exec-2", "node-b", &ssn_id).await?;
+        assert_eq!(task.metadata.unwrap().id, task_id);
+        flame
+            .complete_task(Request::new(CompleteTaskRequest {
+                executor_id: "exec-2".to_string(),
+                task_output: None,
+                session_id: ssn_id.clone(),
+                task_id: task_id.clone(),
+                failure: None,
+                failure_reason: None,
+                generation: None,
+                annotations: Default::default(),
+                session_annotations: Default::default(),
+            }))
+            .await?;
+
+        let task = flame
+            .get_task(Request::new(GetTaskRequest {
+                session_id: ssn_id.clone(),
+                task_id,
+            }))
+            .await?
+            .into_inner();
+        let expected = (Some("exec-2".to_string()), Some("node-b".to_string()));
+        assert_eq!(placement(&task), expected);
+        assert_eq!(
+            task.status.unwrap().state,
+            rpc::TaskState::TaskSucceed as i32
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_quarantine_poisoned_executor() -> Result<(), FlameError> {
+        let flame = Flame {
+            storage: storage::new_ptr("memory://").await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        let app = apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        };
+        flame
+            .storage
+            .set_config_applications(std::slice::from_ref(&app))?;
+        flame.storage.set_quarantine_conf(&FlameQuarantineConf {
+            failures: Some(3),
+            ..Default::default()
+        })?;
+
+        let ssn_id = flame
+            .create_session(Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: app.name.clone(),
+                    slots: 2,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner()
+            .metadata
+            .unwrap()
+            .id;
+        for _ in 0..10 {
+            flame
+                .create_task(Request::new(CreateTaskRequest {
+                    task: Some(TaskSpec {
+                        session_id: ssn_id.clone(),
+                        ..Default::default()
+                    }),
+                }))
+                .await?;
+        }
+        bind_executor(&flame, &app, "bad", "node-a", &ssn_id).await?;
+        bind_executor(&flame, &app, "good", "node-b", &ssn_id).await?;
+
+        let launch = |executor_id: &str| {
+            let req = LaunchTaskRequest {
+                executor_id: executor_id.to_string(),
+            };
+            flame.launch_task(Request::new(req))
+        };
+        let complete = |executor_id: &str, task: &Task, failure: Option<&str>| {
+            flame.complete_task(Request::new(CompleteTaskRequest {
+                executor_id: executor_id.to_string(),
+                task_output: None,
+                session_id: ssn_id.clone(),
+                task_id: task.metadata.clone().unwrap().id,
+                failure: failure.map(str::to_string),
+                failure_reason: None,
+                generation: None,
+                annotations: Default::default(),
+                session_annotations: Default::default(),
+            }))
+        };
+
+        // The bad executor fails every task it launches, until it's quarantined.
+        let mut failed = 0;
+        while let Some(task) = launch("bad").await?.into_inner().task {
+            complete("bad", &task, Some("cuda: no device")).await?;
+            failed += 1;
+        }
+        assert_eq!(failed, 3);
+
+        let exe = flame
+            .get_executor(Request::new(GetExecutorRequest {
+                executor_id: "bad".to_string(),
+            }))
+            .await?
+            .into_inner();
+        let status = exe.status.unwrap();
+        assert!(status.quarantined && status.draining);
+        assert_eq!(status.task_id, None);
+
+        // The failed tasks are requeued, and all the tasks are done by the good executor.
+        let mut succeed = 0;
+        while let Some(task) = launch("good").await?.into_inner().task {
+            complete("good", &task, None).await?;
+            succeed += 1;
+        }
+        assert_eq!(succeed, 10);
+        let ssn = flame
+            .storage
+            .get_session(apis::parse_session_id(&ssn_id)?)?;
+        assert_eq!(ssn.status.counts.succeed, 10);
+
+        let events = flame
+            .storage
+            .watch_session(apis::parse_session_id(&ssn_id)?, 0)
+            .await?;
+        let quarantined = events
+            .iter()
+            .filter(|e| matches!(&e.kind, apis::EventKind::ExecutorQuarantined { executor_id, .. } if executor_id == "bad"))
+            .count();
+        assert_eq!(quarantined, 1);
+
+        // The uncordoned executor gets the sessions and the tasks again.
+        let exe = flame
+            .uncordon_executor(Request::new(UncordonExecutorRequest {
+                executor_id: "bad".to_string(),
+            }))
+            .await?
+            .into_inner();
+        let status = exe.status.unwrap();
+        assert!(!status.quarantined && !status.draining);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_session_templates() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_session_templates_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        flame.storage.set_config_applications(&[
+            apis::Application {
+                name: "flmexec".to_string(),
+                ..Default::default()
+            },
+            apis::Application {
+                name: "pi".to_string(),
+                ..Default::default()
+            },
+        ])?;
+        flame
+            .storage
+            .set_session_templates(&[FlameSessionTemplate {
+                name: "nightly-batch".to_string(),
+                application: "flmexec".to_string(),
+                slots: Some(2),
+                labels: HashMap::from([
+                    ("team".to_string(), "ml".to_string()),
+                    ("tier".to_string(), "batch".to_string()),
+                ]),
+                max_pending_tasks: Some(100),
+                timeout: Some("8h".to_string()),
+            }])?;
+        let create_session = |spec: SessionSpec, template: &str| {
+            Request::new(CreateSessionRequest {
+                session: Some(spec),
+                template: template.to_string(),
+            })
+        };
+
+        // The template gives all the fields.
+        let spec = flame
+            .create_session(create_session(SessionSpec::default(), "nightly-batch"))
+            .await?
+            .into_inner()
+            .spec
+            .unwrap();
+        assert_eq!((spec.application.as_str(), spec.slots), ("flmexec", 2));
+        assert_eq!(spec.labels["tier"], "batch");
+        assert_eq!(spec.max_pending_tasks, Some(100));
+        let timeout = spec.deadline.unwrap() - Utc::now().timestamp();
+        assert!((8 * 3600 - 1..=8 * 3600).contains(&timeout));
+
+        // The explicit fields win over the template.
+        let spec = flame
+            .create_session(create_session(
+                SessionSpec {
+                    application: "pi".to_string(),
+                    slots: 1,
+                    labels: HashMap::from([("tier".to_string(), "urgent".to_string())]),
+                    timeout: Some(60),
+                    ..Default::default()
+                },
+                "nightly-batch",
+            ))
+            .await?
+            .into_inner()
+            .spec
+            .unwrap();
+        assert_eq!((spec.application.as_str(), spec.slots), ("pi", 1));
+        assert_eq!(spec.labels["tier"], "urgent");
+        assert_eq!(spec.labels["team"], "ml");
+        assert_eq!(spec.max_pending_tasks, Some(100));
+        assert!(spec.deadline.unwrap() - Utc::now().timestamp() <= 60);
+
+        let e = flame
+            .create_session(create_session(SessionSpec::default(), "daily"))
+            .await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::NotFound);
+
+        let list = flame
+            .list_session_template(Request::new(ListSessionTemplateRequest {}))
+            .await?
+            .into_inner();
+        assert_eq!(list.templates.len(), 1);
+        assert_eq!(list.templates[0].timeout, Some(8 * 3600));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_session_deadline() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_session_deadline_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        let app = apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        };
+        flame
+            .storage
+            .set_config_applications(std::slice::from_ref(&app))?;
+
+        let create_session = |timeout: u64| {
+            Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: app.name.clone(),
+                    slots: 1,
+                    timeout: Some(timeout),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        };
+        let e = flame.create_session(create_session(0)).await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+        let ssn = flame.create_session(create_session(2)).await?.into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+        let deadline = ssn.spec.unwrap().deadline.unwrap();
+        assert!((1..=2).contains(&(deadline - Utc::now().timestamp())));
+
+        let mut tasks = vec![];
+        for _ in 0..2 {
+            let task = flame
+                .create_task(Request::new(CreateTaskRequest {
+                    task: Some(TaskSpec {
+                        session_id: ssn_id.clone(),
+                        ..Default::default()
+                    }),
+                }))
+                .await?
+                .into_inner();
+            tasks.push(task.metadata.unwrap().id);
+        }
+
+        // One of the tasks is running when the session expires.
+        let executor_id = "exec-1".to_string();
+        flame
+            .register_executor(Request::new(RegisterExecutorRequest {
+                executor_id: executor_id.clone(),
+                executor_spec: Some(ExecutorSpec {
+                    slots: 1,
+                    applications: vec![rpc::Application::from(&app)],
+                    ..Default::default()
+                }),
+            }))
+            .await?;
+        flame
+            .storage
+            .bind_session(executor_id.clone(), apis::parse_session_id(&ssn_id)?)
+            .await?;
+        flame
+            .bind_executor(Request::new(BindExecutorRequest {
+                executor_id: executor_id.clone(),
+            }))
+            .await?;
+        flame
+            .bind_executor_completed(Request::new(BindExecutorCompletedRequest {
+                executor_id: executor_id.clone(),
+                ..Default::default()
+            }))
+            .await?;
+        flame
+            .launch_task(Request::new(LaunchTaskRequest {
+                executor_id: executor_id.clone(),
+            }))
+            .await?;
+
+        // The deadline is extended while the session is open; the sweeper gets the time, so
+        // the session expires without waiting.
+        let update_session = |deadline: i64| {
+            Request::new(UpdateSessionRequest {
+                session_id: ssn_id.clone(),
+                deadline,
+                ..Default::default()
+            })
+        };
+        let e = flame.update_session(update_session(deadline - 60)).await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::InvalidArgument);
+        let ssn = flame
+            .update_session(update_session(deadline + 60))
+            .await?
+            .into_inner();
+        assert_eq!(ssn.spec.unwrap().deadline, Some(deadline + 60));
+
+        let deadline = DateTime::<Utc>::from_timestamp(deadline + 60, 0).unwrap();
+        let sweep = |secs: i64| {
+            flame
+                .storage
+                .expire_sessions(deadline + chrono::Duration::seconds(secs))
+        };
+        assert!(sweep(-1).await?.is_empty());
+        assert_eq!(sweep(0).await?, vec![apis::parse_session_id(&ssn_id)?]);
+        assert!(sweep(1).await?.is_empty());
+
+        let get_session = GetSessionRequest {
+            session_id: ssn_id.clone(),
+            ..Default::default()
+        };
+        let ssn = flame
+            .get_session(Request::new(get_session))
+            .await?
+            .into_inner();
+        let status = ssn.status.unwrap();
+        assert_eq!(status.state, rpc::SessionState::SessionClosed as i32);
+        assert!(status.completion_time.is_some());
+        assert_eq!(status.failed, 2);
+
+        // The output of the running task is dropped, as it was aborted.
+        flame
+            .complete_task(Request::new(CompleteTaskRequest {
+                executor_id,
+                task_output: Some(b"late".to_vec()),
+                ..Default::default()
+            }))
+            .await?;
+        for task_id in &tasks {
+            let task = flame
+                .get_task(Request::new(GetTaskRequest {
+                    session_id: ssn_id.clone(),
+                    task_id: task_id.clone(),
+                }))
+                .await?
+                .into_inner();
+            let status = task.status.unwrap();
+            assert_eq!(status.state, rpc::TaskState::TaskFailed as i32);
+            let failure = status.failure.unwrap();
+            assert_eq!(failure.reason, rpc::FailureReason::FailureTimeout as i32);
+            assert_eq!(task.spec.unwrap().output, None);
+        }
+
+        let e = flame
+            .update_session(update_session(Utc::now().timestamp() + 60))
+            .await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::FailedPrecondition);
+
+        // The watchers see the expiry, then the aborted tasks and the closing of the session.
+        let watch = WatchSessionRequest {
+            session_id: ssn_id.clone(),
+            since: 3,
+            ..Default::default()
+        };
+        let events = flame
+            .watch_session(Request::new(watch))
+            .await?
+            .into_inner()
+            .collect::<Result<Vec<_>, _>>()
+            .await?;
+        let events = events
+            .into_iter()
+            .map(|e| match e.event.unwrap() {
+                rpc::session_event::Event::SessionExpired(e) => format!("expired {}", e.deadline),
+                rpc::session_event::Event::TaskStateChanged(e) => format!("task {}", e.state),
+                rpc::session_event::Event::SessionClosed(_) => "closed".to_string(),
+                e => format!("{:?}", e),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                format!("expired {}", deadline.timestamp()),
+                format!("task {}", rpc::TaskState::TaskFailed as i32),
+                format!("task {}", rpc::TaskState::TaskFailed as i32),
+                "closed".to_string(),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_namespaces() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_namespaces_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let token = |name: &str, namespace: Option<&str>| FlameTokenConf {
+            name: name.to_string(),
+            token: format!("{}-token", name),
+            namespace: namespace.map(str::to_string),
+        };
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: Some(Arc::new(Auth::new(&FlameAuthConf {
+                tokens: vec![
+                    token("admin", None),
+                    token("team-a", Some("team-a")),
+                    token("team-b", Some("team-b")),
+                ],
+            }))),
+            limiter: None,
+        };
+        flame.storage.set_config_applications(&[apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        }])?;
+
+        fn request<T>(msg: T, name: &str) -> Request<T> {
+            let mut req = Request::new(msg);
+            let token = format!("Bearer {}-token", name).parse().unwrap();
+            req.metadata_mut().insert(AUTHORIZATION, token);
+            req
+        }
+        let create_session = |namespace: &str| CreateSessionRequest {
+            session: Some(SessionSpec {
+                application: "flmexec".to_string(),
+                slots: 1,
+                namespace: namespace.to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let get_session = |id: &str, namespace: &str| GetSessionRequest {
+            session_id: id.to_string(),
+            namespace: namespace.to_string(),
+        };
+        let session = |ssn: Response<Session>| {
+            let ssn = ssn.into_inner();
+            (ssn.metadata.unwrap().id, ssn.spec.unwrap().namespace)
+        };
+
+        // The callers are authenticated by their tokens.
+        let e = flame.create_session(Request::new(create_session(""))).await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::Unauthenticated);
+        let e = flame
+            .create_session(request(create_session(""), "nobody"))
+            .await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::Unauthenticated);
+
+        // The information of the server is public.
+        let info = flame
+            .get_server_info(Request::new(GetServerInfoRequest {}))
+            .await?
+            .into_inner();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(info
+            .capabilities
+            .contains(&capability::NAMESPACES.to_string()));
+
+        // The sessions are created in the namespace of the request, or the one of the caller.
+        let (default_id, ns) = session(
+            flame
+                .create_session(request(create_session(""), "admin"))
+                .await?,
+        );
+        assert_eq!(ns, apis::DEFAULT_NAMESPACE);
+        let (a_id, ns) = session(
+            flame
+                .create_session(request(create_session(""), "team-a"))
+                .await?,
+        );
+        assert_eq!(ns, "team-a");
+        let (b_id, ns) = session(
+            flame
+                .create_session(request(create_session("team-b"), "admin"))
+                .await?,
+        );
+        assert_eq!(ns, "team-b");
+        let e = flame
+            .create_session(request(create_session("team-b"), "team-a"))
+            .await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::PermissionDenied);
+
+        // The sessions of other namespaces are not addressable.
+        let (id, _) = session(
+            flame
+                .get_session(request(get_session(&a_id, ""), "team-a"))
+                .await?,
+        );
+        assert_eq!(id, a_id);
+        for (id, namespace, name, code) in [
+            (&b_id, "", "team-a", tonic::Code::NotFound),
+            (&default_id, "", "team-a", tonic::Code::NotFound),
+            (&a_id, "", "admin", tonic::Code::NotFound),
+            (&b_id, "team-b", "team-a", tonic::Code::PermissionDenied),
+        ] {
+            let e = flame
+                .get_session(request(get_session(id, namespace), name))
+                .await;
+            assert_eq!(e.unwrap_err().code(), code, "{} in <{}>", id, namespace);
+        }
+        flame
+            .get_session(request(get_session(&a_id, "team-a"), "admin"))
+            .await?;
+
+        let req = CloseSessionRequest {
+            session_id: a_id.clone(),
+            namespace: String::new(),
+        };
+        let e = flame.close_session(request(req.clone(), "team-b")).await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::NotFound);
+        flame.close_session(request(req, "team-a")).await?;
+        let req = DeleteSessionRequest {
+            session_id: a_id.clone(),
+            namespace: String::new(),
+        };
+        let e = flame.delete_session(request(req.clone(), "team-b")).await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::NotFound);
+
+        let req = CreateTaskRequest {
+            task: Some(TaskSpec {
+                session_id: b_id.clone(),
+                ..Default::default()
+            }),
+        };
+        let e = flame.create_task(request(req.clone(), "team-a")).await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::NotFound);
+        flame.create_task(request(req, "team-b")).await?;
+
+        // The sessions are listed in the namespaces of the caller.
+        let flame = &flame;
+        let list_session = |namespace: &str, name: &str| {
+            let req = request(
+                ListSessionRequest {
+                    namespace: namespace.to_string(),
+                    ..Default::default()
+                },
+                name,
+            );
+            async move {
+                let ssn_list = flame.list_session(req).await?.into_inner();
+                let mut ids: Vec<String> = ssn_list
+                    .sessions
+                    .into_iter()
+                    .map(|ssn| ssn.metadata.unwrap().id)
+                    .collect();
+                ids.sort();
+                Ok::<_, Status>(ids)
+            }
+        };
+        assert_eq!(list_session("", "team-a").await?, vec![a_id.clone()]);
+        assert_eq!(list_session("", "team-b").await?, vec![b_id.clone()]);
+        assert_eq!(list_session("team-b", "admin").await?, vec![b_id.clone()]);
+        assert_eq!(
+            list_session("", "admin").await?,
+            vec![default_id.clone(), a_id.clone(), b_id.clone()]
+        );
+        let e = list_session("team-b", "team-a").await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::PermissionDenied);
+
+        // The executors and applications are shared by the namespaces.
+        let req = DrainExecutorRequest {
+            executor_id: "exec-1".to_string(),
+        };
+        let e = flame.drain_executor(request(req, "team-a")).await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::PermissionDenied);
+        flame
+            .list_executor(request(ListExecutorRequest::default(), "team-a"))
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_list_task_by_index() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_list_task_by_index_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Arc::new(Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        });
+        flame.storage.set_config_applications(&[apis::Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }])?;
+
+        let ssn_id = flame
+            .create_session(Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: "flmexec".to_string(),
+                    slots: 1,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner()
+            .metadata
+            .unwrap()
+            .id;
+
+        // The tasks are submitted concurrently, and their indexes are still dense.
+        let submitters = (0..4).map(|_| {
+            let (flame, ssn_id) = (flame.clone(), ssn_id.clone());
+            tokio::spawn(async move {
+                for _ in 0..25 {
+                    let req = CreateTaskRequest {
+                        task: Some(TaskSpec {
+                            session_id: ssn_id.clone(),
+                            ..Default::default()
+                        }),
+                    };
+                    flame.create_task(Request::new(req)).await?;
+                }
+                Ok::<_, Status>(())
+            })
+        });
+        for submitter in futures::future::join_all(submitters).await {
+            submitter.unwrap()?;
+        }
+
+        let list_task = |start_index: u64, limit: u32| {
+            let flame = flame.clone();
+            let session_id = ssn_id.clone();
+            async move {
+                let req = ListTaskRequest {
+                    session_id,
+                    start_index,
+                    limit,
+                    ..Default::default()
+                };
+                let tasks = flame.list_task(Request::new(req)).await?.into_inner().tasks;
+                Ok::<_, Status>(tasks.iter().map(|t| t.index).collect::<Vec<_>>())
+            }
+        };
+        assert_eq!(list_task(0, 0).await?, (0..100).collect::<Vec<_>>());
+        assert_eq!(list_task(98, 10).await?, vec![98, 99]);
+        assert_eq!(list_task(10, 3).await?, vec![10, 11, 12]);
+
+        let req = ListTaskRequest {
+            session_id: "1000".to_string(),
+            ..Default::default()
+        };
+        let e = flame.list_task(Request::new(req)).await.unwrap_err();
+        assert_eq!(e.code(), tonic::Code::NotFound);
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_get_session_independent_of_tasks() -> Result<(), FlameError> {
+        use prost::Message;
+
+        let flame = Arc::new(Flame {
+            storage: storage::new_ptr("memory://").await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        });
+        flame.storage.set_config_applications(&[apis::Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }])?;
+
+        let mut ssn_ids = vec![];
+        for tasks in [10, 50_000] {
+            let ssn = flame
+                .create_session(Request::new(CreateSessionRequest {
+                    session: Some(SessionSpec {
+                        application: "flmexec".to_string(),
+                        slots: 1,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }))
+                .await?
+                .into_inner();
+            let ssn_id = ssn.metadata.unwrap().id;
+            for _ in 0..tasks {
+                flame
+                    .storage
+                    .create_task(apis::parse_session_id(&ssn_id)?, Default::default())
+                    .await?;
+            }
+            ssn_ids.push(ssn_id);
+        }
+
+        let get_session = |ssn_id: &str| {
+            let req = GetSessionRequest {
+                session_id: ssn_id.to_string(),
+                ..Default::default()
+            };
+            flame.get_session(Request::new(req))
+        };
+
+        // The session carries the counts of its tasks only, so it's the same size however
+        // many tasks it has, and the copy in the storage is taken without the tasks.
+        let small = get_session(&ssn_ids[0]).await?.into_inner();
+        let large = get_session(&ssn_ids[1]).await?.into_inner();
+        assert!(
+            large.encoded_len() <= small.encoded_len() + 4,
+            "{} bytes",
+            large.encoded_len()
+        );
+        let copy = flame
+            .storage
+            .get_session(apis::parse_session_id(&ssn_ids[1])?)?;
+        assert!(copy.tasks.is_empty() && copy.tasks_index.is_empty());
+        assert_eq!(copy.status.counts.pending, 50_000);
+        assert_eq!(large.status.unwrap().pending, 50_000);
+
+        // The tasks are only listed by pages.
+        let req = ListTaskRequest {
+            session_id: ssn_ids[1].clone(),
+            skip_payloads: true,
+            ..Default::default()
+        };
+        let tasks = flame.list_task(Request::new(req)).await?.into_inner().tasks;
+        assert_eq!(tasks.len(), MAX_TASK_PAGE as usize);
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_task_outputs() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_fetch_task_outputs_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        let app = apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        };
+        flame
+            .storage
+            .set_config_applications(std::slice::from_ref(&app))?;
+
+        let ssn_id = flame
+            .create_session(Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: app.name.clone(),
+                    slots: 1,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner()
+            .metadata
+            .unwrap()
+            .id;
+        for _ in 0..100 {
+            let req = CreateTaskRequest {
+                task: Some(TaskSpec {
+                    session_id: ssn_id.clone(),
+                    ..Default::default()
+                }),
+            };
+            flame.create_task(Request::new(req)).await?;
+        }
+
+        // The first 70 tasks are completed with their indexes as the outputs.
+        let executor_id = "exec-1".to_string();
+        flame
+            .register_executor(Request::new(RegisterExecutorRequest {
+                executor_id: executor_id.clone(),
+                executor_spec: Some(ExecutorSpec {
+                    slots: 1,
+                    applications: vec![rpc::Application::from(&app)],
+                    ..Default::default()
+                }),
+            }))
+            .await?;
+        flame
+            .storage
+            .bind_session(executor_id.clone(), apis::parse_session_id(&ssn_id)?)
+            .await?;
+        flame
+            .bind_executor(Request::new(BindExecutorRequest {
+                executor_id: executor_id.clone(),
+            }))
+            .await?;
+        flame
+            .bind_executor_completed(Request::new(BindExecutorCompletedRequest {
+                executor_id: executor_id.clone(),
+                ..Default::default()
+            }))
+            .await?;
+        for _ in 0..70 {
+            let task = flame
+                .launch_task(Request::new(LaunchTaskRequest {
+                    executor_id: executor_id.clone(),
+                }))
+                .await?
+                .into_inner()
+                .task
+                .unwrap();
+            flame
+                .complete_task(Request::new(CompleteTaskRequest {
+                    executor_id: executor_id.clone(),
+                    task_output: Some(task.index.to_string().into_bytes()),
+                    ..Default::default()
+                }))
+                .await?;
+        }
+
+        let fetch = |start_index: u64, end_index: Option<u64>, state: Option<rpc::TaskState>| {
+            let req = FetchTaskOutputsRequest {
+                session_id: ssn_id.clone(),
+                start_index,
+                end_index,
+                state: state.map(|s| s as i32),
+                ..Default::default()
+            };
+            async {
+                flame
+                    .fetch_task_outputs(Request::new(req))
+                    .await?
+                    .into_inner()
+                    .collect::<Result<Vec<_>, _>>()
+                    .await
+            }
+        };
+
+        // All the tasks are streamed in the order of their indexes, across the pages.
+        let entries = fetch(0, None, None).await?;
+        assert_eq!(
+            entries.iter().map(|e| e.index).collect::<Vec<_>>(),
+            (0..100).collect::<Vec<_>>()
+        );
+        for entry in &entries {
+            let output = (entry.index < 70).then(|| entry.index.to_string().into_bytes());
+            assert_eq!(entry.output, output);
+        }
+
+        let indexes =
+            |entries: Vec<TaskOutputEntry>| entries.iter().map(|e| e.index).collect::<Vec<_>>();
+        assert_eq!(
+            indexes(fetch(30, Some(40), None).await?),
+            (30..40).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            indexes(fetch(60, None, Some(rpc::TaskState::TaskSucceed)).await?),
+            (60..70).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            indexes(fetch(0, None, Some(rpc::TaskState::TaskPending)).await?),
+            (70..100).collect::<Vec<_>>()
+        );
+        assert!(fetch(100, None, None).await?.is_empty());
+
+        let req = FetchTaskOutputsRequest {
+            session_id: "1000".to_string(),
+            ..Default::default()
+        };
+        let e = flame.fetch_task_outputs(Request::new(req)).await.err();
+        assert_eq!(e.map(|e| e.code()), Some(tonic::Code::NotFound));
+
+        // The outputs are read only as fast as they're consumed, so a slow client holds no
+        // more than the buffer of the channel.
+        let (tx, mut rx) = mpsc::channel(4);
+        let probe = tx.clone();
+        let ssn = apis::parse_session_id(&ssn_id)?;
+        let sender = tokio::spawn(send_outputs(
+            flame.storage.clone(),
+            ssn,
+            0..u64::MAX,
+            None,
+            tx,
+        ));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(probe.capacity(), 0);
+        assert!(!sender.is_finished());
+        drop(probe);
+
+        let mut received = 0;
+        while let Some(entry) = rx.recv().await {
+            assert_eq!(entry?.index, received);
+            received += 1;
+        }
+        assert_eq!(received, 100);
+        assert!(sender.await.is_ok());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resubmit_failed_task() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_resubmit_failed_task_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let app = apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        };
+        let connect = || async {
+            let flame = Flame {
+                storage: storage::new_ptr(&url).await?,
+                audit: None,
+                auth: None,
+                limiter: None,
+            };
+            flame.storage.load_data().await?;
+            flame
+                .storage
+                .set_config_applications(std::slice::from_ref(&app))?;
+            Ok::<_, FlameError>(flame)
+        };
+
+        let flame = connect().await?;
+        let ssn = flame
+            .create_session(Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: app.name.clone(),
+                    slots: 1,
+                    timeout: Some(60),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+        for input in ["a", "b"] {
+            flame
+                .create_task(Request::new(CreateTaskRequest {
+                    task: Some(TaskSpec {
+                        session_id: ssn_id.clone(),
+                        input: Some(input.as_bytes().to_vec()),
+                        ..Default::default()
+                    }),
+                }))
+                .await?;
+        }
+
+        // The tasks are failed by the expiry of the session, which is opened again to
+        // resubmit them.
+        let deadline = ssn.spec.unwrap().deadline.unwrap();
+        let deadline = DateTime::<Utc>::from_timestamp(deadline, 0).unwrap();
+        flame.storage.expire_sessions(deadline).await?;
+
+        let resubmit = |task_id: &str| {
+            Request::new(ResubmitTaskRequest {
+                session_id: ssn_id.clone(),
+                task_id: task_id.to_string(),
+                ..Default::default()
+            })
+        };
+        let e = flame.resubmit_task(resubmit("1")).await.unwrap_err();
+        assert_eq!(e.code(), tonic::Code::FailedPrecondition);
+        flame
+            .open_session(Request::new(OpenSessionRequest {
+                session_id: ssn_id.clone(),
+                ..Default::default()
+            }))
+            .await?;
+
+        let list_failed = || {
+            Request::new(ListTaskRequest {
+                session_id: ssn_id.clone(),
+                state: Some(rpc::TaskState::TaskFailed as i32),
+                ..Default::default()
+            })
+        };
+        let failed = flame.list_task(list_failed()).await?.into_inner().tasks;
+        assert_eq!(failed.len(), 2);
+
+        let task = flame.resubmit_task(resubmit("1")).await?.into_inner();
+        assert_eq!(task.metadata.unwrap().id, "3");
+        assert_eq!(task.original_task_id.as_deref(), Some("1"));
+        assert_eq!(task.spec.unwrap().input, Some(b"a".to_vec()));
+        let status = task.status.unwrap();
+        assert_eq!(status.state, rpc::TaskState::TaskPending as i32);
+        assert_eq!(status.failure, None);
+
+        // A task is resubmitted once, and only the failed tasks are resubmitted.
+        let e = flame.resubmit_task(resubmit("1")).await.unwrap_err();
+        assert_eq!(e.code(), tonic::Code::FailedPrecondition);
+        let e = flame.resubmit_task(resubmit("3")).await.unwrap_err();
+        assert_eq!(e.code(), tonic::Code::FailedPrecondition);
+        let e = flame.resubmit_task(resubmit("10")).await.unwrap_err();
+        assert_eq!(e.code(), tonic::Code::NotFound);
+
+        bind_executor(&flame, &app, "exec-1", "node-a", &ssn_id).await?;
+        let launched = flame
+            .launch_task(Request::new(LaunchTaskRequest {
+                executor_id: "exec-1".to_string(),
+            }))
+            .await?
+            .into_inner()
+            .task
+            .unwrap();
+        assert_eq!(launched.metadata.unwrap().id, "3");
+        flame
+            .complete_task(Request::new(CompleteTaskRequest {
+                executor_id: "exec-1".to_string(),
+                task_output: None,
+                session_id: ssn_id.clone(),
+                task_id: "3".to_string(),
+                failure: None,
+                failure_reason: None,
+                generation: None,
+                annotations: Default::default(),
+                session_annotations: Default::default(),
+            }))
+            .await?;
+
+        // Both of the tasks and their link are persisted.
+        let flame = connect().await?;
+        let get_task = |task_id: &str| {
+            let req = GetTaskRequest {
+                session_id: ssn_id.clone(),
+                task_id: task_id.to_string(),
+            };
+            flame.get_task(Request::new(req))
+        };
+        let original = get_task("1").await?.into_inner();
+        assert_eq!(original.original_task_id, None);
+        assert_eq!(
+            original.status.unwrap().state,
+            rpc::TaskState::TaskFailed as i32
+        );
+        let task = get_task("3").await?.into_inner();
+        assert_eq!(task.original_task_id.as_deref(), Some("1"));
+        assert_eq!(task.spec.unwrap().input, Some(b"a".to_vec()));
+        assert_eq!(
+            task.status.unwrap().state,
+            rpc::TaskState::TaskSucceed as i32
+        );
+
+        let failed = flame.list_task(list_failed()).await?.into_inner().tasks;
+        let failed = failed
+            .iter()
+            .map(|t| t.metadata.clone().unwrap().id)
+            .collect::<Vec<_>>();
+        assert_eq!(failed, vec!["1", "2"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_clone_session() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_clone_session_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let app = apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        };
+        let connect = || async {
+            let flame = Flame {
+                storage: storage::new_ptr(&url).await?,
+                audit: None,
+                auth: None,
+                limiter: None,
+            };
+            flame.storage.load_data().await?;
+            flame
+                .storage
+                .set_config_applications(std::slice::from_ref(&app))?;
+            Ok::<_, FlameError>(flame)
+        };
+
+        let flame = connect().await?;
+        let ssn = flame
+            .create_session(Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: app.name.clone(),
+                    slots: 1,
+                    common_data: Some(b"model".to_vec()),
+                    labels: [("team".to_string(), "ml".to_string())].into(),
+                    config: [("model_version".to_string(), "v2".to_string())].into(),
+                    timeout: Some(60),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner();
+        let src_id = ssn.metadata.unwrap().id;
+        for input in ["a", "b"] {
+            flame
+                .create_task(Request::new(CreateTaskRequest {
+                    task: Some(TaskSpec {
+                        session_id: src_id.clone(),
+                        input: Some(input.as_bytes().to_vec()),
+                        ..Default::default()
+                    }),
+                }))
+                .await?;
+        }
+
+        // The batch is completed and closed.
+        bind_executor(&flame, &app, "exec-1", "node-a", &src_id).await?;
+        for _ in 0..2 {
+            let task = flame
+                .launch_task(Request::new(LaunchTaskRequest {
+                    executor_id: "exec-1".to_string(),
+                }))
+                .await?
+                .into_inner()
+                .task
+                .unwrap();
+            flame
+                .complete_task(Request::new(CompleteTaskRequest {
+                    executor_id: "exec-1".to_string(),
+                    task_output: Some(b"done".to_vec()),
+                    session_id: src_id.clone(),
+                    task_id: task.metadata.unwrap().id,
+                    failure: None,
+                    failure_reason: None,
+                    generation: None,
+                    annotations: Default::default(),
+                    session_annotations: Default::default(),
+                }))
+                .await?;
+        }
+        flame
+            .close_session(Request::new(CloseSessionRequest {
+                session_id: src_id.clone(),
+                ..Default::default()
+            }))
+            .await?;
+
+        let clone_session = |with_tasks: bool| {
+            Request::new(CloneSessionRequest {
+                session_id: src_id.clone(),
+                with_tasks,
+                ..Default::default()
+            })
+        };
+
+        // Only the settings are copied by default.
+        let empty = flame
+            .clone_session(clone_session(false))
+            .await?
+            .into_inner();
+        let status = empty.status.unwrap();
+        assert_eq!(status.state, rpc::SessionState::SessionOpen as i32);
+        assert_eq!(status.pending + status.succeed, 0);
+
+        let clone = flame.clone_session(clone_session(true)).await?.into_inner();
+        let clone_id = clone.metadata.unwrap().id;
+        assert_ne!(clone_id, src_id);
+        let status = clone.status.unwrap();
+        assert_eq!(status.state, rpc::SessionState::SessionOpen as i32);
+        assert_eq!(status.pending, 2);
+        assert_eq!(status.succeed, 0);
+
+        // The clone and its link are persisted.
+        let flame = connect().await?;
+        let clone = flame
+            .get_session(Request::new(GetSessionRequest {
+                session_id: clone_id.clone(),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner();
+        let spec = clone.spec.unwrap();
+        assert_eq!(spec.cloned_from.as_deref(), Some(src_id.as_str()));
+        assert_eq!(spec.application, app.name);
+        assert_eq!(spec.common_data, Some(b"model".to_vec()));
+        assert_eq!(spec.labels.get("team").map(String::as_str), Some("ml"));
+        assert_eq!(
+            spec.config.get("model_version").map(String::as_str),
+            Some("v2")
+        );
+        assert_eq!(spec.deadline, None);
+
+        let tasks = flame
+            .list_task(Request::new(ListTaskRequest {
+                session_id: clone_id.clone(),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner()
+            .tasks;
+        let tasks: Vec<_> = tasks
+            .into_iter()
+            .map(|t| {
+                let spec = t.spec.unwrap();
+                (spec.input, spec.output, t.status.unwrap().state)
+            })
+            .collect();
+        let pending = rpc::TaskState::TaskPending as i32;
+        assert_eq!(
+            tasks,
+            vec![
+                (Some(b"a".to_vec()), None, pending),
+                (Some(b"b".to_vec()), None, pending),
+            ]
+        );
+
+        // The clone is runnable.
+        bind_executor(&flame, &app, "exec-2", "node-a", &clone_id).await?;
+        let task = flame
+            .launch_task(Request::new(LaunchTaskRequest {
+                executor_id: "exec-2".to_string(),
+            }))
+            .await?
+            .into_inner()
+            .task
+            .unwrap();
+        assert_eq!(task.spec.unwrap().session_id, clone_id);
+
+        let req = CloneSessionRequest {
+            session_id: "100".to_string(),
+            ..Default::default()
+        };
+        let e = flame.clone_session(Request::new(req)).await.unwrap_err();
+        assert_eq!(e.code(), tonic::Code::NotFound);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_task_failure_reasons() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_task_failure_reasons_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let app = apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        };
+        let connect = || async {
+            let flame = Flame {
+                storage: storage::new_ptr(&url).await?,
+                audit: None,
+                auth: None,
+                limiter: None,
+            };
+            flame.storage.load_data().await?;
+            flame
+                .storage
+                .set_config_applications(std::slice::from_ref(&app))?;
+            Ok::<_, FlameError>(flame)
+        };
+
+        let flame = connect().await?;
+        let ssn = flame
+            .create_session(Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: app.name.clone(),
+                    slots: 1,
+                    timeout: Some(60),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+        let create_task = || {
+            let req = CreateTaskRequest {
+                task: Some(TaskSpec {
+                    session_id: ssn_id.clone(),
+                    ..Default::default()
+                }),
+            };
+            flame.create_task(Request::new(req))
+        };
+        create_task().await?;
+        bind_executor(&flame, &app, "exec-1", "node-a", &ssn_id).await?;
+
+        let launch = || {
+            let req = LaunchTaskRequest {
+                executor_id: "exec-1".to_string(),
+            };
+            flame.launch_task(Request::new(req))
+        };
+        let complete = |task_id: &str, failure: Option<&str>, reason: Option<i32>| {
+            Request::new(CompleteTaskRequest {
+                executor_id: "exec-1".to_string(),
+                task_output: None,
+                session_id: ssn_id.clone(),
+                task_id: task_id.to_string(),
+                failure: failure.map(str::to_string),
+                failure_reason: reason,
+                generation: None,
+                annotations: Default::default(),
+                session_annotations: Default::default(),
+            })
+        };
+        async fn failure(
+            flame: &Flame,
+            ssn_id: &str,
+            task_id: &str,
+        ) -> Result<Option<rpc::TaskFailure>, FlameError> {
+            let req = GetTaskRequest {
+                session_id: ssn_id.to_string(),
+                task_id: task_id.to_string(),
+            };
+            let task = flame.get_task(Request::new(req)).await?.into_inner();
+            Ok(task.status.unwrap().failure)
+        }
+
+        // The failure of the executor is a shim error unless it tells the reason.
+        let task = launch().await?.into_inner().task.unwrap();
+        assert_eq!(task.metadata.unwrap().id, "1");
+        flame
+            .complete_task(complete("1", Some("shim crashed"), None))
+            .await?;
+        let failed = failure(&flame, &ssn_id, "1").await?.unwrap();
+        assert_eq!(failed.reason, rpc::FailureReason::FailureShimError as i32);
+        assert_eq!(failed.message, "shim crashed");
+
+        // The failure of the requeued task is kept, and replaced by the next one.
+        let task = launch().await?.into_inner().task.unwrap();
+        assert_eq!(task.metadata.unwrap().id, "1");
+        let oom = Some(rpc::FailureReason::FailureOomKilled as i32);
+        flame
+            .complete_task(complete("1", Some("killed by signal 9"), oom))
+            .await?;
+        let failed = failure(&flame, &ssn_id, "1").await?.unwrap();
+        assert_eq!(failed.reason, rpc::FailureReason::FailureOomKilled as i32);
+
+        // An unknown reason is rejected.
+        let task = launch().await?.into_inner().task.unwrap();
+        assert_eq!(task.metadata.unwrap().id, "1");
+        let e = flame
+            .complete_task(complete("1", Some("?"), Some(10)))
+            .await
+            .unwrap_err();
+        assert_eq!(e.code(), tonic::Code::InvalidArgument);
+
+        // The failure is cleared once the task succeeds.
+        flame.complete_task(complete("1", None, None)).await?;
+        assert_eq!(failure(&flame, &ssn_id, "1").await?, None);
+
+        // The tasks aborted by the deadline timed out.
+        create_task().await?;
+        let task = launch().await?.into_inner().task.unwrap();
+        assert_eq!(task.metadata.unwrap().id, "2");
+        let deadline = ssn.spec.unwrap().deadline.unwrap();
+        let deadline = DateTime::<Utc>::from_timestamp(deadline, 0).unwrap();
+        flame.storage.expire_sessions(deadline).await?;
+
+        // The failures are persisted, and listed with the tasks.
+        let flame = connect().await?;
+        let tasks = flame
+            .list_task(Request::new(ListTaskRequest {
+                session_id: ssn_id.clone(),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner()
+            .tasks;
+        let failures = tasks
+            .into_iter()
+            .map(|t| t.status.unwrap().failure)
+            .collect::<Vec<_>>();
+        assert_eq!(failures[0], None);
+        let expired = failures[1].clone().unwrap();
+        assert_eq!(expired.reason, rpc::FailureReason::FailureTimeout as i32);
+        assert!(expired.message.starts_with("session expired at"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_session_order() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_list_session_order_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        flame.storage.set_config_applications(&[apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        }])?;
+
+        // The i-th session has i pending tasks, and the first one is closed.
+        let mut ids = vec![];
+        for i in 0..5 {
+            let req = CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: "flmexec".to_string(),
+                    slots: 1,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let ssn = flame.create_session(Request::new(req)).await?.into_inner();
+            let ssn_id = ssn.metadata.unwrap().id;
+            for _ in 0..i {
+                let req = CreateTaskRequest {
+                    task: Some(TaskSpec {
+                        session_id: ssn_id.clone(),
+                        ..Default::default()
+                    }),
+                };
+                flame.create_task(Request::new(req)).await?;
+            }
+            ids.push(ssn_id);
+        }
+        let req = CloseSessionRequest {
+            session_id: ids[0].clone(),
+            ..Default::default()
+        };
+        flame.close_session(Request::new(req)).await?;
+
+        let list_session = |order: rpc::SessionOrder, offset: u32, limit: u32| {
+            let req = ListSessionRequest {
+                order_by: order as i32,
+                offset,
+                limit,
+                ..Default::default()
+            };
+            let flame = &flame;
+            async move {
+                let ssn_list = flame.list_session(Request::new(req)).await?.into_inner();
+                Ok::<_, Status>(
+                    ssn_list
+                        .sessions
+                        .into_iter()
+                        .map(|ssn| ssn.metadata.unwrap().id)
+                        .collect::<Vec<_>>(),
+                )
+            }
+        };
+
+        let sessions = |order: &[usize]| order.iter().map(|i| ids[*i].clone()).collect::<Vec<_>>();
+        let orders = [
+            rpc::SessionOrder::OrderByCreationTime,
+            rpc::SessionOrder::OrderById,
+            rpc::SessionOrder::OrderByState,
+            rpc::SessionOrder::OrderByPending,
+        ];
+        for order in orders {
+            let all = list_session(order, 0, 0).await?;
+            assert_eq!(all.len(), ids.len(), "{:?}", order);
+            // The order is the same across the calls and the pages.
+            assert_eq!(list_session(order, 0, 0).await?, all, "{:?}", order);
+            let mut pages = vec![];
+            for offset in (0..all.len() as u32 + 2).step_by(2) {
+                pages.extend(list_session(order, offset, 2).await?);
+            }
+            assert_eq!(pages, all, "{:?}", order);
+        }
+
+        assert_eq!(
+            list_session(rpc::SessionOrder::OrderById, 0, 0).await?,
+            sessions(&[0, 1, 2, 3, 4])
+        );
+        assert_eq!(
+            list_session(rpc::SessionOrder::OrderByState, 0, 0).await?,
+            sessions(&[1, 2, 3, 4, 0])
+        );
+        assert_eq!(
+            list_session(rpc::SessionOrder::OrderByPending, 1, 3).await?,
+            sessions(&[3, 2, 1])
+        );
+
+        // The sessions are filtered by their state before they're paged.
+        let list_session_in = |state: rpc::SessionState, offset: u32, limit: u32| {
+            let req = ListSessionRequest {
+                order_by: rpc::SessionOrder::OrderById as i32,
+                offset,
+                limit,
+                state: Some(state as i32),
+                ..Default::default()
+            };
+            let flame = &flame;
+            async move {
+                let ssn_list = flame.list_session(Request::new(req)).await?.into_inner();
+                Ok::<_, Status>(
+                    ssn_list
+                        .sessions
+                        .into_iter()
+                        .map(|ssn| ssn.metadata.unwrap().id)
+                        .collect::<Vec<_>>(),
+                )
+            }
+        };
+        assert_eq!(
+            list_session_in(rpc::SessionState::SessionOpen, 0, 0).await?,
+            sessions(&[1, 2, 3, 4])
+        );
+        assert_eq!(
+            list_session_in(rpc::SessionState::SessionOpen, 1, 2).await?,
+            sessions(&[2, 3])
+        );
+        assert_eq!(
+            list_session_in(rpc::SessionState::SessionClosed, 0, 0).await?,
+            sessions(&[0])
+        );
+
+        let req = ListSessionRequest {
+            order_by: 10,
+            ..Default::default()
+        };
+        let e = flame.list_session(Request::new(req)).await.unwrap_err();
+        assert_eq!(e.code(), tonic::Code::InvalidArgument);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_session_config() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_session_config_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        flame.storage.set_config_applications(&[apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Stdio,
+            ..Default::default()
+        }])?;
+
+        let create_session = |config: &[(&str, &str)]| CreateSessionRequest {
+            session: Some(SessionSpec {
+                application: "flmexec".to_string(),
+                slots: 1,
+                config: config
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        // The keys are exported as the same environment variable.
+        let req = create_session(&[("model-version", "v1"), ("MODEL_VERSION", "v2")]);
+        let e = flame.create_session(Request::new(req)).await.unwrap_err();
+        assert_eq!(e.code(), tonic::Code::InvalidArgument);
+
+        let config = [("model_version", "v2"), ("output-bucket", "s3://out")];
+        let ssn = flame
+            .create_session(Request::new(create_session(&config)))
+            .await?
+            .into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+
+        // The config is delivered to the executor bound to the session.
+        let req = RegisterExecutorRequest {
+            executor_id: "exec-1".to_string(),
+            executor_spec: Some(ExecutorSpec {
+                slots: 1,
+                ..Default::default()
+            }),
+        };
+        flame.register_executor(Request::new(req)).await?;
+        let id = apis::parse_session_id(&ssn_id)?;
+        flame.storage.bind_session("exec-1".to_string(), id).await?;
+        let req = BindExecutorRequest {
+            executor_id: "exec-1".to_string(),
+        };
+        let ssn = flame.bind_executor(Request::new(req)).await?.into_inner();
+        let spec = ssn.spec.unwrap();
+        assert_eq!(spec.config.len(), 2);
+        assert_eq!(spec.config["model_version"], "v2");
+        assert_eq!(spec.config["output-bucket"], "s3://out");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_task_settings() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_task_settings_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+
+        let app = rpc::Application {
+            name: "batch".to_string(),
+            shim: rpc::Shim::StdioShim as i32,
+            command: "/usr/bin/batch".to_string(),
+            working_directory: "/tmp".to_string(),
+            default_slots: Some(0),
+            default_task_timeout: Some(600),
+            default_max_retries: Some(2),
+            default_labels: HashMap::from([("tier".to_string(), "batch".to_string())]),
+            ..Default::default()
+        };
+        let register = |app: &rpc::Application| RegisterApplicationRequest {
+            application: Some(app.clone()),
+        };
+        let e = flame.register_application(Request::new(register(&app)));
+        assert_eq!(e.await.unwrap_err().code(), tonic::Code::InvalidArgument);
+        let app = rpc::Application {
+            default_slots: Some(2),
+            ..app
+        };
+        flame
+            .register_application(Request::new(register(&app)))
+            .await?;
+
+        let create_session = |spec: SessionSpec| CreateSessionRequest {
+            session: Some(SessionSpec {
+                application: "batch".to_string(),
+                ..spec
+            }),
+            ..Default::default()
+        };
+        for spec in [
+            SessionSpec {
+                slots: -1,
+                ..Default::default()
+            },
+            SessionSpec {
+                task_timeout: Some(0),
+                ..Default::default()
+            },
+        ] {
+            let e = flame.create_session(Request::new(create_session(spec)));
+            assert_eq!(e.await.unwrap_err().code(), tonic::Code::InvalidArgument);
+        }
+
+        // The session shows the settings resolved from the application.
+        let ssn = flame
+            .create_session(Request::new(create_session(SessionSpec {
+                max_task_retries: Some(5),
+                ..Default::default()
+            })))
+            .await?
+            .into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+        let get_session = GetSessionRequest {
+            session_id: ssn_id.clone(),
+            ..Default::default()
+        };
+        let spec = flame
+            .get_session(Request::new(get_session))
+            .await?
+            .into_inner()
+            .spec
+            .unwrap();
+        assert_eq!(spec.slots, 2);
+        assert_eq!(spec.labels["tier"], "batch");
+        assert_eq!(
+            (spec.task_timeout, spec.max_task_retries),
+            (Some(600), Some(5))
+        );
+
+        // The task shows the settings resolved from the session.
+        let create_task = |timeout| CreateTaskRequest {
+            task: Some(TaskSpec {
+                session_id: ssn_id.clone(),
+                timeout,
+                ..Default::default()
+            }),
+        };
+        let e = flame.create_task(Request::new(create_task(Some(0))));
+        assert_eq!(e.await.unwrap_err().code(), tonic::Code::InvalidArgument);
+        for (timeout, expected) in [(None, Some(600)), (Some(60), Some(60))] {
+            let task = flame
+                .create_task(Request::new(create_task(timeout)))
+                .await?
+                .into_inner();
+            let spec = task.spec.unwrap();
+            assert_eq!((spec.timeout, spec.max_retries), (expected, Some(5)));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_task_idempotency_key() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_task_idempotency_key_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        flame.storage.set_config_applications(&[apis::Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }])?;
+
+        let ssn = flame
+            .create_session(Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: "flmexec".to_string(),
+                    slots: 1,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+        let create_task = |key: &str| CreateTaskRequest {
+            task: Some(TaskSpec {
+                session_id: ssn_id.clone(),
+                idempotency_key: Some(key.to_string()),
+                ..Default::default()
+            }),
+        };
+
+        for key in [String::new(), "k".repeat(MAX_IDEMPOTENCY_KEY + 1)] {
+            let e = flame.create_task(Request::new(create_task(&key)));
+            assert_eq!(e.await.unwrap_err().code(), tonic::Code::InvalidArgument);
+        }
+
+        let mut ids = vec![];
+        for key in ["0", "1", "0"] {
+            let task = flame
+                .create_task(Request::new(create_task(key)))
+                .await?
+                .into_inner();
+            assert_eq!(task.spec.unwrap().idempotency_key.as_deref(), Some(key));
+            ids.push(task.metadata.unwrap().id);
+        }
+        assert_ne!(ids[0], ids[1]);
+        assert_eq!(ids[0], ids[2]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_rate_limit_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let token = |name: &str| FlameTokenConf {
+            name: name.to_string(),
+            token: format!("{}-token", name),
+            namespace: Some(name.to_string()),
+        };
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: Some(Arc::new(Auth::new(&FlameAuthConf {
+                tokens: vec![token("team-a"), token("team-b")],
+            }))),
+            limiter: Some(Arc::new(Limiter::new(&FlameRateLimitConf {
+                rate: 1.0,
+                burst: Some(3),
+            }))),
+        };
+        flame.storage.set_config_applications(&[apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        }])?;
+
+        fn request<T>(msg: T, name: &str) -> Request<T> {
+            let mut req = Request::new(msg);
+            let token = format!("Bearer {}-token", name).parse().unwrap();
+            req.metadata_mut().insert(AUTHORIZATION, token);
+            req
+        }
+        let create_session = || CreateSessionRequest {
+            session: Some(SessionSpec {
+                application: "flmexec".to_string(),
+                slots: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let create_task = |ssn_id: &str| CreateTaskRequest {
+            task: Some(TaskSpec {
+                session_id: ssn_id.to_string(),
+                ..Default::default()
+            }),
+        };
+
+        let ssn = flame
+            .create_session(request(create_session(), "team-a"))
+            .await?
+            .into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+
+        // The burst is used up by the caller hammering the Frontend.
+        let before = throttled_calls();
+        let mut created = 1;
+        let mut throttled = vec![];
+        for _ in 0..10 {
+            match flame
+                .create_task(request(create_task(&ssn_id), "team-a"))
+                .await
+            {
+                Ok(_) => created += 1,
+                Err(e) => throttled.push(FlameError::from(e)),
+            }
+        }
+        assert_eq!(created, 3);
+        assert_eq!(throttled.len(), 8);
+        for e in &throttled {
+            assert!(matches!(e, FlameError::ResourceExhausted { .. }));
+            assert!(e.retry_after().unwrap() <= Duration::from_secs(1));
+        }
+        assert!(throttled_calls() >= before + 8);
+
+        // The reads, the other callers and the executors are not throttled.
+        let req = GetSessionRequest {
+            session_id: ssn_id.clone(),
+            namespace: String::new(),
+        };
+        flame.get_session(request(req, "team-a")).await?;
+        flame
+            .create_session(request(create_session(), "team-b"))
+            .await?;
+        let req = RegisterExecutorRequest {
+            executor_id: "exec-1".to_string(),
+            executor_spec: Some(ExecutorSpec {
+                slots: 1,
+                ..Default::default()
+            }),
+        };
+        flame.register_executor(Request::new(req)).await?;
+
+        // The caller recovers once the tokens are refilled.
+        let retry_after = throttled.last().and_then(|e| e.retry_after()).unwrap();
+        tokio::time::sleep(retry_after + Duration::from_millis(10)).await;
+        flame
+            .create_task(request(create_task(&ssn_id), "team-a"))
+            .await?;
+        let e = flame
+            .create_task(request(create_task(&ssn_id), "team-a"))
+            .await
+            .unwrap_err();
+        assert_eq!(e.code(), tonic::Code::ResourceExhausted);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expired_caller() -> Result<(), FlameError> {
+        let flame = Flame {
+            storage: storage::new_ptr("memory://").await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        flame.storage.set_config_applications(&[apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        }])?;
+
+        let ssn = flame
+            .create_session(Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: "flmexec".to_string(),
+                    slots: 1,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner();
+        let ssn_id = ssn.metadata.unwrap_or_default().id;
+        let create_task = |timeout: &str| {
+            let mut req = Request::new(CreateTaskRequest {
+                task: Some(TaskSpec {
+                    session_id: ssn_id.clone(),
+                    ..Default::default()
+                }),
+            });
+            req.metadata_mut()
+                .insert("grpc-timeout", timeout.parse().unwrap());
+            req
+        };
+        let tasks = || async {
+            let ssn_id = apis::parse_session_id(&ssn_id)?;
+            let tasks = flame.storage.list_tasks(ssn_id, 0, None, None, None, false);
+            Ok::<_, FlameError>(tasks.await?.len())
+        };
+
+        // The task of a caller which is gone is not created.
+        let e = flame.create_task(create_task("0n")).await.unwrap_err();
+        assert_eq!(e.code(), tonic::Code::DeadlineExceeded);
+        assert_eq!(tasks().await?, 0);
+
+        // The callers with time left are served, and the invalid timeouts are ignored.
+        flame.create_task(create_task("10S")).await?;
+        flame.create_task(create_task("soon")).await?;
+        assert_eq!(tasks().await?, 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_session_events() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_session_events_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        let app = apis::Application {
+            name: "flmexec".to_string(),
+            shim: apis::Shim::Log,
+            ..Default::default()
+        };
+        flame
+            .storage
+            .set_config_applications(std::slice::from_ref(&app))?;
+
+        let ssn = flame
+            .create_session(Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: app.name.clone(),
+                    slots: 1,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+            .await?
+            .into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+        let start = Utc::now().timestamp();
+        flame
+            .create_task(Request::new(CreateTaskRequest {
+                task: Some(TaskSpec {
+                    session_id: ssn_id.clone(),
+                    ..Default::default()
+                }),
+            }))
+            .await?;
+
+        // The task runs through its whole lifecycle on an executor.
+        let executor_id = "exec-1".to_string();
+        flame
+            .register_executor(Request::new(RegisterExecutorRequest {
+                executor_id: executor_id.clone(),
+                executor_spec: Some(ExecutorSpec {
+                    slots: 1,
+                    applications: vec![rpc::Application::from(&app)],
+                    ..Default::default()
+                }),
+            }))
+            .await?;
+        flame
+            .storage
+            .bind_session(executor_id.clone(), apis::parse_session_id(&ssn_id)?)
+            .await?;
+        flame
+            .bind_executor(Request::new(BindExecutorRequest {
+                executor_id: executor_id.clone(),
+            }))
+            .await?;
+        flame
+            .bind_executor_completed(Request::new(BindExecutorCompletedRequest {
+                executor_id: executor_id.clone(),
+                ..Default::default()
+            }))
+            .await?;
+        flame
+            .launch_task(Request::new(LaunchTaskRequest {
+                executor_id: executor_id.clone(),
+            }))
+            .await?;
+        flame
+            .complete_task(Request::new(CompleteTaskRequest {
+                executor_id,
+                task_output: None,
+                ..Default::default()
+            }))
+            .await?;
+        flame
+            .close_session(Request::new(CloseSessionRequest {
+                session_id: ssn_id.clone(),
+                namespace: String::new(),
+            }))
+            .await?;
+
+        let get_events = |since: Option<i64>, until: Option<i64>| {
+            Request::new(GetSessionEventsRequest {
+                session_id: ssn_id.clone(),
+                namespace: String::new(),
+                since,
+                until,
+            })
+        };
+        let kinds = |events: Vec<rpc::SessionEvent>| {
+            events
+                .into_iter()
+                .map(|e| match e.event {
+                    Some(rpc::session_event::Event::TaskStateChanged(c)) => {
+                        format!("{}:{:?}", c.task_id, rpc::TaskState::try_from(c.state))
+                    }
+                    Some(rpc::session_event::Event::SessionClosed(_)) => "closed".to_string(),
+                    e => format!("{:?}", e),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let events = flame
+            .get_session_events(get_events(None, None))
+            .await?
+            .into_inner()
+            .events;
+        let sequences: Vec<_> = events.iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, (1..=sequences.len() as u64).collect::<Vec<_>>());
+        assert_eq!(
+            kinds(events),
+            vec![
+                "1:Ok(TaskPending)".to_string(),
+                "1:Ok(TaskRunning)".to_string(),
+                "1:Ok(TaskSucceed)".to_string(),
+                "closed".to_string(),
+            ]
+        );
+
+        // The events are filtered by their times.
+        let events = flame
+            .get_session_events(get_events(None, Some(start)))
+            .await?
+            .into_inner()
+            .events;
+        assert!(events.is_empty());
+        let events = flame
+            .get_session_events(get_events(Some(start), Some(start + 3600)))
+            .await?
+            .into_inner()
+            .events;
+        assert_eq!(events.len(), 4);
+        let e = flame
+            .get_session_events(get_events(Some(i64::MAX), None))
+            .await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+        // The history is kept by the session manager after restarting.
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        flame.storage.load_data().await?;
+        let events = flame
+            .get_session_events(get_events(None, None))
+            .await?
+            .into_inner()
+            .events;
+        assert_eq!(kinds(events).last(), Some(&"closed".to_string()));
+
+        let mut req = get_events(None, None);
+        req.get_mut().session_id = "9999".to_string();
+        let e = flame.get_session_events(req).await;
+        assert_eq!(e.unwrap_err().code(), tonic::Code::NotFound);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_task_with_invalid_input() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_create_task_with_invalid_input_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let flame = Flame {
+            storage: storage::new_ptr(&url).await?,
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+
+        let app = rpc::Application {
+            name: "llm".to_string(),
+            input: Some(rpc::InputConstraints {
+                max_bytes: Some(64),
+                required_fields: vec!["prompt".to_string()],
+                strict: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        flame
+            .register_application(Request::new(RegisterApplicationRequest {
+                application: Some(app),
+            }))
+            .await?;
+
+        let create_session = |common_data: &[u8]| {
+            Request::new(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: "llm".to_string(),
+                    slots: 1,
+                    common_data: Some(common_data.to_vec()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        };
+        let violation = |status: Status| match FlameError::from(status) {
+            FlameError::InvalidArgument { field, message } => (field, message),
+            e => panic!("unexpected error: {:?}", e),
+        };
+
+        // The common data is checked too, as the constraints are strict.
+        let e = flame.create_session(create_session(&[b'x'; 65])).await;
+        assert_eq!(
+            violation(e.unwrap_err()),
+            (
+                "common_data".to_string(),
+                "65 bytes exceed the limit of 64 bytes".to_string()
+            )
+        );
+        let ssn = flame
+            .create_session(create_session(b"model"))
+            .await?
+            .into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+
+        let create_task = |input: Vec<u8>| {
+            Request::new(CreateTaskRequest {
+                task: Some(TaskSpec {
+                    session_id: ssn_id.clone(),
+                    input: Some(input),
+                    ..Default::default()
+                }),
+            })
+        };
+        let json = |value: serde_json::Value| common::message::to_task_input(&value).unwrap();
+
+        let e = flame.create_task(create_task(vec![b'x'; 100])).await;
+        assert_eq!(
+            violation(e.unwrap_err()),
+            (
+                "input".to_string(),
+                "100 bytes exceed the limit of 64 bytes".to_string()
+            )
+        );
+        let e = flame
+            .create_task(create_task(
+                json(serde_json::json!({"model": "m"})).to_vec(),
+            ))
+            .await;
+        assert_eq!(
+            violation(e.unwrap_err()),
+            ("input".to_string(), "missing field(s) <prompt>".to_string())
+        );
+
+        // The valid input, and the raw one which is not JSON.
+        let input = json(serde_json::json!({"prompt": "hello"}));
+        flame.create_task(create_task(input.to_vec())).await?;
+        flame.create_task(create_task(b"hello".to_vec())).await?;
+        let ssn = flame
+            .storage
+            .get_session(apis::parse_session_id(&ssn_id)?)?;
+        assert_eq!(ssn.pending(), 2);
+
+        // The schema is rejected at registration, unless its feature is enabled.
+        let app = rpc::Application {
+            name: "schema".to_string(),
+            input: Some(rpc::InputConstraints {
+                schema: Some(r#"{"type": "object"}"#.to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let registered = flame
+            .register_application(Request::new(RegisterApplicationRequest {
+                application: Some(app),
+            }))
+            .await;
+        assert_eq!(registered.is_ok(), cfg!(feature = "jsonschema"));
+
+        Ok(())
+    }
 }
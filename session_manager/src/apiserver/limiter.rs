@@ -0,0 +1,179 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tonic::Request;
+
+use common::audit::ANONYMOUS;
+use common::ctx::FlameRateLimitConf;
+use common::{lock_ptr, FlameError};
+
+use crate::apiserver::Flame;
+
+/// The buckets of the callers are forgotten once they're full and there are more of them.
+const MAX_IDLE_BUCKETS: usize = 1024;
+
+/// The number of the mutating Frontend calls rejected by the rate limiters.
+static THROTTLED_CALLS: AtomicU64 = AtomicU64::new(0);
+
+/// The number of the mutating Frontend calls rejected by the rate limiters.
+pub fn throttled_calls() -> u64 {
+    THROTTLED_CALLS.load(Ordering::Relaxed)
+}
+
+pub type LimiterPtr = Arc<Limiter>;
+
+/// The token bucket of a caller.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// The token buckets of the callers; each call takes a token, and the tokens are refilled
+/// at `rate` per second up to `burst`.
+pub struct Limiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl Limiter {
+    pub fn new(conf: &FlameRateLimitConf) -> Self {
+        Limiter {
+            rate: conf.rate,
+            burst: conf.burst() as f64,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token of the caller at `now`, or returns how long the caller has to wait for
+    /// the next one.
+    fn acquire_at(&self, caller: &str, now: Instant) -> Result<(), FlameError> {
+        let mut buckets = lock_ptr!(self.buckets)?;
+
+        if buckets.len() >= MAX_IDLE_BUCKETS && !buckets.contains_key(caller) {
+            buckets.retain(|_, b| self.refill(b, now) < self.burst);
+        }
+
+        let bucket = buckets.entry(caller.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let tokens = self.refill(bucket, now);
+        if tokens >= 1.0 {
+            bucket.tokens = tokens - 1.0;
+            bucket.updated = now;
+            return Ok(());
+        }
+
+        THROTTLED_CALLS.fetch_add(1, Ordering::Relaxed);
+        let retry_after = Duration::from_secs_f64((1.0 - tokens) / self.rate);
+        Err(FlameError::ResourceExhausted {
+            message: format!("<{}> exceeded the rate limit of {}/s", caller, self.rate),
+            retry_after: Some(retry_after),
+        })
+    }
+
+    /// The tokens of the bucket at `now`.
+    fn refill(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated);
+        (bucket.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst)
+    }
+}
+
+impl Flame {
+    /// Takes a token of the caller of the request if the rate limit is enabled; the callers
+    /// are identified by their tokens, or by their addresses if the authentication is
+    /// disabled or failed.
+    pub fn throttle<T>(&self, req: &Request<T>) -> Result<(), FlameError> {
+        let Some(limiter) = &self.limiter else {
+            return Ok(());
+        };
+
+        let caller = match (&self.auth, self.identity(req)) {
+            (Some(_), Ok(identity)) => identity.name,
+            _ => req
+                .remote_addr()
+                .map(|addr| addr.ip().to_string())
+                .unwrap_or(ANONYMOUS.to_string()),
+        };
+
+        limiter.acquire_at(&caller, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() -> Result<(), FlameError> {
+        let limiter = Limiter::new(&FlameRateLimitConf {
+            rate: 2.0,
+            burst: Some(3),
+        });
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+
+        // The burst is allowed at once.
+        for _ in 0..3 {
+            limiter.acquire_at("team-a", start)?;
+        }
+        let before = throttled_calls();
+        let e = limiter.acquire_at("team-a", start).unwrap_err();
+        assert_eq!(e.retry_after(), Some(Duration::from_millis(500)));
+        assert!(throttled_calls() > before);
+
+        // The other callers have their own buckets.
+        limiter.acquire_at("team-b", start)?;
+
+        // A token is refilled every 500ms.
+        let e = limiter.acquire_at("team-a", at(250)).unwrap_err();
+        assert_eq!(e.retry_after(), Some(Duration::from_millis(250)));
+        limiter.acquire_at("team-a", at(500))?;
+        assert!(limiter.acquire_at("team-a", at(500)).is_err());
+
+        // The tokens are refilled up to the burst.
+        for _ in 0..3 {
+            limiter.acquire_at("team-a", at(60_000))?;
+        }
+        assert!(limiter.acquire_at("team-a", at(60_000)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_forget_idle_buckets() -> Result<(), FlameError> {
+        let limiter = Limiter::new(&FlameRateLimitConf {
+            rate: 1.0,
+            burst: None,
+        });
+        let start = Instant::now();
+
+        for i in 0..MAX_IDLE_BUCKETS {
+            limiter.acquire_at(&i.to_string(), start)?;
+        }
+        limiter.acquire_at("busy", start)?;
+        assert_eq!(lock_ptr!(limiter.buckets)?.len(), MAX_IDLE_BUCKETS + 1);
+
+        // The buckets refilled to the burst are the same as the new ones.
+        limiter.acquire_at("late", start + Duration::from_secs(1))?;
+        assert_eq!(lock_ptr!(limiter.buckets)?.len(), 1);
+
+        Ok(())
+    }
+}
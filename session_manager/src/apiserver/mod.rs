@@ -12,37 +12,105 @@ limitations under the License.
 */
 
 use std::env;
+use std::fs::{self, Permissions};
+use std::net::SocketAddr;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use futures::future;
+use tokio::net::UnixListener;
 use tokio::runtime::Runtime;
+use tokio_stream::wrappers::UnixListenerStream;
+use tonic::transport::server::Router;
 use tonic::transport::Server;
+use tonic::Request;
 
+use common::audit::{AuditLog, AuditLogPtr};
 use common::ctx::FlameContext;
+use common::{trace, with_grpc_options};
+use rpc::flame::admin_server::AdminServer;
 use rpc::flame::backend_server::BackendServer;
 use rpc::flame::frontend_server::FrontendServer;
 
+use crate::apiserver::admin::Admin;
+use crate::apiserver::auth::{Auth, AuthPtr};
+use crate::apiserver::limiter::{Limiter, LimiterPtr};
+use crate::scheduler::SchedulerStatePtr;
 use crate::storage::StoragePtr;
+use crate::supervisor::{StopSignal, SupervisorPtr};
 use crate::{FlameError, FlameThread};
 
+mod admin;
+mod audit;
+mod auth;
 mod backend;
 mod frontend;
+mod limiter;
+mod spool;
+mod validator;
+
+pub use limiter::throttled_calls;
 
 pub struct Flame {
     storage: StoragePtr,
+    /// The audit log of the mutating Frontend calls, if it's enabled.
+    audit: Option<AuditLogPtr>,
+    /// The tokens of the Frontend callers, if the authentication is enabled.
+    auth: Option<AuthPtr>,
+    /// The rate limit of the mutating Frontend calls, if it's enabled.
+    limiter: Option<LimiterPtr>,
 }
 
-pub fn new(storage: StoragePtr) -> Box<dyn FlameThread> {
-    Box::new(ApiserverRunner {
-        storage: storage.clone(),
-    })
+/// Continues the trace of the caller in the span of the call, and returns the trace context
+/// to persist, e.g. in the created task.
+fn continue_trace<T>(req: &Request<T>) -> Option<String> {
+    let parent = trace::extract(req.metadata());
+    trace::set_parent(&tracing::Span::current(), parent.as_deref());
+
+    trace::current().or(parent)
 }
 
-struct ApiserverRunner {
-    storage: StoragePtr,
+/// The deadline of the caller from the `grpc-timeout` of the request, e.g. `100m` for 100
+/// milliseconds; it's ignored if it's invalid.
+fn call_deadline<T>(req: &Request<T>) -> Option<Instant> {
+    let timeout = req.metadata().get("grpc-timeout")?.to_str().ok()?;
+    if !(2..=9).contains(&timeout.len()) {
+        return None;
+    }
+    let (value, unit) = timeout.split_at(timeout.len() - 1);
+    if !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let value: u64 = value.parse().ok()?;
+
+    let timeout = match unit {
+        "H" => Duration::from_secs(value * 3600),
+        "M" => Duration::from_secs(value * 60),
+        "S" => Duration::from_secs(value),
+        "m" => Duration::from_millis(value),
+        "u" => Duration::from_micros(value),
+        "n" => Duration::from_nanos(value),
+        _ => return None,
+    };
+
+    Some(Instant::now() + timeout)
 }
 
-impl FlameThread for ApiserverRunner {
-    fn run(&self, ctx: FlameContext) -> Result<(), FlameError> {
-        let url = url::Url::parse(&ctx.endpoint)
+/// The address of an endpoint to listen on.
+enum Listener {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl Listener {
+    fn new(endpoint: &str) -> Result<Self, FlameError> {
+        if let Some(path) = common::endpoint::socket_path(endpoint) {
+            return Ok(Listener::Unix(path));
+        }
+
+        let url = url::Url::parse(endpoint)
             .map_err(|_| FlameError::InvalidConfig("invalid endpoint".to_string()))?;
         let port = url.port().unwrap_or(8080);
 
@@ -53,36 +121,309 @@ impl FlameThread for ApiserverRunner {
 
         // The fsm will bind to localhost address directly.
         let address_str = format!("{}:{}", host, port);
-        log::info!("Listening apiserver at {}", address_str);
         let address = address_str
             .parse()
             .map_err(|_| FlameError::InvalidConfig("failed to parse url".to_string()))?;
 
+        Ok(Listener::Tcp(address))
+    }
+}
+
+/// Serves the services on the listener until it's stopped, and the calls in flight are done;
+/// the unix socket is created with the permissions of `mode`, and the stale one of the last
+/// run is replaced.
+async fn serve(
+    router: Router,
+    listener: Listener,
+    mode: u32,
+    stop: StopSignal,
+) -> Result<(), FlameError> {
+    let path = match listener {
+        Listener::Tcp(address) => {
+            log::info!("Listening apiserver at {}", address);
+            return router
+                .serve_with_shutdown(address, stop.stopped())
+                .await
+                .map_err(|e| FlameError::Internal(format!("failed to serve {}: {}", address, e)));
+        }
+        Listener::Unix(path) => path,
+    };
+
+    let io_error = |e: std::io::Error| FlameError::Internal(format!("<{}>: {}", path.display(), e));
+    if let Ok(meta) = fs::symlink_metadata(&path) {
+        if !meta.file_type().is_socket() {
+            return Err(FlameError::InvalidConfig(format!(
+                "<{}> exists and is not a socket",
+                path.display()
+            )));
+        }
+        fs::remove_file(&path).map_err(io_error)?;
+    }
+
+    let listener = UnixListener::bind(&path).map_err(io_error)?;
+    fs::set_permissions(&path, Permissions::from_mode(mode)).map_err(io_error)?;
+
+    log::info!("Listening apiserver at <{}> ({:o})", path.display(), mode);
+    router
+        .serve_with_incoming_shutdown(UnixListenerStream::new(listener), stop.stopped())
+        .await
+        .map_err(|e| FlameError::Internal(format!("failed to serve <{}>: {}", path.display(), e)))
+}
+
+pub fn new(
+    storage: StoragePtr,
+    scheduler: SchedulerStatePtr,
+    supervisor: SupervisorPtr,
+) -> Box<dyn FlameThread> {
+    Box::new(ApiserverRunner {
+        storage: storage.clone(),
+        scheduler,
+        supervisor,
+    })
+}
+
+struct ApiserverRunner {
+    storage: StoragePtr,
+    scheduler: SchedulerStatePtr,
+    supervisor: SupervisorPtr,
+}
+
+impl FlameThread for ApiserverRunner {
+    fn run(&self, ctx: FlameContext, stop: &StopSignal) -> Result<(), FlameError> {
+        let frontend_listener = Listener::new(&ctx.endpoint)?;
+        // The Backend is served with the Frontend unless it has its own endpoint.
+        let backend_listener = match &ctx.backend_endpoint {
+            Some(endpoint) if *endpoint != ctx.endpoint => Some(Listener::new(endpoint)?),
+            _ => None,
+        };
+        let mode = ctx.socket_mode()?;
+
+        let audit = match &ctx.audit {
+            Some(conf) => {
+                log::info!("Writing audit log to <{}>", conf.path);
+                Some(Arc::new(AuditLog::new(conf)?))
+            }
+            None => None,
+        };
+
+        let auth = ctx.auth.as_ref().map(|conf| {
+            log::info!(
+                "Authenticating Frontend calls by {} token(s)",
+                conf.tokens.len()
+            );
+            Arc::new(Auth::new(conf))
+        });
+
+        let limiter = ctx.rate_limit.as_ref().map(|conf| {
+            log::info!(
+                "Limiting the mutating Frontend calls to {}/s (burst {}) per caller",
+                conf.rate,
+                conf.burst()
+            );
+            Arc::new(Limiter::new(conf))
+        });
+
+        let admin_service = Admin {
+            storage: self.storage.clone(),
+            scheduler: self.scheduler.clone(),
+            supervisor: self.supervisor.clone(),
+            auth: auth.clone(),
+            ctx: ctx.clone(),
+        };
+
         let frontend_service = Flame {
             storage: self.storage.clone(),
+            audit,
+            auth,
+            limiter,
         };
 
+        // The executors are shared by the namespaces, and their calls are not limited.
         let backend_service = Flame {
             storage: self.storage.clone(),
+            audit: None,
+            auth: None,
+            limiter: None,
         };
 
+        let grpc = ctx.grpc_options()?;
+
         let rt = Runtime::new()
             .map_err(|_| FlameError::Internal("failed to start tokio runtime".to_string()))?;
         // Execute the future, blocking the current thread until completion
         rt.block_on(async {
-            let rc = Server::builder()
-                .add_service(FrontendServer::new(frontend_service))
-                .add_service(BackendServer::new(backend_service))
-                .serve(address)
-                .await;
-
-            match rc {
-                Ok(_) => {}
-                Err(e) => {
-                    log::error!("Failed to run apiserver: {}", e)
-                }
+            let mut router = Server::builder()
+                .add_service(with_grpc_options!(
+                    FrontendServer::new(frontend_service),
+                    grpc
+                ))
+                .add_service(with_grpc_options!(AdminServer::new(admin_service), grpc));
+            let backend_service = with_grpc_options!(BackendServer::new(backend_service), grpc);
+
+            let mut servers = vec![];
+            match backend_listener {
+                Some(listener) => servers.push(serve(
+                    Server::builder().add_service(backend_service),
+                    listener,
+                    mode,
+                    stop.clone(),
+                )),
+                None => router = router.add_service(backend_service),
             }
-        });
+            servers.push(serve(router, frontend_listener, mode, stop.clone()));
+
+            future::try_join_all(servers).await
+        })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use ::rpc::flame::backend_client::BackendClient;
+    use ::rpc::flame::frontend_client::FrontendClient;
+    use ::rpc::flame::{
+        self as rpc, BindExecutorCompletedRequest, BindExecutorRequest, CompleteTaskRequest,
+        CreateSessionRequest, CreateTaskRequest, ExecutorSpec, GetTaskRequest, LaunchTaskRequest,
+        RegisterApplicationRequest, RegisterExecutorRequest, SessionSpec, TaskSpec,
+    };
+    use chrono::Utc;
+    use common::apis;
+    use common::endpoint;
+
+    use crate::storage;
+
+    #[tokio::test]
+    async fn test_unix_socket() -> Result<(), Box<dyn std::error::Error>> {
+        let ts = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        let dir = env::temp_dir().join(format!("flame_test_uds_{}", ts));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("flame.sock");
+        let addr = format!("unix://{}", path.display());
+
+        let storage = storage::new_ptr(&format!("sqlite:///tmp/flame_test_uds_{}.db", ts)).await?;
+        let flame = || Flame {
+            storage: storage.clone(),
+            audit: None,
+            auth: None,
+            limiter: None,
+        };
+        let router = Server::builder()
+            .add_service(FrontendServer::new(flame()))
+            .add_service(BackendServer::new(flame()));
+        let stop = StopSignal::default();
+        let server = tokio::spawn(serve(router, Listener::new(&addr)?, 0o600, stop.clone()));
+        while !path.exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+
+        let channel = endpoint::connect(&addr, endpoint::to_endpoint(&addr)?).await?;
+        let mut frontend = FrontendClient::new(channel.clone());
+        let mut backend = BackendClient::new(channel);
+
+        let app = rpc::Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        };
+        frontend
+            .register_application(RegisterApplicationRequest {
+                application: Some(app.clone()),
+            })
+            .await?;
+        let ssn = frontend
+            .create_session(CreateSessionRequest {
+                session: Some(SessionSpec {
+                    application: app.name.clone(),
+                    slots: 1,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .await?
+            .into_inner();
+        let ssn_id = ssn.metadata.unwrap().id;
+        let task = frontend
+            .create_task(CreateTaskRequest {
+                task: Some(TaskSpec {
+                    session_id: ssn_id.clone(),
+                    input: Some(b"hello".to_vec()),
+                    ..Default::default()
+                }),
+            })
+            .await?
+            .into_inner();
+        let task_id = task.metadata.unwrap().id;
+
+        let executor_id = "exe-1".to_string();
+        backend
+            .register_executor(RegisterExecutorRequest {
+                executor_id: executor_id.clone(),
+                executor_spec: Some(ExecutorSpec {
+                    slots: 1,
+                    applications: vec![app],
+                    ..Default::default()
+                }),
+            })
+            .await?;
+        // Bind the executor as the scheduler does.
+        storage
+            .bind_session(executor_id.clone(), apis::parse_session_id(&ssn_id)?)
+            .await?;
+        backend
+            .bind_executor(BindExecutorRequest {
+                executor_id: executor_id.clone(),
+            })
+            .await?;
+        backend
+            .bind_executor_completed(BindExecutorCompletedRequest {
+                executor_id: executor_id.clone(),
+                ..Default::default()
+            })
+            .await?;
+        let launched = backend
+            .launch_task(LaunchTaskRequest {
+                executor_id: executor_id.clone(),
+            })
+            .await?
+            .into_inner();
+        assert_eq!(launched.task.unwrap().metadata.unwrap().id, task_id);
+        backend
+            .complete_task(CompleteTaskRequest {
+                executor_id,
+                task_output: Some(b"world".to_vec()),
+                session_id: ssn_id.clone(),
+                task_id: task_id.clone(),
+                failure: None,
+                failure_reason: None,
+                generation: None,
+                annotations: Default::default(),
+                session_annotations: Default::default(),
+            })
+            .await?;
+
+        let task = frontend
+            .get_task(GetTaskRequest {
+                task_id,
+                session_id: ssn_id,
+            })
+            .await?
+            .into_inner();
+        assert_eq!(
+            task.status.unwrap().state,
+            rpc::TaskState::TaskSucceed as i32
+        );
+
+        // The server is stopped gracefully.
+        stop.stop();
+        server.await??;
+
+        fs::remove_dir_all(&dir)?;
 
         Ok(())
     }
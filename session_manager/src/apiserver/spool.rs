@@ -0,0 +1,178 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bytes::Bytes;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+
+use common::FlameError;
+
+/// The bytes of an input kept in memory while it's received; the rest is spooled to a file.
+pub const SPOOL_THRESHOLD: usize = 8 << 20;
+
+static SPOOLS: AtomicU64 = AtomicU64::new(0);
+
+/// The input of a task received in chunks by `CreateTaskStream`; it's kept in memory up to
+/// the threshold and spooled to a temporary file after that, so the inputs being received
+/// don't pile up in memory. The file is removed when the spool is dropped, e.g. the stream
+/// was aborted.
+pub struct Spool {
+    threshold: usize,
+    buffer: Vec<u8>,
+    file: Option<(PathBuf, File)>,
+    len: usize,
+}
+
+impl Spool {
+    pub fn new(threshold: usize) -> Self {
+        Spool {
+            threshold,
+            buffer: vec![],
+            file: None,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The bytes held in memory by the spool.
+    #[cfg(test)]
+    fn memory(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), FlameError> {
+        self.len += data.len();
+        if self.file.is_none() && self.buffer.len() + data.len() <= self.threshold {
+            self.buffer.extend_from_slice(data);
+            return Ok(());
+        }
+
+        if self.file.is_none() {
+            let path = std::env::temp_dir().join(format!(
+                "flame-spool-{}-{}",
+                std::process::id(),
+                SPOOLS.fetch_add(1, Ordering::Relaxed)
+            ));
+            let file = File::create(&path).await.map_err(|e| io_error(&path, e))?;
+            self.file = Some((path, file));
+        }
+        let Some((path, file)) = &mut self.file else {
+            return Ok(());
+        };
+        if !self.buffer.is_empty() {
+            let buffer = std::mem::take(&mut self.buffer);
+            file.write_all(&buffer)
+                .await
+                .map_err(|e| io_error(path, e))?;
+        }
+        file.write_all(data).await.map_err(|e| io_error(path, e))
+    }
+
+    /// The whole input, which is read back in one buffer if it was spooled.
+    pub async fn into_bytes(mut self) -> Result<Bytes, FlameError> {
+        let Some((path, file)) = &mut self.file else {
+            return Ok(Bytes::from(std::mem::take(&mut self.buffer)));
+        };
+
+        file.flush().await.map_err(|e| io_error(path, e))?;
+        let data = tokio::fs::read(&path)
+            .await
+            .map_err(|e| io_error(path, e))?;
+
+        Ok(Bytes::from(data))
+    }
+
+    #[cfg(test)]
+    fn path(&self) -> Option<PathBuf> {
+        self.file.as_ref().map(|(path, _)| path.clone())
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        if let Some((path, _)) = &self.file {
+            if let Err(e) = std::fs::remove_file(path) {
+                log::warn!("Failed to remove spool <{}>: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+fn io_error(path: &std::path::Path, e: std::io::Error) -> FlameError {
+    FlameError::Internal(format!("spool <{}>: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHUNK: usize = 1 << 20;
+
+    fn chunk(i: usize) -> Vec<u8> {
+        vec![(i % 251) as u8; CHUNK]
+    }
+
+    #[tokio::test]
+    async fn test_spool_large_input() -> Result<(), FlameError> {
+        // A 300MB input is received with at most the threshold in memory.
+        let threshold = 4 * CHUNK;
+        let mut spool = Spool::new(threshold);
+        for i in 0..300 {
+            spool.write(&chunk(i)).await?;
+            assert!(spool.memory() <= threshold, "{} bytes", spool.memory());
+        }
+        assert_eq!(spool.len(), 300 * CHUNK);
+
+        let path = spool.path().expect("the spooled input");
+        let input = spool.into_bytes().await?;
+        assert_eq!(input.len(), 300 * CHUNK);
+        for i in [0, 3, 4, 150, 299] {
+            assert_eq!(
+                input[i * CHUNK..(i + 1) * CHUNK],
+                chunk(i)[..],
+                "chunk {}",
+                i
+            );
+        }
+        assert!(!path.exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_spool_aborted() -> Result<(), FlameError> {
+        // The small inputs are kept in memory.
+        let mut spool = Spool::new(2 * CHUNK);
+        spool.write(&chunk(0)).await?;
+        assert!(spool.path().is_none());
+        assert_eq!(spool.into_bytes().await?, Bytes::from(chunk(0)));
+
+        // The spooled input is removed once the stream is aborted.
+        let mut spool = Spool::new(2 * CHUNK);
+        for i in 0..3 {
+            spool.write(&chunk(i)).await?;
+        }
+        let path = spool.path().expect("the spooled input");
+        assert!(path.exists());
+        drop(spool);
+        assert!(!path.exists());
+
+        Ok(())
+    }
+}
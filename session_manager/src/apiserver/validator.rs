@@ -0,0 +1,299 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Checks the inputs of the tasks by the constraints of their applications when the tasks are
+//! created, so a garbage input is rejected by the Frontend instead of failing an executor.
+
+use std::cell::OnceCell;
+
+use serde_json::Value;
+
+use common::apis::InputConstraints;
+use common::message::{self, Codec};
+use common::FlameError;
+
+/// The input to check; its JSON value is decoded once, and only if a validator needs it.
+pub struct Input<'a> {
+    data: &'a [u8],
+    json: OnceCell<Result<Option<Value>, String>>,
+}
+
+impl<'a> Input<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Input {
+            data,
+            json: OnceCell::new(),
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        self.data
+    }
+
+    /// The value of the envelope of the json codec, or None if the input is not one.
+    pub fn json(&self) -> Result<Option<&Value>, String> {
+        let json = self
+            .json
+            .get_or_init(|| match message::codec_of(self.data) {
+                Some(Codec::Json) => {
+                    message::decode::<Value>(self.data)
+                        .map(Some)
+                        .map_err(|e| match e {
+                            FlameError::InvalidArgument { message, .. } => message,
+                            e => e.to_string(),
+                        })
+                }
+                _ => Ok(None),
+            });
+
+        json.as_ref().map(Option::as_ref).map_err(String::clone)
+    }
+}
+
+/// A check of the inputs, e.g. their size; it returns the violation of the input, which is
+/// sent back to the caller.
+pub trait InputValidator: Send + Sync {
+    fn validate(&self, input: &Input) -> Result<(), String>;
+}
+
+pub struct MaxBytes(pub u64);
+
+impl InputValidator for MaxBytes {
+    fn validate(&self, input: &Input) -> Result<(), String> {
+        match input.data().len() as u64 {
+            len if len > self.0 => Err(format!(
+                "{} bytes exceed the limit of {} bytes",
+                len, self.0
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The fields of the JSON object in the input; the inputs which are not JSON pass through.
+pub struct RequiredFields(pub Vec<String>);
+
+impl InputValidator for RequiredFields {
+    fn validate(&self, input: &Input) -> Result<(), String> {
+        let object = match input.json()? {
+            Some(Value::Object(object)) => object,
+            Some(_) => return Err("the JSON value is not an object".to_string()),
+            None => return Ok(()),
+        };
+
+        let missing: Vec<_> = self
+            .0
+            .iter()
+            .filter(|field| !object.contains_key(field.as_str()))
+            .map(String::as_str)
+            .collect();
+        match missing.is_empty() {
+            true => Ok(()),
+            false => Err(format!("missing field(s) <{}>", missing.join(", "))),
+        }
+    }
+}
+
+/// The JSON schema of the value in the input; the inputs which are not JSON pass through.
+#[cfg(feature = "jsonschema")]
+pub struct JsonSchema(jsonschema::JSONSchema);
+
+#[cfg(feature = "jsonschema")]
+impl JsonSchema {
+    pub fn new(schema: &str) -> Result<Self, String> {
+        let schema: Value =
+            serde_json::from_str(schema).map_err(|e| format!("schema is not JSON: {}", e))?;
+        jsonschema::JSONSchema::compile(&schema)
+            .map(JsonSchema)
+            .map_err(|e| format!("invalid schema: {}", e))
+    }
+}
+
+#[cfg(feature = "jsonschema")]
+impl InputValidator for JsonSchema {
+    fn validate(&self, input: &Input) -> Result<(), String> {
+        let value = match input.json()? {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+
+        self.0.validate(value).map_err(|errors| {
+            errors
+                .map(|e| match e.instance_path.to_string() {
+                    path if path.is_empty() => e.to_string(),
+                    path => format!("{}: {}", path, e),
+                })
+                .collect::<Vec<_>>()
+                .join("; ")
+        })
+    }
+}
+
+/// The validators of the constraints, which are checked in order; the size is checked first,
+/// so an oversized input is never decoded.
+pub fn validators(constraints: &InputConstraints) -> Result<Vec<Box<dyn InputValidator>>, String> {
+    let mut validators: Vec<Box<dyn InputValidator>> = vec![];
+    if let Some(max_bytes) = constraints.max_bytes {
+        validators.push(Box::new(MaxBytes(max_bytes)));
+    }
+    if !constraints.required_fields.is_empty() {
+        validators.push(Box::new(RequiredFields(
+            constraints.required_fields.clone(),
+        )));
+    }
+    if let Some(schema) = &constraints.schema {
+        #[cfg(feature = "jsonschema")]
+        validators.push(Box::new(JsonSchema::new(schema)?));
+        #[cfg(not(feature = "jsonschema"))]
+        {
+            let _ = schema;
+            return Err(
+                "input.schema is not enabled, rebuild with feature <jsonschema>".to_string(),
+            );
+        }
+    }
+
+    Ok(validators)
+}
+
+/// Checks the data, e.g. the input of a task, by the constraints; the violation is an invalid
+/// argument of the field.
+pub fn check(field: &str, constraints: &InputConstraints, data: &[u8]) -> Result<(), FlameError> {
+    let validators = validators(constraints).map_err(FlameError::InvalidConfig)?;
+
+    let input = Input::new(data);
+    for validator in validators {
+        validator
+            .validate(&input)
+            .map_err(|violation| FlameError::invalid_argument(field, violation))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    fn constraints(max_bytes: u64, fields: &[&str]) -> InputConstraints {
+        InputConstraints {
+            max_bytes: Some(max_bytes),
+            required_fields: fields.iter().map(|f| f.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn violation(e: FlameError) -> (String, String) {
+        match e {
+            FlameError::InvalidArgument { field, message } => (field, message),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let c = constraints(4, &[]);
+        assert!(check("input", &c, b"1234").is_ok());
+
+        let (field, message) = violation(check("input", &c, b"12345").unwrap_err());
+        assert_eq!(field, "input");
+        assert_eq!(message, "5 bytes exceed the limit of 4 bytes");
+    }
+
+    #[test]
+    fn test_required_fields() {
+        let c = constraints(1024, &["model", "prompt"]);
+        let input = |value: Value| message::to_task_input(&value).unwrap();
+
+        assert!(check("input", &c, &input(json!({"model": "m", "prompt": "p"}))).is_ok());
+
+        let (_, message) =
+            violation(check("input", &c, &input(json!({"model": "m"}))).unwrap_err());
+        assert_eq!(message, "missing field(s) <prompt>");
+
+        let (_, message) = violation(check("input", &c, &input(json!([1, 2]))).unwrap_err());
+        assert_eq!(message, "the JSON value is not an object");
+
+        // The envelope of the json codec which is not JSON.
+        let (_, message) = violation(
+            check(
+                "common_data",
+                &c,
+                &[message::VERSION, Codec::Json as u8, b'{'],
+            )
+            .unwrap_err(),
+        );
+        assert!(
+            message.starts_with("failed to decode by json"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn test_non_json_passthrough() {
+        let c = constraints(1024, &["model"]);
+        for data in [&b""[..], b"a b c", br#"{"prompt": "p"}"#] {
+            assert!(check("input", &c, data).is_ok());
+        }
+
+        // The size of the raw inputs is checked still.
+        assert!(check("input", &constraints(2, &["model"]), b"a b c").is_err());
+    }
+
+    #[cfg(not(feature = "jsonschema"))]
+    #[test]
+    fn test_schema_not_enabled() {
+        let c = InputConstraints {
+            schema: Some("{}".to_string()),
+            ..Default::default()
+        };
+        assert!(validators(&c).is_err());
+        assert!(matches!(
+            check("input", &c, b""),
+            Err(FlameError::InvalidConfig(_))
+        ));
+    }
+
+    #[cfg(feature = "jsonschema")]
+    #[test]
+    fn test_schema() {
+        let c = InputConstraints {
+            schema: Some(
+                json!({
+                    "type": "object",
+                    "properties": {"n": {"type": "integer", "minimum": 1}},
+                    "required": ["n"],
+                })
+                .to_string(),
+            ),
+            ..Default::default()
+        };
+        let input = |value: Value| message::to_task_input(&value).unwrap();
+
+        assert!(check("input", &c, &input(json!({"n": 2}))).is_ok());
+        assert!(check("input", &c, b"raw").is_ok());
+
+        let (_, message) = violation(check("input", &c, &input(json!({"n": 0}))).unwrap_err());
+        assert!(message.starts_with("/n: "), "{}", message);
+        assert!(check("input", &c, &input(json!({}))).is_err());
+
+        let c = InputConstraints {
+            schema: Some(json!({"type": "no-such-type"}).to_string()),
+            ..Default::default()
+        };
+        assert!(validators(&c).is_err());
+    }
+}
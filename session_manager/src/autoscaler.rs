@@ -0,0 +1,509 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::{BTreeMap, HashMap};
+use std::process::Stdio;
+use std::time;
+
+use chrono::{DateTime, Utc};
+use hyper::{Body, Client, Request};
+use serde_json::json;
+use tokio::io::AsyncWriteExt;
+
+use crate::model::SnapShot;
+use crate::scheduler::SchedulerStatePtr;
+use crate::storage::StoragePtr;
+use crate::supervisor::StopSignal;
+use crate::FlameThread;
+use common::apis::{ExecutorID, ExecutorState, SessionState, TaskState};
+use common::ctx::{FlameAutoscalerConf, FlameContext};
+use common::FlameError;
+
+/// How long to wait for the hook, so a hanging provisioner does not stall the signals.
+const DELIVERY_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+pub fn new(
+    storage: StoragePtr,
+    state: SchedulerStatePtr,
+    conf: FlameAutoscalerConf,
+) -> Box<dyn FlameThread> {
+    Box::new(AutoscaleRunner {
+        storage,
+        state,
+        conf,
+    })
+}
+
+/// Signals the provisioner of the executors in the background; the deliveries are recorded
+/// in the cluster stats of the scheduler.
+struct AutoscaleRunner {
+    storage: StoragePtr,
+    state: SchedulerStatePtr,
+    conf: FlameAutoscalerConf,
+}
+
+impl FlameThread for AutoscaleRunner {
+    fn run(&self, ctx: FlameContext, stop: &StopSignal) -> Result<(), FlameError> {
+        let timings = ctx.timings.unwrap_or_default();
+        let interval = timings.evaluate_interval()?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| FlameError::Internal(e.to_string()))?;
+        let mut autoscaler = Autoscaler::new(&self.conf, self.state.clone())?;
+
+        loop {
+            let now = Utc::now();
+            match self.storage.snapshot() {
+                Ok(snapshot) => {
+                    let signals = autoscaler.evaluate(&snapshot.borrow(), now);
+                    runtime.block_on(autoscaler.notify(signals, now));
+                }
+                Err(e) => log::error!("Failed to evaluate the demand: {}", e),
+            }
+            if stop.wait(timings.jittered(interval)) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum SignalKind {
+    ScaleUp,
+    ScaleDown,
+}
+
+/// The signal to the provisioner; the time is not part of it, so the same signal is
+/// deduplicated.
+#[derive(Clone, Debug, PartialEq)]
+struct Signal {
+    kind: SignalKind,
+    /// The slots of the pending tasks of each application.
+    applications: BTreeMap<String, i32>,
+    /// The pending slots not covered by the idle executors.
+    deficit: i32,
+    pending: i32,
+    executors: usize,
+    idle_executors: usize,
+    /// The executors idle longer than `scale_down_after`.
+    candidates: Vec<ExecutorID>,
+}
+
+impl Signal {
+    fn to_json(&self, now: DateTime<Utc>) -> serde_json::Value {
+        let applications = self
+            .applications
+            .iter()
+            .map(|(name, deficit)| json!({"name": name, "deficit": deficit}))
+            .collect::<Vec<_>>();
+
+        json!({
+            "kind": match self.kind {
+                SignalKind::ScaleUp => "scale_up",
+                SignalKind::ScaleDown => "scale_down",
+            },
+            "time": now.to_rfc3339(),
+            "applications": applications,
+            "deficit": self.deficit,
+            "pending": self.pending,
+            "executors": self.executors,
+            "idle_executors": self.idle_executors,
+            "candidates": self.candidates,
+        })
+    }
+}
+
+enum Target {
+    Url(hyper::Uri),
+    Command(String),
+}
+
+/// Evaluates the demand of the snapshots, and notifies the hook when the thresholds are
+/// exceeded; the time is given by the caller, so it can be tested with any time.
+struct Autoscaler {
+    target: Target,
+    scale_up_after: time::Duration,
+    scale_down_after: time::Duration,
+    min_interval: time::Duration,
+    repeat_after: time::Duration,
+    state: SchedulerStatePtr,
+
+    /// Since when the pending tasks exceed the idle executors.
+    deficit_since: Option<DateTime<Utc>>,
+    idle_since: HashMap<ExecutorID, DateTime<Utc>>,
+    /// The last attempt of each kind, for the rate limit.
+    last_attempt: HashMap<SignalKind, DateTime<Utc>>,
+    /// The last delivered signal of each kind, which is not repeated within `repeat_after`.
+    last_delivered: HashMap<SignalKind, (DateTime<Utc>, Signal)>,
+}
+
+impl Autoscaler {
+    fn new(conf: &FlameAutoscalerConf, state: SchedulerStatePtr) -> Result<Self, FlameError> {
+        let target = match (&conf.url, &conf.command) {
+            (Some(url), _) => Target::Url(url.parse().map_err(|e| {
+                FlameError::InvalidConfig(format!("autoscaler.url <{}>: {}", url, e))
+            })?),
+            (None, Some(cmd)) => Target::Command(cmd.clone()),
+            (None, None) => {
+                return Err(FlameError::InvalidConfig(
+                    "autoscaler: url or command is required".to_string(),
+                ))
+            }
+        };
+
+        Ok(Autoscaler {
+            target,
+            scale_up_after: conf.scale_up_after()?,
+            scale_down_after: conf.scale_down_after()?,
+            min_interval: conf.min_interval()?,
+            repeat_after: conf.repeat_after()?,
+            state,
+            deficit_since: None,
+            idle_since: HashMap::new(),
+            last_attempt: HashMap::new(),
+            last_delivered: HashMap::new(),
+        })
+    }
+
+    /// Returns the signals to notify at `now`, excluding the rate limited and repeated ones.
+    fn evaluate(&mut self, snapshot: &SnapShot, now: DateTime<Utc>) -> Vec<Signal> {
+        let mut applications = BTreeMap::<String, i32>::new();
+        for ssn in snapshot
+            .ssn_index
+            .get(&SessionState::Open)
+            .into_iter()
+            .flat_map(|ssns| ssns.values())
+        {
+            let pending = ssn.tasks_status.get(&TaskState::Pending).copied();
+            let slots = pending.unwrap_or_default() * ssn.slots;
+            if slots > 0 {
+                *applications.entry(ssn.application.clone()).or_default() += slots;
+            }
+        }
+        let pending: i32 = applications.values().sum();
+
+        let idle = snapshot
+            .executors
+            .values()
+            .filter(|e| e.state == ExecutorState::Idle && !e.draining)
+            .collect::<Vec<_>>();
+        let idle_slots: i32 = idle.iter().map(|e| e.slots).sum();
+        let deficit = (pending - idle_slots).max(0);
+
+        self.idle_since
+            .retain(|id, _| idle.iter().any(|e| &e.id == id));
+        for exec in &idle {
+            self.idle_since.entry(exec.id.clone()).or_insert(now);
+        }
+
+        let mut candidates = self
+            .idle_since
+            .iter()
+            .filter(|(_, since)| elapsed(**since, now) >= self.scale_down_after)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        candidates.sort();
+
+        self.deficit_since = match deficit {
+            0 => None,
+            _ => Some(self.deficit_since.unwrap_or(now)),
+        };
+
+        let signal = |kind, candidates| Signal {
+            kind,
+            applications: applications.clone(),
+            deficit,
+            pending,
+            executors: snapshot.executors.len(),
+            idle_executors: idle.len(),
+            candidates,
+        };
+        let mut signals = vec![];
+        if matches!(self.deficit_since, Some(since) if elapsed(since, now) >= self.scale_up_after) {
+            signals.push(signal(SignalKind::ScaleUp, vec![]));
+        }
+        // The idle executors are kept while there's any pending task.
+        if pending == 0 && !candidates.is_empty() {
+            signals.push(signal(SignalKind::ScaleDown, candidates));
+        }
+
+        signals.retain(|s| self.is_due(s, now));
+        signals
+    }
+
+    fn is_due(&self, signal: &Signal, now: DateTime<Utc>) -> bool {
+        if let Some(last) = self.last_attempt.get(&signal.kind) {
+            if elapsed(*last, now) < self.min_interval {
+                return false;
+            }
+        }
+
+        match self.last_delivered.get(&signal.kind) {
+            Some((last, s)) => s != signal || elapsed(*last, now) >= self.repeat_after,
+            None => true,
+        }
+    }
+
+    /// Delivers the signals to the hook, and records the deliveries; the failed ones are
+    /// retried after the rate limit.
+    async fn notify(&mut self, signals: Vec<Signal>, now: DateTime<Utc>) {
+        for signal in signals {
+            self.last_attempt.insert(signal.kind, now);
+
+            let res = self.deliver(&signal.to_json(now)).await;
+            if let Err(e) = self.state.record_autoscaler_delivery(res.is_ok()) {
+                log::error!("Failed to record the autoscaler delivery: {}", e);
+            }
+            match res {
+                Ok(()) => {
+                    log::info!(
+                        "Signaled {:?}: {} pending slots, {} deficit, {} candidates.",
+                        signal.kind,
+                        signal.pending,
+                        signal.deficit,
+                        signal.candidates.len()
+                    );
+                    self.last_delivered.insert(signal.kind, (now, signal));
+                }
+                Err(e) => log::warn!("Failed to signal {:?}: {}", signal.kind, e),
+            }
+        }
+    }
+
+    async fn deliver(&self, payload: &serde_json::Value) -> Result<(), FlameError> {
+        let delivery = async {
+            match &self.target {
+                Target::Url(uri) => post(uri, payload).await,
+                Target::Command(cmd) => exec(cmd, payload).await,
+            }
+        };
+
+        tokio::time::timeout(DELIVERY_TIMEOUT, delivery)
+            .await
+            .map_err(|_| unavailable(format!("no response in {}s", DELIVERY_TIMEOUT.as_secs())))?
+    }
+}
+
+async fn post(uri: &hyper::Uri, payload: &serde_json::Value) -> Result<(), FlameError> {
+    let req = Request::post(uri.clone())
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .map_err(|e| FlameError::Internal(e.to_string()))?;
+
+    let resp = Client::new()
+        .request(req)
+        .await
+        .map_err(|e| unavailable(e.to_string()))?;
+    if !resp.status().is_success() {
+        return Err(unavailable(format!("HTTP {}", resp.status())));
+    }
+
+    Ok(())
+}
+
+async fn exec(cmd: &str, payload: &serde_json::Value) -> Result<(), FlameError> {
+    let mut child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| FlameError::Internal(format!("failed to run <{}>: {}", cmd, e)))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(payload.to_string().as_bytes())
+            .await
+            .map_err(|e| FlameError::Internal(e.to_string()))?;
+    }
+
+    let status = child
+        .wait()
+        .await
+        .map_err(|e| FlameError::Internal(e.to_string()))?;
+    if !status.success() {
+        return Err(FlameError::Internal(format!("<{}> {}", cmd, status)));
+    }
+
+    Ok(())
+}
+
+fn unavailable(message: String) -> FlameError {
+    FlameError::Unavailable {
+        message,
+        retryable: true,
+    }
+}
+
+fn elapsed(since: DateTime<Utc>, now: DateTime<Utc>) -> time::Duration {
+    (now - since).to_std().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+
+    use chrono::Duration;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    use crate::model::{ExecutorInfo, SessionInfo};
+    use crate::scheduler::SchedulerState;
+
+    /// Captures the payloads posted to it, and replies 200 to all of them.
+    async fn listen() -> (String, mpsc::UnboundedReceiver<serde_json::Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/scale", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = vec![];
+                let mut chunk = [0u8; 4096];
+                // Reads the whole request; the payload is a JSON object.
+                while !buf.ends_with(b"}") {
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    buf.extend_from_slice(&chunk[..n]);
+                }
+                let req = String::from_utf8(buf).unwrap();
+                let body = req.split("\r\n\r\n").nth(1).unwrap_or_default();
+                tx.send(serde_json::from_str(body).unwrap()).unwrap();
+
+                let resp = b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n";
+                stream.write_all(resp).await.unwrap();
+            }
+        });
+
+        (url, rx)
+    }
+
+    fn snapshot(pending: i32, idle: &[&str]) -> SnapShot {
+        let mut snapshot = SnapShot::default();
+        snapshot.add_session(Rc::new(SessionInfo {
+            id: 1,
+            application: "pi".to_string(),
+            slots: 1,
+            tasks_status: HashMap::from([(TaskState::Pending, pending)]),
+            state: SessionState::Open,
+            ..Default::default()
+        }));
+        for id in idle {
+            snapshot.add_executor(Rc::new(ExecutorInfo {
+                id: id.to_string(),
+                slots: 1,
+                state: ExecutorState::Idle,
+                ..Default::default()
+            }));
+        }
+
+        snapshot
+    }
+
+    #[tokio::test]
+    async fn test_autoscaler_signals() -> Result<(), FlameError> {
+        let (url, mut payloads) = listen().await;
+        let state = SchedulerState::new_ptr();
+        let mut autoscaler = Autoscaler::new(
+            &FlameAutoscalerConf {
+                url: Some(url),
+                scale_up_after: Some("30s".to_string()),
+                scale_down_after: Some("5m".to_string()),
+                min_interval: Some("10s".to_string()),
+                repeat_after: Some("10m".to_string()),
+                ..Default::default()
+            },
+            state.clone(),
+        )?;
+        let t0 = Utc::now();
+        let at = |secs: i64| t0 + Duration::seconds(secs);
+
+        // The deficit is signaled after it lasts longer than the threshold.
+        let backlog = snapshot(3, &["e1"]);
+        assert!(autoscaler.evaluate(&backlog, t0).is_empty());
+        let signals = autoscaler.evaluate(&backlog, at(30));
+        assert_eq!(signals.len(), 1);
+        autoscaler.notify(signals, at(30)).await;
+
+        let payload = payloads.recv().await.unwrap();
+        assert_eq!(payload["kind"], "scale_up");
+        assert_eq!(payload["applications"][0]["name"], "pi");
+        assert_eq!(payload["applications"][0]["deficit"], 3);
+        assert_eq!(
+            (&payload["pending"], &payload["deficit"]),
+            (&json!(3), &json!(2))
+        );
+        assert_eq!(payload["executors"], 1);
+
+        // The same signal is neither rate limited nor repeated, but a changed one is.
+        assert!(autoscaler.evaluate(&backlog, at(35)).is_empty());
+        assert!(autoscaler.evaluate(&backlog, at(60)).is_empty());
+        let signals = autoscaler.evaluate(&snapshot(5, &["e1"]), at(60));
+        assert_eq!(signals[0].deficit, 4);
+
+        // The executors idle longer than the threshold are the candidates once the backlog
+        // is drained.
+        let drained = snapshot(0, &["e1", "e2"]);
+        assert!(autoscaler.evaluate(&drained, at(100)).is_empty());
+        let signals = autoscaler.evaluate(&drained, at(330));
+        assert_eq!(signals.len(), 1);
+        autoscaler.notify(signals, at(330)).await;
+        let payload = payloads.recv().await.unwrap();
+        assert_eq!(payload["kind"], "scale_down");
+        assert_eq!(payload["candidates"], json!(["e1"]));
+
+        let stats = state.cluster_stats()?;
+        assert_eq!(
+            (stats.autoscaler_deliveries, stats.autoscaler_failures),
+            (2, 0)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_autoscaler_failures() -> Result<(), FlameError> {
+        let state = SchedulerState::new_ptr();
+        let mut autoscaler = Autoscaler::new(
+            &FlameAutoscalerConf {
+                command: Some("cat > /dev/null; exit 1".to_string()),
+                scale_up_after: Some("0s".to_string()),
+                ..Default::default()
+            },
+            state.clone(),
+        )?;
+        let now = Utc::now();
+
+        // The failed signal is retried after the rate limit.
+        let signals = autoscaler.evaluate(&snapshot(1, &[]), now);
+        autoscaler.notify(signals, now).await;
+        assert!(autoscaler.evaluate(&snapshot(1, &[]), now).is_empty());
+        let later = now + Duration::seconds(30);
+        assert_eq!(autoscaler.evaluate(&snapshot(1, &[]), later).len(), 1);
+
+        let stats = state.cluster_stats()?;
+        assert_eq!(
+            (stats.autoscaler_deliveries, stats.autoscaler_failures),
+            (0, 1)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The workloads of the benchmarks in `benches`, which are run against the storage of the
+//! session manager; each workload is set up first, so only its `run` is measured.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::Utc;
+
+use common::apis::{
+    Application, Executor, ExecutorState, SessionAttributes, SessionID, TaskAttributes, TaskGID,
+    TaskState,
+};
+use common::ctx::Durability;
+use common::FlameError;
+
+use crate::storage::{self, StoragePtr};
+
+/// The sqlite databases of the workloads, which are new for every workload.
+static DATABASES: AtomicUsize = AtomicUsize::new(0);
+
+async fn new_storage(url: &str) -> Result<StoragePtr, FlameError> {
+    let storage = storage::new_ptr(url).await?;
+    storage.set_config_applications(&[Application {
+        name: "flmexec".to_string(),
+        ..Default::default()
+    }])?;
+
+    Ok(storage)
+}
+
+async fn create_session(storage: &StoragePtr) -> Result<SessionID, FlameError> {
+    let ssn = storage
+        .create_session(SessionAttributes {
+            application: "flmexec".to_string(),
+            slots: 1,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(ssn.id)
+}
+
+/// The completions of the pending tasks of a session by concurrent workers with sqlite in
+/// the given durability.
+pub struct CompleteTasks {
+    storage: StoragePtr,
+    ssn_id: SessionID,
+    gids: Vec<TaskGID>,
+}
+
+impl CompleteTasks {
+    pub async fn new(durability: Durability, tasks: usize) -> Result<Self, FlameError> {
+        let path = std::env::temp_dir().join(format!(
+            "flame_bench_complete_tasks_{}_{}.db",
+            std::process::id(),
+            DATABASES.fetch_add(1, Ordering::SeqCst)
+        ));
+        let storage = new_storage(&format!("sqlite://{}", path.display())).await?;
+        storage.set_durability(durability, 512)?;
+
+        let ssn_id = create_session(&storage).await?;
+        let mut gids = vec![];
+        for _ in 0..tasks {
+            let task = storage
+                .create_task(ssn_id, TaskAttributes::default())
+                .await?;
+            gids.push(task.gid());
+        }
+
+        Ok(CompleteTasks {
+            storage,
+            ssn_id,
+            gids,
+        })
+    }
+
+    /// Completes all the tasks by `workers` concurrent workers, and flushes the changes
+    /// written behind.
+    pub async fn run(&self, workers: usize) -> Result<(), FlameError> {
+        let mut handles = vec![];
+        for i in 0..workers {
+            let storage = self.storage.clone();
+            let ssn_id = self.ssn_id;
+            let gids: Vec<_> = self.gids.iter().skip(i).step_by(workers).copied().collect();
+            handles.push(tokio::spawn(async move {
+                let ssn_ptr = storage.get_session_ptr(ssn_id)?;
+                for gid in gids {
+                    let task_ptr = storage.get_task_ptr(gid)?;
+                    storage
+                        .update_task_state(ssn_ptr.clone(), task_ptr, TaskState::Succeed)
+                        .await?;
+                }
+                Ok::<_, FlameError>(())
+            }));
+        }
+        for handle in handles {
+            handle
+                .await
+                .map_err(|e| FlameError::Internal(e.to_string()))??;
+        }
+        self.storage.flush_task_updates().await?;
+
+        Ok(())
+    }
+}
+
+/// The launches and completions of the tasks by the executors bound to the sessions with
+/// the memory engine, i.e. the Backend calls looking up the sessions and the executors.
+pub struct LaunchComplete {
+    storage: StoragePtr,
+    executors: usize,
+}
+
+impl LaunchComplete {
+    /// Creates `tasks` pending tasks in each of the sessions, and binds the executors to
+    /// the sessions in turn.
+    pub async fn new(executors: usize, sessions: usize, tasks: usize) -> Result<Self, FlameError> {
+        let storage = new_storage("memory://").await?;
+
+        let mut ssn_ids = vec![];
+        for _ in 0..sessions {
+            let ssn_id = create_session(&storage).await?;
+            for _ in 0..tasks {
+                storage
+                    .create_task(ssn_id, TaskAttributes::default())
+                    .await?;
+            }
+            ssn_ids.push(ssn_id);
+        }
+
+        for i in 0..executors {
+            let id = format!("exec-{}", i);
+            storage.register_executor(&Executor {
+                id: id.clone(),
+                slots: 1,
+                applications: vec![],
+                task_id: None,
+                ssn_id: None,
+                labels: HashMap::new(),
+                locality_keys: vec![],
+                host: Default::default(),
+                creation_time: Utc::now(),
+                last_heartbeat: Utc::now(),
+                state: ExecutorState::Idle,
+                draining: false,
+                quarantined: false,
+                generation: 0,
+            })?;
+            storage
+                .bind_session(id.clone(), ssn_ids[i % sessions])
+                .await?;
+            storage.bind_session_completed(id, None, None).await?;
+        }
+
+        Ok(LaunchComplete { storage, executors })
+    }
+
+    /// Launches and completes the tasks by all the executors concurrently until the sessions
+    /// are drained; returns the number of the completed tasks.
+    pub async fn run(&self) -> Result<usize, FlameError> {
+        let mut handles = vec![];
+        for i in 0..self.executors {
+            let storage = self.storage.clone();
+            handles.push(tokio::spawn(async move {
+                let id = format!("exec-{}", i);
+                let mut n = 0;
+                while storage.launch_task(id.clone()).await?.is_some() {
+                    storage.complete_task(id.clone(), None, None, None).await?;
+                    n += 1;
+                }
+                Ok::<_, FlameError>(n)
+            }));
+        }
+
+        let mut completed = 0;
+        for handle in handles {
+            completed += handle
+                .await
+                .map_err(|e| FlameError::Internal(e.to_string()))??;
+        }
+
+        Ok(completed)
+    }
+}
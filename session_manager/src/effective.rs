@@ -0,0 +1,74 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use serde::Serialize;
+
+use common::ctx::FlameContext;
+
+use crate::storage::{EngineInfo, StoragePtr};
+
+/// The optional features of the session manager, and whether they're built in.
+const FEATURES: [(&str, bool); 3] = [
+    ("otel", cfg!(feature = "otel")),
+    ("gzip", cfg!(feature = "gzip")),
+    ("zstd", cfg!(feature = "zstd")),
+];
+
+/// What the session manager actually runs with: the configuration resolved from the file,
+/// the environments and the flags, with the secrets redacted, and the storage and the build
+/// serving it. It's logged at startup, and returned by `GetEffectiveConfig`.
+#[derive(Clone, Debug, Serialize)]
+pub struct EffectiveConfig {
+    pub config: FlameContext,
+    pub storage: EngineInfo,
+    /// The optional features built in, e.g. otel.
+    pub features: Vec<String>,
+    pub build: BuildInfo,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BuildInfo {
+    pub version: String,
+    /// The architecture and the OS, e.g. x86_64-linux.
+    pub target: String,
+    /// debug or release.
+    pub profile: String,
+}
+
+impl Default for BuildInfo {
+    fn default() -> Self {
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            target: format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS),
+            profile: match cfg!(debug_assertions) {
+                true => "debug".to_string(),
+                false => "release".to_string(),
+            },
+        }
+    }
+}
+
+impl EffectiveConfig {
+    pub fn new(ctx: &FlameContext, storage: &StoragePtr) -> Self {
+        EffectiveConfig {
+            config: ctx.clone().redacted(),
+            storage: storage.engine_info(),
+            features: FEATURES
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| name.to_string())
+                .collect(),
+            build: BuildInfo::default(),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+/*
+Copyright 2023 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The session manager, which is run by `flame-session-manager` or in process by the tests,
+//! e.g. the end-to-end harness.
+
+use std::time::Duration;
+
+use common::ctx::{Durability, FlameContext};
+use common::FlameError;
+
+mod apiserver;
+mod autoscaler;
+#[doc(hidden)]
+pub mod bench;
+mod effective;
+mod model;
+mod persister;
+mod scheduler;
+mod sim;
+mod storage;
+mod supervisor;
+mod sweeper;
+
+pub use crate::sim::run_file as simulate;
+pub use crate::supervisor::{LoopHealth, LoopState, StopSignal, Supervisor, SupervisorPtr};
+
+/// How long a thread may take to stop at shutdown, e.g. to finish the calls in flight.
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A background loop of the session manager, which runs until it's stopped, see
+/// `Supervisor`.
+pub trait FlameThread: Send + Sync + 'static {
+    fn run(&self, ctx: FlameContext, stop: &StopSignal) -> Result<(), FlameError>;
+}
+
+/// Starts the threads of the session manager, e.g. the scheduler and the apiserver, with the
+/// resolved configuration; it returns their supervisor, and the threads run until they're shut
+/// down by it.
+pub async fn start(ctx: &FlameContext) -> Result<SupervisorPtr, FlameError> {
+    let supervisor = supervisor::Supervisor::new_ptr(Default::default());
+
+    let storage = storage::new_ptr(&ctx.storage).await?;
+    match serde_json::to_string(&effective::EffectiveConfig::new(ctx, &storage)) {
+        Ok(conf) => log::info!("The effective configuration: {}", conf),
+        Err(e) => log::warn!("Failed to print the effective configuration: {}", e),
+    }
+    storage.set_config_applications(&ctx.applications)?;
+    storage.set_max_pending_tasks(ctx.max_pending_tasks)?;
+    storage.set_cache_conf(ctx.cache.clone().unwrap_or_default())?;
+    storage.set_spill_completed_tasks(ctx.spill_completed_tasks.unwrap_or(true))?;
+    storage.set_max_task_retries(ctx.max_task_retries)?;
+    storage.set_reject_unschedulable(ctx.reject_unschedulable.unwrap_or(false))?;
+    storage.set_locality_wait(ctx.timings.clone().unwrap_or_default().locality_wait()?)?;
+    let durability = ctx.durability.unwrap_or_default();
+    storage.set_durability(
+        durability,
+        ctx.write_behind.clone().unwrap_or_default().max_batch(),
+    )?;
+    storage.set_time_slice(
+        ctx.time_slice
+            .as_ref()
+            .map(model::TimeSlice::try_from)
+            .transpose()?,
+    )?;
+    storage.set_session_templates(&ctx.session_templates)?;
+    storage.set_quotas(&ctx.quotas)?;
+    storage.set_priority_classes(&ctx.priority_classes)?;
+    storage.set_quarantine_conf(&ctx.quarantine.clone().unwrap_or_default())?;
+    storage.set_utilization_conf(&ctx.utilization.clone().unwrap_or_default())?;
+
+    let monitor = ctx.monitor.clone().unwrap_or_default();
+    common::monitor::set_lock_threshold(monitor.lock_threshold()?);
+    storage::set_engine_budget(monitor.engine_budget()?);
+    let engine_retry = ctx.engine_retry.clone().unwrap_or_default();
+    storage::set_engine_retry(engine_retry.attempts, engine_retry.backoff()?);
+    let debug = ctx.debug.clone().unwrap_or_default();
+    common::payload::set_preview(debug.payload_preview.unwrap_or_default());
+
+    // Load data from engine, e.g. sqlite.
+    storage.load_data().await?;
+
+    // The threads are stopped in this order, so the scheduler stops binding the sessions
+    // before the calls are drained, and the sweeper flushes the storage at last.
+    let scheduler = scheduler::SchedulerState::new_ptr();
+    let mut threads = vec![(
+        "scheduler",
+        scheduler::new(storage.clone(), scheduler.clone()),
+    )];
+    if let Some(conf) = &ctx.autoscaler {
+        threads.push((
+            "autoscaler",
+            autoscaler::new(storage.clone(), scheduler.clone(), conf.clone()),
+        ));
+    }
+    threads.push((
+        "apiserver",
+        apiserver::new(storage.clone(), scheduler, supervisor.clone()),
+    ));
+    threads.push(("sweeper", sweeper::new(storage.clone())));
+    // It's stopped after the sweeper, so the changes of the last sweep are persisted.
+    if durability == Durability::Batched {
+        log::warn!("The changes of tasks are written behind, a crash may lose the last batch.");
+        threads.push(("persister", persister::new(storage.clone())));
+    }
+
+    for (name, thread) in threads {
+        supervisor.spawn(name, thread, ctx.clone())?;
+    }
+
+    Ok(supervisor)
+}
+
+/// Runs the session manager until it's interrupted or terminated, and shuts it down.
+pub async fn run(ctx: &FlameContext) -> Result<(), FlameError> {
+    let supervisor = start(ctx).await?;
+
+    log::info!("flame-session-manager started.");
+
+    shutdown_signal().await?;
+    log::info!("flame-session-manager is stopping ...");
+
+    tokio::task::spawn_blocking(move || supervisor.shutdown(STOP_TIMEOUT))
+        .await
+        .map_err(|e| FlameError::Internal(format!("failed to shut down: {}", e)))??;
+    log::info!("flame-session-manager stopped.");
+
+    Ok(())
+}
+
+/// Resolves on Ctrl-C or SIGTERM.
+async fn shutdown_signal() -> Result<(), FlameError> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())
+        .map_err(|e| FlameError::Internal(format!("failed to listen to SIGTERM: {}", e)))?;
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => {
+            res.map_err(|e| FlameError::Internal(format!("failed to listen to Ctrl-C: {}", e)))
+        }
+        _ = terminate.recv() => Ok(()),
+    }
+}
@@ -11,19 +11,14 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::collections::HashMap;
 use std::thread;
 
 use clap::Parser;
 
-use common::ctx::FlameContext;
+use common::ctx::{FlameContext, FlameOverrides};
+use common::trace::LogFormat;
 use common::FlameError;
 
-mod apiserver;
-mod model;
-mod scheduler;
-mod storage;
-
 #[derive(Parser)]
 #[command(name = "flame-session-manager")]
 #[command(author = "Klaus Ma <klaus@xflops.cn>")]
@@ -32,53 +27,51 @@ mod storage;
 struct Cli {
     #[arg(long)]
     flame_conf: Option<String>,
+    /// The endpoint to listen on, e.g. http://0.0.0.0:8080
+    #[arg(long, alias = "bind-address")]
+    endpoint: Option<String>,
+    /// The storage of the sessions, e.g. sqlite:///var/lib/flame/flame.db
+    #[arg(long)]
+    storage: Option<String>,
+    /// The scheduling policy, e.g. fairshare
+    #[arg(long)]
+    policy: Option<String>,
+    /// The format of the logs, text or json; it's $FLAME_LOG_FORMAT by default
+    #[arg(long)]
+    log_format: Option<LogFormat>,
+    /// Replays the workload in the YAML file against the scheduler with a virtual clock and
+    /// prints the report, instead of serving
+    #[arg(long, value_name = "WORKLOAD")]
+    simulate: Option<String>,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), FlameError> {
-    env_logger::init();
-
     let cli = Cli::parse();
-    let ctx = FlameContext::from_file(cli.flame_conf)?;
-
-    log::info!("flame-session-manager is starting ...");
-
-    let mut handlers = vec![];
-    let mut threads = HashMap::new();
-
-    let storage = storage::new_ptr(&ctx.storage).await?;
-
-    // Load data from engine, e.g. sqlite.
-    storage.load_data().await?;
-
-    threads.insert("scheduler", scheduler::new(storage.clone()));
-    threads.insert("apiserver", apiserver::new(storage.clone()));
-
-    for (n, thread) in threads {
-        let ctx = ctx.clone();
-        let handler = thread::spawn(move || {
-            match thread.run(ctx) {
-                Ok(_) => {}
-                Err(e) => {
-                    log::error!("Failed to run thread: {}", e);
-                }
-            };
-        });
-
-        log::info!("<{}> thread was started.", n);
-
-        handlers.push(handler);
+    if let Some(workload) = cli.simulate {
+        // The scheduler blocks on its own runtime, so it's simulated out of this one.
+        let report = thread::spawn(move || flame_session_manager::simulate(&workload))
+            .join()
+            .map_err(|_| FlameError::Internal("the simulation panicked".to_string()))??;
+        print!("{}", report.to_yaml()?);
+
+        return Ok(());
     }
 
-    log::info!("flame-session-manager started.");
+    let flags = FlameOverrides {
+        endpoint: cli.endpoint,
+        storage: cli.storage,
+        policy: cli.policy,
+    };
+    let ctx = FlameContext::resolve(cli.flame_conf, std::env::vars(), &flags)?;
 
-    for h in handlers {
-        h.join().unwrap();
-    }
+    common::trace::init(
+        "flame-session-manager",
+        ctx.telemetry.as_ref(),
+        cli.log_format.unwrap_or_else(LogFormat::from_env),
+    )?;
 
-    Ok(())
-}
+    log::info!("flame-session-manager is starting ...");
 
-pub trait FlameThread: Send + Sync + 'static {
-    fn run(&self, ctx: FlameContext) -> Result<(), FlameError>;
+    flame_session_manager::run(&ctx).await
 }
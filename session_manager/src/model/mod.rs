@@ -12,26 +12,71 @@ limitations under the License.
 */
 
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
+use std::time::Duration;
 
 use chrono::{DateTime, Utc};
 
 use common::apis::{
     Application, Executor, ExecutorID, ExecutorState, Session, SessionID, SessionState, Task,
-    TaskID, TaskState,
+    TaskID, TaskState, DEFAULT_APPLICATION_WEIGHT,
 };
+use common::ctx::{FlamePriorityClassConf, FlameQuotaConf, FlameTimeSliceConf};
+use common::FlameError;
 
 pub type SessionInfoPtr = Rc<SessionInfo>;
 pub type ExecutorInfoPtr = Rc<ExecutorInfo>;
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct SnapShot {
     pub sessions: HashMap<SessionID, SessionInfoPtr>,
     pub ssn_index: HashMap<SessionState, HashMap<SessionID, SessionInfoPtr>>,
 
     pub executors: HashMap<ExecutorID, ExecutorInfoPtr>,
     pub exec_index: HashMap<ExecutorState, HashMap<ExecutorID, ExecutorInfoPtr>>,
+
+    /// The applications registered at runtime, which are fetched by the executors when
+    /// binding, so any executor could run them.
+    pub applications: HashSet<String>,
+    /// The weights of the applications in the configuration and the registry.
+    pub weights: HashMap<String, u32>,
+    /// The slice of the executors bound to a session; none if the executors are kept by
+    /// their sessions.
+    pub time_slice: Option<TimeSlice>,
+    /// The caps of the slots and the running tasks of the sessions in a namespace or of an
+    /// application.
+    pub quotas: Vec<FlameQuotaConf>,
+    /// The classes of the sessions, e.g. the executors reserved for them.
+    pub priority_classes: Vec<FlamePriorityClassConf>,
+}
+
+/// The usage of a quota in the snapshot.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QuotaUsage {
+    /// The slots of the executors bound to the sessions of the quota.
+    pub slots: u32,
+    /// The executors bound to the sessions of the quota, each of them runs a task at most.
+    pub executors: u32,
+    pub running_tasks: u32,
+}
+
+/// The slice of an executor bound to a session, which is used up by either of the limits.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TimeSlice {
+    pub tasks: Option<u32>,
+    pub duration: Option<Duration>,
+}
+
+impl TryFrom<&FlameTimeSliceConf> for TimeSlice {
+    type Error = FlameError;
+
+    fn try_from(conf: &FlameTimeSliceConf) -> Result<Self, Self::Error> {
+        Ok(TimeSlice {
+            tasks: conf.tasks,
+            duration: conf.duration()?,
+        })
+    }
 }
 
 pub type SnapShotPtr = Rc<RefCell<SnapShot>>;
@@ -40,6 +85,12 @@ pub type SnapShotPtr = Rc<RefCell<SnapShot>>;
 pub struct TaskInfo {
     pub id: TaskID,
     pub ssn_id: SessionID,
+    /// The size of the input in bytes, e.g. to bin-pack the tasks.
+    pub input_size: usize,
+    /// The labels of the task, e.g. `zone=a` as the hint of its locality.
+    pub labels: HashMap<String, String>,
+    /// The key of the data of the task, e.g. a shard id.
+    pub locality: Option<String>,
 
     pub creation_time: DateTime<Utc>,
     pub completion_time: Option<DateTime<Utc>>,
@@ -50,15 +101,26 @@ pub struct TaskInfo {
 #[derive(Debug, Default, Clone)]
 pub struct SessionInfo {
     pub id: SessionID,
+    pub namespace: String,
     pub application: String,
     pub slots: i32,
+    pub priority_class: Option<String>,
+    /// The executors bound to the session are limited by its `max_running_tasks`, as each of
+    /// them runs a task at most.
+    pub max_running_tasks: Option<u32>,
 
     pub tasks_status: HashMap<TaskState, i32>,
+    /// The creation time of the oldest pending task, none if no task is pending.
+    pub oldest_pending: Option<DateTime<Utc>>,
 
     pub creation_time: DateTime<Utc>,
     pub completion_time: Option<DateTime<Utc>>,
 
     pub state: SessionState,
+
+    /// The pending tasks not reserved yet, which are loaded only if a plugin asks for them
+    /// to assign the tasks to the executors, see `Context::assign_task`.
+    pub pending_tasks: RefCell<Option<Vec<TaskInfo>>>,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -66,11 +128,24 @@ pub struct ExecutorInfo {
     pub id: ExecutorID,
     pub slots: i32,
     pub applications: Vec<AppInfo>,
+    pub labels: HashMap<String, String>,
+    /// The keys of the data local to the executor, e.g. the shards cached on its host.
+    pub locality_keys: Vec<String>,
     pub task_id: Option<TaskID>,
     pub ssn_id: Option<SessionID>,
 
     pub creation_time: DateTime<Utc>,
     pub state: ExecutorState,
+    pub draining: bool,
+    pub quarantined: bool,
+
+    /// When the executor was bound to its session, and the tasks it launched since then.
+    pub bound_time: Option<DateTime<Utc>>,
+    pub launched_tasks: u32,
+    /// The session the executor is bound to once it's unbound, e.g. its slice was used up.
+    pub next_ssn_id: Option<SessionID>,
+    /// The sessions the executor served recently, the most recent first.
+    pub served: Vec<SessionID>,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -100,10 +175,18 @@ impl From<&Executor> for ExecutorInfo {
             id: exec.id.clone(),
             slots: exec.slots,
             applications,
+            labels: exec.labels.clone(),
+            locality_keys: exec.locality_keys.clone(),
             task_id: exec.task_id,
             ssn_id: exec.ssn_id,
             creation_time: exec.creation_time,
             state: exec.state,
+            draining: exec.draining,
+            quarantined: exec.quarantined,
+            bound_time: None,
+            launched_tasks: 0,
+            next_ssn_id: None,
+            served: vec![],
         }
     }
 }
@@ -113,6 +196,9 @@ impl From<&Task> for TaskInfo {
         TaskInfo {
             id: task.id,
             ssn_id: task.ssn_id,
+            input_size: task.input.as_ref().map(|i| i.len()).unwrap_or_default(),
+            labels: task.labels.clone(),
+            locality: task.locality.clone(),
             creation_time: task.creation_time,
             completion_time: task.completion_time,
             state: task.state,
@@ -120,6 +206,47 @@ impl From<&Task> for TaskInfo {
     }
 }
 
+impl SessionInfo {
+    /// The slots desired by the session, i.e. the slots of its pending and running tasks up
+    /// to its `max_running_tasks`.
+    pub fn desired(&self) -> f64 {
+        let tasks: i32 = [TaskState::Pending, TaskState::Running]
+            .iter()
+            .filter_map(|state| self.tasks_status.get(state))
+            .sum();
+        let tasks = match self.max_running_tasks {
+            Some(max) => tasks.min(max as i32),
+            None => tasks,
+        };
+
+        tasks as f64 * self.slots as f64
+    }
+
+    /// The number of the pending tasks.
+    pub fn pending(&self) -> i32 {
+        self.tasks_status
+            .get(&TaskState::Pending)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The pending tasks, none if they're not loaded.
+    pub fn pending_tasks(&self) -> Option<Vec<TaskInfo>> {
+        self.pending_tasks.borrow().clone()
+    }
+
+    pub fn set_pending_tasks(&self, tasks: Vec<TaskInfo>) {
+        *self.pending_tasks.borrow_mut() = Some(tasks);
+    }
+
+    /// Removes the task assigned to an executor, so it's not assigned again in the cycle.
+    pub fn remove_pending_task(&self, id: TaskID) {
+        if let Some(tasks) = self.pending_tasks.borrow_mut().as_mut() {
+            tasks.retain(|t| t.id != id);
+        }
+    }
+}
+
 impl From<&Session> for SessionInfo {
     fn from(ssn: &Session) -> Self {
         // let mut tasks = vec![];
@@ -128,20 +255,184 @@ impl From<&Session> for SessionInfo {
             tasks_status.insert(*k, v.len() as i32);
         }
 
+        // The task IDs are increasing, so the smallest pending one is the oldest.
+        let oldest_pending = ssn
+            .tasks_index
+            .get(&TaskState::Pending)
+            .and_then(|tasks| tasks.iter().min_by_key(|(id, _)| **id))
+            .and_then(|(_, task)| task.lock().ok().map(|t| t.creation_time));
+
         SessionInfo {
             id: ssn.id,
+            namespace: ssn.namespace.clone(),
             application: ssn.application.clone(),
             slots: ssn.slots,
+            priority_class: ssn.priority_class.clone(),
+            max_running_tasks: ssn.max_running_tasks,
             // tasks,
             tasks_status,
+            oldest_pending,
             creation_time: ssn.creation_time,
             completion_time: ssn.completion_time,
             state: ssn.status.state,
+            pending_tasks: RefCell::new(None),
         }
     }
 }
 
 impl SnapShot {
+    pub fn weight(&self, application: &str) -> u32 {
+        self.weights
+            .get(application)
+            .copied()
+            .unwrap_or(DEFAULT_APPLICATION_WEIGHT)
+    }
+
+    /// The slots desired by the open sessions of each application.
+    pub fn app_demand(&self) -> HashMap<String, f64> {
+        let mut demand = HashMap::new();
+        for ssn in self
+            .ssn_index
+            .get(&SessionState::Open)
+            .into_iter()
+            .flat_map(HashMap::values)
+        {
+            *demand.entry(ssn.application.clone()).or_default() += ssn.desired();
+        }
+
+        demand
+    }
+
+    /// Whether the executor runs the application, i.e. the application is registered at
+    /// runtime or it's one of the applications of the executor.
+    pub fn offers(&self, exec: &ExecutorInfo, application: &str) -> bool {
+        self.applications.contains(application)
+            || exec.applications.iter().any(|app| app.name == application)
+    }
+
+    /// Why no executor could ever run a session of the application with the slots, e.g. none
+    /// of them offers the application; the busy executors count as they're released later,
+    /// but the draining ones don't. It's none if no executor is registered, e.g. they're
+    /// provisioned on demand.
+    pub fn unschedulable(&self, application: &str, slots: i32) -> Option<String> {
+        let execs: Vec<_> = self
+            .executors
+            .values()
+            .filter(|exec| !exec.draining)
+            .collect();
+        if execs.is_empty() {
+            return None;
+        }
+
+        let largest = execs
+            .iter()
+            .filter(|exec| self.offers(exec, application))
+            .map(|exec| exec.slots)
+            .max();
+        match largest {
+            None => Some(format!("no executor offers application <{}>", application)),
+            Some(largest) if largest < slots => Some(format!(
+                "slots <{}> don't fit any executor of application <{}>, which have <{}> slots at most",
+                slots, application, largest
+            )),
+            Some(_) => None,
+        }
+    }
+
+    /// The usage of the quota; the executor excluded is not counted, e.g. it's moved from a
+    /// session of the quota to another one.
+    pub fn quota_usage(&self, quota: &FlameQuotaConf, excluded: Option<&ExecutorID>) -> QuotaUsage {
+        let matches = |id: &SessionID| {
+            self.sessions
+                .get(id)
+                .filter(|ssn| quota.matches(&ssn.namespace, &ssn.application))
+        };
+
+        let mut usage = QuotaUsage::default();
+        for exec in self.executors.values() {
+            if Some(&exec.id) == excluded {
+                continue;
+            }
+            if let Some(ssn) = exec.ssn_id.as_ref().and_then(matches) {
+                usage.slots += ssn.slots.max(0) as u32;
+                usage.executors += 1;
+            }
+        }
+        for ssn in self.sessions.values() {
+            if quota.matches(&ssn.namespace, &ssn.application) {
+                let running = ssn.tasks_status.get(&TaskState::Running).copied();
+                usage.running_tasks += running.unwrap_or_default().max(0) as u32;
+            }
+        }
+
+        usage
+    }
+
+    /// The quota which binding the executor to the session would exceed: the slots of the
+    /// session over `max_slots`, or more executors than `max_running_tasks` as each of them
+    /// runs a task at most.
+    pub fn exceeded_quota(
+        &self,
+        exec: &ExecutorInfo,
+        ssn: &SessionInfo,
+    ) -> Option<&FlameQuotaConf> {
+        self.quotas
+            .iter()
+            .filter(|quota| quota.matches(&ssn.namespace, &ssn.application))
+            .find(|quota| {
+                let usage = self.quota_usage(quota, Some(&exec.id));
+                quota
+                    .max_slots
+                    .is_some_and(|max| usage.slots + ssn.slots.max(0) as u32 > max)
+                    || quota
+                        .max_running_tasks
+                        .is_some_and(|max| usage.executors + 1 > max)
+            })
+    }
+
+    /// The class of the session, none if it has no class or its class is not configured.
+    pub fn priority_class(&self, ssn: &SessionInfo) -> Option<&FlamePriorityClassConf> {
+        let name = ssn.priority_class.as_deref()?;
+        self.priority_classes
+            .iter()
+            .find(|class| class.name == name)
+    }
+
+    /// The weight of the class of the session; the sessions without class are the lightest.
+    pub fn class_weight(&self, ssn: &SessionInfo) -> u32 {
+        self.priority_class(ssn)
+            .map_or(0, FlamePriorityClassConf::weight)
+    }
+
+    /// The session holding the executor: the one it's bound to next if it's rebound, e.g.
+    /// it's reclaimed for a class, or the one it's bound to.
+    pub fn holder(&self, exec: &ExecutorInfo) -> Option<&SessionInfoPtr> {
+        exec.next_ssn_id
+            .or(exec.ssn_id)
+            .and_then(|id| self.sessions.get(&id))
+    }
+
+    /// The executors held by the sessions of the class.
+    pub fn class_executors(&self, class: &str) -> u32 {
+        self.executors
+            .values()
+            .filter_map(|exec| self.holder(exec))
+            .filter(|ssn| ssn.priority_class.as_deref() == Some(class))
+            .count() as u32
+    }
+
+    /// Whether the executor used up its slice of the session bound to it.
+    pub fn slice_used_up(&self, exec: &ExecutorInfo, now: DateTime<Utc>) -> bool {
+        let (Some(slice), Some(bound_time)) = (self.time_slice, exec.bound_time) else {
+            return false;
+        };
+
+        slice.tasks.is_some_and(|n| exec.launched_tasks >= n)
+            || slice
+                .duration
+                .is_some_and(|d| (now - bound_time).to_std().is_ok_and(|e| e >= d))
+    }
+
     pub fn add_session(&mut self, ssn: SessionInfoPtr) {
         self.sessions.insert(ssn.id, ssn.clone());
         self.ssn_index.entry(ssn.state).or_default();
@@ -187,10 +478,18 @@ impl SnapShot {
             id: exec.id.clone(),
             slots: exec.slots,
             applications: exec.applications.to_vec(),
+            labels: exec.labels.clone(),
+            locality_keys: exec.locality_keys.clone(),
             task_id: exec.task_id,
             ssn_id: exec.ssn_id,
             creation_time: exec.creation_time,
             state,
+            draining: exec.draining,
+            quarantined: exec.quarantined,
+            bound_time: exec.bound_time,
+            launched_tasks: exec.launched_tasks,
+            next_ssn_id: exec.next_ssn_id,
+            served: exec.served.clone(),
         });
 
         self.delete_executor(new_exec.clone());
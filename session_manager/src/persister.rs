@@ -0,0 +1,54 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::storage::StoragePtr;
+use crate::supervisor::StopSignal;
+use crate::FlameThread;
+use common::ctx::FlameContext;
+use common::FlameError;
+
+pub fn new(storage: StoragePtr) -> Box<dyn FlameThread> {
+    Box::new(PersistRunner { storage })
+}
+
+/// Writes the state changes of the tasks queued by `durability: batched` to the engine every
+/// `write_behind.flush_interval`, so a change waits at most one interval for its batch unless
+/// the batch is full first; see `Storage::flush_task_updates`. Once stopped, it writes the
+/// changes for the last time.
+struct PersistRunner {
+    storage: StoragePtr,
+}
+
+impl FlameThread for PersistRunner {
+    fn run(&self, ctx: FlameContext, stop: &StopSignal) -> Result<(), FlameError> {
+        let interval = ctx.write_behind.unwrap_or_default().flush_interval()?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| FlameError::Internal(e.to_string()))?;
+
+        loop {
+            if let Err(e) = runtime.block_on(self.storage.flush_task_updates()) {
+                log::error!("Failed to persist the changes of tasks: {}", e);
+            }
+            if stop.wait(interval) {
+                break;
+            }
+        }
+
+        let n = runtime.block_on(self.storage.flush_task_updates())?;
+        log::info!("The changes of tasks were flushed: {} persisted.", n);
+
+        Ok(())
+    }
+}
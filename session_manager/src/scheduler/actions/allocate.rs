@@ -60,6 +60,32 @@ impl Action for AllocateAction {
                 idle_execs.push(exec.clone());
             }
         }
+        // The executors are tried in the same order in every cycle.
+        idle_execs.sort_by(|a, b| a.id.cmp(&b.id));
+
+        // The executors rebound to a session, e.g. their slices were used up, are bound to it
+        // before the others are allocated.
+        idle_execs.retain(|exec| {
+            let Some(ssn) = exec.next_ssn_id.and_then(|id| ss.sessions.get(&id)) else {
+                return true;
+            };
+            if ssn.state != SessionState::Open || !ctx.filter_one(exec, ssn) {
+                return true;
+            }
+
+            match ctx.bind_session(exec, ssn) {
+                Ok(()) => false,
+                Err(e) => {
+                    log::error!(
+                        "Failed to rebind Session <{}> to Executor <{}>: {}.",
+                        ssn.id,
+                        exec.id,
+                        e
+                    );
+                    true
+                }
+            }
+        });
 
         loop {
             if open_ssns.is_empty() {
@@ -78,7 +104,8 @@ impl Action for AllocateAction {
             );
 
             let mut pos = None;
-            for (i, exec) in idle_execs.iter_mut().enumerate() {
+            for i in ctx.preferred(&idle_execs, &ssn) {
+                let exec = &idle_execs[i];
                 log::debug!(
                     "Try to allocate executor <{}> for session <{}>",
                     exec.id.clone(),
@@ -60,6 +60,8 @@ impl Action for BackfillAction {
                 idle_execs.push(exec.clone());
             }
         }
+        // The executors are tried in the same order in every cycle.
+        idle_execs.sort_by(|a, b| a.id.cmp(&b.id));
 
         loop {
             if open_ssns.is_empty() {
@@ -70,7 +72,8 @@ impl Action for BackfillAction {
             log::debug!("Start resources allocation for session <{}>", &ssn.id);
 
             let mut pos = None;
-            for (i, exec) in idle_execs.iter_mut().enumerate() {
+            for i in ctx.preferred(&idle_execs, &ssn) {
+                let exec = &idle_execs[i];
                 log::debug!(
                     "Try to allocate executor <{}> for session <{}>",
                     exec.id.clone(),
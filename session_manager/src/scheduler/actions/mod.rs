@@ -18,10 +18,14 @@ use crate::FlameError;
 
 pub use allocate::AllocateAction;
 pub use backfill::BackfillAction;
+pub use rebind::RebindAction;
+pub use reserve::ReserveAction;
 pub use shuffle::ShuffleAction;
 
 mod allocate;
 mod backfill;
+mod rebind;
+mod reserve;
 mod shuffle;
 
 pub type ActionPtr = Arc<dyn Action>;
@@ -0,0 +1,98 @@
+/*
+Copyright 2023 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use stdng::collections::BinaryHeap;
+
+use crate::scheduler::actions::{Action, ActionPtr};
+use crate::scheduler::ctx::Context;
+use crate::scheduler::plugins::ssn_order_fn;
+
+use common::apis::{ExecutorState, SessionState};
+use common::FlameError;
+use common::{trace::TraceFn, trace_fn};
+
+/// Rebinds the executors which used up their slices to the most underserved sessions, so the
+/// sessions sharing the executors take turns instead of waiting for the bound ones to drain.
+/// The executors are unbound after their running tasks, which are never interrupted.
+pub struct RebindAction {}
+
+impl RebindAction {
+    pub fn new_ptr() -> ActionPtr {
+        Arc::new(RebindAction {})
+    }
+}
+
+impl Action for RebindAction {
+    fn execute(&self, ctx: &mut Context) -> Result<(), FlameError> {
+        trace_fn!("RebindAction::execute");
+        let ss = ctx.snapshot.borrow().clone();
+        if ss.time_slice.is_none() {
+            return Ok(());
+        }
+
+        let now = Utc::now();
+        let mut used_up = vec![];
+        if let Some(execs) = ss.exec_index.get(&ExecutorState::Bound) {
+            for exec in execs.values() {
+                if ss.slice_used_up(exec, now) {
+                    used_up.push(exec.clone());
+                }
+            }
+        }
+        // The executors are tried in the same order in every cycle.
+        used_up.sort_by(|a, b| a.id.cmp(&b.id));
+
+        for exec in used_up {
+            let Some(current) = exec.ssn_id.and_then(|id| ss.sessions.get(&id).cloned()) else {
+                continue;
+            };
+
+            let mut underused = BinaryHeap::new(ssn_order_fn(ctx));
+            if let Some(open_ssns) = ss.ssn_index.get(&SessionState::Open) {
+                for ssn in open_ssns.values() {
+                    if ssn.id != current.id && ctx.is_underused(ssn) && ctx.filter_one(&exec, ssn) {
+                        underused.push(ssn.clone());
+                    }
+                }
+            }
+
+            // The executor keeps its session if no other session is waiting for it.
+            let Some(next) = underused.pop() else {
+                continue;
+            };
+
+            if let Err(e) = ctx.rebind_session(&exec, &current, &next) {
+                log::error!(
+                    "Failed to rebind Executor <{}> from Session <{}> to Session <{}>: {}.",
+                    exec.id,
+                    current.id,
+                    next.id,
+                    e
+                );
+                continue;
+            }
+
+            log::debug!(
+                "Executor <{}> used up its slice of session <{}>, rebind it to session <{}>.",
+                exec.id,
+                current.id,
+                next.id
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+/*
+Copyright 2023 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use stdng::collections::BinaryHeap;
+
+use crate::model::{ExecutorInfoPtr, SessionInfoPtr, SnapShot};
+use crate::scheduler::actions::{Action, ActionPtr};
+use crate::scheduler::ctx::Context;
+use crate::scheduler::plugins::ssn_order_fn;
+
+use common::apis::{ExecutorState, SessionID, SessionState};
+use common::FlameError;
+use common::{trace::TraceFn, trace_fn};
+
+/// Meets the reservations of the priority classes with pending work before the executors are
+/// allocated to the others: the idle executors are bound to the sessions of the class, and
+/// the ones lent to the lighter classes are rebound to them after their running tasks. The
+/// reserved executors are lent to the others while their class has no pending work.
+pub struct ReserveAction {}
+
+impl ReserveAction {
+    pub fn new_ptr() -> ActionPtr {
+        Arc::new(ReserveAction {})
+    }
+}
+
+impl Action for ReserveAction {
+    fn execute(&self, ctx: &mut Context) -> Result<(), FlameError> {
+        trace_fn!("ReserveAction::execute");
+        let ss = ctx.snapshot.borrow().clone();
+
+        let mut classes: Vec<_> = ss
+            .priority_classes
+            .iter()
+            .filter(|class| class.reserved_executors() > 0)
+            .collect();
+        if classes.is_empty() {
+            return Ok(());
+        }
+        // The reservations of the heavier classes are met first.
+        classes.sort_by(|a, b| b.weight().cmp(&a.weight()).then(a.name.cmp(&b.name)));
+
+        // The executors are tried in the same order in every cycle.
+        let executors = |state: ExecutorState| {
+            let mut execs: Vec<ExecutorInfoPtr> = ss
+                .exec_index
+                .get(&state)
+                .into_iter()
+                .flat_map(HashMap::values)
+                .filter(|exec| exec.next_ssn_id.is_none())
+                .cloned()
+                .collect();
+            execs.sort_by(|a, b| a.id.cmp(&b.id));
+            execs
+        };
+        let mut idle_execs = executors(ExecutorState::Idle);
+        let mut bound_execs = executors(ExecutorState::Bound);
+
+        // The executors held by each session, which are not taken for it beyond its pending
+        // tasks.
+        let mut held: HashMap<SessionID, i32> = HashMap::new();
+        for exec in ss.executors.values() {
+            if let Some(ssn) = ss.holder(exec) {
+                *held.entry(ssn.id).or_default() += 1;
+            }
+        }
+
+        for class in classes {
+            let mut waiting = BinaryHeap::new(ssn_order_fn(ctx));
+            for ssn in ss
+                .ssn_index
+                .get(&SessionState::Open)
+                .into_iter()
+                .flat_map(HashMap::values)
+            {
+                if ssn.priority_class.as_deref() == Some(class.name.as_str())
+                    && ssn.pending() > held.get(&ssn.id).copied().unwrap_or_default()
+                {
+                    waiting.push(ssn.clone());
+                }
+            }
+
+            let mut missing = class
+                .reserved_executors()
+                .saturating_sub(ss.class_executors(&class.name));
+            while missing > 0 {
+                let Some(ssn) = waiting.pop() else {
+                    break;
+                };
+
+                let taken = take_idle(ctx, &mut idle_execs, &ssn)
+                    || reclaim(ctx, &ss, &mut bound_execs, &ssn, class.weight());
+                if !taken {
+                    continue;
+                }
+
+                log::debug!(
+                    "Executor of class <{}> was reserved for session <{}>.",
+                    class.name,
+                    ssn.id
+                );
+                missing -= 1;
+                let held = held.entry(ssn.id).or_default();
+                *held += 1;
+                if ssn.pending() > *held {
+                    waiting.push(ssn);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Binds an idle executor to the session.
+fn take_idle(ctx: &Context, idle_execs: &mut Vec<ExecutorInfoPtr>, ssn: &SessionInfoPtr) -> bool {
+    for i in ctx.preferred(idle_execs, ssn) {
+        let exec = &idle_execs[i];
+        if !ctx.filter_one(exec, ssn) {
+            continue;
+        }
+
+        if let Err(e) = ctx.bind_session(exec, ssn) {
+            log::error!(
+                "Failed to bind Session <{}> to Executor <{}>: {}.",
+                ssn.id,
+                exec.id,
+                e
+            );
+            continue;
+        }
+
+        idle_execs.remove(i);
+        return true;
+    }
+
+    false
+}
+
+/// Rebinds an executor lent to a session of a lighter class to the session once its running
+/// task completes; the executors of the lightest sessions are reclaimed first.
+fn reclaim(
+    ctx: &Context,
+    ss: &SnapShot,
+    bound_execs: &mut Vec<ExecutorInfoPtr>,
+    ssn: &SessionInfoPtr,
+    weight: u32,
+) -> bool {
+    let mut lent: Vec<_> = bound_execs
+        .iter()
+        .enumerate()
+        .filter_map(|(i, exec)| {
+            let current = exec.ssn_id.and_then(|id| ss.sessions.get(&id))?;
+            let current_weight = ss.class_weight(current);
+            (current_weight < weight).then_some((current_weight, i, current.clone()))
+        })
+        .collect();
+    lent.sort_by_key(|(current_weight, i, _)| (*current_weight, *i));
+
+    for (_, i, current) in lent {
+        let exec = &bound_execs[i];
+        if !ctx.filter_one(exec, ssn) {
+            continue;
+        }
+
+        if let Err(e) = ctx.rebind_session(exec, &current, ssn) {
+            log::error!(
+                "Failed to reclaim Executor <{}> from Session <{}> for Session <{}>: {}.",
+                exec.id,
+                current.id,
+                ssn.id,
+                e
+            );
+            continue;
+        }
+
+        bound_execs.remove(i);
+        return true;
+    }
+
+    false
+}
@@ -51,6 +51,8 @@ impl Action for ShuffleAction {
                 bound_execs.push(exec.clone());
             }
         }
+        // The executors are tried in the same order in every cycle.
+        bound_execs.sort_by(|a, b| a.id.cmp(&b.id));
 
         loop {
             if underused.is_empty() {
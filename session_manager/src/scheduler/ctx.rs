@@ -11,24 +11,30 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use crate::model::{ExecutorInfoPtr, SessionInfoPtr, SnapShotPtr};
-use crate::scheduler::actions::{ActionPtr, AllocateAction, BackfillAction, ShuffleAction};
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::model::{ExecutorInfo, ExecutorInfoPtr, SessionInfoPtr, SnapShotPtr};
+use crate::scheduler::actions::{
+    ActionPtr, AllocateAction, BackfillAction, RebindAction, ReserveAction, ShuffleAction,
+};
 use crate::scheduler::plugins::{PluginManager, PluginManagerPtr};
 
 use crate::storage::StoragePtr;
 
-use common::apis::ExecutorState;
+use common::apis::{ExecutorState, SessionID, SessionState, TaskGID, TaskState};
 
 use common::FlameError;
 
-const DEFAULT_SCHEDULE_INTERVAL: u64 = 500;
-
 pub struct Context {
     pub snapshot: SnapShotPtr,
     pub storage: StoragePtr,
     pub actions: Vec<ActionPtr>,
     pub plugins: PluginManagerPtr,
-    pub schedule_interval: u64,
+    /// The number of the bindings and unbindings in this cycle.
+    pub decisions: Cell<u32>,
 }
 
 impl Context {
@@ -42,11 +48,13 @@ impl Context {
             storage,
             // TODO(k82cn): Add ActionManager for them.
             actions: vec![
+                ReserveAction::new_ptr(),
                 AllocateAction::new_ptr(),
                 ShuffleAction::new_ptr(),
+                RebindAction::new_ptr(),
                 BackfillAction::new_ptr(),
             ],
-            schedule_interval: DEFAULT_SCHEDULE_INTERVAL,
+            decisions: Cell::new(0),
         })
     }
 
@@ -55,11 +63,98 @@ impl Context {
         execs: &Vec<ExecutorInfoPtr>,
         ssn: &SessionInfoPtr,
     ) -> Vec<ExecutorInfoPtr> {
+        // The registered applications are fetched by the executors when binding.
+        if self
+            .snapshot
+            .borrow()
+            .applications
+            .contains(&ssn.application)
+        {
+            return execs.clone();
+        }
+
         self.plugins.borrow().filter(execs, ssn)
     }
 
     pub fn filter_one(&self, exec: &ExecutorInfoPtr, ssn: &SessionInfoPtr) -> bool {
-        !self.filter(&vec![exec.clone()], ssn).is_empty()
+        // The draining executors will not be bound to any session, and the executors without
+        // enough slots will never run it.
+        !exec.draining
+            && exec.slots >= ssn.slots
+            && self.within_quota(exec, ssn)
+            && self.within_running_limit(exec, ssn)
+            && !self.filter(&vec![exec.clone()], ssn).is_empty()
+    }
+
+    /// Whether the executor could be bound to the session without exceeding the quotas of
+    /// the session; the quotas are ceilings, so the executors are not unbound if they're
+    /// over them, e.g. the quotas were lowered.
+    fn within_quota(&self, exec: &ExecutorInfoPtr, ssn: &SessionInfoPtr) -> bool {
+        let ss = self.snapshot.borrow();
+        match ss.exceeded_quota(exec, ssn) {
+            Some(quota) => {
+                log::debug!(
+                    "Executor <{}> is not bound to session <{}> over quota <{}>.",
+                    exec.id,
+                    ssn.id,
+                    quota.name()
+                );
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Whether the executor could be bound to the session without more executors than its
+    /// `max_running_tasks`, as each of them runs a task at most.
+    fn within_running_limit(&self, exec: &ExecutorInfoPtr, ssn: &SessionInfoPtr) -> bool {
+        let Some(max) = ssn.max_running_tasks else {
+            return true;
+        };
+        let ss = self.snapshot.borrow();
+        let bound = ss
+            .executors
+            .values()
+            .filter(|e| e.id != exec.id && e.ssn_id == Some(ssn.id))
+            .count();
+
+        bound < max as usize
+    }
+
+    /// The reasons of the open sessions with pending tasks which no executor could ever run;
+    /// the sessions bound to any executor are schedulable.
+    pub fn unschedulable(&self) -> HashMap<SessionID, String> {
+        let ss = self.snapshot.borrow();
+        let bound: HashSet<SessionID> = ss.executors.values().filter_map(|e| e.ssn_id).collect();
+
+        ss.ssn_index
+            .get(&SessionState::Open)
+            .into_iter()
+            .flat_map(HashMap::values)
+            .filter(|ssn| {
+                ssn.tasks_status
+                    .get(&TaskState::Pending)
+                    .copied()
+                    .unwrap_or(0)
+                    > 0
+            })
+            .filter(|ssn| !bound.contains(&ssn.id))
+            .filter_map(|ssn| {
+                ss.unschedulable(&ssn.application, ssn.slots)
+                    .map(|reason| (ssn.id, reason))
+            })
+            .collect()
+    }
+
+    /// The indexes of the executors in the order to try them for the session: the ones
+    /// preferred by the plugins first, e.g. those which served the session recently, and the
+    /// others in their order.
+    pub fn preferred(&self, execs: &[ExecutorInfoPtr], ssn: &SessionInfoPtr) -> Vec<usize> {
+        let plugins = self.plugins.borrow();
+        let mut order: Vec<usize> = (0..execs.len()).collect();
+        order.sort_by_cached_key(|i| Reverse(plugins.score(&execs[*i], ssn)));
+
+        order
     }
 
     pub fn is_underused(&self, ssn: &SessionInfoPtr) -> bool {
@@ -79,12 +174,50 @@ impl Context {
             .enable_all()
             .build()
             .map_err(|e| FlameError::Internal(e.to_string()))?;
-        runtime.block_on(self.storage.bind_session(exec.id.clone(), ssn.id))?;
+        let bound = runtime.block_on(self.storage.bind_session(exec.id.clone(), ssn.id))?;
+        self.decisions.set(self.decisions.get() + 1);
 
-        self.plugins.borrow_mut().on_session_bind(ssn);
+        // The executor was bound to the session already, so it's allocated once.
+        if bound {
+            self.plugins.borrow_mut().on_session_bind(ssn);
+            self.assign_task(exec, ssn)?;
+        }
+        self.move_executor(exec, ssn);
+
+        Ok(())
+    }
+
+    /// Records the executor as binding the session in the snapshot, so the quotas count it
+    /// for the rest of the cycle.
+    fn move_executor(&self, exec: &ExecutorInfoPtr, ssn: &SessionInfoPtr) {
+        let exec = ExecutorInfo {
+            ssn_id: Some(ssn.id),
+            ..(**exec).clone()
+        };
         self.snapshot
             .borrow_mut()
-            .update_executor_state(exec.clone(), ExecutorState::Binding);
+            .update_executor_state(Rc::new(exec), ExecutorState::Binding);
+    }
+
+    /// Reserves the pending task chosen by the plugins for the executor bound to the session;
+    /// the pending tasks of the session are loaded only if a plugin asks for them.
+    fn assign_task(&self, exec: &ExecutorInfoPtr, ssn: &SessionInfoPtr) -> Result<(), FlameError> {
+        let plugins = self.plugins.borrow();
+        if !plugins.wants_tasks(ssn) {
+            return Ok(());
+        }
+
+        if ssn.pending_tasks().is_none() {
+            ssn.set_pending_tasks(self.storage.pending_tasks(ssn.id)?);
+        }
+        if let Some(task_id) = plugins.assign_task(exec, ssn) {
+            let gid = TaskGID {
+                ssn_id: ssn.id,
+                task_id,
+            };
+            self.storage.reserve_task(exec.id.clone(), gid)?;
+            ssn.remove_pending_task(task_id);
+        }
 
         Ok(())
     }
@@ -95,10 +228,24 @@ impl Context {
         ssn: &SessionInfoPtr,
     ) -> Result<(), FlameError> {
         self.plugins.borrow_mut().on_session_bind(ssn);
+        self.decisions.set(self.decisions.get() + 1);
 
-        self.snapshot
-            .borrow_mut()
-            .update_executor_state(exec.clone(), ExecutorState::Binding);
+        self.move_executor(exec, ssn);
+
+        Ok(())
+    }
+
+    /// Unbinds the executor from its session after the running task, and binds it to the
+    /// next session once it's unbound, e.g. its slice of the session was used up.
+    pub fn rebind_session(
+        &self,
+        exec: &ExecutorInfoPtr,
+        ssn: &SessionInfoPtr,
+        next: &SessionInfoPtr,
+    ) -> Result<(), FlameError> {
+        self.unbind_session(exec, ssn)?;
+        self.storage.rebind_executor(exec.id.clone(), next.id)?;
+        self.plugins.borrow_mut().on_session_bind(next);
 
         Ok(())
     }
@@ -112,7 +259,8 @@ impl Context {
             .enable_all()
             .build()
             .map_err(|e| FlameError::Internal(e.to_string()))?;
-        runtime.block_on(self.storage.unbind_executor(exec.id.clone()))?;
+        runtime.block_on(self.storage.unbind_executor(exec.id.clone(), None))?;
+        self.decisions.set(self.decisions.get() + 1);
 
         self.plugins.borrow_mut().on_session_unbind(ssn);
 
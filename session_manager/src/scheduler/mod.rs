@@ -11,41 +11,1104 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::{thread, time};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
 
 use crate::scheduler::ctx::Context;
+use crate::scheduler::stats::StatsRecorder;
 
 use crate::storage::StoragePtr;
+use crate::supervisor::StopSignal;
 use crate::FlameThread;
 use common::ctx::FlameContext;
-use common::FlameError;
+use common::ptr::{self, MutexPtr};
+use common::{lock_ptr, FlameError};
 
 mod actions;
 mod ctx;
 mod plugins;
+mod report;
+mod stats;
+
+pub use report::{Allocation, AllocationReport};
+pub use stats::{ClusterStats, Histogram};
+
+pub type SchedulerStatePtr = Arc<SchedulerState>;
+
+/// The state of the scheduler shared with the admin service.
+pub struct SchedulerState {
+    status: MutexPtr<SchedulerStatus>,
+    stats: MutexPtr<StatsRecorder>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SchedulerStatus {
+    /// No sessions are bound or unbound by the scheduler if it's paused.
+    pub paused: bool,
+    pub last_cycle_time: Option<DateTime<Utc>>,
+    /// The number of the bindings and unbindings in the last cycle.
+    pub last_decisions: u32,
+    pub total_decisions: u64,
+    pub cycles: u64,
+}
+
+impl SchedulerState {
+    pub fn new_ptr() -> SchedulerStatePtr {
+        Arc::new(SchedulerState {
+            status: ptr::new_ptr(SchedulerStatus::default()),
+            stats: ptr::new_ptr(StatsRecorder::default()),
+        })
+    }
+
+    pub fn status(&self) -> Result<SchedulerStatus, FlameError> {
+        let status = lock_ptr!(self.status)?;
+        Ok(status.clone())
+    }
+
+    /// The health of the queue in the last cycle, which is recorded even if the scheduling
+    /// is paused.
+    pub fn cluster_stats(&self) -> Result<ClusterStats, FlameError> {
+        let stats = lock_ptr!(self.stats)?;
+        Ok(stats.stats().clone())
+    }
+
+    /// How the slots were shared by the applications and the namespaces in the last cycle,
+    /// which is recorded with the cluster stats.
+    pub fn allocation_report(&self) -> Result<AllocationReport, FlameError> {
+        let stats = lock_ptr!(self.stats)?;
+        Ok(stats.report().clone())
+    }
+
+    /// Pauses or resumes the scheduling from the next cycle; the Frontend and Backend calls
+    /// are served as usual.
+    pub fn set_paused(&self, paused: bool) -> Result<SchedulerStatus, FlameError> {
+        let mut status = lock_ptr!(self.status)?;
+        if status.paused != paused {
+            log::info!(
+                "Scheduling is {}.",
+                if paused { "paused" } else { "resumed" }
+            );
+        }
+        status.paused = paused;
+
+        Ok(status.clone())
+    }
+
+    fn is_paused(&self) -> Result<bool, FlameError> {
+        let status = lock_ptr!(self.status)?;
+        Ok(status.paused)
+    }
+
+    fn record_cycle(&self, decisions: u32) -> Result<(), FlameError> {
+        let mut status = lock_ptr!(self.status)?;
+        status.last_cycle_time = Some(Utc::now());
+        status.last_decisions = decisions;
+        status.total_decisions += decisions as u64;
+        status.cycles += 1;
+
+        Ok(())
+    }
+
+    /// Records whether a signal was delivered to the autoscaler hook.
+    pub fn record_autoscaler_delivery(&self, delivered: bool) -> Result<(), FlameError> {
+        let mut stats = lock_ptr!(self.stats)?;
+        stats.record_delivery(delivered);
+
+        Ok(())
+    }
 
-pub fn new(storage: StoragePtr) -> Box<dyn FlameThread> {
-    Box::new(ScheduleRunner { storage })
+    fn record_stats(&self, ctx: &Context, now: DateTime<Utc>) -> Result<(), FlameError> {
+        let mut stats = lock_ptr!(self.stats)?;
+        stats.record(&ctx.snapshot.borrow(), now);
+
+        Ok(())
+    }
+}
+
+pub fn new(storage: StoragePtr, state: SchedulerStatePtr) -> Box<dyn FlameThread> {
+    Box::new(ScheduleRunner { storage, state })
+}
+
+/// Runs a cycle of the scheduling out of the scheduler thread, e.g. in the simulation.
+pub fn schedule(storage: StoragePtr, state: SchedulerStatePtr) -> Result<(), FlameError> {
+    ScheduleRunner { storage, state }.schedule()
 }
 
 struct ScheduleRunner {
     storage: StoragePtr,
+    state: SchedulerStatePtr,
 }
 
 impl FlameThread for ScheduleRunner {
-    fn run(&self, _flame_ctx: FlameContext) -> Result<(), FlameError> {
+    fn run(&self, flame_ctx: FlameContext, stop: &StopSignal) -> Result<(), FlameError> {
+        let timings = flame_ctx.timings.unwrap_or_default();
+        let interval = timings.schedule_interval()?;
+
         loop {
-            let mut ctx = Context::new(self.storage.clone())?;
+            self.schedule()?;
+            if stop.wait(timings.jittered(interval)) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl ScheduleRunner {
+    /// Runs a cycle of the scheduling unless it's paused.
+    fn schedule(&self) -> Result<(), FlameError> {
+        let mut ctx = Context::new(self.storage.clone())?;
+        self.state.record_stats(&ctx, Utc::now())?;
+
+        if self.state.is_paused()? {
+            return Ok(());
+        }
+
+        for action in ctx.actions.clone() {
+            if let Err(e) = action.execute(&mut ctx) {
+                log::error!("Failed to run scheduling: {}", e);
+                break;
+            };
+        }
+        self.storage.set_unschedulable(ctx.unschedulable())?;
+        self.state.record_cycle(ctx.decisions.get())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use common::apis::{Application, Executor, ExecutorState, SessionAttributes, TaskAttributes};
+    use common::ctx::{FlamePriorityClassConf, FlameQuotaConf};
+
+    use crate::model::TimeSlice;
+
+    #[test]
+    fn test_pause_scheduling() -> Result<(), FlameError> {
+        // The scheduler runs out of the runtime, as it blocks on its own.
+        let rt = tokio::runtime::Runtime::new().map_err(|e| FlameError::Internal(e.to_string()))?;
+        let url = format!(
+            "sqlite:///tmp/flame_test_scheduler_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let storage = rt.block_on(async {
+            let storage = crate::storage::new_ptr(&url).await?;
+            storage
+                .register_application(Application {
+                    name: "flmexec".to_string(),
+                    ..Default::default()
+                })
+                .await?;
+            let ssn = storage
+                .create_session(SessionAttributes {
+                    application: "flmexec".to_string(),
+                    ..Default::default()
+                })
+                .await?;
+            storage
+                .create_task(ssn.id, TaskAttributes::default())
+                .await?;
+
+            Ok::<_, FlameError>(storage)
+        })?;
+        storage.register_executor(&Executor {
+            id: "exec-1".to_string(),
+            slots: 1,
+            applications: vec![],
+            task_id: None,
+            ssn_id: None,
+            labels: Default::default(),
+            locality_keys: vec![],
+            host: Default::default(),
+            creation_time: Utc::now(),
+            last_heartbeat: Utc::now(),
+            state: ExecutorState::Idle,
+            draining: false,
+            quarantined: false,
+            generation: 0,
+        })?;
+
+        let state = SchedulerState::new_ptr();
+        let runner = ScheduleRunner {
+            storage: storage.clone(),
+            state: state.clone(),
+        };
+        let executor_state = || storage.get_executor("exec-1".to_string()).map(|e| e.state);
+
+        // No sessions are bound while the scheduling is paused.
+        state.set_paused(true)?;
+        for _ in 0..3 {
+            runner.schedule()?;
+        }
+        assert_eq!(executor_state()?, ExecutorState::Idle);
+        let status = state.status()?;
+        assert_eq!((status.cycles, status.total_decisions), (0, 0));
+        assert_eq!(status.last_cycle_time, None);
+
+        // The session is bound in the first cycle after resumed.
+        state.set_paused(false)?;
+        runner.schedule()?;
+        assert_eq!(executor_state()?, ExecutorState::Binding);
+        let status = state.status()?;
+        assert_eq!((status.cycles, status.last_decisions), (1, 1));
+        assert!(status.last_cycle_time.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cluster_stats() -> Result<(), FlameError> {
+        let rt = tokio::runtime::Runtime::new().map_err(|e| FlameError::Internal(e.to_string()))?;
+        let url = format!(
+            "sqlite:///tmp/flame_test_cluster_stats_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let storage = rt.block_on(crate::storage::new_ptr(&url))?;
+        rt.block_on(storage.register_application(Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }))?;
+        let ssn = rt.block_on(storage.create_session(SessionAttributes {
+            application: "flmexec".to_string(),
+            ..Default::default()
+        }))?;
+        for _ in 0..2 {
+            rt.block_on(storage.create_task(ssn.id, TaskAttributes::default()))?;
+        }
+        storage.register_executor(&Executor {
+            id: "exec-1".to_string(),
+            slots: 1,
+            applications: vec![],
+            task_id: None,
+            ssn_id: None,
+            labels: Default::default(),
+            locality_keys: vec![],
+            host: Default::default(),
+            creation_time: Utc::now(),
+            last_heartbeat: Utc::now(),
+            state: ExecutorState::Idle,
+            draining: false,
+            quarantined: false,
+            generation: 0,
+        })?;
+
+        // The oldest pending task is getting older while the scheduling is paused.
+        let state = SchedulerState::new_ptr();
+        let runner = ScheduleRunner {
+            storage: storage.clone(),
+            state: state.clone(),
+        };
+        state.set_paused(true)?;
+        runner.schedule()?;
+        let stats = state.cluster_stats()?;
+        assert_eq!(stats.applications["flmexec"].pending, 2);
+        assert_eq!((stats.idle_executors, stats.bound_executors), (1, 0));
+        assert_eq!(stats.bind_latency.count, 0);
+
+        let ctx = Context::new(storage.clone())?;
+        let now = Utc::now();
+        let age = |secs: i64| {
+            state.record_stats(&ctx, now + chrono::Duration::seconds(secs))?;
+            Ok::<_, FlameError>(state.cluster_stats()?.applications["flmexec"].oldest_pending_age)
+        };
+        let (age_10, age_20) = (age(10)?, age(20)?);
+        assert!(age_10 >= 10.0);
+        assert!(
+            (age_20 - age_10 - 10.0).abs() < 1e-3,
+            "{} vs {}",
+            age_20,
+            age_10
+        );
+
+        // The bind latency is observed once the session is bound.
+        state.set_paused(false)?;
+        runner.schedule()?;
+        runner.schedule()?;
+        let stats = state.cluster_stats()?;
+        assert_eq!((stats.idle_executors, stats.bound_executors), (0, 1));
+        assert_eq!(stats.bind_latency.count, 1);
+
+        // The gauge is reset once the backlog is drained.
+        rt.block_on(async {
+            storage
+                .bind_session_completed("exec-1".to_string(), None, None)
+                .await?;
+            for _ in 0..2 {
+                storage.launch_task("exec-1".to_string()).await?;
+                storage
+                    .complete_task("exec-1".to_string(), None, None, None)
+                    .await?;
+            }
+            Ok::<_, FlameError>(())
+        })?;
+        runner.schedule()?;
+        let stats = state.cluster_stats()?;
+        assert_eq!(stats.applications["flmexec"].pending, 0);
+        assert_eq!(stats.applications["flmexec"].oldest_pending_age, 0.0);
+        assert_eq!(stats.bind_latency.count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_locality() -> Result<(), FlameError> {
+        let rt = tokio::runtime::Runtime::new().map_err(|e| FlameError::Internal(e.to_string()))?;
+        let url = format!(
+            "sqlite:///tmp/flame_test_task_locality_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let zone = |z: &str| HashMap::from([("zone".to_string(), z.to_string())]);
+        let storage = rt.block_on(crate::storage::new_ptr(&url))?;
+        rt.block_on(storage.register_application(Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }))?;
+        let ssn = rt.block_on(storage.create_session(SessionAttributes {
+            application: "flmexec".to_string(),
+            slots: 1,
+            ..Default::default()
+        }))?;
+        for labels in [HashMap::new(), zone("b"), zone("a")] {
+            let attrs = TaskAttributes {
+                labels,
+                ..Default::default()
+            };
+            rt.block_on(storage.create_task(ssn.id, attrs))?;
+        }
+        for (id, z) in [("exec-a", "a"), ("exec-b", "b")] {
+            storage.register_executor(&Executor {
+                id: id.to_string(),
+                slots: 1,
+                applications: vec![],
+                task_id: None,
+                ssn_id: None,
+                labels: zone(z),
+                locality_keys: vec![],
+                host: Default::default(),
+                creation_time: Utc::now(),
+                last_heartbeat: Utc::now(),
+                state: ExecutorState::Idle,
+                draining: false,
+                quarantined: false,
+                generation: 0,
+            })?;
+        }
+
+        let runner = ScheduleRunner {
+            storage: storage.clone(),
+            state: SchedulerState::new_ptr(),
+        };
+        runner.schedule()?;
 
-            for action in ctx.actions.clone() {
-                if let Err(e) = action.execute(&mut ctx) {
-                    log::error!("Failed to run scheduling: {}", e);
-                    break;
-                };
+        // The tasks are launched on the executors of their zones, whichever launches first.
+        rt.block_on(async {
+            for (id, z) in [("exec-b", "b"), ("exec-a", "a")] {
+                storage
+                    .bind_session_completed(id.to_string(), None, None)
+                    .await?;
+                let task = storage.launch_task(id.to_string()).await?.unwrap();
+                assert_eq!(task.labels, zone(z));
             }
 
-            let delay = time::Duration::from_millis(ctx.schedule_interval);
-            thread::sleep(delay);
+            // The reservations are taken, so any pending task is launched next.
+            storage
+                .complete_task("exec-a".to_string(), None, None, None)
+                .await?;
+            let task = storage.launch_task("exec-a".to_string()).await?.unwrap();
+            assert!(task.labels.is_empty());
+
+            Ok::<_, FlameError>(())
+        })?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_locality_keys() -> Result<(), FlameError> {
+        let rt = tokio::runtime::Runtime::new().map_err(|e| FlameError::Internal(e.to_string()))?;
+        let url = format!(
+            "sqlite:///tmp/flame_test_task_locality_keys_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let storage = rt.block_on(crate::storage::new_ptr(&url))?;
+        rt.block_on(storage.register_application(Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }))?;
+        let ssn = rt.block_on(storage.create_session(SessionAttributes {
+            application: "flmexec".to_string(),
+            slots: 1,
+            ..Default::default()
+        }))?;
+        let create_task = |locality: &str| {
+            let attrs = TaskAttributes {
+                locality: Some(locality.to_string()),
+                ..Default::default()
+            };
+            rt.block_on(storage.create_task(ssn.id, attrs))
+        };
+        create_task("shard-2")?;
+        create_task("shard-1")?;
+        for (id, key) in [("exec-1", "shard-1"), ("exec-2", "shard-2")] {
+            storage.register_executor(&Executor {
+                id: id.to_string(),
+                slots: 1,
+                applications: vec![],
+                task_id: None,
+                ssn_id: None,
+                labels: HashMap::new(),
+                locality_keys: vec![key.to_string()],
+                host: Default::default(),
+                creation_time: Utc::now(),
+                last_heartbeat: Utc::now(),
+                state: ExecutorState::Idle,
+                draining: false,
+                quarantined: false,
+                generation: 0,
+            })?;
+        }
+
+        let runner = ScheduleRunner {
+            storage: storage.clone(),
+            state: SchedulerState::new_ptr(),
+        };
+        runner.schedule()?;
+
+        rt.block_on(async {
+            // The tasks are launched on the executors having their keys.
+            for (id, key) in [("exec-1", "shard-1"), ("exec-2", "shard-2")] {
+                storage
+                    .bind_session_completed(id.to_string(), None, None)
+                    .await?;
+                let task = storage.launch_task(id.to_string()).await?.unwrap();
+                assert_eq!(task.locality.as_deref(), Some(key));
+            }
+            storage
+                .complete_task("exec-1".to_string(), None, None, None)
+                .await?;
+
+            // No executor has the key of the task, so it waits for one before it's launched
+            // on any executor.
+            let misses = crate::storage::locality_misses();
+            let attrs = TaskAttributes {
+                locality: Some("shard-3".to_string()),
+                ..Default::default()
+            };
+            let task = storage.create_task(ssn.id, attrs).await?;
+            assert!(storage.launch_task("exec-1".to_string()).await?.is_none());
+
+            storage.set_locality_wait(Duration::from_millis(100))?;
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            let launched = storage.launch_task("exec-1".to_string()).await?.unwrap();
+            assert_eq!(launched.id, task.id);
+            assert!(crate::storage::locality_misses() > misses);
+
+            Ok::<_, FlameError>(())
+        })?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unschedulable_sessions() -> Result<(), FlameError> {
+        let rt = tokio::runtime::Runtime::new().map_err(|e| FlameError::Internal(e.to_string()))?;
+        let url = format!(
+            "sqlite:///tmp/flame_test_unschedulable_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let app = |name: &str| Application {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let executor = |id: &str, slots, applications| Executor {
+            id: id.to_string(),
+            slots,
+            applications,
+            task_id: None,
+            ssn_id: None,
+            labels: Default::default(),
+            locality_keys: vec![],
+            host: Default::default(),
+            creation_time: Utc::now(),
+            last_heartbeat: Utc::now(),
+            state: ExecutorState::Idle,
+            draining: false,
+            quarantined: false,
+            generation: 0,
+        };
+
+        // The applications in the configuration are only run by the executors offering them.
+        let storage = rt.block_on(crate::storage::new_ptr(&url))?;
+        storage.set_config_applications(&[app("pi")])?;
+        rt.block_on(storage.register_application(app("flmexec")))?;
+        let mut ssn_ids = vec![];
+        for (application, slots) in [("flmexec", 2), ("pi", 1)] {
+            let ssn = rt.block_on(storage.create_session(SessionAttributes {
+                application: application.to_string(),
+                slots,
+                ..Default::default()
+            }))?;
+            rt.block_on(storage.create_task(ssn.id, TaskAttributes::default()))?;
+            ssn_ids.push(ssn.id);
+        }
+        storage.register_executor(&executor("exec-1", 1, vec![]))?;
+
+        let runner = ScheduleRunner {
+            storage: storage.clone(),
+            state: SchedulerState::new_ptr(),
+        };
+        let unschedulable = |i: usize| {
+            storage
+                .get_session(ssn_ids[i])
+                .map(|ssn| ssn.status.unschedulable)
+        };
+
+        runner.schedule()?;
+        assert_eq!(
+            unschedulable(0)?.as_deref(),
+            Some("slots <2> don't fit any executor of application <flmexec>, which have <1> slots at most")
+        );
+        assert_eq!(
+            unschedulable(1)?.as_deref(),
+            Some("no executor offers application <pi>")
+        );
+        assert_eq!(
+            storage.get_executor("exec-1".to_string())?.state,
+            ExecutorState::Idle
+        );
+
+        // The conditions are cleared once a suitable executor is registered.
+        storage.register_executor(&executor("exec-2", 2, vec![app("pi")]))?;
+        runner.schedule()?;
+        assert_eq!(unschedulable(0)?, None);
+        assert_eq!(unschedulable(1)?, None);
+
+        // The sessions no executor could run are rejected in the strict mode.
+        storage.set_reject_unschedulable(true)?;
+        let res = rt.block_on(storage.create_session(SessionAttributes {
+            application: "flmexec".to_string(),
+            slots: 4,
+            ..Default::default()
+        }));
+        assert!(
+            matches!(&res, Err(FlameError::FailedPrecondition(msg)) if msg.contains("slots <4>")),
+            "{:?}",
+            res.map(|ssn| ssn.id)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_time_slice() -> Result<(), FlameError> {
+        let rt = tokio::runtime::Runtime::new().map_err(|e| FlameError::Internal(e.to_string()))?;
+        let url = format!(
+            "sqlite:///tmp/flame_test_time_slice_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let storage = rt.block_on(crate::storage::new_ptr(&url))?;
+        storage.set_time_slice(Some(TimeSlice {
+            tasks: Some(2),
+            duration: None,
+        }))?;
+        rt.block_on(storage.register_application(Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }))?;
+        let mut ssn_ids = vec![];
+        for _ in 0..2 {
+            let ssn = rt.block_on(storage.create_session(SessionAttributes {
+                application: "flmexec".to_string(),
+                slots: 1,
+                ..Default::default()
+            }))?;
+            for _ in 0..4 {
+                rt.block_on(storage.create_task(ssn.id, TaskAttributes::default()))?;
+            }
+            ssn_ids.push(ssn.id);
+        }
+        storage.register_executor(&Executor {
+            id: "exec-1".to_string(),
+            slots: 1,
+            applications: vec![],
+            task_id: None,
+            ssn_id: None,
+            labels: Default::default(),
+            locality_keys: vec![],
+            host: Default::default(),
+            creation_time: Utc::now(),
+            last_heartbeat: Utc::now(),
+            state: ExecutorState::Idle,
+            draining: false,
+            quarantined: false,
+            generation: 0,
+        })?;
+
+        let runner = ScheduleRunner {
+            storage: storage.clone(),
+            state: SchedulerState::new_ptr(),
+        };
+        let id = || "exec-1".to_string();
+
+        // The executor is driven like the executor manager, one step per cycle.
+        let mut completed = vec![];
+        for _ in 0..30 {
+            runner.schedule()?;
+            rt.block_on(async {
+                match storage.get_executor(id())?.state {
+                    ExecutorState::Binding => {
+                        storage.bind_session_completed(id(), None, None).await?
+                    }
+                    ExecutorState::Bound | ExecutorState::Unbinding => {
+                        match storage.launch_task(id()).await? {
+                            Some(task) => {
+                                storage
+                                    .complete_task(id(), Some(task.gid()), None, None)
+                                    .await?;
+                                completed.push(task.ssn_id);
+                            }
+                            None => {
+                                storage.unbind_executor(id(), None).await?;
+                                storage.unbind_executor_completed(id(), None).await?;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                Ok::<_, FlameError>(())
+            })?;
+        }
+
+        // The sessions take turns of two tasks, and the running tasks are not interrupted.
+        let (a, b) = (ssn_ids[0], ssn_ids[1]);
+        assert_eq!(completed, vec![a, a, b, b, a, a, b, b]);
+        assert_eq!(storage.get_executor(id())?.state, ExecutorState::Idle);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_quotas() -> Result<(), FlameError> {
+        let rt = tokio::runtime::Runtime::new().map_err(|e| FlameError::Internal(e.to_string()))?;
+        let url = format!(
+            "sqlite:///tmp/flame_test_quotas_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let storage = rt.block_on(crate::storage::new_ptr(&url))?;
+        storage.set_quotas(&[FlameQuotaConf {
+            namespace: Some("research".to_string()),
+            max_slots: Some(2),
+            ..Default::default()
+        }])?;
+        rt.block_on(storage.register_application(Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }))?;
+        let ssn = rt.block_on(storage.create_session(SessionAttributes {
+            application: "flmexec".to_string(),
+            namespace: "research".to_string(),
+            slots: 1,
+            ..Default::default()
+        }))?;
+        for _ in 0..6 {
+            rt.block_on(storage.create_task(ssn.id, TaskAttributes::default()))?;
+        }
+        for i in 1..=3 {
+            storage.register_executor(&Executor {
+                id: format!("exec-{}", i),
+                slots: 1,
+                applications: vec![],
+                task_id: None,
+                ssn_id: None,
+                labels: Default::default(),
+                locality_keys: vec![],
+                host: Default::default(),
+                creation_time: Utc::now(),
+                last_heartbeat: Utc::now(),
+                state: ExecutorState::Idle,
+                draining: false,
+                quarantined: false,
+                generation: 0,
+            })?;
+        }
+
+        let state = SchedulerState::new_ptr();
+        let runner = ScheduleRunner {
+            storage: storage.clone(),
+            state: state.clone(),
+        };
+        let bound = || {
+            let mut ids: Vec<_> = storage
+                .list_executor()?
+                .into_iter()
+                .filter(|e| e.ssn_id == Some(ssn.id))
+                .map(|e| e.id)
+                .collect();
+            ids.sort();
+            Ok::<_, FlameError>(ids)
+        };
+
+        // The binding stops at the slots of the quota, even with more pending tasks.
+        for _ in 0..3 {
+            runner.schedule()?;
         }
+        let ids = bound()?;
+        assert_eq!(ids.len(), 2, "{:?}", ids);
+        runner.schedule()?;
+        let stats = state.cluster_stats()?;
+        assert_eq!(stats.quotas.len(), 1);
+        assert_eq!(stats.quotas[0].usage.slots, 2);
+
+        // It resumes once an executor is freed.
+        rt.block_on(async {
+            storage
+                .bind_session_completed(ids[0].clone(), None, None)
+                .await?;
+            storage.unbind_executor(ids[0].clone(), None).await?;
+            storage
+                .unbind_executor_completed(ids[0].clone(), None)
+                .await
+        })?;
+        assert_eq!(bound()?.len(), 1);
+        runner.schedule()?;
+        assert_eq!(bound()?.len(), 2);
+
+        // The running tasks over the quota stay pending at dispatch time.
+        storage.set_quotas(&[FlameQuotaConf {
+            application: Some("flmexec".to_string()),
+            max_running_tasks: Some(1),
+            ..Default::default()
+        }])?;
+        let ids = bound()?;
+        let task = rt.block_on(async {
+            for id in &ids {
+                if storage.get_executor(id.clone())?.state == ExecutorState::Binding {
+                    storage
+                        .bind_session_completed(id.clone(), None, None)
+                        .await?;
+                }
+            }
+            let task = storage.launch_task(ids[0].clone()).await?;
+            assert!(storage.launch_task(ids[1].clone()).await?.is_none());
+            Ok::<_, FlameError>(task)
+        })?;
+        let task = task.expect("the task within the quota");
+        rt.block_on(async {
+            storage
+                .complete_task(ids[0].clone(), Some(task.gid()), None, None)
+                .await?;
+            assert!(storage.launch_task(ids[1].clone()).await?.is_some());
+            Ok::<_, FlameError>(())
+        })?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_running_tasks() -> Result<(), FlameError> {
+        let rt = tokio::runtime::Runtime::new().map_err(|e| FlameError::Internal(e.to_string()))?;
+        let url = format!(
+            "sqlite:///tmp/flame_test_max_running_tasks_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let storage = rt.block_on(crate::storage::new_ptr(&url))?;
+        rt.block_on(storage.register_application(Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }))?;
+        let ssn = rt.block_on(storage.create_session(SessionAttributes {
+            application: "flmexec".to_string(),
+            slots: 1,
+            max_running_tasks: Some(2),
+            ..Default::default()
+        }))?;
+        for _ in 0..6 {
+            rt.block_on(storage.create_task(ssn.id, TaskAttributes::default()))?;
+        }
+        for i in 1..=3 {
+            storage.register_executor(&Executor {
+                id: format!("exec-{}", i),
+                slots: 1,
+                applications: vec![],
+                task_id: None,
+                ssn_id: None,
+                labels: Default::default(),
+                locality_keys: vec![],
+                host: Default::default(),
+                creation_time: Utc::now(),
+                last_heartbeat: Utc::now(),
+                state: ExecutorState::Idle,
+                draining: false,
+                quarantined: false,
+                generation: 0,
+            })?;
+        }
+
+        let runner = ScheduleRunner {
+            storage: storage.clone(),
+            state: SchedulerState::new_ptr(),
+        };
+        let bound = || {
+            let mut ids: Vec<_> = storage
+                .list_executor()?
+                .into_iter()
+                .filter(|e| e.ssn_id == Some(ssn.id))
+                .map(|e| e.id)
+                .collect();
+            ids.sort();
+            Ok::<_, FlameError>(ids)
+        };
+
+        // The binding stops at the limit of the session, even with more pending tasks.
+        for _ in 0..3 {
+            runner.schedule()?;
+        }
+        let ids = bound()?;
+        assert_eq!(ids.len(), 2, "{:?}", ids);
+
+        // The tasks over the limit stay pending at dispatch time, e.g. the executor bound
+        // before the limit was reached.
+        rt.block_on(async {
+            for id in &ids {
+                storage
+                    .bind_session_completed(id.clone(), None, None)
+                    .await?;
+            }
+            assert!(storage.bind_session("exec-3".to_string(), ssn.id).await?);
+            storage
+                .bind_session_completed("exec-3".to_string(), None, None)
+                .await?;
+
+            let task = storage.launch_task(ids[0].clone()).await?;
+            assert!(storage.launch_task(ids[1].clone()).await?.is_some());
+            assert!(storage.launch_task("exec-3".to_string()).await?.is_none());
+
+            let task = task.expect("the task within the limit");
+            storage
+                .complete_task(ids[0].clone(), Some(task.gid()), None, None)
+                .await?;
+            assert!(storage.launch_task("exec-3".to_string()).await?.is_some());
+            Ok::<_, FlameError>(())
+        })?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_affinity() -> Result<(), FlameError> {
+        let rt = tokio::runtime::Runtime::new().map_err(|e| FlameError::Internal(e.to_string()))?;
+        let url = format!(
+            "sqlite:///tmp/flame_test_affinity_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let storage = rt.block_on(crate::storage::new_ptr(&url))?;
+        rt.block_on(storage.register_application(Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }))?;
+        let create_session = || {
+            rt.block_on(async {
+                let ssn = storage
+                    .create_session(SessionAttributes {
+                        application: "flmexec".to_string(),
+                        slots: 1,
+                        ..Default::default()
+                    })
+                    .await?;
+                storage
+                    .create_task(ssn.id, TaskAttributes::default())
+                    .await?;
+                Ok::<_, FlameError>(ssn.id)
+            })
+        };
+        let register = |id: &str| {
+            storage.register_executor(&Executor {
+                id: id.to_string(),
+                slots: 1,
+                applications: vec![],
+                task_id: None,
+                ssn_id: None,
+                labels: Default::default(),
+                locality_keys: vec![],
+                host: Default::default(),
+                creation_time: Utc::now(),
+                last_heartbeat: Utc::now(),
+                state: ExecutorState::Idle,
+                draining: false,
+                quarantined: false,
+                generation: 0,
+            })
+        };
+
+        let runner = ScheduleRunner {
+            storage: storage.clone(),
+            state: SchedulerState::new_ptr(),
+        };
+        let bound = |id: &str| Ok::<_, FlameError>(storage.get_executor(id.to_string())?.ssn_id);
+
+        let ssn_1 = create_session()?;
+        register("exec-b")?;
+        runner.schedule()?;
+        assert_eq!(bound("exec-b")?, Some(ssn_1));
+        rt.block_on(async {
+            storage
+                .bind_session_completed("exec-b".to_string(), None, None)
+                .await?;
+            storage.unbind_executor("exec-b".to_string(), None).await?;
+            storage
+                .unbind_executor_completed("exec-b".to_string(), None)
+                .await
+        })?;
+
+        // The older session is allocated first, and picks the executor which served it
+        // over the idle stranger, which is tried first otherwise.
+        let ssn_2 = create_session()?;
+        register("exec-a")?;
+        let hits = crate::storage::affinity_hits();
+        runner.schedule()?;
+        assert_eq!(bound("exec-b")?, Some(ssn_1));
+        assert_eq!(bound("exec-a")?, Some(ssn_2));
+        assert!(crate::storage::affinity_hits() > hits);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_priority_classes() -> Result<(), FlameError> {
+        let rt = tokio::runtime::Runtime::new().map_err(|e| FlameError::Internal(e.to_string()))?;
+        let url = format!(
+            "sqlite:///tmp/flame_test_priority_classes_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let storage = rt.block_on(crate::storage::new_ptr(&url))?;
+        storage.set_priority_classes(&[FlamePriorityClassConf {
+            name: "interactive".to_string(),
+            weight: Some(10),
+            reserved_executors: Some(2),
+        }])?;
+        rt.block_on(storage.register_application(Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }))?;
+        let create_session = |priority_class: Option<&str>, tasks| {
+            rt.block_on(async {
+                let ssn = storage
+                    .create_session(SessionAttributes {
+                        application: "flmexec".to_string(),
+                        slots: 1,
+                        priority_class: priority_class.map(String::from),
+                        ..Default::default()
+                    })
+                    .await?;
+                for _ in 0..tasks {
+                    storage
+                        .create_task(ssn.id, TaskAttributes::default())
+                        .await?;
+                }
+                Ok::<_, FlameError>(ssn.id)
+            })
+        };
+        for i in 1..=3 {
+            storage.register_executor(&Executor {
+                id: format!("exec-{}", i),
+                slots: 1,
+                applications: vec![],
+                task_id: None,
+                ssn_id: None,
+                labels: Default::default(),
+                locality_keys: vec![],
+                host: Default::default(),
+                creation_time: Utc::now(),
+                last_heartbeat: Utc::now(),
+                state: ExecutorState::Idle,
+                draining: false,
+                quarantined: false,
+                generation: 0,
+            })?;
+        }
+
+        let runner = ScheduleRunner {
+            storage: storage.clone(),
+            state: SchedulerState::new_ptr(),
+        };
+        let executors = || {
+            let mut execs: Vec<_> = storage
+                .list_executor()?
+                .into_iter()
+                .map(|e| (e.id, e.state, e.ssn_id))
+                .collect();
+            execs.sort_by(|a, b| a.0.cmp(&b.0));
+            Ok::<_, FlameError>(execs)
+        };
+
+        // The sessions reference the classes in the configuration only.
+        let res = create_session(Some("urgent"), 1);
+        assert!(
+            matches!(&res, Err(FlameError::InvalidArgument { field, .. }) if field == "priority_class"),
+            "{:?}",
+            res
+        );
+
+        // The reserved executors are lent to the batch work while no class work is pending.
+        let batch = create_session(None, 10)?;
+        runner.schedule()?;
+        rt.block_on(async {
+            for i in 1..=3 {
+                let id = format!("exec-{}", i);
+                storage
+                    .bind_session_completed(id.clone(), None, None)
+                    .await?;
+                assert!(storage.launch_task(id).await?.is_some());
+            }
+            Ok::<_, FlameError>(())
+        })?;
+        for (_, state, ssn_id) in executors()? {
+            assert_eq!((state, ssn_id), (ExecutorState::Bound, Some(batch)));
+        }
+
+        // They're reclaimed in the cycle the class work arrives, and released after their
+        // running tasks; the other executor keeps running the batch work.
+        let interactive = create_session(Some("interactive"), 2)?;
+        runner.schedule()?;
+        let execs = executors()?;
+        assert_eq!(execs[0].1, ExecutorState::Unbinding);
+        assert_eq!(execs[1].1, ExecutorState::Unbinding);
+        assert_eq!(
+            execs[2],
+            ("exec-3".to_string(), ExecutorState::Bound, Some(batch))
+        );
+        rt.block_on(async {
+            for id in ["exec-1", "exec-2"] {
+                storage
+                    .complete_task(id.to_string(), None, None, None)
+                    .await?;
+                assert!(storage.launch_task(id.to_string()).await?.is_none());
+                storage
+                    .unbind_executor_completed(id.to_string(), None)
+                    .await?;
+            }
+            Ok::<_, FlameError>(())
+        })?;
+
+        // The released executors are bound to the class within the next cycle.
+        runner.schedule()?;
+        let execs = executors()?;
+        for (id, state, ssn_id) in &execs[..2] {
+            assert_eq!(
+                (*state, *ssn_id),
+                (ExecutorState::Binding, Some(interactive)),
+                "{}",
+                id
+            );
+        }
+        assert_eq!(execs[2].2, Some(batch));
+
+        Ok(())
     }
 }
@@ -0,0 +1,63 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::cmp::Ordering;
+
+use crate::model::{ExecutorInfoPtr, SessionInfo, SessionInfoPtr, SnapShot};
+use crate::scheduler::plugins::{Plugin, PluginPtr};
+
+/// The score of the executor which served the session most recently; it's lowered by one
+/// for every session the executor served since then.
+const AFFINITY_SCORE: i32 = 100;
+
+/// Prefers the executors which served the session recently, e.g. they still have its
+/// models or data loaded, when allocating executors to the session.
+pub struct Affinity {}
+
+impl Affinity {
+    pub fn new_ptr() -> PluginPtr {
+        Box::new(Affinity {})
+    }
+}
+
+impl Plugin for Affinity {
+    fn setup(&mut self, _: &SnapShot) {}
+
+    fn ssn_order_fn(&self, _: &SessionInfo, _: &SessionInfo) -> Option<Ordering> {
+        None
+    }
+
+    // The sessions are allocated by the other plugins.
+    fn is_underused(&self, _: &SessionInfoPtr) -> Option<bool> {
+        Some(true)
+    }
+
+    fn is_preemptible(&self, _: &SessionInfoPtr) -> Option<bool> {
+        Some(true)
+    }
+
+    fn filter(&self, _: &[ExecutorInfoPtr], _: &SessionInfoPtr) -> Option<Vec<ExecutorInfoPtr>> {
+        None
+    }
+
+    fn on_session_bind(&mut self, _: &SessionInfoPtr) {}
+
+    fn on_session_unbind(&mut self, _: &SessionInfoPtr) {}
+
+    fn score(&self, exec: &ExecutorInfoPtr, ssn: &SessionInfoPtr) -> i32 {
+        exec.served
+            .iter()
+            .position(|id| *id == ssn.id)
+            .map_or(0, |i| AFFINITY_SCORE - i as i32)
+    }
+}
@@ -12,15 +12,15 @@ limitations under the License.
 */
 
 use std::cmp::Ordering;
-use std::collections::binary_heap::BinaryHeap;
 use std::collections::HashMap;
 
 use crate::model::{ExecutorInfoPtr, SessionInfo, SessionInfoPtr, SnapShot};
 use crate::scheduler::plugins::{Plugin, PluginPtr};
-use common::apis::{SessionID, SessionState, TaskState};
+use common::apis::{SessionID, SessionState};
 
-#[derive(Default, Clone)]
-struct SSNInfo {
+/// The share of an open session, in slots.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SSNInfo {
     pub id: SessionID,
     pub slots: i32,
     pub desired: f64,
@@ -28,34 +28,6 @@ struct SSNInfo {
     pub allocated: f64,
 }
 
-impl Eq for SSNInfo {}
-
-impl PartialEq<Self> for SSNInfo {
-    fn eq(&self, other: &Self) -> bool {
-        self.id == other.id
-    }
-}
-
-impl PartialOrd<Self> for SSNInfo {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl Ord for SSNInfo {
-    fn cmp(&self, other: &Self) -> Ordering {
-        if self.deserved < other.deserved {
-            return Ordering::Greater;
-        }
-
-        if self.deserved > other.deserved {
-            return Ordering::Less;
-        }
-
-        Ordering::Equal
-    }
-}
-
 pub struct FairShare {
     ssn_map: HashMap<SessionID, SSNInfo>,
 }
@@ -68,63 +40,105 @@ impl FairShare {
     }
 }
 
-impl Plugin for FairShare {
-    fn setup(&mut self, ss: &SnapShot) {
-        let empty_map = HashMap::new();
-        let open_ssns = ss.ssn_index.get(&SessionState::Open).unwrap_or(&empty_map);
-
-        for ssn in open_ssns.values() {
-            let mut desired = 0.0;
-            for state in [TaskState::Pending, TaskState::Running] {
-                if let Some(d) = ssn.tasks_status.get(&state) {
-                    desired += *d as f64 * ssn.slots as f64;
-                }
+/// Divides the slots proportionally to the weights of the `(weight, desired)` demands, but
+/// none deserves more than it desires; the slots not desired are divided by the others again.
+fn share(slots: f64, demands: &[(f64, f64)]) -> Vec<f64> {
+    let mut deserved = vec![0.0; demands.len()];
+    let mut underused: Vec<_> = (0..demands.len()).filter(|i| demands[*i].1 > 0.0).collect();
+    let mut remaining = slots;
+
+    while remaining >= 0.001 && !underused.is_empty() {
+        let weights: f64 = underused.iter().map(|i| demands[*i].0).sum();
+        let quota = |i: usize| remaining * demands[i].0 / weights;
+
+        let (satisfied, unsatisfied): (Vec<_>, Vec<_>) =
+            underused.iter().partition(|i| quota(**i) >= demands[**i].1);
+        if satisfied.is_empty() {
+            for i in unsatisfied {
+                deserved[i] = quota(i);
             }
+            break;
+        }
 
-            self.ssn_map.insert(
-                ssn.id,
-                SSNInfo {
-                    id: ssn.id,
-                    desired,
-                    slots: ssn.slots,
-                    ..SSNInfo::default()
-                },
-            );
+        // The satisfied ones give the rest of their quotas back to the others.
+        for i in satisfied {
+            deserved[i] = demands[i].1;
+            remaining -= demands[i].1;
         }
+        underused = unsatisfied;
+    }
 
-        let mut remaining_slots = 0.0;
+    deserved
+}
 
-        for exe in ss.executors.values() {
-            remaining_slots += exe.slots as f64;
-            if let Some(ssn_id) = exe.ssn_id {
-                if let Some(ssn) = self.ssn_map.get_mut(&ssn_id) {
-                    ssn.allocated += ssn.slots as f64;
-                }
-            }
-        }
+/// The shares of the open sessions in the snapshot. The executors are divided across the
+/// applications by their weights first, and then fair-shared within the sessions of each
+/// application; the share of an application or a session is capped by its demand, and the
+/// rest spills over to the others. It's also the fair-share target of the allocation report,
+/// so the report never drifts from the scheduling.
+pub fn shares(ss: &SnapShot) -> HashMap<SessionID, SSNInfo> {
+    let empty_map = HashMap::new();
+    let open_ssns = ss.ssn_index.get(&SessionState::Open).unwrap_or(&empty_map);
+
+    let mut ssn_map = HashMap::new();
+    let mut app_ssns: HashMap<&str, Vec<SessionID>> = HashMap::new();
+    for ssn in open_ssns.values() {
+        ssn_map.insert(
+            ssn.id,
+            SSNInfo {
+                id: ssn.id,
+                desired: ssn.desired(),
+                slots: ssn.slots,
+                ..SSNInfo::default()
+            },
+        );
+        app_ssns.entry(&ssn.application).or_default().push(ssn.id);
+    }
 
-        let mut underused = BinaryHeap::from_iter(self.ssn_map.values_mut());
-        loop {
-            if remaining_slots < 0.001 {
-                break;
-            }
+    let mut total_slots = 0.0;
 
-            if underused.is_empty() {
-                break;
+    for exe in ss.executors.values() {
+        total_slots += exe.slots as f64;
+        if let Some(ssn_id) = exe.ssn_id {
+            if let Some(ssn) = ssn_map.get_mut(&ssn_id) {
+                ssn.allocated += ssn.slots as f64;
             }
+        }
+    }
 
-            let delta = remaining_slots / underused.len() as f64;
-            let ssn = underused.pop().unwrap();
-
-            if ssn.deserved + delta < ssn.desired {
-                ssn.deserved += delta;
-                remaining_slots -= delta;
-                underused.push(ssn);
-            } else {
-                remaining_slots -= ssn.desired - ssn.deserved;
-                ssn.deserved = ssn.desired;
+    let app_demand: Vec<_> = ss.app_demand().into_iter().collect();
+    let weighted: Vec<_> = app_demand
+        .iter()
+        .map(|(name, desired)| (ss.weight(name) as f64, *desired))
+        .collect();
+    for ((name, desired), deserved) in app_demand.iter().zip(share(total_slots, &weighted)) {
+        log::debug!(
+            "Application <{}>: weight <{}>, desired <{}>, deserved <{}>.",
+            name,
+            ss.weight(name),
+            desired,
+            deserved
+        );
+
+        let ssn_ids = app_ssns.get(name.as_str()).cloned().unwrap_or_default();
+        let demands: Vec<_> = ssn_ids
+            .iter()
+            .map(|id| (1.0, ssn_map[id].desired))
+            .collect();
+        for (id, deserved) in ssn_ids.iter().zip(share(deserved, &demands)) {
+            if let Some(ssn) = ssn_map.get_mut(id) {
+                ssn.deserved = deserved;
             }
         }
+    }
+
+    ssn_map
+}
+
+impl Plugin for FairShare {
+    /// Fair-shares the executors across the open sessions, see `shares`.
+    fn setup(&mut self, ss: &SnapShot) {
+        self.ssn_map = shares(ss);
 
         if log::log_enabled!(log::Level::Debug) {
             for ssn in self.ssn_map.values() {
@@ -197,3 +211,118 @@ impl Plugin for FairShare {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+
+    use common::apis::TaskState;
+
+    use crate::model::ExecutorInfo;
+
+    /// The snapshot of the executors with 1 slot, and the sessions of `(id, application,
+    /// pending tasks)`.
+    fn snapshot(
+        executors: usize,
+        weights: &[(&str, u32)],
+        sessions: &[(i64, &str, i32)],
+    ) -> SnapShot {
+        let mut ss = SnapShot {
+            weights: weights
+                .iter()
+                .map(|(name, weight)| (name.to_string(), *weight))
+                .collect(),
+            ..Default::default()
+        };
+        for (id, application, pending) in sessions {
+            ss.add_session(Rc::new(SessionInfo {
+                id: *id,
+                application: application.to_string(),
+                slots: 1,
+                tasks_status: HashMap::from([(TaskState::Pending, *pending)]),
+                ..Default::default()
+            }));
+        }
+        for i in 0..executors {
+            ss.add_executor(Rc::new(ExecutorInfo {
+                id: format!("exec-{}", i),
+                slots: 1,
+                ..Default::default()
+            }));
+        }
+
+        ss
+    }
+
+    fn deserved(ss: &SnapShot) -> HashMap<SessionID, f64> {
+        let mut plugin = FairShare {
+            ssn_map: HashMap::new(),
+        };
+        plugin.setup(ss);
+
+        plugin
+            .ssn_map
+            .values()
+            .map(|ssn| (ssn.id, (ssn.deserved * 1000.0).round() / 1000.0))
+            .collect()
+    }
+
+    const WEIGHTS: [(&str, u32); 2] = [("training", 7), ("preprocessing", 3)];
+
+    #[test]
+    fn test_weighted_share() {
+        // Both applications are backlogged, so they get 70% and 30% of the executors.
+        let ss = snapshot(
+            10,
+            &WEIGHTS,
+            &[
+                (1, "training", 20),
+                (2, "training", 20),
+                (3, "preprocessing", 20),
+            ],
+        );
+        assert_eq!(deserved(&ss), HashMap::from([(1, 3.5), (2, 3.5), (3, 3.0)]));
+
+        // The applications without weight share the executors equally.
+        let ss = snapshot(10, &[], &[(1, "training", 20), (2, "preprocessing", 20)]);
+        assert_eq!(deserved(&ss), HashMap::from([(1, 5.0), (2, 5.0)]));
+    }
+
+    #[test]
+    fn test_spillover() {
+        // The executors not desired by preprocessing spill over to training.
+        let ss = snapshot(
+            10,
+            &WEIGHTS,
+            &[
+                (1, "training", 20),
+                (2, "training", 20),
+                (3, "preprocessing", 1),
+            ],
+        );
+        assert_eq!(deserved(&ss), HashMap::from([(1, 4.5), (2, 4.5), (3, 1.0)]));
+
+        // An application without demand does not take any executor, and the share of an
+        // application spills over between its own sessions first.
+        let ss = snapshot(
+            10,
+            &[("training", 7), ("preprocessing", 3), ("idle", 5)],
+            &[
+                (1, "training", 2),
+                (2, "training", 20),
+                (3, "preprocessing", 20),
+                (4, "idle", 0),
+            ],
+        );
+        assert_eq!(
+            deserved(&ss),
+            HashMap::from([(1, 2.0), (2, 5.0), (3, 3.0), (4, 0.0)])
+        );
+
+        // All the demands are satisfied if there're enough executors.
+        let ss = snapshot(10, &WEIGHTS, &[(1, "training", 2), (2, "preprocessing", 3)]);
+        assert_eq!(deserved(&ss), HashMap::from([(1, 2.0), (2, 3.0)]));
+    }
+}
@@ -0,0 +1,203 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::cmp::Ordering;
+
+use crate::model::{ExecutorInfoPtr, SessionInfo, SessionInfoPtr, SnapShot, TaskInfo};
+use crate::scheduler::plugins::{Plugin, PluginPtr};
+use common::apis::TaskID;
+
+/// Assigns the pending tasks to the executors having their localities, e.g. the tasks of
+/// `shard-1` are launched on the executors caching `shard-1`, or else to the executors having
+/// all the labels of the tasks, e.g. the tasks labeled `zone=a` are launched on the executors
+/// labeled `zone=a`; the tasks are not loaded if no executor has labels or locality keys.
+pub struct Locality {
+    enabled: bool,
+}
+
+impl Locality {
+    pub fn new_ptr() -> PluginPtr {
+        Box::new(Locality { enabled: false })
+    }
+}
+
+fn has_locality(task: &TaskInfo, exec: &ExecutorInfoPtr) -> bool {
+    task.locality
+        .as_ref()
+        .is_some_and(|locality| exec.locality_keys.contains(locality))
+}
+
+fn is_local(task: &TaskInfo, exec: &ExecutorInfoPtr) -> bool {
+    !task.labels.is_empty()
+        && task
+            .labels
+            .iter()
+            .all(|(k, v)| exec.labels.get(k) == Some(v))
+}
+
+impl Plugin for Locality {
+    fn setup(&mut self, ss: &SnapShot) {
+        self.enabled = ss
+            .executors
+            .values()
+            .any(|exec| !exec.labels.is_empty() || !exec.locality_keys.is_empty());
+    }
+
+    fn ssn_order_fn(&self, _: &SessionInfo, _: &SessionInfo) -> Option<Ordering> {
+        None
+    }
+
+    // The sessions are allocated by the other plugins.
+    fn is_underused(&self, _: &SessionInfoPtr) -> Option<bool> {
+        Some(true)
+    }
+
+    fn is_preemptible(&self, _: &SessionInfoPtr) -> Option<bool> {
+        Some(true)
+    }
+
+    fn filter(&self, _: &[ExecutorInfoPtr], _: &SessionInfoPtr) -> Option<Vec<ExecutorInfoPtr>> {
+        None
+    }
+
+    fn on_session_bind(&mut self, _: &SessionInfoPtr) {}
+
+    fn on_session_unbind(&mut self, _: &SessionInfoPtr) {}
+
+    fn wants_tasks(&self, _: &SessionInfoPtr) -> bool {
+        self.enabled
+    }
+
+    /// The oldest pending task whose locality is on the executor, or else the oldest one
+    /// local to the executor by the labels.
+    fn assign_task(&self, exec: &ExecutorInfoPtr, ssn: &SessionInfoPtr) -> Option<TaskID> {
+        let tasks = ssn.pending_tasks()?;
+        let oldest = |local: fn(&TaskInfo, &ExecutorInfoPtr) -> bool| {
+            tasks
+                .iter()
+                .filter(|task| local(task, exec))
+                .min_by_key(|task| task.id)
+                .map(|task| task.id)
+        };
+
+        oldest(has_locality).or_else(|| oldest(is_local))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    use crate::model::ExecutorInfo;
+
+    fn labels(labels: &[(&str, &str)]) -> HashMap<String, String> {
+        labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn executor(id: &str, zone: Option<&str>) -> ExecutorInfoPtr {
+        Rc::new(ExecutorInfo {
+            id: id.to_string(),
+            slots: 1,
+            labels: zone.map(|z| labels(&[("zone", z)])).unwrap_or_default(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_assign_local_task() {
+        let (exec_a, exec_b) = (executor("exec-a", Some("a")), executor("exec-b", None));
+        let mut ss = SnapShot::default();
+        ss.add_executor(exec_b.clone());
+
+        // The tasks are not loaded if no executor is labeled.
+        let mut plugin = Locality { enabled: false };
+        plugin.setup(&ss);
+        let ssn: SessionInfoPtr = Rc::new(SessionInfo::default());
+        assert!(!plugin.wants_tasks(&ssn));
+
+        ss.add_executor(exec_a.clone());
+        plugin.setup(&ss);
+        assert!(plugin.wants_tasks(&ssn));
+        assert_eq!(plugin.assign_task(&exec_a, &ssn), None);
+
+        let task = |id: TaskID, task_labels: &[(&str, &str)]| TaskInfo {
+            id,
+            labels: labels(task_labels),
+            ..Default::default()
+        };
+        ssn.set_pending_tasks(vec![
+            task(1, &[]),
+            task(2, &[("zone", "b")]),
+            task(4, &[("zone", "a"), ("gpu", "true")]),
+            task(5, &[("zone", "a")]),
+            task(3, &[("zone", "a")]),
+        ]);
+        assert_eq!(plugin.assign_task(&exec_a, &ssn), Some(3));
+        assert_eq!(plugin.assign_task(&exec_b, &ssn), None);
+
+        ssn.remove_pending_task(3);
+        assert_eq!(plugin.assign_task(&exec_a, &ssn), Some(5));
+    }
+
+    #[test]
+    fn test_assign_by_locality() {
+        let exec = |id: &str, keys: &[&str]| {
+            Rc::new(ExecutorInfo {
+                id: id.to_string(),
+                slots: 1,
+                locality_keys: keys.iter().map(|k| k.to_string()).collect(),
+                ..Default::default()
+            })
+        };
+        let (exec_1, exec_2) = (exec("exec-1", &["shard-1"]), exec("exec-2", &["shard-2"]));
+        let mut ss = SnapShot::default();
+        ss.add_executor(exec_1.clone());
+
+        // The tasks are loaded if an executor has locality keys, even without labels.
+        let mut plugin = Locality { enabled: false };
+        plugin.setup(&ss);
+        let ssn: SessionInfoPtr = Rc::new(SessionInfo::default());
+        assert!(plugin.wants_tasks(&ssn));
+
+        let task = |id: TaskID, locality: Option<&str>, zone: &[(&str, &str)]| TaskInfo {
+            id,
+            locality: locality.map(str::to_string),
+            labels: labels(zone),
+            ..Default::default()
+        };
+        ssn.set_pending_tasks(vec![
+            task(1, None, &[]),
+            task(2, Some("shard-2"), &[]),
+            task(3, None, &[("zone", "a")]),
+            task(4, Some("shard-1"), &[]),
+        ]);
+        assert_eq!(plugin.assign_task(&exec_1, &ssn), Some(4));
+        assert_eq!(plugin.assign_task(&exec_2, &ssn), Some(2));
+
+        // The locality goes before the labels.
+        let exec_3 = Rc::new(ExecutorInfo {
+            labels: labels(&[("zone", "a")]),
+            ..(*exec("exec-3", &["shard-1"])).clone()
+        });
+        assert_eq!(plugin.assign_task(&exec_3, &ssn), Some(4));
+        ssn.remove_pending_task(4);
+        assert_eq!(plugin.assign_task(&exec_3, &ssn), Some(3));
+        assert_eq!(plugin.assign_task(&exec_1, &ssn), None);
+    }
+}
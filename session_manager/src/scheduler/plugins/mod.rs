@@ -19,12 +19,21 @@ use std::rc::Rc;
 use stdng::collections;
 
 use crate::model::{ExecutorInfoPtr, SessionInfo, SessionInfoPtr, SnapShot};
+use crate::scheduler::plugins::affinity::Affinity;
 use crate::scheduler::plugins::fairshare::FairShare;
+use crate::scheduler::plugins::locality::Locality;
+use crate::scheduler::plugins::priority::Priority;
 use crate::scheduler::Context;
 
+use common::apis::TaskID;
 use common::FlameError;
 
+mod affinity;
 mod fairshare;
+mod locality;
+mod priority;
+
+pub use fairshare::{shares, SSNInfo};
 
 // lazy_static! {
 //     static ref INSTANCE: MutexPtr<PluginManager> = Arc::new(Mutex::new(PluginManager {
@@ -55,6 +64,24 @@ pub trait Plugin: Send + Sync + 'static {
     // Events
     fn on_session_bind(&mut self, ssn: &SessionInfoPtr);
     fn on_session_unbind(&mut self, ssn: &SessionInfoPtr);
+
+    /// Whether the plugin assigns the pending tasks of the session to its executors, so the
+    /// tasks are loaded into the snapshot.
+    fn wants_tasks(&self, _ssn: &SessionInfoPtr) -> bool {
+        false
+    }
+
+    /// The pending task of the session to launch on the executor bound to it, which is
+    /// reserved for the executor; any pending task is launched if none.
+    fn assign_task(&self, _exec: &ExecutorInfoPtr, _ssn: &SessionInfoPtr) -> Option<TaskID> {
+        None
+    }
+
+    /// How much the executor is preferred for the session among the executors passing the
+    /// filters; it never makes an executor eligible.
+    fn score(&self, _exec: &ExecutorInfoPtr, _ssn: &SessionInfoPtr) -> i32 {
+        0
+    }
 }
 
 pub struct PluginManager {
@@ -63,7 +90,12 @@ pub struct PluginManager {
 
 impl PluginManager {
     pub fn setup(ss: &SnapShot) -> Result<PluginManagerPtr, FlameError> {
-        let mut plugins = HashMap::from([("fairshare".to_string(), FairShare::new_ptr())]);
+        let mut plugins = HashMap::from([
+            ("fairshare".to_string(), FairShare::new_ptr()),
+            ("locality".to_string(), Locality::new_ptr()),
+            ("affinity".to_string(), Affinity::new_ptr()),
+            ("priority".to_string(), Priority::new_ptr()),
+        ]);
 
         for plugin in plugins.values_mut() {
             plugin.setup(ss);
@@ -105,6 +137,23 @@ impl PluginManager {
         res
     }
 
+    pub fn wants_tasks(&self, ssn: &SessionInfoPtr) -> bool {
+        self.plugins.values().any(|plugin| plugin.wants_tasks(ssn))
+    }
+
+    pub fn assign_task(&self, exec: &ExecutorInfoPtr, ssn: &SessionInfoPtr) -> Option<TaskID> {
+        self.plugins
+            .values()
+            .find_map(|plugin| plugin.assign_task(exec, ssn))
+    }
+
+    pub fn score(&self, exec: &ExecutorInfoPtr, ssn: &SessionInfoPtr) -> i32 {
+        self.plugins
+            .values()
+            .map(|plugin| plugin.score(exec, ssn))
+            .sum()
+    }
+
     pub fn on_session_bind(&mut self, ssn: &SessionInfoPtr) {
         for plugin in self.plugins.values_mut() {
             plugin.on_session_bind(ssn);
@@ -117,6 +166,8 @@ impl PluginManager {
         }
     }
 
+    /// Orders the sessions by the plugins; the older session goes first if they're equal to
+    /// all the plugins, so the sessions are scheduled in the same order in every cycle.
     pub fn ssn_order_fn(&self, t1: &SessionInfoPtr, t2: &SessionInfoPtr) -> Ordering {
         for plugin in self.plugins.values() {
             if let Some(order) = plugin.ssn_order_fn(t1, t2) {
@@ -126,7 +177,7 @@ impl PluginManager {
             }
         }
 
-        Ordering::Equal
+        t2.id.cmp(&t1.id)
     }
 }
 
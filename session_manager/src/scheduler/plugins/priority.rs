@@ -0,0 +1,143 @@
+/*
+Copyright 2023 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::model::{ExecutorInfoPtr, SessionInfo, SessionInfoPtr, SnapShot};
+use crate::scheduler::plugins::{Plugin, PluginPtr};
+
+/// Keeps the executors reserved for the priority classes: the sessions of a class are not
+/// preempted by the other plugins while the class holds no more executors than its
+/// reservation. The reservations are met by `ReserveAction`.
+pub struct Priority {
+    /// The executors reserved for each class, and the ones held by its sessions.
+    classes: HashMap<String, (u32, u32)>,
+}
+
+impl Priority {
+    pub fn new_ptr() -> PluginPtr {
+        Box::new(Priority {
+            classes: HashMap::new(),
+        })
+    }
+
+    fn held(&mut self, ssn: &SessionInfo) -> Option<&mut u32> {
+        let class = ssn.priority_class.as_ref()?;
+        self.classes.get_mut(class).map(|(_, held)| held)
+    }
+}
+
+impl Plugin for Priority {
+    fn setup(&mut self, ss: &SnapShot) {
+        for class in &ss.priority_classes {
+            if class.reserved_executors() > 0 {
+                self.classes.insert(
+                    class.name.clone(),
+                    (class.reserved_executors(), ss.class_executors(&class.name)),
+                );
+            }
+        }
+    }
+
+    fn ssn_order_fn(&self, _: &SessionInfo, _: &SessionInfo) -> Option<Ordering> {
+        None
+    }
+
+    // The sessions are allocated by the other plugins.
+    fn is_underused(&self, _: &SessionInfoPtr) -> Option<bool> {
+        Some(true)
+    }
+
+    fn is_preemptible(&self, ssn: &SessionInfoPtr) -> Option<bool> {
+        let within_reservation = ssn
+            .priority_class
+            .as_ref()
+            .and_then(|class| self.classes.get(class))
+            .is_some_and(|(reserved, held)| held <= reserved);
+
+        Some(!within_reservation)
+    }
+
+    fn filter(&self, _: &[ExecutorInfoPtr], _: &SessionInfoPtr) -> Option<Vec<ExecutorInfoPtr>> {
+        None
+    }
+
+    fn on_session_bind(&mut self, ssn: &SessionInfoPtr) {
+        if let Some(held) = self.held(ssn) {
+            *held += 1;
+        }
+    }
+
+    fn on_session_unbind(&mut self, ssn: &SessionInfoPtr) {
+        if let Some(held) = self.held(ssn) {
+            *held = held.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+
+    use common::ctx::FlamePriorityClassConf;
+
+    use crate::model::ExecutorInfo;
+
+    #[test]
+    fn test_reserved_not_preemptible() {
+        let mut ss = SnapShot {
+            priority_classes: vec![FlamePriorityClassConf {
+                name: "interactive".to_string(),
+                weight: Some(10),
+                reserved_executors: Some(2),
+            }],
+            ..Default::default()
+        };
+        let session = |id, class: Option<&str>| {
+            Rc::new(SessionInfo {
+                id,
+                slots: 1,
+                priority_class: class.map(String::from),
+                ..Default::default()
+            })
+        };
+        let (interactive, batch) = (session(1, Some("interactive")), session(2, None));
+        ss.add_session(interactive.clone());
+        ss.add_session(batch.clone());
+        for i in 0..3 {
+            ss.add_executor(Rc::new(ExecutorInfo {
+                id: format!("exec-{}", i),
+                slots: 1,
+                ssn_id: Some(if i < 2 { 1 } else { 2 }),
+                ..Default::default()
+            }));
+        }
+
+        let mut plugin = Priority {
+            classes: HashMap::new(),
+        };
+        plugin.setup(&ss);
+        assert_eq!(plugin.classes["interactive"], (2, 2));
+        assert_eq!(plugin.is_preemptible(&interactive), Some(false));
+        assert_eq!(plugin.is_preemptible(&batch), Some(true));
+
+        // The executors over the reservation are preempted like the others.
+        plugin.on_session_bind(&interactive);
+        assert_eq!(plugin.is_preemptible(&interactive), Some(true));
+        plugin.on_session_unbind(&interactive);
+        assert_eq!(plugin.is_preemptible(&interactive), Some(false));
+    }
+}
@@ -0,0 +1,224 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+
+use common::apis::SessionID;
+
+use crate::model::SnapShot;
+use crate::scheduler::plugins;
+use crate::scheduler::stats::seconds;
+
+/// How the slots are shared by the applications and the namespaces, which is computed from
+/// the snapshot in every cycle of the scheduler with the shares of the fairshare plugin, so
+/// the targets are exactly what the scheduler works towards.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AllocationReport {
+    /// The time of the cycle computing the report, none if no cycle was completed.
+    pub update_time: Option<DateTime<Utc>>,
+    /// The slots of all the executors, which are shared by the open sessions.
+    pub total_slots: f64,
+    pub applications: BTreeMap<String, Allocation>,
+    pub namespaces: BTreeMap<String, Allocation>,
+    /// The open sessions with pending tasks which are allocated less than their targets,
+    /// the longest waiting first.
+    pub starved: Vec<StarvedSession>,
+}
+
+/// The slots of the open sessions of an application or a namespace.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Allocation {
+    /// The slots of the pending and running tasks.
+    pub desired: f64,
+    /// The slots of the executors bound to the sessions.
+    pub allocated: f64,
+    /// The fair-share target of the slots.
+    pub target: f64,
+}
+
+impl Allocation {
+    /// How much more than the target is allocated, negative if less.
+    pub fn deviation(&self) -> f64 {
+        self.allocated - self.target
+    }
+
+    fn add(&mut self, ssn: &plugins::SSNInfo) {
+        self.desired += ssn.desired;
+        self.allocated += ssn.allocated;
+        self.target += ssn.deserved;
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StarvedSession {
+    pub id: SessionID,
+    pub application: String,
+    pub namespace: String,
+    pub pending: u32,
+    pub allocated: f64,
+    pub target: f64,
+    /// The age of the oldest pending task in seconds.
+    pub wait: f64,
+}
+
+impl AllocationReport {
+    pub fn new(snapshot: &SnapShot, now: DateTime<Utc>) -> Self {
+        let mut report = AllocationReport {
+            update_time: Some(now),
+            total_slots: snapshot.executors.values().map(|e| e.slots as f64).sum(),
+            ..Default::default()
+        };
+
+        for share in plugins::shares(snapshot).values() {
+            let Some(ssn) = snapshot.sessions.get(&share.id) else {
+                continue;
+            };
+            report
+                .applications
+                .entry(ssn.application.clone())
+                .or_default()
+                .add(share);
+            report
+                .namespaces
+                .entry(ssn.namespace.clone())
+                .or_default()
+                .add(share);
+
+            if ssn.pending() > 0 && share.allocated < share.deserved {
+                report.starved.push(StarvedSession {
+                    id: ssn.id,
+                    application: ssn.application.clone(),
+                    namespace: ssn.namespace.clone(),
+                    pending: ssn.pending() as u32,
+                    allocated: share.allocated,
+                    target: share.deserved,
+                    wait: ssn.oldest_pending.map(|t| seconds(now - t)).unwrap_or(0.0),
+                });
+            }
+        }
+        report
+            .starved
+            .sort_by(|a, b| b.wait.total_cmp(&a.wait).then(a.id.cmp(&b.id)));
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    use common::apis::{ExecutorState, SessionState, TaskState};
+
+    use crate::model::{ExecutorInfo, SessionInfo};
+
+    #[test]
+    fn test_allocation_report() {
+        let now = Utc::now();
+        let mut ss = SnapShot {
+            weights: HashMap::from([
+                ("training".to_string(), 7),
+                ("preprocessing".to_string(), 3),
+            ]),
+            ..Default::default()
+        };
+        // The sessions of `(id, namespace, application, pending, waiting seconds)`.
+        for (id, namespace, application, pending, wait) in [
+            (1, "team-a", "training", 20, 30),
+            (2, "team-b", "training", 20, 10),
+            (3, "team-a", "preprocessing", 1, 60),
+        ] {
+            ss.add_session(Rc::new(SessionInfo {
+                id,
+                namespace: namespace.to_string(),
+                application: application.to_string(),
+                slots: 1,
+                tasks_status: HashMap::from([(TaskState::Pending, pending)]),
+                oldest_pending: Some(now - chrono::Duration::seconds(wait)),
+                state: SessionState::Open,
+                ..Default::default()
+            }));
+        }
+        // The closed sessions are not reported.
+        ss.add_session(Rc::new(SessionInfo {
+            id: 4,
+            namespace: "team-c".to_string(),
+            application: "training".to_string(),
+            slots: 1,
+            tasks_status: HashMap::from([(TaskState::Pending, 5)]),
+            state: SessionState::Closed,
+            ..Default::default()
+        }));
+        // 2 executors are bound to session 1, 5 to session 2, and 3 are idle.
+        for i in 0..10 {
+            let ssn_id = match i {
+                0..=1 => Some(1),
+                2..=6 => Some(2),
+                _ => None,
+            };
+            ss.add_executor(Rc::new(ExecutorInfo {
+                id: format!("exec-{}", i),
+                slots: 1,
+                ssn_id,
+                state: match ssn_id {
+                    Some(_) => ExecutorState::Bound,
+                    None => ExecutorState::Idle,
+                },
+                ..Default::default()
+            }));
+        }
+
+        // Preprocessing desires 1 of its 3 slots, so the other 2 spill over to training,
+        // which is shared by sessions 1 and 2 equally.
+        let report = AllocationReport::new(&ss, now);
+        let allocation = |desired, allocated, target| Allocation {
+            desired,
+            allocated,
+            target,
+        };
+        assert_eq!(report.update_time, Some(now));
+        assert_eq!(report.total_slots, 10.0);
+        assert_eq!(
+            report.applications,
+            BTreeMap::from([
+                ("preprocessing".to_string(), allocation(1.0, 0.0, 1.0)),
+                ("training".to_string(), allocation(40.0, 7.0, 9.0)),
+            ])
+        );
+        assert_eq!(
+            report.namespaces,
+            BTreeMap::from([
+                ("team-a".to_string(), allocation(21.0, 2.0, 5.5)),
+                ("team-b".to_string(), allocation(20.0, 5.0, 4.5)),
+            ])
+        );
+        assert_eq!(report.applications["training"].deviation(), -2.0);
+        assert_eq!(report.namespaces["team-b"].deviation(), 0.5);
+
+        // Session 2 is allocated more than its target, so it's not starved.
+        let starved: Vec<_> = report
+            .starved
+            .iter()
+            .map(|s| (s.id, s.pending, s.allocated, s.target, s.wait))
+            .collect();
+        assert_eq!(
+            starved,
+            vec![(3, 1, 0.0, 1.0, 60.0), (1, 20, 2.0, 4.5, 30.0)]
+        );
+    }
+}
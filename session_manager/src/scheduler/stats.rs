@@ -0,0 +1,228 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{DateTime, Utc};
+
+use common::apis::{ExecutorState, SessionID, SessionState, TaskState};
+use common::ctx::FlameQuotaConf;
+
+use crate::apiserver;
+use crate::model::{QuotaUsage, SnapShot};
+use crate::scheduler::report::AllocationReport;
+use crate::storage;
+
+/// The upper bounds of the buckets of the bind latency in seconds.
+pub const BIND_LATENCY_BOUNDS: [f64; 9] = [0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+/// The health of the queue, which is computed from the snapshot in every cycle of the
+/// scheduler for `flmctl top` and the autoscalers.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClusterStats {
+    /// The time of the cycle computing the stats, none if no cycle was completed.
+    pub update_time: Option<DateTime<Utc>>,
+    /// The backlog of the open sessions of each application.
+    pub applications: BTreeMap<String, AppStats>,
+    pub idle_executors: u32,
+    /// The executors binding, bound or unbinding a session.
+    pub bound_executors: u32,
+    /// The executors quarantined for failing too many tasks.
+    pub quarantined_executors: u32,
+    /// The latency from the creation of the sessions to their first bound executors.
+    pub bind_latency: Histogram,
+    /// The signals delivered to the autoscaler hook, and those failed to.
+    pub autoscaler_deliveries: u64,
+    pub autoscaler_failures: u64,
+    /// The acquisitions of the locks and the calls of the engine which were slower than
+    /// the thresholds of the self-monitoring.
+    pub slow_locks: u64,
+    pub slow_engine_calls: u64,
+    /// The mutating Frontend calls rejected by the rate limiter.
+    pub throttled_calls: u64,
+    /// The usage of the quotas, in the order of the configuration.
+    pub quotas: Vec<QuotaStats>,
+    /// The executors bound to a session they served recently, and to a session served
+    /// recently by another executor only.
+    pub affinity_hits: u64,
+    pub affinity_misses: u64,
+    /// The tasks with a locality launched on an executor having its key, and those launched
+    /// on another executor after the locality wait.
+    pub locality_hits: u64,
+    pub locality_misses: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AppStats {
+    pub pending: u32,
+    /// The age of the oldest pending task in seconds, zero if no task is pending.
+    pub oldest_pending_age: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QuotaStats {
+    pub quota: FlameQuotaConf,
+    pub usage: QuotaUsage,
+}
+
+/// The histogram of the observations; `counts[i]` is the number of the observations in
+/// `(bounds[i-1], bounds[i]]`, and the last one is of those greater than all the bounds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    pub bounds: Vec<f64>,
+    pub counts: Vec<u64>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::new(&BIND_LATENCY_BOUNDS)
+    }
+}
+
+impl Histogram {
+    pub fn new(bounds: &[f64]) -> Self {
+        Histogram {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, v: f64) {
+        let i = self.bounds.iter().position(|b| v <= *b);
+        self.counts[i.unwrap_or(self.bounds.len())] += 1;
+        self.sum += v;
+        self.count += 1;
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            n => Some(self.sum / n as f64),
+        }
+    }
+}
+
+/// Computes the stats of the cycles; the bind latency is observed once per session, when
+/// an executor is found bound to it for the first time.
+#[derive(Default)]
+pub struct StatsRecorder {
+    stats: ClusterStats,
+    report: AllocationReport,
+    /// The sessions whose bind latency was observed; they're forgotten once they're gone.
+    observed: HashSet<SessionID>,
+}
+
+impl StatsRecorder {
+    pub fn stats(&self) -> &ClusterStats {
+        &self.stats
+    }
+
+    pub fn report(&self) -> &AllocationReport {
+        &self.report
+    }
+
+    pub fn record_delivery(&mut self, delivered: bool) {
+        match delivered {
+            true => self.stats.autoscaler_deliveries += 1,
+            false => self.stats.autoscaler_failures += 1,
+        }
+    }
+
+    pub fn record(&mut self, snapshot: &SnapShot, now: DateTime<Utc>) {
+        let mut applications = BTreeMap::<String, AppStats>::new();
+        for ssn in snapshot
+            .ssn_index
+            .get(&SessionState::Open)
+            .into_iter()
+            .flat_map(|ssns| ssns.values())
+        {
+            let app = applications.entry(ssn.application.clone()).or_default();
+            let pending = ssn.tasks_status.get(&TaskState::Pending);
+            app.pending += pending.copied().unwrap_or_default().max(0) as u32;
+
+            if let Some(oldest) = ssn.oldest_pending {
+                let age = seconds(now - oldest);
+                app.oldest_pending_age = app.oldest_pending_age.max(age);
+            }
+        }
+
+        let (mut idle_executors, mut bound_executors, mut quarantined_executors) = (0, 0, 0);
+        for exec in snapshot.executors.values() {
+            match exec.state {
+                ExecutorState::Idle => idle_executors += 1,
+                _ => bound_executors += 1,
+            }
+            if exec.quarantined {
+                quarantined_executors += 1;
+            }
+
+            let Some(ssn) = exec.ssn_id.and_then(|id| snapshot.sessions.get(&id)) else {
+                continue;
+            };
+            if self.observed.insert(ssn.id) {
+                self.stats
+                    .bind_latency
+                    .observe(seconds(now - ssn.creation_time));
+            }
+        }
+        self.observed
+            .retain(|id| snapshot.sessions.contains_key(id));
+
+        self.stats.update_time = Some(now);
+        self.stats.applications = applications;
+        self.stats.idle_executors = idle_executors;
+        self.stats.bound_executors = bound_executors;
+        self.stats.quarantined_executors = quarantined_executors;
+        self.stats.slow_locks = common::monitor::slow_locks();
+        self.stats.slow_engine_calls = storage::slow_engine_calls();
+        self.stats.throttled_calls = apiserver::throttled_calls();
+        self.stats.affinity_hits = storage::affinity_hits();
+        self.stats.affinity_misses = storage::affinity_misses();
+        self.stats.locality_hits = storage::locality_hits();
+        self.stats.locality_misses = storage::locality_misses();
+        self.stats.quotas = snapshot
+            .quotas
+            .iter()
+            .map(|quota| QuotaStats {
+                quota: quota.clone(),
+                usage: snapshot.quota_usage(quota, None),
+            })
+            .collect();
+        self.report = AllocationReport::new(snapshot, now);
+    }
+}
+
+pub(super) fn seconds(d: chrono::Duration) -> f64 {
+    d.num_milliseconds().max(0) as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut h = Histogram::new(&[1.0, 10.0]);
+        assert_eq!(h.mean(), None);
+
+        for v in [0.5, 1.0, 5.0, 100.0] {
+            h.observe(v);
+        }
+        assert_eq!(h.counts, vec![2, 1, 1]);
+        assert_eq!((h.count, h.mean()), (4, Some(26.625)));
+    }
+}
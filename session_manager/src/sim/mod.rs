@@ -0,0 +1,427 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Replays a synthetic workload against the scheduler and the storage with a virtual clock;
+//! the executors are simulated by following the protocol of the executor manager, and the
+//! tasks take their sampled durations without running anything.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use chrono::Utc;
+use tokio::runtime::Runtime;
+
+use common::apis::{
+    Application, Executor, ExecutorID, ExecutorState, SessionAttributes, SessionID, TaskAttributes,
+    TaskGID,
+};
+use common::FlameError;
+
+use crate::scheduler::{self, SchedulerState, SchedulerStatePtr};
+use crate::storage::{self, StoragePtr};
+
+mod report;
+mod workload;
+
+pub use report::Report;
+use report::TaskRecord;
+pub use workload::Workload;
+use workload::{millis, Rng};
+
+/// Replays the workload in the YAML file, see `Workload`.
+pub fn run_file(path: &str) -> Result<Report, FlameError> {
+    let yaml = std::fs::read_to_string(path)
+        .map_err(|e| FlameError::InvalidConfig(format!("failed to read <{}>: {}", path, e)))?;
+
+    run(&Workload::from_yaml(&yaml)?)
+}
+
+/// Replays the workload; it blocks on its own runtime, so it must not be called in an async
+/// context.
+pub fn run(workload: &Workload) -> Result<Report, FlameError> {
+    Simulation::new(workload)?.run()
+}
+
+/// A session arriving at `time` with the durations of its tasks in milliseconds.
+struct Arrival {
+    time: u64,
+    application: String,
+    labels: HashMap<String, String>,
+    durations: Vec<u64>,
+}
+
+struct FakeExecutor {
+    id: ExecutorID,
+    /// The task launched on the executor and the time it's completed.
+    running: Option<(TaskGID, u64)>,
+}
+
+struct SubmittedTask {
+    application: String,
+    submitted: u64,
+    duration: u64,
+    launched: Option<u64>,
+}
+
+struct Simulation {
+    runtime: Runtime,
+    storage: StoragePtr,
+    scheduler: SchedulerStatePtr,
+    /// The virtual time in milliseconds.
+    now: u64,
+    interval: u64,
+    horizon: u64,
+    next_cycle: u64,
+    arrivals: VecDeque<Arrival>,
+    executors: Vec<FakeExecutor>,
+    tasks: HashMap<TaskGID, SubmittedTask>,
+    /// The number of the tasks not completed of the open sessions.
+    remaining: HashMap<SessionID, usize>,
+    weights: BTreeMap<String, u32>,
+    records: Vec<TaskRecord>,
+}
+
+impl Simulation {
+    fn new(workload: &Workload) -> Result<Self, FlameError> {
+        let runtime = Runtime::new().map_err(|e| FlameError::Internal(e.to_string()))?;
+        let storage = runtime.block_on(storage::new_ptr("memory://"))?;
+
+        // The applications are registered with the default weight unless they're declared.
+        let mut weights = BTreeMap::new();
+        for ssn in &workload.sessions {
+            weights.insert(ssn.application.clone(), None);
+        }
+        for app in &workload.applications {
+            weights.insert(app.name.clone(), app.weight);
+        }
+        for (name, weight) in &weights {
+            runtime.block_on(storage.register_application(Application {
+                name: name.clone(),
+                weight: *weight,
+                ..Default::default()
+            }))?;
+        }
+        let weights = storage
+            .list_application()?
+            .iter()
+            .map(|app| (app.name.clone(), app.weight()))
+            .collect();
+
+        let mut executors = vec![];
+        for spec in &workload.executors {
+            for i in 0..spec.count {
+                let id = format!("{}-{}", spec.name, i);
+                storage.register_executor(&Executor {
+                    id: id.clone(),
+                    slots: 1,
+                    applications: vec![],
+                    task_id: None,
+                    ssn_id: None,
+                    labels: spec.labels.clone(),
+                    locality_keys: spec.locality_keys.clone(),
+                    host: Default::default(),
+                    creation_time: Utc::now(),
+                    last_heartbeat: Utc::now(),
+                    state: ExecutorState::Idle,
+                    draining: false,
+                    quarantined: false,
+                    generation: 0,
+                })?;
+                executors.push(FakeExecutor { id, running: None });
+            }
+        }
+        executors.sort_by(|a, b| a.id.cmp(&b.id));
+
+        // The durations are sampled up front, so they don't depend on the scheduling.
+        let mut rng = Rng::new(workload.seed);
+        let mut arrivals = vec![];
+        for spec in &workload.sessions {
+            for i in 0..spec.count {
+                arrivals.push(Arrival {
+                    time: millis(spec.arrival + spec.every * i as f64),
+                    application: spec.application.clone(),
+                    labels: spec.labels.clone(),
+                    durations: (0..spec.tasks)
+                        .map(|_| spec.duration.sample(&mut rng))
+                        .collect(),
+                });
+            }
+        }
+        arrivals.sort_by_key(|a| a.time);
+
+        Ok(Simulation {
+            runtime,
+            storage,
+            scheduler: SchedulerState::new_ptr(),
+            now: 0,
+            interval: millis(workload.schedule_interval).max(1),
+            horizon: millis(workload.horizon),
+            next_cycle: 0,
+            arrivals: arrivals.into(),
+            executors,
+            tasks: HashMap::new(),
+            remaining: HashMap::new(),
+            weights,
+            records: vec![],
+        })
+    }
+
+    /// Advances the virtual clock from an event to the next one until all the sessions are
+    /// completed; the events at the same time are handled in the order of the completions,
+    /// the arrivals, the scheduling and the executors.
+    fn run(mut self) -> Result<Report, FlameError> {
+        loop {
+            self.complete_tasks()?;
+            self.arrive()?;
+            if self.now >= self.next_cycle {
+                scheduler::schedule(self.storage.clone(), self.scheduler.clone())?;
+                self.next_cycle += self.interval;
+            }
+            self.step_executors()?;
+
+            if self.arrivals.is_empty() && self.remaining.is_empty() {
+                break;
+            }
+
+            let next = self
+                .executors
+                .iter()
+                .filter_map(|exec| exec.running.map(|(_, completion)| completion))
+                .chain(self.arrivals.front().map(|a| a.time))
+                .fold(self.next_cycle, u64::min);
+            if next > self.horizon {
+                return Err(FlameError::Internal(format!(
+                    "the workload was not completed in {}s",
+                    self.horizon / 1000
+                )));
+            }
+            self.now = next;
+        }
+
+        self.records.sort_by_key(|r| (r.submitted, r.launched));
+        Ok(Report::new(&self.records, &self.weights))
+    }
+
+    fn arrive(&mut self) -> Result<(), FlameError> {
+        while self.arrivals.front().is_some_and(|a| a.time <= self.now) {
+            let Some(arrival) = self.arrivals.pop_front() else {
+                break;
+            };
+
+            let ssn = self
+                .runtime
+                .block_on(self.storage.create_session(SessionAttributes {
+                    application: arrival.application.clone(),
+                    slots: 1,
+                    ..Default::default()
+                }))?;
+            for duration in arrival.durations {
+                let attrs = TaskAttributes {
+                    labels: arrival.labels.clone(),
+                    ..Default::default()
+                };
+                let task = self
+                    .runtime
+                    .block_on(self.storage.create_task(ssn.id, attrs))?;
+                self.tasks.insert(
+                    task.gid(),
+                    SubmittedTask {
+                        application: arrival.application.clone(),
+                        submitted: self.now,
+                        duration,
+                        launched: None,
+                    },
+                );
+                *self.remaining.entry(ssn.id).or_default() += 1;
+            }
+
+            if !self.remaining.contains_key(&ssn.id) {
+                self.runtime.block_on(self.storage.close_session(ssn.id))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Completes the tasks due, and closes their sessions once all the tasks are completed.
+    fn complete_tasks(&mut self) -> Result<(), FlameError> {
+        for exec in &mut self.executors {
+            let Some((gid, completion)) = exec.running else {
+                continue;
+            };
+            if completion > self.now {
+                continue;
+            }
+
+            self.runtime.block_on(self.storage.complete_task(
+                exec.id.clone(),
+                Some(gid),
+                None,
+                None,
+            ))?;
+            exec.running = None;
+
+            let task = self
+                .tasks
+                .remove(&gid)
+                .ok_or(FlameError::NotFound(gid.to_string()))?;
+            self.records.push(TaskRecord {
+                application: task.application,
+                submitted: task.submitted,
+                launched: task.launched.unwrap_or(task.submitted),
+                completed: self.now,
+            });
+
+            let remaining = self.remaining.entry(gid.ssn_id).or_default();
+            *remaining = remaining.saturating_sub(1);
+            if *remaining == 0 {
+                self.remaining.remove(&gid.ssn_id);
+                self.runtime
+                    .block_on(self.storage.close_session(gid.ssn_id))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves the executors not running a task by the states of the storage as the executor
+    /// manager does: the bindings are completed at once, the next task is launched, and the
+    /// executors are unbound if no task is left.
+    fn step_executors(&mut self) -> Result<(), FlameError> {
+        for exec in &mut self.executors {
+            if exec.running.is_some() {
+                continue;
+            }
+
+            let state = self.storage.get_executor(exec.id.clone())?.state;
+            if state == ExecutorState::Binding {
+                self.runtime.block_on(self.storage.bind_session_completed(
+                    exec.id.clone(),
+                    None,
+                    None,
+                ))?;
+            }
+            if !matches!(state, ExecutorState::Binding | ExecutorState::Bound) {
+                if state == ExecutorState::Unbinding {
+                    unbind(&self.runtime, &self.storage, &exec.id)?;
+                }
+                continue;
+            }
+
+            match self
+                .runtime
+                .block_on(self.storage.launch_task(exec.id.clone()))?
+            {
+                Some(task) => {
+                    let gid = task.gid();
+                    let submitted = self
+                        .tasks
+                        .get_mut(&gid)
+                        .ok_or(FlameError::NotFound(gid.to_string()))?;
+                    submitted.launched = Some(self.now);
+                    exec.running = Some((gid, self.now + submitted.duration));
+                }
+                None => unbind(&self.runtime, &self.storage, &exec.id)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn unbind(runtime: &Runtime, storage: &StoragePtr, id: &ExecutorID) -> Result<(), FlameError> {
+    runtime.block_on(async {
+        storage.unbind_executor(id.clone(), None).await?;
+        storage.unbind_executor_completed(id.clone(), None).await
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay(yaml: &str) -> Result<String, FlameError> {
+        run(&Workload::from_yaml(yaml)?)?.to_yaml()
+    }
+
+    #[test]
+    fn test_golden_reports() -> Result<(), FlameError> {
+        let workloads = [
+            (
+                include_str!("../../tests/workloads/burst.yaml"),
+                include_str!("../../tests/golden/burst.yaml"),
+            ),
+            (
+                include_str!("../../tests/workloads/weighted.yaml"),
+                include_str!("../../tests/golden/weighted.yaml"),
+            ),
+        ];
+
+        // The workloads are replayed the same way every time.
+        for (workload, golden) in workloads {
+            for _ in 0..3 {
+                assert_eq!(replay(workload)?, golden);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_executor_locality_keys() -> Result<(), FlameError> {
+        let yaml = concat!(
+            "executors:\n",
+            "- {name: near, count: 2, locality_keys: [shard-1, shard-2]}\n",
+            "- {name: far, count: 1}\n",
+            "sessions:\n",
+            "- {application: a, tasks: 3, duration: {distribution: constant, seconds: 1}}\n",
+        );
+        let workload = Workload::from_yaml(yaml)?;
+        let sim = Simulation::new(&workload)?;
+
+        let mut keys: Vec<_> = sim
+            .storage
+            .list_executor()?
+            .into_iter()
+            .map(|e| (e.id, e.locality_keys))
+            .collect();
+        keys.sort();
+        let shards = vec!["shard-1".to_string(), "shard-2".to_string()];
+        assert_eq!(
+            keys,
+            vec![
+                ("far-0".to_string(), vec![]),
+                ("near-0".to_string(), shards.clone()),
+                ("near-1".to_string(), shards),
+            ]
+        );
+
+        assert_eq!(sim.run()?.tasks, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_workload() {
+        let yaml = |executors: u32, duration: &str| {
+            format!(
+                "executors: [{{count: {}}}]\nsessions:\n- {{application: a, tasks: 1, duration: {}}}",
+                executors, duration
+            )
+        };
+
+        assert!(replay(&yaml(1, "{distribution: constant, seconds: 1}")).is_ok());
+        assert!(replay(&yaml(0, "{distribution: constant, seconds: 1}")).is_err());
+        assert!(replay(&yaml(1, "{distribution: uniform, min: 2, max: 1}")).is_err());
+        assert!(replay(&yaml(1, "{distribution: normal, mean: 1}")).is_err());
+    }
+}
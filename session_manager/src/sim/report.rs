@@ -0,0 +1,168 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use common::FlameError;
+
+/// The times of a task in the virtual milliseconds.
+#[derive(Clone, Debug)]
+pub struct TaskRecord {
+    pub application: String,
+    pub submitted: u64,
+    pub launched: u64,
+    pub completed: u64,
+}
+
+/// The outcome of a simulation; the times are in seconds.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Report {
+    /// From the first arrival to the last completion.
+    pub makespan: f64,
+    pub tasks: usize,
+    /// From the submission of the tasks to their launch.
+    pub queue_time: Percentiles,
+    /// The Jain's index of the slowdowns of the applications, 1.0 if they're all slowed
+    /// down the same.
+    pub fairness: f64,
+    pub applications: BTreeMap<String, AppReport>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AppReport {
+    pub tasks: usize,
+    /// The share of the executors the application is entitled to by its weight.
+    pub weight_share: f64,
+    /// The share of the busy time of the executors taken by the tasks of the application.
+    pub usage_share: f64,
+    pub queue_time: Percentiles,
+    /// The mean of the turnaround times of the tasks over their durations.
+    pub slowdown: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Report {
+    pub fn new(records: &[TaskRecord], weights: &BTreeMap<String, u32>) -> Self {
+        let start = records
+            .iter()
+            .map(|r| r.submitted)
+            .min()
+            .unwrap_or_default();
+        let end = records
+            .iter()
+            .map(|r| r.completed)
+            .max()
+            .unwrap_or_default();
+        let busy: u64 = records.iter().map(|r| r.completed - r.launched).sum();
+        let total_weight: u32 = weights.values().sum();
+
+        let mut applications = BTreeMap::new();
+        for (name, weight) in weights {
+            let app_records: Vec<_> = records.iter().filter(|r| &r.application == name).collect();
+            let app_busy: u64 = app_records.iter().map(|r| r.completed - r.launched).sum();
+            let slowdowns: Vec<_> = app_records.iter().map(|r| slowdown(r)).collect();
+
+            applications.insert(
+                name.clone(),
+                AppReport {
+                    tasks: app_records.len(),
+                    weight_share: round(*weight as f64 / total_weight.max(1) as f64),
+                    usage_share: round(app_busy as f64 / busy.max(1) as f64),
+                    queue_time: Percentiles::new(app_records.iter().map(|r| queue_time(r))),
+                    slowdown: round(mean(&slowdowns)),
+                },
+            );
+        }
+
+        let slowdowns: Vec<_> = applications
+            .values()
+            .filter(|app| app.tasks > 0)
+            .map(|app| app.slowdown)
+            .collect();
+
+        Report {
+            makespan: seconds(end - start),
+            tasks: records.len(),
+            queue_time: Percentiles::new(records.iter().map(queue_time)),
+            fairness: round(jain(&slowdowns)),
+            applications,
+        }
+    }
+
+    pub fn to_yaml(&self) -> Result<String, FlameError> {
+        serde_yaml::to_string(self).map_err(|e| FlameError::Internal(e.to_string()))
+    }
+}
+
+impl Percentiles {
+    /// The nearest-rank percentiles of the times in milliseconds.
+    fn new(times: impl Iterator<Item = u64>) -> Self {
+        let mut times: Vec<_> = times.collect();
+        times.sort_unstable();
+        let rank = |p: usize| match times.len() {
+            0 => 0,
+            n => times[((p * n).div_ceil(100)).clamp(1, n) - 1],
+        };
+
+        Percentiles {
+            p50: seconds(rank(50)),
+            p90: seconds(rank(90)),
+            p99: seconds(rank(99)),
+            max: seconds(rank(100)),
+        }
+    }
+}
+
+fn queue_time(r: &TaskRecord) -> u64 {
+    r.launched - r.submitted
+}
+
+/// The tasks completed at once are slowed down by their queue times only.
+fn slowdown(r: &TaskRecord) -> f64 {
+    let duration = (r.completed - r.launched).max(1);
+    (r.completed - r.submitted).max(1) as f64 / duration as f64
+}
+
+fn mean(xs: &[f64]) -> f64 {
+    match xs.len() {
+        0 => 0.0,
+        n => xs.iter().sum::<f64>() / n as f64,
+    }
+}
+
+fn jain(xs: &[f64]) -> f64 {
+    let squares: f64 = xs.iter().map(|x| x * x).sum();
+    if squares == 0.0 {
+        return 1.0;
+    }
+
+    xs.iter().sum::<f64>().powi(2) / (xs.len() as f64 * squares)
+}
+
+fn seconds(ms: u64) -> f64 {
+    ms as f64 / 1000.0
+}
+
+/// The values are rounded, so the reports are the same across the platforms.
+fn round(v: f64) -> f64 {
+    (v * 1000.0).round() / 1000.0
+}
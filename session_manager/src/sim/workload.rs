@@ -0,0 +1,179 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use common::FlameError;
+
+/// The synthetic workload replayed by the simulation; the times are in seconds from the
+/// start of the simulation.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Workload {
+    /// The seed of the task durations, so a workload is always replayed the same way.
+    #[serde(default)]
+    pub seed: u64,
+    /// The interval of the scheduling cycles.
+    #[serde(default = "default_schedule_interval")]
+    pub schedule_interval: f64,
+    /// The simulation fails if the workload is not completed by then.
+    #[serde(default = "default_horizon")]
+    pub horizon: f64,
+    #[serde(default)]
+    pub applications: Vec<ApplicationSpec>,
+    pub executors: Vec<ExecutorSpec>,
+    pub sessions: Vec<SessionSpec>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApplicationSpec {
+    pub name: String,
+    pub weight: Option<u32>,
+}
+
+/// A group of the same executors, which are named `<name>-<index>`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExecutorSpec {
+    #[serde(default = "default_executor_name")]
+    pub name: String,
+    pub count: u32,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// The locality keys the executors have, e.g. the shards cached on them.
+    #[serde(default)]
+    pub locality_keys: Vec<String>,
+}
+
+/// The sessions of an application submitting all their tasks once they arrive; the session
+/// is repeated `count` times, every `every` seconds.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SessionSpec {
+    pub application: String,
+    #[serde(default)]
+    pub arrival: f64,
+    #[serde(default = "default_count")]
+    pub count: u32,
+    #[serde(default)]
+    pub every: f64,
+    pub tasks: u32,
+    pub duration: DurationSpec,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+/// The distribution of the durations of the tasks in seconds.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "distribution", rename_all = "lowercase", deny_unknown_fields)]
+pub enum DurationSpec {
+    Constant { seconds: f64 },
+    Uniform { min: f64, max: f64 },
+    Exponential { mean: f64 },
+}
+
+fn default_schedule_interval() -> f64 {
+    1.0
+}
+
+fn default_horizon() -> f64 {
+    86400.0
+}
+
+fn default_executor_name() -> String {
+    "exec".to_string()
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+impl Workload {
+    pub fn from_yaml(yaml: &str) -> Result<Self, FlameError> {
+        let workload: Workload =
+            serde_yaml::from_str(yaml).map_err(|e| FlameError::InvalidConfig(e.to_string()))?;
+        workload.validate()?;
+
+        Ok(workload)
+    }
+
+    fn validate(&self) -> Result<(), FlameError> {
+        let invalid =
+            |field: &str, message: &str| Err(FlameError::invalid_argument(field, message));
+
+        if self.schedule_interval <= 0.0 {
+            return invalid("schedule_interval", "must be positive");
+        }
+        if self.executors.iter().all(|e| e.count == 0) {
+            return invalid("executors", "no executor in the fleet");
+        }
+        for ssn in &self.sessions {
+            if ssn.arrival < 0.0 || ssn.every < 0.0 {
+                return invalid("sessions", "the arrival must not be negative");
+            }
+            let valid = match ssn.duration {
+                DurationSpec::Constant { seconds } => seconds >= 0.0,
+                DurationSpec::Uniform { min, max } => 0.0 <= min && min <= max,
+                DurationSpec::Exponential { mean } => mean > 0.0,
+            };
+            if !valid {
+                return invalid("duration", "invalid distribution of the task durations");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl DurationSpec {
+    /// Samples a duration in milliseconds.
+    pub fn sample(&self, rng: &mut Rng) -> u64 {
+        let seconds = match *self {
+            DurationSpec::Constant { seconds } => seconds,
+            DurationSpec::Uniform { min, max } => min + (max - min) * rng.next_f64(),
+            DurationSpec::Exponential { mean } => -mean * (1.0 - rng.next_f64()).ln(),
+        };
+
+        millis(seconds)
+    }
+}
+
+pub fn millis(seconds: f64) -> u64 {
+    (seconds * 1000.0).round() as u64
+}
+
+/// The SplitMix64 generator; it's self-contained, so the durations of a seed never change
+/// with the dependencies.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
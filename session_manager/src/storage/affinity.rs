@@ -0,0 +1,110 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::{DateTime, Utc};
+
+use common::apis::{ExecutorID, SessionID};
+
+/// The sessions remembered by each executor; the least recently served one is forgotten.
+const MAX_SERVED_SESSIONS: usize = 8;
+
+/// The bindings to an executor which served the session before, and those to a stranger
+/// while another executor served it before; the first binding of a session is neither.
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+pub fn hits() -> u64 {
+    HITS.load(Ordering::Relaxed)
+}
+
+pub fn misses() -> u64 {
+    MISSES.load(Ordering::Relaxed)
+}
+
+/// The sessions served recently by each executor, which are kept in memory only, so the
+/// scheduler prefers the executors which may still have the data of the session, e.g. the
+/// ones downloaded by `on_session_enter`.
+#[derive(Debug, Default)]
+pub struct Affinity {
+    executors: HashMap<ExecutorID, VecDeque<(SessionID, DateTime<Utc>)>>,
+}
+
+impl Affinity {
+    /// Records the executor was bound to the session, and counts whether it served the
+    /// session before.
+    pub fn record(&mut self, id: &ExecutorID, ssn_id: SessionID, now: DateTime<Utc>) {
+        let served_before = |served: &VecDeque<(SessionID, DateTime<Utc>)>| {
+            served.iter().any(|(s, _)| *s == ssn_id)
+        };
+        if self.executors.get(id).is_some_and(served_before) {
+            HITS.fetch_add(1, Ordering::Relaxed);
+        } else if self.executors.values().any(served_before) {
+            MISSES.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let served = self.executors.entry(id.clone()).or_default();
+        served.retain(|(s, _)| *s != ssn_id);
+        served.push_front((ssn_id, now));
+        served.truncate(MAX_SERVED_SESSIONS);
+    }
+
+    /// Forgets the sessions served by the executor, e.g. it's registered again.
+    pub fn forget(&mut self, id: &ExecutorID) {
+        self.executors.remove(id);
+    }
+
+    /// The sessions served by the executor and when, the most recent first.
+    pub fn served(&self, id: &ExecutorID) -> Vec<(SessionID, DateTime<Utc>)> {
+        self.executors
+            .get(id)
+            .map(|served| served.iter().copied().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_affinity() {
+        let mut affinity = Affinity::default();
+        let (a, b) = ("a".to_string(), "b".to_string());
+        let now = Utc::now();
+
+        // The first bindings of the sessions are neither hits nor misses.
+        let (hits0, misses0) = (hits(), misses());
+        for ssn_id in 1..=MAX_SERVED_SESSIONS as SessionID {
+            affinity.record(&a, ssn_id, now);
+        }
+        assert_eq!(affinity.served(&a).len(), MAX_SERVED_SESSIONS);
+        assert_eq!(affinity.served(&a)[0].0, MAX_SERVED_SESSIONS as SessionID);
+
+        affinity.record(&a, 1, now);
+        affinity.record(&b, 2, now);
+        assert!(hits() > hits0);
+        assert!(misses() > misses0);
+        assert_eq!(affinity.served(&a)[0].0, 1);
+
+        // The least recently served session is forgotten.
+        affinity.record(&a, 100, now);
+        assert!(!affinity.served(&a).iter().any(|(s, _)| *s == 2));
+        assert_eq!(affinity.served(&a).len(), MAX_SERVED_SESSIONS);
+
+        affinity.forget(&a);
+        assert!(affinity.served(&a).is_empty());
+    }
+}
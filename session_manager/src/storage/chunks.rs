@@ -0,0 +1,127 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::VecDeque;
+
+use common::apis::OutputChunk;
+
+/// The bytes of the chunks kept in memory for each running task; the older ones are spilled
+/// to the engine.
+const MAX_BUFFERED_BYTES: usize = 4 << 20;
+
+/// The latest chunks of the output of a running task, which are read by the clients following
+/// the task without the engine. A chunk is dropped only after it's spilled, so the chunks are
+/// always found either here or in the engine.
+#[derive(Debug, Default)]
+pub struct ChunkBuffer {
+    chunks: VecDeque<OutputChunk>,
+    bytes: usize,
+}
+
+impl ChunkBuffer {
+    pub fn push(&mut self, chunk: OutputChunk) {
+        self.bytes += chunk.data.len();
+        self.chunks.push_back(chunk);
+    }
+
+    /// The oldest chunks over `MAX_BUFFERED_BYTES`, which are to be spilled; the latest chunk
+    /// is always kept.
+    pub fn overflow(&self) -> Vec<OutputChunk> {
+        let mut bytes = self.bytes;
+        self.chunks
+            .iter()
+            .take(self.chunks.len().saturating_sub(1))
+            .take_while(|c| {
+                let over = bytes > MAX_BUFFERED_BYTES;
+                bytes -= c.data.len();
+                over
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Drops the chunks up to the sequence, once they're spilled.
+    pub fn release(&mut self, sequence: u64) {
+        while let Some(chunk) = self.chunks.front() {
+            if chunk.sequence > sequence {
+                break;
+            }
+            self.bytes -= chunk.data.len();
+            self.chunks.pop_front();
+        }
+    }
+
+    /// The sequence of the oldest chunk in memory.
+    pub fn first(&self) -> Option<u64> {
+        self.chunks.front().map(|c| c.sequence)
+    }
+
+    /// At most `limit` chunks after the sequence, in order.
+    pub fn since(&self, since: u64, limit: usize) -> Vec<OutputChunk> {
+        self.chunks
+            .iter()
+            .filter(|c| c.sequence > since)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bytes::Bytes;
+    use chrono::Utc;
+
+    fn chunk(sequence: u64, len: usize) -> OutputChunk {
+        OutputChunk {
+            sequence,
+            data: Bytes::from(vec![0; len]),
+            creation_time: Utc::now(),
+        }
+    }
+
+    fn sequences(chunks: &[OutputChunk]) -> Vec<u64> {
+        chunks.iter().map(|c| c.sequence).collect()
+    }
+
+    #[test]
+    fn test_chunk_buffer() {
+        let mut buffer = ChunkBuffer::default();
+        assert_eq!(buffer.first(), None);
+        assert!(buffer.overflow().is_empty());
+
+        // 4 chunks of 1MiB fit in the buffer, the 5th overflows the 1st.
+        for sequence in 1..=4 {
+            buffer.push(chunk(sequence, 1 << 20));
+        }
+        assert!(buffer.overflow().is_empty());
+        buffer.push(chunk(5, 1 << 20));
+        assert_eq!(sequences(&buffer.overflow()), vec![1]);
+
+        // The chunks are kept until they're released.
+        assert_eq!(buffer.first(), Some(1));
+        assert_eq!(sequences(&buffer.since(2, 2)), vec![3, 4]);
+        buffer.release(1);
+        assert_eq!(buffer.first(), Some(2));
+        assert!(buffer.overflow().is_empty());
+
+        // The latest chunk is kept even if it's over the buffer by itself.
+        buffer.push(chunk(6, 5 << 20));
+        assert_eq!(sequences(&buffer.overflow()), vec![2, 3, 4, 5]);
+        buffer.release(5);
+        assert_eq!(sequences(&buffer.since(0, usize::MAX)), vec![6]);
+        assert!(buffer.overflow().is_empty());
+    }
+}
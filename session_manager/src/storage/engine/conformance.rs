@@ -0,0 +1,1889 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The conformance tests of the engines, which are the contract of `Engine`:
+//!
+//! * the ids of the sessions are unique and never reused; the ids of the tasks start from 1
+//!   in each session, and their indexes start from 0 and are never reused in the session;
+//! * the sessions and tasks are returned in the order of their ids, and the applications
+//!   in the order of their names;
+//! * the missing objects are `NotFound`, the objects in an unexpected state are
+//!   `FailedPrecondition`, and the duplicated applications are `AlreadyExists`;
+//! * deleting a session deletes its tasks, its events, and its cached outputs which are not
+//!   shared;
+//! * the cached outputs are found by their digests in the same session, or in the shared
+//!   outputs of the application in the namespace; the expired and the oldest outputs over
+//!   the limit are removed when an output is cached;
+//! * the events of a session are found in the order they were put, and the oldest ones over
+//!   the limit of the session are removed when the events are put;
+//! * the output chunks of a task are found in the order of their sequences, the chunks of
+//!   the same sequences are replaced, and the chunks are deleted with their tasks;
+//! * the deadline of a session is only updated while it's open;
+//! * the annotations of a task are kept by its retries;
+//! * the batched state changes of the tasks are applied in order, and the changes of the
+//!   missing tasks are skipped;
+//! * the imported sessions are closed with new ids, and keep the timestamps and tasks of
+//!   the archived ones;
+//! * the data is found after a restart, which is how the session manager recovers.
+//!
+//! A new engine opts in by declaring its differences in `Engine::capabilities`, e.g. it's
+//! not persistent, and calling `verify` in its tests with a function connecting to the
+//! engine, see `test_conformance` of the sqlite engine. The function is called once per
+//! scenario with the name of the scenario, which should get an empty database, and called
+//! again with the same name to restart the engine.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use chrono::{Duration, Utc};
+use futures::future::BoxFuture;
+
+use common::apis::{
+    Application, CacheScope, EventKind, FailureReason, OutputChunk, Session, SessionAttributes,
+    SessionEvent, SessionID, SessionState, Shim, Task, TaskAttributes, TaskFailure, TaskGID,
+    TaskID, TaskState, DEFAULT_NAMESPACE,
+};
+use common::FlameError;
+
+use crate::storage::engine::{CacheKey, CachedOutput, EnginePtr, TaskUpdate};
+
+type ConnectFn =
+    Arc<dyn Fn(String) -> BoxFuture<'static, Result<EnginePtr, FlameError>> + Send + Sync>;
+
+macro_rules! assert_err {
+    ( $res:expr, $pat:pat ) => {{
+        let res = $res;
+        assert!(matches!(res, Err($pat)), "unexpected {:?}", res.map(|_| ()));
+    }};
+}
+
+/// Runs all the scenarios against the engine, and fails with the failed ones.
+pub async fn verify<F, Fut>(connect: F)
+where
+    F: Fn(String) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<EnginePtr, FlameError>> + Send + 'static,
+{
+    let connect: ConnectFn = Arc::new(move |name| Box::pin(connect(name)));
+    let mut failures = vec![];
+
+    macro_rules! run {
+        ( $( $scenario:ident ),* $(,)? ) => {
+            $(
+                if let Err(e) = run(&connect, stringify!($scenario), $scenario).await {
+                    failures.push(format!("{}: {}", stringify!($scenario), e));
+                }
+            )*
+        };
+    }
+
+    run!(
+        create_session,
+        get_session,
+        get_missing_session,
+        session_ids_are_unique,
+        session_ids_are_not_reused,
+        close_session,
+        close_session_with_open_tasks,
+        close_missing_session,
+        update_session_deadline,
+        update_deadline_of_closed_session,
+        update_session_activity,
+        update_session_annotations,
+        open_closed_session,
+        open_missing_session,
+        delete_closed_session,
+        delete_open_session,
+        delete_missing_session,
+        delete_session_with_tasks,
+        find_sessions,
+        import_session,
+        import_session_with_duplicated_tasks,
+        create_task,
+        task_ids_per_session,
+        task_indexes,
+        create_task_in_closed_session,
+        create_task_in_missing_session,
+        get_missing_task,
+        update_task_state,
+        update_missing_task,
+        update_task_placement,
+        update_task_failure,
+        update_task_output,
+        update_task_annotations,
+        update_tasks,
+        retry_task,
+        retry_missing_task,
+        resubmit_task,
+        resubmit_task_in_closed_session,
+        delete_task,
+        find_tasks,
+        batch_tasks,
+        cached_outputs,
+        cached_outputs_are_bounded,
+        delete_session_with_cached_outputs,
+        session_events,
+        session_events_are_bounded,
+        delete_session_with_events,
+        output_chunks,
+        delete_task_with_output_chunks,
+        delete_session_with_output_chunks,
+        concurrent_sessions,
+        concurrent_tasks,
+        register_application,
+        delete_application,
+        find_applications,
+        restart_and_find_sessions,
+        restart_and_retry_running_tasks,
+        restart_and_create,
+        restart_and_find_applications,
+        restart_and_find_imported_session,
+        restart_and_find_cached_outputs,
+        restart_and_find_events,
+    );
+
+    assert!(
+        failures.is_empty(),
+        "{} scenarios failed:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
+
+/// Runs the scenario with a new engine; the panics of the assertions are reported as the
+/// failures of the scenario.
+async fn run<F, Fut>(connect: &ConnectFn, name: &str, scenario: F) -> Result<(), String>
+where
+    F: FnOnce(Scenario) -> Fut,
+    Fut: Future<Output = Result<(), FlameError>> + Send + 'static,
+{
+    let engine = connect(name.to_string()).await.map_err(|e| e.to_string())?;
+    let scenario = scenario(Scenario {
+        name: name.to_string(),
+        engine,
+        connect: connect.clone(),
+    });
+
+    match tokio::spawn(scenario).await {
+        Ok(res) => res.map_err(|e| e.to_string()),
+        Err(e) if e.is_panic() => {
+            let panic = e.into_panic();
+            let msg = panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or(panic.downcast_ref::<&str>().map(|s| s.to_string()));
+            Err(msg.unwrap_or("panicked".to_string()))
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+struct Scenario {
+    name: String,
+    engine: EnginePtr,
+    connect: ConnectFn,
+}
+
+impl Scenario {
+    fn persistent(&self) -> bool {
+        self.engine.capabilities().persistent
+    }
+
+    async fn restart(&mut self) -> Result<(), FlameError> {
+        self.engine = (self.connect)(self.name.clone()).await?;
+        Ok(())
+    }
+
+    async fn session(&self) -> Result<Session, FlameError> {
+        self.engine
+            .create_session(SessionAttributes {
+                application: "flmexec".to_string(),
+                ..Default::default()
+            })
+            .await
+    }
+
+    async fn closed_session(&self) -> Result<Session, FlameError> {
+        let ssn = self.session().await?;
+        self.engine.close_session(ssn.id).await
+    }
+
+    async fn task(&self, ssn: &Session) -> Result<Task, FlameError> {
+        self.engine
+            .create_task(ssn.id, TaskAttributes::default())
+            .await
+    }
+
+    /// A closed session with a succeeded and a failed task, as it's exported; the session
+    /// is deleted, so only the archive is left.
+    async fn archived_session(&self) -> Result<(Session, Vec<Task>), FlameError> {
+        let labels = HashMap::from([("env".to_string(), "dev".to_string())]);
+        let ssn = self
+            .engine
+            .create_session(SessionAttributes {
+                namespace: "team-a".to_string(),
+                application: "flmlog".to_string(),
+                slots: 2,
+                labels,
+                common_data: Some(Bytes::from("common data")),
+                config: HashMap::from([("model".to_string(), "v1".to_string())]),
+                max_pending_tasks: Some(10),
+                task_timeout: Some(600),
+                max_task_retries: Some(2),
+                ..Default::default()
+            })
+            .await?;
+        let mut tasks = vec![];
+        for (input, state) in [("a", TaskState::Succeed), ("b", TaskState::Failed)] {
+            let labels = HashMap::from([("zone".to_string(), input.to_string())]);
+            let attrs = TaskAttributes {
+                input: Some(Bytes::from(input)),
+                labels,
+                locality: Some(format!("shard-{}", input)),
+                env: HashMap::from([("SEED".to_string(), input.to_string())]),
+                timeout: Some(600),
+                max_retries: Some(2),
+                ..Default::default()
+            };
+            let task = self.engine.create_task(ssn.id, attrs).await?;
+            let task = self.engine.update_task_state(task.gid(), state).await?;
+            tasks.push(Task {
+                output: Some(Bytes::from(format!("output of {}", input))),
+                ..task
+            });
+        }
+        let ssn = self.engine.close_session(ssn.id).await?;
+        self.engine.delete_session(ssn.id).await?;
+
+        Ok((ssn, tasks))
+    }
+}
+
+fn missing_task() -> TaskGID {
+    TaskGID {
+        ssn_id: 1000,
+        task_id: 1,
+    }
+}
+
+fn application(name: &str) -> Application {
+    Application {
+        name: name.to_string(),
+        command: format!("/usr/bin/{}", name),
+        ..Default::default()
+    }
+}
+
+/// The key of an output of the application flmexec in the namespace team-a.
+fn cache_key(digest: &str, ssn_id: SessionID, shared: bool) -> CacheKey {
+    CacheKey {
+        digest: digest.to_string(),
+        namespace: "team-a".to_string(),
+        application: "flmexec".to_string(),
+        ssn_id,
+        shared,
+    }
+}
+
+/// The output cached `age` seconds ago; the timestamps are kept in seconds by the engines.
+fn cached_output(key: &CacheKey, output: &str, age: i64) -> CachedOutput {
+    CachedOutput {
+        key: key.clone(),
+        output: Some(Bytes::from(output.to_string())),
+        creation_time: chrono::DateTime::<Utc>::from_timestamp(Utc::now().timestamp() - age, 0)
+            .unwrap_or_default(),
+    }
+}
+
+/// The event `sequence` of a task, which happened `age` seconds ago.
+fn event(sequence: u64, age: i64) -> SessionEvent {
+    SessionEvent {
+        sequence,
+        event_time: deadline(-age),
+        kind: EventKind::TaskStateChanged {
+            task_id: sequence as TaskID,
+            state: TaskState::Running,
+        },
+    }
+}
+
+fn chunk(sequence: u64, data: &'static str) -> OutputChunk {
+    OutputChunk {
+        sequence,
+        data: Bytes::from_static(data.as_bytes()),
+        creation_time: deadline(0),
+    }
+}
+
+fn sequences(events: &[SessionEvent]) -> Vec<u64> {
+    events.iter().map(|e| e.sequence).collect()
+}
+
+/// The deadline `secs` seconds later; the timestamps are kept in seconds by the engines.
+fn deadline(secs: i64) -> chrono::DateTime<Utc> {
+    chrono::DateTime::<Utc>::from_timestamp(Utc::now().timestamp() + secs, 0).unwrap_or_default()
+}
+
+fn assert_recent(time: chrono::DateTime<Utc>) {
+    let now = Utc::now();
+    assert!(
+        time <= now && now - time < Duration::minutes(1),
+        "unexpected time {}",
+        time
+    );
+}
+
+async fn create_session(s: Scenario) -> Result<(), FlameError> {
+    let labels = HashMap::from([("env".to_string(), "dev".to_string())]);
+    let ssn = s
+        .engine
+        .create_session(SessionAttributes {
+            namespace: "team-a".to_string(),
+            application: "flmlog".to_string(),
+            slots: 3,
+            labels: labels.clone(),
+            common_data: Some(Bytes::from("common data")),
+            config: HashMap::from([("model".to_string(), "v1".to_string())]),
+            max_pending_tasks: Some(100),
+            max_running_tasks: Some(4),
+            cache_scope: CacheScope::Application,
+            deadline: Some(deadline(60)),
+            task_timeout: Some(600),
+            max_task_retries: Some(2),
+            cloned_from: Some(7),
+            priority_class: Some("interactive".to_string()),
+            latest_application: false,
+            application_spec: Some(Application {
+                name: "flmlog".to_string(),
+                shim: Shim::Log,
+                arguments: vec!["--model".to_string(), "v1".to_string()],
+                ..Default::default()
+            }),
+        })
+        .await?;
+
+    assert_eq!(ssn.namespace, "team-a");
+    assert_eq!(ssn.application, "flmlog");
+    assert_eq!(ssn.slots, 3);
+    assert_eq!(ssn.labels, labels);
+    assert_eq!(ssn.common_data, Some(Bytes::from("common data")));
+    assert_eq!(ssn.config["model"], "v1");
+    assert_eq!(ssn.cloned_from, Some(7));
+    let found = s.engine.get_session(ssn.id).await?;
+    assert_eq!(found.config, ssn.config);
+    assert_eq!(found.cloned_from, Some(7));
+    assert_eq!(found.priority_class.as_deref(), Some("interactive"));
+    assert_eq!(found.application_spec, ssn.application_spec);
+    assert_eq!(
+        found.application_spec.map(|app| app.arguments),
+        Some(vec!["--model".to_string(), "v1".to_string()])
+    );
+    assert_eq!(ssn.max_pending_tasks, Some(100));
+    assert_eq!(found.max_running_tasks, Some(4));
+    assert_eq!(ssn.cache_scope, CacheScope::Application);
+    assert_eq!(ssn.deadline, Some(deadline(60)));
+    assert_eq!(
+        (ssn.task_timeout, ssn.max_task_retries),
+        (Some(600), Some(2))
+    );
+    assert_eq!(ssn.status.state, SessionState::Open);
+    assert_recent(ssn.creation_time);
+    assert!(ssn.completion_time.is_none());
+    assert!(ssn.tasks.is_empty());
+
+    Ok(())
+}
+
+async fn get_session(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let got = s.engine.get_session(ssn.id).await?;
+
+    assert_eq!(got.id, ssn.id);
+    assert_eq!(got.namespace, DEFAULT_NAMESPACE);
+    assert_eq!(got.application, ssn.application);
+    assert_eq!(got.labels, ssn.labels);
+    assert_eq!(got.common_data, None);
+    assert_eq!(got.max_pending_tasks, None);
+    assert_eq!(got.creation_time, ssn.creation_time);
+    assert_eq!(got.status.state, SessionState::Open);
+
+    Ok(())
+}
+
+async fn get_missing_session(s: Scenario) -> Result<(), FlameError> {
+    assert_err!(s.engine.get_session(1000).await, FlameError::NotFound(_));
+    Ok(())
+}
+
+async fn session_ids_are_unique(s: Scenario) -> Result<(), FlameError> {
+    let mut ids = vec![];
+    for _ in 0..10 {
+        ids.push(s.session().await?.id);
+    }
+
+    assert!(ids.windows(2).all(|w| w[0] < w[1]), "{:?}", ids);
+    Ok(())
+}
+
+async fn session_ids_are_not_reused(s: Scenario) -> Result<(), FlameError> {
+    s.session().await?;
+    let ssn = s.closed_session().await?;
+    s.engine.delete_session(ssn.id).await?;
+
+    assert!(s.session().await?.id > ssn.id);
+    Ok(())
+}
+
+async fn close_session(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let task = s.task(&ssn).await?;
+    s.engine
+        .update_task_state(task.gid(), TaskState::Failed)
+        .await?;
+
+    let ssn = s.engine.close_session(ssn.id).await?;
+    assert_eq!(ssn.status.state, SessionState::Closed);
+    assert_recent(ssn.completion_time.expect("no completion time"));
+
+    let got = s.engine.get_session(ssn.id).await?;
+    assert_eq!(got.status.state, SessionState::Closed);
+    assert_eq!(got.completion_time, ssn.completion_time);
+
+    Ok(())
+}
+
+async fn close_session_with_open_tasks(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let task = s.task(&ssn).await?;
+
+    for state in [TaskState::Pending, TaskState::Running] {
+        s.engine.update_task_state(task.gid(), state).await?;
+        assert_err!(
+            s.engine.close_session(ssn.id).await,
+            FlameError::FailedPrecondition(_)
+        );
+    }
+    assert_eq!(
+        s.engine.get_session(ssn.id).await?.status.state,
+        SessionState::Open
+    );
+
+    s.engine
+        .update_task_state(task.gid(), TaskState::Succeed)
+        .await?;
+    s.engine.close_session(ssn.id).await?;
+
+    Ok(())
+}
+
+async fn close_missing_session(s: Scenario) -> Result<(), FlameError> {
+    assert_err!(s.engine.close_session(1000).await, FlameError::NotFound(_));
+    Ok(())
+}
+
+async fn update_session_deadline(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    assert_eq!(ssn.deadline, None);
+
+    let later = deadline(600);
+    let ssn = s
+        .engine
+        .update_session_deadline(ssn.id, Some(later))
+        .await?;
+    assert_eq!(ssn.deadline, Some(later));
+    assert_eq!(s.engine.get_session(ssn.id).await?.deadline, Some(later));
+
+    let ssn = s.engine.update_session_deadline(ssn.id, None).await?;
+    assert_eq!(ssn.deadline, None);
+    assert_err!(
+        s.engine.update_session_deadline(1000, Some(later)).await,
+        FlameError::NotFound(_)
+    );
+
+    Ok(())
+}
+
+async fn update_deadline_of_closed_session(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.closed_session().await?;
+
+    assert_err!(
+        s.engine
+            .update_session_deadline(ssn.id, Some(deadline(600)))
+            .await,
+        FlameError::FailedPrecondition(_)
+    );
+    assert_eq!(s.engine.get_session(ssn.id).await?.deadline, None);
+
+    Ok(())
+}
+
+async fn update_session_activity(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    assert_eq!(ssn.last_activity, None);
+
+    let now = deadline(0);
+    let ssn = s.engine.update_session_activity(ssn.id, now).await?;
+    assert_eq!(ssn.last_activity, Some(now));
+    assert_eq!(s.engine.get_session(ssn.id).await?.last_activity, Some(now));
+
+    // The activity is recorded whatever the state of the session.
+    let ssn = s.closed_session().await?;
+    let ssn = s.engine.update_session_activity(ssn.id, now).await?;
+    assert_eq!(ssn.last_activity, Some(now));
+    assert_err!(
+        s.engine.update_session_activity(1000, now).await,
+        FlameError::NotFound(_)
+    );
+
+    Ok(())
+}
+
+async fn update_session_annotations(mut s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    assert!(ssn.annotations.is_empty());
+
+    let annotations = HashMap::from([("model".to_string(), "v2".to_string())]);
+    let got = s
+        .engine
+        .update_session_annotations(ssn.id, &annotations)
+        .await?;
+    assert_eq!(got.annotations, annotations);
+
+    // The annotations are recorded whatever the state of the session.
+    s.engine.close_session(ssn.id).await?;
+    if s.persistent() {
+        s.restart().await?;
+    }
+    assert_eq!(s.engine.get_session(ssn.id).await?.annotations, annotations);
+
+    assert_err!(
+        s.engine
+            .update_session_annotations(1000, &annotations)
+            .await,
+        FlameError::NotFound(_)
+    );
+
+    Ok(())
+}
+
+async fn open_closed_session(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.closed_session().await?;
+
+    let ssn = s.engine.open_session(ssn.id).await?;
+    assert_eq!(ssn.status.state, SessionState::Open);
+    assert!(ssn.completion_time.is_none());
+    assert_eq!(s.task(&ssn).await?.id, 1);
+
+    Ok(())
+}
+
+async fn open_missing_session(s: Scenario) -> Result<(), FlameError> {
+    assert_err!(s.engine.open_session(1000).await, FlameError::NotFound(_));
+    Ok(())
+}
+
+async fn delete_closed_session(s: Scenario) -> Result<(), FlameError> {
+    let other = s.session().await?;
+    let ssn = s.closed_session().await?;
+
+    let deleted = s.engine.delete_session(ssn.id).await?;
+    assert_eq!(deleted.id, ssn.id);
+    assert_eq!(deleted.status.state, SessionState::Closed);
+
+    assert_err!(s.engine.get_session(ssn.id).await, FlameError::NotFound(_));
+    let ids: Vec<_> = s
+        .engine
+        .find_session()
+        .await?
+        .iter()
+        .map(|s| s.id)
+        .collect();
+    assert_eq!(ids, vec![other.id]);
+
+    Ok(())
+}
+
+async fn delete_open_session(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+
+    assert_err!(
+        s.engine.delete_session(ssn.id).await,
+        FlameError::FailedPrecondition(_)
+    );
+    s.engine.get_session(ssn.id).await?;
+
+    Ok(())
+}
+
+async fn delete_missing_session(s: Scenario) -> Result<(), FlameError> {
+    assert_err!(s.engine.delete_session(1000).await, FlameError::NotFound(_));
+    Ok(())
+}
+
+async fn delete_session_with_tasks(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let task = s.task(&ssn).await?;
+    s.engine
+        .update_task_state(task.gid(), TaskState::Succeed)
+        .await?;
+    s.engine.close_session(ssn.id).await?;
+
+    s.engine.delete_session(ssn.id).await?;
+    assert!(s.engine.find_tasks(ssn.id).await?.is_empty());
+    assert_err!(s.engine.get_task(task.gid()).await, FlameError::NotFound(_));
+
+    Ok(())
+}
+
+async fn find_sessions(s: Scenario) -> Result<(), FlameError> {
+    assert!(s.engine.find_session().await?.is_empty());
+
+    let mut ids = vec![];
+    for namespace in ["team-b", DEFAULT_NAMESPACE, "team-a"] {
+        let ssn = s
+            .engine
+            .create_session(SessionAttributes {
+                namespace: namespace.to_string(),
+                application: "flmexec".to_string(),
+                ..Default::default()
+            })
+            .await?;
+        ids.push((ssn.id, namespace.to_string()));
+    }
+    s.engine.close_session(ids[1].0).await?;
+
+    let found: Vec<_> = s
+        .engine
+        .find_session()
+        .await?
+        .into_iter()
+        .map(|s| (s.id, s.namespace))
+        .collect();
+    assert_eq!(found, ids);
+
+    Ok(())
+}
+
+async fn import_session(s: Scenario) -> Result<(), FlameError> {
+    let (archived, tasks) = s.archived_session().await?;
+
+    let ssn = s
+        .engine
+        .import_session(archived.clone(), tasks.clone())
+        .await?;
+    assert!(ssn.id > archived.id);
+    assert_eq!(ssn.namespace, archived.namespace);
+    assert_eq!(ssn.application, archived.application);
+    assert_eq!(ssn.slots, archived.slots);
+    assert_eq!(ssn.labels, archived.labels);
+    assert_eq!(ssn.common_data, archived.common_data);
+    assert_eq!(ssn.config, archived.config);
+    assert_eq!(ssn.max_pending_tasks, archived.max_pending_tasks);
+    assert_eq!(ssn.task_timeout, archived.task_timeout);
+    assert_eq!(ssn.max_task_retries, archived.max_task_retries);
+    assert_eq!(ssn.creation_time, archived.creation_time);
+    assert_eq!(ssn.completion_time, archived.completion_time);
+    assert_eq!(ssn.status.state, SessionState::Closed);
+    assert_eq!(
+        s.engine.get_session(ssn.id).await?.status.state,
+        SessionState::Closed
+    );
+
+    let found = s.engine.find_tasks(ssn.id).await?;
+    assert_eq!(found.len(), tasks.len());
+    for (found, task) in found.iter().zip(&tasks) {
+        assert_eq!(found.id, task.id);
+        assert_eq!(found.ssn_id, ssn.id);
+        assert_eq!(found.index, task.index);
+        assert_eq!(found.state, task.state);
+        assert_eq!(found.input, task.input);
+        assert_eq!(found.output, task.output);
+        assert_eq!(found.labels, task.labels);
+        assert_eq!(found.locality, task.locality);
+        assert_eq!(found.env, task.env);
+        assert_eq!(
+            (found.timeout, found.max_retries),
+            (task.timeout, task.max_retries)
+        );
+        assert_eq!(found.creation_time, task.creation_time);
+        assert_eq!(found.completion_time, task.completion_time);
+    }
+
+    assert_err!(s.task(&ssn).await, FlameError::FailedPrecondition(_));
+    s.engine.delete_session(ssn.id).await?;
+    assert!(s.engine.find_tasks(ssn.id).await?.is_empty());
+
+    Ok(())
+}
+
+async fn import_session_with_duplicated_tasks(s: Scenario) -> Result<(), FlameError> {
+    let (archived, tasks) = s.archived_session().await?;
+    let tasks = vec![tasks[0].clone(), tasks[0].clone()];
+
+    assert_err!(
+        s.engine.import_session(archived, tasks).await,
+        FlameError::AlreadyExists(_)
+    );
+    assert!(s.engine.find_session().await?.is_empty());
+
+    Ok(())
+}
+
+async fn create_task(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let task = s
+        .engine
+        .create_task(
+            ssn.id,
+            TaskAttributes {
+                input: Some(Bytes::from("input")),
+                trace_context: Some("00-trace-span-01".to_string()),
+                labels: HashMap::from([("zone".to_string(), "a".to_string())]),
+                locality: Some("shard-1".to_string()),
+                env: HashMap::from([("SEED".to_string(), "42".to_string())]),
+                timeout: Some(600),
+                max_retries: Some(2),
+                idempotency_key: Some("input-1".to_string()),
+            },
+        )
+        .await?;
+
+    assert_eq!(task.id, 1);
+    assert_eq!(task.ssn_id, ssn.id);
+    assert_eq!(task.index, 0);
+    assert_eq!(task.input, Some(Bytes::from("input")));
+    assert_eq!(task.output, None);
+    assert_eq!(task.trace_context.as_deref(), Some("00-trace-span-01"));
+    assert_eq!(task.labels["zone"], "a");
+    assert_eq!(task.locality.as_deref(), Some("shard-1"));
+    assert_eq!(task.env["SEED"], "42");
+    assert_eq!((task.timeout, task.max_retries), (Some(600), Some(2)));
+    assert_eq!(task.idempotency_key.as_deref(), Some("input-1"));
+    assert_eq!(task.state, TaskState::Pending);
+    assert_recent(task.creation_time);
+    assert!(task.completion_time.is_none());
+
+    let got = s.engine.get_task(task.gid()).await?;
+    assert_eq!(got.input, task.input);
+    assert_eq!(got.trace_context, task.trace_context);
+    assert_eq!(got.labels, task.labels);
+    assert_eq!(got.locality, task.locality);
+    assert_eq!(got.env, task.env);
+    assert_eq!(
+        (got.timeout, got.max_retries),
+        (task.timeout, task.max_retries)
+    );
+    assert_eq!(got.idempotency_key, task.idempotency_key);
+    assert_eq!(got.creation_time, task.creation_time);
+
+    Ok(())
+}
+
+async fn task_ids_per_session(s: Scenario) -> Result<(), FlameError> {
+    let ssn_1 = s.session().await?;
+    let ssn_2 = s.session().await?;
+
+    for id in 1..=3 {
+        assert_eq!(s.task(&ssn_1).await?.id, id);
+        assert_eq!(s.task(&ssn_2).await?.id, id);
+    }
+
+    Ok(())
+}
+
+async fn task_indexes(s: Scenario) -> Result<(), FlameError> {
+    let ssn_1 = s.session().await?;
+    let ssn_2 = s.session().await?;
+    for index in 0..3 {
+        assert_eq!(s.task(&ssn_1).await?.index, index);
+    }
+    assert_eq!(s.task(&ssn_2).await?.index, 0);
+
+    // The index of the deleted task is not reused, and the retried task keeps its index.
+    let last = s.engine.find_tasks(ssn_1.id).await?.pop().expect("no task");
+    s.engine.delete_task(last.gid()).await?;
+    let task = s.task(&ssn_1).await?;
+    assert_eq!(task.index, 3);
+    assert_eq!(s.engine.retry_task(task.gid()).await?.index, 3);
+
+    Ok(())
+}
+
+async fn create_task_in_closed_session(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.closed_session().await?;
+
+    assert_err!(s.task(&ssn).await, FlameError::FailedPrecondition(_));
+    assert!(s.engine.find_tasks(ssn.id).await?.is_empty());
+
+    Ok(())
+}
+
+async fn create_task_in_missing_session(s: Scenario) -> Result<(), FlameError> {
+    assert_err!(
+        s.engine.create_task(1000, TaskAttributes::default()).await,
+        FlameError::NotFound(_)
+    );
+    Ok(())
+}
+
+async fn get_missing_task(s: Scenario) -> Result<(), FlameError> {
+    let ssn_1 = s.session().await?;
+    let ssn_2 = s.session().await?;
+    s.task(&ssn_1).await?;
+
+    let gid = TaskGID {
+        ssn_id: ssn_2.id,
+        task_id: 1,
+    };
+    assert_err!(s.engine.get_task(gid).await, FlameError::NotFound(_));
+    assert_err!(
+        s.engine.get_task(missing_task()).await,
+        FlameError::NotFound(_)
+    );
+
+    Ok(())
+}
+
+async fn update_task_state(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let task = s.task(&ssn).await?;
+
+    let task = s
+        .engine
+        .update_task_state(task.gid(), TaskState::Running)
+        .await?;
+    assert_eq!(task.state, TaskState::Running);
+    assert!(task.completion_time.is_none());
+
+    for state in [TaskState::Succeed, TaskState::Failed] {
+        let task = s.engine.update_task_state(task.gid(), state).await?;
+        assert_eq!(task.state, state);
+        assert_recent(task.completion_time.expect("no completion time"));
+
+        let got = s.engine.get_task(task.gid()).await?;
+        assert_eq!(got.state, state);
+        assert_eq!(got.completion_time, task.completion_time);
+    }
+
+    Ok(())
+}
+
+async fn update_missing_task(s: Scenario) -> Result<(), FlameError> {
+    assert_err!(
+        s.engine
+            .update_task_state(missing_task(), TaskState::Running)
+            .await,
+        FlameError::NotFound(_)
+    );
+    Ok(())
+}
+
+async fn update_task_placement(mut s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let task = s.task(&ssn).await?;
+    assert!(task.executor_id.is_none() && task.hostname.is_none());
+
+    s.engine
+        .update_task_placement(task.gid(), "exec-1", "host-1")
+        .await?;
+    // The retried task is launched by another executor.
+    s.engine.retry_task(task.gid()).await?;
+    let placed = s
+        .engine
+        .update_task_placement(task.gid(), "exec-2", "host-2")
+        .await?;
+    assert_eq!(placed.executor_id.as_deref(), Some("exec-2"));
+    assert_eq!(placed.hostname.as_deref(), Some("host-2"));
+
+    if s.persistent() {
+        s.restart().await?;
+    }
+    let got = s.engine.get_task(task.gid()).await?;
+    assert_eq!(got.executor_id.as_deref(), Some("exec-2"));
+    assert_eq!(got.hostname.as_deref(), Some("host-2"));
+
+    assert_err!(
+        s.engine
+            .update_task_placement(missing_task(), "exec-1", "host-1")
+            .await,
+        FlameError::NotFound(_)
+    );
+
+    Ok(())
+}
+
+async fn update_task_failure(mut s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let task = s.task(&ssn).await?;
+    assert!(task.failure.is_none());
+
+    let failure = TaskFailure {
+        reason: FailureReason::ShimError,
+        message: "shim crashed".to_string(),
+    };
+    s.engine
+        .update_task_state(task.gid(), TaskState::Running)
+        .await?;
+    s.engine
+        .update_task_failure(task.gid(), Some(&failure))
+        .await?;
+    // The failure is kept by the retry.
+    let retried = s
+        .engine
+        .update_task_state(task.gid(), TaskState::Pending)
+        .await?;
+    assert_eq!(retried.failure.as_ref(), Some(&failure));
+
+    if s.persistent() {
+        s.restart().await?;
+    }
+    let got = s.engine.get_task(task.gid()).await?;
+    assert_eq!(got.failure.as_ref(), Some(&failure));
+
+    // It's cleared once the task succeeds.
+    let succeed = s
+        .engine
+        .update_task_state(task.gid(), TaskState::Succeed)
+        .await?;
+    assert!(succeed.failure.is_none());
+    assert!(s.engine.get_task(task.gid()).await?.failure.is_none());
+
+    assert_err!(
+        s.engine
+            .update_task_failure(missing_task(), Some(&failure))
+            .await,
+        FlameError::NotFound(_)
+    );
+
+    Ok(())
+}
+
+async fn update_task_output(mut s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let task = s.task(&ssn).await?;
+    assert!(task.output.is_none());
+
+    let output = Bytes::from("output");
+    s.engine
+        .update_task_output(task.gid(), Some(&output))
+        .await?;
+    // The output is kept once the task succeeds.
+    let succeed = s
+        .engine
+        .update_task_state(task.gid(), TaskState::Succeed)
+        .await?;
+    assert_eq!(succeed.output.as_ref(), Some(&output));
+
+    if s.persistent() {
+        s.restart().await?;
+    }
+    let got = s.engine.get_task(task.gid()).await?;
+    assert_eq!(got.output, Some(output));
+
+    assert_err!(
+        s.engine.update_task_output(missing_task(), None).await,
+        FlameError::NotFound(_)
+    );
+
+    Ok(())
+}
+
+async fn update_task_annotations(mut s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let task = s.task(&ssn).await?;
+    assert!(task.annotations.is_empty());
+
+    let annotations = HashMap::from([("rows".to_string(), "100".to_string())]);
+    s.engine
+        .update_task_state(task.gid(), TaskState::Running)
+        .await?;
+    let got = s
+        .engine
+        .update_task_annotations(task.gid(), &annotations)
+        .await?;
+    assert_eq!(got.annotations, annotations);
+
+    // The annotations are kept by the retry and the completion.
+    let retried = s.engine.retry_task(task.gid()).await?;
+    assert_eq!(retried.annotations, annotations);
+    s.engine
+        .update_task_state(task.gid(), TaskState::Succeed)
+        .await?;
+    if s.persistent() {
+        s.restart().await?;
+    }
+    assert_eq!(
+        s.engine.get_task(task.gid()).await?.annotations,
+        annotations
+    );
+
+    assert_err!(
+        s.engine
+            .update_task_annotations(missing_task(), &annotations)
+            .await,
+        FlameError::NotFound(_)
+    );
+
+    Ok(())
+}
+
+async fn update_tasks(mut s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let (t1, t2) = (s.task(&ssn).await?, s.task(&ssn).await?);
+
+    let failure = TaskFailure {
+        reason: FailureReason::ShimError,
+        message: "shim crashed".to_string(),
+    };
+    s.engine
+        .update_task_failure(t1.gid(), Some(&failure))
+        .await?;
+
+    let now = Utc::now();
+    let update = |task: &Task, state: TaskState, output: Option<&str>| TaskUpdate {
+        gid: task.gid(),
+        state,
+        completion_time: (state == TaskState::Succeed).then_some(now),
+        output: output.map(|s| Bytes::copy_from_slice(s.as_bytes())),
+    };
+    s.engine
+        .update_tasks(vec![
+            update(&t1, TaskState::Running, None),
+            update(&t2, TaskState::Running, None),
+            update(&t1, TaskState::Succeed, Some("output")),
+            update(&t2, TaskState::Pending, None),
+            update(&t2, TaskState::Running, None),
+            TaskUpdate {
+                gid: missing_task(),
+                ..update(&t1, TaskState::Failed, None)
+            },
+        ])
+        .await?;
+
+    if s.persistent() {
+        s.restart().await?;
+    }
+    let got = s.engine.get_task(t1.gid()).await?;
+    assert_eq!(got.state, TaskState::Succeed);
+    assert_eq!(got.output, Some(Bytes::from("output")));
+    assert!(got.failure.is_none());
+    assert_eq!(
+        got.completion_time.map(|t| t.timestamp()),
+        Some(now.timestamp())
+    );
+
+    let got = s.engine.get_task(t2.gid()).await?;
+    assert_eq!(got.state, TaskState::Running);
+    assert!(got.completion_time.is_none());
+
+    // The output is kept by the changes without one.
+    s.engine
+        .update_tasks(vec![update(&t1, TaskState::Succeed, None)])
+        .await?;
+    let got = s.engine.get_task(t1.gid()).await?;
+    assert_eq!(got.output, Some(Bytes::from("output")));
+
+    Ok(())
+}
+
+async fn retry_task(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let task = s.task(&ssn).await?;
+    s.engine
+        .update_task_state(task.gid(), TaskState::Running)
+        .await?;
+
+    let task = s.engine.retry_task(task.gid()).await?;
+    assert_eq!(task.state, TaskState::Pending);
+    assert_eq!(
+        s.engine.get_task(task.gid()).await?.state,
+        TaskState::Pending
+    );
+
+    Ok(())
+}
+
+async fn retry_missing_task(s: Scenario) -> Result<(), FlameError> {
+    assert_err!(
+        s.engine.retry_task(missing_task()).await,
+        FlameError::NotFound(_)
+    );
+    Ok(())
+}
+
+async fn resubmit_task(mut s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let original = s
+        .engine
+        .create_task(
+            ssn.id,
+            TaskAttributes {
+                input: Some(Bytes::from("input")),
+                trace_context: Some("00-trace-span-01".to_string()),
+                labels: HashMap::from([("zone".to_string(), "a".to_string())]),
+                locality: Some("shard-1".to_string()),
+                env: HashMap::from([("SEED".to_string(), "42".to_string())]),
+                timeout: Some(600),
+                max_retries: Some(2),
+                idempotency_key: None,
+            },
+        )
+        .await?;
+    let original = s
+        .engine
+        .update_task_state(original.gid(), TaskState::Failed)
+        .await?;
+
+    let task = s.engine.resubmit_task(original.gid()).await?;
+    assert_eq!((task.id, task.index), (2, 1));
+    assert_eq!(task.input, original.input);
+    assert_eq!(task.trace_context, original.trace_context);
+    assert_eq!(task.labels, original.labels);
+    assert_eq!(task.locality, original.locality);
+    assert_eq!(task.env, original.env);
+    assert_eq!(task.timeout, original.timeout);
+    assert_eq!(task.max_retries, original.max_retries);
+    assert_eq!(task.original_task_id, Some(original.id));
+    assert_eq!(task.state, TaskState::Pending);
+    assert!(task.completion_time.is_none());
+
+    if s.persistent() {
+        s.restart().await?;
+    }
+    let got = s.engine.get_task(task.gid()).await?;
+    assert_eq!(got.original_task_id, Some(original.id));
+    assert_eq!(got.labels, original.labels);
+    // The original task is not changed.
+    let got = s.engine.get_task(original.gid()).await?;
+    assert_eq!(got.state, TaskState::Failed);
+    assert_eq!(got.original_task_id, None);
+
+    assert_err!(
+        s.engine.resubmit_task(missing_task()).await,
+        FlameError::NotFound(_)
+    );
+
+    Ok(())
+}
+
+async fn resubmit_task_in_closed_session(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let task = s.task(&ssn).await?;
+    s.engine
+        .update_task_state(task.gid(), TaskState::Failed)
+        .await?;
+    s.engine.close_session(ssn.id).await?;
+
+    assert_err!(
+        s.engine.resubmit_task(task.gid()).await,
+        FlameError::FailedPrecondition(_)
+    );
+    assert_eq!(s.engine.find_tasks(ssn.id).await?.len(), 1);
+
+    Ok(())
+}
+
+async fn delete_task(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let task_1 = s.task(&ssn).await?;
+    let task_2 = s.task(&ssn).await?;
+
+    let deleted = s.engine.delete_task(task_1.gid()).await?;
+    assert_eq!(deleted.gid(), task_1.gid());
+
+    assert_err!(
+        s.engine.get_task(task_1.gid()).await,
+        FlameError::NotFound(_)
+    );
+    assert_err!(
+        s.engine.delete_task(task_1.gid()).await,
+        FlameError::NotFound(_)
+    );
+    let ids: Vec<_> = s
+        .engine
+        .find_tasks(ssn.id)
+        .await?
+        .iter()
+        .map(|t| t.id)
+        .collect();
+    assert_eq!(ids, vec![task_2.id]);
+
+    Ok(())
+}
+
+async fn find_tasks(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let other = s.session().await?;
+    s.task(&other).await?;
+
+    assert!(s.engine.find_tasks(ssn.id).await?.is_empty());
+    assert!(s.engine.find_tasks(1000).await?.is_empty());
+
+    for _ in 0..3 {
+        s.task(&ssn).await?;
+    }
+    s.engine
+        .update_task_state(
+            TaskGID {
+                ssn_id: ssn.id,
+                task_id: 2,
+            },
+            TaskState::Running,
+        )
+        .await?;
+
+    let found: Vec<_> = s
+        .engine
+        .find_tasks(ssn.id)
+        .await?
+        .into_iter()
+        .map(|t| (t.ssn_id, t.id, t.state))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (ssn.id, 1, TaskState::Pending),
+            (ssn.id, 2, TaskState::Running),
+            (ssn.id, 3, TaskState::Pending),
+        ]
+    );
+
+    Ok(())
+}
+
+async fn batch_tasks(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    for i in 0..100 {
+        let input = Bytes::from(format!("input-{}", i));
+        let attrs = TaskAttributes {
+            input: Some(input),
+            ..Default::default()
+        };
+        s.engine.create_task(ssn.id, attrs).await?;
+    }
+    for task in s.engine.find_tasks(ssn.id).await? {
+        let state = match task.id % 2 {
+            0 => TaskState::Succeed,
+            _ => TaskState::Failed,
+        };
+        s.engine.update_task_state(task.gid(), state).await?;
+    }
+
+    let tasks = s.engine.find_tasks(ssn.id).await?;
+    assert_eq!(tasks.len(), 100);
+    for (i, task) in tasks.iter().enumerate() {
+        assert_eq!(task.id, i as i64 + 1);
+        assert_eq!(task.input, Some(Bytes::from(format!("input-{}", i))));
+        assert!(task.completion_time.is_some());
+    }
+    s.engine.close_session(ssn.id).await?;
+
+    Ok(())
+}
+
+async fn cached_outputs(s: Scenario) -> Result<(), FlameError> {
+    let expired = Utc::now() - Duration::hours(1);
+    let key = cache_key("d1", 1, false);
+    assert!(s.engine.find_cached_output(&key, expired).await?.is_none());
+
+    s.engine
+        .put_cached_output(cached_output(&key, "a", 0), 10, expired)
+        .await?;
+    let found = s.engine.find_cached_output(&key, expired).await?;
+    let found = found.expect("the cached output");
+    assert_eq!(found.key, key);
+    assert_eq!(found.output, Some(Bytes::from("a")));
+    assert_recent(found.creation_time);
+
+    // The output of the same digest in the session is replaced.
+    s.engine
+        .put_cached_output(cached_output(&key, "b", 0), 10, expired)
+        .await?;
+    let found = s.engine.find_cached_output(&key, expired).await?;
+    assert_eq!(found.and_then(|o| o.output), Some(Bytes::from("b")));
+
+    let others = [
+        cache_key("d2", 1, false),
+        cache_key("d1", 2, false),
+        cache_key("d1", 2, true),
+        CacheKey {
+            namespace: "team-b".to_string(),
+            ..key.clone()
+        },
+        CacheKey {
+            application: "flmlog".to_string(),
+            ..key.clone()
+        },
+    ];
+    for other in others {
+        let found = s.engine.find_cached_output(&other, expired).await?;
+        assert!(found.is_none(), "unexpected output of {:?}", other);
+    }
+
+    // The shared outputs are only found by the other sessions which share theirs.
+    let shared = cache_key("d1", 3, true);
+    s.engine
+        .put_cached_output(cached_output(&shared, "c", 0), 10, expired)
+        .await?;
+    for (other, output) in [
+        (cache_key("d1", 4, true), Some(Bytes::from("c"))),
+        (cache_key("d1", 4, false), None),
+        (key, Some(Bytes::from("b"))),
+    ] {
+        let found = s.engine.find_cached_output(&other, expired).await?;
+        assert_eq!(found.and_then(|o| o.output), output, "{:?}", other);
+    }
+
+    Ok(())
+}
+
+async fn cached_outputs_are_bounded(s: Scenario) -> Result<(), FlameError> {
+    let expired = Utc::now() - Duration::hours(1);
+    let long_ago = Utc::now() - Duration::hours(3);
+
+    let old = cache_key("old", 1, false);
+    s.engine
+        .put_cached_output(cached_output(&old, "old", 7200), 10, expired)
+        .await?;
+    assert!(s.engine.find_cached_output(&old, expired).await?.is_none());
+    assert!(s.engine.find_cached_output(&old, long_ago).await?.is_some());
+
+    // The expired and the oldest outputs are removed by the new ones.
+    let keys: Vec<_> = ["d1", "d2", "d3"]
+        .iter()
+        .map(|d| cache_key(d, 1, false))
+        .collect();
+    for key in &keys {
+        s.engine
+            .put_cached_output(cached_output(key, "output", 0), 2, expired)
+            .await?;
+    }
+    assert!(s.engine.find_cached_output(&old, long_ago).await?.is_none());
+    assert!(s
+        .engine
+        .find_cached_output(&keys[0], expired)
+        .await?
+        .is_none());
+    assert!(s
+        .engine
+        .find_cached_output(&keys[1], expired)
+        .await?
+        .is_some());
+    assert!(s
+        .engine
+        .find_cached_output(&keys[2], expired)
+        .await?
+        .is_some());
+
+    Ok(())
+}
+
+async fn delete_session_with_cached_outputs(s: Scenario) -> Result<(), FlameError> {
+    let expired = Utc::now() - Duration::hours(1);
+    let ssn = s.closed_session().await?;
+    let key = cache_key("d1", ssn.id, false);
+    let shared = cache_key("d2", ssn.id, true);
+    for key in [&key, &shared] {
+        s.engine
+            .put_cached_output(cached_output(key, "output", 0), 10, expired)
+            .await?;
+    }
+
+    s.engine.delete_session(ssn.id).await?;
+
+    assert!(s.engine.find_cached_output(&key, expired).await?.is_none());
+    let other = cache_key("d2", ssn.id + 1, true);
+    assert!(s
+        .engine
+        .find_cached_output(&other, expired)
+        .await?
+        .is_some());
+
+    Ok(())
+}
+
+async fn session_events(s: Scenario) -> Result<(), FlameError> {
+    let (ssn1, ssn2) = (s.session().await?, s.session().await?);
+    let first = event(1, 300);
+    let events = vec![
+        (ssn1.id, first.clone()),
+        (ssn2.id, event(1, 300)),
+        (ssn1.id, event(2, 200)),
+        (ssn1.id, event(3, 100)),
+        // The events of the unknown sessions are dropped.
+        (ssn2.id + 1, event(1, 100)),
+    ];
+    s.engine.put_events(events, 10).await?;
+
+    let closed = SessionEvent {
+        sequence: 4,
+        event_time: deadline(0),
+        kind: EventKind::SessionClosed,
+    };
+    s.engine
+        .put_events(vec![(ssn1.id, closed.clone())], 10)
+        .await?;
+
+    let found = s.engine.find_events(ssn1.id, None, None).await?;
+    assert_eq!(sequences(&found), vec![1, 2, 3, 4]);
+    assert_eq!(found[0], first);
+    assert_eq!(found[3], closed);
+    assert_eq!(
+        sequences(&s.engine.find_events(ssn2.id, None, None).await?),
+        vec![1]
+    );
+    assert!(s
+        .engine
+        .find_events(ssn2.id + 1, None, None)
+        .await?
+        .is_empty());
+
+    // The events are found in `[since, until)`.
+    let found = s
+        .engine
+        .find_events(ssn1.id, Some(deadline(-250)), Some(deadline(-150)))
+        .await?;
+    assert_eq!(sequences(&found), vec![2]);
+    let found = s
+        .engine
+        .find_events(ssn1.id, Some(deadline(-150)), None)
+        .await?;
+    assert_eq!(sequences(&found), vec![3, 4]);
+
+    Ok(())
+}
+
+async fn session_events_are_bounded(s: Scenario) -> Result<(), FlameError> {
+    let (ssn1, ssn2) = (s.session().await?, s.session().await?);
+    s.engine.put_events(vec![(ssn2.id, event(1, 0))], 2).await?;
+    for sequence in 1..=3 {
+        s.engine
+            .put_events(vec![(ssn1.id, event(sequence, 0))], 2)
+            .await?;
+    }
+    s.engine
+        .put_events(vec![(ssn1.id, event(4, 0)), (ssn1.id, event(5, 0))], 2)
+        .await?;
+
+    // The oldest events are removed per session.
+    let found = s.engine.find_events(ssn1.id, None, None).await?;
+    assert_eq!(sequences(&found), vec![4, 5]);
+    let found = s.engine.find_events(ssn2.id, None, None).await?;
+    assert_eq!(sequences(&found), vec![1]);
+
+    Ok(())
+}
+
+async fn delete_session_with_events(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.closed_session().await?;
+    s.engine.put_events(vec![(ssn.id, event(1, 0))], 10).await?;
+
+    s.engine.delete_session(ssn.id).await?;
+
+    assert!(s.engine.find_events(ssn.id, None, None).await?.is_empty());
+
+    Ok(())
+}
+
+async fn output_chunks(mut s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let (t1, t2) = (s.task(&ssn).await?, s.task(&ssn).await?);
+    s.engine
+        .put_output_chunks(t1.gid(), vec![chunk(1, "a"), chunk(2, "b")])
+        .await?;
+    s.engine
+        .put_output_chunks(t2.gid(), vec![chunk(1, "x")])
+        .await?;
+    // The chunks of the same sequences are replaced, and those of the unknown tasks are
+    // dropped.
+    s.engine
+        .put_output_chunks(t1.gid(), vec![chunk(2, "c"), chunk(3, "d")])
+        .await?;
+    s.engine
+        .put_output_chunks(missing_task(), vec![chunk(1, "y")])
+        .await?;
+    if s.persistent() {
+        s.restart().await?;
+    }
+
+    let found = s.engine.find_output_chunks(t1.gid(), 0, 10).await?;
+    assert_eq!(found, vec![chunk(1, "a"), chunk(2, "c"), chunk(3, "d")]);
+    assert_eq!(
+        s.engine.find_output_chunks(t1.gid(), 1, 1).await?,
+        vec![chunk(2, "c")]
+    );
+    assert!(s
+        .engine
+        .find_output_chunks(t1.gid(), 3, 10)
+        .await?
+        .is_empty());
+    assert_eq!(
+        s.engine.find_output_chunks(t2.gid(), 0, 10).await?,
+        vec![chunk(1, "x")]
+    );
+    assert!(s
+        .engine
+        .find_output_chunks(missing_task(), 0, 10)
+        .await?
+        .is_empty());
+
+    Ok(())
+}
+
+async fn delete_task_with_output_chunks(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let task = s.task(&ssn).await?;
+    s.engine
+        .put_output_chunks(task.gid(), vec![chunk(1, "a")])
+        .await?;
+
+    s.engine.delete_task(task.gid()).await?;
+
+    assert!(s
+        .engine
+        .find_output_chunks(task.gid(), 0, 10)
+        .await?
+        .is_empty());
+
+    Ok(())
+}
+
+async fn delete_session_with_output_chunks(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let task = s.task(&ssn).await?;
+    s.engine
+        .put_output_chunks(task.gid(), vec![chunk(1, "a")])
+        .await?;
+    s.engine
+        .update_task_state(task.gid(), TaskState::Succeed)
+        .await?;
+    s.engine.close_session(ssn.id).await?;
+
+    s.engine.delete_session(ssn.id).await?;
+
+    assert!(s
+        .engine
+        .find_output_chunks(task.gid(), 0, 10)
+        .await?
+        .is_empty());
+
+    Ok(())
+}
+
+async fn concurrent_sessions(s: Scenario) -> Result<(), FlameError> {
+    let writers = (0..8).map(|_| {
+        let engine = s.engine.clone();
+        tokio::spawn(async move {
+            let mut ids = vec![];
+            for _ in 0..10 {
+                let ssn = engine
+                    .create_session(SessionAttributes {
+                        application: "flmexec".to_string(),
+                        ..Default::default()
+                    })
+                    .await?;
+                ids.push(ssn.id);
+            }
+            Ok::<_, FlameError>(ids)
+        })
+    });
+
+    let mut ids = vec![];
+    for writer in futures::future::join_all(writers).await {
+        ids.extend(writer.map_err(|e| FlameError::Internal(e.to_string()))??);
+    }
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 80);
+    assert_eq!(s.engine.find_session().await?.len(), 80);
+
+    Ok(())
+}
+
+async fn concurrent_tasks(s: Scenario) -> Result<(), FlameError> {
+    let ssn = s.session().await?;
+    let writers = (0..8).map(|_| {
+        let engine = s.engine.clone();
+        tokio::spawn(async move {
+            let mut tasks = vec![];
+            for _ in 0..10 {
+                let task = engine
+                    .create_task(ssn.id, TaskAttributes::default())
+                    .await?;
+                tasks.push((task.id, task.index));
+            }
+            Ok::<_, FlameError>(tasks)
+        })
+    });
+
+    let mut tasks = vec![];
+    for writer in futures::future::join_all(writers).await {
+        tasks.extend(writer.map_err(|e| FlameError::Internal(e.to_string()))??);
+    }
+    let (mut ids, mut indexes): (Vec<_>, Vec<_>) = tasks.into_iter().unzip();
+    ids.sort();
+    indexes.sort();
+    assert_eq!(ids, (1..=80).collect::<Vec<_>>());
+    assert_eq!(indexes, (0..80).collect::<Vec<_>>());
+
+    Ok(())
+}
+
+async fn register_application(s: Scenario) -> Result<(), FlameError> {
+    let app = s
+        .engine
+        .register_application(application("flmexec"))
+        .await?;
+    assert_eq!(app.name, "flmexec");
+    assert_eq!(app.command, "/usr/bin/flmexec");
+
+    assert_err!(
+        s.engine.register_application(application("flmexec")).await,
+        FlameError::AlreadyExists(_)
+    );
+
+    Ok(())
+}
+
+async fn delete_application(s: Scenario) -> Result<(), FlameError> {
+    s.engine
+        .register_application(application("flmexec"))
+        .await?;
+
+    let app = s.engine.delete_application("flmexec".to_string()).await?;
+    assert_eq!(app.command, "/usr/bin/flmexec");
+    assert!(s.engine.find_application().await?.is_empty());
+
+    assert_err!(
+        s.engine.delete_application("flmexec".to_string()).await,
+        FlameError::NotFound(_)
+    );
+
+    Ok(())
+}
+
+async fn find_applications(s: Scenario) -> Result<(), FlameError> {
+    for name in ["flmping", "flmexec", "flmlog"] {
+        s.engine.register_application(application(name)).await?;
+    }
+
+    let names: Vec<_> = s
+        .engine
+        .find_application()
+        .await?
+        .into_iter()
+        .map(|app| app.name)
+        .collect();
+    assert_eq!(names, vec!["flmexec", "flmlog", "flmping"]);
+
+    Ok(())
+}
+
+async fn restart_and_find_sessions(mut s: Scenario) -> Result<(), FlameError> {
+    if !s.persistent() {
+        return Ok(());
+    }
+
+    let labels = HashMap::from([("env".to_string(), "dev".to_string())]);
+    let open = s
+        .engine
+        .create_session(SessionAttributes {
+            namespace: "team-a".to_string(),
+            application: "flmlog".to_string(),
+            slots: 2,
+            labels: labels.clone(),
+            common_data: Some(Bytes::from("common data")),
+            config: HashMap::from([("model".to_string(), "v1".to_string())]),
+            max_pending_tasks: Some(10),
+            max_running_tasks: Some(2),
+            cache_scope: CacheScope::Session,
+            deadline: Some(deadline(3600)),
+            task_timeout: Some(600),
+            max_task_retries: Some(2),
+            cloned_from: None,
+            priority_class: None,
+            latest_application: true,
+            application_spec: None,
+        })
+        .await?;
+    let attrs = TaskAttributes {
+        input: Some(Bytes::from("input")),
+        labels: labels.clone(),
+        idempotency_key: Some("input-1".to_string()),
+        ..Default::default()
+    };
+    let task = s.engine.create_task(open.id, attrs).await?;
+    s.engine
+        .update_task_state(task.gid(), TaskState::Succeed)
+        .await?;
+    let closed = s.closed_session().await?;
+
+    s.restart().await?;
+
+    let found = s.engine.find_session().await?;
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0].id, open.id);
+    assert_eq!(found[0].namespace, "team-a");
+    assert_eq!(found[0].labels, labels);
+    assert_eq!(found[0].common_data, open.common_data);
+    assert_eq!(found[0].config, open.config);
+    assert_eq!(found[0].max_pending_tasks, Some(10));
+    assert_eq!(found[0].max_running_tasks, Some(2));
+    assert_eq!(found[0].cache_scope, CacheScope::Session);
+    assert_eq!(found[0].deadline, open.deadline);
+    assert_eq!(found[0].status.state, SessionState::Open);
+    assert_eq!(found[1].id, closed.id);
+    assert_eq!(found[1].status.state, SessionState::Closed);
+    assert_eq!(found[1].completion_time, closed.completion_time);
+
+    let tasks = s.engine.find_tasks(open.id).await?;
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].input, Some(Bytes::from("input")));
+    assert_eq!(tasks[0].labels, labels);
+    assert_eq!(tasks[0].idempotency_key.as_deref(), Some("input-1"));
+    assert_eq!(tasks[0].state, TaskState::Succeed);
+
+    Ok(())
+}
+
+async fn restart_and_retry_running_tasks(mut s: Scenario) -> Result<(), FlameError> {
+    if !s.persistent() {
+        return Ok(());
+    }
+
+    let ssn = s.session().await?;
+    let running = s.task(&ssn).await?;
+    s.engine
+        .update_task_state(running.gid(), TaskState::Running)
+        .await?;
+    s.task(&ssn).await?;
+
+    s.restart().await?;
+
+    // The same queries as `Storage::load_data`.
+    for task in s.engine.find_tasks(ssn.id).await? {
+        if task.state == TaskState::Running {
+            s.engine.retry_task(task.gid()).await?;
+        }
+    }
+    let states: Vec<_> = s
+        .engine
+        .find_tasks(ssn.id)
+        .await?
+        .into_iter()
+        .map(|t| t.state)
+        .collect();
+    assert_eq!(states, vec![TaskState::Pending, TaskState::Pending]);
+
+    Ok(())
+}
+
+async fn restart_and_create(mut s: Scenario) -> Result<(), FlameError> {
+    if !s.persistent() {
+        return Ok(());
+    }
+
+    let ssn = s.session().await?;
+    s.task(&ssn).await?;
+    let deleted = s.closed_session().await?;
+    s.engine.delete_session(deleted.id).await?;
+
+    s.restart().await?;
+
+    assert!(s.session().await?.id > deleted.id);
+    let task = s.task(&ssn).await?;
+    assert_eq!((task.id, task.index), (2, 1));
+
+    Ok(())
+}
+
+async fn restart_and_find_applications(mut s: Scenario) -> Result<(), FlameError> {
+    if !s.persistent() {
+        return Ok(());
+    }
+
+    s.engine
+        .register_application(application("flmexec"))
+        .await?;
+
+    s.restart().await?;
+
+    let apps = s.engine.find_application().await?;
+    assert_eq!(apps.len(), 1);
+    assert_eq!(apps[0].command, "/usr/bin/flmexec");
+
+    Ok(())
+}
+
+async fn restart_and_find_imported_session(mut s: Scenario) -> Result<(), FlameError> {
+    if !s.persistent() {
+        return Ok(());
+    }
+
+    let (archived, tasks) = s.archived_session().await?;
+    let ssn = s.engine.import_session(archived, tasks.clone()).await?;
+
+    s.restart().await?;
+
+    let found = s.engine.find_session().await?;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id, ssn.id);
+    assert_eq!(found[0].status.state, SessionState::Closed);
+    assert_eq!(found[0].completion_time, ssn.completion_time);
+
+    let found = s.engine.find_tasks(ssn.id).await?;
+    let outputs: Vec<_> = found.iter().map(|t| t.output.clone()).collect();
+    let expected: Vec<_> = tasks.iter().map(|t| t.output.clone()).collect();
+    assert_eq!(outputs, expected);
+
+    Ok(())
+}
+
+async fn restart_and_find_cached_outputs(mut s: Scenario) -> Result<(), FlameError> {
+    if !s.persistent() {
+        return Ok(());
+    }
+
+    let expired = Utc::now() - Duration::hours(1);
+    let key = cache_key("d1", 1, true);
+    s.engine
+        .put_cached_output(cached_output(&key, "output", 0), 10, expired)
+        .await?;
+
+    s.restart().await?;
+
+    let found = s.engine.find_cached_output(&key, expired).await?;
+    let found = found.expect("the cached output");
+    assert_eq!(found.key, key);
+    assert_eq!(found.output, Some(Bytes::from("output")));
+
+    Ok(())
+}
+
+async fn restart_and_find_events(mut s: Scenario) -> Result<(), FlameError> {
+    if !s.persistent() {
+        return Ok(());
+    }
+
+    let ssn = s.session().await?;
+    let e = event(1, 0);
+    s.engine.put_events(vec![(ssn.id, e.clone())], 10).await?;
+
+    s.restart().await?;
+
+    let found = s.engine.find_events(ssn.id, None, None).await?;
+    assert_eq!(found, vec![e]);
+
+    Ok(())
+}
@@ -0,0 +1,311 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::FlameError;
+use common::apis::{
+    Application, OutputChunk, Session, SessionAttributes, SessionEvent, SessionID, Task,
+    TaskAttributes, TaskFailure, TaskGID, TaskOutput, TaskState,
+};
+
+use crate::storage::engine::memory::MemoryEngine;
+use crate::storage::engine::{
+    CacheKey, CachedOutput, Capabilities, Engine, EngineInfo, EnginePtr, TaskUpdate,
+};
+
+/// The memory engine failing the calls on demand, e.g. as a busy database, so the tests can
+/// check how the failures of each step are handled.
+pub struct FaultyEngine {
+    engine: EnginePtr,
+    /// The number of the next calls of each operation to fail.
+    faults: Mutex<HashMap<&'static str, u32>>,
+    /// The number of the calls of each operation, including the failed ones.
+    calls: Mutex<HashMap<&'static str, u32>>,
+}
+
+impl FaultyEngine {
+    pub fn new_ptr() -> Arc<FaultyEngine> {
+        Arc::new(FaultyEngine {
+            engine: MemoryEngine::new_ptr(),
+            faults: Mutex::new(HashMap::new()),
+            calls: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Fails the next `n` calls of the operation with a transient failure.
+    pub fn fail(&self, op: &'static str, n: u32) {
+        self.faults.lock().unwrap().insert(op, n);
+    }
+
+    pub fn calls(&self, op: &'static str) -> u32 {
+        self.calls
+            .lock()
+            .unwrap()
+            .get(op)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn check(&self, op: &'static str) -> Result<(), FlameError> {
+        *self.calls.lock().unwrap().entry(op).or_default() += 1;
+
+        let mut faults = self.faults.lock().unwrap();
+        match faults.get_mut(op) {
+            Some(n) if *n > 0 => {
+                *n -= 1;
+                Err(FlameError::Unavailable {
+                    message: format!("database is locked in <{}>", op),
+                    retryable: true,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl Engine for FaultyEngine {
+    fn capabilities(&self) -> Capabilities {
+        self.engine.capabilities()
+    }
+
+    fn info(&self) -> EngineInfo {
+        self.engine.info()
+    }
+
+    async fn create_session(&self, attrs: SessionAttributes) -> Result<Session, FlameError> {
+        self.check("create_session")?;
+        self.engine.create_session(attrs).await
+    }
+
+    async fn get_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        self.check("get_session")?;
+        self.engine.get_session(id).await
+    }
+
+    async fn open_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        self.check("open_session")?;
+        self.engine.open_session(id).await
+    }
+
+    async fn close_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        self.check("close_session")?;
+        self.engine.close_session(id).await
+    }
+
+    async fn update_session_deadline(
+        &self,
+        id: SessionID,
+        deadline: Option<DateTime<Utc>>,
+    ) -> Result<Session, FlameError> {
+        self.check("update_session_deadline")?;
+        self.engine.update_session_deadline(id, deadline).await
+    }
+
+    async fn update_session_activity(
+        &self,
+        id: SessionID,
+        last_activity: DateTime<Utc>,
+    ) -> Result<Session, FlameError> {
+        self.check("update_session_activity")?;
+        self.engine.update_session_activity(id, last_activity).await
+    }
+
+    async fn update_session_annotations(
+        &self,
+        id: SessionID,
+        annotations: &HashMap<String, String>,
+    ) -> Result<Session, FlameError> {
+        self.check("update_session_annotations")?;
+        self.engine
+            .update_session_annotations(id, annotations)
+            .await
+    }
+
+    async fn delete_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        self.check("delete_session")?;
+        self.engine.delete_session(id).await
+    }
+
+    async fn find_session(&self) -> Result<Vec<Session>, FlameError> {
+        self.check("find_session")?;
+        self.engine.find_session().await
+    }
+
+    async fn import_session(&self, ssn: Session, tasks: Vec<Task>) -> Result<Session, FlameError> {
+        self.check("import_session")?;
+        self.engine.import_session(ssn, tasks).await
+    }
+
+    async fn create_task(
+        &self,
+        ssn_id: SessionID,
+        attrs: TaskAttributes,
+    ) -> Result<Task, FlameError> {
+        self.check("create_task")?;
+        self.engine.create_task(ssn_id, attrs).await
+    }
+
+    async fn resubmit_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        self.check("resubmit_task")?;
+        self.engine.resubmit_task(gid).await
+    }
+
+    async fn get_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        self.check("get_task")?;
+        self.engine.get_task(gid).await
+    }
+
+    async fn retry_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        self.check("retry_task")?;
+        self.engine.retry_task(gid).await
+    }
+
+    async fn delete_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        self.check("delete_task")?;
+        self.engine.delete_task(gid).await
+    }
+
+    async fn update_task_state(&self, gid: TaskGID, state: TaskState) -> Result<Task, FlameError> {
+        self.check("update_task_state")?;
+        self.engine.update_task_state(gid, state).await
+    }
+
+    async fn update_task_placement(
+        &self,
+        gid: TaskGID,
+        executor_id: &str,
+        hostname: &str,
+    ) -> Result<Task, FlameError> {
+        self.check("update_task_placement")?;
+        self.engine
+            .update_task_placement(gid, executor_id, hostname)
+            .await
+    }
+
+    async fn update_task_failure(
+        &self,
+        gid: TaskGID,
+        failure: Option<&TaskFailure>,
+    ) -> Result<Task, FlameError> {
+        self.check("update_task_failure")?;
+        self.engine.update_task_failure(gid, failure).await
+    }
+
+    async fn update_task_annotations(
+        &self,
+        gid: TaskGID,
+        annotations: &HashMap<String, String>,
+    ) -> Result<Task, FlameError> {
+        self.check("update_task_annotations")?;
+        self.engine.update_task_annotations(gid, annotations).await
+    }
+
+    async fn update_task_output(
+        &self,
+        gid: TaskGID,
+        output: Option<&TaskOutput>,
+    ) -> Result<Task, FlameError> {
+        self.check("update_task_output")?;
+        self.engine.update_task_output(gid, output).await
+    }
+
+    async fn find_tasks(&self, ssn_id: SessionID) -> Result<Vec<Task>, FlameError> {
+        self.check("find_tasks")?;
+        self.engine.find_tasks(ssn_id).await
+    }
+
+    async fn update_tasks(&self, updates: Vec<TaskUpdate>) -> Result<(), FlameError> {
+        self.check("update_tasks")?;
+        self.engine.update_tasks(updates).await
+    }
+
+    async fn put_cached_output(
+        &self,
+        output: CachedOutput,
+        max_entries: usize,
+        expired: DateTime<Utc>,
+    ) -> Result<(), FlameError> {
+        self.check("put_cached_output")?;
+        self.engine
+            .put_cached_output(output, max_entries, expired)
+            .await
+    }
+
+    async fn find_cached_output(
+        &self,
+        key: &CacheKey,
+        expired: DateTime<Utc>,
+    ) -> Result<Option<CachedOutput>, FlameError> {
+        self.check("find_cached_output")?;
+        self.engine.find_cached_output(key, expired).await
+    }
+
+    async fn put_events(
+        &self,
+        events: Vec<(SessionID, SessionEvent)>,
+        max_events: usize,
+    ) -> Result<(), FlameError> {
+        self.check("put_events")?;
+        self.engine.put_events(events, max_events).await
+    }
+
+    async fn find_events(
+        &self,
+        ssn_id: SessionID,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SessionEvent>, FlameError> {
+        self.check("find_events")?;
+        self.engine.find_events(ssn_id, since, until).await
+    }
+
+    async fn put_output_chunks(
+        &self,
+        gid: TaskGID,
+        chunks: Vec<OutputChunk>,
+    ) -> Result<(), FlameError> {
+        self.check("put_output_chunks")?;
+        self.engine.put_output_chunks(gid, chunks).await
+    }
+
+    async fn find_output_chunks(
+        &self,
+        gid: TaskGID,
+        since: u64,
+        limit: usize,
+    ) -> Result<Vec<OutputChunk>, FlameError> {
+        self.check("find_output_chunks")?;
+        self.engine.find_output_chunks(gid, since, limit).await
+    }
+
+    async fn register_application(&self, app: Application) -> Result<Application, FlameError> {
+        self.check("register_application")?;
+        self.engine.register_application(app).await
+    }
+
+    async fn delete_application(&self, name: String) -> Result<Application, FlameError> {
+        self.check("delete_application")?;
+        self.engine.delete_application(name).await
+    }
+
+    async fn find_application(&self) -> Result<Vec<Application>, FlameError> {
+        self.check("find_application")?;
+        self.engine.find_application().await
+    }
+}
@@ -0,0 +1,645 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::FlameError;
+use common::apis::{
+    Application, OutputChunk, Session, SessionAttributes, SessionEvent, SessionID, SessionState,
+    SessionStatus, Task, TaskAttributes, TaskCounts, TaskFailure, TaskGID, TaskID, TaskOutput,
+    TaskState,
+};
+use common::lock_ptr;
+use common::ptr::{self, MutexPtr};
+
+use crate::storage::engine::{
+    CacheKey, CachedOutput, Capabilities, Engine, EngineInfo, EnginePtr, TaskUpdate,
+};
+
+/// The engine keeping the data in memory, e.g. for the tests and the short-lived clusters;
+/// nothing is recovered after a restart.
+pub struct MemoryEngine {
+    data: MutexPtr<Data>,
+}
+
+#[derive(Default)]
+struct Data {
+    /// The id of the last session, which is never reused as the autoincrement of sqlite.
+    last_session_id: SessionID,
+    sessions: BTreeMap<SessionID, Session>,
+    tasks: BTreeMap<SessionID, BTreeMap<TaskID, Task>>,
+    /// The index of the next task of each session, which is not reused after deletions.
+    next_task_index: BTreeMap<SessionID, u64>,
+    applications: BTreeMap<String, Application>,
+    /// The cached outputs from the oldest to the latest.
+    outputs: VecDeque<CachedOutput>,
+    /// The events of each session from the oldest to the latest.
+    events: BTreeMap<SessionID, VecDeque<SessionEvent>>,
+    /// The chunks of the outputs of the tasks by their sequences.
+    output_chunks: BTreeMap<TaskGID, BTreeMap<u64, OutputChunk>>,
+}
+
+impl MemoryEngine {
+    pub fn new_ptr() -> EnginePtr {
+        Arc::new(MemoryEngine {
+            data: ptr::new_ptr(Data::default()),
+        })
+    }
+}
+
+impl Data {
+    fn session_mut(&mut self, id: SessionID) -> Result<&mut Session, FlameError> {
+        self.sessions
+            .get_mut(&id)
+            .ok_or(FlameError::NotFound(format!("session <{}>", id)))
+    }
+
+    fn task_mut(&mut self, gid: TaskGID) -> Result<&mut Task, FlameError> {
+        self.tasks
+            .get_mut(&gid.ssn_id)
+            .and_then(|tasks| tasks.get_mut(&gid.task_id))
+            .ok_or(FlameError::NotFound(format!("task <{}>", gid)))
+    }
+
+    /// Creates a pending task in the open session.
+    fn new_task(
+        &mut self,
+        ssn_id: SessionID,
+        attrs: TaskAttributes,
+        original_task_id: Option<TaskID>,
+    ) -> Result<Task, FlameError> {
+        if self.session_mut(ssn_id)?.status.state != SessionState::Open {
+            return Err(FlameError::FailedPrecondition(format!(
+                "session <{}> is not open",
+                ssn_id
+            )));
+        }
+
+        let index = self.next_task_index.entry(ssn_id).or_default();
+        *index += 1;
+        let index = *index - 1;
+
+        let tasks = self.tasks.entry(ssn_id).or_default();
+        let id = tasks.keys().next_back().map(|id| id + 1).unwrap_or(1);
+        let task = Task {
+            id,
+            ssn_id,
+            index,
+            input: attrs.input,
+            output: None,
+            trace_context: attrs.trace_context,
+            labels: attrs.labels,
+            locality: attrs.locality,
+            env: attrs.env,
+            timeout: attrs.timeout,
+            max_retries: attrs.max_retries,
+            progress: None,
+            failure: None,
+            executor_id: None,
+            hostname: None,
+            annotations: HashMap::new(),
+            original_task_id,
+            idempotency_key: attrs.idempotency_key,
+            output_chunks: 0,
+            spilled: false,
+            creation_time: now(),
+            completion_time: None,
+            state: TaskState::Pending,
+        };
+        tasks.insert(id, task.clone());
+
+        Ok(task)
+    }
+}
+
+/// The timestamps are kept in seconds as the other engines.
+fn now() -> DateTime<Utc> {
+    DateTime::<Utc>::from_timestamp(Utc::now().timestamp(), 0).unwrap_or_default()
+}
+
+#[async_trait]
+impl Engine for MemoryEngine {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { persistent: false }
+    }
+
+    fn info(&self) -> EngineInfo {
+        // It's built into the session manager.
+        EngineInfo {
+            kind: "memory".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    async fn create_session(&self, attrs: SessionAttributes) -> Result<Session, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        data.last_session_id += 1;
+        let ssn = Session {
+            id: data.last_session_id,
+            namespace: attrs.namespace,
+            application: attrs.application,
+            slots: attrs.slots,
+            labels: attrs.labels,
+            common_data: attrs.common_data,
+            config: attrs.config,
+            max_pending_tasks: attrs.max_pending_tasks,
+            max_running_tasks: attrs.max_running_tasks,
+            cache_scope: attrs.cache_scope,
+            deadline: attrs.deadline,
+            task_timeout: attrs.task_timeout,
+            max_task_retries: attrs.max_task_retries,
+            cloned_from: attrs.cloned_from,
+            priority_class: attrs.priority_class,
+            application_spec: attrs.application_spec,
+            last_activity: None,
+            annotations: HashMap::new(),
+            creation_time: now(),
+            completion_time: None,
+            tasks: HashMap::new(),
+            tasks_index: HashMap::new(),
+            idempotency_keys: HashMap::new(),
+            status: SessionStatus {
+                state: SessionState::Open,
+                counts: TaskCounts::default(),
+                unschedulable: None,
+                generation: 0,
+            },
+        };
+        data.sessions.insert(ssn.id, ssn.clone());
+
+        Ok(ssn)
+    }
+
+    async fn get_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        let data = lock_ptr!(self.data)?;
+        data.sessions
+            .get(&id)
+            .cloned()
+            .ok_or(FlameError::NotFound(format!("session <{}>", id)))
+    }
+
+    async fn open_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        let ssn = data.session_mut(id)?;
+        ssn.status.state = SessionState::Open;
+        ssn.completion_time = None;
+
+        Ok(ssn.clone())
+    }
+
+    async fn close_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        data.session_mut(id)?;
+        let open_tasks = data.tasks.get(&id).is_some_and(|tasks| {
+            tasks
+                .values()
+                .any(|t| !matches!(t.state, TaskState::Failed | TaskState::Succeed))
+        });
+        if open_tasks {
+            return Err(FlameError::FailedPrecondition(format!(
+                "session <{}> has open tasks",
+                id
+            )));
+        }
+
+        let ssn = data.session_mut(id)?;
+        ssn.status.state = SessionState::Closed;
+        ssn.completion_time = Some(now());
+
+        Ok(ssn.clone())
+    }
+
+    async fn update_session_deadline(
+        &self,
+        id: SessionID,
+        deadline: Option<DateTime<Utc>>,
+    ) -> Result<Session, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        let ssn = data.session_mut(id)?;
+        if ssn.status.state != SessionState::Open {
+            return Err(FlameError::FailedPrecondition(format!(
+                "session <{}> is not open",
+                id
+            )));
+        }
+        ssn.deadline = deadline;
+
+        Ok(ssn.clone())
+    }
+
+    async fn update_session_activity(
+        &self,
+        id: SessionID,
+        last_activity: DateTime<Utc>,
+    ) -> Result<Session, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        let ssn = data.session_mut(id)?;
+        ssn.last_activity = Some(last_activity);
+
+        Ok(ssn.clone())
+    }
+
+    async fn update_session_annotations(
+        &self,
+        id: SessionID,
+        annotations: &HashMap<String, String>,
+    ) -> Result<Session, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        let ssn = data.session_mut(id)?;
+        ssn.annotations = annotations.clone();
+
+        Ok(ssn.clone())
+    }
+
+    async fn delete_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        if data.session_mut(id)?.status.state != SessionState::Closed {
+            return Err(FlameError::FailedPrecondition(format!(
+                "session <{}> is not closed",
+                id
+            )));
+        }
+        data.tasks.remove(&id);
+        data.next_task_index.remove(&id);
+        data.outputs.retain(|o| o.key.ssn_id != id || o.key.shared);
+        data.events.remove(&id);
+        data.output_chunks.retain(|gid, _| gid.ssn_id != id);
+
+        data.sessions
+            .remove(&id)
+            .ok_or(FlameError::NotFound(format!("session <{}>", id)))
+    }
+
+    async fn find_session(&self) -> Result<Vec<Session>, FlameError> {
+        let data = lock_ptr!(self.data)?;
+        Ok(data.sessions.values().cloned().collect())
+    }
+
+    async fn import_session(&self, ssn: Session, tasks: Vec<Task>) -> Result<Session, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        let id = data.last_session_id + 1;
+        let mut imported = BTreeMap::new();
+        for task in tasks {
+            if imported.contains_key(&task.id) {
+                return Err(FlameError::AlreadyExists(format!(
+                    "task <{}/{}>",
+                    id, task.id
+                )));
+            }
+            imported.insert(task.id, Task { ssn_id: id, ..task });
+        }
+
+        data.last_session_id = id;
+        let ssn = Session {
+            id,
+            tasks: HashMap::new(),
+            tasks_index: HashMap::new(),
+            idempotency_keys: HashMap::new(),
+            status: SessionStatus {
+                state: SessionState::Closed,
+                counts: TaskCounts::default(),
+                unschedulable: None,
+                generation: 0,
+            },
+            ..ssn
+        };
+        let next_index = imported.values().map(|t| t.index + 1).max();
+        data.sessions.insert(id, ssn.clone());
+        data.tasks.insert(id, imported);
+        data.next_task_index
+            .insert(id, next_index.unwrap_or_default());
+
+        Ok(ssn)
+    }
+
+    async fn create_task(
+        &self,
+        ssn_id: SessionID,
+        attrs: TaskAttributes,
+    ) -> Result<Task, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+        data.new_task(ssn_id, attrs, None)
+    }
+
+    async fn resubmit_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        let original = data.task_mut(gid)?;
+        let attrs = TaskAttributes {
+            input: original.input.clone(),
+            trace_context: original.trace_context.clone(),
+            labels: original.labels.clone(),
+            locality: original.locality.clone(),
+            env: original.env.clone(),
+            timeout: original.timeout,
+            max_retries: original.max_retries,
+            idempotency_key: None,
+        };
+        data.new_task(gid.ssn_id, attrs, Some(gid.task_id))
+    }
+
+    async fn get_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        let data = lock_ptr!(self.data)?;
+        data.tasks
+            .get(&gid.ssn_id)
+            .and_then(|tasks| tasks.get(&gid.task_id))
+            .cloned()
+            .ok_or(FlameError::NotFound(format!("task <{}>", gid)))
+    }
+
+    async fn retry_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        let task = data.task_mut(gid)?;
+        task.state = TaskState::Pending;
+
+        Ok(task.clone())
+    }
+
+    async fn delete_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        let task = data
+            .tasks
+            .get_mut(&gid.ssn_id)
+            .and_then(|tasks| tasks.remove(&gid.task_id))
+            .ok_or(FlameError::NotFound(format!("task <{}>", gid)))?;
+        data.output_chunks.remove(&gid);
+
+        Ok(task)
+    }
+
+    async fn update_task_state(&self, gid: TaskGID, state: TaskState) -> Result<Task, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        let task = data.task_mut(gid)?;
+        task.completion_time = match state {
+            TaskState::Failed | TaskState::Succeed => Some(now()),
+            _ => None,
+        };
+        if state == TaskState::Succeed {
+            task.failure = None;
+        }
+        task.state = state;
+
+        Ok(task.clone())
+    }
+
+    async fn update_task_placement(
+        &self,
+        gid: TaskGID,
+        executor_id: &str,
+        hostname: &str,
+    ) -> Result<Task, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        let task = data.task_mut(gid)?;
+        task.executor_id = Some(executor_id.to_string());
+        task.hostname = Some(hostname.to_string());
+
+        Ok(task.clone())
+    }
+
+    async fn update_task_failure(
+        &self,
+        gid: TaskGID,
+        failure: Option<&TaskFailure>,
+    ) -> Result<Task, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        let task = data.task_mut(gid)?;
+        task.failure = failure.cloned();
+
+        Ok(task.clone())
+    }
+
+    async fn update_task_annotations(
+        &self,
+        gid: TaskGID,
+        annotations: &HashMap<String, String>,
+    ) -> Result<Task, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        let task = data.task_mut(gid)?;
+        task.annotations = annotations.clone();
+
+        Ok(task.clone())
+    }
+
+    async fn update_task_output(
+        &self,
+        gid: TaskGID,
+        output: Option<&TaskOutput>,
+    ) -> Result<Task, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        let task = data.task_mut(gid)?;
+        task.output = output.cloned();
+
+        Ok(task.clone())
+    }
+
+    async fn find_tasks(&self, ssn_id: SessionID) -> Result<Vec<Task>, FlameError> {
+        let data = lock_ptr!(self.data)?;
+
+        Ok(data
+            .tasks
+            .get(&ssn_id)
+            .map(|tasks| tasks.values().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn update_tasks(&self, updates: Vec<TaskUpdate>) -> Result<(), FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        for update in updates {
+            let Ok(task) = data.task_mut(update.gid) else {
+                continue;
+            };
+            task.completion_time = update
+                .completion_time
+                .and_then(|t| DateTime::<Utc>::from_timestamp(t.timestamp(), 0));
+            if update.state == TaskState::Succeed {
+                task.failure = None;
+            }
+            if update.output.is_some() {
+                task.output = update.output;
+            }
+            task.state = update.state;
+        }
+
+        Ok(())
+    }
+
+    async fn put_cached_output(
+        &self,
+        output: CachedOutput,
+        max_entries: usize,
+        expired: DateTime<Utc>,
+    ) -> Result<(), FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        data.outputs.retain(|o| {
+            o.creation_time >= expired
+                && (o.key.digest != output.key.digest || o.key.ssn_id != output.key.ssn_id)
+        });
+        data.outputs.push_back(output);
+        while data.outputs.len() > max_entries {
+            data.outputs.pop_front();
+        }
+
+        Ok(())
+    }
+
+    async fn find_cached_output(
+        &self,
+        key: &CacheKey,
+        expired: DateTime<Utc>,
+    ) -> Result<Option<CachedOutput>, FlameError> {
+        let data = lock_ptr!(self.data)?;
+
+        Ok(data
+            .outputs
+            .iter()
+            .rev()
+            .find(|o| o.creation_time >= expired && o.key.matches(key))
+            .cloned())
+    }
+
+    async fn put_events(
+        &self,
+        events: Vec<(SessionID, SessionEvent)>,
+        max_events: usize,
+    ) -> Result<(), FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        for (ssn_id, event) in events {
+            if !data.sessions.contains_key(&ssn_id) {
+                continue;
+            }
+            let events = data.events.entry(ssn_id).or_default();
+            events.push_back(event);
+            while events.len() > max_events {
+                events.pop_front();
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn find_events(
+        &self,
+        ssn_id: SessionID,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SessionEvent>, FlameError> {
+        let data = lock_ptr!(self.data)?;
+
+        Ok(data
+            .events
+            .get(&ssn_id)
+            .into_iter()
+            .flatten()
+            .filter(|e| since.is_none_or(|t| e.event_time >= t))
+            .filter(|e| until.is_none_or(|t| e.event_time < t))
+            .cloned()
+            .collect())
+    }
+
+    async fn put_output_chunks(
+        &self,
+        gid: TaskGID,
+        chunks: Vec<OutputChunk>,
+    ) -> Result<(), FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        if data.task_mut(gid).is_err() {
+            return Ok(());
+        }
+        let output_chunks = data.output_chunks.entry(gid).or_default();
+        for chunk in chunks {
+            output_chunks.insert(chunk.sequence, chunk);
+        }
+
+        Ok(())
+    }
+
+    async fn find_output_chunks(
+        &self,
+        gid: TaskGID,
+        since: u64,
+        limit: usize,
+    ) -> Result<Vec<OutputChunk>, FlameError> {
+        let data = lock_ptr!(self.data)?;
+
+        Ok(data
+            .output_chunks
+            .get(&gid)
+            .into_iter()
+            .flat_map(|chunks| chunks.range(since.saturating_add(1)..))
+            .take(limit)
+            .map(|(_, chunk)| chunk.clone())
+            .collect())
+    }
+
+    async fn register_application(&self, app: Application) -> Result<Application, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        if data.applications.contains_key(&app.name) {
+            return Err(FlameError::AlreadyExists(format!(
+                "application <{}>",
+                app.name
+            )));
+        }
+        data.applications.insert(app.name.clone(), app.clone());
+
+        Ok(app)
+    }
+
+    async fn delete_application(&self, name: String) -> Result<Application, FlameError> {
+        let mut data = lock_ptr!(self.data)?;
+
+        data.applications
+            .remove(&name)
+            .ok_or(FlameError::NotFound(format!("application <{}>", name)))
+    }
+
+    async fn find_application(&self) -> Result<Vec<Application>, FlameError> {
+        let data = lock_ptr!(self.data)?;
+        Ok(data.applications.values().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::storage::engine::conformance;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_conformance() {
+        conformance::verify(|_| async { Ok(MemoryEngine::new_ptr()) }).await;
+    }
+}
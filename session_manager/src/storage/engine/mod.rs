@@ -11,42 +11,246 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
 
 use crate::FlameError;
-use common::apis::{CommonData, Session, SessionID, Task, TaskGID, TaskInput, TaskState};
+use common::apis::{
+    Application, OutputChunk, Session, SessionAttributes, SessionEvent, SessionID, Task,
+    TaskAttributes, TaskFailure, TaskGID, TaskOutput, TaskState,
+};
 
+#[cfg(test)]
+mod conformance;
+#[cfg(test)]
+pub mod faulty;
+mod memory;
+pub mod monitor;
+pub mod retry;
 mod sqlite;
 
 pub type EnginePtr = Arc<dyn Engine>;
 
+/// The differences of the engines which are allowed by the contract, see `conformance`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The data is recovered after a restart of the session manager.
+    pub persistent: bool,
+}
+
+/// The key of the cached output of a task, see `CachedOutput`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheKey {
+    /// The digest of the input and the common data of the task.
+    pub digest: String,
+    pub namespace: String,
+    pub application: String,
+    pub ssn_id: SessionID,
+    /// The output is shared with the other sessions of the application, which also share
+    /// their outputs.
+    pub shared: bool,
+}
+
+impl CacheKey {
+    /// Whether the output of this key can be reused by the task of the other key.
+    pub fn matches(&self, other: &CacheKey) -> bool {
+        self.digest == other.digest
+            && self.namespace == other.namespace
+            && self.application == other.application
+            && (self.ssn_id == other.ssn_id || (self.shared && other.shared))
+    }
+}
+
+/// The output of a succeeded task, which is reused by the new tasks with the same digest.
+#[derive(Clone, Debug)]
+pub struct CachedOutput {
+    pub key: CacheKey,
+    pub output: Option<TaskOutput>,
+    pub creation_time: DateTime<Utc>,
+}
+
+/// A state change of a task written behind by the storage, see `Engine::update_tasks`.
+#[derive(Clone, Debug)]
+pub struct TaskUpdate {
+    pub gid: TaskGID,
+    pub state: TaskState,
+    /// The time the task was completed, if it's completed by the change.
+    pub completion_time: Option<DateTime<Utc>>,
+    /// The output recorded with the change, e.g. the task succeeded; the output of the task
+    /// is kept if none.
+    pub output: Option<TaskOutput>,
+}
+
+/// The kind and the version of an engine, e.g. to debug the deployments.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct EngineInfo {
+    pub kind: String,
+    pub version: String,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities { persistent: true }
+    }
+}
+
+/// The storage engine of the session manager; all engines have to pass the conformance
+/// tests in `conformance`, which is the contract of this trait.
 #[async_trait]
 pub trait Engine: Send + Sync + 'static {
-    async fn create_session(
-        &self,
-        app: String,
-        slots: i32,
-        common_data: Option<CommonData>,
-    ) -> Result<Session, FlameError>;
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn info(&self) -> EngineInfo;
+
+    async fn create_session(&self, attrs: SessionAttributes) -> Result<Session, FlameError>;
     async fn get_session(&self, id: SessionID) -> Result<Session, FlameError>;
+    async fn open_session(&self, id: SessionID) -> Result<Session, FlameError>;
     async fn close_session(&self, id: SessionID) -> Result<Session, FlameError>;
+    /// Sets the deadline of the session, which has to be open.
+    async fn update_session_deadline(
+        &self,
+        id: SessionID,
+        deadline: Option<DateTime<Utc>>,
+    ) -> Result<Session, FlameError>;
+    /// Records the last call of the client on the session, whatever its state.
+    async fn update_session_activity(
+        &self,
+        id: SessionID,
+        last_activity: DateTime<Utc>,
+    ) -> Result<Session, FlameError>;
+    /// Replaces the annotations of the session, whatever its state; they're merged by the
+    /// storage.
+    async fn update_session_annotations(
+        &self,
+        id: SessionID,
+        annotations: &HashMap<String, String>,
+    ) -> Result<Session, FlameError>;
     async fn delete_session(&self, id: SessionID) -> Result<Session, FlameError>;
     async fn find_session(&self) -> Result<Vec<Session>, FlameError>;
+    /// Creates a closed session with the attributes, timestamps and tasks of an archived one;
+    /// the session gets a new id, and the tasks keep their ids, states and outputs.
+    async fn import_session(&self, ssn: Session, tasks: Vec<Task>) -> Result<Session, FlameError>;
 
     async fn create_task(
         &self,
         ssn_id: SessionID,
-        task_input: Option<TaskInput>,
+        attrs: TaskAttributes,
     ) -> Result<Task, FlameError>;
+    /// Creates a pending task in the open session with the input, the trace context, the
+    /// labels, the locality and the settings of the task, which is recorded as its original
+    /// task.
+    async fn resubmit_task(&self, gid: TaskGID) -> Result<Task, FlameError>;
     async fn get_task(&self, gid: TaskGID) -> Result<Task, FlameError>;
     async fn retry_task(&self, gid: TaskGID) -> Result<Task, FlameError>;
     async fn delete_task(&self, gid: TaskGID) -> Result<Task, FlameError>;
     async fn update_task_state(&self, gid: TaskGID, state: TaskState) -> Result<Task, FlameError>;
+    /// Records the executor launching the task and its host, which replace the ones of the
+    /// previous launch.
+    async fn update_task_placement(
+        &self,
+        gid: TaskGID,
+        executor_id: &str,
+        hostname: &str,
+    ) -> Result<Task, FlameError>;
+    /// Records the last failure of the task, or clears it if none; the failure is kept by
+    /// `update_task_state` unless the task succeeds.
+    async fn update_task_failure(
+        &self,
+        gid: TaskGID,
+        failure: Option<&TaskFailure>,
+    ) -> Result<Task, FlameError>;
+    /// Records the output of the task, e.g. before it succeeds; the output is kept by
+    /// `update_task_state`.
+    async fn update_task_output(
+        &self,
+        gid: TaskGID,
+        output: Option<&TaskOutput>,
+    ) -> Result<Task, FlameError>;
+    /// Replaces the annotations of the task, which are kept by `retry_task` and
+    /// `update_task_state`.
+    async fn update_task_annotations(
+        &self,
+        gid: TaskGID,
+        annotations: &HashMap<String, String>,
+    ) -> Result<Task, FlameError>;
     async fn find_tasks(&self, ssn_id: SessionID) -> Result<Vec<Task>, FlameError>;
+    /// Applies the state changes of the tasks in order, as `update_task_state` and
+    /// `update_task_output` do, in one transaction, so either all or none of them are
+    /// persisted; the changes of the missing tasks are skipped, e.g. they were deleted.
+    async fn update_tasks(&self, updates: Vec<TaskUpdate>) -> Result<(), FlameError>;
+
+    /// Caches the output, which replaces the one of the same digest in the session; the
+    /// outputs created before `expired` are removed, and the oldest ones are evicted if there
+    /// are more than `max_entries`.
+    async fn put_cached_output(
+        &self,
+        output: CachedOutput,
+        max_entries: usize,
+        expired: DateTime<Utc>,
+    ) -> Result<(), FlameError>;
+    /// Finds the latest cached output which matches the key and is not expired.
+    async fn find_cached_output(
+        &self,
+        key: &CacheKey,
+        expired: DateTime<Utc>,
+    ) -> Result<Option<CachedOutput>, FlameError>;
+
+    /// Appends the events of the sessions in their order; the oldest events of a session are
+    /// evicted if it has more than `max_events`. The events of the unknown sessions are
+    /// dropped, and the events are deleted with their sessions.
+    async fn put_events(
+        &self,
+        events: Vec<(SessionID, SessionEvent)>,
+        max_events: usize,
+    ) -> Result<(), FlameError>;
+    /// Finds the events of the session in `[since, until)` in the order they were put.
+    async fn find_events(
+        &self,
+        ssn_id: SessionID,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SessionEvent>, FlameError>;
+
+    /// Appends the chunks of the output of the task, which replace the ones of the same
+    /// sequences, e.g. they're streamed again by the task retried after a restart. The chunks
+    /// of the unknown tasks are dropped, and the chunks are deleted with their tasks.
+    async fn put_output_chunks(
+        &self,
+        gid: TaskGID,
+        chunks: Vec<OutputChunk>,
+    ) -> Result<(), FlameError>;
+    /// Finds at most `limit` chunks of the output of the task after the `since` sequence, in
+    /// the order of their sequences.
+    async fn find_output_chunks(
+        &self,
+        gid: TaskGID,
+        since: u64,
+        limit: usize,
+    ) -> Result<Vec<OutputChunk>, FlameError>;
+
+    async fn register_application(&self, app: Application) -> Result<Application, FlameError>;
+    async fn delete_application(&self, name: String) -> Result<Application, FlameError>;
+    async fn find_application(&self) -> Result<Vec<Application>, FlameError>;
 }
 
+/// Connects to the engine of the url; the latency of its calls is recorded by `monitor`.
 pub async fn connect(url: &str) -> Result<EnginePtr, FlameError> {
-    sqlite::SqliteEngine::new_ptr(url).await
+    let engine = match url.split_once("://").map(|(scheme, _)| scheme) {
+        Some("memory") => memory::MemoryEngine::new_ptr(),
+        _ => sqlite::SqliteEngine::new_ptr(url).await?,
+    };
+
+    Ok(decorate(engine))
+}
+
+/// Wraps the engine, so its transient failures are retried by `retry`, and the latency of
+/// the calls, including the retries, is recorded by `monitor`.
+pub fn decorate(engine: EnginePtr) -> EnginePtr {
+    monitor::MonitoredEngine::new_ptr(retry::RetryEngine::new_ptr(engine))
 }
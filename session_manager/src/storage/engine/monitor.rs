@@ -0,0 +1,487 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::FlameError;
+use common::apis::{
+    Application, OutputChunk, Session, SessionAttributes, SessionEvent, SessionID, Task,
+    TaskAttributes, TaskFailure, TaskGID, TaskOutput, TaskState,
+};
+
+use crate::storage::engine::{
+    CacheKey, CachedOutput, Capabilities, Engine, EngineInfo, EnginePtr, TaskUpdate,
+};
+
+/// The budget of the calls of the engines in nanoseconds; the calls are not timed if it's 0.
+static BUDGET: AtomicU64 = AtomicU64::new(0);
+/// The number of the calls of the engines which exceeded the budget.
+static SLOW_CALLS: AtomicU64 = AtomicU64::new(0);
+
+tokio::task_local! {
+    /// The deadline of the caller of the engine calls in the scope, see `with_deadline`.
+    static DEADLINE: Cell<Option<Instant>>;
+}
+
+/// Sets the budget of the calls of the engines, or disables the monitoring of the calls if
+/// none.
+pub fn set_budget(budget: Option<Duration>) {
+    let nanos = budget.map(|b| b.as_nanos().clamp(1, u64::MAX as u128) as u64);
+    BUDGET.store(nanos.unwrap_or_default(), Ordering::Relaxed);
+}
+
+/// The number of the calls of the engines which exceeded the budget.
+pub fn slow_calls() -> u64 {
+    SLOW_CALLS.load(Ordering::Relaxed)
+}
+
+/// Runs the call of the engine, which is logged with the operation and the ids involved if it
+/// takes longer than the budget.
+async fn observe<T>(op: &'static str, ids: impl Display, call: impl Future<Output = T>) -> T {
+    let budget = BUDGET.load(Ordering::Relaxed);
+    if budget == 0 {
+        return call.await;
+    }
+
+    let start = Instant::now();
+    let res = call.await;
+    let elapsed = start.elapsed();
+    if elapsed >= Duration::from_nanos(budget) {
+        SLOW_CALLS.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(
+            op,
+            ids = %ids,
+            elapsed_ms = elapsed.as_millis() as u64,
+            "The engine call <{}> of <{}> took {:?}.",
+            op,
+            ids,
+            elapsed
+        );
+    }
+
+    res
+}
+
+/// Runs the calls of the storage for a caller with the deadline: the writes of the engine are
+/// skipped once it passed, so no work is done for a caller which is gone. Only the first
+/// write is checked, so a change is never left half-written.
+pub async fn with_deadline<F: Future>(deadline: Option<Instant>, call: F) -> F::Output {
+    DEADLINE.scope(Cell::new(deadline), call).await
+}
+
+/// Runs the write of the engine as `observe`, unless it's the first write of a caller whose
+/// deadline passed.
+async fn write<T>(
+    op: &'static str,
+    ids: impl Display,
+    call: impl Future<Output = Result<T, FlameError>>,
+) -> Result<T, FlameError> {
+    let deadline = DEADLINE.try_with(Cell::take).ok().flatten();
+    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        log::debug!(
+            "Skip the engine call <{}> of <{}> of an expired caller.",
+            op,
+            ids
+        );
+        return Err(FlameError::DeadlineExceeded(format!(
+            "the caller was gone before <{}> of <{}>",
+            op, ids
+        )));
+    }
+
+    observe(op, ids, call).await
+}
+
+/// The engine recording the latency of the calls of another engine, and skipping the writes
+/// of the expired callers.
+pub struct MonitoredEngine {
+    engine: EnginePtr,
+}
+
+impl MonitoredEngine {
+    pub fn new_ptr(engine: EnginePtr) -> EnginePtr {
+        Arc::new(MonitoredEngine { engine })
+    }
+}
+
+#[async_trait]
+impl Engine for MonitoredEngine {
+    fn capabilities(&self) -> Capabilities {
+        self.engine.capabilities()
+    }
+
+    fn info(&self) -> EngineInfo {
+        self.engine.info()
+    }
+
+    async fn create_session(&self, attrs: SessionAttributes) -> Result<Session, FlameError> {
+        let app = attrs.application.clone();
+        write("create_session", app, self.engine.create_session(attrs)).await
+    }
+
+    async fn get_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        observe("get_session", id, self.engine.get_session(id)).await
+    }
+
+    async fn open_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        write("open_session", id, self.engine.open_session(id)).await
+    }
+
+    async fn close_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        write("close_session", id, self.engine.close_session(id)).await
+    }
+
+    async fn update_session_deadline(
+        &self,
+        id: SessionID,
+        deadline: Option<DateTime<Utc>>,
+    ) -> Result<Session, FlameError> {
+        let call = self.engine.update_session_deadline(id, deadline);
+        write("update_session_deadline", id, call).await
+    }
+
+    async fn update_session_activity(
+        &self,
+        id: SessionID,
+        last_activity: DateTime<Utc>,
+    ) -> Result<Session, FlameError> {
+        let call = self.engine.update_session_activity(id, last_activity);
+        write("update_session_activity", id, call).await
+    }
+
+    async fn update_session_annotations(
+        &self,
+        id: SessionID,
+        annotations: &HashMap<String, String>,
+    ) -> Result<Session, FlameError> {
+        let call = self.engine.update_session_annotations(id, annotations);
+        write("update_session_annotations", id, call).await
+    }
+
+    async fn delete_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        write("delete_session", id, self.engine.delete_session(id)).await
+    }
+
+    async fn find_session(&self) -> Result<Vec<Session>, FlameError> {
+        observe("find_session", "", self.engine.find_session()).await
+    }
+
+    async fn import_session(&self, ssn: Session, tasks: Vec<Task>) -> Result<Session, FlameError> {
+        let id = ssn.id;
+        write("import_session", id, self.engine.import_session(ssn, tasks)).await
+    }
+
+    async fn create_task(
+        &self,
+        ssn_id: SessionID,
+        attrs: TaskAttributes,
+    ) -> Result<Task, FlameError> {
+        let call = self.engine.create_task(ssn_id, attrs);
+        write("create_task", ssn_id, call).await
+    }
+
+    async fn resubmit_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        write("resubmit_task", gid, self.engine.resubmit_task(gid)).await
+    }
+
+    async fn get_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        observe("get_task", gid, self.engine.get_task(gid)).await
+    }
+
+    async fn retry_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        write("retry_task", gid, self.engine.retry_task(gid)).await
+    }
+
+    async fn delete_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        write("delete_task", gid, self.engine.delete_task(gid)).await
+    }
+
+    async fn update_task_state(&self, gid: TaskGID, state: TaskState) -> Result<Task, FlameError> {
+        let call = self.engine.update_task_state(gid, state);
+        write("update_task_state", gid, call).await
+    }
+
+    async fn update_task_placement(
+        &self,
+        gid: TaskGID,
+        executor_id: &str,
+        hostname: &str,
+    ) -> Result<Task, FlameError> {
+        let call = self
+            .engine
+            .update_task_placement(gid, executor_id, hostname);
+        write("update_task_placement", gid, call).await
+    }
+
+    async fn update_task_failure(
+        &self,
+        gid: TaskGID,
+        failure: Option<&TaskFailure>,
+    ) -> Result<Task, FlameError> {
+        let call = self.engine.update_task_failure(gid, failure);
+        write("update_task_failure", gid, call).await
+    }
+
+    async fn update_task_annotations(
+        &self,
+        gid: TaskGID,
+        annotations: &HashMap<String, String>,
+    ) -> Result<Task, FlameError> {
+        let call = self.engine.update_task_annotations(gid, annotations);
+        write("update_task_annotations", gid, call).await
+    }
+
+    async fn update_task_output(
+        &self,
+        gid: TaskGID,
+        output: Option<&TaskOutput>,
+    ) -> Result<Task, FlameError> {
+        let call = self.engine.update_task_output(gid, output);
+        write("update_task_output", gid, call).await
+    }
+
+    async fn find_tasks(&self, ssn_id: SessionID) -> Result<Vec<Task>, FlameError> {
+        observe("find_tasks", ssn_id, self.engine.find_tasks(ssn_id)).await
+    }
+
+    /// The batch carries the changes of many callers, so it's never skipped for one of them.
+    async fn update_tasks(&self, updates: Vec<TaskUpdate>) -> Result<(), FlameError> {
+        let n = updates.len();
+        observe("update_tasks", n, self.engine.update_tasks(updates)).await
+    }
+
+    async fn put_cached_output(
+        &self,
+        output: CachedOutput,
+        max_entries: usize,
+        expired: DateTime<Utc>,
+    ) -> Result<(), FlameError> {
+        let ssn_id = output.key.ssn_id;
+        let call = self.engine.put_cached_output(output, max_entries, expired);
+        write("put_cached_output", ssn_id, call).await
+    }
+
+    async fn find_cached_output(
+        &self,
+        key: &CacheKey,
+        expired: DateTime<Utc>,
+    ) -> Result<Option<CachedOutput>, FlameError> {
+        let call = self.engine.find_cached_output(key, expired);
+        observe("find_cached_output", key.ssn_id, call).await
+    }
+
+    async fn put_events(
+        &self,
+        events: Vec<(SessionID, SessionEvent)>,
+        max_events: usize,
+    ) -> Result<(), FlameError> {
+        let n = events.len();
+        write("put_events", n, self.engine.put_events(events, max_events)).await
+    }
+
+    async fn find_events(
+        &self,
+        ssn_id: SessionID,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SessionEvent>, FlameError> {
+        let call = self.engine.find_events(ssn_id, since, until);
+        observe("find_events", ssn_id, call).await
+    }
+
+    async fn put_output_chunks(
+        &self,
+        gid: TaskGID,
+        chunks: Vec<OutputChunk>,
+    ) -> Result<(), FlameError> {
+        let call = self.engine.put_output_chunks(gid, chunks);
+        write("put_output_chunks", gid, call).await
+    }
+
+    async fn find_output_chunks(
+        &self,
+        gid: TaskGID,
+        since: u64,
+        limit: usize,
+    ) -> Result<Vec<OutputChunk>, FlameError> {
+        let call = self.engine.find_output_chunks(gid, since, limit);
+        observe("find_output_chunks", gid, call).await
+    }
+
+    async fn register_application(&self, app: Application) -> Result<Application, FlameError> {
+        let name = app.name.clone();
+        write(
+            "register_application",
+            name,
+            self.engine.register_application(app),
+        )
+        .await
+    }
+
+    async fn delete_application(&self, name: String) -> Result<Application, FlameError> {
+        let call = self.engine.delete_application(name.clone());
+        write("delete_application", name, call).await
+    }
+
+    async fn find_application(&self) -> Result<Vec<Application>, FlameError> {
+        observe("find_application", "", self.engine.find_application()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use tracing::field::{Field, Visit};
+    use tracing::{Event, Subscriber};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::Layer;
+
+    use super::*;
+    use crate::storage::engine;
+
+    /// Captures the operations and the ids of the warnings.
+    #[derive(Clone, Default)]
+    struct Calls(Arc<Mutex<Vec<HashMap<String, String>>>>);
+
+    #[derive(Default)]
+    struct Fields(HashMap<String, String>);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for Calls {
+        fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+            self.0.lock().unwrap().push(fields.0);
+        }
+    }
+
+    impl Calls {
+        fn take(&self) -> Vec<(String, String)> {
+            let mut calls = self.0.lock().unwrap();
+            calls
+                .drain(..)
+                .map(|c| (c["op"].clone(), c["ids"].clone()))
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_engine_calls() -> Result<(), FlameError> {
+        let calls = Calls::default();
+        let subscriber = tracing_subscriber::registry().with(calls.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let slow = |ms: u64| tokio::time::sleep(Duration::from_millis(ms));
+        let gid = TaskGID {
+            ssn_id: 1,
+            task_id: 2,
+        };
+
+        // The calls are not timed by default.
+        observe("get_task", gid, slow(100)).await;
+        assert!(calls.take().is_empty());
+
+        set_budget(Some(Duration::from_millis(50)));
+        let before = slow_calls();
+        observe("get_task", gid, slow(0)).await;
+        assert!(calls.take().is_empty());
+        observe("get_task", gid, slow(100)).await;
+        assert_eq!(
+            calls.take(),
+            vec![("get_task".to_string(), "1/2".to_string())]
+        );
+        assert!(slow_calls() > before);
+
+        // The calls of the engine are labeled by their operations.
+        set_budget(Some(Duration::from_nanos(1)));
+        let engine = engine::connect("memory://").await?;
+        let ssn = engine
+            .create_session(SessionAttributes {
+                application: "flmexec".to_string(),
+                ..Default::default()
+            })
+            .await?;
+        engine
+            .create_task(ssn.id, TaskAttributes::default())
+            .await?;
+        set_budget(None);
+
+        assert_eq!(
+            calls.take(),
+            vec![
+                ("create_session".to_string(), "flmexec".to_string()),
+                ("create_task".to_string(), ssn.id.to_string()),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_skip_writes_of_expired_callers() -> Result<(), FlameError> {
+        let slow = |ms: u64| tokio::time::sleep(Duration::from_millis(ms));
+        let writes = AtomicU64::new(0);
+        let put = || async {
+            writes.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        };
+
+        // The write after a slow read is skipped once the caller is gone.
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let res = with_deadline(Some(deadline), async {
+            observe("find_cached_output", 1, slow(100)).await;
+            write("create_task", 1, put()).await
+        });
+        assert!(matches!(res.await, Err(FlameError::DeadlineExceeded(_))));
+        assert_eq!(writes.load(Ordering::SeqCst), 0);
+
+        // The writes after the first one are done, so a change is not left half-written.
+        let deadline = Instant::now() + Duration::from_millis(50);
+        with_deadline(Some(deadline), async {
+            write("create_task", 1, put()).await?;
+            slow(100).await;
+            write("update_task_output", 1, put()).await
+        })
+        .await?;
+        assert_eq!(writes.load(Ordering::SeqCst), 2);
+
+        // The calls without a deadline, e.g. of the scheduler, are never skipped.
+        slow(100).await;
+        write("update_task_state", 1, put()).await?;
+        with_deadline(None, write("update_task_state", 1, put())).await?;
+        assert_eq!(writes.load(Ordering::SeqCst), 4);
+
+        Ok(())
+    }
+}
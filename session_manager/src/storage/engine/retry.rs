@@ -0,0 +1,421 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::FlameError;
+use common::apis::{
+    Application, OutputChunk, Session, SessionAttributes, SessionEvent, SessionID, Task,
+    TaskAttributes, TaskFailure, TaskGID, TaskOutput, TaskState,
+};
+
+use crate::storage::engine::{
+    CacheKey, CachedOutput, Capabilities, Engine, EngineInfo, EnginePtr, TaskUpdate,
+};
+
+const DEFAULT_ATTEMPTS: u32 = 3;
+const DEFAULT_BACKOFF: Duration = Duration::from_millis(50);
+
+/// The attempts of a call of the engines, and the delay before the first retry in
+/// nanoseconds, which is doubled by every retry.
+static ATTEMPTS: AtomicU32 = AtomicU32::new(DEFAULT_ATTEMPTS);
+static BACKOFF: AtomicU64 = AtomicU64::new(DEFAULT_BACKOFF.as_nanos() as u64);
+
+/// Sets the policy of retrying the transient failures of the engines; the defaults, 3
+/// attempts and 50ms, are used if not given.
+pub fn set_policy(attempts: Option<u32>, backoff: Option<Duration>) {
+    let backoff = backoff.unwrap_or(DEFAULT_BACKOFF);
+    ATTEMPTS.store(
+        attempts.unwrap_or(DEFAULT_ATTEMPTS).max(1),
+        Ordering::Relaxed,
+    );
+    BACKOFF.store(
+        backoff.as_nanos().min(u64::MAX as u128) as u64,
+        Ordering::Relaxed,
+    );
+}
+
+/// The failures of the engines which may succeed if retried, e.g. the database is busy;
+/// they're classified by the engines.
+fn is_transient(e: &FlameError) -> bool {
+    matches!(
+        e,
+        FlameError::Unavailable {
+            retryable: true,
+            ..
+        }
+    )
+}
+
+/// Runs the call of the engine until it succeeds, fails for good, or runs out of the attempts;
+/// the last transient failure is returned as `Unavailable`, so the clients retry the request.
+async fn retry<T, F, Fut>(op: &'static str, mut call: F) -> Result<T, FlameError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, FlameError>>,
+{
+    let attempts = ATTEMPTS.load(Ordering::Relaxed).max(1);
+    let mut backoff = Duration::from_nanos(BACKOFF.load(Ordering::Relaxed));
+
+    let mut attempt = 1;
+    loop {
+        match call().await {
+            Err(e) if is_transient(&e) && attempt < attempts => {
+                log::debug!(
+                    "The engine call <{}> failed at attempt {}, retry in {:?}: {}",
+                    op,
+                    attempt,
+                    backoff,
+                    e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(e) if is_transient(&e) => {
+                log::warn!(
+                    "The engine call <{}> failed after {} attempts: {}",
+                    op,
+                    attempts,
+                    e
+                );
+                return Err(FlameError::Unavailable {
+                    message: format!("the storage is unavailable for <{}>: {}", op, e),
+                    retryable: true,
+                });
+            }
+            res => return res,
+        }
+    }
+}
+
+/// The engine retrying the transient failures of another engine; the engines roll back a
+/// failed call, so it's safe to retry any call.
+pub struct RetryEngine {
+    engine: EnginePtr,
+}
+
+impl RetryEngine {
+    pub fn new_ptr(engine: EnginePtr) -> EnginePtr {
+        Arc::new(RetryEngine { engine })
+    }
+}
+
+#[async_trait]
+impl Engine for RetryEngine {
+    fn capabilities(&self) -> Capabilities {
+        self.engine.capabilities()
+    }
+
+    fn info(&self) -> EngineInfo {
+        self.engine.info()
+    }
+
+    async fn create_session(&self, attrs: SessionAttributes) -> Result<Session, FlameError> {
+        retry("create_session", || {
+            self.engine.create_session(attrs.clone())
+        })
+        .await
+    }
+
+    async fn get_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        retry("get_session", || self.engine.get_session(id)).await
+    }
+
+    async fn open_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        retry("open_session", || self.engine.open_session(id)).await
+    }
+
+    async fn close_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        retry("close_session", || self.engine.close_session(id)).await
+    }
+
+    async fn update_session_deadline(
+        &self,
+        id: SessionID,
+        deadline: Option<DateTime<Utc>>,
+    ) -> Result<Session, FlameError> {
+        retry("update_session_deadline", || {
+            self.engine.update_session_deadline(id, deadline)
+        })
+        .await
+    }
+
+    async fn update_session_activity(
+        &self,
+        id: SessionID,
+        last_activity: DateTime<Utc>,
+    ) -> Result<Session, FlameError> {
+        retry("update_session_activity", || {
+            self.engine.update_session_activity(id, last_activity)
+        })
+        .await
+    }
+
+    async fn update_session_annotations(
+        &self,
+        id: SessionID,
+        annotations: &HashMap<String, String>,
+    ) -> Result<Session, FlameError> {
+        retry("update_session_annotations", || {
+            self.engine.update_session_annotations(id, annotations)
+        })
+        .await
+    }
+
+    async fn delete_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        retry("delete_session", || self.engine.delete_session(id)).await
+    }
+
+    async fn find_session(&self) -> Result<Vec<Session>, FlameError> {
+        retry("find_session", || self.engine.find_session()).await
+    }
+
+    async fn import_session(&self, ssn: Session, tasks: Vec<Task>) -> Result<Session, FlameError> {
+        retry("import_session", || {
+            self.engine.import_session(ssn.clone(), tasks.clone())
+        })
+        .await
+    }
+
+    async fn create_task(
+        &self,
+        ssn_id: SessionID,
+        attrs: TaskAttributes,
+    ) -> Result<Task, FlameError> {
+        retry("create_task", || {
+            self.engine.create_task(ssn_id, attrs.clone())
+        })
+        .await
+    }
+
+    async fn resubmit_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        retry("resubmit_task", || self.engine.resubmit_task(gid)).await
+    }
+
+    async fn get_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        retry("get_task", || self.engine.get_task(gid)).await
+    }
+
+    async fn retry_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        retry("retry_task", || self.engine.retry_task(gid)).await
+    }
+
+    async fn delete_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        retry("delete_task", || self.engine.delete_task(gid)).await
+    }
+
+    async fn update_task_state(&self, gid: TaskGID, state: TaskState) -> Result<Task, FlameError> {
+        retry("update_task_state", || {
+            self.engine.update_task_state(gid, state)
+        })
+        .await
+    }
+
+    async fn update_task_placement(
+        &self,
+        gid: TaskGID,
+        executor_id: &str,
+        hostname: &str,
+    ) -> Result<Task, FlameError> {
+        retry("update_task_placement", || {
+            self.engine
+                .update_task_placement(gid, executor_id, hostname)
+        })
+        .await
+    }
+
+    async fn update_task_failure(
+        &self,
+        gid: TaskGID,
+        failure: Option<&TaskFailure>,
+    ) -> Result<Task, FlameError> {
+        retry("update_task_failure", || {
+            self.engine.update_task_failure(gid, failure)
+        })
+        .await
+    }
+
+    async fn update_task_annotations(
+        &self,
+        gid: TaskGID,
+        annotations: &HashMap<String, String>,
+    ) -> Result<Task, FlameError> {
+        retry("update_task_annotations", || {
+            self.engine.update_task_annotations(gid, annotations)
+        })
+        .await
+    }
+
+    async fn update_task_output(
+        &self,
+        gid: TaskGID,
+        output: Option<&TaskOutput>,
+    ) -> Result<Task, FlameError> {
+        retry("update_task_output", || {
+            self.engine.update_task_output(gid, output)
+        })
+        .await
+    }
+
+    async fn find_tasks(&self, ssn_id: SessionID) -> Result<Vec<Task>, FlameError> {
+        retry("find_tasks", || self.engine.find_tasks(ssn_id)).await
+    }
+
+    async fn update_tasks(&self, updates: Vec<TaskUpdate>) -> Result<(), FlameError> {
+        retry("update_tasks", || self.engine.update_tasks(updates.clone())).await
+    }
+
+    async fn put_cached_output(
+        &self,
+        output: CachedOutput,
+        max_entries: usize,
+        expired: DateTime<Utc>,
+    ) -> Result<(), FlameError> {
+        retry("put_cached_output", || {
+            self.engine
+                .put_cached_output(output.clone(), max_entries, expired)
+        })
+        .await
+    }
+
+    async fn find_cached_output(
+        &self,
+        key: &CacheKey,
+        expired: DateTime<Utc>,
+    ) -> Result<Option<CachedOutput>, FlameError> {
+        retry("find_cached_output", || {
+            self.engine.find_cached_output(key, expired)
+        })
+        .await
+    }
+
+    async fn put_events(
+        &self,
+        events: Vec<(SessionID, SessionEvent)>,
+        max_events: usize,
+    ) -> Result<(), FlameError> {
+        retry("put_events", || {
+            self.engine.put_events(events.clone(), max_events)
+        })
+        .await
+    }
+
+    async fn find_events(
+        &self,
+        ssn_id: SessionID,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SessionEvent>, FlameError> {
+        retry("find_events", || {
+            self.engine.find_events(ssn_id, since, until)
+        })
+        .await
+    }
+
+    async fn put_output_chunks(
+        &self,
+        gid: TaskGID,
+        chunks: Vec<OutputChunk>,
+    ) -> Result<(), FlameError> {
+        retry("put_output_chunks", || {
+            self.engine.put_output_chunks(gid, chunks.clone())
+        })
+        .await
+    }
+
+    async fn find_output_chunks(
+        &self,
+        gid: TaskGID,
+        since: u64,
+        limit: usize,
+    ) -> Result<Vec<OutputChunk>, FlameError> {
+        retry("find_output_chunks", || {
+            self.engine.find_output_chunks(gid, since, limit)
+        })
+        .await
+    }
+
+    async fn register_application(&self, app: Application) -> Result<Application, FlameError> {
+        retry("register_application", || {
+            self.engine.register_application(app.clone())
+        })
+        .await
+    }
+
+    async fn delete_application(&self, name: String) -> Result<Application, FlameError> {
+        retry("delete_application", || {
+            self.engine.delete_application(name.clone())
+        })
+        .await
+    }
+
+    async fn find_application(&self) -> Result<Vec<Application>, FlameError> {
+        retry("find_application", || self.engine.find_application()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::engine::faulty::FaultyEngine;
+
+    #[tokio::test]
+    async fn test_retry_transient_failures() -> Result<(), FlameError> {
+        let faulty = FaultyEngine::new_ptr();
+        let engine = RetryEngine::new_ptr(faulty.clone());
+        let attrs = SessionAttributes {
+            application: "flmexec".to_string(),
+            ..Default::default()
+        };
+
+        // The failures within the attempts are not seen by the caller.
+        faulty.fail("create_session", DEFAULT_ATTEMPTS - 1);
+        let ssn = engine.create_session(attrs.clone()).await?;
+        assert_eq!(engine.find_session().await?.len(), 1);
+
+        // The last failure is returned as unavailable, and nothing is created.
+        faulty.fail("create_task", DEFAULT_ATTEMPTS);
+        let err = engine
+            .create_task(ssn.id, TaskAttributes::default())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                FlameError::Unavailable {
+                    retryable: true,
+                    ..
+                }
+            ),
+            "{:?}",
+            err
+        );
+        assert!(err.to_string().contains("create_task"), "{}", err);
+        assert!(engine.find_tasks(ssn.id).await?.is_empty());
+
+        // The other failures are not retried.
+        let err = engine.close_session(ssn.id + 1).await.unwrap_err();
+        assert!(matches!(err, FlameError::NotFound(_)), "{:?}", err);
+        assert_eq!(faulty.calls("close_session"), 1);
+
+        Ok(())
+    }
+}
@@ -17,25 +17,44 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
-use sqlx::{migrate::MigrateDatabase, FromRow, Sqlite, SqlitePool};
+use prost::Message;
+use sqlx::{migrate::MigrateDatabase, FromRow, Sqlite, SqlitePool, Transaction};
 
 use crate::FlameError;
 use common::apis::{
-    CommonData, Session, SessionID, SessionState, SessionStatus, Task, TaskGID, TaskID, TaskInput,
-    TaskState,
+    Application, CacheScope, OutputChunk, Session, SessionAttributes, SessionEvent, SessionID,
+    SessionState, SessionStatus, Task, TaskAttributes, TaskCounts, TaskFailure, TaskGID, TaskID,
+    TaskOutput, TaskState,
 };
+use rpc::flame as rpc;
 
-use crate::storage::engine::{Engine, EnginePtr};
+use crate::storage::engine::{CacheKey, CachedOutput, Engine, EngineInfo, EnginePtr, TaskUpdate};
 
 const SQLITE_SQL: &str = "migrations/sqlite";
 
 #[derive(Clone, FromRow, Debug)]
 struct SessionDao {
     pub id: SessionID,
+    pub namespace: String,
     pub application: String,
     pub slots: i32,
+    pub labels: Option<String>,
 
     pub common_data: Option<Vec<u8>>,
+    pub config: Option<String>,
+    pub max_pending_tasks: Option<u32>,
+    pub max_running_tasks: Option<u32>,
+    pub cache_scope: i32,
+    pub deadline: Option<i64>,
+    pub task_timeout: Option<i64>,
+    pub max_task_retries: Option<u32>,
+    pub cloned_from: Option<SessionID>,
+    pub priority_class: Option<String>,
+    /// The application recorded on the session as JSON.
+    pub application_spec: Option<String>,
+    pub last_activity: Option<i64>,
+    /// The annotations set by the executors as JSON.
+    pub annotations: Option<String>,
     pub creation_time: i64,
     pub completion_time: Option<i64>,
 
@@ -46,9 +65,24 @@ struct SessionDao {
 struct TaskDao {
     pub id: TaskID,
     pub ssn_id: SessionID,
+    pub idx: i64,
 
     pub input: Option<Vec<u8>>,
     pub output: Option<Vec<u8>>,
+    pub trace_context: Option<String>,
+    pub labels: Option<String>,
+    pub locality: Option<String>,
+    pub env: Option<String>,
+    pub timeout: Option<i64>,
+    pub max_retries: Option<u32>,
+    pub executor_id: Option<String>,
+    pub hostname: Option<String>,
+    pub original_task_id: Option<TaskID>,
+    pub idempotency_key: Option<String>,
+    pub failure_reason: Option<i32>,
+    pub failure_message: Option<String>,
+    /// The annotations set by the executors as JSON.
+    pub annotations: Option<String>,
 
     pub creation_time: i64,
     pub completion_time: Option<i64>,
@@ -56,128 +90,233 @@ struct TaskDao {
     pub state: i32,
 }
 
+#[derive(Clone, FromRow, Debug)]
+struct CachedOutputDao {
+    pub digest: String,
+    pub namespace: String,
+    pub application: String,
+    pub ssn_id: SessionID,
+    pub shared: bool,
+
+    pub output: Option<Vec<u8>>,
+
+    pub creation_time: i64,
+}
+
+#[derive(Clone, FromRow, Debug)]
+struct SessionEventDao {
+    /// The event encoded as `rpc::SessionEvent`.
+    pub event: Vec<u8>,
+}
+
+#[derive(Clone, FromRow, Debug)]
+struct OutputChunkDao {
+    pub sequence: i64,
+    pub data: Vec<u8>,
+    pub creation_time: i64,
+}
+
+#[derive(Clone, FromRow, Debug)]
+struct ApplicationDao {
+    pub spec: String,
+}
+
 pub struct SqliteEngine {
     pool: SqlitePool,
+    /// The version of the SQLite library, e.g. 3.45.1.
+    version: String,
 }
 
 impl SqliteEngine {
     pub async fn new_ptr(url: &str) -> Result<EnginePtr, FlameError> {
         if !Sqlite::database_exists(url).await.unwrap_or(false) {
-            Sqlite::create_database(url)
-                .await
-                .map_err(|e| FlameError::Storage(e.to_string()))?;
-            let db = SqlitePool::connect(url)
-                .await
-                .map_err(|e| FlameError::Storage(e.to_string()))?;
+            Sqlite::create_database(url).await.map_err(storage_error)?;
+        }
 
-            let migrations = std::path::Path::new(&SQLITE_SQL);
-            let migrator = sqlx::migrate::Migrator::new(migrations)
-                .await
-                .map_err(|e| FlameError::Storage(e.to_string()))?;
-            migrator
-                .run(&db)
-                .await
-                .map_err(|e| FlameError::Storage(e.to_string()))?;
+        let db = SqlitePool::connect(url).await.map_err(storage_error)?;
 
-            return Ok(Arc::new(SqliteEngine { pool: db }));
-        }
+        // Always run the migrations, so the databases of previous versions are upgraded.
+        let migrations = std::path::Path::new(&SQLITE_SQL);
+        let migrator = sqlx::migrate::Migrator::new(migrations)
+            .await
+            .map_err(FlameError::storage)?;
+        migrator.run(&db).await.map_err(FlameError::storage)?;
 
-        let db = SqlitePool::connect(url)
+        let version = sqlx::query_scalar("SELECT sqlite_version()")
+            .fetch_one(&db)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
 
-        Ok(Arc::new(SqliteEngine { pool: db }))
+        Ok(Arc::new(SqliteEngine { pool: db, version }))
     }
 }
 
 #[async_trait]
 impl Engine for SqliteEngine {
-    async fn create_session(
-        &self,
-        app: String,
-        slots: i32,
-        common_data: Option<CommonData>,
-    ) -> Result<Session, FlameError> {
-        let mut tx = self
-            .pool
-            .begin()
-            .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+    fn info(&self) -> EngineInfo {
+        EngineInfo {
+            kind: "sqlite".to_string(),
+            version: self.version.clone(),
+        }
+    }
 
-        let common_data: Option<Vec<u8>> = common_data.map(Bytes::into);
-        let sql = "INSERT INTO sessions (application, slots, common_data, creation_time, state) VALUES (?, ?, ?, ?, ?) RETURNING *";
+    #[tracing::instrument(name = "SqliteEngine::create_session", level = "debug", skip_all)]
+    async fn create_session(&self, attrs: SessionAttributes) -> Result<Session, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let common_data: Option<Vec<u8>> = attrs.common_data.map(Bytes::into);
+        let labels = serde_json::to_string(&attrs.labels).map_err(FlameError::storage)?;
+        let config = serde_json::to_string(&attrs.config).map_err(FlameError::storage)?;
+        let application_spec = attrs
+            .application_spec
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(FlameError::storage)?;
+        let sql = "INSERT INTO sessions (namespace, application, slots, labels, common_data, config, max_pending_tasks, max_running_tasks, cache_scope, deadline, task_timeout, max_task_retries, cloned_from, priority_class, application_spec, creation_time, state) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING *";
         let ssn: SessionDao = sqlx::query_as(sql)
-            .bind(app)
-            .bind(slots)
+            .bind(attrs.namespace)
+            .bind(attrs.application)
+            .bind(attrs.slots)
+            .bind(labels)
             .bind(common_data)
+            .bind(config)
+            .bind(attrs.max_pending_tasks)
+            .bind(attrs.max_running_tasks)
+            .bind(attrs.cache_scope as i32)
+            .bind(attrs.deadline.map(|t| t.timestamp()))
+            .bind(attrs.task_timeout.map(|t| t as i64))
+            .bind(attrs.max_task_retries)
+            .bind(attrs.cloned_from)
+            .bind(attrs.priority_class)
+            .bind(application_spec)
             .bind(Utc::now().timestamp())
             .bind(SessionState::Open as i32)
             .fetch_one(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
 
-        tx.commit()
-            .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+        tx.commit().await.map_err(storage_error)?;
 
         ssn.try_into()
     }
 
+    #[tracing::instrument(
+        name = "SqliteEngine::get_session",
+        level = "debug",
+        skip_all,
+        fields(session_id = id)
+    )]
     async fn get_session(&self, id: SessionID) -> Result<Session, FlameError> {
-        let mut tx = self
-            .pool
-            .begin()
-            .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
 
         let sql = "SELECT * FROM sessions WHERE id=?";
         let ssn: SessionDao = sqlx::query_as(sql)
             .bind(id)
             .fetch_one(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
 
-        tx.commit()
-            .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+        tx.commit().await.map_err(storage_error)?;
 
         ssn.try_into()
     }
 
+    #[tracing::instrument(
+        name = "SqliteEngine::delete_session",
+        level = "debug",
+        skip_all,
+        fields(session_id = id)
+    )]
     async fn delete_session(&self, id: SessionID) -> Result<Session, FlameError> {
-        let mut tx = self
-            .pool
-            .begin()
-            .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
 
         let sql = "DELETE FROM sessions WHERE id=? AND state=? RETURNING *";
-        let ssn: SessionDao = sqlx::query_as(sql)
+        let res = sqlx::query_as(sql)
             .bind(id)
             .bind(SessionState::Closed as i32)
             .fetch_one(&mut *tx)
+            .await;
+        let ssn: SessionDao = match res {
+            Ok(ssn) => ssn,
+            Err(e) => {
+                let msg = format!("session <{}> is not closed", id);
+                return Err(session_error(&mut tx, id, e, msg).await);
+            }
+        };
+
+        let sql = "DELETE FROM tasks WHERE ssn_id=?";
+        sqlx::query(sql)
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        let sql = "DELETE FROM task_cache WHERE ssn_id=? AND shared=0";
+        sqlx::query(sql)
+            .bind(id)
+            .execute(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
 
-        tx.commit()
+        let sql = "DELETE FROM session_events WHERE ssn_id=?";
+        sqlx::query(sql)
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        let sql = "DELETE FROM task_output_chunks WHERE ssn_id=?";
+        sqlx::query(sql)
+            .bind(id)
+            .execute(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
 
         ssn.try_into()
     }
 
-    async fn close_session(&self, id: SessionID) -> Result<Session, FlameError> {
-        let mut tx = self
-            .pool
-            .begin()
+    #[tracing::instrument(
+        name = "SqliteEngine::open_session",
+        level = "debug",
+        skip_all,
+        fields(session_id = id)
+    )]
+    async fn open_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let sql = r#"UPDATE sessions 
+            SET state=?, completion_time=NULL
+            WHERE id=?
+            RETURNING *"#;
+        let ssn: SessionDao = sqlx::query_as(sql)
+            .bind(SessionState::Open as i32)
+            .bind(id)
+            .fetch_one(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
+
+        ssn.try_into()
+    }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::close_session",
+        level = "debug",
+        skip_all,
+        fields(session_id = id)
+    )]
+    async fn close_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
 
         let sql = r#"UPDATE sessions 
             SET state=?, completion_time=?
             WHERE id=? AND (SELECT COUNT(*) FROM tasks WHERE ssn_id=? AND state NOT IN (?, ?))=0
             RETURNING *"#;
-        let ssn: SessionDao = sqlx::query_as(sql)
+        let res = sqlx::query_as(sql)
             .bind(SessionState::Closed as i32)
             .bind(Utc::now().timestamp())
             .bind(id)
@@ -185,32 +324,120 @@ impl Engine for SqliteEngine {
             .bind(TaskState::Failed as i32)
             .bind(TaskState::Succeed as i32)
             .fetch_one(&mut *tx)
+            .await;
+        let ssn: SessionDao = match res {
+            Ok(ssn) => ssn,
+            Err(e) => {
+                let msg = format!("session <{}> has open tasks", id);
+                return Err(session_error(&mut tx, id, e, msg).await);
+            }
+        };
+
+        tx.commit().await.map_err(storage_error)?;
+
+        ssn.try_into()
+    }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::update_session_deadline",
+        level = "debug",
+        skip_all,
+        fields(session_id = id)
+    )]
+    async fn update_session_deadline(
+        &self,
+        id: SessionID,
+        deadline: Option<DateTime<Utc>>,
+    ) -> Result<Session, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let sql = "UPDATE sessions SET deadline=? WHERE id=? AND state=? RETURNING *";
+        let res = sqlx::query_as(sql)
+            .bind(deadline.map(|t| t.timestamp()))
+            .bind(id)
+            .bind(SessionState::Open as i32)
+            .fetch_one(&mut *tx)
+            .await;
+        let ssn: SessionDao = match res {
+            Ok(ssn) => ssn,
+            Err(e) => {
+                let msg = format!("session <{}> is not open", id);
+                return Err(session_error(&mut tx, id, e, msg).await);
+            }
+        };
+
+        tx.commit().await.map_err(storage_error)?;
+
+        ssn.try_into()
+    }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::update_session_activity",
+        level = "debug",
+        skip_all,
+        fields(session_id = id)
+    )]
+    async fn update_session_activity(
+        &self,
+        id: SessionID,
+        last_activity: DateTime<Utc>,
+    ) -> Result<Session, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let sql = "UPDATE sessions SET last_activity=? WHERE id=? RETURNING *";
+        let ssn: SessionDao = sqlx::query_as(sql)
+            .bind(last_activity.timestamp())
+            .bind(id)
+            .fetch_optional(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?
+            .ok_or(FlameError::NotFound(format!("session <{}>", id)))?;
+
+        tx.commit().await.map_err(storage_error)?;
+
+        ssn.try_into()
+    }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::update_session_annotations",
+        level = "debug",
+        skip_all,
+        fields(session_id = id)
+    )]
+    async fn update_session_annotations(
+        &self,
+        id: SessionID,
+        annotations: &HashMap<String, String>,
+    ) -> Result<Session, FlameError> {
+        let annotations = serde_json::to_string(annotations).map_err(FlameError::storage)?;
+
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
 
-        tx.commit()
+        let sql = "UPDATE sessions SET annotations=? WHERE id=? RETURNING *";
+        let ssn: SessionDao = sqlx::query_as(sql)
+            .bind(annotations)
+            .bind(id)
+            .fetch_optional(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?
+            .ok_or(FlameError::NotFound(format!("session <{}>", id)))?;
+
+        tx.commit().await.map_err(storage_error)?;
 
         ssn.try_into()
     }
 
+    #[tracing::instrument(name = "SqliteEngine::find_session", level = "debug", skip_all)]
     async fn find_session(&self) -> Result<Vec<Session>, FlameError> {
-        let mut tx = self
-            .pool
-            .begin()
-            .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
 
-        let sql = "SELECT * FROM sessions";
+        let sql = "SELECT * FROM sessions ORDER BY id";
         let ssn: Vec<SessionDao> = sqlx::query_as(sql)
             .fetch_all(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
 
-        tx.commit()
-            .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+        tx.commit().await.map_err(storage_error)?;
 
         Ok(ssn
             .iter()
@@ -219,49 +446,237 @@ impl Engine for SqliteEngine {
             .collect())
     }
 
+    #[tracing::instrument(name = "SqliteEngine::import_session", level = "debug", skip_all)]
+    async fn import_session(&self, ssn: Session, tasks: Vec<Task>) -> Result<Session, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let common_data: Option<Vec<u8>> = ssn.common_data.map(Bytes::into);
+        let labels = serde_json::to_string(&ssn.labels).map_err(FlameError::storage)?;
+        let config = serde_json::to_string(&ssn.config).map_err(FlameError::storage)?;
+        let annotations = serde_json::to_string(&ssn.annotations).map_err(FlameError::storage)?;
+        let sql = "INSERT INTO sessions (namespace, application, slots, labels, common_data, config, max_pending_tasks, max_running_tasks, task_timeout, max_task_retries, priority_class, annotations, creation_time, completion_time, state) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING *";
+        let imported: SessionDao = sqlx::query_as(sql)
+            .bind(ssn.namespace)
+            .bind(ssn.application)
+            .bind(ssn.slots)
+            .bind(labels)
+            .bind(common_data)
+            .bind(config)
+            .bind(ssn.max_pending_tasks)
+            .bind(ssn.max_running_tasks)
+            .bind(ssn.task_timeout.map(|t| t as i64))
+            .bind(ssn.max_task_retries)
+            .bind(ssn.priority_class)
+            .bind(annotations)
+            .bind(ssn.creation_time.timestamp())
+            .bind(ssn.completion_time.map(|t| t.timestamp()))
+            .bind(SessionState::Closed as i32)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        let next_index = tasks.iter().map(|t| t.index + 1).max().unwrap_or_default();
+        for task in tasks {
+            let input: Option<Vec<u8>> = task.input.map(Bytes::into);
+            let output: Option<Vec<u8>> = task.output.map(Bytes::into);
+            let (failure_reason, failure_message) = match task.failure {
+                Some(f) => (Some(f.reason as i32), Some(f.message)),
+                None => (None, None),
+            };
+            let labels = serde_json::to_string(&task.labels).map_err(FlameError::storage)?;
+            let env = serde_json::to_string(&task.env).map_err(FlameError::storage)?;
+            let annotations =
+                serde_json::to_string(&task.annotations).map_err(FlameError::storage)?;
+            let sql = "INSERT INTO tasks (id, ssn_id, idx, input, output, trace_context, labels, locality, env, timeout, max_retries, executor_id, hostname, original_task_id, idempotency_key, failure_reason, failure_message, annotations, creation_time, completion_time, state) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+            sqlx::query(sql)
+                .bind(task.id)
+                .bind(imported.id)
+                .bind(task.index as i64)
+                .bind(input)
+                .bind(output)
+                .bind(task.trace_context)
+                .bind(labels)
+                .bind(task.locality)
+                .bind(env)
+                .bind(task.timeout.map(|t| t as i64))
+                .bind(task.max_retries)
+                .bind(task.executor_id)
+                .bind(task.hostname)
+                .bind(task.original_task_id)
+                .bind(task.idempotency_key)
+                .bind(failure_reason)
+                .bind(failure_message)
+                .bind(annotations)
+                .bind(task.creation_time.timestamp())
+                .bind(task.completion_time.map(|t| t.timestamp()))
+                .bind(task.state as i32)
+                .execute(&mut *tx)
+                .await
+                .map_err(storage_error)?;
+        }
+
+        let sql = "UPDATE sessions SET next_task_index=? WHERE id=?";
+        sqlx::query(sql)
+            .bind(next_index as i64)
+            .bind(imported.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
+
+        imported.try_into()
+    }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::create_task",
+        level = "debug",
+        skip_all,
+        fields(session_id = ssn_id)
+    )]
     async fn create_task(
         &self,
         ssn_id: SessionID,
-        input: Option<TaskInput>,
+        attrs: TaskAttributes,
     ) -> Result<Task, FlameError> {
-        let mut tx = self
-            .pool
-            .begin()
-            .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
-
-        let input: Option<Vec<u8>> = input.map(Bytes::into);
-        let sql = r#"INSERT INTO tasks (id, ssn_id, input, creation_time, state)
-            VALUES (
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let input: Option<Vec<u8>> = attrs.input.map(Bytes::into);
+        let labels = serde_json::to_string(&attrs.labels).map_err(FlameError::storage)?;
+        let env = serde_json::to_string(&attrs.env).map_err(FlameError::storage)?;
+        // Nothing is inserted if the session is not open; the index is taken from the
+        // session in the same transaction, so it's dense even if the tasks are created
+        // concurrently.
+        let sql = r#"INSERT INTO tasks (id, ssn_id, idx, input, trace_context, labels, locality, env, timeout, max_retries, idempotency_key, creation_time, state)
+            SELECT
                 COALESCE((SELECT MAX(id)+1 FROM tasks WHERE ssn_id=?), 1),
-                (SELECT id FROM sessions WHERE id=? AND state=?),
+                id,
+                next_task_index,
+                ?,
+                ?,
                 ?,
                 ?,
-                ?)
+                ?,
+                ?,
+                ?,
+                ?,
+                ?,
+                ?
+            FROM sessions WHERE id=? AND state=?
             RETURNING *"#;
-        let task: TaskDao = sqlx::query_as(sql)
+        let res = sqlx::query_as(sql)
             .bind(ssn_id)
-            .bind(ssn_id)
-            .bind(SessionState::Open as i32)
             .bind(input)
+            .bind(attrs.trace_context)
+            .bind(labels)
+            .bind(attrs.locality)
+            .bind(env)
+            .bind(attrs.timeout.map(|t| t as i64))
+            .bind(attrs.max_retries)
+            .bind(attrs.idempotency_key)
             .bind(Utc::now().timestamp())
             .bind(TaskState::Pending as i32)
+            .bind(ssn_id)
+            .bind(SessionState::Open as i32)
+            .fetch_one(&mut *tx)
+            .await;
+        let task: TaskDao = match res {
+            Ok(task) => task,
+            Err(e) => {
+                let msg = format!("session <{}> is not open", ssn_id);
+                return Err(session_error(&mut tx, ssn_id, e, msg).await);
+            }
+        };
+
+        let sql = "UPDATE sessions SET next_task_index=next_task_index+1 WHERE id=?";
+        sqlx::query(sql)
+            .bind(ssn_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
+
+        task.try_into()
+    }
+    #[tracing::instrument(
+        name = "SqliteEngine::resubmit_task",
+        level = "debug",
+        skip_all,
+        fields(session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
+    async fn resubmit_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let sql = "SELECT * FROM tasks WHERE id=? AND ssn_id=?";
+        let original: TaskDao = sqlx::query_as(sql)
+            .bind(gid.task_id)
+            .bind(gid.ssn_id)
             .fetch_one(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
+
+        // The same as create_task, but the input is copied from the original task.
+        let sql = r#"INSERT INTO tasks (id, ssn_id, idx, input, trace_context, labels, locality, env, timeout, max_retries, original_task_id, creation_time, state)
+            SELECT
+                COALESCE((SELECT MAX(id)+1 FROM tasks WHERE ssn_id=?), 1),
+                id,
+                next_task_index,
+                ?,
+                ?,
+                ?,
+                ?,
+                ?,
+                ?,
+                ?,
+                ?,
+                ?,
+                ?
+            FROM sessions WHERE id=? AND state=?
+            RETURNING *"#;
+        let res = sqlx::query_as(sql)
+            .bind(gid.ssn_id)
+            .bind(original.input)
+            .bind(original.trace_context)
+            .bind(original.labels)
+            .bind(original.locality)
+            .bind(original.env)
+            .bind(original.timeout)
+            .bind(original.max_retries)
+            .bind(original.id)
+            .bind(Utc::now().timestamp())
+            .bind(TaskState::Pending as i32)
+            .bind(gid.ssn_id)
+            .bind(SessionState::Open as i32)
+            .fetch_one(&mut *tx)
+            .await;
+        let task: TaskDao = match res {
+            Ok(task) => task,
+            Err(e) => {
+                let msg = format!("session <{}> is not open", gid.ssn_id);
+                return Err(session_error(&mut tx, gid.ssn_id, e, msg).await);
+            }
+        };
 
-        tx.commit()
+        let sql = "UPDATE sessions SET next_task_index=next_task_index+1 WHERE id=?";
+        sqlx::query(sql)
+            .bind(gid.ssn_id)
+            .execute(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
 
         task.try_into()
     }
+    #[tracing::instrument(
+        name = "SqliteEngine::get_task",
+        level = "debug",
+        skip_all,
+        fields(session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
     async fn get_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
-        let mut tx = self
-            .pool
-            .begin()
-            .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
 
         let sql = r#"SELECT * FROM tasks WHERE id=? AND ssn_id=?"#;
         let task: TaskDao = sqlx::query_as(sql)
@@ -269,42 +684,50 @@ impl Engine for SqliteEngine {
             .bind(gid.ssn_id)
             .fetch_one(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
 
-        tx.commit()
-            .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+        tx.commit().await.map_err(storage_error)?;
 
         task.try_into()
     }
+    #[tracing::instrument(
+        name = "SqliteEngine::delete_task",
+        level = "debug",
+        skip_all,
+        fields(session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
     async fn delete_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
-        let mut tx = self
-            .pool
-            .begin()
-            .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
 
-        let sql = r#"DELETE tasks WHERE id=? AND ssn_id=? RETURNING *"#;
+        let sql = r#"DELETE FROM tasks WHERE id=? AND ssn_id=? RETURNING *"#;
         let task: TaskDao = sqlx::query_as(sql)
             .bind(gid.task_id)
             .bind(gid.ssn_id)
             .fetch_one(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
 
-        tx.commit()
+        let sql = "DELETE FROM task_output_chunks WHERE ssn_id=? AND task_id=?";
+        sqlx::query(sql)
+            .bind(gid.ssn_id)
+            .bind(gid.task_id)
+            .execute(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
 
         task.try_into()
     }
 
+    #[tracing::instrument(
+        name = "SqliteEngine::retry_task",
+        level = "debug",
+        skip_all,
+        fields(session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
     async fn retry_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
-        let mut tx = self
-            .pool
-            .begin()
-            .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
 
         let sql = r#"UPDATE tasks SET state=? WHERE id=? AND ssn_id=? RETURNING *"#;
         let task: TaskDao = sqlx::query_as(sql)
@@ -313,62 +736,179 @@ impl Engine for SqliteEngine {
             .bind(gid.ssn_id)
             .fetch_one(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
 
-        tx.commit()
-            .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+        tx.commit().await.map_err(storage_error)?;
 
         task.try_into()
     }
 
+    #[tracing::instrument(
+        name = "SqliteEngine::update_task_state",
+        level = "debug",
+        skip_all,
+        fields(session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
     async fn update_task_state(&self, gid: TaskGID, state: TaskState) -> Result<Task, FlameError> {
-        let mut tx = self
-            .pool
-            .begin()
-            .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
 
         let completion_time = match state {
             TaskState::Failed | TaskState::Succeed => Some(Utc::now().timestamp()),
             _ => None,
         };
 
-        let sql =
-            r#"UPDATE tasks SET state=?, completion_time=? WHERE id=? AND ssn_id=? RETURNING *"#;
+        // The failure is kept by the retries, and cleared once the task succeeds.
+        let sql = r#"UPDATE tasks SET state=?, completion_time=?,
+                failure_reason=CASE WHEN ? THEN NULL ELSE failure_reason END,
+                failure_message=CASE WHEN ? THEN NULL ELSE failure_message END
+            WHERE id=? AND ssn_id=? RETURNING *"#;
         let task: TaskDao = sqlx::query_as(sql)
             .bind(state as i32)
             .bind(completion_time)
+            .bind(state == TaskState::Succeed)
+            .bind(state == TaskState::Succeed)
             .bind(gid.task_id)
             .bind(gid.ssn_id)
             .fetch_one(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
+
+        task.try_into()
+    }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::update_task_placement",
+        level = "debug",
+        skip_all,
+        fields(session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
+    async fn update_task_placement(
+        &self,
+        gid: TaskGID,
+        executor_id: &str,
+        hostname: &str,
+    ) -> Result<Task, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
 
-        tx.commit()
+        let sql =
+            r#"UPDATE tasks SET executor_id=?, hostname=? WHERE id=? AND ssn_id=? RETURNING *"#;
+        let task: TaskDao = sqlx::query_as(sql)
+            .bind(executor_id)
+            .bind(hostname)
+            .bind(gid.task_id)
+            .bind(gid.ssn_id)
+            .fetch_one(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
 
         task.try_into()
     }
 
-    async fn find_tasks(&self, ssn_id: SessionID) -> Result<Vec<Task>, FlameError> {
-        let mut tx = self
-            .pool
-            .begin()
+    #[tracing::instrument(
+        name = "SqliteEngine::update_task_failure",
+        level = "debug",
+        skip_all,
+        fields(session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
+    async fn update_task_failure(
+        &self,
+        gid: TaskGID,
+        failure: Option<&TaskFailure>,
+    ) -> Result<Task, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let sql = r#"UPDATE tasks SET failure_reason=?, failure_message=? WHERE id=? AND ssn_id=? RETURNING *"#;
+        let task: TaskDao = sqlx::query_as(sql)
+            .bind(failure.map(|f| f.reason as i32))
+            .bind(failure.map(|f| f.message.clone()))
+            .bind(gid.task_id)
+            .bind(gid.ssn_id)
+            .fetch_one(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
+
+        task.try_into()
+    }
 
-        let sql = "SELECT * FROM tasks WHERE ssn_id=?";
+    #[tracing::instrument(
+        name = "SqliteEngine::update_task_annotations",
+        level = "debug",
+        skip_all,
+        fields(session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
+    async fn update_task_annotations(
+        &self,
+        gid: TaskGID,
+        annotations: &HashMap<String, String>,
+    ) -> Result<Task, FlameError> {
+        let annotations = serde_json::to_string(annotations).map_err(FlameError::storage)?;
+
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let sql = r#"UPDATE tasks SET annotations=? WHERE id=? AND ssn_id=? RETURNING *"#;
+        let task: TaskDao = sqlx::query_as(sql)
+            .bind(annotations)
+            .bind(gid.task_id)
+            .bind(gid.ssn_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
+
+        task.try_into()
+    }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::update_task_output",
+        level = "debug",
+        skip_all,
+        fields(session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
+    async fn update_task_output(
+        &self,
+        gid: TaskGID,
+        output: Option<&TaskOutput>,
+    ) -> Result<Task, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let sql = r#"UPDATE tasks SET output=? WHERE id=? AND ssn_id=? RETURNING *"#;
+        let task: TaskDao = sqlx::query_as(sql)
+            .bind(output.map(|o| Vec::<u8>::from(o.clone())))
+            .bind(gid.task_id)
+            .bind(gid.ssn_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
+
+        task.try_into()
+    }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::find_tasks",
+        level = "debug",
+        skip_all,
+        fields(session_id = ssn_id)
+    )]
+    async fn find_tasks(&self, ssn_id: SessionID) -> Result<Vec<Task>, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let sql = "SELECT * FROM tasks WHERE ssn_id=? ORDER BY id";
         let task_list: Vec<TaskDao> = sqlx::query_as(sql)
             .bind(ssn_id)
             .fetch_all(&mut *tx)
             .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+            .map_err(storage_error)?;
 
-        tx.commit()
-            .await
-            .map_err(|e| FlameError::Storage(e.to_string()))?;
+        tx.commit().await.map_err(storage_error)?;
 
         Ok(task_list
             .iter()
@@ -376,6 +916,383 @@ impl Engine for SqliteEngine {
             .filter_map(Result::ok)
             .collect())
     }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::update_tasks",
+        level = "debug",
+        skip_all,
+        fields(updates = updates.len())
+    )]
+    async fn update_tasks(&self, updates: Vec<TaskUpdate>) -> Result<(), FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        // The same changes as `update_task_state` and `update_task_output`.
+        let sql = r#"UPDATE tasks SET state=?, completion_time=?,
+                failure_reason=CASE WHEN ? THEN NULL ELSE failure_reason END,
+                failure_message=CASE WHEN ? THEN NULL ELSE failure_message END,
+                output=COALESCE(?, output)
+            WHERE id=? AND ssn_id=?"#;
+        for update in updates {
+            let succeed = update.state == TaskState::Succeed;
+            sqlx::query(sql)
+                .bind(update.state as i32)
+                .bind(update.completion_time.map(|t| t.timestamp()))
+                .bind(succeed)
+                .bind(succeed)
+                .bind(update.output.map(Vec::<u8>::from))
+                .bind(update.gid.task_id)
+                .bind(update.gid.ssn_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(storage_error)?;
+        }
+
+        tx.commit().await.map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::put_cached_output",
+        level = "debug",
+        skip_all,
+        fields(session_id = output.key.ssn_id)
+    )]
+    async fn put_cached_output(
+        &self,
+        output: CachedOutput,
+        max_entries: usize,
+        expired: DateTime<Utc>,
+    ) -> Result<(), FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let sql = "DELETE FROM task_cache WHERE creation_time<? OR (digest=? AND ssn_id=?)";
+        sqlx::query(sql)
+            .bind(expired.timestamp())
+            .bind(&output.key.digest)
+            .bind(output.key.ssn_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        let sql = "INSERT INTO task_cache (digest, namespace, application, ssn_id, shared, output, creation_time) VALUES (?, ?, ?, ?, ?, ?, ?)";
+        sqlx::query(sql)
+            .bind(output.key.digest)
+            .bind(output.key.namespace)
+            .bind(output.key.application)
+            .bind(output.key.ssn_id)
+            .bind(output.key.shared)
+            .bind(output.output.map(Vec::<u8>::from))
+            .bind(output.creation_time.timestamp())
+            .execute(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        let sql = "DELETE FROM task_cache WHERE id NOT IN (SELECT id FROM task_cache ORDER BY id DESC LIMIT ?)";
+        sqlx::query(sql)
+            .bind(i64::try_from(max_entries).unwrap_or(i64::MAX))
+            .execute(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::find_cached_output",
+        level = "debug",
+        skip_all,
+        fields(session_id = key.ssn_id)
+    )]
+    async fn find_cached_output(
+        &self,
+        key: &CacheKey,
+        expired: DateTime<Utc>,
+    ) -> Result<Option<CachedOutput>, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let sql = r#"SELECT * FROM task_cache
+            WHERE digest=? AND namespace=? AND application=? AND creation_time>=?
+                AND (ssn_id=? OR (shared AND ?))
+            ORDER BY id DESC LIMIT 1"#;
+        let output: Option<CachedOutputDao> = sqlx::query_as(sql)
+            .bind(&key.digest)
+            .bind(&key.namespace)
+            .bind(&key.application)
+            .bind(expired.timestamp())
+            .bind(key.ssn_id)
+            .bind(key.shared)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
+
+        output.map(CachedOutput::try_from).transpose()
+    }
+
+    #[tracing::instrument(name = "SqliteEngine::put_events", level = "debug", skip_all)]
+    async fn put_events(
+        &self,
+        events: Vec<(SessionID, SessionEvent)>,
+        max_events: usize,
+    ) -> Result<(), FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let mut ssn_ids = vec![];
+        for (ssn_id, event) in &events {
+            let sql = r#"INSERT INTO session_events (ssn_id, sequence, event_time, event)
+                SELECT ?, ?, ?, ? WHERE EXISTS (SELECT 1 FROM sessions WHERE id=?)"#;
+            sqlx::query(sql)
+                .bind(ssn_id)
+                .bind(i64::try_from(event.sequence).unwrap_or(i64::MAX))
+                .bind(event.event_time.timestamp())
+                .bind(rpc::SessionEvent::from(event).encode_to_vec())
+                .bind(ssn_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(storage_error)?;
+
+            if !ssn_ids.contains(ssn_id) {
+                ssn_ids.push(*ssn_id);
+            }
+        }
+
+        for ssn_id in ssn_ids {
+            let sql = r#"DELETE FROM session_events WHERE ssn_id=? AND id NOT IN
+                (SELECT id FROM session_events WHERE ssn_id=? ORDER BY id DESC LIMIT ?)"#;
+            sqlx::query(sql)
+                .bind(ssn_id)
+                .bind(ssn_id)
+                .bind(i64::try_from(max_events).unwrap_or(i64::MAX))
+                .execute(&mut *tx)
+                .await
+                .map_err(storage_error)?;
+        }
+
+        tx.commit().await.map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::find_events",
+        level = "debug",
+        skip_all,
+        fields(session_id = ssn_id)
+    )]
+    async fn find_events(
+        &self,
+        ssn_id: SessionID,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SessionEvent>, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let sql = r#"SELECT event FROM session_events
+            WHERE ssn_id=? AND event_time>=? AND event_time<?
+            ORDER BY id"#;
+        let event_list: Vec<SessionEventDao> = sqlx::query_as(sql)
+            .bind(ssn_id)
+            .bind(since.map(|t| t.timestamp()).unwrap_or(i64::MIN))
+            .bind(until.map(|t| t.timestamp()).unwrap_or(i64::MAX))
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
+
+        event_list.into_iter().map(SessionEvent::try_from).collect()
+    }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::put_output_chunks",
+        level = "debug",
+        skip_all,
+        fields(session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
+    async fn put_output_chunks(
+        &self,
+        gid: TaskGID,
+        chunks: Vec<OutputChunk>,
+    ) -> Result<(), FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        for chunk in &chunks {
+            let sql = r#"INSERT OR REPLACE INTO task_output_chunks
+                    (ssn_id, task_id, sequence, data, creation_time)
+                SELECT ?, ?, ?, ?, ? WHERE EXISTS (SELECT 1 FROM tasks WHERE id=? AND ssn_id=?)"#;
+            sqlx::query(sql)
+                .bind(gid.ssn_id)
+                .bind(gid.task_id)
+                .bind(i64::try_from(chunk.sequence).unwrap_or(i64::MAX))
+                .bind(chunk.data.as_ref())
+                .bind(chunk.creation_time.timestamp())
+                .bind(gid.task_id)
+                .bind(gid.ssn_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(storage_error)?;
+        }
+
+        tx.commit().await.map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::find_output_chunks",
+        level = "debug",
+        skip_all,
+        fields(session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
+    async fn find_output_chunks(
+        &self,
+        gid: TaskGID,
+        since: u64,
+        limit: usize,
+    ) -> Result<Vec<OutputChunk>, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let sql = r#"SELECT sequence, data, creation_time FROM task_output_chunks
+            WHERE ssn_id=? AND task_id=? AND sequence>?
+            ORDER BY sequence LIMIT ?"#;
+        let chunk_list: Vec<OutputChunkDao> = sqlx::query_as(sql)
+            .bind(gid.ssn_id)
+            .bind(gid.task_id)
+            .bind(i64::try_from(since).unwrap_or(i64::MAX))
+            .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
+
+        chunk_list.into_iter().map(OutputChunk::try_from).collect()
+    }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::register_application",
+        level = "debug",
+        skip_all,
+        fields(application = %app.name)
+    )]
+    async fn register_application(&self, app: Application) -> Result<Application, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let spec = serde_json::to_string(&app).map_err(FlameError::storage)?;
+        let sql =
+            "INSERT INTO applications (name, spec, creation_time) VALUES (?, ?, ?) RETURNING spec";
+        let app: ApplicationDao = sqlx::query_as(sql)
+            .bind(&app.name)
+            .bind(spec)
+            .bind(Utc::now().timestamp())
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
+
+        app.try_into()
+    }
+
+    #[tracing::instrument(
+        name = "SqliteEngine::delete_application",
+        level = "debug",
+        skip_all,
+        fields(application = %name)
+    )]
+    async fn delete_application(&self, name: String) -> Result<Application, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let sql = "DELETE FROM applications WHERE name=? RETURNING spec";
+        let app: ApplicationDao = sqlx::query_as(sql)
+            .bind(&name)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::RowNotFound => FlameError::NotFound(format!("application <{}>", name)),
+                e => storage_error(e),
+            })?;
+
+        tx.commit().await.map_err(storage_error)?;
+
+        app.try_into()
+    }
+
+    #[tracing::instrument(name = "SqliteEngine::find_application", level = "debug", skip_all)]
+    async fn find_application(&self) -> Result<Vec<Application>, FlameError> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+
+        let sql = "SELECT spec FROM applications ORDER BY name";
+        let app_list: Vec<ApplicationDao> = sqlx::query_as(sql)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(storage_error)?;
+
+        tx.commit().await.map_err(storage_error)?;
+
+        app_list.into_iter().map(Application::try_from).collect()
+    }
+}
+
+/// Maps the errors of sqlx to FlameError, so the transient failures, e.g. the busy database,
+/// could be retried by the clients.
+fn storage_error(e: sqlx::Error) -> FlameError {
+    match e {
+        sqlx::Error::RowNotFound => FlameError::NotFound("row".to_string()),
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            FlameError::AlreadyExists(db.message().to_string())
+        }
+        // SQLITE_BUSY and SQLITE_LOCKED
+        sqlx::Error::Database(ref db) if matches!(db.code().as_deref(), Some("5" | "6")) => {
+            FlameError::Unavailable {
+                message: db.message().to_string(),
+                retryable: true,
+            }
+        }
+        sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed | sqlx::Error::Io(_) => {
+            FlameError::Unavailable {
+                message: e.to_string(),
+                retryable: true,
+            }
+        }
+        e => FlameError::storage(e),
+    }
+}
+
+/// The statements with conditions return no row if the session is not found or not in the
+/// expected state, which are told apart by looking up the session.
+async fn session_error(
+    tx: &mut Transaction<'_, Sqlite>,
+    id: SessionID,
+    e: sqlx::Error,
+    msg: String,
+) -> FlameError {
+    if !matches!(e, sqlx::Error::RowNotFound) {
+        return storage_error(e);
+    }
+
+    let sql = "SELECT COUNT(*) FROM sessions WHERE id=?";
+    match sqlx::query_scalar::<_, i64>(sql)
+        .bind(id)
+        .fetch_one(&mut **tx)
+        .await
+    {
+        Ok(0) => FlameError::NotFound(format!("session <{}>", id)),
+        Ok(_) => FlameError::FailedPrecondition(msg),
+        Err(e) => storage_error(e),
+    }
+}
+
+impl TryFrom<ApplicationDao> for Application {
+    type Error = FlameError;
+
+    fn try_from(app: ApplicationDao) -> Result<Self, Self::Error> {
+        serde_json::from_str(&app.spec).map_err(FlameError::storage)
+    }
 }
 
 impl TryFrom<&SessionDao> for Session {
@@ -384,22 +1301,75 @@ impl TryFrom<&SessionDao> for Session {
     fn try_from(ssn: &SessionDao) -> Result<Self, Self::Error> {
         Ok(Self {
             id: ssn.id,
+            namespace: ssn.namespace.clone(),
             application: ssn.application.clone(),
             slots: ssn.slots,
+            labels: ssn
+                .labels
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()
+                .map_err(FlameError::storage)?
+                .unwrap_or_default(),
             common_data: ssn.common_data.clone().map(Bytes::from),
+            config: ssn
+                .config
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()
+                .map_err(FlameError::storage)?
+                .unwrap_or_default(),
+            max_pending_tasks: ssn.max_pending_tasks,
+            max_running_tasks: ssn.max_running_tasks,
+            cache_scope: CacheScope::try_from(ssn.cache_scope)?,
+            deadline: ssn
+                .deadline
+                .map(|t| {
+                    DateTime::<Utc>::from_timestamp(t, 0)
+                        .ok_or(FlameError::storage("invalid deadline"))
+                })
+                .transpose()?,
+            task_timeout: ssn.task_timeout.map(|t| t as u64),
+            max_task_retries: ssn.max_task_retries,
+            cloned_from: ssn.cloned_from,
+            priority_class: ssn.priority_class.clone(),
+            application_spec: ssn
+                .application_spec
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()
+                .map_err(FlameError::storage)?,
+            last_activity: ssn
+                .last_activity
+                .map(|t| {
+                    DateTime::<Utc>::from_timestamp(t, 0)
+                        .ok_or(FlameError::storage("invalid last activity"))
+                })
+                .transpose()?,
+            annotations: ssn
+                .annotations
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()
+                .map_err(FlameError::storage)?
+                .unwrap_or_default(),
             creation_time: DateTime::<Utc>::from_timestamp(ssn.creation_time, 0)
-                .ok_or(FlameError::Storage("invalid creation time".to_string()))?,
+                .ok_or(FlameError::storage("invalid creation time"))?,
             completion_time: ssn
                 .completion_time
                 .map(|t| {
                     DateTime::<Utc>::from_timestamp(t, 0)
-                        .ok_or(FlameError::Storage("invalid completion time".to_string()))
+                        .ok_or(FlameError::storage("invalid completion time"))
                 })
                 .transpose()?,
             tasks: HashMap::new(),
             tasks_index: HashMap::new(),
+            idempotency_keys: HashMap::new(),
             status: SessionStatus {
                 state: ssn.state.try_into()?,
+                counts: TaskCounts::default(),
+                unschedulable: None,
+                generation: 0,
             },
         })
     }
@@ -420,16 +1390,56 @@ impl TryFrom<&TaskDao> for Task {
         Ok(Self {
             id: task.id,
             ssn_id: task.ssn_id,
+            index: task.idx as u64,
             input: task.input.clone().map(Bytes::from),
             output: task.output.clone().map(Bytes::from),
+            trace_context: task.trace_context.clone(),
+            labels: task
+                .labels
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()
+                .map_err(FlameError::storage)?
+                .unwrap_or_default(),
+            locality: task.locality.clone(),
+            env: task
+                .env
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()
+                .map_err(FlameError::storage)?
+                .unwrap_or_default(),
+            timeout: task.timeout.map(|t| t as u64),
+            max_retries: task.max_retries,
+            progress: None,
+            failure: match task.failure_reason {
+                Some(reason) => Some(TaskFailure {
+                    reason: reason.try_into()?,
+                    message: task.failure_message.clone().unwrap_or_default(),
+                }),
+                None => None,
+            },
+            executor_id: task.executor_id.clone(),
+            hostname: task.hostname.clone(),
+            original_task_id: task.original_task_id,
+            idempotency_key: task.idempotency_key.clone(),
+            annotations: task
+                .annotations
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()
+                .map_err(FlameError::storage)?
+                .unwrap_or_default(),
+            output_chunks: 0,
+            spilled: false,
 
             creation_time: DateTime::<Utc>::from_timestamp(task.creation_time, 0)
-                .ok_or(FlameError::Storage("invalid creation time".to_string()))?,
+                .ok_or(FlameError::storage("invalid creation time"))?,
             completion_time: task
                 .completion_time
                 .map(|t| {
                     DateTime::<Utc>::from_timestamp(t, 0)
-                        .ok_or(FlameError::Storage("invalid completion time".to_string()))
+                        .ok_or(FlameError::storage("invalid completion time"))
                 })
                 .transpose()?,
 
@@ -446,10 +1456,67 @@ impl TryFrom<TaskDao> for Task {
     }
 }
 
+impl TryFrom<CachedOutputDao> for CachedOutput {
+    type Error = FlameError;
+
+    fn try_from(output: CachedOutputDao) -> Result<Self, Self::Error> {
+        Ok(Self {
+            key: CacheKey {
+                digest: output.digest,
+                namespace: output.namespace,
+                application: output.application,
+                ssn_id: output.ssn_id,
+                shared: output.shared,
+            },
+            output: output.output.map(Bytes::from),
+            creation_time: DateTime::<Utc>::from_timestamp(output.creation_time, 0)
+                .ok_or(FlameError::storage("invalid creation time"))?,
+        })
+    }
+}
+
+impl TryFrom<OutputChunkDao> for OutputChunk {
+    type Error = FlameError;
+
+    fn try_from(chunk: OutputChunkDao) -> Result<Self, Self::Error> {
+        Ok(Self {
+            sequence: u64::try_from(chunk.sequence).map_err(FlameError::storage)?,
+            data: Bytes::from(chunk.data),
+            creation_time: DateTime::<Utc>::from_timestamp(chunk.creation_time, 0)
+                .ok_or(FlameError::storage("invalid creation time"))?,
+        })
+    }
+}
+
+impl TryFrom<SessionEventDao> for SessionEvent {
+    type Error = FlameError;
+
+    fn try_from(event: SessionEventDao) -> Result<Self, Self::Error> {
+        let event =
+            rpc::SessionEvent::decode(event.event.as_slice()).map_err(FlameError::storage)?;
+
+        SessionEvent::try_from(event)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use common::apis::{SessionAttributes, DEFAULT_NAMESPACE};
+
+    use crate::storage::engine::conformance;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_conformance() {
+        let ts = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        conformance::verify(move |name| async move {
+            let url = format!("sqlite:///tmp/flame_test_conformance_{}_{}.db", ts, name);
+            SqliteEngine::new_ptr(&url).await
+        })
+        .await;
+    }
+
     #[test]
     fn test_single_session() -> Result<(), FlameError> {
         let url = format!(
@@ -457,16 +1524,21 @@ mod tests {
             Utc::now().timestamp()
         );
         let storage = tokio_test::block_on(SqliteEngine::new_ptr(&url))?;
-        let ssn_1 = tokio_test::block_on(storage.create_session("flmexec".to_string(), 1, None))?;
+        let ssn_1 = tokio_test::block_on(storage.create_session(SessionAttributes {
+            application: "flmexec".to_string(),
+            ..Default::default()
+        }))?;
 
         assert_eq!(ssn_1.id, 1);
         assert_eq!(ssn_1.application, "flmexec");
         assert_eq!(ssn_1.status.state, SessionState::Open);
 
-        let task_1_1 = tokio_test::block_on(storage.create_task(ssn_1.id, None))?;
+        let task_1_1 =
+            tokio_test::block_on(storage.create_task(ssn_1.id, TaskAttributes::default()))?;
         assert_eq!(task_1_1.id, 1);
 
-        let task_1_2 = tokio_test::block_on(storage.create_task(ssn_1.id, None))?;
+        let task_1_2 =
+            tokio_test::block_on(storage.create_task(ssn_1.id, TaskAttributes::default()))?;
         assert_eq!(task_1_2.id, 2);
 
         let task_list = tokio_test::block_on(storage.find_tasks(ssn_1.id))?;
@@ -493,16 +1565,21 @@ mod tests {
             Utc::now().timestamp()
         );
         let storage = tokio_test::block_on(SqliteEngine::new_ptr(&url))?;
-        let ssn_1 = tokio_test::block_on(storage.create_session("flmexec".to_string(), 1, None))?;
+        let ssn_1 = tokio_test::block_on(storage.create_session(SessionAttributes {
+            application: "flmexec".to_string(),
+            ..Default::default()
+        }))?;
 
         assert_eq!(ssn_1.id, 1);
         assert_eq!(ssn_1.application, "flmexec");
         assert_eq!(ssn_1.status.state, SessionState::Open);
 
-        let task_1_1 = tokio_test::block_on(storage.create_task(ssn_1.id, None))?;
+        let task_1_1 =
+            tokio_test::block_on(storage.create_task(ssn_1.id, TaskAttributes::default()))?;
         assert_eq!(task_1_1.id, 1);
 
-        let task_1_2 = tokio_test::block_on(storage.create_task(ssn_1.id, None))?;
+        let task_1_2 =
+            tokio_test::block_on(storage.create_task(ssn_1.id, TaskAttributes::default()))?;
         assert_eq!(task_1_2.id, 2);
 
         let task_1_1 =
@@ -513,16 +1590,26 @@ mod tests {
             tokio_test::block_on(storage.update_task_state(task_1_2.gid(), TaskState::Succeed))?;
         assert_eq!(task_1_2.state, TaskState::Succeed);
 
-        let ssn_2 = tokio_test::block_on(storage.create_session("flmlog".to_string(), 1, None))?;
+        let labels = HashMap::from([("env".to_string(), "dev".to_string())]);
+        let ssn_2 = tokio_test::block_on(storage.create_session(SessionAttributes {
+            namespace: "team-a".to_string(),
+            application: "flmlog".to_string(),
+            labels: labels.clone(),
+            ..Default::default()
+        }))?;
 
         assert_eq!(ssn_2.id, 2);
         assert_eq!(ssn_2.application, "flmlog");
+        assert_eq!(ssn_2.namespace, "team-a");
+        assert_eq!(ssn_2.labels, labels);
         assert_eq!(ssn_2.status.state, SessionState::Open);
 
-        let task_2_1 = tokio_test::block_on(storage.create_task(ssn_2.id, None))?;
+        let task_2_1 =
+            tokio_test::block_on(storage.create_task(ssn_2.id, TaskAttributes::default()))?;
         assert_eq!(task_2_1.id, 1);
 
-        let task_2_2 = tokio_test::block_on(storage.create_task(ssn_2.id, None))?;
+        let task_2_2 =
+            tokio_test::block_on(storage.create_task(ssn_2.id, TaskAttributes::default()))?;
         assert_eq!(task_2_2.id, 2);
 
         let task_2_1 =
@@ -535,6 +1622,13 @@ mod tests {
 
         let ssn_list = tokio_test::block_on(storage.find_session())?;
         assert_eq!(ssn_list.len(), 2);
+        assert!(ssn_list.iter().any(|ssn| ssn.labels == labels));
+        assert!(ssn_list
+            .iter()
+            .any(|ssn| ssn.id == 1 && ssn.namespace == DEFAULT_NAMESPACE));
+        assert!(ssn_list
+            .iter()
+            .any(|ssn| ssn.id == 2 && ssn.namespace == "team-a"));
 
         let ssn_1 = tokio_test::block_on(storage.close_session(1))?;
         assert_eq!(ssn_1.status.state, SessionState::Closed);
@@ -551,16 +1645,21 @@ mod tests {
             Utc::now().timestamp()
         );
         let storage = tokio_test::block_on(SqliteEngine::new_ptr(&url))?;
-        let ssn_1 = tokio_test::block_on(storage.create_session("flmexec".to_string(), 1, None))?;
+        let ssn_1 = tokio_test::block_on(storage.create_session(SessionAttributes {
+            application: "flmexec".to_string(),
+            ..Default::default()
+        }))?;
 
         assert_eq!(ssn_1.id, 1);
         assert_eq!(ssn_1.application, "flmexec");
         assert_eq!(ssn_1.status.state, SessionState::Open);
 
-        let task_1_1 = tokio_test::block_on(storage.create_task(ssn_1.id, None))?;
+        let task_1_1 =
+            tokio_test::block_on(storage.create_task(ssn_1.id, TaskAttributes::default()))?;
         assert_eq!(task_1_1.id, 1);
 
-        let task_1_2 = tokio_test::block_on(storage.create_task(ssn_1.id, None))?;
+        let task_1_2 =
+            tokio_test::block_on(storage.create_task(ssn_1.id, TaskAttributes::default()))?;
         assert_eq!(task_1_2.id, 2);
 
         let res = tokio_test::block_on(storage.close_session(1));
@@ -577,13 +1676,17 @@ mod tests {
         );
 
         let storage = tokio_test::block_on(SqliteEngine::new_ptr(&url))?;
-        let ssn_1 = tokio_test::block_on(storage.create_session("flmexec".to_string(), 1, None))?;
+        let ssn_1 = tokio_test::block_on(storage.create_session(SessionAttributes {
+            application: "flmexec".to_string(),
+            ..Default::default()
+        }))?;
 
         assert_eq!(ssn_1.id, 1);
         assert_eq!(ssn_1.application, "flmexec");
         assert_eq!(ssn_1.status.state, SessionState::Open);
 
-        let task_1_1 = tokio_test::block_on(storage.create_task(ssn_1.id, None))?;
+        let task_1_1 =
+            tokio_test::block_on(storage.create_task(ssn_1.id, TaskAttributes::default()))?;
         assert_eq!(task_1_1.id, 1);
 
         let task_1_1 =
@@ -593,9 +1696,37 @@ mod tests {
         let ssn_1 = tokio_test::block_on(storage.close_session(1))?;
         assert_eq!(ssn_1.status.state, SessionState::Closed);
 
-        let res = tokio_test::block_on(storage.create_task(ssn_1.id, None));
+        let res = tokio_test::block_on(storage.create_task(ssn_1.id, TaskAttributes::default()));
         assert!(res.is_err());
 
         Ok(())
     }
+
+    #[test]
+    fn test_open_closed_session() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_open_closed_session_{}.db",
+            Utc::now().timestamp()
+        );
+
+        let storage = tokio_test::block_on(SqliteEngine::new_ptr(&url))?;
+        let ssn_1 = tokio_test::block_on(storage.create_session(SessionAttributes {
+            application: "flmexec".to_string(),
+            ..Default::default()
+        }))?;
+
+        let ssn_1 = tokio_test::block_on(storage.close_session(ssn_1.id))?;
+        assert_eq!(ssn_1.status.state, SessionState::Closed);
+        assert!(ssn_1.completion_time.is_some());
+
+        let ssn_1 = tokio_test::block_on(storage.open_session(ssn_1.id))?;
+        assert_eq!(ssn_1.status.state, SessionState::Open);
+        assert!(ssn_1.completion_time.is_none());
+
+        let task_1_1 =
+            tokio_test::block_on(storage.create_task(ssn_1.id, TaskAttributes::default()))?;
+        assert_eq!(task_1_1.id, 1);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,97 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::VecDeque;
+
+use chrono::Utc;
+
+use common::apis::{EventKind, SessionEvent};
+
+/// The maximum number of events kept for each session; the watchers which fall further
+/// behind will see a gap in the sequence.
+const MAX_EVENTS: usize = 1024;
+
+/// The recent events of a session for the watchers; the history of the session is persisted
+/// by `Storage::flush_events`.
+#[derive(Debug, Default)]
+pub struct EventLog {
+    last: u64,
+    events: VecDeque<SessionEvent>,
+}
+
+impl EventLog {
+    pub fn push(&mut self, kind: EventKind) -> SessionEvent {
+        self.last += 1;
+        let event = SessionEvent {
+            sequence: self.last,
+            event_time: Utc::now(),
+            kind,
+        };
+        self.events.push_back(event.clone());
+
+        if self.events.len() > MAX_EVENTS {
+            self.events.pop_front();
+        }
+
+        event
+    }
+
+    /// Whether there are events after `since`; a `since` ahead of the log, e.g. the session
+    /// manager was restarted, is taken as 0.
+    pub fn has_events(&self, since: u64) -> bool {
+        since > self.last || self.last > since
+    }
+
+    pub fn since(&self, since: u64) -> Vec<SessionEvent> {
+        let since = match since > self.last {
+            true => 0,
+            false => since,
+        };
+
+        self.events
+            .iter()
+            .filter(|e| e.sequence > since)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequences(events: &[SessionEvent]) -> Vec<u64> {
+        events.iter().map(|e| e.sequence).collect()
+    }
+
+    #[test]
+    fn test_event_log() {
+        let mut log = EventLog::default();
+        assert!(!log.has_events(0));
+
+        for _ in 0..3 {
+            log.push(EventKind::SessionClosed);
+        }
+        assert!(log.has_events(2));
+        assert!(!log.has_events(3));
+        assert_eq!(sequences(&log.since(1)), vec![2, 3]);
+        assert_eq!(sequences(&log.since(10)), vec![1, 2, 3]);
+
+        for _ in 0..MAX_EVENTS {
+            log.push(EventKind::SessionClosed);
+        }
+        let events = log.since(0);
+        assert_eq!(events.len(), MAX_EVENTS);
+        assert_eq!(events[0].sequence, 4);
+    }
+}
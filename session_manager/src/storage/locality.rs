@@ -0,0 +1,125 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use common::apis::Task;
+
+/// The tasks with a locality launched on an executor having its key, and those launched on
+/// another executor after the wait; the tasks without a locality are neither.
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+pub fn hits() -> u64 {
+    HITS.load(Ordering::Relaxed)
+}
+
+pub fn misses() -> u64 {
+    MISSES.load(Ordering::Relaxed)
+}
+
+fn is_local(task: &Task, keys: &[String]) -> bool {
+    task.locality
+        .as_ref()
+        .is_some_and(|locality| keys.contains(locality))
+}
+
+/// Whether the task may be launched on the executor having the keys: it has no locality,
+/// the executor has its key, or it waited for such an executor longer than the wait, so the
+/// locality never starves it.
+pub fn admits(task: &Task, keys: &[String], wait: Duration, now: DateTime<Utc>) -> bool {
+    if task.locality.is_none() || is_local(task, keys) {
+        return true;
+    }
+
+    (now - task.creation_time)
+        .to_std()
+        .is_ok_and(|waited| waited >= wait)
+}
+
+/// Counts the launch of the task on the executor having the keys.
+pub fn record(task: &Task, keys: &[String]) {
+    if task.locality.is_none() {
+        return;
+    }
+
+    match is_local(task, keys) {
+        true => HITS.fetch_add(1, Ordering::Relaxed),
+        false => MISSES.fetch_add(1, Ordering::Relaxed),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use common::apis::TaskState;
+
+    fn task(locality: Option<&str>, age: i64, now: DateTime<Utc>) -> Task {
+        Task {
+            id: 1,
+            ssn_id: 1,
+            index: 0,
+            input: None,
+            output: None,
+            trace_context: None,
+            labels: Default::default(),
+            locality: locality.map(str::to_string),
+            env: Default::default(),
+            timeout: None,
+            max_retries: None,
+            progress: None,
+            failure: None,
+            executor_id: None,
+            hostname: None,
+            original_task_id: None,
+            idempotency_key: None,
+            annotations: Default::default(),
+            output_chunks: 0,
+            spilled: false,
+            creation_time: now - chrono::Duration::seconds(age),
+            completion_time: None,
+            state: TaskState::Pending,
+        }
+    }
+
+    #[test]
+    fn test_admits() {
+        let now = Utc::now();
+        let wait = Duration::from_secs(3);
+        let keys = vec!["shard-1".to_string()];
+
+        assert!(admits(&task(None, 0, now), &[], wait, now));
+        assert!(admits(&task(Some("shard-1"), 0, now), &keys, wait, now));
+        // The task waits for the executors having its key, but not forever.
+        assert!(!admits(&task(Some("shard-2"), 0, now), &keys, wait, now));
+        assert!(!admits(&task(Some("shard-2"), 2, now), &[], wait, now));
+        assert!(admits(&task(Some("shard-2"), 3, now), &keys, wait, now));
+    }
+
+    #[test]
+    fn test_record() {
+        let now = Utc::now();
+        let keys = vec!["shard-1".to_string()];
+
+        let (hits0, misses0) = (hits(), misses());
+        record(&task(Some("shard-1"), 0, now), &keys);
+        assert!(hits() > hits0);
+
+        record(&task(Some("shard-2"), 5, now), &keys);
+        assert!(misses() > misses0);
+    }
+}
@@ -12,42 +12,206 @@ limitations under the License.
 */
 
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::future::Future;
-use std::ops::Deref;
 use std::pin::Pin;
 use std::rc::Rc;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use tokio::sync::OwnedMutexGuard;
 
 use common::apis::{
-    CommonData, Executor, ExecutorID, ExecutorPtr, Session, SessionID, SessionPtr, SessionState,
-    Task, TaskGID, TaskID, TaskInput, TaskOutput, TaskPtr, TaskState,
+    self, Annotations, Application, CacheScope, CommonData, EventKind, Executor, ExecutorID,
+    ExecutorPtr, ExecutorState, FailureReason, OutputChunk, Session, SessionAttributes,
+    SessionEvent, SessionID, SessionPtr, SessionState, Task, TaskAttributes, TaskFailure, TaskGID,
+    TaskID, TaskInput, TaskOutput, TaskProgress, TaskPtr, TaskState, DEFAULT_SESSION_SLOTS,
+    MAX_OUTPUT_CHUNK_BYTES,
+};
+use common::ctx::{
+    self, Durability, FlameCacheConf, FlamePriorityClassConf, FlameQuarantineConf, FlameQuotaConf,
+    FlameSessionTemplate, FlameUtilizationConf,
 };
-use common::ptr::{self, MutexPtr};
-use common::{lock_ptr, trace::TraceFn, trace_fn, FlameError};
+use common::payload;
+use common::ptr::{self, AsyncPtr, MutexPtr, ShardedMap};
+use common::{lock_ptr, FlameError};
 
-use crate::model::{ExecutorInfo, SessionInfo, SnapShot, SnapShotPtr};
-use crate::storage::engine::EnginePtr;
+use crate::model::{ExecutorInfo, SessionInfo, SnapShot, SnapShotPtr, TaskInfo, TimeSlice};
+use crate::storage::affinity::Affinity;
+use crate::storage::chunks::ChunkBuffer;
+use crate::storage::engine::{CacheKey, CachedOutput, EnginePtr, TaskUpdate};
+use crate::storage::events::EventLog;
+use crate::storage::quarantine::Quarantine;
+use crate::storage::states::Operation;
+use crate::storage::utilization::Utilization;
 
+mod affinity;
+mod chunks;
 mod engine;
+mod events;
+mod locality;
+mod quarantine;
 mod states;
+mod utilization;
+
+pub use affinity::{hits as affinity_hits, misses as affinity_misses};
+pub use engine::monitor::{
+    set_budget as set_engine_budget, slow_calls as slow_engine_calls, with_deadline,
+};
+pub use engine::retry::set_policy as set_engine_retry;
+pub use engine::EngineInfo;
+pub use locality::{hits as locality_hits, misses as locality_misses};
+pub use utilization::ExecutorUsage;
+
+/// How long the clients wait before retrying, if the backlog of the session is full.
+const BACKLOG_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// The maximum number of the events in the history of each session; the oldest ones are
+/// evicted first.
+const MAX_HISTORY_EVENTS: usize = 10_000;
+
+/// The times a task is requeued before it's failed, if it's not configured.
+const DEFAULT_MAX_TASK_RETRIES: u32 = 3;
+
+/// The last call of the client on a session is persisted at most once in it, so the calls
+/// don't write to the engine each time.
+pub const ACTIVITY_INTERVAL: Duration = Duration::from_secs(60);
 
 pub type StoragePtr = Arc<Storage>;
 
 #[derive(Clone)]
 pub struct Storage {
     engine: EnginePtr,
-    sessions: MutexPtr<HashMap<SessionID, SessionPtr>>,
-    executors: MutexPtr<HashMap<ExecutorID, ExecutorPtr>>,
+    /// The sessions and executors are looked up by every call of the Backend, so their maps
+    /// are sharded rather than locked as a whole.
+    sessions: Arc<ShardedMap<SessionID, SessionPtr>>,
+    executors: Arc<ShardedMap<ExecutorID, ExecutorPtr>>,
+    events: MutexPtr<HashMap<SessionID, EventLog>>,
+    /// The applications registered at runtime, which are persisted by the engine.
+    applications: MutexPtr<HashMap<String, Application>>,
+    /// The applications in the configuration of the session manager.
+    config_applications: MutexPtr<HashMap<String, Application>>,
+    /// The default maximum number of the pending and running tasks of a session.
+    max_pending_tasks: MutexPtr<Option<u32>>,
+    /// The bounds of the cached task outputs.
+    cache: MutexPtr<FlameCacheConf>,
+    /// Whether the payloads of the completed tasks are dropped from the memory.
+    spill_completed_tasks: MutexPtr<bool>,
+    /// The session templates in the configuration of the session manager.
+    session_templates: MutexPtr<Vec<FlameSessionTemplate>>,
+    /// The failures of the tasks on the executors, which quarantine the bad executors.
+    quarantine: MutexPtr<Quarantine>,
+    /// The busy and idle time of the executors, which is reported as their utilization.
+    utilization: MutexPtr<Utilization>,
+    /// The sessions served recently by the executors, which are preferred by the scheduler
+    /// when the sessions are bound again.
+    affinity: MutexPtr<Affinity>,
+    /// The tasks reserved by the scheduler for the executors, which are launched on them.
+    reservations: MutexPtr<HashMap<ExecutorID, TaskGID>>,
+    /// How long the tasks with a locality wait for the executors having their keys.
+    locality_wait: MutexPtr<Duration>,
+    /// The times a task is requeued before it's failed.
+    max_task_retries: MutexPtr<u32>,
+    /// Whether the sessions which no executor could ever run are rejected when created.
+    reject_unschedulable: MutexPtr<bool>,
+    /// The slice of the executors bound to a session, if the sessions take turns.
+    time_slice: MutexPtr<Option<TimeSlice>>,
+    /// The caps of the slots and the running tasks of the sessions in a namespace or of an
+    /// application.
+    quotas: MutexPtr<Vec<FlameQuotaConf>>,
+    /// The classes of the sessions, e.g. the executors reserved for them.
+    priority_classes: MutexPtr<Vec<FlamePriorityClassConf>>,
+    /// When each bound executor was bound and the tasks it launched since then, which are
+    /// kept in memory only; the executors bound before a restart start their slices at the
+    /// first snapshot after it.
+    slices: MutexPtr<HashMap<ExecutorID, (DateTime<Utc>, u32)>>,
+    /// The sessions the unbinding executors are bound to next, e.g. their slices were used
+    /// up for them.
+    rebinds: MutexPtr<HashMap<ExecutorID, SessionID>>,
+    /// The times each task not completed was requeued, which are kept in memory only.
+    retries: MutexPtr<HashMap<TaskGID, u32>>,
+    /// The time each running task was launched, which is kept in memory only; the tasks
+    /// running before a restart are timed from the first sweep after it.
+    launches: MutexPtr<HashMap<TaskGID, DateTime<Utc>>>,
+    /// The events not persisted yet, which are written to the engine in batches by
+    /// `flush_events`, so the transitions of the tasks don't wait for them.
+    pending_events: MutexPtr<Vec<(SessionID, SessionEvent)>>,
+    /// Serializes the flushes, so the events are persisted in their order.
+    flushing: AsyncPtr<()>,
+    /// Whether the state changes of the tasks are written behind, and the most changes
+    /// queued before they're written.
+    durability: MutexPtr<Durability>,
+    max_batch: MutexPtr<usize>,
+    /// The state changes of the tasks not persisted yet, which are written to the engine in
+    /// batches by `flush_task_updates`.
+    pending_updates: MutexPtr<Vec<TaskUpdate>>,
+    /// Serializes the flushes, so the changes are persisted in their order.
+    flushing_updates: AsyncPtr<()>,
+    /// The last calls of the clients on the sessions which are newer than the persisted
+    /// ones, see `touch_session`.
+    activities: MutexPtr<HashMap<SessionID, DateTime<Utc>>>,
+    /// The guards serializing the operations changing each session, see `lock_session`. A
+    /// guard is taken before any other lock of the storage, i.e. the sessions, the tasks and
+    /// the executors, and at most one guard is held at a time, so the operations never wait
+    /// for each other in a cycle.
+    session_ops: Arc<ShardedMap<SessionID, AsyncPtr<()>>>,
+    /// The latest output chunks of the running tasks, see `append_output_chunk`; they're
+    /// spilled to the engine once the tasks are completed.
+    output_chunks: MutexPtr<HashMap<TaskGID, ChunkBuffer>>,
 }
 
 pub async fn new_ptr(url: &str) -> Result<StoragePtr, FlameError> {
-    Ok(Arc::new(Storage {
-        engine: engine::connect(url).await?,
-        sessions: ptr::new_ptr(HashMap::new()),
-        executors: ptr::new_ptr(HashMap::new()),
-    }))
+    let engine = engine::connect(url).await?;
+    if !engine.capabilities().persistent {
+        log::warn!(
+            "The storage <{}> is not persistent, nothing is recovered after a restart.",
+            url
+        );
+    }
+
+    Ok(from_engine(engine))
+}
+
+/// The storage of the engine, which has nothing in memory until it's loaded.
+fn from_engine(engine: EnginePtr) -> StoragePtr {
+    Arc::new(Storage {
+        engine,
+        sessions: Arc::new(ShardedMap::new()),
+        executors: Arc::new(ShardedMap::new()),
+        events: ptr::new_ptr(HashMap::new()),
+        applications: ptr::new_ptr(HashMap::new()),
+        config_applications: ptr::new_ptr(HashMap::new()),
+        max_pending_tasks: ptr::new_ptr(None),
+        cache: ptr::new_ptr(FlameCacheConf::default()),
+        spill_completed_tasks: ptr::new_ptr(true),
+        session_templates: ptr::new_ptr(vec![]),
+        quarantine: ptr::new_ptr(Quarantine::default()),
+        utilization: ptr::new_ptr(Utilization::default()),
+        affinity: ptr::new_ptr(Affinity::default()),
+        reservations: ptr::new_ptr(HashMap::new()),
+        locality_wait: ptr::new_ptr(ctx::DEFAULT_LOCALITY_WAIT),
+        max_task_retries: ptr::new_ptr(DEFAULT_MAX_TASK_RETRIES),
+        reject_unschedulable: ptr::new_ptr(false),
+        time_slice: ptr::new_ptr(None),
+        quotas: ptr::new_ptr(vec![]),
+        priority_classes: ptr::new_ptr(vec![]),
+        slices: ptr::new_ptr(HashMap::new()),
+        rebinds: ptr::new_ptr(HashMap::new()),
+        retries: ptr::new_ptr(HashMap::new()),
+        launches: ptr::new_ptr(HashMap::new()),
+        pending_events: ptr::new_ptr(vec![]),
+        flushing: ptr::new_async_ptr(()),
+        durability: ptr::new_ptr(Durability::Strict),
+        max_batch: ptr::new_ptr(1),
+        pending_updates: ptr::new_ptr(vec![]),
+        flushing_updates: ptr::new_async_ptr(()),
+        activities: ptr::new_ptr(HashMap::new()),
+        session_ops: Arc::new(ShardedMap::new()),
+        output_chunks: ptr::new_ptr(HashMap::new()),
+    })
 }
 
 impl Storage {
@@ -56,34 +220,132 @@ impl Storage {
     }
 
     pub fn snapshot(&self) -> Result<SnapShotPtr, FlameError> {
-        let mut res = SnapShot {
-            sessions: HashMap::new(),
-            ssn_index: HashMap::new(),
-            executors: HashMap::new(),
-            exec_index: HashMap::new(),
-        };
+        let mut res = SnapShot::default();
 
         {
-            let ssn_map = lock_ptr!(self.sessions)?;
-            for ssn in ssn_map.deref().values() {
-                let ssn = lock_ptr!(ssn)?;
-                let info = SessionInfo::from(&(*ssn));
-                res.add_session(Rc::new(info));
+            let app_map = lock_ptr!(self.applications)?;
+            res.applications = app_map.keys().cloned().collect();
+        }
+        res.weights = self
+            .list_application()?
+            .iter()
+            .map(|app| (app.name.clone(), app.weight()))
+            .collect();
+        res.time_slice = *lock_ptr!(self.time_slice)?;
+        res.quotas = lock_ptr!(self.quotas)?.clone();
+        res.priority_classes = lock_ptr!(self.priority_classes)?.clone();
+
+        for ssn in self.sessions.values()? {
+            let ssn = lock_ptr!(ssn)?;
+            let info = SessionInfo::from(&(*ssn));
+            res.add_session(Rc::new(info));
+        }
+
+        let mut slices = lock_ptr!(self.slices)?;
+        let rebinds = lock_ptr!(self.rebinds)?.clone();
+        let affinity = lock_ptr!(self.affinity)?;
+        for exe in self.executors.values()? {
+            let exe = lock_ptr!(exe)?;
+            let mut info = ExecutorInfo::from(&(*exe).clone());
+            info.served = affinity
+                .served(&info.id)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect();
+            if info.state == ExecutorState::Bound {
+                let (bound_time, launched_tasks) =
+                    slices.entry(info.id.clone()).or_insert((Utc::now(), 0));
+                info.bound_time = Some(*bound_time);
+                info.launched_tasks = *launched_tasks;
             }
+            info.next_ssn_id = rebinds.get(&info.id).copied();
+            res.add_executor(Rc::new(info));
         }
 
+        Ok(Rc::new(RefCell::new(res)))
+    }
+
+    /// The pending tasks of the session which are not reserved, in the order of their ids;
+    /// they're loaded into the snapshot only for the sessions the plugins ask about.
+    pub fn pending_tasks(&self, ssn_id: SessionID) -> Result<Vec<TaskInfo>, FlameError> {
+        let reserved: HashSet<_> = lock_ptr!(self.reservations)?.values().copied().collect();
+
+        let ssn_ptr = self.get_session_ptr(ssn_id)?;
+        let ssn = lock_ptr!(ssn_ptr)?;
+        let mut tasks = vec![];
+        for task_ptr in ssn
+            .tasks_index
+            .get(&TaskState::Pending)
+            .into_iter()
+            .flat_map(HashMap::values)
         {
-            let exe_map = lock_ptr!(self.executors)?;
-            for exe in exe_map.deref().values() {
-                let exe = lock_ptr!(exe)?;
-                let info = ExecutorInfo::from(&(*exe).clone());
-                res.add_executor(Rc::new(info));
+            let task = lock_ptr!(task_ptr)?;
+            if !reserved.contains(&task.gid()) {
+                tasks.push(TaskInfo::from(&*task));
             }
         }
+        tasks.sort_by_key(|t| t.id);
 
-        Ok(Rc::new(RefCell::new(res)))
+        Ok(tasks)
+    }
+
+    /// Reserves the pending task for the executor, so it's launched on the executor and not
+    /// on the others; the last reservation of the executor is replaced.
+    pub fn reserve_task(&self, id: ExecutorID, gid: TaskGID) -> Result<(), FlameError> {
+        let mut reservations = lock_ptr!(self.reservations)?;
+        reservations.insert(id, gid);
+
+        Ok(())
+    }
+
+    /// Takes the task reserved for the executor in the session, and returns it with the tasks
+    /// of the session reserved for the others.
+    fn take_reservation(
+        &self,
+        id: &ExecutorID,
+        ssn_id: SessionID,
+    ) -> Result<(Option<TaskID>, HashSet<TaskID>), FlameError> {
+        let mut reservations = lock_ptr!(self.reservations)?;
+        let reserved = reservations
+            .remove(id)
+            .filter(|gid| gid.ssn_id == ssn_id)
+            .map(|gid| gid.task_id);
+        let others = reservations
+            .values()
+            .filter(|gid| gid.ssn_id == ssn_id)
+            .map(|gid| gid.task_id)
+            .collect();
+
+        Ok((reserved, others))
+    }
+
+    fn locality_wait(&self) -> Result<Duration, FlameError> {
+        Ok(*lock_ptr!(self.locality_wait)?)
+    }
+
+    /// Drops the task reserved for the executor, e.g. it's unbound before launching it.
+    fn release_reservation(&self, id: &ExecutorID) -> Result<(), FlameError> {
+        lock_ptr!(self.reservations)?.remove(id);
+
+        Ok(())
+    }
+
+    /// Binds the executor to the session once it's unbound, instead of the session chosen by
+    /// the scheduler then.
+    pub fn rebind_executor(&self, id: ExecutorID, ssn_id: SessionID) -> Result<(), FlameError> {
+        lock_ptr!(self.rebinds)?.insert(id, ssn_id);
+
+        Ok(())
+    }
+
+    /// Ends the slice of the executor, e.g. it's unbound from its session.
+    fn end_slice(&self, id: &ExecutorID) -> Result<(), FlameError> {
+        lock_ptr!(self.slices)?.remove(id);
+
+        Ok(())
     }
 
+    #[tracing::instrument(name = "Storage::load_data", level = "debug", skip_all)]
     pub async fn load_data(&self) -> Result<(), FlameError> {
         let ssn_list = self.engine.find_session().await?;
         for ssn in ssn_list {
@@ -95,295 +357,2483 @@ impl Storage {
                     _ => task,
                 };
 
-                ssn.update_task(&task);
+                ssn.update_task(&self.resident(task)?);
             }
 
-            let mut ssn_map = lock_ptr!(self.sessions)?;
-            ssn_map.insert(ssn.id, SessionPtr::new(ssn.into()));
+            self.sessions.insert(ssn.id, SessionPtr::new(ssn.into()))?;
+        }
+
+        let app_list = self.engine.find_application().await?;
+        let mut app_map = lock_ptr!(self.applications)?;
+        for app in app_list {
+            app_map.insert(app.name.clone(), app);
         }
 
         Ok(())
     }
 
-    pub async fn create_session(
-        &self,
-        app: String,
-        slots: i32,
-        common_data: Option<CommonData>,
-    ) -> Result<Session, FlameError> {
-        let ssn = self.engine.create_session(app, slots, common_data).await?;
+    /// Persists the states of the sessions and tasks which differ from the engine, e.g. after
+    /// a failed write; returns the number of the sessions and tasks persisted. The changes
+    /// written behind are flushed first, so nothing else is persisted normally.
+    #[tracing::instrument(name = "Storage::flush", level = "debug", skip_all)]
+    pub async fn flush(&self) -> Result<(u32, u32), FlameError> {
+        // The changes written behind go first, so they're not taken as differences.
+        self.flush_task_updates().await?;
 
-        let mut ssn_map = lock_ptr!(self.sessions)?;
-        ssn_map.insert(ssn.id, SessionPtr::new(ssn.clone().into()));
+        // The copies of the sessions do not have their tasks.
+        let mut ssn_list: Vec<(Session, Vec<TaskPtr>)> = vec![];
+        for ssn in self.sessions.values()? {
+            let ssn = lock_ptr!(ssn)?;
+            ssn_list.push((ssn.summary(), ssn.tasks.values().cloned().collect()));
+        }
 
-        Ok(ssn)
-    }
+        let persisted: HashMap<SessionID, Session> = self
+            .engine
+            .find_session()
+            .await?
+            .into_iter()
+            .map(|ssn| (ssn.id, ssn))
+            .collect();
 
-    pub async fn close_session(&self, id: SessionID) -> Result<Session, FlameError> {
-        let ssn = self.engine.close_session(id).await?;
+        let (mut ssn_count, mut task_count) = (0, 0);
+        for (ssn, tasks) in ssn_list {
+            let Some(persisted_ssn) = persisted.get(&ssn.id) else {
+                log::warn!("Session <{}> is not found in the engine.", ssn.id);
+                continue;
+            };
 
-        let ssn_ptr = self.get_session_ptr(ssn.id)?;
-        let mut ssn = lock_ptr!(ssn_ptr)?;
-        ssn.status.state = SessionState::Closed;
+            // The tasks go first, as a session can only be closed when its tasks are completed.
+            let persisted_tasks: HashMap<_, _> = self
+                .engine
+                .find_tasks(ssn.id)
+                .await?
+                .into_iter()
+                .map(|task| (task.id, task.state))
+                .collect();
+            for task in &tasks {
+                let (gid, state) = {
+                    let task = lock_ptr!(task)?;
+                    (task.gid(), task.state)
+                };
+                match persisted_tasks.get(&gid.task_id) {
+                    Some(persisted_state) if *persisted_state != state => {
+                        self.engine.update_task_state(gid, state).await?;
+                        task_count += 1;
+                    }
+                    Some(_) => {}
+                    None => log::warn!("Task <{}> is not found in the engine.", gid),
+                }
+            }
+
+            if persisted_ssn.status.state != ssn.status.state {
+                match ssn.status.state {
+                    SessionState::Open => self.engine.open_session(ssn.id).await?,
+                    SessionState::Closed => self.engine.close_session(ssn.id).await?,
+                };
+                ssn_count += 1;
+            }
+        }
+
+        if ssn_count + task_count > 0 {
+            log::info!(
+                "Flushed {} session(s) and {} task(s) to the engine.",
+                ssn_count,
+                task_count
+            );
+        }
 
-        Ok(ssn.clone())
+        Ok((ssn_count, task_count))
     }
 
-    pub fn get_session(&self, id: SessionID) -> Result<Session, FlameError> {
-        let ssn_ptr = self.get_session_ptr(id)?;
-        let ssn = lock_ptr!(ssn_ptr)?;
-        Ok(ssn.clone())
+    pub fn engine_info(&self) -> EngineInfo {
+        self.engine.info()
     }
 
-    pub fn get_session_ptr(&self, id: SessionID) -> Result<SessionPtr, FlameError> {
-        let ssn_map = lock_ptr!(self.sessions)?;
-        let ssn = ssn_map
-            .get(&id)
-            .ok_or(FlameError::NotFound(id.to_string()))?;
+    /// Sets the applications in the configuration, which are merged with the registered
+    /// applications; the registered one wins if both have the same name.
+    pub fn set_config_applications(&self, apps: &[Application]) -> Result<(), FlameError> {
+        let mut app_map = lock_ptr!(self.config_applications)?;
+        *app_map = apps
+            .iter()
+            .map(|app| (app.name.clone(), app.clone()))
+            .collect();
 
-        Ok(ssn.clone())
+        Ok(())
     }
 
-    pub fn get_task_ptr(&self, gid: TaskGID) -> Result<TaskPtr, FlameError> {
-        let ssn_map = lock_ptr!(self.sessions)?;
-        let ssn_ptr = ssn_map
-            .get(&gid.ssn_id)
-            .ok_or(FlameError::NotFound(gid.ssn_id.to_string()))?;
+    pub fn set_max_pending_tasks(&self, max_pending_tasks: Option<u32>) -> Result<(), FlameError> {
+        *lock_ptr!(self.max_pending_tasks)? = max_pending_tasks;
+        Ok(())
+    }
 
-        let ssn = lock_ptr!(ssn_ptr)?;
-        let task_ptr = ssn
-            .tasks
-            .get(&gid.task_id)
-            .ok_or(FlameError::NotFound(gid.to_string()))?;
+    pub fn set_cache_conf(&self, conf: FlameCacheConf) -> Result<(), FlameError> {
+        *lock_ptr!(self.cache)? = conf;
+        Ok(())
+    }
 
-        Ok(task_ptr.clone())
+    pub fn set_spill_completed_tasks(&self, spill: bool) -> Result<(), FlameError> {
+        *lock_ptr!(self.spill_completed_tasks)? = spill;
+        Ok(())
     }
 
-    pub async fn delete_session(&self, id: SessionID) -> Result<Session, FlameError> {
-        let ssn = self.engine.delete_session(id).await?;
+    pub fn set_max_task_retries(&self, max_task_retries: Option<u32>) -> Result<(), FlameError> {
+        *lock_ptr!(self.max_task_retries)? = max_task_retries.unwrap_or(DEFAULT_MAX_TASK_RETRIES);
+        Ok(())
+    }
 
-        let mut ssn_map = lock_ptr!(self.sessions)?;
-        ssn_map.remove(&ssn.id);
+    /// Writes the state changes of the tasks behind in batches of at most `max_batch` if the
+    /// durability is batched, see `flush_task_updates`.
+    pub fn set_durability(
+        &self,
+        durability: Durability,
+        max_batch: usize,
+    ) -> Result<(), FlameError> {
+        *lock_ptr!(self.durability)? = durability;
+        *lock_ptr!(self.max_batch)? = max_batch.max(1);
+        Ok(())
+    }
 
-        Ok(ssn)
+    pub fn set_locality_wait(&self, wait: Duration) -> Result<(), FlameError> {
+        *lock_ptr!(self.locality_wait)? = wait;
+        Ok(())
     }
 
-    pub fn list_session(&self) -> Result<Vec<Session>, FlameError> {
-        let mut ssn_list = vec![];
-        let ssn_map = lock_ptr!(self.sessions)?;
+    pub fn set_reject_unschedulable(&self, reject: bool) -> Result<(), FlameError> {
+        *lock_ptr!(self.reject_unschedulable)? = reject;
+        Ok(())
+    }
 
-        for ssn in ssn_map.deref().values() {
-            let ssn = lock_ptr!(ssn)?;
-            ssn_list.push((*ssn).clone());
-        }
+    pub fn set_time_slice(&self, time_slice: Option<TimeSlice>) -> Result<(), FlameError> {
+        *lock_ptr!(self.time_slice)? = time_slice;
+        Ok(())
+    }
 
-        Ok(ssn_list)
+    pub fn set_quotas(&self, quotas: &[FlameQuotaConf]) -> Result<(), FlameError> {
+        *lock_ptr!(self.quotas)? = quotas.to_vec();
+        Ok(())
     }
 
-    pub async fn create_task(
+    pub fn set_priority_classes(
         &self,
-        ssn_id: SessionID,
-        task_input: Option<TaskInput>,
-    ) -> Result<Task, FlameError> {
-        let task = self.engine.create_task(ssn_id, task_input).await?;
+        classes: &[FlamePriorityClassConf],
+    ) -> Result<(), FlameError> {
+        *lock_ptr!(self.priority_classes)? = classes.to_vec();
+        Ok(())
+    }
 
-        let ssn = self.get_session_ptr(ssn_id)?;
-        let mut ssn = lock_ptr!(ssn)?;
-        ssn.update_task(&task);
+    /// The `max_running_tasks` of the session if its running tasks reached it.
+    fn running_limit_reached(ssn_ptr: &SessionPtr) -> Result<Option<u32>, FlameError> {
+        let ssn = lock_ptr!(ssn_ptr)?;
+        let running = ssn
+            .tasks_index
+            .get(&TaskState::Running)
+            .map(HashMap::len)
+            .unwrap_or_default();
 
-        Ok(task)
+        Ok(ssn.max_running_tasks.filter(|max| running >= *max as usize))
     }
 
-    pub fn get_task(&self, ssn_id: SessionID, id: TaskID) -> Result<Task, FlameError> {
-        let ssn_map = lock_ptr!(self.sessions)?;
+    /// The quota whose running tasks reached its `max_running_tasks` if a task of the session
+    /// is launched; the concurrent launches may exceed it by a few tasks, as they're counted
+    /// without a lock of all the sessions.
+    fn running_quota_reached(&self, ssn_ptr: &SessionPtr) -> Result<Option<String>, FlameError> {
+        let (namespace, application) = {
+            let ssn = lock_ptr!(ssn_ptr)?;
+            (ssn.namespace.clone(), ssn.application.clone())
+        };
+        let quotas: Vec<_> = lock_ptr!(self.quotas)?
+            .iter()
+            .filter(|q| q.max_running_tasks.is_some() && q.matches(&namespace, &application))
+            .cloned()
+            .collect();
+        if quotas.is_empty() {
+            return Ok(None);
+        }
 
-        let ssn = ssn_map
-            .get(&ssn_id)
-            .ok_or(FlameError::NotFound(ssn_id.to_string()))?;
+        let mut running = vec![0; quotas.len()];
+        for ssn_ptr in self.sessions.values()? {
+            let ssn = lock_ptr!(ssn_ptr)?;
+            let n = ssn
+                .tasks_index
+                .get(&TaskState::Running)
+                .map(HashMap::len)
+                .unwrap_or_default();
+            for (i, quota) in quotas.iter().enumerate() {
+                if quota.matches(&ssn.namespace, &ssn.application) {
+                    running[i] += n;
+                }
+            }
+        }
 
-        let ssn = lock_ptr!(ssn)?;
-        let task = ssn
-            .tasks
-            .get(&id)
-            .ok_or(FlameError::NotFound(id.to_string()))?;
-        let task = lock_ptr!(task)?;
-        Ok(task.clone())
+        Ok(quotas
+            .iter()
+            .zip(running)
+            .find(|(q, n)| q.max_running_tasks.is_some_and(|max| *n >= max as usize))
+            .map(|(q, _)| q.name()))
     }
 
-    pub async fn update_task_state(
-        &self,
-        ssn: SessionPtr,
-        task: TaskPtr,
-        state: TaskState,
-    ) -> Result<(), FlameError> {
-        let gid = TaskGID {
-            ssn_id: {
-                let ssn_ptr = lock_ptr!(ssn)?;
-                ssn_ptr.id
-            },
-            task_id: {
-                let task_ptr = lock_ptr!(task)?;
-                task_ptr.id
-            },
-        };
+    /// The task kept in memory: the input and the output of a completed task are dropped if
+    /// they're spilled, as they're persisted by the engine; it's a stub with the state and
+    /// the timestamps of the task, so the counting and the scheduling are not changed.
+    fn resident(&self, task: Task) -> Result<Task, FlameError> {
+        if !task.is_completed() || !*lock_ptr!(self.spill_completed_tasks)? {
+            return Ok(task);
+        }
+
+        Ok(Task {
+            input: None,
+            output: None,
+            spilled: true,
+            ..task
+        })
+    }
 
-        let task = self.engine.update_task_state(gid, state).await?;
+    /// Loads the input and the output of the spilled task from the engine.
+    async fn rehydrate(&self, task: Task) -> Result<Task, FlameError> {
+        if !task.spilled {
+            return Ok(task);
+        }
 
-        let mut ssn_ptr = lock_ptr!(ssn)?;
-        ssn_ptr.update_task(&task);
+        // The output of the task may be written behind still.
+        self.flush_task_updates().await?;
+        let persisted = self.engine.get_task(task.gid()).await?;
+        Ok(Task {
+            input: persisted.input,
+            output: persisted.output,
+            spilled: false,
+            ..task
+        })
+    }
 
+    pub fn set_quarantine_conf(&self, conf: &FlameQuarantineConf) -> Result<(), FlameError> {
+        *lock_ptr!(self.quarantine)? = Quarantine::new(conf)?;
         Ok(())
     }
 
-    pub async fn watch_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
-        let task_ptr = self.get_task_ptr(gid)?;
-        WatchTaskFuture::new(self.clone_ptr(), &task_ptr)?.await?;
-
-        let task = lock_ptr!(task_ptr)?;
-        Ok((*task).clone())
+    pub fn set_utilization_conf(&self, conf: &FlameUtilizationConf) -> Result<(), FlameError> {
+        *lock_ptr!(self.utilization)? = Utilization::new(conf)?;
+        Ok(())
     }
 
-    pub fn register_executor(&self, e: &Executor) -> Result<(), FlameError> {
-        let mut exe_map = lock_ptr!(self.executors)?;
-        let exe = ExecutorPtr::new(e.clone().into());
-        exe_map.insert(e.id.clone(), exe);
-
+    pub fn set_session_templates(
+        &self,
+        templates: &[FlameSessionTemplate],
+    ) -> Result<(), FlameError> {
+        *lock_ptr!(self.session_templates)? = templates.to_vec();
         Ok(())
     }
 
-    pub fn get_executor_ptr(&self, id: ExecutorID) -> Result<ExecutorPtr, FlameError> {
-        let exe_map = lock_ptr!(self.executors)?;
-        let exe = exe_map
-            .get(&id)
-            .ok_or(FlameError::NotFound(id.to_string()))?;
+    pub fn get_session_template(&self, name: &str) -> Result<FlameSessionTemplate, FlameError> {
+        let templates = lock_ptr!(self.session_templates)?;
+        templates
+            .iter()
+            .find(|t| t.name == name)
+            .cloned()
+            .ok_or(FlameError::NotFound(format!("session template <{}>", name)))
+    }
 
-        Ok(exe.clone())
+    pub fn list_session_templates(&self) -> Result<Vec<FlameSessionTemplate>, FlameError> {
+        let templates = lock_ptr!(self.session_templates)?;
+        Ok(templates.clone())
     }
 
-    pub async fn wait_for_session(&self, id: ExecutorID) -> Result<Session, FlameError> {
-        let exe_ptr = self.get_executor_ptr(id)?;
-        let ssn_id = WaitForSsnFuture::new(&exe_ptr).await?;
+    #[tracing::instrument(
+        name = "Storage::register_application",
+        level = "debug",
+        skip_all,
+        fields(application = %app.name)
+    )]
+    pub async fn register_application(&self, app: Application) -> Result<(), FlameError> {
+        if let Some(problem) = app.problems().into_iter().next() {
+            return Err(FlameError::invalid_argument("application", problem));
+        }
 
-        let ssn_ptr = self.get_session_ptr(ssn_id)?;
-        let ssn = lock_ptr!(ssn_ptr)?;
+        if lock_ptr!(self.applications)?.contains_key(&app.name) {
+            return Err(FlameError::AlreadyExists(format!(
+                "application <{}>",
+                app.name
+            )));
+        }
 
-        Ok((*ssn).clone())
+        let app = self.engine.register_application(app).await?;
+
+        let mut app_map = lock_ptr!(self.applications)?;
+        app_map.insert(app.name.clone(), app);
+
+        Ok(())
     }
 
-    pub async fn bind_session(&self, id: ExecutorID, ssn_id: SessionID) -> Result<(), FlameError> {
-        trace_fn!("Storage::bind_session");
+    /// Deletes the registered application; it fails if there're open sessions of the
+    /// application, unless `force`.
+    #[tracing::instrument(
+        name = "Storage::delete_application",
+        level = "debug",
+        skip_all,
+        fields(application = %name)
+    )]
+    pub async fn delete_application(&self, name: String, force: bool) -> Result<(), FlameError> {
+        if !lock_ptr!(self.applications)?.contains_key(&name) {
+            if lock_ptr!(self.config_applications)?.contains_key(&name) {
+                return Err(FlameError::FailedPrecondition(format!(
+                    "application <{}> is in the configuration",
+                    name
+                )));
+            }
+            return Err(FlameError::NotFound(format!("application <{}>", name)));
+        }
 
-        let exe_ptr = self.get_executor_ptr(id)?;
-        let state = states::from(Arc::new(self.clone()), exe_ptr)?;
+        if !force {
+            let open = self
+                .list_session()?
+                .iter()
+                .filter(|ssn| ssn.application == name && ssn.status.state == SessionState::Open)
+                .count();
+            if open > 0 {
+                return Err(FlameError::FailedPrecondition(format!(
+                    "application <{}> has {} open sessions",
+                    name, open
+                )));
+            }
+        }
 
-        let ssn_ptr = self.get_session_ptr(ssn_id)?;
-        state.bind_session(ssn_ptr).await?;
+        self.engine.delete_application(name.clone()).await?;
+
+        let mut app_map = lock_ptr!(self.applications)?;
+        app_map.remove(&name);
 
         Ok(())
     }
 
-    pub async fn bind_session_completed(&self, id: ExecutorID) -> Result<(), FlameError> {
-        trace_fn!("Storage::bind_session_completed");
+    pub fn get_application(&self, name: &str) -> Result<Application, FlameError> {
+        if let Some(app) = lock_ptr!(self.applications)?.get(name) {
+            return Ok(app.clone());
+        }
 
-        let exe_ptr = self.get_executor_ptr(id)?;
-        let state = states::from(Arc::new(self.clone()), exe_ptr)?;
+        lock_ptr!(self.config_applications)?
+            .get(name)
+            .cloned()
+            .ok_or(FlameError::NotFound(format!("application <{}>", name)))
+    }
 
-        state.bind_session_completed().await?;
+    /// The application run by the session, which is the one recorded when it was created, or
+    /// the latest one of its name.
+    pub fn session_application(&self, ssn: &Session) -> Result<Application, FlameError> {
+        match &ssn.application_spec {
+            Some(app) => Ok(app.clone()),
+            None => self.get_application(&ssn.application),
+        }
+    }
 
-        Ok(())
+    /// Lists the applications in the configuration and the registered ones.
+    pub fn list_application(&self) -> Result<Vec<Application>, FlameError> {
+        let mut app_map = lock_ptr!(self.config_applications)?.clone();
+        for (name, app) in lock_ptr!(self.applications)?.iter() {
+            app_map.insert(name.clone(), app.clone());
+        }
+
+        let mut app_list: Vec<_> = app_map.into_values().collect();
+        app_list.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(app_list)
     }
 
-    pub async fn launch_task(&self, id: ExecutorID) -> Result<Option<Task>, FlameError> {
-        trace_fn!("Storage::launch_task");
-        let exe_ptr = self.get_executor_ptr(id)?;
-        let state = states::from(self.clone_ptr(), exe_ptr.clone())?;
-        let (ssn_id, task_id) = {
-            let exec = lock_ptr!(exe_ptr)?;
-            (exec.ssn_id, exec.task_id)
-        };
-        let ssn_id = ssn_id.ok_or(FlameError::InvalidState(
-            "no session in bound executor".to_string(),
-        ))?;
+    #[tracing::instrument(name = "Storage::create_session", level = "debug", skip_all)]
+    pub async fn create_session(&self, attrs: SessionAttributes) -> Result<Session, FlameError> {
+        let app = self.get_application(&attrs.application)?;
+        let attrs = self.session_defaults(attrs, &app)?;
 
-        //
-        if let Some(task_id) = task_id {
-            log::warn!(
-                "Re-launch the task <{}/{}>",
-                ssn_id.clone(),
-                task_id.clone()
-            );
-            let task_ptr = self.get_task_ptr(TaskGID { ssn_id, task_id })?;
+        if let Some(class) = &attrs.priority_class {
+            let classes = lock_ptr!(self.priority_classes)?;
+            if !classes.iter().any(|c| &c.name == class) {
+                return Err(FlameError::invalid_argument(
+                    "priority_class",
+                    format!("unknown priority class <{}>", class),
+                ));
+            }
+        }
 
-            let task = lock_ptr!(task_ptr)?;
-            return Ok(Some((*task).clone()));
+        if *lock_ptr!(self.reject_unschedulable)? {
+            let snapshot = self.snapshot()?;
+            let reason = snapshot
+                .borrow()
+                .unschedulable(&attrs.application, attrs.slots);
+            if let Some(reason) = reason {
+                return Err(FlameError::FailedPrecondition(format!(
+                    "the session is unschedulable: {}",
+                    reason
+                )));
+            }
         }
 
-        let ssn_ptr = self.get_session_ptr(ssn_id)?;
-        state.launch_task(ssn_ptr).await
+        let ssn = self.engine.create_session(attrs).await?;
+
+        self.sessions
+            .insert(ssn.id, SessionPtr::new(ssn.clone().into()))?;
+
+        Ok(ssn)
     }
 
-    pub async fn complete_task(
+    /// Fills the settings not set in the attributes, e.g. the slots are 0, by the defaults
+    /// of the application, and then by the ones of the session manager; the labels are
+    /// merged, and the ones of the session win on conflicts. The resolved settings and the
+    /// application itself, unless `latest_application`, are recorded on the session, so
+    /// they're not changed by the application afterwards.
+    fn session_defaults(
         &self,
-        id: ExecutorID,
-        task_output: Option<TaskOutput>,
-    ) -> Result<(), FlameError> {
-        trace_fn!("Storage::complete_task");
-        let exe_ptr = self.get_executor_ptr(id)?;
-        let (ssn_id, task_id) = {
-            let exe = lock_ptr!(exe_ptr)?;
-            (
-                exe.ssn_id.ok_or(FlameError::InvalidState(
-                    "no session in executor".to_string(),
-                ))?,
-                exe.task_id
-                    .ok_or(FlameError::InvalidState("no task in executor".to_string()))?,
-            )
+        attrs: SessionAttributes,
+        app: &Application,
+    ) -> Result<SessionAttributes, FlameError> {
+        let mut labels = app.default_labels.clone();
+        labels.extend(attrs.labels);
+        let max_task_retries = match attrs.max_task_retries.or(app.default_max_retries) {
+            Some(n) => n,
+            None => *lock_ptr!(self.max_task_retries)?,
         };
 
-        let task_ptr = self.get_task_ptr(TaskGID { ssn_id, task_id })?;
-        let ssn_ptr = self.get_session_ptr(ssn_id)?;
-
-        let state = states::from(self.clone_ptr(), exe_ptr)?;
-        state.complete_task(ssn_ptr, task_ptr, task_output).await?;
-
-        Ok(())
+        Ok(SessionAttributes {
+            slots: match attrs.slots {
+                0 => app.default_slots.unwrap_or(DEFAULT_SESSION_SLOTS),
+                slots => slots,
+            },
+            labels,
+            task_timeout: attrs.task_timeout.or(app.default_task_timeout),
+            max_task_retries: Some(max_task_retries),
+            application_spec: match attrs.latest_application {
+                true => None,
+                false => attrs.application_spec.or_else(|| Some(app.clone())),
+            },
+            ..attrs
+        })
     }
 
-    pub async fn unbind_executor(&self, id: ExecutorID) -> Result<(), FlameError> {
-        let exe_ptr = self.get_executor_ptr(id)?;
-        let state = states::from(Arc::new(self.clone()), exe_ptr)?;
-        state.unbind_executor().await?;
+    /// Fills the settings not set in the attributes by the ones of the session, which were
+    /// resolved when it was created; the sessions created before the settings were recorded
+    /// take the default of the session manager.
+    fn task_defaults(
+        &self,
+        ssn_id: SessionID,
+        attrs: TaskAttributes,
+    ) -> Result<TaskAttributes, FlameError> {
+        let (timeout, max_retries) = {
+            let ssn_ptr = self.get_session_ptr(ssn_id)?;
+            let ssn = lock_ptr!(ssn_ptr)?;
+            (ssn.task_timeout, ssn.max_task_retries)
+        };
+        let max_retries = match attrs.max_retries.or(max_retries) {
+            Some(n) => n,
+            None => *lock_ptr!(self.max_task_retries)?,
+        };
 
-        Ok(())
+        Ok(TaskAttributes {
+            timeout: attrs.timeout.or(timeout),
+            max_retries: Some(max_retries),
+            ..attrs
+        })
     }
 
-    pub async fn unbind_executor_completed(&self, id: ExecutorID) -> Result<(), FlameError> {
-        let exe_ptr = self.get_executor_ptr(id)?;
-        let state = states::from(Arc::new(self.clone()), exe_ptr)?;
+    #[tracing::instrument(
+        name = "Storage::open_session",
+        level = "debug",
+        skip_all,
+        fields(session_id = id)
+    )]
+    pub async fn open_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        let _guard = self.lock_session(id).await?;
+        let ssn = self.engine.open_session(id).await?;
 
-        state.unbind_executor_completed().await?;
+        let ssn_ptr = self.get_session_ptr(ssn.id)?;
+        let mut ssn = lock_ptr!(ssn_ptr)?;
+        ssn.status.state = SessionState::Open;
+        ssn.status.generation += 1;
+        ssn.completion_time = None;
 
-        Ok(())
+        Ok(ssn.summary())
     }
-}
-
-struct WaitForSsnFuture {
-    executor: ExecutorPtr,
-}
 
-impl WaitForSsnFuture {
-    pub fn new(exe_ptr: &ExecutorPtr) -> Self {
-        Self {
-            executor: exe_ptr.clone(),
-        }
+    #[tracing::instrument(
+        name = "Storage::close_session",
+        level = "debug",
+        skip_all,
+        fields(session_id = id)
+    )]
+    pub async fn close_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        let _guard = self.lock_session(id).await?;
+        self.close_locked_session(id).await
     }
-}
 
-impl Future for WaitForSsnFuture {
-    type Output = Result<SessionID, FlameError>;
+    /// Closes the session whose guard is held by the caller.
+    async fn close_locked_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        // The engine closes the session only if its tasks are completed there.
+        self.flush_task_updates().await?;
+        let closed = self.engine.close_session(id).await?;
 
-    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
-        let exe = lock_ptr!(self.executor)?;
+        let ssn = {
+            let ssn_ptr = self.get_session_ptr(closed.id)?;
+            let mut ssn = lock_ptr!(ssn_ptr)?;
+            ssn.status.state = SessionState::Closed;
+            ssn.status.generation += 1;
+            ssn.completion_time = closed.completion_time;
+            ssn.summary()
+        };
+        self.push_event(closed.id, EventKind::SessionClosed)?;
+
+        Ok(ssn)
+    }
+
+    /// Sets the deadline of the open session, e.g. to extend it.
+    #[tracing::instrument(
+        name = "Storage::update_session_deadline",
+        level = "debug",
+        skip_all,
+        fields(session_id = id)
+    )]
+    pub async fn update_session_deadline(
+        &self,
+        id: SessionID,
+        deadline: Option<DateTime<Utc>>,
+    ) -> Result<Session, FlameError> {
+        let _guard = self.lock_session(id).await?;
+        let ssn = self.engine.update_session_deadline(id, deadline).await?;
+
+        let ssn_ptr = self.get_session_ptr(ssn.id)?;
+        let mut ssn = lock_ptr!(ssn_ptr)?;
+        ssn.deadline = deadline;
+
+        Ok(ssn.summary())
+    }
+
+    /// Closes the open sessions whose deadlines are not after `now`, and aborts their pending
+    /// and running tasks; returns the ids of the expired sessions.
+    #[tracing::instrument(name = "Storage::expire_sessions", level = "debug", skip_all)]
+    pub async fn expire_sessions(&self, now: DateTime<Utc>) -> Result<Vec<SessionID>, FlameError> {
+        let mut expired = vec![];
+        for ssn in self.sessions.values()? {
+            let ssn = lock_ptr!(ssn)?;
+            match ssn.deadline {
+                Some(deadline) if ssn.status.state == SessionState::Open && deadline <= now => {
+                    expired.push((ssn.id, deadline));
+                }
+                _ => {}
+            }
+        }
+
+        let mut ids = vec![];
+        for (id, deadline) in expired {
+            // The other sessions are expired anyway; the failed one is retried by the next sweep.
+            match self.expire_session(id, deadline).await {
+                Ok(Some(_)) => {
+                    log::info!("Session <{}> expired at <{}>.", id, deadline);
+                    ids.push(id);
+                }
+                Ok(None) => {}
+                Err(e) => log::error!("Failed to expire session <{}>: {}", id, e),
+            }
+        }
+
+        Ok(ids)
+    }
+
+    /// Expires the session unless it was closed or its deadline was changed since it was
+    /// found expired, in which case none is returned.
+    async fn expire_session(
+        &self,
+        id: SessionID,
+        deadline: DateTime<Utc>,
+    ) -> Result<Option<Session>, FlameError> {
+        let _guard = self.lock_session(id).await?;
+        let ssn_ptr = self.get_session_ptr(id)?;
+        let tasks: Vec<TaskPtr> = {
+            let ssn = lock_ptr!(ssn_ptr)?;
+            if ssn.status.state != SessionState::Open || ssn.deadline != Some(deadline) {
+                return Ok(None);
+            }
+            ssn.tasks.values().cloned().collect()
+        };
+        self.push_event(id, EventKind::SessionExpired { deadline })?;
+
+        let failure = TaskFailure {
+            reason: FailureReason::Timeout,
+            message: format!("session expired at {}", deadline.to_rfc3339()),
+        };
+        for task_ptr in tasks {
+            let gid = {
+                let task = lock_ptr!(task_ptr)?;
+                if task.is_completed() {
+                    continue;
+                }
+                task.gid()
+            };
+            self.update_task_failure(gid, Some(&failure)).await?;
+            self.update_task_state(ssn_ptr.clone(), task_ptr, TaskState::Failed)
+                .await?;
+        }
+
+        self.close_locked_session(id).await.map(Some)
+    }
+
+    /// Records a call of the client on the session in memory; it's written to the engine by
+    /// `persist_activities`, so the orphaned sessions are detected after a restart too.
+    pub fn touch_session(&self, id: SessionID, now: DateTime<Utc>) -> Result<(), FlameError> {
+        if self.sessions.get(&id)?.is_none() {
+            return Ok(());
+        }
+
+        let mut activities = lock_ptr!(self.activities)?;
+        let last_activity = activities.entry(id).or_insert(now);
+        *last_activity = (*last_activity).max(now);
+
+        Ok(())
+    }
+
+    /// Persists the last calls of the clients which are at least `ACTIVITY_INTERVAL` after
+    /// the persisted ones; returns the number of the sessions persisted. The other calls are
+    /// kept in memory, so a session is written at most once an interval.
+    #[tracing::instrument(name = "Storage::persist_activities", level = "debug", skip_all)]
+    pub async fn persist_activities(&self) -> Result<usize, FlameError> {
+        let activities = lock_ptr!(self.activities)?.clone();
+
+        let mut n = 0;
+        for (id, last_activity) in activities {
+            let Some(ssn_ptr) = self.sessions.get(&id)? else {
+                lock_ptr!(self.activities)?.remove(&id);
+                continue;
+            };
+            let persisted = {
+                let ssn = lock_ptr!(ssn_ptr)?;
+                ssn.last_activity.unwrap_or(ssn.creation_time)
+            };
+            if (last_activity - persisted).to_std().unwrap_or_default() < ACTIVITY_INTERVAL {
+                continue;
+            }
+
+            self.engine
+                .update_session_activity(id, last_activity)
+                .await?;
+            lock_ptr!(ssn_ptr)?.last_activity = Some(last_activity);
+            // The calls meanwhile are kept for the next interval.
+            let mut activities = lock_ptr!(self.activities)?;
+            if activities.get(&id) == Some(&last_activity) {
+                activities.remove(&id);
+            }
+            n += 1;
+        }
+
+        Ok(n)
+    }
+
+    /// Closes the open sessions without tasks whose clients made no call on them within
+    /// `timeout` before `now`, e.g. the clients crashed right after creating them; returns
+    /// the ids of the orphaned sessions. The sessions with tasks are left to their deadlines.
+    #[tracing::instrument(name = "Storage::orphan_sessions", level = "debug", skip_all)]
+    pub async fn orphan_sessions(
+        &self,
+        now: DateTime<Utc>,
+        timeout: Duration,
+    ) -> Result<Vec<SessionID>, FlameError> {
+        let activities = lock_ptr!(self.activities)?.clone();
+
+        let mut orphaned = vec![];
+        for ssn in self.sessions.values()? {
+            let ssn = lock_ptr!(ssn)?;
+            if ssn.status.state != SessionState::Open || !ssn.tasks.is_empty() {
+                continue;
+            }
+            let last_activity = [activities.get(&ssn.id).copied(), ssn.last_activity]
+                .into_iter()
+                .flatten()
+                .fold(ssn.creation_time, DateTime::max);
+            if (now - last_activity)
+                .to_std()
+                .is_ok_and(|idle| idle >= timeout)
+            {
+                orphaned.push((ssn.id, last_activity));
+            }
+        }
+
+        let mut ids = vec![];
+        for (id, last_activity) in orphaned {
+            // The other sessions are closed anyway; the failed one is retried by the next sweep.
+            match self.orphan_session(id, last_activity).await {
+                Ok(Some(_)) => {
+                    log::info!(
+                        "Session <{}> has no call since <{}>, it's closed as orphaned.",
+                        id,
+                        last_activity
+                    );
+                    ids.push(id);
+                }
+                Ok(None) => {}
+                Err(e) => log::error!("Failed to close orphaned session <{}>: {}", id, e),
+            }
+        }
+
+        Ok(ids)
+    }
+
+    /// Closes the orphaned session unless it was closed or got a task since it was found
+    /// orphaned, in which case none is returned.
+    async fn orphan_session(
+        &self,
+        id: SessionID,
+        last_activity: DateTime<Utc>,
+    ) -> Result<Option<Session>, FlameError> {
+        let _guard = self.lock_session(id).await?;
+        {
+            let ssn_ptr = self.get_session_ptr(id)?;
+            let ssn = lock_ptr!(ssn_ptr)?;
+            if ssn.status.state != SessionState::Open || !ssn.tasks.is_empty() {
+                return Ok(None);
+            }
+        }
+        self.push_event(id, EventKind::SessionOrphaned { last_activity })?;
+        self.close_locked_session(id).await.map(Some)
+    }
+
+    /// Fails the running tasks which were launched at least their timeouts before `now`,
+    /// and releases them from their executors, so the late completions are rejected; returns
+    /// the ids of the failed tasks.
+    #[tracing::instrument(name = "Storage::expire_tasks", level = "debug", skip_all)]
+    pub async fn expire_tasks(&self, now: DateTime<Utc>) -> Result<Vec<TaskGID>, FlameError> {
+        let mut expired = vec![];
+        for ssn_ptr in self.sessions.values()? {
+            let ssn = lock_ptr!(ssn_ptr)?;
+            let Some(running) = ssn.tasks_index.get(&TaskState::Running) else {
+                continue;
+            };
+            for task_ptr in running.values() {
+                let task = lock_ptr!(task_ptr)?;
+                let Some(timeout) = task.timeout else {
+                    continue;
+                };
+                let launched = *lock_ptr!(self.launches)?.entry(task.gid()).or_insert(now);
+                if (now - launched).num_seconds() >= timeout as i64 {
+                    expired.push((task.gid(), task.executor_id.clone(), timeout));
+                }
+            }
+        }
+
+        let mut gids = vec![];
+        for (gid, executor_id, timeout) in expired {
+            // The other tasks are expired anyway; the failed one is retried by the next sweep.
+            match self.expire_task(gid, executor_id, timeout).await {
+                Ok(_) => {
+                    log::warn!(
+                        "Task <{}> is not completed in {}s, it's failed.",
+                        gid,
+                        timeout
+                    );
+                    gids.push(gid);
+                }
+                Err(e) => log::error!("Failed to expire task <{}>: {}", gid, e),
+            }
+        }
+
+        Ok(gids)
+    }
+
+    async fn expire_task(
+        &self,
+        gid: TaskGID,
+        executor_id: Option<ExecutorID>,
+        timeout: u64,
+    ) -> Result<(), FlameError> {
+        let exe_ptr = match executor_id {
+            Some(id) => self.executors.get(&id)?,
+            None => None,
+        };
+        if let Some(exe_ptr) = exe_ptr {
+            // The executor may have moved to another task meanwhile, which is kept.
+            if let Err(e) = states::release_task(&exe_ptr, Some(gid)) {
+                log::debug!("Task <{}> is not released: {}", gid, e);
+            }
+        }
+
+        let failure = TaskFailure {
+            reason: FailureReason::Timeout,
+            message: format!("not completed in {}s", timeout),
+        };
+        self.update_task_failure(gid, Some(&failure)).await?;
+        let ssn_ptr = self.get_session_ptr(gid.ssn_id)?;
+        let task_ptr = self.get_task_ptr(gid)?;
+        self.update_task_state(ssn_ptr, task_ptr, TaskState::Failed)
+            .await
+    }
+
+    pub fn get_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        let ssn_ptr = self.get_session_ptr(id)?;
+        let ssn = lock_ptr!(ssn_ptr)?;
+        Ok(ssn.summary())
+    }
+
+    /// Gets the session in the namespace; the sessions of other namespaces are not found, so
+    /// their ids are not addressable.
+    pub fn get_namespaced_session(
+        &self,
+        namespace: &str,
+        id: SessionID,
+    ) -> Result<Session, FlameError> {
+        match self.get_session(id)? {
+            ssn if ssn.namespace == namespace => Ok(ssn),
+            _ => Err(FlameError::NotFound(id.to_string())),
+        }
+    }
+
+    pub fn get_session_ptr(&self, id: SessionID) -> Result<SessionPtr, FlameError> {
+        self.sessions
+            .get(&id)?
+            .ok_or(FlameError::NotFound(id.to_string()))
+    }
+
+    /// Takes the guard of the session, which serializes the operations changing it, i.e.
+    /// creating and resubmitting its tasks, opening, closing, expiring and deleting it, and
+    /// updating its deadline and annotations; so each of them checks and changes the session
+    /// in the engine and in the memory as a whole. The reads, the scheduling and the
+    /// transitions of the tasks don't take it. The guard is not reentrant, and it's released
+    /// when dropped.
+    async fn lock_session(&self, id: SessionID) -> Result<OwnedMutexGuard<()>, FlameError> {
+        self.get_session_ptr(id)?;
+        let op = self
+            .session_ops
+            .get_or_insert_with(id, || ptr::new_async_ptr(()))?;
+        let guard = op.lock_owned().await;
+        // The session was deleted while waiting for the guard.
+        if self.sessions.get(&id)?.is_none() {
+            self.session_ops.remove(&id)?;
+            return Err(FlameError::NotFound(id.to_string()));
+        }
+
+        Ok(guard)
+    }
+
+    pub fn get_task_ptr(&self, gid: TaskGID) -> Result<TaskPtr, FlameError> {
+        let ssn_ptr = self
+            .sessions
+            .get(&gid.ssn_id)?
+            .ok_or(FlameError::NotFound(gid.ssn_id.to_string()))?;
+
+        let ssn = lock_ptr!(ssn_ptr)?;
+        let task_ptr = ssn
+            .tasks
+            .get(&gid.task_id)
+            .ok_or(FlameError::NotFound(gid.to_string()))?;
+
+        Ok(task_ptr.clone())
+    }
+
+    #[tracing::instrument(
+        name = "Storage::delete_session",
+        level = "debug",
+        skip_all,
+        fields(session_id = id)
+    )]
+    pub async fn delete_session(&self, id: SessionID) -> Result<Session, FlameError> {
+        let _guard = self.lock_session(id).await?;
+        self.flush_task_updates().await?;
+        let ssn = self.engine.delete_session(id).await?;
+
+        self.sessions.remove(&ssn.id)?;
+        // The operations waiting for the guard find the session deleted.
+        self.session_ops.remove(&ssn.id)?;
+
+        lock_ptr!(self.output_chunks)?.retain(|gid, _| gid.ssn_id != ssn.id);
+
+        let mut events = lock_ptr!(self.events)?;
+        events.remove(&ssn.id);
+
+        Ok(ssn)
+    }
+
+    /// Recreates an archived session for inspection, so it's closed and its tasks have to be
+    /// completed; the session gets a new id in the namespace.
+    #[tracing::instrument(name = "Storage::import_session", level = "debug", skip_all)]
+    pub async fn import_session(
+        &self,
+        namespace: String,
+        ssn: Session,
+        mut tasks: Vec<Task>,
+    ) -> Result<Session, FlameError> {
+        if let Some(task) = tasks.iter().find(|t| !t.is_completed()) {
+            return Err(FlameError::invalid_argument(
+                "tasks",
+                format!(
+                    "task <{}> is {}, only the completed tasks can be imported",
+                    task.id, task.state
+                ),
+            ));
+        }
+
+        // The archives of the previous versions have no indexes, so the tasks are indexed in
+        // the order of their ids.
+        let indexes: HashSet<u64> = tasks.iter().map(|t| t.index).collect();
+        if indexes.len() < tasks.len() {
+            tasks.sort_by_key(|t| t.id);
+            for (index, task) in tasks.iter_mut().enumerate() {
+                task.index = index as u64;
+            }
+        }
+
+        // The source of a cloned session is not in this cluster.
+        let ssn = Session {
+            namespace,
+            cloned_from: None,
+            ..ssn
+        };
+        let mut ssn = self.engine.import_session(ssn, tasks).await?;
+        for task in self.engine.find_tasks(ssn.id).await? {
+            ssn.update_task(&self.resident(task)?);
+        }
+
+        let imported = ssn.summary();
+        self.sessions.insert(ssn.id, SessionPtr::new(ssn.into()))?;
+
+        Ok(imported)
+    }
+
+    /// Creates an open session with the settings and the common data of the session, so a
+    /// batch is run again without rebuilding its spec. The inputs of its tasks are copied as
+    /// new pending tasks if `with_tasks`, except the failed tasks which were resubmitted, as
+    /// their inputs are copied by the resubmitted ones; the deadline is not copied, as it's
+    /// usually passed. The clone runs the application recorded on the session unless
+    /// `latest_application`.
+    #[tracing::instrument(
+        name = "Storage::clone_session",
+        level = "debug",
+        skip_all,
+        fields(session_id = id)
+    )]
+    pub async fn clone_session(
+        &self,
+        id: SessionID,
+        with_tasks: bool,
+        latest_application: bool,
+    ) -> Result<Session, FlameError> {
+        let src = self.get_session(id)?;
+        let mut tasks = match with_tasks {
+            true => {
+                self.flush_task_updates().await?;
+                self.engine.find_tasks(id).await?
+            }
+            false => vec![],
+        };
+        let resubmitted: HashSet<TaskID> =
+            tasks.iter().filter_map(|t| t.original_task_id).collect();
+        tasks.retain(|t| !resubmitted.contains(&t.id));
+        tasks.sort_by_key(|t| t.index);
+
+        // The clone is rejected upfront instead of failing half way.
+        let default = *lock_ptr!(self.max_pending_tasks)?;
+        if let Some(limit) = src.max_pending_tasks.or(default) {
+            if tasks.len() > limit as usize {
+                return Err(FlameError::FailedPrecondition(format!(
+                    "session <{}> has {} tasks, which exceed max_pending_tasks {}",
+                    id,
+                    tasks.len(),
+                    limit
+                )));
+            }
+        }
+
+        let ssn = self
+            .create_session(SessionAttributes {
+                namespace: src.namespace,
+                application: src.application,
+                slots: src.slots,
+                labels: src.labels,
+                common_data: src.common_data,
+                config: src.config,
+                max_pending_tasks: src.max_pending_tasks,
+                max_running_tasks: src.max_running_tasks,
+                cache_scope: src.cache_scope,
+                deadline: None,
+                task_timeout: src.task_timeout,
+                max_task_retries: src.max_task_retries,
+                cloned_from: Some(id),
+                priority_class: src.priority_class,
+                latest_application,
+                application_spec: src.application_spec,
+            })
+            .await?;
+
+        for task in tasks {
+            let attrs = TaskAttributes {
+                input: task.input,
+                trace_context: None,
+                labels: task.labels,
+                locality: task.locality,
+                env: task.env,
+                timeout: task.timeout,
+                max_retries: task.max_retries,
+                idempotency_key: task.idempotency_key,
+            };
+            if let Err(e) = self.create_task(ssn.id, attrs).await {
+                // The partial clone is removed, so it's not run as if it were complete.
+                if let Err(e) = self.close_session(ssn.id).await {
+                    log::warn!("Failed to close the partial clone <{}>: {}", ssn.id, e);
+                } else if let Err(e) = self.delete_session(ssn.id).await {
+                    log::warn!("Failed to delete the partial clone <{}>: {}", ssn.id, e);
+                }
+                return Err(e);
+            }
+        }
+
+        self.get_session(ssn.id)
+    }
+
+    /// Records why the sessions can't be scheduled, which is detected by the scheduler in
+    /// every cycle; the sessions not in the reasons are schedulable again, e.g. a suitable
+    /// executor was registered.
+    pub fn set_unschedulable(
+        &self,
+        mut reasons: HashMap<SessionID, String>,
+    ) -> Result<(), FlameError> {
+        for ssn_ptr in self.sessions.values()? {
+            let mut ssn = lock_ptr!(ssn_ptr)?;
+            let reason = reasons.remove(&ssn.id);
+            if ssn.status.unschedulable == reason {
+                continue;
+            }
+
+            match &reason {
+                Some(reason) => log::warn!("Session <{}> is unschedulable: {}.", ssn.id, reason),
+                None => log::info!("Session <{}> is schedulable again.", ssn.id),
+            }
+            ssn.status.unschedulable = reason;
+        }
+
+        Ok(())
+    }
+
+    /// Lists the sessions in the order of their ids, so the order is deterministic.
+    pub fn list_session(&self) -> Result<Vec<Session>, FlameError> {
+        let mut ssn_list = vec![];
+        for ssn in self.sessions.values()? {
+            let ssn = lock_ptr!(ssn)?;
+            ssn_list.push(ssn.summary());
+        }
+        ssn_list.sort_by_key(|ssn| ssn.id);
+
+        Ok(ssn_list)
+    }
+
+    #[tracing::instrument(
+        name = "Storage::create_task",
+        level = "debug",
+        skip_all,
+        fields(session_id = ssn_id)
+    )]
+    pub async fn create_task(
+        &self,
+        ssn_id: SessionID,
+        attrs: TaskAttributes,
+    ) -> Result<Task, FlameError> {
+        let _guard = self.lock_session(ssn_id).await?;
+        // The task of the same key is returned, so the clients can submit their tasks again.
+        if let Some(key) = &attrs.idempotency_key {
+            let ssn_ptr = self.get_session_ptr(ssn_id)?;
+            let task_id = lock_ptr!(ssn_ptr)?.task_by_key(key);
+            if let Some(task_id) = task_id {
+                return self.get_task(TaskGID { ssn_id, task_id }).await;
+            }
+        }
+        self.check_backlog(ssn_id)?;
+        let attrs = self.task_defaults(ssn_id, attrs)?;
+        tracing::debug!(
+            "Create task of session <{}> with input {}",
+            ssn_id,
+            payload::display(attrs.input.as_deref())
+        );
+
+        // The task succeeds with the cached output at once, instead of being dispatched.
+        let cached = match self.cache_key(ssn_id, &attrs.input)? {
+            Some(key) => {
+                let (_, expired) = self.cache_limits()?;
+                self.engine.find_cached_output(&key, expired).await?
+            }
+            None => None,
+        };
+
+        // The task is created once it's persisted; it's dispatched as a new task if the
+        // cached output fails to be persisted, as the outputs are the same.
+        let task = self.engine.create_task(ssn_id, attrs).await?;
+        let task = match cached {
+            Some(cached) => match self.reuse_output(&task, &cached).await {
+                Ok(task) => task,
+                Err(e) => {
+                    log::warn!(
+                        "Failed to reuse the cached output for task <{}>: {}",
+                        task.gid(),
+                        e
+                    );
+                    task
+                }
+            },
+            None => task,
+        };
+
+        self.push_event(ssn_id, task_changed(&task))?;
+
+        let ssn = self.get_session_ptr(ssn_id)?;
+        let mut ssn = lock_ptr!(ssn)?;
+        ssn.update_task(&self.resident(task.clone())?);
+
+        Ok(task)
+    }
+
+    /// Persists the cached output as the output of the new task, which succeeds at once.
+    async fn reuse_output(&self, task: &Task, cached: &CachedOutput) -> Result<Task, FlameError> {
+        self.engine
+            .update_task_output(task.gid(), cached.output.as_ref())
+            .await?;
+        self.engine
+            .update_task_state(task.gid(), TaskState::Succeed)
+            .await
+    }
+
+    /// Resubmits the failed task as a new pending task of its session, which is linked to the
+    /// failed one; a task is resubmitted once, so resubmitting the failed tasks of a session
+    /// again does not duplicate them.
+    #[tracing::instrument(
+        name = "Storage::resubmit_task",
+        level = "debug",
+        skip_all,
+        fields(session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
+    pub async fn resubmit_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        let _guard = self.lock_session(gid.ssn_id).await?;
+        let original = self.get_task(gid).await?;
+        if original.state != TaskState::Failed {
+            return Err(FlameError::FailedPrecondition(format!(
+                "task <{}> is not failed",
+                gid
+            )));
+        }
+        let tasks = self
+            .list_tasks(gid.ssn_id, 0, None, None, None, false)
+            .await?;
+        if let Some(task) = tasks
+            .iter()
+            .find(|t| t.original_task_id == Some(gid.task_id))
+        {
+            return Err(FlameError::FailedPrecondition(format!(
+                "task <{}> was resubmitted as task <{}>",
+                gid, task.id
+            )));
+        }
+        self.check_backlog(gid.ssn_id)?;
+
+        // The engine resubmits the task only if it failed there.
+        self.flush_task_updates().await?;
+        let task = self.engine.resubmit_task(gid).await?;
+        self.push_event(gid.ssn_id, task_changed(&task))?;
+
+        let ssn = self.get_session_ptr(gid.ssn_id)?;
+        let mut ssn = lock_ptr!(ssn)?;
+        ssn.update_task(&task);
+
+        Ok(task)
+    }
+
+    /// Rejects the new tasks of the session if there are too many tasks not completed, so a
+    /// runaway client can not exhaust the memory; the completed tasks are not counted.
+    fn check_backlog(&self, ssn_id: SessionID) -> Result<(), FlameError> {
+        let default = *lock_ptr!(self.max_pending_tasks)?;
+
+        let ssn_ptr = self.get_session_ptr(ssn_id)?;
+        let ssn = lock_ptr!(ssn_ptr)?;
+        let Some(limit) = ssn.max_pending_tasks.or(default) else {
+            return Ok(());
+        };
+
+        let backlog = ssn.backlog();
+        if backlog < limit as usize {
+            return Ok(());
+        }
+
+        Err(FlameError::ResourceExhausted {
+            message: format!(
+                "session <{}> has {} pending tasks, which reaches max_pending_tasks {}",
+                ssn_id, backlog, limit
+            ),
+            retry_after: Some(BACKLOG_RETRY_AFTER),
+        })
+    }
+
+    /// The key of the cached output of a task in the session; none if the session has no
+    /// `cache_scope`.
+    fn cache_key(
+        &self,
+        ssn_id: SessionID,
+        input: &Option<TaskInput>,
+    ) -> Result<Option<CacheKey>, FlameError> {
+        let ssn_ptr = self.get_session_ptr(ssn_id)?;
+        let (scope, namespace, application, common_data) = {
+            let ssn = lock_ptr!(ssn_ptr)?;
+            (
+                ssn.cache_scope,
+                ssn.namespace.clone(),
+                ssn.application.clone(),
+                ssn.common_data.clone(),
+            )
+        };
+        if scope == CacheScope::None {
+            return Ok(None);
+        }
+
+        Ok(Some(CacheKey {
+            digest: task_digest(&common_data, input),
+            namespace,
+            application,
+            ssn_id,
+            shared: scope == CacheScope::Application,
+        }))
+    }
+
+    /// The maximum number of the cached outputs, and the time before which they're expired.
+    fn cache_limits(&self) -> Result<(usize, DateTime<Utc>), FlameError> {
+        let conf = lock_ptr!(self.cache)?;
+        let ttl = chrono::Duration::from_std(conf.ttl()?)
+            .map_err(|e| FlameError::InvalidConfig(format!("cache.ttl: {}", e)))?;
+
+        Ok((
+            conf.max_entries(),
+            Utc::now()
+                .checked_sub_signed(ttl)
+                .unwrap_or(DateTime::<Utc>::MIN_UTC),
+        ))
+    }
+
+    /// Caches the output of the succeeded task, if its session has a `cache_scope`.
+    async fn cache_output(&self, task: &Task) -> Result<(), FlameError> {
+        let Some(key) = self.cache_key(task.ssn_id, &task.input)? else {
+            return Ok(());
+        };
+        let (max_entries, expired) = self.cache_limits()?;
+
+        let output = CachedOutput {
+            key,
+            output: task.output.clone(),
+            creation_time: Utc::now(),
+        };
+        self.engine
+            .put_cached_output(output, max_entries, expired)
+            .await
+    }
+
+    /// Lists the tasks of the session in the order of their indexes, from the start index;
+    /// at most `limit` tasks are listed if any, and only those in the state and having the
+    /// annotation key if any. The payloads of the spilled tasks are loaded from the engine
+    /// only if `payloads` is set.
+    pub async fn list_tasks(
+        &self,
+        ssn_id: SessionID,
+        start_index: u64,
+        limit: Option<usize>,
+        state: Option<TaskState>,
+        annotation: Option<&str>,
+        payloads: bool,
+    ) -> Result<Vec<Task>, FlameError> {
+        let tasks = {
+            let ssn_ptr = self.get_session_ptr(ssn_id)?;
+            let ssn = lock_ptr!(ssn_ptr)?;
+
+            // Only the first `limit` tasks are kept while scanning, so only those are cloned
+            // however large the session is.
+            let mut page = BinaryHeap::new();
+            for task_ptr in ssn.tasks.values() {
+                let task = lock_ptr!(task_ptr)?;
+                if task.index >= start_index
+                    && state.is_none_or(|s| s == task.state)
+                    && annotation.is_none_or(|key| task.annotations.contains_key(key))
+                {
+                    page.push((task.index, task.id));
+                    if limit.is_some_and(|limit| page.len() > limit) {
+                        page.pop();
+                    }
+                }
+            }
+
+            let mut tasks = Vec::with_capacity(page.len());
+            for (_, id) in page.into_sorted_vec() {
+                if let Some(task_ptr) = ssn.tasks.get(&id) {
+                    tasks.push(lock_ptr!(task_ptr)?.clone());
+                }
+            }
+            tasks
+        };
+
+        if !payloads {
+            return Ok(tasks);
+        }
+        let mut rehydrated = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            rehydrated.push(self.rehydrate(task).await?);
+        }
+
+        Ok(rehydrated)
+    }
+
+    /// Gets the task with its payloads, which are loaded from the engine if they're spilled.
+    pub async fn get_task(&self, gid: TaskGID) -> Result<Task, FlameError> {
+        let task = {
+            let task_ptr = self.get_task_ptr(gid)?;
+            let task = lock_ptr!(task_ptr)?;
+            task.clone()
+        };
+
+        self.rehydrate(task).await
+    }
+
+    pub async fn update_task_state(
+        &self,
+        ssn: SessionPtr,
+        task: TaskPtr,
+        state: TaskState,
+    ) -> Result<(), FlameError> {
+        let gid = TaskGID {
+            ssn_id: {
+                let ssn_ptr = lock_ptr!(ssn)?;
+                ssn_ptr.id
+            },
+            task_id: {
+                let task_ptr = lock_ptr!(task)?;
+                task_ptr.id
+            },
+        };
+
+        // The output is set by the executor, and it's persisted once the task succeeds; the
+        // progress is only kept in memory, and it's reset if the task is pending again. The
+        // output chunks are counted over the retries, so their sequences never repeat.
+        let (output, progress, output_chunks) = {
+            let task_ptr = lock_ptr!(task)?;
+            (
+                task_ptr.output.clone(),
+                task_ptr.progress.clone(),
+                task_ptr.output_chunks,
+            )
+        };
+        let persisted = match self.write_behind(&task, state).await? {
+            Some(task) => task,
+            None => {
+                // The changes written behind go first, so the changes are in order.
+                self.flush_task_updates().await?;
+                if state == TaskState::Succeed && output.is_some() {
+                    self.engine.update_task_output(gid, output.as_ref()).await?;
+                }
+                self.engine.update_task_state(gid, state).await?
+            }
+        };
+        let task = Task {
+            output,
+            progress: progress.filter(|_| state != TaskState::Pending),
+            output_chunks,
+            ..persisted
+        };
+        self.push_event(gid.ssn_id, task_changed(&task))?;
+        if task.is_completed() {
+            lock_ptr!(self.retries)?.remove(&gid);
+            // The chunks are spilled before the task is completed in memory, so the clients
+            // following them read the rest from the engine.
+            self.spill_output_chunks(gid).await?;
+        }
+        match state {
+            TaskState::Running => lock_ptr!(self.launches)?.insert(gid, Utc::now()),
+            _ => lock_ptr!(self.launches)?.remove(&gid),
+        };
+
+        {
+            let mut ssn_ptr = lock_ptr!(ssn)?;
+            ssn_ptr.update_task(&self.resident(task.clone())?);
+        }
+
+        // The task is completed anyway, so a failure of the cache is only logged.
+        if state == TaskState::Succeed {
+            if let Err(e) = self.cache_output(&task).await {
+                log::warn!("Failed to cache the output of task <{}>: {}", gid, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records the executor launching the task and its host, so they're known after the task
+    /// is completed, e.g. which host failed it.
+    pub async fn update_task_placement(
+        &self,
+        gid: TaskGID,
+        executor_id: &str,
+        hostname: &str,
+    ) -> Result<(), FlameError> {
+        let placed = self
+            .engine
+            .update_task_placement(gid, executor_id, hostname)
+            .await?;
+
+        let task_ptr = self.get_task_ptr(gid)?;
+        let mut task = lock_ptr!(task_ptr)?;
+        task.executor_id = placed.executor_id;
+        task.hostname = placed.hostname;
+
+        Ok(())
+    }
+
+    /// Records the last failure of the task, which is kept until the task succeeds.
+    pub async fn update_task_failure(
+        &self,
+        gid: TaskGID,
+        failure: Option<&TaskFailure>,
+    ) -> Result<(), FlameError> {
+        // The failure is cleared by the success of the task, which may be written behind.
+        self.flush_task_updates().await?;
+        let failed = self.engine.update_task_failure(gid, failure).await?;
+
+        let task_ptr = self.get_task_ptr(gid)?;
+        let mut task = lock_ptr!(task_ptr)?;
+        task.failure = failed.failure;
+
+        Ok(())
+    }
+
+    /// Merges the annotations set by the executor into its task and the session of the task;
+    /// they're rejected as a whole before any of them is persisted if either of the merged
+    /// ones is over `MAX_ANNOTATIONS_BYTES`. The annotations of the task are kept by its
+    /// retries, so those of the attempts are merged.
+    #[tracing::instrument(
+        name = "Storage::annotate_task",
+        level = "debug",
+        skip_all,
+        fields(executor_id = %id)
+    )]
+    pub async fn annotate_task(
+        &self,
+        id: ExecutorID,
+        gid: Option<TaskGID>,
+        annotations: &Annotations,
+    ) -> Result<(), FlameError> {
+        let (_, gid) = self.assigned_task(&id, gid)?;
+        let _guard = self.lock_session(gid.ssn_id).await?;
+        let task_ptr = self.get_task_ptr(gid)?;
+        let ssn_ptr = self.get_session_ptr(gid.ssn_id)?;
+
+        let task_annotations = apis::merge_annotations(
+            "annotations",
+            &lock_ptr!(task_ptr)?.annotations,
+            &annotations.task,
+        )?;
+        let ssn_annotations = apis::merge_annotations(
+            "session_annotations",
+            &lock_ptr!(ssn_ptr)?.annotations,
+            &annotations.session,
+        )?;
+
+        if !annotations.task.is_empty() {
+            let task = self
+                .engine
+                .update_task_annotations(gid, &task_annotations)
+                .await?;
+            lock_ptr!(task_ptr)?.annotations = task.annotations;
+        }
+        if !annotations.session.is_empty() {
+            let ssn = self
+                .engine
+                .update_session_annotations(gid.ssn_id, &ssn_annotations)
+                .await?;
+            lock_ptr!(ssn_ptr)?.annotations = ssn.annotations;
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "Storage::watch_task",
+        level = "debug",
+        skip_all,
+        fields(session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
+    pub async fn watch_task(&self, gid: TaskGID, include_chunks: bool) -> Result<Task, FlameError> {
+        let task_ptr = self.get_task_ptr(gid)?;
+        WatchTaskFuture::new(self.clone_ptr(), &task_ptr, include_chunks)?.await?;
+
+        self.get_task(gid).await
+    }
+
+    /// Appends the chunk streamed by the executor to the output of its running task, and
+    /// returns the sequence of the chunk. The latest chunks are kept in memory for the clients
+    /// following them, and the older ones over the buffer are spilled to the engine.
+    #[tracing::instrument(
+        name = "Storage::append_output_chunk",
+        level = "debug",
+        skip_all,
+        fields(executor_id = %id, session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
+    pub async fn append_output_chunk(
+        &self,
+        id: ExecutorID,
+        gid: TaskGID,
+        data: TaskOutput,
+    ) -> Result<u64, FlameError> {
+        if data.len() > MAX_OUTPUT_CHUNK_BYTES {
+            return Err(FlameError::invalid_argument(
+                "data",
+                format!(
+                    "{} bytes are over the limit of {} bytes of a chunk",
+                    data.len(),
+                    MAX_OUTPUT_CHUNK_BYTES
+                ),
+            ));
+        }
+        let exe_ptr = self.get_executor_ptr(id.clone())?;
+        {
+            let exe = lock_ptr!(exe_ptr)?;
+            if exe.ssn_id != Some(gid.ssn_id) || exe.task_id != Some(gid.task_id) {
+                return Err(FlameError::PermissionDenied(format!(
+                    "task <{}> is not launched by executor <{}>",
+                    gid, id
+                )));
+            }
+        }
+
+        let task_ptr = self.get_task_ptr(gid)?;
+        let (sequence, overflow) = {
+            let mut task = lock_ptr!(task_ptr)?;
+            if task.state != TaskState::Running {
+                return Err(FlameError::FailedPrecondition(format!(
+                    "task <{}> is not running",
+                    gid
+                )));
+            }
+            task.output_chunks += 1;
+
+            let mut buffers = lock_ptr!(self.output_chunks)?;
+            let buffer = buffers.entry(gid).or_default();
+            buffer.push(OutputChunk {
+                sequence: task.output_chunks,
+                data,
+                creation_time: Utc::now(),
+            });
+            (task.output_chunks, buffer.overflow())
+        };
+
+        if let Some(last) = overflow.last().map(|c| c.sequence) {
+            self.engine.put_output_chunks(gid, overflow).await?;
+            if let Some(buffer) = lock_ptr!(self.output_chunks)?.get_mut(&gid) {
+                buffer.release(last);
+            }
+        }
+
+        Ok(sequence)
+    }
+
+    /// At most `limit` output chunks of the task after the sequence, in order; the chunks
+    /// spilled to the engine go before those in memory.
+    pub async fn fetch_output_chunks(
+        &self,
+        gid: TaskGID,
+        since: u64,
+        limit: usize,
+    ) -> Result<Vec<OutputChunk>, FlameError> {
+        let (first, buffered) = match lock_ptr!(self.output_chunks)?.get(&gid) {
+            Some(buffer) => (buffer.first(), buffer.since(since, limit)),
+            None => (None, vec![]),
+        };
+        if first.is_some_and(|first| first <= since + 1) {
+            return Ok(buffered);
+        }
+
+        // The chunks in memory may be spilled meanwhile, so the engine is read after them.
+        let mut chunks = self.engine.find_output_chunks(gid, since, limit).await?;
+        let last = chunks.last().map(|c| c.sequence).unwrap_or(since);
+        chunks.extend(buffered.into_iter().filter(|c| c.sequence > last));
+        chunks.truncate(limit);
+
+        Ok(chunks)
+    }
+
+    /// Waits for the output chunks of the task after the sequence, and returns them; none is
+    /// returned once the task is completed and all of its chunks are returned.
+    #[tracing::instrument(
+        name = "Storage::watch_output_chunks",
+        level = "debug",
+        skip_all,
+        fields(session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
+    pub async fn watch_output_chunks(
+        &self,
+        gid: TaskGID,
+        since: u64,
+        limit: usize,
+    ) -> Result<Vec<OutputChunk>, FlameError> {
+        WatchChunkFuture::new(self.clone_ptr(), gid, since).await?;
+
+        self.fetch_output_chunks(gid, since, limit).await
+    }
+
+    /// Writes the output chunks of the completed task in memory to the engine, and drops
+    /// them; the task is completed anyway, so a failure of the engine is only logged.
+    async fn spill_output_chunks(&self, gid: TaskGID) -> Result<(), FlameError> {
+        let chunks = match lock_ptr!(self.output_chunks)?.get(&gid) {
+            Some(buffer) => buffer.since(0, usize::MAX),
+            None => return Ok(()),
+        };
+        if let Err(e) = self.engine.put_output_chunks(gid, chunks).await {
+            log::warn!("Failed to spill the output chunks of task <{}>: {}", gid, e);
+        }
+        lock_ptr!(self.output_chunks)?.remove(&gid);
+
+        Ok(())
+    }
+
+    fn push_event(&self, ssn_id: SessionID, kind: EventKind) -> Result<(), FlameError> {
+        let event = {
+            let mut events = lock_ptr!(self.events)?;
+            events.entry(ssn_id).or_default().push(kind)
+        };
+        lock_ptr!(self.pending_events)?.push((ssn_id, event));
+
+        Ok(())
+    }
+
+    /// Applies the state change to a copy of the task and queues it for the engine, if the
+    /// durability is batched; the queue is written at once if it's full, and it's kept for the
+    /// next flush if the engine fails. Returns none if the change has to be written through,
+    /// e.g. the input of the task is spilled, so it's loaded from the engine.
+    async fn write_behind(
+        &self,
+        task: &TaskPtr,
+        state: TaskState,
+    ) -> Result<Option<Task>, FlameError> {
+        if *lock_ptr!(self.durability)? != Durability::Batched {
+            return Ok(None);
+        }
+
+        let mut task = lock_ptr!(task)?.clone();
+        if task.spilled {
+            return Ok(None);
+        }
+
+        // The same changes as the engines, whose timestamps are in seconds.
+        task.completion_time = match state {
+            TaskState::Failed | TaskState::Succeed => {
+                DateTime::<Utc>::from_timestamp(Utc::now().timestamp(), 0)
+            }
+            _ => None,
+        };
+        if state == TaskState::Succeed {
+            task.failure = None;
+        }
+        task.state = state;
+
+        let max_batch = *lock_ptr!(self.max_batch)?;
+        let full = {
+            let mut updates = lock_ptr!(self.pending_updates)?;
+            updates.push(TaskUpdate {
+                gid: task.gid(),
+                state,
+                completion_time: task.completion_time,
+                output: task.output.clone().filter(|_| state == TaskState::Succeed),
+            });
+            updates.len() >= max_batch
+        };
+        if full {
+            if let Err(e) = self.flush_task_updates().await {
+                log::warn!("Failed to write the full batch of task changes: {}", e);
+            }
+        }
+
+        Ok(Some(task))
+    }
+
+    /// Writes the state changes of the tasks queued by `write_behind` to the engine in one
+    /// transaction, so a crash loses the changes not written yet but never a part of a
+    /// batch; returns the number of the changes written. The changes are kept for the next
+    /// flush if the engine fails.
+    #[tracing::instrument(name = "Storage::flush_task_updates", level = "debug", skip_all)]
+    pub async fn flush_task_updates(&self) -> Result<usize, FlameError> {
+        let _flushing = self.flushing_updates.lock().await;
+
+        let updates = std::mem::take(&mut *lock_ptr!(self.pending_updates)?);
+        if updates.is_empty() {
+            return Ok(0);
+        }
+
+        let n = updates.len();
+        if let Err(e) = self.engine.update_tasks(updates.clone()).await {
+            lock_ptr!(self.pending_updates)?.splice(0..0, updates);
+            return Err(e);
+        }
+
+        Ok(n)
+    }
+
+    /// Persists the events pushed since the last flush in a batch; returns the number of the
+    /// events persisted. The events are kept for the next flush if the engine fails.
+    #[tracing::instrument(name = "Storage::flush_events", level = "debug", skip_all)]
+    pub async fn flush_events(&self) -> Result<usize, FlameError> {
+        let _flushing = self.flushing.lock().await;
+
+        let events = std::mem::take(&mut *lock_ptr!(self.pending_events)?);
+        if events.is_empty() {
+            return Ok(0);
+        }
+
+        let n = events.len();
+        if let Err(e) = self
+            .engine
+            .put_events(events.clone(), MAX_HISTORY_EVENTS)
+            .await
+        {
+            lock_ptr!(self.pending_events)?.splice(0..0, events);
+            return Err(e);
+        }
+
+        Ok(n)
+    }
+
+    /// The history of the session in `[since, until)`, from the oldest event to the latest;
+    /// the sequences of the events restart from 1 after a restart of the session manager.
+    #[tracing::instrument(
+        name = "Storage::get_session_events",
+        level = "debug",
+        skip_all,
+        fields(session_id = id)
+    )]
+    pub async fn get_session_events(
+        &self,
+        id: SessionID,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SessionEvent>, FlameError> {
+        self.get_session_ptr(id)?;
+        self.flush_events().await?;
+
+        self.engine.find_events(id, since, until).await
+    }
+
+    /// Waits for the events of the session after `since`; returns no event if the session
+    /// was closed without any event after `since`.
+    #[tracing::instrument(
+        name = "Storage::watch_session",
+        level = "debug",
+        skip_all,
+        fields(session_id = id)
+    )]
+    pub async fn watch_session(
+        &self,
+        id: SessionID,
+        since: u64,
+    ) -> Result<Vec<SessionEvent>, FlameError> {
+        let ssn = self.get_session(id)?;
+        let has_events = {
+            let events = lock_ptr!(self.events)?;
+            events.get(&id).map(|log| log.has_events(since)) == Some(true)
+        };
+        if ssn.status.state == SessionState::Closed && !has_events {
+            return Ok(vec![]);
+        }
+
+        WatchEventFuture::new(self.clone_ptr(), id, since).await?;
+
+        let events = lock_ptr!(self.events)?;
+        Ok(events
+            .get(&id)
+            .map(|log| log.since(since))
+            .unwrap_or_default())
+    }
+
+    pub fn register_executor(&self, e: &Executor) -> Result<(), FlameError> {
+        let exe = ExecutorPtr::new(e.clone().into());
+        self.executors.insert(e.id.clone(), exe)?;
+        lock_ptr!(self.utilization)?.reset(&e.id, Utc::now());
+        // A registered executor starts without the data of any session.
+        lock_ptr!(self.affinity)?.forget(&e.id);
+
+        Ok(())
+    }
+
+    /// Removes the executor if it's idle, e.g. the one registered by the dry run of
+    /// `flame-executor-manager --check`.
+    pub fn unregister_executor(&self, id: ExecutorID) -> Result<(), FlameError> {
+        let removed = self.executors.remove_with(&id, |exe_ptr| {
+            let state = lock_ptr!(exe_ptr)?.state;
+            match state {
+                ExecutorState::Idle => Ok(()),
+                _ => Err(FlameError::FailedPrecondition(format!(
+                    "executor <{}> is {}",
+                    id, state
+                ))),
+            }
+        })?;
+        removed.ok_or(FlameError::NotFound(id.to_string()))?;
+        self.release_reservation(&id)?;
+        self.end_slice(&id)?;
+        lock_ptr!(self.rebinds)?.remove(&id);
+        lock_ptr!(self.utilization)?.forget(&id);
+        lock_ptr!(self.affinity)?.forget(&id);
+
+        Ok(())
+    }
+
+    /// Removes the executor in any state, e.g. it's shutting down with a task; its running
+    /// task is requeued by `requeue_task_from_executor`.
+    pub async fn remove_executor(
+        &self,
+        id: ExecutorID,
+        failure: &TaskFailure,
+    ) -> Result<Option<(TaskGID, TaskState)>, FlameError> {
+        self.remove_executor_with(id, |_| Ok(()), failure).await
+    }
+
+    /// Removes the executors without heartbeats within the lease, i.e. they're lost, and
+    /// requeues their running tasks; returns the ids of the removed executors.
+    #[tracing::instrument(name = "Storage::expire_executors", level = "debug", skip_all)]
+    pub async fn expire_executors(
+        &self,
+        now: DateTime<Utc>,
+        lease: Duration,
+    ) -> Result<Vec<ExecutorID>, FlameError> {
+        let expired =
+            |exe: &Executor| (now - exe.last_heartbeat).to_std().unwrap_or_default() >= lease;
+
+        let mut lost = vec![];
+        for exe_ptr in self.executors.values()? {
+            let exe = lock_ptr!(exe_ptr)?;
+            if expired(&exe) {
+                lost.push(exe.id.clone());
+            }
+        }
+
+        let mut ids = vec![];
+        for id in lost {
+            let failure = TaskFailure {
+                reason: FailureReason::ExecutorLost,
+                message: format!("executor <{}> sent no heartbeat in {:?}", id, lease),
+            };
+            // The heartbeat is checked again, as the executor may have called meanwhile.
+            let check = |exe: &Executor| match expired(exe) {
+                true => Ok(()),
+                false => Err(FlameError::FailedPrecondition(format!(
+                    "executor <{}> is alive",
+                    exe.id
+                ))),
+            };
+            match self.remove_executor_with(id.clone(), check, &failure).await {
+                Ok(requeued) => {
+                    log::warn!("Executor <{}> is lost, it's removed.", id);
+                    if let Some((gid, state)) = requeued {
+                        log::warn!("Task <{}> of the lost executor <{}> is {}.", gid, id, state);
+                    }
+                    ids.push(id);
+                }
+                Err(FlameError::NotFound(_)) | Err(FlameError::FailedPrecondition(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(ids)
+    }
+
+    async fn remove_executor_with(
+        &self,
+        id: ExecutorID,
+        check: impl FnOnce(&Executor) -> Result<(), FlameError>,
+        failure: &TaskFailure,
+    ) -> Result<Option<(TaskGID, TaskState)>, FlameError> {
+        let removed = self
+            .executors
+            .remove_with(&id, |exe_ptr| check(&*lock_ptr!(exe_ptr)?))?;
+        let exe_ptr = removed.ok_or(FlameError::NotFound(id.to_string()))?;
+        self.release_reservation(&id)?;
+        self.end_slice(&id)?;
+        lock_ptr!(self.rebinds)?.remove(&id);
+        lock_ptr!(self.quarantine)?.release(&id);
+
+        let requeued = self
+            .requeue_task_from_executor(&exe_ptr, None, failure)
+            .await;
+        lock_ptr!(self.utilization)?.forget(&id);
+        lock_ptr!(self.affinity)?.forget(&id);
+
+        requeued
+    }
+
+    pub fn get_executor(&self, id: ExecutorID) -> Result<Executor, FlameError> {
+        let exe_ptr = self.get_executor_ptr(id)?;
+        let exe = lock_ptr!(exe_ptr)?;
+        Ok(exe.clone())
+    }
+
+    /// The utilization of the executor over the configured windows.
+    pub fn executor_usage(&self, id: &ExecutorID) -> Result<ExecutorUsage, FlameError> {
+        Ok(lock_ptr!(self.utilization)?.usage(id, Utc::now()))
+    }
+
+    /// Records whether the executor runs a task after a transition, for its utilization.
+    fn track_utilization(&self, exe_ptr: &ExecutorPtr) -> Result<(), FlameError> {
+        let (id, busy) = {
+            let exe = lock_ptr!(exe_ptr)?;
+            (exe.id.clone(), exe.task_id.is_some())
+        };
+        lock_ptr!(self.utilization)?.record(&id, busy, Utc::now());
+
+        Ok(())
+    }
+
+    pub fn list_executor(&self) -> Result<Vec<Executor>, FlameError> {
+        let mut exe_list = vec![];
+        for exe in self.executors.values()? {
+            let exe = lock_ptr!(exe)?;
+            exe_list.push((*exe).clone());
+        }
+
+        Ok(exe_list)
+    }
+
+    /// Marks the executor as draining, so the scheduler will not bind new sessions to it;
+    /// the current session keeps running until it's unbound.
+    pub fn drain_executor(&self, id: ExecutorID) -> Result<Executor, FlameError> {
+        let exe_ptr = self.get_executor_ptr(id)?;
+        let mut exe = lock_ptr!(exe_ptr)?;
+        exe.draining = true;
+
+        Ok(exe.clone())
+    }
+
+    /// Binds new sessions to the drained or quarantined executor again; its failures are
+    /// forgotten.
+    pub fn uncordon_executor(&self, id: ExecutorID) -> Result<Executor, FlameError> {
+        lock_ptr!(self.quarantine)?.release(&id);
+
+        let exe_ptr = self.get_executor_ptr(id)?;
+        let mut exe = lock_ptr!(exe_ptr)?;
+        exe.draining = false;
+        exe.quarantined = false;
+
+        Ok(exe.clone())
+    }
+
+    /// Uncordons the quarantined executors past the cooldown, and returns their ids.
+    pub fn release_quarantined(&self, now: DateTime<Utc>) -> Result<Vec<ExecutorID>, FlameError> {
+        let expired = lock_ptr!(self.quarantine)?.expired(now);
+
+        let mut ids = vec![];
+        for id in expired {
+            match self.uncordon_executor(id.clone()) {
+                Ok(_) => {
+                    log::info!("Executor <{}> is released from the quarantine.", id);
+                    ids.push(id);
+                }
+                // The executor is gone, so it's only forgotten.
+                Err(FlameError::NotFound(_)) => lock_ptr!(self.quarantine)?.release(&id),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(ids)
+    }
+
+    /// Records that the executor is alive; it's called on every request from the executor.
+    pub fn heartbeat(&self, id: ExecutorID) -> Result<(), FlameError> {
+        let exe_ptr = self.get_executor_ptr(id)?;
+        let mut exe = lock_ptr!(exe_ptr)?;
+        exe.last_heartbeat = Utc::now();
+
+        Ok(())
+    }
+
+    /// Replaces the locality keys of the executor, e.g. it cached the data of the tasks it ran.
+    pub fn set_locality_keys(&self, id: ExecutorID, keys: Vec<String>) -> Result<(), FlameError> {
+        let exe_ptr = self.get_executor_ptr(id)?;
+        let mut exe = lock_ptr!(exe_ptr)?;
+        exe.locality_keys = keys;
+
+        Ok(())
+    }
+
+    /// Records the busy seconds measured by the executor itself, and logs the drift from
+    /// the measure of the session manager, e.g. the clock of the host is skewed or the
+    /// transitions are lost.
+    pub fn report_busy_seconds(&self, id: ExecutorID, busy_seconds: f64) -> Result<(), FlameError> {
+        let drift = lock_ptr!(self.utilization)?.report(&id, busy_seconds, Utc::now());
+        if let Some(measured) = drift {
+            tracing::warn!(
+                "Executor <{}> reported {:.1}s busy, but it's {:.1}s by the session manager.",
+                id,
+                busy_seconds,
+                measured
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn get_executor_ptr(&self, id: ExecutorID) -> Result<ExecutorPtr, FlameError> {
+        self.executors
+            .get(&id)?
+            .ok_or(FlameError::NotFound(id.to_string()))
+    }
+
+    #[tracing::instrument(
+        name = "Storage::wait_for_session",
+        level = "debug",
+        skip_all,
+        fields(executor_id = %id)
+    )]
+    pub async fn wait_for_session(&self, id: ExecutorID) -> Result<Session, FlameError> {
+        let exe_ptr = self.get_executor_ptr(id)?;
+        let ssn_id = WaitForSsnFuture::new(&exe_ptr).await?;
+
+        let ssn_ptr = self.get_session_ptr(ssn_id)?;
+        let ssn = lock_ptr!(ssn_ptr)?;
+
+        Ok(ssn.summary())
+    }
+
+    /// Binds the idle executor to the session; returns false if it was bound to the session
+    /// already, e.g. the bind was retried, so it's not counted again.
+    #[tracing::instrument(
+        name = "Storage::bind_session",
+        level = "debug",
+        skip_all,
+        fields(executor_id = %id, session_id = ssn_id)
+    )]
+    pub async fn bind_session(
+        &self,
+        id: ExecutorID,
+        ssn_id: SessionID,
+    ) -> Result<bool, FlameError> {
+        let exe_ptr = self.get_executor_ptr(id.clone())?;
+        let state = states::from(
+            Arc::new(self.clone()),
+            exe_ptr,
+            Operation::BindSession,
+            None,
+        )?;
+
+        let ssn_ptr = self.get_session_ptr(ssn_id)?;
+        let bound = state.bind_session(ssn_ptr).await?;
+        lock_ptr!(self.rebinds)?.remove(&id);
+        if bound {
+            lock_ptr!(self.affinity)?.record(&id, ssn_id, Utc::now());
+        }
+
+        Ok(bound)
+    }
+
+    /// Completes the bind of the executor; it's rejected if the executor was bound again, or
+    /// its session was opened or closed, since the generations echoed by the executor.
+    #[tracing::instrument(
+        name = "Storage::bind_session_completed",
+        level = "debug",
+        skip_all,
+        fields(executor_id = %id)
+    )]
+    pub async fn bind_session_completed(
+        &self,
+        id: ExecutorID,
+        generation: Option<u64>,
+        ssn_generation: Option<u64>,
+    ) -> Result<(), FlameError> {
+        let exe_ptr = self.get_executor_ptr(id.clone())?;
+        if let Some(ssn_generation) = ssn_generation {
+            self.check_session_generation(&exe_ptr, ssn_generation)?;
+        }
+        let state = states::from(
+            Arc::new(self.clone()),
+            exe_ptr,
+            Operation::BindSessionCompleted,
+            generation,
+        )?;
+
+        state.bind_session_completed().await?;
+        lock_ptr!(self.slices)?.insert(id, (Utc::now(), 0));
+
+        Ok(())
+    }
+
+    /// Checks the generation of the session the executor is bound to against the one
+    /// echoed by the executor; it's not checked if the executor is not bound, which is
+    /// rejected by its state anyway.
+    fn check_session_generation(
+        &self,
+        exe_ptr: &ExecutorPtr,
+        generation: u64,
+    ) -> Result<(), FlameError> {
+        let Some(ssn_id) = lock_ptr!(exe_ptr)?.ssn_id else {
+            return Ok(());
+        };
+        let ssn_ptr = self.get_session_ptr(ssn_id)?;
+        let current = lock_ptr!(ssn_ptr)?.status.generation;
+        if current != generation {
+            return Err(FlameError::FailedPrecondition(format!(
+                "the request of session <{}> at generation <{}> is stale, it's at generation <{}>",
+                ssn_id, generation, current
+            )));
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "Storage::launch_task",
+        level = "debug",
+        skip_all,
+        fields(executor_id = %id)
+    )]
+    pub async fn launch_task(&self, id: ExecutorID) -> Result<Option<Task>, FlameError> {
+        let exe_ptr = self.get_executor_ptr(id.clone())?;
+        let state = states::from(
+            self.clone_ptr(),
+            exe_ptr.clone(),
+            Operation::LaunchTask,
+            None,
+        )?;
+        let (ssn_id, task_id) = {
+            let exec = lock_ptr!(exe_ptr)?;
+            (exec.ssn_id, exec.task_id)
+        };
+        let ssn_id = ssn_id.ok_or(FlameError::FailedPrecondition(
+            "no session in bound executor".to_string(),
+        ))?;
+
+        //
+        if let Some(task_id) = task_id {
+            let gid = TaskGID { ssn_id, task_id };
+            log::warn!("Re-launch the task <{}>", gid);
+            let task_ptr = self.get_task_ptr(gid)?;
+
+            let task = lock_ptr!(task_ptr)?;
+            return Ok(Some((*task).clone()));
+        }
+
+        // The quarantined executor gets no more task, so it's unbound from the session.
+        if lock_ptr!(exe_ptr)?.quarantined {
+            return Ok(None);
+        }
+
+        let ssn_ptr = self.get_session_ptr(ssn_id)?;
+        // The tasks over the limit of the session stay pending, and the executor is released
+        // for the others, as the quotas below.
+        if let Some(limit) = Self::running_limit_reached(&ssn_ptr)? {
+            log::debug!(
+                "The running tasks of session <{}> reached its max_running_tasks {}, executor <{}> launches no task.",
+                ssn_id,
+                limit,
+                id
+            );
+            return Ok(None);
+        }
+        // The tasks over the quota stay pending, and the executor is released for the others.
+        if let Some(quota) = self.running_quota_reached(&ssn_ptr)? {
+            log::debug!(
+                "The running tasks of quota <{}> reached its limit, executor <{}> launches no task of session <{}>.",
+                quota,
+                id,
+                ssn_id
+            );
+            return Ok(None);
+        }
+        let task = state.launch_task(ssn_ptr).await?;
+        if task.is_some() {
+            if let Some((_, launched_tasks)) = lock_ptr!(self.slices)?.get_mut(&id) {
+                *launched_tasks += 1;
+            }
+            self.track_utilization(&exe_ptr)?;
+        }
+
+        Ok(task)
+    }
+
+    #[tracing::instrument(
+        name = "Storage::complete_task",
+        level = "debug",
+        skip_all,
+        fields(executor_id = %id)
+    )]
+    /// Completes the task launched by the executor; the task is the one recorded in the
+    /// executor if its identity is not given, which is deprecated. It's rejected if the
+    /// executor was bound again since the generation echoed by it.
+    pub async fn complete_task(
+        &self,
+        id: ExecutorID,
+        gid: Option<TaskGID>,
+        task_output: Option<TaskOutput>,
+        generation: Option<u64>,
+    ) -> Result<(), FlameError> {
+        let (exe_ptr, gid) = self.assigned_task(&id, gid)?;
+        tracing::debug!(
+            "Complete task <{}> with output {}",
+            gid,
+            payload::display(task_output.as_deref())
+        );
+        let task_ptr = self.get_task_ptr(gid)?;
+        let ssn_ptr = self.get_session_ptr(gid.ssn_id)?;
+
+        let state = states::from(
+            self.clone_ptr(),
+            exe_ptr.clone(),
+            Operation::CompleteTask,
+            generation,
+        )?;
+        state.complete_task(ssn_ptr, task_ptr, task_output).await?;
+        self.track_utilization(&exe_ptr)?;
+
+        lock_ptr!(self.quarantine)?.record_success(&id, gid);
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "Storage::fail_task",
+        level = "debug",
+        skip_all,
+        fields(executor_id = %id)
+    )]
+    /// Requeues the task failed by the executor, e.g. its shim crashed; the executor is
+    /// quarantined if it failed too many tasks in a row.
+    pub async fn fail_task(
+        &self,
+        id: ExecutorID,
+        gid: Option<TaskGID>,
+        failure: &TaskFailure,
+        generation: Option<u64>,
+    ) -> Result<(), FlameError> {
+        let (exe_ptr, gid) = self.assigned_task(&id, gid)?;
+        let task_ptr = self.get_task_ptr(gid)?;
+        let ssn_ptr = self.get_session_ptr(gid.ssn_id)?;
+        log::warn!(
+            "Task <{}> failed on executor <{}>: {} ({})",
+            gid,
+            id,
+            failure.message,
+            failure.reason
+        );
+
+        let state = states::from(
+            self.clone_ptr(),
+            exe_ptr.clone(),
+            Operation::FailTask,
+            generation,
+        )?;
+        state.fail_task(ssn_ptr, task_ptr, failure).await?;
+        self.track_utilization(&exe_ptr)?;
+
+        if !lock_ptr!(self.quarantine)?.record_failure(&id, gid, Utc::now()) {
+            return Ok(());
+        }
+        {
+            let mut exe = lock_ptr!(exe_ptr)?;
+            exe.draining = true;
+            exe.quarantined = true;
+        }
+        log::warn!(
+            "Executor <{}> is quarantined after failing task <{}>.",
+            id,
+            gid
+        );
+        self.push_event(
+            gid.ssn_id,
+            EventKind::ExecutorQuarantined {
+                executor_id: id,
+                task_id: gid.task_id,
+            },
+        )?;
+
+        Ok(())
+    }
+
+    /// Requeues the task running on the executor, e.g. its shim crashed or the executor is
+    /// lost; the task is failed instead once it was requeued `max_retries` times. The
+    /// task is released from the executor first, so a late completion from the executor is
+    /// rejected; nothing is done if it's released already, e.g. it's completed. Returns the
+    /// task and its new state.
+    pub async fn requeue_task_from_executor(
+        &self,
+        exe_ptr: &ExecutorPtr,
+        gid: Option<TaskGID>,
+        failure: &TaskFailure,
+    ) -> Result<Option<(TaskGID, TaskState)>, FlameError> {
+        let Some(gid) = states::release_task(exe_ptr, gid)? else {
+            return Ok(None);
+        };
+        self.track_utilization(exe_ptr)?;
+        let task_ptr = self.get_task_ptr(gid)?;
+        // The task was aborted, e.g. its session expired, so it's not requeued.
+        if lock_ptr!(task_ptr)?.is_completed() {
+            return Ok(None);
+        }
+        let ssn_ptr = self.get_session_ptr(gid.ssn_id)?;
+
+        let retries = {
+            let mut retries = lock_ptr!(self.retries)?;
+            let n = retries.entry(gid).or_default();
+            *n += 1;
+            *n
+        };
+        let max_retries = match lock_ptr!(task_ptr)?.max_retries {
+            Some(n) => n,
+            None => *lock_ptr!(self.max_task_retries)?,
+        };
+        let state = match retries > max_retries {
+            true => TaskState::Failed,
+            false => TaskState::Pending,
+        };
+        if state == TaskState::Failed {
+            log::warn!("Task <{}> failed after {} retries.", gid, retries - 1);
+        }
+
+        self.update_task_failure(gid, Some(failure)).await?;
+        self.update_task_state(ssn_ptr, task_ptr, state).await?;
+
+        Ok(Some((gid, state)))
+    }
+
+    /// The task launched by the executor; the stale requests are rejected, e.g. of the task
+    /// requeued to another executor. It's the one recorded in the executor if not given.
+    fn assigned_task(
+        &self,
+        id: &ExecutorID,
+        gid: Option<TaskGID>,
+    ) -> Result<(ExecutorPtr, TaskGID), FlameError> {
+        let exe_ptr = self.get_executor_ptr(id.clone())?;
+        let (ssn_id, task_id) = {
+            let exe = lock_ptr!(exe_ptr)?;
+            (
+                exe.ssn_id.ok_or(FlameError::FailedPrecondition(
+                    "no session in executor".to_string(),
+                ))?,
+                exe.task_id.ok_or(FlameError::FailedPrecondition(
+                    "no task in executor".to_string(),
+                ))?,
+            )
+        };
+
+        let assigned = TaskGID { ssn_id, task_id };
+        let gid = gid.unwrap_or(assigned);
+        if gid != assigned {
+            return Err(FlameError::FailedPrecondition(format!(
+                "task <{}> is not assigned to executor <{}>",
+                gid, id
+            )));
+        }
+
+        Ok((exe_ptr, gid))
+    }
+
+    /// Records the progress of the task launched by the executor; the progress is only kept in
+    /// memory and pushed to the watchers of the session.
+    #[tracing::instrument(
+        name = "Storage::report_task_progress",
+        level = "debug",
+        skip_all,
+        fields(executor_id = %id, session_id = gid.ssn_id, task_id = gid.task_id)
+    )]
+    pub fn report_task_progress(
+        &self,
+        id: ExecutorID,
+        gid: TaskGID,
+        progress: TaskProgress,
+    ) -> Result<(), FlameError> {
+        let exe_ptr = self.get_executor_ptr(id.clone())?;
+        {
+            let exe = lock_ptr!(exe_ptr)?;
+            if exe.ssn_id != Some(gid.ssn_id) || exe.task_id != Some(gid.task_id) {
+                return Err(FlameError::PermissionDenied(format!(
+                    "task <{}> is not launched by executor <{}>",
+                    gid, id
+                )));
+            }
+        }
+
+        let task_ptr = self.get_task_ptr(gid)?;
+        {
+            let mut task = lock_ptr!(task_ptr)?;
+            if task.state != TaskState::Running {
+                return Err(FlameError::FailedPrecondition(format!(
+                    "task <{}> is not running",
+                    gid
+                )));
+            }
+            task.progress = Some(progress.clone());
+        }
+
+        self.push_event(
+            gid.ssn_id,
+            EventKind::TaskProgressed {
+                task_id: gid.task_id,
+                progress,
+            },
+        )
+    }
+
+    #[tracing::instrument(
+        name = "Storage::unbind_executor",
+        level = "debug",
+        skip_all,
+        fields(executor_id = %id)
+    )]
+    pub async fn unbind_executor(
+        &self,
+        id: ExecutorID,
+        generation: Option<u64>,
+    ) -> Result<(), FlameError> {
+        let exe_ptr = self.get_executor_ptr(id.clone())?;
+        let state = states::from(
+            Arc::new(self.clone()),
+            exe_ptr,
+            Operation::UnbindExecutor,
+            generation,
+        )?;
+        self.release_reservation(&id)?;
+        self.end_slice(&id)?;
+        state.unbind_executor().await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "Storage::unbind_executor_completed",
+        level = "debug",
+        skip_all,
+        fields(executor_id = %id)
+    )]
+    pub async fn unbind_executor_completed(
+        &self,
+        id: ExecutorID,
+        generation: Option<u64>,
+    ) -> Result<(), FlameError> {
+        let exe_ptr = self.get_executor_ptr(id)?;
+        let state = states::from(
+            Arc::new(self.clone()),
+            exe_ptr.clone(),
+            Operation::UnbindExecutorCompleted,
+            generation,
+        )?;
+
+        state.unbind_executor_completed().await?;
+        self.track_utilization(&exe_ptr)?;
+
+        Ok(())
+    }
+}
+
+struct WaitForSsnFuture {
+    executor: ExecutorPtr,
+}
+
+impl WaitForSsnFuture {
+    pub fn new(exe_ptr: &ExecutorPtr) -> Self {
+        Self {
+            executor: exe_ptr.clone(),
+        }
+    }
+}
+
+impl Future for WaitForSsnFuture {
+    type Output = Result<SessionID, FlameError>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut exe = lock_ptr!(self.executor)?;
+        // The executor is waiting on the connection, so it's still alive.
+        exe.last_heartbeat = Utc::now();
 
         match exe.ssn_id {
             None => {
@@ -391,29 +2841,97 @@ impl Future for WaitForSsnFuture {
                 ctx.waker().wake_by_ref();
                 Poll::Pending
             }
-            Some(ssn_id) => Poll::Ready(Ok(ssn_id)),
+            Some(ssn_id) => Poll::Ready(Ok(ssn_id)),
+        }
+    }
+}
+
+/// The digest of the common data and the input of a task; the lengths are hashed too, so the
+/// bytes can not be moved from one to the other.
+fn task_digest(common_data: &Option<CommonData>, input: &Option<TaskInput>) -> String {
+    let mut hasher = Sha256::new();
+    for data in [common_data, input] {
+        match data {
+            None => hasher.update([0u8]),
+            Some(data) => {
+                hasher.update([1u8]);
+                hasher.update((data.len() as u64).to_be_bytes());
+                hasher.update(data);
+            }
+        }
+    }
+
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn task_changed(task: &Task) -> EventKind {
+    EventKind::TaskStateChanged {
+        task_id: task.id,
+        state: task.state,
+    }
+}
+
+struct WatchEventFuture {
+    storage: StoragePtr,
+    ssn_id: SessionID,
+    since: u64,
+}
+
+impl WatchEventFuture {
+    pub fn new(storage: StoragePtr, ssn_id: SessionID, since: u64) -> Self {
+        Self {
+            storage,
+            ssn_id,
+            since,
+        }
+    }
+}
+
+impl Future for WatchEventFuture {
+    type Output = Result<(), FlameError>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The session may be deleted during watching.
+        self.storage.get_session_ptr(self.ssn_id)?;
+
+        let events = lock_ptr!(self.storage.events)?;
+        if let Some(log) = events.get(&self.ssn_id) {
+            if log.has_events(self.since) {
+                return Poll::Ready(Ok(()));
+            }
         }
+
+        ctx.waker().wake_by_ref();
+        Poll::Pending
     }
 }
 
 struct WatchTaskFuture {
     storage: StoragePtr,
     current_state: TaskState,
+    /// The output chunks of the task, if a new chunk also changes it.
+    current_chunks: Option<u64>,
     task_gid: TaskGID,
 }
 
 impl WatchTaskFuture {
-    pub fn new(storage: StoragePtr, task_ptr: &TaskPtr) -> Result<Self, FlameError> {
+    pub fn new(
+        storage: StoragePtr,
+        task_ptr: &TaskPtr,
+        include_chunks: bool,
+    ) -> Result<Self, FlameError> {
         let task_ptr = task_ptr.clone();
         let task = lock_ptr!(task_ptr)?;
 
         Ok(Self {
             storage,
             current_state: task.state,
-            task_gid: TaskGID {
-                ssn_id: task.ssn_id,
-                task_id: task.id,
-            },
+            current_chunks: include_chunks.then_some(task.output_chunks),
+            task_gid: task.gid(),
         })
     }
 }
@@ -429,8 +2947,1208 @@ impl Future for WatchTaskFuture {
         if self.current_state != task.state || task.is_completed() {
             return Poll::Ready(Ok(()));
         }
+        if self.current_chunks.is_some_and(|n| task.output_chunks > n) {
+            return Poll::Ready(Ok(()));
+        }
+
+        ctx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Ready once the task has the output chunks after the sequence, or it's completed.
+struct WatchChunkFuture {
+    storage: StoragePtr,
+    task_gid: TaskGID,
+    since: u64,
+}
+
+impl WatchChunkFuture {
+    pub fn new(storage: StoragePtr, task_gid: TaskGID, since: u64) -> Self {
+        Self {
+            storage,
+            task_gid,
+            since,
+        }
+    }
+}
+
+impl Future for WatchChunkFuture {
+    type Output = Result<(), FlameError>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let task_ptr = self.storage.get_task_ptr(self.task_gid)?;
+
+        let task = lock_ptr!(task_ptr)?;
+        if task.output_chunks > self.since || task.is_completed() {
+            return Poll::Ready(Ok(()));
+        }
 
         ctx.waker().wake_by_ref();
         Poll::Pending
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bytes::Bytes;
+
+    use crate::storage::engine::faulty::FaultyEngine;
+
+    /// The bytes of the inputs and outputs of the tasks kept in memory by the storage; those
+    /// in the engine are not counted, e.g. they're on the disk with a persistent engine.
+    fn resident_payloads(storage: &Storage, ssn_id: SessionID) -> Result<usize, FlameError> {
+        let ssn_ptr = storage.get_session_ptr(ssn_id)?;
+        let ssn = lock_ptr!(ssn_ptr)?;
+
+        let mut bytes = 0;
+        for task_ptr in ssn.tasks.values() {
+            let task = lock_ptr!(task_ptr)?;
+            bytes += task.input.as_ref().map(Bytes::len).unwrap_or_default();
+            bytes += task.output.as_ref().map(Bytes::len).unwrap_or_default();
+        }
+
+        Ok(bytes)
+    }
+
+    async fn complete_tasks(
+        storage: &Storage,
+        ssn_id: SessionID,
+        n: usize,
+    ) -> Result<Vec<TaskGID>, FlameError> {
+        let payload = Bytes::from(vec![0u8; 1024]);
+        let ssn_ptr = storage.get_session_ptr(ssn_id)?;
+
+        let mut gids = vec![];
+        for _ in 0..n {
+            let attrs = TaskAttributes {
+                input: Some(payload.clone()),
+                ..Default::default()
+            };
+            let task = storage.create_task(ssn_id, attrs).await?;
+            let task_ptr = storage.get_task_ptr(task.gid())?;
+            lock_ptr!(task_ptr)?.output = Some(payload.clone());
+            storage
+                .update_task_state(ssn_ptr.clone(), task_ptr, TaskState::Succeed)
+                .await?;
+            gids.push(task.gid());
+        }
+
+        Ok(gids)
+    }
+
+    async fn new_storage() -> Result<(StoragePtr, SessionID), FlameError> {
+        let storage = new_ptr("memory://").await?;
+        storage.set_config_applications(&[Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }])?;
+        let ssn = storage
+            .create_session(SessionAttributes {
+                application: "flmexec".to_string(),
+                slots: 1,
+                ..Default::default()
+            })
+            .await?;
+
+        Ok((storage, ssn.id))
+    }
+
+    #[tokio::test]
+    async fn test_spill_completed_tasks() -> Result<(), FlameError> {
+        let (storage, ssn_id) = new_storage().await?;
+
+        // The memory is flat however many tasks are completed.
+        let mut gids = vec![];
+        for _ in 0..10 {
+            gids.extend(complete_tasks(&storage, ssn_id, 10_000).await?);
+            assert_eq!(resident_payloads(&storage, ssn_id)?, 0);
+        }
+
+        // The stubs are still counted, and there's no backlog.
+        let ssn_ptr = storage.get_session_ptr(ssn_id)?;
+        {
+            let ssn = lock_ptr!(ssn_ptr)?;
+            assert_eq!(ssn.tasks.len(), 100_000);
+            assert_eq!(ssn.backlog(), 0);
+        }
+
+        // The payloads are loaded from the engine when they're requested.
+        let task = storage.get_task(gids[42]).await?;
+        assert!(!task.spilled);
+        assert_eq!(task.input.map(|i| i.len()), Some(1024));
+        assert_eq!(task.output.map(|o| o.len()), Some(1024));
+
+        let stubs = storage
+            .list_tasks(ssn_id, 0, Some(10), None, None, false)
+            .await?;
+        assert!(stubs.iter().all(|t| t.spilled && t.output.is_none()));
+        let tasks = storage
+            .list_tasks(ssn_id, 0, Some(10), None, None, true)
+            .await?;
+        assert!(tasks.iter().all(|t| !t.spilled && t.output.is_some()));
+
+        assert_eq!(resident_payloads(&storage, ssn_id)?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_keep_completed_tasks() -> Result<(), FlameError> {
+        let (storage, ssn_id) = new_storage().await?;
+        storage.set_spill_completed_tasks(false)?;
+
+        complete_tasks(&storage, ssn_id, 10).await?;
+        assert_eq!(resident_payloads(&storage, ssn_id)?, 10 * 2 * 1024);
+
+        Ok(())
+    }
+
+    /// Registers an executor which last called at the given time, and launches a task of the
+    /// session on it.
+    async fn launch_on(
+        storage: &Storage,
+        id: &str,
+        ssn_id: SessionID,
+        last_heartbeat: DateTime<Utc>,
+    ) -> Result<TaskGID, FlameError> {
+        storage.register_executor(&Executor {
+            id: id.to_string(),
+            slots: 1,
+            applications: vec![],
+            task_id: None,
+            ssn_id: None,
+            labels: HashMap::new(),
+            locality_keys: vec![],
+            host: Default::default(),
+            creation_time: last_heartbeat,
+            last_heartbeat,
+            state: ExecutorState::Idle,
+            draining: false,
+            quarantined: false,
+            generation: 0,
+        })?;
+        storage.bind_session(id.to_string(), ssn_id).await?;
+        storage
+            .bind_session_completed(id.to_string(), None, None)
+            .await?;
+        let task = storage.launch_task(id.to_string()).await?;
+
+        task.map(|t| t.gid())
+            .ok_or(FlameError::Internal("no task launched".to_string()))
+    }
+
+    /// The sessions with their tasks and pending tasks, as seen by the storage and by the
+    /// engine.
+    type View = Vec<(SessionID, SessionState, Vec<(TaskID, TaskState)>, usize)>;
+
+    async fn views(storage: &Storage) -> Result<(View, View), FlameError> {
+        let mut memory = vec![];
+        for ssn in storage.list_session()? {
+            let ssn_ptr = storage.get_session_ptr(ssn.id)?;
+            let ssn = lock_ptr!(ssn_ptr)?;
+            let mut tasks = vec![];
+            for task_ptr in ssn.tasks.values() {
+                let task = lock_ptr!(task_ptr)?;
+                tasks.push((task.id, task.state));
+            }
+            tasks.sort_by_key(|(id, _)| *id);
+            memory.push((ssn.id, ssn.status.state, tasks, ssn.pending()));
+        }
+
+        let mut engine = vec![];
+        let mut ssn_list = storage.engine.find_session().await?;
+        ssn_list.sort_by_key(|ssn| ssn.id);
+        for ssn in ssn_list {
+            let mut tasks: Vec<_> = storage
+                .engine
+                .find_tasks(ssn.id)
+                .await?
+                .iter()
+                .map(|t| (t.id, t.state))
+                .collect();
+            tasks.sort_by_key(|(id, _)| *id);
+            let pending = tasks
+                .iter()
+                .filter(|(_, s)| *s == TaskState::Pending)
+                .count();
+            engine.push((ssn.id, ssn.status.state, tasks, pending));
+        }
+
+        Ok((memory, engine))
+    }
+
+    fn unavailable<T: std::fmt::Debug>(res: Result<T, FlameError>) {
+        assert!(
+            matches!(
+                res,
+                Err(FlameError::Unavailable {
+                    retryable: true,
+                    ..
+                })
+            ),
+            "{:?}",
+            res
+        );
+    }
+
+    #[tokio::test]
+    async fn test_engine_failures() -> Result<(), FlameError> {
+        // The failures beyond the default attempts of the engine calls.
+        const FAILURES: u32 = 3;
+
+        let faulty = FaultyEngine::new_ptr();
+        let storage = from_engine(engine::decorate(faulty.clone()));
+        storage.set_config_applications(&[Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }])?;
+        let attrs = SessionAttributes {
+            application: "flmexec".to_string(),
+            slots: 1,
+            ..Default::default()
+        };
+        let consistent = || async {
+            let (memory, engine) = views(&storage).await?;
+            assert_eq!(memory, engine);
+            Ok::<_, FlameError>(memory)
+        };
+
+        // A transient failure is retried.
+        faulty.fail("create_session", FAILURES - 1);
+        let ssn_id = storage.create_session(attrs.clone()).await?.id;
+        assert_eq!(consistent().await?.len(), 1);
+
+        faulty.fail("create_session", FAILURES);
+        unavailable(storage.create_session(attrs.clone()).await);
+        assert_eq!(consistent().await?.len(), 1);
+
+        faulty.fail("create_task", FAILURES);
+        unavailable(storage.create_task(ssn_id, TaskAttributes::default()).await);
+        assert!(consistent().await?[0].2.is_empty());
+        let gid = storage
+            .create_task(ssn_id, TaskAttributes::default())
+            .await?
+            .gid();
+
+        // The task stays pending if it fails to be launched.
+        faulty.fail("update_task_state", FAILURES);
+        unavailable(launch_on(&storage, "e1", ssn_id, Utc::now()).await);
+        assert_eq!(consistent().await?[0].3, 1);
+        let launched = storage.launch_task("e1".to_string()).await?;
+        assert_eq!(launched.map(|t| t.gid()), Some(gid));
+        assert_eq!(
+            consistent().await?[0].2,
+            vec![(gid.task_id, TaskState::Running)]
+        );
+
+        // The executor completes the task again if it fails to be completed.
+        faulty.fail("update_task_state", FAILURES);
+        unavailable(
+            storage
+                .complete_task("e1".to_string(), Some(gid), None, None)
+                .await,
+        );
+        assert_eq!(
+            consistent().await?[0].2,
+            vec![(gid.task_id, TaskState::Running)]
+        );
+        storage
+            .complete_task("e1".to_string(), Some(gid), None, None)
+            .await?;
+        assert_eq!(
+            consistent().await?[0].2,
+            vec![(gid.task_id, TaskState::Succeed)]
+        );
+
+        faulty.fail("close_session", FAILURES);
+        unavailable(storage.close_session(ssn_id).await);
+        assert_eq!(consistent().await?[0].1, SessionState::Open);
+        storage.close_session(ssn_id).await?;
+        assert_eq!(consistent().await?[0].1, SessionState::Closed);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_requeue_task_of_lost_executor() -> Result<(), FlameError> {
+        let (storage, ssn_id) = new_storage().await?;
+        let attrs = TaskAttributes {
+            max_retries: Some(1),
+            ..Default::default()
+        };
+        let gid = storage.create_task(ssn_id, attrs).await?.gid();
+
+        let now = Utc::now();
+        let lease = Duration::from_secs(30);
+        assert_eq!(launch_on(&storage, "exec-1", ssn_id, now).await?, gid);
+        assert!(storage.expire_executors(now, lease).await?.is_empty());
+
+        // The task of the lost executor is requeued, and its late completion is rejected.
+        let later = now + chrono::Duration::seconds(30);
+        assert_eq!(
+            storage.expire_executors(later, lease).await?,
+            vec!["exec-1"]
+        );
+        assert!(storage.list_executor()?.is_empty());
+        let task = storage.get_task(gid).await?;
+        assert_eq!(task.state, TaskState::Pending);
+        assert_eq!(
+            task.failure.map(|f| f.reason),
+            Some(FailureReason::ExecutorLost)
+        );
+        let e = storage.complete_task("exec-1".to_string(), Some(gid), None, None);
+        assert!(matches!(e.await, Err(FlameError::NotFound(_))));
+
+        // The task is failed once it's out of retries.
+        assert_eq!(launch_on(&storage, "exec-2", ssn_id, now).await?, gid);
+        let failure = TaskFailure {
+            reason: FailureReason::ExecutorLost,
+            message: "removed".to_string(),
+        };
+        let requeued = storage.remove_executor("exec-2".to_string(), &failure);
+        assert_eq!(requeued.await?, Some((gid, TaskState::Failed)));
+        assert_eq!(storage.get_task(gid).await?.state, TaskState::Failed);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_requeue_completed_task() -> Result<(), FlameError> {
+        let (storage, ssn_id) = new_storage().await?;
+        let gid = storage
+            .create_task(ssn_id, TaskAttributes::default())
+            .await?
+            .gid();
+        launch_on(&storage, "exec-1", ssn_id, Utc::now()).await?;
+        let exe_ptr = storage.get_executor_ptr("exec-1".to_string())?;
+
+        // The task completed before the executor is lost is not requeued.
+        storage
+            .complete_task("exec-1".to_string(), Some(gid), None, None)
+            .await?;
+        let failure = TaskFailure {
+            reason: FailureReason::ExecutorLost,
+            message: "lost".to_string(),
+        };
+        let requeued = storage.requeue_task_from_executor(&exe_ptr, None, &failure);
+        assert_eq!(requeued.await?, None);
+        let task = storage.get_task(gid).await?;
+        assert_eq!(task.state, TaskState::Succeed);
+        assert!(task.failure.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_annotate_task() -> Result<(), FlameError> {
+        let (storage, ssn_id) = new_storage().await?;
+        let gid = storage
+            .create_task(ssn_id, TaskAttributes::default())
+            .await?
+            .gid();
+        let pairs = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        // The annotations of the failed attempt are kept by the retry.
+        launch_on(&storage, "exec-1", ssn_id, Utc::now()).await?;
+        let annotations = Annotations {
+            task: pairs(&[("rows", "100"), ("attempt", "1")]),
+            session: pairs(&[("model", "v1")]),
+        };
+        storage
+            .annotate_task("exec-1".to_string(), Some(gid), &annotations)
+            .await?;
+        let failure = TaskFailure {
+            reason: FailureReason::ShimError,
+            message: "crashed".to_string(),
+        };
+        storage
+            .fail_task("exec-1".to_string(), Some(gid), &failure, None)
+            .await?;
+        assert_eq!(storage.get_task(gid).await?.state, TaskState::Pending);
+
+        // The annotations of the retry are merged, and an empty value removes its key.
+        launch_on(&storage, "exec-2", ssn_id, Utc::now()).await?;
+        let annotations = Annotations {
+            task: pairs(&[("rows", "200"), ("attempt", ""), ("score", "0.9")]),
+            session: pairs(&[("model", "v2")]),
+        };
+        storage
+            .annotate_task("exec-2".to_string(), Some(gid), &annotations)
+            .await?;
+        storage
+            .complete_task("exec-2".to_string(), Some(gid), None, None)
+            .await?;
+        let expected = pairs(&[("rows", "200"), ("score", "0.9")]);
+        let task = storage.get_task(gid).await?;
+        assert_eq!(task.state, TaskState::Succeed);
+        assert_eq!(task.annotations, expected);
+        assert_eq!(storage.engine.get_task(gid).await?.annotations, expected);
+        assert_eq!(
+            storage.get_session(ssn_id)?.annotations,
+            pairs(&[("model", "v2")])
+        );
+        let annotated = storage
+            .list_tasks(ssn_id, 0, None, None, Some("score"), false)
+            .await?;
+        assert_eq!(annotated.len(), 1);
+        let annotated = storage
+            .list_tasks(ssn_id, 0, None, None, Some("attempt"), false)
+            .await?;
+        assert!(annotated.is_empty());
+
+        // The annotations over the size limit are rejected as a whole, and nothing changes.
+        let gid = storage
+            .create_task(ssn_id, TaskAttributes::default())
+            .await?
+            .gid();
+        launch_on(&storage, "exec-3", ssn_id, Utc::now()).await?;
+        let large = "x".repeat(apis::MAX_ANNOTATIONS_BYTES);
+        let annotations = Annotations {
+            task: pairs(&[("rows", "300")]),
+            session: pairs(&[("summary", large.as_str())]),
+        };
+        let res = storage
+            .annotate_task("exec-3".to_string(), Some(gid), &annotations)
+            .await;
+        assert!(
+            matches!(&res, Err(FlameError::InvalidArgument { field, .. }) if field == "session_annotations"),
+            "{:?}",
+            res
+        );
+        assert!(storage.get_task(gid).await?.annotations.is_empty());
+        assert!(storage.engine.get_task(gid).await?.annotations.is_empty());
+        assert_eq!(
+            storage.get_session(ssn_id)?.annotations,
+            pairs(&[("model", "v2")])
+        );
+
+        // Only the executor running the task annotates it.
+        let annotations = Annotations {
+            task: pairs(&[("rows", "300")]),
+            ..Default::default()
+        };
+        let res = storage
+            .annotate_task("exec-1".to_string(), Some(gid), &annotations)
+            .await;
+        assert!(res.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_output_chunks() -> Result<(), FlameError> {
+        let (storage, ssn_id) = new_storage().await?;
+        let mut gids = vec![];
+        for _ in 0..2 {
+            let task = storage
+                .create_task(ssn_id, TaskAttributes::default())
+                .await?;
+            gids.push(task.gid());
+        }
+        let gid = gids[0];
+        assert_eq!(
+            launch_on(&storage, "exec-1", ssn_id, Utc::now()).await?,
+            gid
+        );
+        assert_eq!(
+            launch_on(&storage, "exec-2", ssn_id, Utc::now()).await?,
+            gids[1]
+        );
+
+        // A client follows the chunks from the start until the task is completed.
+        let follower = {
+            let storage = storage.clone();
+            tokio::spawn(async move {
+                let mut sequences = vec![];
+                loop {
+                    let since = sequences.last().copied().unwrap_or_default();
+                    let chunks = storage.watch_output_chunks(gid, since, 3).await?;
+                    if chunks.is_empty() {
+                        return Ok::<_, FlameError>(sequences);
+                    }
+                    sequences.extend(chunks.iter().map(|c| c.sequence));
+                }
+            })
+        };
+
+        // The chunks over the buffer are spilled, and they're still read in order.
+        let chunk = |i: u8| Bytes::from(vec![i; MAX_OUTPUT_CHUNK_BYTES]);
+        for i in 1..=10 {
+            let sequence = storage
+                .append_output_chunk("exec-1".to_string(), gid, chunk(i))
+                .await?;
+            assert_eq!(sequence, i as u64);
+        }
+        let spilled = storage
+            .engine
+            .find_output_chunks(gid, 0, usize::MAX)
+            .await?;
+        assert_eq!(spilled.len(), 6);
+        let chunks = storage.fetch_output_chunks(gid, 4, usize::MAX).await?;
+        assert_eq!(
+            chunks.iter().map(|c| c.sequence).collect::<Vec<_>>(),
+            (5..=10).collect::<Vec<_>>()
+        );
+        assert!(chunks.iter().all(|c| c.data == chunk(c.sequence as u8)));
+
+        // Only the executor running the task streams its chunks, and a chunk is bounded.
+        let res = storage
+            .append_output_chunk("exec-2".to_string(), gid, chunk(11))
+            .await;
+        assert!(
+            matches!(res, Err(FlameError::PermissionDenied(_))),
+            "{:?}",
+            res
+        );
+        let res = storage
+            .append_output_chunk(
+                "exec-1".to_string(),
+                gid,
+                Bytes::from(vec![0; MAX_OUTPUT_CHUNK_BYTES + 1]),
+            )
+            .await;
+        assert!(
+            matches!(res, Err(FlameError::InvalidArgument { .. })),
+            "{:?}",
+            res
+        );
+
+        // The rest of the chunks are spilled once the task is completed.
+        storage
+            .complete_task("exec-1".to_string(), Some(gid), None, None)
+            .await?;
+        assert!(lock_ptr!(storage.output_chunks)?.is_empty());
+        let spilled = storage
+            .engine
+            .find_output_chunks(gid, 0, usize::MAX)
+            .await?;
+        assert_eq!(spilled.len(), 10);
+        assert_eq!(storage.get_task(gid).await?.output_chunks, 10);
+        assert_eq!(follower.await.unwrap()?, (1..=10).collect::<Vec<_>>());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stale_generations() -> Result<(), FlameError> {
+        let (storage, ssn_id) = new_storage().await?;
+        for _ in 0..2 {
+            storage
+                .create_task(ssn_id, TaskAttributes::default())
+                .await?;
+        }
+        let id = || "exec-1".to_string();
+        let stale_error = |res: Result<(), FlameError>| {
+            assert!(
+                matches!(&res, Err(FlameError::FailedPrecondition(msg)) if msg.contains("stale")),
+                "{:?}",
+                res
+            );
+        };
+
+        let gid = launch_on(&storage, "exec-1", ssn_id, Utc::now()).await?;
+        let stale = storage.get_executor(id())?.generation;
+        assert_eq!(stale, 1);
+
+        // The executor is released and bound again, which bumps its generation.
+        storage
+            .complete_task(id(), Some(gid), None, Some(stale))
+            .await?;
+        storage.unbind_executor(id(), Some(stale)).await?;
+        storage.unbind_executor_completed(id(), Some(stale)).await?;
+        assert!(storage.bind_session(id(), ssn_id).await?);
+        let exe = storage.get_executor(id())?;
+        assert_eq!((exe.state, exe.generation), (ExecutorState::Binding, 2));
+
+        // The requests of the previous binding are rejected and change nothing.
+        stale_error(
+            storage
+                .bind_session_completed(id(), Some(stale), None)
+                .await,
+        );
+        assert_eq!(storage.get_executor(id())?.state, ExecutorState::Binding);
+
+        storage.bind_session_completed(id(), Some(2), None).await?;
+        let task = storage.launch_task(id()).await?;
+        let gid = task.map(|t| t.gid()).unwrap();
+        stale_error(
+            storage
+                .complete_task(id(), Some(gid), None, Some(stale))
+                .await,
+        );
+        stale_error(storage.unbind_executor(id(), Some(stale)).await);
+        let exe = storage.get_executor(id())?;
+        assert_eq!(
+            (exe.state, exe.task_id),
+            (ExecutorState::Bound, Some(gid.task_id))
+        );
+        assert_eq!(storage.get_task(gid).await?.state, TaskState::Running);
+
+        // The bind is rejected once its session was closed since then.
+        storage
+            .complete_task(id(), Some(gid), None, Some(2))
+            .await?;
+        storage.unbind_executor(id(), Some(2)).await?;
+        storage.unbind_executor_completed(id(), Some(2)).await?;
+        let ssn_generation = storage.get_session(ssn_id)?.status.generation;
+        storage.bind_session(id(), ssn_id).await?;
+        storage.close_session(ssn_id).await?;
+        assert_eq!(
+            storage.get_session(ssn_id)?.status.generation,
+            ssn_generation + 1
+        );
+        stale_error(
+            storage
+                .bind_session_completed(id(), Some(3), Some(ssn_generation))
+                .await,
+        );
+        assert_eq!(storage.get_executor(id())?.state, ExecutorState::Binding);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_task_settings_precedence() -> Result<(), FlameError> {
+        let (storage, _) = new_storage().await?;
+        storage.set_max_task_retries(Some(5))?;
+        storage.set_config_applications(&[
+            Application {
+                name: "flmexec".to_string(),
+                ..Default::default()
+            },
+            Application {
+                name: "batch".to_string(),
+                default_slots: Some(4),
+                default_task_timeout: Some(600),
+                default_max_retries: Some(2),
+                default_labels: HashMap::from([
+                    ("tier".to_string(), "batch".to_string()),
+                    ("env".to_string(), "prod".to_string()),
+                ]),
+                ..Default::default()
+            },
+        ])?;
+        let session = |application: &str, slots, task_timeout, max_task_retries| {
+            storage.create_session(SessionAttributes {
+                application: application.to_string(),
+                slots,
+                labels: HashMap::from([("env".to_string(), "dev".to_string())]),
+                task_timeout,
+                max_task_retries,
+                ..Default::default()
+            })
+        };
+        let settings = |ssn: &Session| (ssn.slots, ssn.task_timeout, ssn.max_task_retries);
+
+        // The global defaults, if the application has none.
+        let ssn = session("flmexec", 0, None, None).await?;
+        assert_eq!(settings(&ssn), (1, None, Some(5)));
+
+        // The defaults of the application, and the labels of the session win on conflicts.
+        let ssn = session("batch", 0, None, None).await?;
+        assert_eq!(settings(&ssn), (4, Some(600), Some(2)));
+        assert_eq!(ssn.labels["tier"], "batch");
+        assert_eq!(ssn.labels["env"], "dev");
+        assert_eq!(
+            settings(&storage.get_session(ssn.id)?),
+            (4, Some(600), Some(2))
+        );
+
+        // The settings of the session, which are the defaults of its tasks.
+        let ssn = session("batch", 2, Some(60), Some(1)).await?;
+        assert_eq!(settings(&ssn), (2, Some(60), Some(1)));
+        let task = storage
+            .create_task(ssn.id, TaskAttributes::default())
+            .await?;
+        assert_eq!((task.timeout, task.max_retries), (Some(60), Some(1)));
+
+        // The settings of the task.
+        let attrs = TaskAttributes {
+            timeout: Some(30),
+            max_retries: Some(0),
+            ..Default::default()
+        };
+        let task = storage.create_task(ssn.id, attrs).await?;
+        assert_eq!((task.timeout, task.max_retries), (Some(30), Some(0)));
+        let task = storage.get_task(task.gid()).await?;
+        assert_eq!((task.timeout, task.max_retries), (Some(30), Some(0)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_task_with_idempotency_key() -> Result<(), FlameError> {
+        let url = format!(
+            "sqlite:///tmp/flame_test_create_task_with_idempotency_key_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let storage = new_ptr(&url).await?;
+        storage.set_config_applications(&[Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }])?;
+        let ssn_id = storage
+            .create_session(SessionAttributes {
+                application: "flmexec".to_string(),
+                slots: 1,
+                ..Default::default()
+            })
+            .await?
+            .id;
+        let attrs = |input: &'static str, key: Option<&str>| TaskAttributes {
+            input: Some(Bytes::from(input)),
+            idempotency_key: key.map(str::to_string),
+            ..Default::default()
+        };
+
+        // The task of the same key is returned, even if its input is different.
+        let task = storage.create_task(ssn_id, attrs("a", Some("0"))).await?;
+        let again = storage.create_task(ssn_id, attrs("b", Some("0"))).await?;
+        assert_eq!(again.id, task.id);
+        assert_eq!(again.input, Some(Bytes::from("a")));
+        let other = storage.create_task(ssn_id, attrs("a", Some("1"))).await?;
+        assert_ne!(other.id, task.id);
+        // The tasks without keys are never deduplicated.
+        storage.create_task(ssn_id, attrs("a", None)).await?;
+        storage.create_task(ssn_id, attrs("a", None)).await?;
+        assert_eq!(storage.get_session(ssn_id)?.pending(), 4);
+
+        // The keys are persisted, e.g. for the clients submitting again after a restart.
+        storage
+            .update_task_state(
+                storage.get_session_ptr(ssn_id)?,
+                storage.get_task_ptr(task.gid())?,
+                TaskState::Succeed,
+            )
+            .await?;
+        drop(storage);
+        let recovered = new_ptr(&url).await?;
+        recovered.load_data().await?;
+        let again = recovered.create_task(ssn_id, attrs("a", Some("0"))).await?;
+        assert_eq!(again.id, task.id);
+        assert_eq!(again.state, TaskState::Succeed);
+        assert_eq!(recovered.get_session(ssn_id)?.pending(), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expire_running_task() -> Result<(), FlameError> {
+        let (storage, ssn_id) = new_storage().await?;
+        let attrs = TaskAttributes {
+            timeout: Some(60),
+            ..Default::default()
+        };
+        let gid = storage.create_task(ssn_id, attrs).await?.gid();
+        launch_on(&storage, "exec-1", ssn_id, Utc::now()).await?;
+        let now = Utc::now();
+        assert!(storage.expire_tasks(now).await?.is_empty());
+
+        // The task is failed once it runs for its timeout, and its late completion is rejected.
+        let later = now + chrono::Duration::seconds(61);
+        assert_eq!(storage.expire_tasks(later).await?, vec![gid]);
+        let task = storage.get_task(gid).await?;
+        assert_eq!(task.state, TaskState::Failed);
+        assert_eq!(task.failure.map(|f| f.reason), Some(FailureReason::Timeout));
+        let e = storage.complete_task("exec-1".to_string(), Some(gid), None, None);
+        assert!(matches!(e.await, Err(FlameError::FailedPrecondition(_))));
+
+        // The task without a timeout runs as long as it takes.
+        let gid = storage
+            .create_task(ssn_id, TaskAttributes::default())
+            .await?
+            .gid();
+        assert_eq!(launch_on(&storage, "exec-2", ssn_id, now).await?, gid);
+        let much_later = now + chrono::Duration::days(1);
+        assert!(storage.expire_tasks(much_later).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_orphan_sessions() -> Result<(), FlameError> {
+        let (storage, idle) = new_storage().await?;
+        let attrs = SessionAttributes {
+            application: "flmexec".to_string(),
+            slots: 1,
+            ..Default::default()
+        };
+        let active = storage.create_session(attrs.clone()).await?.id;
+        let busy = storage.create_session(attrs).await?.id;
+        storage.create_task(busy, TaskAttributes::default()).await?;
+        let timeout = Duration::from_secs(30 * 60);
+        let created = storage.get_session(idle)?.creation_time;
+        let minutes = |n| created + chrono::Duration::minutes(n);
+
+        // The calls within the interval are kept in memory only.
+        storage.touch_session(active, minutes(0) + chrono::Duration::seconds(30))?;
+        assert_eq!(storage.persist_activities().await?, 0);
+        storage.touch_session(active, minutes(20))?;
+        assert_eq!(storage.persist_activities().await?, 1);
+        assert_eq!(
+            storage.engine.get_session(active).await?.last_activity,
+            Some(minutes(20))
+        );
+        storage.touch_session(active, minutes(25))?;
+        assert!(storage
+            .orphan_sessions(minutes(29), timeout)
+            .await?
+            .is_empty());
+
+        // Only the idle session is orphaned, and its watchers see why it's closed.
+        assert_eq!(
+            storage.orphan_sessions(minutes(31), timeout).await?,
+            vec![idle]
+        );
+        let ssn = storage.get_session(idle)?;
+        assert_eq!(ssn.status.state, SessionState::Closed);
+        let events: Vec<_> = storage
+            .watch_session(idle, 0)
+            .await?
+            .into_iter()
+            .map(|e| e.kind)
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                EventKind::SessionOrphaned {
+                    last_activity: minutes(0)
+                },
+                EventKind::SessionClosed
+            ]
+        );
+        for id in [active, busy] {
+            assert_eq!(storage.get_session(id)?.status.state, SessionState::Open);
+        }
+
+        // The active session is orphaned from its last call, even if it's not persisted; the
+        // session with tasks never is.
+        assert_eq!(
+            storage.orphan_sessions(minutes(55), timeout).await?,
+            vec![active]
+        );
+        assert!(storage
+            .orphan_sessions(minutes(24 * 60), timeout)
+            .await?
+            .is_empty());
+        assert_eq!(storage.get_session(busy)?.status.state, SessionState::Open);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_behind() -> Result<(), FlameError> {
+        const FAILURES: u32 = 3;
+
+        let faulty = FaultyEngine::new_ptr();
+        let storage = from_engine(engine::decorate(faulty.clone()));
+        storage.set_durability(Durability::Batched, 4)?;
+        storage.set_config_applications(&[Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }])?;
+        let ssn_id = storage
+            .create_session(SessionAttributes {
+                application: "flmexec".to_string(),
+                slots: 1,
+                ..Default::default()
+            })
+            .await?
+            .id;
+        let persisted = |gid: TaskGID| {
+            let engine = storage.engine.clone();
+            async move { Ok::<_, FlameError>(engine.get_task(gid).await?.state) }
+        };
+
+        // The changes are applied in memory, and written once the batch is full.
+        let gids = complete_tasks(&storage, ssn_id, 3).await?;
+        assert_eq!(faulty.calls("update_task_state"), 0);
+        assert_eq!(faulty.calls("update_tasks"), 0);
+        let task_ptr = storage.get_task_ptr(gids[0])?;
+        assert_eq!(lock_ptr!(task_ptr)?.state, TaskState::Succeed);
+        assert_eq!(persisted(gids[0]).await?, TaskState::Pending);
+
+        let gids = [gids, complete_tasks(&storage, ssn_id, 1).await?].concat();
+        assert_eq!(faulty.calls("update_tasks"), 1);
+        for gid in &gids {
+            assert_eq!(persisted(*gid).await?, TaskState::Succeed);
+        }
+        // The outputs are written with the changes, so the spilled ones are found.
+        let task = storage.get_task(gids[0]).await?;
+        assert_eq!(task.output.map(|o| o.len()), Some(1024));
+
+        // A failed batch is kept for the next flush.
+        let gid = complete_tasks(&storage, ssn_id, 1).await?[0];
+        faulty.fail("update_tasks", FAILURES);
+        unavailable(storage.flush_task_updates().await);
+        assert_eq!(persisted(gid).await?, TaskState::Pending);
+        assert_eq!(storage.flush_task_updates().await?, 1);
+        assert_eq!(persisted(gid).await?, TaskState::Succeed);
+
+        // The changes are written before the session is closed.
+        let gid = complete_tasks(&storage, ssn_id, 1).await?[0];
+        storage.close_session(ssn_id).await?;
+        assert_eq!(persisted(gid).await?, TaskState::Succeed);
+        assert_eq!(storage.flush_task_updates().await?, 0);
+
+        Ok(())
+    }
+
+    /// A crash loses the changes written behind since the last batch, but never a part of a
+    /// batch, so the recovered tasks are as of the last batch.
+    #[tokio::test]
+    async fn test_write_behind_crash() -> Result<(), FlameError> {
+        const MAX_BATCH: usize = 8;
+
+        let url = format!(
+            "sqlite:///tmp/flame_test_write_behind_crash_{}.db",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let storage = new_ptr(&url).await?;
+        storage.set_durability(Durability::Batched, MAX_BATCH)?;
+        storage.set_config_applications(&[Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }])?;
+        let ssn_id = storage
+            .create_session(SessionAttributes {
+                application: "flmexec".to_string(),
+                slots: 1,
+                ..Default::default()
+            })
+            .await?
+            .id;
+        let gids = complete_tasks(&storage, ssn_id, 2 * MAX_BATCH + 3).await?;
+        // The session manager crashes, so the last changes are never flushed.
+        drop(storage);
+
+        let recovered = new_ptr(&url).await?;
+        recovered.set_config_applications(&[Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }])?;
+        recovered.load_data().await?;
+        for (i, gid) in gids.iter().enumerate() {
+            let task = recovered.get_task(*gid).await?;
+            match i < 2 * MAX_BATCH {
+                true => {
+                    assert_eq!(task.state, TaskState::Succeed, "task <{}>", gid);
+                    assert_eq!(task.output.map(|o| o.len()), Some(1024));
+                    assert!(task.completion_time.is_some());
+                }
+                // The lost changes are bounded by the batch, and the tasks run again.
+                false => {
+                    assert_eq!(task.state, TaskState::Pending, "task <{}>", gid);
+                    assert!(task.output.is_none());
+                    assert!(task.completion_time.is_none());
+                    assert_eq!(task.input.map(|i| i.len()), Some(1024));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Captures the fields of the events, e.g. the messages of the logs.
+    #[derive(Clone, Default)]
+    struct Logs(Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl tracing::field::Visit for Logs {
+        fn record_debug(&mut self, _: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.lock().unwrap().push(format!("{:?}", value));
+        }
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Logs {
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            _: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            event.record(&mut self.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redact_payloads() -> Result<(), FlameError> {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let logs = Logs::default();
+        let subscriber = tracing_subscriber::registry().with(logs.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let (storage, ssn_id) = new_storage().await?;
+        let input = Bytes::from_static(b"ssn=123-45-6789");
+        let output = Bytes::from_static(b"card=4111111111111111");
+        let attrs = TaskAttributes {
+            input: Some(input.clone()),
+            ..Default::default()
+        };
+        let task = storage.create_task(ssn_id, attrs).await?;
+        let gid = launch_on(&storage, "exec-1", ssn_id, Utc::now()).await?;
+        assert_eq!(gid, task.gid());
+        storage
+            .complete_task("exec-1".to_string(), Some(gid), Some(output.clone()), None)
+            .await?;
+        let task = storage.get_task(gid).await?;
+        tracing::debug!("Completed {:?}", task);
+
+        // Only the lengths and the digests of the payloads are logged.
+        let logs = logs.0.lock().unwrap().join("\n");
+        for payload in [&input, &output] {
+            let hex: String = payload.iter().map(|b| format!("{:02x}", b)).collect();
+            assert!(!logs.contains(std::str::from_utf8(payload).unwrap()));
+            assert!(!logs.contains(&hex[..8]));
+            assert!(!logs.contains(&format!("{:?}", payload)));
+            assert!(logs.contains(&payload::display(Some(payload)).to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Completes a pending task of the session, if any.
+    async fn complete_pending(storage: &Storage, ssn_id: SessionID) -> Result<(), FlameError> {
+        let ssn_ptr = storage.get_session_ptr(ssn_id)?;
+        let task_ptr = {
+            let ssn = lock_ptr!(ssn_ptr)?;
+            let pending = ssn.tasks_index.get(&TaskState::Pending);
+            match pending.and_then(|tasks| tasks.values().next()) {
+                Some(task_ptr) => task_ptr.clone(),
+                None => return Ok(()),
+            }
+        };
+
+        storage
+            .update_task_state(ssn_ptr, task_ptr, TaskState::Succeed)
+            .await
+    }
+
+    /// The operations on the sessions interleaved at random by many clients leave the
+    /// sessions in memory as they're in the engine.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn test_concurrent_session_ops() -> Result<(), FlameError> {
+        const SESSIONS: usize = 4;
+        const WORKERS: u64 = 16;
+        const OPS: usize = 200;
+        const MAX_PENDING_TASKS: u32 = 8;
+
+        let (storage, _) = new_storage().await?;
+        let mut ssn_ids = vec![];
+        for _ in 0..SESSIONS {
+            let ssn = storage
+                .create_session(SessionAttributes {
+                    application: "flmexec".to_string(),
+                    slots: 1,
+                    max_pending_tasks: Some(MAX_PENDING_TASKS),
+                    ..Default::default()
+                })
+                .await?;
+            ssn_ids.push(ssn.id);
+        }
+
+        let mut handles = vec![];
+        for seed in 1..=WORKERS {
+            let storage = storage.clone();
+            let ssn_ids = ssn_ids.clone();
+            handles.push(tokio::spawn(async move {
+                // A xorshift generator, so the workers interleave differently but every run
+                // does the same operations.
+                let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+                let mut next = move |n: usize| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    (state % n as u64) as usize
+                };
+                for _ in 0..OPS {
+                    let id = ssn_ids[next(ssn_ids.len())];
+                    // Many operations are rejected, e.g. the closed sessions get no task and
+                    // the deleted ones are not found, which is checked below.
+                    let _ = match next(8) {
+                        0..=2 => storage
+                            .create_task(id, TaskAttributes::default())
+                            .await
+                            .map(|_| ()),
+                        3 | 4 => complete_pending(&storage, id).await,
+                        5 => storage.close_session(id).await.map(|_| ()),
+                        6 => match next(4) {
+                            0 => storage.delete_session(id).await.map(|_| ()),
+                            _ => storage.open_session(id).await.map(|_| ()),
+                        },
+                        _ => {
+                            let deadline = Utc::now() + chrono::Duration::minutes(next(60) as i64);
+                            storage
+                                .update_session_deadline(id, Some(deadline))
+                                .await
+                                .map(|_| ())
+                        }
+                    };
+                    tokio::task::yield_now().await;
+                }
+                Ok::<_, FlameError>(())
+            }));
+        }
+        for handle in handles {
+            handle
+                .await
+                .map_err(|e| FlameError::Internal(e.to_string()))??;
+        }
+
+        let mut persisted: HashMap<SessionID, Session> = storage
+            .engine
+            .find_session()
+            .await?
+            .into_iter()
+            .map(|ssn| (ssn.id, ssn))
+            .collect();
+        for id in ssn_ids {
+            let Some(ssn_ptr) = storage.sessions.get(&id)? else {
+                // The deleted sessions leave nothing behind.
+                assert!(!persisted.contains_key(&id));
+                assert!(storage.engine.find_tasks(id).await?.is_empty());
+                assert!(storage.session_ops.get(&id)?.is_none());
+                continue;
+            };
+            let mut tasks: Vec<_> = storage
+                .engine
+                .find_tasks(id)
+                .await?
+                .into_iter()
+                .map(|t| (t.id, t.state))
+                .collect();
+            tasks.sort_by_key(|(id, _)| *id);
+
+            let ssn = lock_ptr!(ssn_ptr)?;
+            let expected = persisted.remove(&id).unwrap();
+            assert_eq!(ssn.status.state, expected.status.state);
+            assert_eq!(ssn.deadline, expected.deadline);
+            assert!(ssn.backlog() <= MAX_PENDING_TASKS as usize);
+
+            let mut resident = vec![];
+            for task_ptr in ssn.tasks.values() {
+                let task = lock_ptr!(task_ptr)?;
+                resident.push((task.id, task.state));
+            }
+            resident.sort_by_key(|(id, _)| *id);
+            assert_eq!(resident, tasks);
+
+            // The counters are of the tasks, and the closed sessions have none open.
+            let count = |state| tasks.iter().filter(|t| t.1 == state).count() as i32;
+            assert_eq!(ssn.status.counts.pending, count(TaskState::Pending));
+            assert_eq!(ssn.status.counts.succeed, count(TaskState::Succeed));
+            if ssn.status.state == SessionState::Closed {
+                assert_eq!(ssn.backlog(), 0);
+            }
+        }
+
+        Ok(())
+    }
+}
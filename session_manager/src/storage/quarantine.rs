@@ -0,0 +1,178 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use common::apis::{ExecutorID, TaskGID};
+use common::ctx::FlameQuarantineConf;
+use common::FlameError;
+
+/// The failures of the tasks on the executors, which are kept in memory only; an executor
+/// failing too many tasks in a row within the window is quarantined, e.g. its GPU driver is
+/// broken. The failures of a task which also failed on another executor are not counted, as
+/// it's the task that fails.
+#[derive(Debug)]
+pub struct Quarantine {
+    failures: usize,
+    window: Duration,
+    cooldown: Option<Duration>,
+    /// The failures of each executor since its last success, the oldest first.
+    recent: HashMap<ExecutorID, VecDeque<(DateTime<Utc>, TaskGID)>>,
+    /// The executors which failed each task.
+    failed_by: HashMap<TaskGID, HashSet<ExecutorID>>,
+    /// The quarantined executors, and when they were quarantined.
+    quarantined: HashMap<ExecutorID, DateTime<Utc>>,
+}
+
+impl Default for Quarantine {
+    fn default() -> Self {
+        Quarantine::new(&FlameQuarantineConf::default()).expect("the default quarantine")
+    }
+}
+
+impl Quarantine {
+    pub fn new(conf: &FlameQuarantineConf) -> Result<Self, FlameError> {
+        Ok(Quarantine {
+            failures: conf.failures() as usize,
+            window: conf.window()?,
+            cooldown: conf.cooldown()?,
+            recent: HashMap::new(),
+            failed_by: HashMap::new(),
+            quarantined: HashMap::new(),
+        })
+    }
+
+    /// Records the failure of the task on the executor, and returns whether the executor
+    /// has to be quarantined for it.
+    pub fn record_failure(&mut self, id: &ExecutorID, gid: TaskGID, now: DateTime<Utc>) -> bool {
+        let executors = self.failed_by.entry(gid).or_default();
+        executors.insert(id.clone());
+        if executors.len() > 1 {
+            for recent in self.recent.values_mut() {
+                recent.retain(|(_, g)| *g != gid);
+            }
+            return false;
+        }
+
+        let window = self.window;
+        let recent = self.recent.entry(id.clone()).or_default();
+        recent.retain(|(t, _)| (now - *t).to_std().unwrap_or_default() < window);
+        recent.push_back((now, gid));
+
+        if recent.len() < self.failures || self.quarantined.contains_key(id) {
+            return false;
+        }
+        self.quarantined.insert(id.clone(), now);
+
+        true
+    }
+
+    /// The executor completed the task, so its failures are not in a row any more.
+    pub fn record_success(&mut self, id: &ExecutorID, gid: TaskGID) {
+        self.recent.remove(id);
+        self.failed_by.remove(&gid);
+    }
+
+    /// Forgets the failures of the executor, e.g. it was uncordoned.
+    pub fn release(&mut self, id: &ExecutorID) {
+        self.recent.remove(id);
+        self.quarantined.remove(id);
+    }
+
+    /// The quarantined executors past the cooldown; none if there's no cooldown.
+    pub fn expired(&self, now: DateTime<Utc>) -> Vec<ExecutorID> {
+        let Some(cooldown) = self.cooldown else {
+            return vec![];
+        };
+
+        self.quarantined
+            .iter()
+            .filter(|(_, t)| (now - **t).to_std().unwrap_or_default() >= cooldown)
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quarantine(cooldown: Option<&str>) -> Quarantine {
+        Quarantine::new(&FlameQuarantineConf {
+            failures: Some(3),
+            window: Some("1m".to_string()),
+            cooldown: cooldown.map(str::to_string),
+        })
+        .unwrap()
+    }
+
+    fn gid(task_id: i64) -> TaskGID {
+        TaskGID { ssn_id: 1, task_id }
+    }
+
+    #[test]
+    fn test_failures_in_a_row() {
+        let mut q = quarantine(None);
+        let (a, now) = ("a".to_string(), Utc::now());
+
+        assert!(!q.record_failure(&a, gid(1), now));
+        assert!(!q.record_failure(&a, gid(2), now));
+        // A success breaks the row.
+        q.record_success(&a, gid(3));
+        assert!(!q.record_failure(&a, gid(4), now));
+        assert!(!q.record_failure(&a, gid(5), now));
+        assert!(q.record_failure(&a, gid(6), now));
+        // It's quarantined once.
+        assert!(!q.record_failure(&a, gid(7), now));
+
+        // It's quarantined until it's released, as there's no cooldown.
+        assert!(q.expired(now + chrono::Duration::days(1)).is_empty());
+        q.release(&a);
+        assert!(!q.record_failure(&a, gid(8), now));
+    }
+
+    #[test]
+    fn test_failures_out_of_window() {
+        let mut q = quarantine(Some("10m"));
+        let (a, now) = ("a".to_string(), Utc::now());
+
+        assert!(!q.record_failure(&a, gid(1), now));
+        assert!(!q.record_failure(&a, gid(2), now));
+        let later = now + chrono::Duration::minutes(2);
+        assert!(!q.record_failure(&a, gid(3), later));
+        assert!(!q.record_failure(&a, gid(4), later));
+        assert!(q.record_failure(&a, gid(5), later));
+
+        let cooled = later + chrono::Duration::minutes(10);
+        assert!(q.expired(cooled - chrono::Duration::seconds(1)).is_empty());
+        assert_eq!(q.expired(cooled), vec![a]);
+    }
+
+    #[test]
+    fn test_task_specific_failures() {
+        let mut q = quarantine(None);
+        let (a, b, now) = ("a".to_string(), "b".to_string(), Utc::now());
+
+        assert!(!q.record_failure(&a, gid(1), now));
+        assert!(!q.record_failure(&a, gid(1), now));
+        // The task fails on another executor too, so none of its failures are counted.
+        assert!(!q.record_failure(&b, gid(1), now));
+        assert!(!q.record_failure(&a, gid(2), now));
+        assert!(!q.record_failure(&a, gid(3), now));
+        assert!(!q.record_failure(&a, gid(1), now));
+        assert!(q.record_failure(&a, gid(4), now));
+    }
+}
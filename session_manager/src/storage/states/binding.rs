@@ -11,9 +11,11 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use crate::storage::states::States;
+use crate::storage::states::{bind, illegal, Operation, States};
 use crate::storage::StoragePtr;
-use common::apis::{ExecutorPtr, ExecutorState, SessionPtr, Task, TaskOutput, TaskPtr};
+use common::apis::{
+    ExecutorPtr, ExecutorState, SessionPtr, Task, TaskFailure, TaskOutput, TaskPtr,
+};
 use common::{lock_ptr, trace::TraceFn, trace_fn, FlameError};
 
 pub struct BindingState {
@@ -23,19 +25,10 @@ pub struct BindingState {
 
 #[async_trait::async_trait]
 impl States for BindingState {
-    async fn bind_session(&self, ssn_ptr: SessionPtr) -> Result<(), FlameError> {
+    async fn bind_session(&self, ssn_ptr: SessionPtr) -> Result<bool, FlameError> {
         trace_fn!("BindingState::bind_session");
 
-        let ssn_id = {
-            let ssn = lock_ptr!(ssn_ptr)?;
-            ssn.id
-        };
-
-        let mut e = lock_ptr!(self.executor)?;
-        e.ssn_id = Some(ssn_id);
-        e.state = ExecutorState::Binding;
-
-        Ok(())
+        bind(&self.executor, &ssn_ptr)
     }
 
     async fn bind_session_completed(&self) -> Result<(), FlameError> {
@@ -48,15 +41,15 @@ impl States for BindingState {
     }
 
     async fn unbind_executor(&self) -> Result<(), FlameError> {
-        todo!()
+        Err(illegal(&self.executor, Operation::UnbindExecutor))
     }
 
     async fn unbind_executor_completed(&self) -> Result<(), FlameError> {
-        todo!()
+        Err(illegal(&self.executor, Operation::UnbindExecutorCompleted))
     }
 
     async fn launch_task(&self, _ssn: SessionPtr) -> Result<Option<Task>, FlameError> {
-        todo!()
+        Err(illegal(&self.executor, Operation::LaunchTask))
     }
 
     async fn complete_task(
@@ -65,6 +58,15 @@ impl States for BindingState {
         _task: TaskPtr,
         _: Option<TaskOutput>,
     ) -> Result<(), FlameError> {
-        todo!()
+        Err(illegal(&self.executor, Operation::CompleteTask))
+    }
+
+    async fn fail_task(
+        &self,
+        _ssn: SessionPtr,
+        _task: TaskPtr,
+        _: &TaskFailure,
+    ) -> Result<(), FlameError> {
+        Err(illegal(&self.executor, Operation::FailTask))
     }
 }
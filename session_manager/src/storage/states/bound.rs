@@ -11,11 +11,15 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use common::apis::{ExecutorPtr, ExecutorState, SessionPtr, Task, TaskOutput, TaskPtr, TaskState};
+use chrono::Utc;
+
+use common::apis::{
+    ExecutorPtr, ExecutorState, SessionPtr, Task, TaskFailure, TaskOutput, TaskPtr, TaskState,
+};
 use common::{lock_ptr, trace::TraceFn, trace_fn, FlameError};
 
-use crate::storage::states::States;
-use crate::storage::StoragePtr;
+use crate::storage::states::{bind, illegal, release_task, Operation, States};
+use crate::storage::{locality, StoragePtr};
 
 pub struct BoundState {
     pub storage: StoragePtr,
@@ -24,12 +28,15 @@ pub struct BoundState {
 
 #[async_trait::async_trait]
 impl States for BoundState {
-    async fn bind_session(&self, _ssn_ptr: SessionPtr) -> Result<(), FlameError> {
-        todo!()
+    async fn bind_session(&self, ssn_ptr: SessionPtr) -> Result<bool, FlameError> {
+        trace_fn!("BoundState::bind_session");
+
+        bind(&self.executor, &ssn_ptr)
     }
 
     async fn bind_session_completed(&self) -> Result<(), FlameError> {
-        todo!()
+        // The executor was bound already, e.g. the RPC was retried.
+        Ok(())
     }
 
     async fn unbind_executor(&self) -> Result<(), FlameError> {
@@ -42,22 +49,40 @@ impl States for BoundState {
     }
 
     async fn unbind_executor_completed(&self) -> Result<(), FlameError> {
-        todo!()
+        Err(illegal(&self.executor, Operation::UnbindExecutorCompleted))
     }
 
     async fn launch_task(&self, ssn_ptr: SessionPtr) -> Result<Option<Task>, FlameError> {
         trace_fn!("BoundState::launch_task");
+        let (exec_id, keys) = {
+            let e = lock_ptr!(self.executor)?;
+            (e.id.clone(), e.locality_keys.clone())
+        };
+        let ssn_id = lock_ptr!(ssn_ptr)?.id;
+        let (reserved, others) = self.storage.take_reservation(&exec_id, ssn_id)?;
+        // The tasks with a locality are left to the executors having their keys for a while.
+        let wait = self.storage.locality_wait()?;
+        let now = Utc::now();
         let task_ptr = {
             let mut ssn = lock_ptr!(ssn_ptr)?;
-            ssn.pop_pending_task()
+            ssn.pop_pending_task(reserved, &others, |task| {
+                locality::admits(task, &keys, wait, now)
+            })
         };
 
         let task_ptr = {
             match task_ptr {
                 Some(task_ptr) => {
-                    self.storage
+                    let launched = self
+                        .storage
                         .update_task_state(ssn_ptr.clone(), task_ptr.clone(), TaskState::Running)
-                        .await?;
+                        .await;
+                    if let Err(e) = launched {
+                        // The task is still pending in the engine, so it's queued again.
+                        let task = lock_ptr!(task_ptr)?.clone();
+                        lock_ptr!(ssn_ptr)?.update_task(&task);
+                        return Err(e);
+                    }
                     Some(task_ptr)
                 }
                 None => None,
@@ -70,23 +95,27 @@ impl States for BoundState {
         }
 
         // let task_ptr = task_ptr.unwrap();
-        let (ssn_id, task_id) = {
+        let gid = {
             let task_ptr = task_ptr.clone().unwrap();
             let task = lock_ptr!(task_ptr)?;
-            (task.ssn_id, task.id)
+            task.gid()
         };
 
-        log::debug!("Launching task <{}/{}>", ssn_id.clone(), task_id.clone());
+        log::debug!("Launching task <{}>", gid);
 
-        {
+        let (executor_id, hostname) = {
             let mut e = lock_ptr!(self.executor)?;
-            e.task_id = Some(task_id);
-            e.ssn_id = Some(ssn_id);
+            e.task_id = Some(gid.task_id);
+            e.ssn_id = Some(gid.ssn_id);
+            (e.id.clone(), e.host.hostname.clone())
         };
+        self.storage
+            .update_task_placement(gid, &executor_id, &hostname)
+            .await?;
 
-        let task_ptr = task_ptr.unwrap();
-        let task = lock_ptr!(task_ptr)?;
-        Ok(Some((*task).clone()))
+        let task = self.storage.get_task(gid).await?;
+        locality::record(&task, &keys);
+        Ok(Some(task))
     }
 
     async fn complete_task(
@@ -97,18 +126,46 @@ impl States for BoundState {
     ) -> Result<(), FlameError> {
         trace_fn!("BoundState::complete_task");
 
-        {
-            let mut e = lock_ptr!(self.executor)?;
-            e.task_id = None;
-        };
+        let gid = lock_ptr!(task_ptr)?.gid();
+        release_task(&self.executor, Some(gid))?;
 
         {
             let mut task = lock_ptr!(task_ptr)?;
+            // The task was aborted, e.g. its session expired, so the output is dropped.
+            if task.is_completed() {
+                return Ok(());
+            }
             task.output = task_output;
         }
 
-        self.storage
+        let completed = self
+            .storage
             .update_task_state(ssn_ptr, task_ptr, TaskState::Succeed)
+            .await;
+        if let Err(e) = completed {
+            // The task is still running in the engine, so it's assigned to the executor again
+            // for the executor to retry the completion.
+            let mut exe = lock_ptr!(self.executor)?;
+            if exe.task_id.is_none() && exe.ssn_id == Some(gid.ssn_id) {
+                exe.task_id = Some(gid.task_id);
+            }
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    async fn fail_task(
+        &self,
+        _ssn_ptr: SessionPtr,
+        task_ptr: TaskPtr,
+        failure: &TaskFailure,
+    ) -> Result<(), FlameError> {
+        trace_fn!("BoundState::fail_task");
+
+        let gid = lock_ptr!(task_ptr)?.gid();
+        self.storage
+            .requeue_task_from_executor(&self.executor, Some(gid), failure)
             .await?;
 
         Ok(())
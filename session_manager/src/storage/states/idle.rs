@@ -11,11 +11,11 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use crate::storage::states::States;
+use crate::storage::states::{bind, illegal, Operation, States};
 use crate::storage::StoragePtr;
 
-use common::apis::{ExecutorPtr, ExecutorState, SessionPtr, Task, TaskOutput, TaskPtr};
-use common::{lock_ptr, trace::TraceFn, trace_fn, FlameError};
+use common::apis::{ExecutorPtr, SessionPtr, Task, TaskFailure, TaskOutput, TaskPtr};
+use common::{trace::TraceFn, trace_fn, FlameError};
 
 pub struct IdleState {
     pub storage: StoragePtr,
@@ -24,35 +24,27 @@ pub struct IdleState {
 
 #[async_trait::async_trait]
 impl States for IdleState {
-    async fn bind_session(&self, ssn_ptr: SessionPtr) -> Result<(), FlameError> {
+    async fn bind_session(&self, ssn_ptr: SessionPtr) -> Result<bool, FlameError> {
         trace_fn!("IdleState::bind_session");
 
-        let ssn_id = {
-            let ssn = lock_ptr!(ssn_ptr)?;
-            ssn.id
-        };
-
-        let mut e = lock_ptr!(self.executor)?;
-        e.ssn_id = Some(ssn_id);
-        e.state = ExecutorState::Binding;
-
-        Ok(())
+        bind(&self.executor, &ssn_ptr)
     }
 
     async fn bind_session_completed(&self) -> Result<(), FlameError> {
-        todo!()
+        Err(illegal(&self.executor, Operation::BindSessionCompleted))
     }
 
     async fn unbind_executor(&self) -> Result<(), FlameError> {
-        todo!()
+        Err(illegal(&self.executor, Operation::UnbindExecutor))
     }
 
     async fn unbind_executor_completed(&self) -> Result<(), FlameError> {
-        todo!()
+        // The executor was unbound already, e.g. the RPC was retried.
+        Ok(())
     }
 
     async fn launch_task(&self, _ssn: SessionPtr) -> Result<Option<Task>, FlameError> {
-        todo!()
+        Err(illegal(&self.executor, Operation::LaunchTask))
     }
 
     async fn complete_task(
@@ -61,6 +53,15 @@ impl States for IdleState {
         _task: TaskPtr,
         _: Option<TaskOutput>,
     ) -> Result<(), FlameError> {
-        todo!()
+        Err(illegal(&self.executor, Operation::CompleteTask))
+    }
+
+    async fn fail_task(
+        &self,
+        _ssn: SessionPtr,
+        _task: TaskPtr,
+        _: &TaskFailure,
+    ) -> Result<(), FlameError> {
+        Err(illegal(&self.executor, Operation::FailTask))
     }
 }
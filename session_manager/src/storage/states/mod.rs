@@ -18,7 +18,10 @@ use crate::storage::states::{
 };
 use crate::storage::StoragePtr;
 
-use common::apis::{ExecutorPtr, ExecutorState, SessionPtr, Task, TaskOutput, TaskPtr};
+use common::apis::{
+    Executor, ExecutorPtr, ExecutorState, SessionID, SessionPtr, Task, TaskFailure, TaskGID,
+    TaskOutput, TaskPtr,
+};
 use common::{lock_ptr, FlameError};
 
 mod binding;
@@ -26,9 +29,142 @@ mod bound;
 mod idle;
 mod unbinding;
 
-pub fn from(storage: StoragePtr, exe_ptr: ExecutorPtr) -> Result<Arc<dyn States>, FlameError> {
+/// The operations on an executor, which are dispatched to the state of the executor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum_macros::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum Operation {
+    BindSession,
+    BindSessionCompleted,
+    UnbindExecutor,
+    UnbindExecutorCompleted,
+    LaunchTask,
+    CompleteTask,
+    FailTask,
+}
+
+/// The transition table of the executors: the state of the executor after the operation, or
+/// none if the operation is illegal in the state. A repeated operation keeps the state, e.g.
+/// a duplicated RPC, so it's a no-op instead of an error.
+pub fn transition(state: ExecutorState, op: Operation) -> Option<ExecutorState> {
+    use ExecutorState::*;
+    use Operation::*;
+
+    match (state, op) {
+        (Idle, BindSession) | (Binding, BindSession) => Some(Binding),
+        (Bound, BindSession) => Some(Bound),
+        (Binding, BindSessionCompleted) | (Bound, BindSessionCompleted) => Some(Bound),
+        (Bound, UnbindExecutor) | (Unbinding, UnbindExecutor) => Some(Unbinding),
+        (Unbinding, UnbindExecutorCompleted) | (Idle, UnbindExecutorCompleted) => Some(Idle),
+        (Bound, LaunchTask) | (Bound, CompleteTask) | (Bound, FailTask) => Some(Bound),
+        (Unbinding, LaunchTask) | (Unbinding, CompleteTask) | (Unbinding, FailTask) => {
+            Some(Unbinding)
+        }
+        _ => None,
+    }
+}
+
+/// Checks the operation against the transition table in the current state of the executor.
+fn check(exe: &Executor, op: Operation) -> Result<ExecutorState, FlameError> {
+    transition(exe.state, op).ok_or(FlameError::FailedPrecondition(format!(
+        "executor <{}> can not {} in state <{}>",
+        exe.id, op, exe.state
+    )))
+}
+
+/// The error of the operation which is illegal in the state of the executor; the operation
+/// is checked by `from`, so it's only returned if the state was changed concurrently.
+fn illegal(exe_ptr: &ExecutorPtr, op: Operation) -> FlameError {
+    match lock_ptr!(exe_ptr) {
+        Ok(exe) => check(&exe, op)
+            .err()
+            .unwrap_or(FlameError::FailedPrecondition(format!(
+                "executor <{}> changed its state to <{}> during {}",
+                exe.id, exe.state, op
+            ))),
+        Err(e) => e,
+    }
+}
+
+/// Checks the generation echoed by the executor against its current one, so the requests
+/// formed before it was bound again are rejected instead of applied to the new binding; it's
+/// not checked if none, e.g. the older executors.
+fn check_generation(exe: &Executor, generation: Option<u64>) -> Result<(), FlameError> {
+    match generation {
+        Some(g) if g != exe.generation => Err(FlameError::FailedPrecondition(format!(
+            "the request of executor <{}> at generation <{}> is stale, it's at generation <{}>",
+            exe.id, g, exe.generation
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Binds the executor to the session; it's checked and changed under the lock of the
+/// executor, so concurrent or duplicated binds are applied once. Binding it to the same
+/// session again is a no-op, and binding it to another session is rejected. Returns whether
+/// the executor was bound by this call; its generation is bumped if so.
+fn bind(exe_ptr: &ExecutorPtr, ssn_ptr: &SessionPtr) -> Result<bool, FlameError> {
+    let ssn_id: SessionID = lock_ptr!(ssn_ptr)?.id;
+
+    let mut e = lock_ptr!(exe_ptr)?;
+    check(&e, Operation::BindSession)?;
+    if e.state != ExecutorState::Idle {
+        return match e.ssn_id {
+            Some(id) if id == ssn_id => Ok(false),
+            id => Err(FlameError::FailedPrecondition(format!(
+                "executor <{}> is {} to session <{}>, can not bind it to session <{}>",
+                e.id,
+                e.state,
+                id.map(|id| id.to_string()).unwrap_or_default(),
+                ssn_id
+            ))),
+        };
+    }
+
+    e.ssn_id = Some(ssn_id);
+    e.state = ExecutorState::Binding;
+    e.generation += 1;
+
+    Ok(true)
+}
+
+/// Releases the task from the executor, so the executor can not complete or fail it again;
+/// the executor may have launched another task since its assignment was checked, or the
+/// task may be requeued already, e.g. the executor was lost. The task recorded in the
+/// executor is released if it's not given, and none is released if there's no task.
+pub fn release_task(
+    exe_ptr: &ExecutorPtr,
+    gid: Option<TaskGID>,
+) -> Result<Option<TaskGID>, FlameError> {
+    let mut e = lock_ptr!(exe_ptr)?;
+    let assigned = match (e.ssn_id, e.task_id) {
+        (Some(ssn_id), Some(task_id)) => Some(TaskGID { ssn_id, task_id }),
+        _ => None,
+    };
+
+    match (gid, assigned) {
+        (None, None) => Ok(None),
+        (Some(gid), assigned) if assigned != Some(gid) => Err(FlameError::FailedPrecondition(
+            format!("task <{}> is not assigned to executor <{}>", gid, e.id),
+        )),
+        (_, assigned) => {
+            e.task_id = None;
+            Ok(assigned)
+        }
+    }
+}
+
+/// Builds the state of the executor for the operation, which is checked against the
+/// transition table and the generation echoed by the executor, if any.
+pub fn from(
+    storage: StoragePtr,
+    exe_ptr: ExecutorPtr,
+    op: Operation,
+    generation: Option<u64>,
+) -> Result<Arc<dyn States>, FlameError> {
     let exe = lock_ptr!(exe_ptr)?;
     log::debug!("Build state <{}> for Executor.", exe.state.to_string());
+    check_generation(&exe, generation)?;
+    check(&exe, op)?;
 
     match exe.state {
         ExecutorState::Idle => Ok(Arc::new(IdleState {
@@ -52,7 +188,9 @@ pub fn from(storage: StoragePtr, exe_ptr: ExecutorPtr) -> Result<Arc<dyn States>
 
 #[async_trait::async_trait]
 pub trait States: Send + Sync + 'static {
-    async fn bind_session(&self, ssn: SessionPtr) -> Result<(), FlameError>;
+    /// Binds the executor to the session; returns whether it was bound by this call, i.e.
+    /// false if it was already bound to the session.
+    async fn bind_session(&self, ssn: SessionPtr) -> Result<bool, FlameError>;
     async fn bind_session_completed(&self) -> Result<(), FlameError>;
 
     async fn unbind_executor(&self) -> Result<(), FlameError>;
@@ -65,4 +203,217 @@ pub trait States: Send + Sync + 'static {
         task: TaskPtr,
         task_output: Option<TaskOutput>,
     ) -> Result<(), FlameError>;
+    /// Requeues the task failed by the executor, and records the failure.
+    async fn fail_task(
+        &self,
+        ssn: SessionPtr,
+        task: TaskPtr,
+        failure: &TaskFailure,
+    ) -> Result<(), FlameError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Utc;
+
+    use common::apis::{Application, SessionAttributes, TaskAttributes, TaskState};
+
+    use crate::storage;
+
+    const STATES: [ExecutorState; 4] = [
+        ExecutorState::Idle,
+        ExecutorState::Binding,
+        ExecutorState::Bound,
+        ExecutorState::Unbinding,
+    ];
+
+    const OPERATIONS: [Operation; 7] = [
+        Operation::BindSession,
+        Operation::BindSessionCompleted,
+        Operation::UnbindExecutor,
+        Operation::UnbindExecutorCompleted,
+        Operation::LaunchTask,
+        Operation::CompleteTask,
+        Operation::FailTask,
+    ];
+
+    struct Fixture {
+        storage: StoragePtr,
+        ssn_ptr: SessionPtr,
+        task_ptr: TaskPtr,
+        exe_ptr: ExecutorPtr,
+    }
+
+    /// An executor in the state with a running task of the session if it's bound to the
+    /// session, and another pending task to launch.
+    async fn fixture(state: ExecutorState) -> Result<Fixture, FlameError> {
+        let storage = storage::new_ptr("memory://").await?;
+        storage.set_config_applications(&[Application {
+            name: "flmexec".to_string(),
+            ..Default::default()
+        }])?;
+        let ssn = storage
+            .create_session(SessionAttributes {
+                application: "flmexec".to_string(),
+                slots: 1,
+                ..Default::default()
+            })
+            .await?;
+        let task = storage
+            .create_task(ssn.id, TaskAttributes::default())
+            .await?;
+        storage
+            .create_task(ssn.id, TaskAttributes::default())
+            .await?;
+
+        let ssn_ptr = storage.get_session_ptr(ssn.id)?;
+        let task_ptr = storage.get_task_ptr(task.gid())?;
+        let bound = matches!(state, ExecutorState::Bound | ExecutorState::Unbinding);
+        if bound {
+            storage
+                .update_task_state(ssn_ptr.clone(), task_ptr.clone(), TaskState::Running)
+                .await?;
+        }
+
+        storage.register_executor(&Executor {
+            id: "exec-1".to_string(),
+            slots: 1,
+            applications: vec![],
+            task_id: bound.then_some(task.id),
+            ssn_id: (state != ExecutorState::Idle).then_some(ssn.id),
+            labels: Default::default(),
+            locality_keys: vec![],
+            host: Default::default(),
+            creation_time: Utc::now(),
+            last_heartbeat: Utc::now(),
+            state,
+            draining: false,
+            quarantined: false,
+            generation: 0,
+        })?;
+        let exe_ptr = storage.get_executor_ptr("exec-1".to_string())?;
+
+        Ok(Fixture {
+            storage,
+            ssn_ptr,
+            task_ptr,
+            exe_ptr,
+        })
+    }
+
+    async fn apply(f: &Fixture, op: Operation) -> Result<(), FlameError> {
+        let state = from(f.storage.clone(), f.exe_ptr.clone(), op, None)?;
+        match op {
+            Operation::BindSession => state.bind_session(f.ssn_ptr.clone()).await.map(|_| ()),
+            Operation::BindSessionCompleted => state.bind_session_completed().await,
+            Operation::UnbindExecutor => state.unbind_executor().await,
+            Operation::UnbindExecutorCompleted => state.unbind_executor_completed().await,
+            Operation::LaunchTask => state.launch_task(f.ssn_ptr.clone()).await.map(|_| ()),
+            Operation::CompleteTask => {
+                state
+                    .complete_task(f.ssn_ptr.clone(), f.task_ptr.clone(), None)
+                    .await
+            }
+            Operation::FailTask => {
+                let failure = TaskFailure {
+                    reason: Default::default(),
+                    message: "shim crashed".to_string(),
+                };
+                state
+                    .fail_task(f.ssn_ptr.clone(), f.task_ptr.clone(), &failure)
+                    .await
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transitions() -> Result<(), FlameError> {
+        for state in STATES {
+            for op in OPERATIONS {
+                let f = fixture(state).await?;
+                let result = apply(&f, op).await;
+                let exe = lock_ptr!(f.exe_ptr)?;
+
+                match transition(state, op) {
+                    Some(next) => {
+                        assert!(result.is_ok(), "{} in {}: {:?}", op, state, result.err());
+                        assert_eq!(exe.state, next, "{} in {}", op, state);
+                    }
+                    None => {
+                        assert!(
+                            matches!(result, Err(FlameError::FailedPrecondition(_))),
+                            "{} in {} is illegal",
+                            op,
+                            state
+                        );
+                        assert_eq!(exe.state, state, "{} in {}", op, state);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bind_session_once() -> Result<(), FlameError> {
+        let f = fixture(ExecutorState::Idle).await?;
+        let ssn_id = lock_ptr!(f.ssn_ptr)?.id;
+        let other = f
+            .storage
+            .create_session(SessionAttributes {
+                application: "flmexec".to_string(),
+                slots: 1,
+                ..Default::default()
+            })
+            .await?;
+
+        let bind = |ssn_id| f.storage.bind_session("exec-1".to_string(), ssn_id);
+        assert!(bind(ssn_id).await?);
+        // The duplicated bind is a no-op, even once the executor is bound.
+        assert!(!bind(ssn_id).await?);
+        f.storage
+            .bind_session_completed("exec-1".to_string(), None, None)
+            .await?;
+        assert!(!bind(ssn_id).await?);
+
+        // The executor is not taken by another session.
+        let e = bind(other.id).await.unwrap_err();
+        assert!(matches!(e, FlameError::FailedPrecondition(_)));
+        let exe = lock_ptr!(f.exe_ptr)?;
+        assert_eq!(
+            (exe.state, exe.ssn_id),
+            (ExecutorState::Bound, Some(ssn_id))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_illegal_operation() {
+        let exe = Executor {
+            id: "exec-1".to_string(),
+            slots: 1,
+            applications: vec![],
+            task_id: None,
+            ssn_id: Some(1),
+            labels: Default::default(),
+            locality_keys: vec![],
+            host: Default::default(),
+            creation_time: Utc::now(),
+            last_heartbeat: Utc::now(),
+            state: ExecutorState::Binding,
+            draining: false,
+            quarantined: false,
+            generation: 0,
+        };
+
+        let e = check(&exe, Operation::LaunchTask).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "'executor <exec-1> can not launch_task in state <Binding>'"
+        );
+    }
 }
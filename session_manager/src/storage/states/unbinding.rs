@@ -11,10 +11,12 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use crate::storage::states::States;
+use crate::storage::states::{illegal, release_task, Operation, States};
 use crate::storage::StoragePtr;
 
-use common::apis::{ExecutorPtr, ExecutorState, SessionPtr, Task, TaskOutput, TaskPtr, TaskState};
+use common::apis::{
+    ExecutorPtr, ExecutorState, SessionPtr, Task, TaskFailure, TaskOutput, TaskPtr, TaskState,
+};
 use common::{lock_ptr, trace::TraceFn, trace_fn, FlameError};
 
 pub struct UnbindingState {
@@ -24,12 +26,12 @@ pub struct UnbindingState {
 
 #[async_trait::async_trait]
 impl States for UnbindingState {
-    async fn bind_session(&self, _ssn_ptr: SessionPtr) -> Result<(), FlameError> {
-        todo!()
+    async fn bind_session(&self, _ssn_ptr: SessionPtr) -> Result<bool, FlameError> {
+        Err(illegal(&self.executor, Operation::BindSession))
     }
 
     async fn bind_session_completed(&self) -> Result<(), FlameError> {
-        todo!()
+        Err(illegal(&self.executor, Operation::BindSessionCompleted))
     }
 
     async fn unbind_executor(&self) -> Result<(), FlameError> {
@@ -64,10 +66,8 @@ impl States for UnbindingState {
     ) -> Result<(), FlameError> {
         trace_fn!("UnbindingState::complete_task");
 
-        {
-            let mut e = lock_ptr!(self.executor)?;
-            e.task_id = None;
-        };
+        let gid = lock_ptr!(task_ptr)?.gid();
+        release_task(&self.executor, Some(gid))?;
 
         {
             let mut task = lock_ptr!(task_ptr)?;
@@ -80,4 +80,20 @@ impl States for UnbindingState {
 
         Ok(())
     }
+
+    async fn fail_task(
+        &self,
+        _ssn_ptr: SessionPtr,
+        task_ptr: TaskPtr,
+        failure: &TaskFailure,
+    ) -> Result<(), FlameError> {
+        trace_fn!("UnbindingState::fail_task");
+
+        let gid = lock_ptr!(task_ptr)?.gid();
+        self.storage
+            .requeue_task_from_executor(&self.executor, Some(gid), failure)
+            .await?;
+
+        Ok(())
+    }
 }
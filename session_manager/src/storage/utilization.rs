@@ -0,0 +1,291 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use common::apis::ExecutorID;
+use common::ctx::FlameUtilizationConf;
+use common::FlameError;
+
+/// The busy time the executor may report apart from the session manager's measure before
+/// it's logged as drifting, besides `DRIFT_RATIO` of the measure; the task is launched and
+/// completed by the two sides at slightly different times.
+const DRIFT_SLACK: f64 = 5.0;
+const DRIFT_RATIO: f64 = 0.1;
+
+/// The busy and idle time of the executors, i.e. whether they run a task, which is kept in
+/// memory only; it's counted from the registration of the executor, so a re-registered
+/// executor starts over.
+#[derive(Debug)]
+pub struct Utilization {
+    windows: Vec<Duration>,
+    executors: HashMap<ExecutorID, Usage>,
+}
+
+#[derive(Debug)]
+struct Usage {
+    registered: DateTime<Utc>,
+    /// When the running task was launched, none if the executor is idle.
+    busy_since: Option<DateTime<Utc>>,
+    /// The busy periods ended within the longest window, the oldest first.
+    periods: VecDeque<(DateTime<Utc>, DateTime<Utc>)>,
+    /// The busy time of the ended periods since the registration.
+    busy: Duration,
+    /// The busy seconds reported by the executor in its last heartbeat.
+    reported: Option<f64>,
+    drifting: bool,
+}
+
+/// The utilization of an executor at a time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExecutorUsage {
+    /// The percentage of the busy time in each window, from 0 to 100.
+    pub windows: Vec<(Duration, f64)>,
+    pub busy_seconds: f64,
+    pub reported_busy_seconds: Option<f64>,
+}
+
+impl Default for Utilization {
+    fn default() -> Self {
+        Utilization::new(&FlameUtilizationConf::default()).expect("the default utilization")
+    }
+}
+
+impl Usage {
+    fn new(now: DateTime<Utc>) -> Self {
+        Usage {
+            registered: now,
+            busy_since: None,
+            periods: VecDeque::new(),
+            busy: Duration::ZERO,
+            reported: None,
+            drifting: false,
+        }
+    }
+
+    fn busy_seconds(&self, now: DateTime<Utc>) -> f64 {
+        let running = self.busy_since.map(|t| elapsed(t, now)).unwrap_or_default();
+        (self.busy + running).as_secs_f64()
+    }
+
+    /// The busy time within the window ending at `now`, and the length of the window, which
+    /// is cut at the registration.
+    fn window(&self, window: Duration, now: DateTime<Utc>) -> (Duration, Duration) {
+        let start = chrono::Duration::from_std(window)
+            .ok()
+            .and_then(|w| now.checked_sub_signed(w))
+            .map_or(self.registered, |t| t.max(self.registered));
+
+        let current = self.busy_since.map(|t| (t, now));
+        let busy = self
+            .periods
+            .iter()
+            .copied()
+            .chain(current)
+            .map(|(from, to)| elapsed(from.max(start), to))
+            .sum();
+
+        (busy, elapsed(start, now))
+    }
+}
+
+fn elapsed(from: DateTime<Utc>, to: DateTime<Utc>) -> Duration {
+    (to - from).to_std().unwrap_or_default()
+}
+
+impl Utilization {
+    pub fn new(conf: &FlameUtilizationConf) -> Result<Self, FlameError> {
+        Ok(Utilization {
+            windows: conf.windows()?,
+            executors: HashMap::new(),
+        })
+    }
+
+    /// Starts over the counters of the executor, e.g. it's registered again.
+    pub fn reset(&mut self, id: &ExecutorID, now: DateTime<Utc>) {
+        self.executors.insert(id.clone(), Usage::new(now));
+    }
+
+    pub fn forget(&mut self, id: &ExecutorID) {
+        self.executors.remove(id);
+    }
+
+    /// Records whether the executor runs a task after its state transition; it's a no-op if
+    /// the executor was already busy or idle.
+    pub fn record(&mut self, id: &ExecutorID, busy: bool, now: DateTime<Utc>) {
+        let longest = self.windows.iter().max().copied().unwrap_or_default();
+        let usage = self
+            .executors
+            .entry(id.clone())
+            .or_insert_with(|| Usage::new(now));
+
+        match (usage.busy_since, busy) {
+            (None, true) => usage.busy_since = Some(now),
+            (Some(since), false) => {
+                usage.busy_since = None;
+                usage.busy += elapsed(since, now);
+                usage.periods.push_back((since, now));
+            }
+            _ => {}
+        }
+
+        while let Some((_, to)) = usage.periods.front() {
+            if elapsed(*to, now) < longest {
+                break;
+            }
+            usage.periods.pop_front();
+        }
+    }
+
+    /// Records the busy seconds reported by the executor, and returns the busy seconds
+    /// measured by the session manager if they drifted apart; it's returned once until they
+    /// agree again.
+    pub fn report(
+        &mut self,
+        id: &ExecutorID,
+        busy_seconds: f64,
+        now: DateTime<Utc>,
+    ) -> Option<f64> {
+        let usage = self.executors.get_mut(id)?;
+        usage.reported = Some(busy_seconds);
+
+        let measured = usage.busy_seconds(now);
+        let drifting = (measured - busy_seconds).abs() > DRIFT_SLACK + measured * DRIFT_RATIO;
+        let started = drifting && !usage.drifting;
+        usage.drifting = drifting;
+
+        started.then_some(measured)
+    }
+
+    pub fn usage(&self, id: &ExecutorID, now: DateTime<Utc>) -> ExecutorUsage {
+        let Some(usage) = self.executors.get(id) else {
+            return ExecutorUsage {
+                windows: self.windows.iter().map(|w| (*w, 0.0)).collect(),
+                ..Default::default()
+            };
+        };
+
+        let windows = self
+            .windows
+            .iter()
+            .map(|w| {
+                let (busy, len) = usage.window(*w, now);
+                let percentage = match len.is_zero() {
+                    true => 0.0,
+                    false => 100.0 * busy.as_secs_f64() / len.as_secs_f64(),
+                };
+                (*w, percentage)
+            })
+            .collect();
+
+        ExecutorUsage {
+            windows,
+            busy_seconds: usage.busy_seconds(now),
+            reported_busy_seconds: usage.reported,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minutes(n: i64) -> chrono::Duration {
+        chrono::Duration::minutes(n)
+    }
+
+    fn utilization() -> Utilization {
+        Utilization::new(&FlameUtilizationConf {
+            windows: Some(vec!["10m".to_string(), "1h".to_string()]),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_busy_idle_pattern() {
+        let mut u = utilization();
+        let (a, t0) = ("a".to_string(), Utc::now());
+        u.reset(&a, t0);
+
+        // Busy 3 minutes out of every 4 minutes for an hour.
+        for i in 0..15 {
+            let start = t0 + minutes(4 * i);
+            u.record(&a, true, start);
+            // The repeated transitions are no-ops.
+            u.record(&a, true, start + minutes(1));
+            u.record(&a, false, start + minutes(3));
+            u.record(&a, false, start + minutes(3));
+        }
+
+        let usage = u.usage(&a, t0 + minutes(60));
+        assert_eq!(usage.busy_seconds, 45.0 * 60.0);
+        assert_eq!(usage.windows[0].0, Duration::from_secs(600));
+        // The last 10 minutes: busy 52-55 and 56-59, and 1 minute of the one from 48.
+        assert!((usage.windows[0].1 - 70.0).abs() < 1e-9, "{:?}", usage);
+        assert!((usage.windows[1].1 - 75.0).abs() < 1e-9, "{:?}", usage);
+
+        // An idle hour later, only the longer window remembers it.
+        let usage = u.usage(&a, t0 + minutes(100));
+        assert_eq!(usage.windows[0].1, 0.0);
+        assert!((usage.windows[1].1 - 25.0).abs() < 1e-9, "{:?}", usage);
+    }
+
+    #[test]
+    fn test_window_cut_at_registration() {
+        let mut u = utilization();
+        let (a, t0) = ("a".to_string(), Utc::now());
+        u.reset(&a, t0);
+
+        // The running task counts until now.
+        u.record(&a, true, t0 + minutes(1));
+        let usage = u.usage(&a, t0 + minutes(4));
+        assert!((usage.windows[0].1 - 75.0).abs() < 1e-9, "{:?}", usage);
+        assert!((usage.windows[1].1 - 75.0).abs() < 1e-9, "{:?}", usage);
+        assert_eq!(usage.busy_seconds, 180.0);
+
+        // A re-registered executor starts over.
+        u.reset(&a, t0 + minutes(4));
+        let usage = u.usage(&a, t0 + minutes(5));
+        assert_eq!(usage.busy_seconds, 0.0);
+        assert_eq!(usage.windows[0].1, 0.0);
+
+        // A forgotten executor has no usage.
+        u.forget(&a);
+        assert_eq!(u.usage(&a, t0 + minutes(5)).windows[1].1, 0.0);
+    }
+
+    #[test]
+    fn test_drift() {
+        let mut u = utilization();
+        let (a, t0) = ("a".to_string(), Utc::now());
+        u.reset(&a, t0);
+        u.record(&a, true, t0);
+        u.record(&a, false, t0 + minutes(10));
+
+        let now = t0 + minutes(20);
+        assert_eq!(u.report(&a, 598.0, now), None);
+        assert_eq!(u.usage(&a, now).reported_busy_seconds, Some(598.0));
+
+        // The drift is returned once, until they agree again.
+        assert_eq!(u.report(&a, 300.0, now), Some(600.0));
+        assert_eq!(u.report(&a, 300.0, now), None);
+        assert_eq!(u.report(&a, 600.0, now), None);
+        assert_eq!(u.report(&a, 900.0, now), Some(600.0));
+
+        // An unknown executor is not tracked.
+        assert_eq!(u.report(&"b".to_string(), 900.0, now), None);
+    }
+}
@@ -0,0 +1,457 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Runs the background loops of the session manager, e.g. the scheduler and the sweeper, each
+//! in its own thread; a loop which fails or panics is restarted with backoff instead of
+//! stopping silently while the others run on, and it's given up after too many restarts.
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use strum_macros::Display;
+use tokio::sync::Notify;
+
+use common::ctx::FlameContext;
+use common::ptr::{self, MutexPtr};
+use common::{lock_ptr, FlameError};
+
+use crate::FlameThread;
+
+pub type SupervisorPtr = Arc<Supervisor>;
+
+/// Tells a loop to stop; the loops wait by it between their cycles, so they stop at once.
+#[derive(Clone, Default)]
+pub struct StopSignal(Arc<StopState>);
+
+#[derive(Default)]
+struct StopState {
+    stopped: Mutex<bool>,
+    cond: Condvar,
+    notify: Notify,
+}
+
+impl StopSignal {
+    pub fn stop(&self) {
+        if let Ok(mut stopped) = self.0.stopped.lock() {
+            *stopped = true;
+        }
+        self.0.cond.notify_all();
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.stopped.lock().map(|s| *s).unwrap_or(true)
+    }
+
+    /// Sleeps for the duration or until the loop is stopped; returns whether it's stopped.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let Ok(stopped) = self.0.stopped.lock() else {
+            return true;
+        };
+        match self.0.cond.wait_timeout_while(stopped, timeout, |s| !*s) {
+            Ok((stopped, _)) => *stopped,
+            Err(_) => true,
+        }
+    }
+
+    /// Resolves once the loop is stopped, e.g. for the graceful shutdown of a server.
+    pub async fn stopped(&self) {
+        loop {
+            // The waiter is registered before the check, so the stop in between is not missed.
+            let notified = self.0.notify.notified();
+            if self.is_stopped() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum LoopState {
+    Running,
+    /// The loop failed, and waits for its backoff to be restarted.
+    Restarting,
+    /// The loop failed more than `RestartPolicy::max_restarts` times, and is not restarted.
+    Failed,
+    Stopped,
+}
+
+/// The health of a loop, which is reported by the admin service.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoopHealth {
+    pub name: String,
+    pub state: LoopState,
+    pub restarts: u32,
+    /// The last error or panic of the loop.
+    pub last_error: Option<String>,
+    pub last_error_time: Option<DateTime<Utc>>,
+}
+
+impl LoopHealth {
+    fn fail(&mut self, error: String) {
+        self.last_error = Some(error);
+        self.last_error_time = Some(Utc::now());
+    }
+}
+
+/// How the failed loops are restarted; the backoff is doubled by every restart.
+#[derive(Clone, Debug)]
+pub struct RestartPolicy {
+    pub max_restarts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            max_restarts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RestartPolicy {
+    fn backoff(&self, restarts: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(restarts))
+            .min(self.max_backoff)
+    }
+}
+
+struct Loop {
+    name: String,
+    health: MutexPtr<LoopHealth>,
+    stop: StopSignal,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// The loops of the session manager, which are stopped in the order they were started.
+pub struct Supervisor {
+    policy: RestartPolicy,
+    loops: MutexPtr<Vec<Loop>>,
+}
+
+impl Supervisor {
+    pub fn new_ptr(policy: RestartPolicy) -> SupervisorPtr {
+        Arc::new(Supervisor {
+            policy,
+            loops: ptr::new_ptr(vec![]),
+        })
+    }
+
+    /// Runs the loop in a thread of the name until it's stopped, or failed too many times.
+    pub fn spawn(
+        &self,
+        name: &str,
+        runner: Box<dyn FlameThread>,
+        ctx: FlameContext,
+    ) -> Result<(), FlameError> {
+        let health = ptr::new_ptr(LoopHealth {
+            name: name.to_string(),
+            state: LoopState::Running,
+            restarts: 0,
+            last_error: None,
+            last_error_time: None,
+        });
+        let stop = StopSignal::default();
+
+        let handle = {
+            let (thread_name, health, stop) = (name.to_string(), health.clone(), stop.clone());
+            let policy = self.policy.clone();
+            thread::Builder::new()
+                .name(thread_name.clone())
+                .spawn(move || supervise(&thread_name, runner, ctx, &policy, &health, &stop))
+                .map_err(|e| FlameError::Internal(format!("failed to start <{}>: {}", name, e)))?
+        };
+        log::info!("<{}> thread was started.", name);
+
+        lock_ptr!(self.loops)?.push(Loop {
+            name: name.to_string(),
+            health,
+            stop,
+            handle: Some(handle),
+        });
+
+        Ok(())
+    }
+
+    /// The health of the loops in the order they were started.
+    pub fn health(&self) -> Result<Vec<LoopHealth>, FlameError> {
+        let loops = lock_ptr!(self.loops)?;
+        loops
+            .iter()
+            .map(|l| Ok(lock_ptr!(l.health)?.clone()))
+            .collect()
+    }
+
+    /// Stops the loops one by one in the order they were started, so the ones started later,
+    /// e.g. the sweeper flushing the storage, stop after the ones they depend on; a loop which
+    /// does not stop in `timeout` is left behind.
+    pub fn shutdown(&self, timeout: Duration) -> Result<(), FlameError> {
+        let loops: Vec<_> = lock_ptr!(self.loops)?
+            .iter_mut()
+            .map(|l| (l.name.clone(), l.stop.clone(), l.handle.take()))
+            .collect();
+
+        for (name, stop, handle) in loops {
+            log::info!("Stopping <{}> thread ...", name);
+            stop.stop();
+            let Some(handle) = handle else {
+                continue;
+            };
+
+            let deadline = Instant::now() + timeout;
+            while !handle.is_finished() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(10));
+            }
+            match handle.is_finished() {
+                true => {
+                    let _ = handle.join();
+                }
+                false => log::warn!("<{}> thread did not stop in {:?}.", name, timeout),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the loop again after its failures, until it's stopped or given up.
+fn supervise(
+    name: &str,
+    runner: Box<dyn FlameThread>,
+    ctx: FlameContext,
+    policy: &RestartPolicy,
+    health: &MutexPtr<LoopHealth>,
+    stop: &StopSignal,
+) {
+    let set = |f: &dyn Fn(&mut LoopHealth)| match health.lock() {
+        Ok(mut health) => f(&mut health),
+        Err(e) => log::error!("Failed to update the health of <{}>: {}", name, e),
+    };
+
+    loop {
+        let res = panic::catch_unwind(AssertUnwindSafe(|| runner.run(ctx.clone(), stop)));
+        if stop.is_stopped() {
+            log::info!("<{}> thread was stopped.", name);
+            set(&|h| h.state = LoopState::Stopped);
+            return;
+        }
+
+        let error = match res {
+            Ok(Ok(_)) => "exited unexpectedly".to_string(),
+            Ok(Err(e)) => e.to_string(),
+            Err(payload) => format!("panicked: {}", panic_message(&*payload)),
+        };
+
+        let restarts = match health.lock() {
+            Ok(h) => h.restarts,
+            Err(_) => policy.max_restarts,
+        };
+        if restarts >= policy.max_restarts {
+            log::error!(
+                "<{}> thread {}; it's given up after {} restarts.",
+                name,
+                error,
+                restarts
+            );
+            set(&|h| {
+                h.state = LoopState::Failed;
+                h.fail(error.clone());
+            });
+            return;
+        }
+
+        let backoff = policy.backoff(restarts);
+        log::error!(
+            "<{}> thread {}; it's restarted in {:?} ({}/{}).",
+            name,
+            error,
+            backoff,
+            restarts + 1,
+            policy.max_restarts
+        );
+        set(&|h| {
+            h.state = LoopState::Restarting;
+            h.fail(error.clone());
+        });
+        if stop.wait(backoff) {
+            set(&|h| h.state = LoopState::Stopped);
+            return;
+        }
+        set(&|h| {
+            h.state = LoopState::Running;
+            h.restarts += 1;
+        });
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        return s.to_string();
+    }
+    match payload.downcast_ref::<String>() {
+        Some(s) => s.clone(),
+        None => "unknown panic".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Panics in its first runs, then runs until it's stopped.
+    struct Flaky {
+        runs: Arc<AtomicU32>,
+        panics: u32,
+    }
+
+    impl FlameThread for Flaky {
+        fn run(&self, _: FlameContext, stop: &StopSignal) -> Result<(), FlameError> {
+            let runs = self.runs.fetch_add(1, Ordering::SeqCst) + 1;
+            if runs <= self.panics {
+                panic!("flaky run {}", runs);
+            }
+            while !stop.wait(Duration::from_secs(10)) {}
+
+            Ok(())
+        }
+    }
+
+    fn policy() -> RestartPolicy {
+        RestartPolicy {
+            max_restarts: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(40),
+        }
+    }
+
+    fn wait_state(supervisor: &Supervisor, index: usize, state: LoopState) -> LoopHealth {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let health = supervisor.health().unwrap().remove(index);
+            if health.state == state {
+                return health;
+            }
+            assert!(Instant::now() < deadline, "{:?}", health);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_restart_panicked_loop() {
+        let supervisor = Supervisor::new_ptr(policy());
+        let runs = Arc::new(AtomicU32::new(0));
+        let flaky = Flaky {
+            runs: runs.clone(),
+            panics: 2,
+        };
+        supervisor
+            .spawn("flaky", Box::new(flaky), FlameContext::default())
+            .unwrap();
+
+        // Restarted twice, and runs since.
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while runs.load(Ordering::SeqCst) < 3 {
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(10));
+        }
+        let health = wait_state(&supervisor, 0, LoopState::Running);
+        assert_eq!(health.name, "flaky");
+        assert_eq!(health.restarts, 2);
+        assert_eq!(health.last_error.as_deref(), Some("panicked: flaky run 2"));
+        assert!(health.last_error_time.is_some());
+
+        supervisor.shutdown(Duration::from_secs(5)).unwrap();
+        let health = wait_state(&supervisor, 0, LoopState::Stopped);
+        assert_eq!(health.restarts, 2);
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_give_up_failing_loop() {
+        struct Failing;
+        impl FlameThread for Failing {
+            fn run(&self, _: FlameContext, _: &StopSignal) -> Result<(), FlameError> {
+                Err(FlameError::Internal("broken".to_string()))
+            }
+        }
+
+        let supervisor = Supervisor::new_ptr(policy());
+        supervisor
+            .spawn("failing", Box::new(Failing), FlameContext::default())
+            .unwrap();
+
+        let health = wait_state(&supervisor, 0, LoopState::Failed);
+        assert_eq!(health.restarts, 3);
+        assert_eq!(health.last_error.as_deref(), Some("'broken'"));
+
+        supervisor.shutdown(Duration::from_secs(5)).unwrap();
+        assert_eq!(supervisor.health().unwrap()[0].state, LoopState::Failed);
+    }
+
+    #[test]
+    fn test_shutdown_in_order() {
+        /// Records the order the loops stopped in.
+        struct Ordered {
+            name: &'static str,
+            stopped: MutexPtr<Vec<&'static str>>,
+        }
+        impl FlameThread for Ordered {
+            fn run(&self, _: FlameContext, stop: &StopSignal) -> Result<(), FlameError> {
+                while !stop.wait(Duration::from_secs(10)) {}
+                lock_ptr!(self.stopped)?.push(self.name);
+                Ok(())
+            }
+        }
+
+        let supervisor = Supervisor::new_ptr(policy());
+        let stopped = ptr::new_ptr(vec![]);
+        for name in ["scheduler", "apiserver", "sweeper"] {
+            let runner = Ordered {
+                name,
+                stopped: stopped.clone(),
+            };
+            supervisor
+                .spawn(name, Box::new(runner), FlameContext::default())
+                .unwrap();
+        }
+
+        supervisor.shutdown(Duration::from_secs(5)).unwrap();
+        assert_eq!(
+            *stopped.lock().unwrap(),
+            vec!["scheduler", "apiserver", "sweeper"]
+        );
+        for health in supervisor.health().unwrap() {
+            assert_eq!(health.state, LoopState::Stopped);
+            assert_eq!(health.restarts, 0);
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = policy();
+        let backoff: Vec<_> = (0..4).map(|n| policy.backoff(n).as_millis()).collect();
+        assert_eq!(backoff, vec![10, 20, 40, 40]);
+    }
+}
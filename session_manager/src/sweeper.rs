@@ -0,0 +1,89 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use chrono::Utc;
+
+use crate::storage::StoragePtr;
+use crate::supervisor::StopSignal;
+use crate::FlameThread;
+use common::ctx::FlameContext;
+use common::FlameError;
+
+pub fn new(storage: StoragePtr) -> Box<dyn FlameThread> {
+    Box::new(SweepRunner { storage })
+}
+
+/// Closes the sessions past their deadlines and the orphaned ones, fails the tasks running
+/// past their timeouts, releases the executors past the cooldown of their quarantine, removes
+/// the executors past their lease, and persists the events and the last calls of the sessions
+/// in the background; the time is taken when sweeping, so the storage can be tested with any
+/// time by `Storage::expire_sessions`, `Storage::orphan_sessions`, `Storage::expire_tasks`,
+/// `Storage::release_quarantined` and `Storage::expire_executors`. A session is closed at
+/// most one `timings.sweep_interval` past its deadline. Once stopped, it persists the events
+/// and the states the engine is behind for the last time.
+struct SweepRunner {
+    storage: StoragePtr,
+}
+
+impl FlameThread for SweepRunner {
+    fn run(&self, ctx: FlameContext, stop: &StopSignal) -> Result<(), FlameError> {
+        let timings = ctx.timings.unwrap_or_default();
+        let interval = timings.sweep_interval()?;
+        let lease = timings.lease()?;
+        let orphan_timeout = timings.orphan_timeout()?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| FlameError::Internal(e.to_string()))?;
+
+        loop {
+            if let Err(e) = runtime.block_on(self.storage.expire_sessions(Utc::now())) {
+                log::error!("Failed to expire sessions: {}", e);
+            }
+            if let Some(timeout) = orphan_timeout {
+                let orphaned = self.storage.orphan_sessions(Utc::now(), timeout);
+                if let Err(e) = runtime.block_on(orphaned) {
+                    log::error!("Failed to close orphaned sessions: {}", e);
+                }
+            }
+            if let Err(e) = runtime.block_on(self.storage.expire_tasks(Utc::now())) {
+                log::error!("Failed to expire tasks: {}", e);
+            }
+            if let Err(e) = self.storage.release_quarantined(Utc::now()) {
+                log::error!("Failed to release quarantined executors: {}", e);
+            }
+            if let Err(e) = runtime.block_on(self.storage.expire_executors(Utc::now(), lease)) {
+                log::error!("Failed to expire lost executors: {}", e);
+            }
+            if let Err(e) = runtime.block_on(self.storage.flush_events()) {
+                log::error!("Failed to persist the events of sessions: {}", e);
+            }
+            if let Err(e) = runtime.block_on(self.storage.persist_activities()) {
+                log::error!("Failed to persist the activities of sessions: {}", e);
+            }
+            if stop.wait(timings.jittered(interval)) {
+                break;
+            }
+        }
+
+        let (sessions, tasks) = runtime.block_on(self.storage.flush())?;
+        log::info!(
+            "The storage was flushed: {} sessions and {} tasks persisted.",
+            sessions,
+            tasks
+        );
+        runtime.block_on(self.storage.flush_events())?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,332 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The end-to-end harness: the session manager with the memory engine and the executors run in
+//! the process of the test, and the scenarios drive them by the client over TCP, e.g.
+//!
+//! ```ignore
+//! let harness = Harness::builder().executors(2).start().await?;
+//! let ssn = harness.connection().create_session(&harness.attributes(1)).await?;
+//! ...
+//! harness.assert_healthy();
+//! ```
+
+use std::net::TcpListener;
+use std::panic;
+use std::sync::{Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot;
+
+use common::apis::{Application, Shim};
+use common::ctx::{FlameContext, FlameExecutorConf, FlameTimingsConf};
+use flame_client::{self as flame, Connection, FlameError, SessionAttributes};
+use flame_session_manager::{LoopState, SupervisorPtr};
+
+/// The application echoing the inputs of its tasks as their outputs.
+pub const ECHO_APP: &str = "echo";
+
+/// How long the harness waits for the session manager and the executors to be ready.
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The panics of the background threads and tasks in this process, e.g. the ones of the
+/// scheduler; the panics of the test threads are their failures, so they're not recorded.
+static PANICS: Mutex<Vec<String>> = Mutex::new(vec![]);
+static TEST_THREADS: Mutex<Vec<String>> = Mutex::new(vec![]);
+static PANIC_HOOK: Once = Once::new();
+
+fn install_panic_hook() {
+    PANIC_HOOK.call_once(|| {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let name = thread::current().name().unwrap_or("<unnamed>").to_string();
+            let is_test = TEST_THREADS
+                .lock()
+                .map(|threads| threads.contains(&name))
+                .unwrap_or(false);
+            if !is_test {
+                if let Ok(mut panics) = PANICS.lock() {
+                    panics.push(format!("<{}>: {}", name, info));
+                }
+            }
+            hook(info);
+        }));
+    });
+
+    if let (Some(name), Ok(mut threads)) = (thread::current().name(), TEST_THREADS.lock()) {
+        threads.push(name.to_string());
+    }
+}
+
+/// A failure injected into the cluster of the harness.
+#[derive(Clone, Debug)]
+pub enum Fault {
+    /// The executor stops calling the session manager after the delay, as if its host was
+    /// lost; its tasks are requeued once its lease expires.
+    LoseExecutor { index: usize, after: Duration },
+}
+
+pub struct HarnessBuilder {
+    executors: usize,
+    slots: i32,
+    policy: String,
+    faults: Vec<Fault>,
+    ctx: FlameContext,
+}
+
+impl HarnessBuilder {
+    /// The number of the executors; 2 by default.
+    pub fn executors(mut self, n: usize) -> Self {
+        self.executors = n;
+        self
+    }
+
+    /// The slots of each executor; 1 by default.
+    pub fn slots(mut self, slots: i32) -> Self {
+        self.slots = slots;
+        self
+    }
+
+    /// The scheduling policy of the session manager, e.g. fairshare.
+    pub fn policy(mut self, policy: &str) -> Self {
+        self.policy = policy.to_string();
+        self
+    }
+
+    pub fn fault(mut self, fault: Fault) -> Self {
+        self.faults.push(fault);
+        self
+    }
+
+    /// Changes the configuration shared by the session manager and the executors, e.g. the
+    /// quotas of a scenario; the endpoint, the storage and the policy are set by the harness.
+    pub fn configure(mut self, f: impl FnOnce(&mut FlameContext)) -> Self {
+        f(&mut self.ctx);
+        self
+    }
+
+    /// Starts the session manager and the executors, and waits until the executors are
+    /// registered.
+    pub async fn start(self) -> Result<Harness, FlameError> {
+        install_panic_hook();
+
+        let port = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .map_err(|e| FlameError::Internal(format!("failed to pick a port: {}", e)))?
+            .port();
+        let mut ctx = self.ctx;
+        // The clients of the executors are shared by the name in the process, so it's unique
+        // per harness.
+        ctx.name = format!("flame-e2e-{}", port);
+        ctx.endpoint = format!("http://127.0.0.1:{}", port);
+        ctx.storage = "memory://".to_string();
+        ctx.policy = self.policy;
+        ctx.executor.get_or_insert_with(Default::default).slots = Some(self.slots);
+        ctx.validate()?;
+
+        let supervisor = flame_session_manager::start(&ctx).await?;
+        let conn = wait_for(|| flame::connect(&ctx.endpoint)).await?;
+
+        let executors = (0..self.executors)
+            .map(|i| Executor::start(&ctx, i))
+            .collect::<Result<_, _>>()?;
+        let mut harness = Harness {
+            ctx,
+            conn,
+            supervisor,
+            executors,
+        };
+        harness.wait_executors(self.executors).await?;
+
+        for fault in self.faults {
+            match fault {
+                Fault::LoseExecutor { index, after } => {
+                    let stop = harness.executors[index].stop.take();
+                    tokio::spawn(async move {
+                        tokio::time::sleep(after).await;
+                        drop(stop);
+                    });
+                }
+            }
+        }
+
+        Ok(harness)
+    }
+}
+
+/// The session manager and the executors of a scenario; the executors are stopped when it's
+/// dropped, and the session manager when the process of the test exits.
+pub struct Harness {
+    ctx: FlameContext,
+    conn: Connection,
+    supervisor: SupervisorPtr,
+    executors: Vec<Executor>,
+}
+
+/// An executor manager running in its own thread and runtime, as in its own process.
+struct Executor {
+    thread: thread::JoinHandle<()>,
+    /// The executor is stopped once it's dropped, so it's none if the executor is lost.
+    stop: Option<oneshot::Sender<()>>,
+}
+
+impl Executor {
+    fn start(ctx: &FlameContext, index: usize) -> Result<Self, FlameError> {
+        let mut ctx = ctx.clone();
+        // Each executor has its own client bound to its runtime.
+        ctx.name = format!("{}-executor-{}", ctx.name, index);
+        let (stop, stopped) = oneshot::channel::<()>();
+
+        let thread = thread::Builder::new()
+            .name(ctx.name.clone())
+            .spawn(move || {
+                let runtime = match tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        log::error!("Failed to build the runtime of <{}>: {}", ctx.name, e);
+                        return;
+                    }
+                };
+                runtime.block_on(async {
+                    tokio::select! {
+                        res = flame_executor_manager::run(&ctx) => {
+                            if let Err(e) = res {
+                                log::error!("Failed to run <{}>: {}", ctx.name, e);
+                            }
+                        }
+                        _ = stopped => {}
+                    }
+                });
+            })
+            .map_err(|e| FlameError::Internal(format!("failed to start executor: {}", e)))?;
+
+        Ok(Executor {
+            thread,
+            stop: Some(stop),
+        })
+    }
+}
+
+impl Harness {
+    pub fn builder() -> HarnessBuilder {
+        let echo = Application {
+            name: ECHO_APP.to_string(),
+            shim: Shim::Stdio,
+            command: "/bin/cat".to_string(),
+            working_directory: "/tmp".to_string(),
+            ..Default::default()
+        };
+        let ctx = FlameContext {
+            applications: vec![echo],
+            executor: Some(FlameExecutorConf::default()),
+            timings: Some(FlameTimingsConf {
+                schedule_interval: Some("100ms".to_string()),
+                sweep_interval: Some("100ms".to_string()),
+                heartbeat_interval: Some("1s".to_string()),
+                lease: Some("3s".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        HarnessBuilder {
+            executors: 2,
+            slots: 1,
+            policy: "fairshare".to_string(),
+            faults: vec![],
+            ctx,
+        }
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    pub fn context(&self) -> &FlameContext {
+        &self.ctx
+    }
+
+    /// The attributes of a session of the echo application.
+    pub fn attributes(&self, slots: i32) -> SessionAttributes {
+        SessionAttributes {
+            application: ECHO_APP.to_string(),
+            slots,
+            ..Default::default()
+        }
+    }
+
+    /// Stops the executor at once, as if its host was lost.
+    pub fn lose_executor(&mut self, index: usize) {
+        self.executors[index].stop = None;
+    }
+
+    /// Waits until the number of the registered executors is `n`, e.g. after the lost ones
+    /// are removed by their leases.
+    pub async fn wait_executors(&self, n: usize) -> Result<(), FlameError> {
+        wait_for(|| async {
+            let executors = self.conn.list_executor().await?;
+            match executors.len() == n {
+                true => Ok(()),
+                false => Err(FlameError::Internal(format!(
+                    "{} of {} executors are registered",
+                    executors.len(),
+                    n
+                ))),
+            }
+        })
+        .await
+    }
+
+    /// Panics if a background thread or task of the process panicked, a loop of the session
+    /// manager was restarted or stopped, or an executor which is not lost stopped.
+    pub fn assert_healthy(&self) {
+        let panics = PANICS.lock().unwrap();
+        assert!(panics.is_empty(), "background panics: {:?}", panics);
+
+        for health in self.supervisor.health().unwrap() {
+            assert!(
+                health.state == LoopState::Running && health.restarts == 0,
+                "<{}> thread is not healthy: {:?}",
+                health.name,
+                health
+            );
+        }
+        for (i, executor) in self.executors.iter().enumerate() {
+            if executor.stop.is_some() {
+                assert!(!executor.thread.is_finished(), "executor {} stopped", i);
+            }
+        }
+    }
+}
+
+/// Calls `f` until it succeeds, or returns its last error after `START_TIMEOUT`.
+pub async fn wait_for<F, Fut, T>(mut f: F) -> Result<T, FlameError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, FlameError>>,
+{
+    let deadline = Instant::now() + START_TIMEOUT;
+    loop {
+        match f().await {
+            Ok(res) => return Ok(res),
+            Err(e) if Instant::now() >= deadline => return Err(e),
+            Err(e) => log::debug!("Waiting: {}", e),
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
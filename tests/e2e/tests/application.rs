@@ -0,0 +1,82 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use bytes::Bytes;
+
+use flame_client::{Application, FlameError, SessionAttributes, Shim};
+use flame_e2e::Harness;
+
+const APP: &str = "counter";
+
+fn application(command: &str) -> Application {
+    Application {
+        name: APP.to_string(),
+        shim: Shim::Stdio,
+        command: command.to_string(),
+        working_directory: "/tmp".to_string(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_session_runs_recorded_application() -> Result<(), FlameError> {
+    let harness = Harness::builder().executors(2).start().await?;
+    let conn = harness.connection();
+    conn.register_application(&application("/bin/cat")).await?;
+
+    let attrs = SessionAttributes {
+        application: APP.to_string(),
+        ..harness.attributes(1)
+    };
+    let pinned = conn.create_session(&attrs).await?;
+    let latest = conn
+        .create_session(&SessionAttributes {
+            latest_application: true,
+            ..attrs.clone()
+        })
+        .await?;
+    assert!(pinned.application_fingerprint.is_some());
+    assert!(latest.application_fingerprint.is_none());
+
+    // The application is changed before any executor is bound to the sessions, which have no
+    // task yet.
+    conn.delete_application(APP, true).await?;
+    conn.register_application(&application("/usr/bin/wc"))
+        .await?;
+
+    let input = Bytes::from("a b c");
+    assert_eq!(pinned.run_task_output(input.clone()).await?, input);
+    let counted = latest.run_task_output(input.clone()).await?;
+    assert_ne!(counted, input);
+    assert_eq!(
+        String::from_utf8_lossy(&counted)
+            .split_whitespace()
+            .collect::<Vec<_>>(),
+        vec!["0", "3", "5"]
+    );
+
+    // The clone runs the application of its source too.
+    let clone = conn.clone_session(&pinned.id, false, false).await?;
+    assert_eq!(
+        clone.application_fingerprint,
+        pinned.application_fingerprint
+    );
+    assert_eq!(clone.run_task_output(input.clone()).await?, input);
+
+    for ssn in [&pinned, &latest, &clone] {
+        ssn.close().await?;
+    }
+    harness.assert_healthy();
+
+    Ok(())
+}
@@ -0,0 +1,152 @@
+/*
+Copyright 2024 The Flame Authors.
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+    http://www.apache.org/licenses/LICENSE-2.0
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::StreamExt;
+
+use flame_client::{
+    Deadline, FlameError, SessionEvent, SessionEvents, SessionState, TaskInput, TaskState,
+};
+use flame_e2e::{Fault, Harness};
+
+/// Reads the events until the session is closed, and returns them.
+async fn until_closed(events: &mut SessionEvents) -> Result<Vec<SessionEvent>, FlameError> {
+    let mut seen = vec![];
+    while let Some(event) = events.next().await {
+        let event = event?;
+        let closed = matches!(event, SessionEvent::SessionClosed { .. });
+        seen.push(event);
+        if closed {
+            break;
+        }
+    }
+
+    Ok(seen)
+}
+
+fn inputs(n: usize) -> Vec<TaskInput> {
+    (0..n).map(|i| Bytes::from(format!("task-{}", i))).collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_session_lifecycle() -> Result<(), FlameError> {
+    let harness = Harness::builder().executors(2).start().await?;
+    let conn = harness.connection();
+
+    let ssn = conn.create_session(&harness.attributes(1)).await?;
+    let mut events = ssn.events();
+
+    let outputs = ssn.map(inputs(20), 8).collect_ordered().await;
+    for (input, output) in inputs(20).into_iter().zip(outputs) {
+        assert_eq!(output?, input);
+    }
+
+    ssn.close().await?;
+    let seen = until_closed(&mut events).await?;
+    let succeed = seen
+        .iter()
+        .filter(|e| {
+            matches!(
+                e,
+                SessionEvent::TaskStateChanged {
+                    state: TaskState::Succeed,
+                    ..
+                }
+            )
+        })
+        .count();
+    assert_eq!(succeed, 20);
+
+    let closed = conn.get_session(&ssn.id).await?;
+    assert_eq!(closed.state, SessionState::Closed);
+    assert_eq!((closed.succeed, closed.failed), (20, 0));
+    assert!(closed.completion_time.is_some());
+
+    conn.delete_session(&ssn.id).await?;
+    assert!(matches!(
+        conn.get_session(&ssn.id).await,
+        Err(FlameError::NotFound(_))
+    ));
+
+    harness.assert_healthy();
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_abort_expired_session() -> Result<(), FlameError> {
+    let harness = Harness::builder().executors(2).start().await?;
+    let conn = harness.connection();
+
+    // The tasks of the session never fit the executors, so they're pending until the session
+    // is aborted by its deadline.
+    let mut attrs = harness.attributes(2);
+    attrs.deadline = Some(Deadline::After(Duration::from_secs(1)));
+    let ssn = conn.create_session(&attrs).await?;
+    let mut events = ssn.events();
+    for input in inputs(3) {
+        ssn.create_task(Some(input)).await?;
+    }
+
+    // The session with open tasks can not be closed.
+    assert!(ssn.close().await.is_err());
+
+    let seen = until_closed(&mut events).await?;
+    assert!(seen
+        .iter()
+        .any(|e| matches!(e, SessionEvent::SessionExpired { .. })));
+
+    let aborted = conn.get_session(&ssn.id).await?;
+    assert_eq!(aborted.state, SessionState::Closed);
+    assert_eq!(
+        (aborted.pending, aborted.running, aborted.failed),
+        (0, 0, 3)
+    );
+    for task in ssn.list_tasks().await? {
+        assert_eq!(task.state, TaskState::Failed);
+    }
+
+    conn.delete_session(&ssn.id).await?;
+    harness.assert_healthy();
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_lost_executor() -> Result<(), FlameError> {
+    let harness = Harness::builder()
+        .executors(2)
+        .fault(Fault::LoseExecutor {
+            index: 0,
+            after: Duration::from_millis(500),
+        })
+        .start()
+        .await?;
+    let conn = harness.connection();
+
+    // The tasks of the lost executor are requeued to the other one.
+    let ssn = conn.create_session(&harness.attributes(1)).await?;
+    let outputs = ssn.map(inputs(10), 4).collect_ordered().await;
+    for (input, output) in inputs(10).into_iter().zip(outputs) {
+        assert_eq!(output?, input);
+    }
+    ssn.close().await?;
+
+    // The lost executor is removed once its lease expires.
+    harness.wait_executors(1).await?;
+    harness.assert_healthy();
+
+    Ok(())
+}